@@ -0,0 +1,129 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Benchmarks for streaming pipeline hot paths.
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use fms_guardrails_orchestr8::orchestrator::{
+    common::{apply_masks, slice_codepoints},
+    types::{Chunk, DetectionBatcher, Detections, MaxProcessedIndexBatcher},
+};
+use tokio::sync::broadcast;
+
+const TEXT: &str = "Lorem ipsum dolor sit amet, consectetuer adipiscing elit. \
+    Aenean commodo ligula eget dolor. Cum sociis natoque penatibus et magnis \
+    dis parturient montes, nascetur ridiculus mus. ";
+
+fn chunks(n: usize) -> Vec<Chunk> {
+    let len = TEXT.chars().count();
+    (0..n)
+        .map(|i| Chunk {
+            input_start_index: i,
+            input_end_index: i,
+            start: i * len,
+            end: (i + 1) * len,
+            text: TEXT.into(),
+        })
+        .collect()
+}
+
+/// Broadcasts chunks to detector subscribers, as chunk streams are fanned out to detectors.
+fn bench_chunk_fanout(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chunk_fanout");
+    let chunks = chunks(100);
+    for n_detectors in [1, 4, 16] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(n_detectors),
+            &n_detectors,
+            |b, &n_detectors| {
+                b.iter(|| {
+                    let (tx, _) = broadcast::channel(chunks.len());
+                    let mut receivers =
+                        (0..n_detectors).map(|_| tx.subscribe()).collect::<Vec<_>>();
+                    for chunk in &chunks {
+                        let _ = tx.send(chunk.clone());
+                    }
+                    for rx in receivers.iter_mut() {
+                        while let Ok(chunk) = rx.try_recv() {
+                            black_box(chunk);
+                        }
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Pushes detections for each chunk and detector into a batcher and pops all batches.
+fn bench_aggregation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("aggregation");
+    let chunks = chunks(100);
+    for n_detectors in [1, 4, 16] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(n_detectors),
+            &n_detectors,
+            |b, &n_detectors| {
+                b.iter(|| {
+                    let mut batcher = MaxProcessedIndexBatcher::new(n_detectors);
+                    // Push out-of-order by detector to exercise pending state
+                    for detector in 0..n_detectors {
+                        for chunk in chunks.iter().rev() {
+                            batcher.push(
+                                0,
+                                format!("detector_{detector}"),
+                                chunk.clone(),
+                                Detections::default(),
+                            );
+                        }
+                    }
+                    while let Some(batch) = batcher.pop_batch() {
+                        black_box(batch);
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Slices spans from text by codepoint offsets, as with input masks and detection spans.
+fn bench_span_slicing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("span_slicing");
+    let text = TEXT.repeat(100);
+    let len = text.chars().count();
+    group.bench_function("slice_codepoints", |b| {
+        b.iter(|| slice_codepoints(black_box(&text), len / 2, len / 2 + 100))
+    });
+    let masks = (0..len)
+        .step_by(TEXT.len())
+        .map(|start| (start, (start + 50).min(len)))
+        .collect::<Vec<_>>();
+    group.bench_function("apply_masks", |b| {
+        b.iter(|| apply_masks(black_box(text.clone()), Some(&masks)))
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_chunk_fanout,
+    bench_aggregation,
+    bench_span_slicing
+);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+#![no_main]
+
+//! Aligns chunks with arbitrary offsets, in bytes, chars or out of bounds, to text.
+use fms_guardrails_orchestr8::orchestrator::{
+    common::align_chunks,
+    types::{Chunk, Chunks},
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (String, Vec<(usize, usize, String)>)| {
+    let (text, spans) = input;
+    let chunks = spans
+        .into_iter()
+        .map(|(start, end, chunk_text)| Chunk {
+            start,
+            end,
+            text: chunk_text.into(),
+            ..Default::default()
+        })
+        .collect::<Chunks>();
+    let char_len = text.chars().count();
+    for chunk in align_chunks("chunker", &text, chunks).iter() {
+        assert!(chunk.start <= chunk.end && chunk.end <= char_len);
+    }
+});
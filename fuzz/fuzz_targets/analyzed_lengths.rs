@@ -0,0 +1,35 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+#![no_main]
+
+//! Parses arbitrary `x-analyzed-lengths` headers of partial detector responses.
+use fms_guardrails_orchestr8::clients::detector::{ANALYZED_LENGTHS_HEADER_NAME, analyzed_lengths};
+use hyper::{HeaderMap, header::HeaderValue};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (u8, &[u8])| {
+    let (n_contents, value) = input;
+    let Ok(value) = HeaderValue::from_bytes(value) else {
+        return;
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert(ANALYZED_LENGTHS_HEADER_NAME, value);
+    if let Ok(lengths) = analyzed_lengths(&headers, n_contents.into()) {
+        assert_eq!(lengths.len(), usize::from(n_contents));
+    }
+});
@@ -0,0 +1,34 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+#![no_main]
+
+//! Parses arbitrary text contents detector responses and converts them to detections.
+use fms_guardrails_orchestr8::{
+    clients::detector::ContentAnalysisResponse, orchestrator::types::Detections,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(response) = serde_json::from_slice::<Vec<Vec<ContentAnalysisResponse>>>(data) else {
+        return;
+    };
+    let n_detections = response.iter().map(Vec::len).sum::<usize>();
+    let detections = Detections::from(response);
+    assert_eq!(detections.len(), n_detections);
+    let _ = Vec::<ContentAnalysisResponse>::from(detections);
+});
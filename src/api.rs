@@ -0,0 +1,297 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Request and response models of the orchestrator API, for Rust clients.
+//!
+//! Requests are created with `new`, taking required fields, and configured with
+//! `with_*` methods, e.g.
+//!
+//! ```
+//! use fms_guardrails_orchestr8::api::{DetectorParams, TextContentDetectionHttpRequest, WithDetectors};
+//!
+//! let request = TextContentDetectionHttpRequest::new("some content")
+//!     .with_detector("hap", DetectorParams::new().with_threshold(0.5));
+//! assert_eq!(
+//!     serde_json::to_value(&request).unwrap(),
+//!     serde_json::json!({"content": "some content", "detectors": {"hap": {"threshold": 0.5}}}),
+//! );
+//! ```
+use std::collections::HashMap;
+
+use crate::models::{CHUNKER_ID_PARAM, THRESHOLD_PARAM};
+pub use crate::{
+    clients::{
+        detector::{ContentAnalysisResponse, ContextType},
+        openai::{
+            ChatCompletion, ChatCompletionChunk, ChatCompletionsRequest, ChatDetections, Content,
+            DetectorConfig, Message, OrchestratorWarning, Role, Tool,
+        },
+    },
+    models::{
+        ChatDetectionHttpRequest, ChatDetectionResult, ChunksHttpRequest, ChunksResult,
+        ClassificationWithGenerationHttpRequest, ClassifiedGeneratedTextResult,
+        ClassifiedGeneratedTextStreamResult, ContextDocsHttpRequest, ContextDocsResult,
+        DetectionOnGeneratedHttpRequest, DetectionOnGenerationResult, DetectionResult,
+        DetectionWarning, DetectorParams, GenerationWithDetectionHttpRequest,
+        GenerationWithDetectionResult, GuardrailsConfig, GuardrailsConfigInput,
+        GuardrailsConfigOutput, GuardrailsHttpRequest, GuardrailsTextGenerationParameters,
+        PendingDetectors, StreamingContentDetectionRequest, StreamingContentDetectionResponse,
+        StreamingInputDetectionResponse, SuitabilityHttpRequest, SuitabilityResult,
+        TextContentDetectionHttpRequest, TextContentDetectionResult, ValidationError, Verdict,
+    },
+};
+
+impl DetectorParams {
+    /// Sets the detector parameter `name`.
+    pub fn with(mut self, name: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.insert(name.into(), value.into());
+        self
+    }
+
+    /// Sets the threshold to filter detector results by score.
+    pub fn with_threshold(self, threshold: f64) -> Self {
+        self.with(THRESHOLD_PARAM, threshold)
+    }
+
+    /// Sets the chunker, overriding the chunker configured for the detector.
+    pub fn with_chunker_id(self, chunker_id: impl Into<String>) -> Self {
+        self.with(CHUNKER_ID_PARAM, chunker_id.into())
+    }
+}
+
+/// Requests configured with a map of detectors.
+pub trait WithDetectors: Sized {
+    fn detectors_mut(&mut self) -> &mut HashMap<String, DetectorParams>;
+
+    /// Adds the detector `detector_id` with `params`.
+    fn with_detector(mut self, detector_id: impl Into<String>, params: DetectorParams) -> Self {
+        self.detectors_mut().insert(detector_id.into(), params);
+        self
+    }
+}
+
+macro_rules! impl_with_detectors {
+    ($($request:ty),*) => {
+        $(
+            impl WithDetectors for $request {
+                fn detectors_mut(&mut self) -> &mut HashMap<String, DetectorParams> {
+                    &mut self.detectors
+                }
+            }
+        )*
+    };
+}
+
+impl_with_detectors!(
+    TextContentDetectionHttpRequest,
+    SuitabilityHttpRequest,
+    GenerationWithDetectionHttpRequest,
+    ContextDocsHttpRequest,
+    ChatDetectionHttpRequest,
+    DetectionOnGeneratedHttpRequest
+);
+
+impl TextContentDetectionHttpRequest {
+    pub fn new(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            detectors: HashMap::new(),
+            max_wait_ms: None,
+        }
+    }
+
+    /// Sets the maximum time to wait for detectors, after which detections of detectors
+    /// still running are pending.
+    pub fn with_max_wait_ms(mut self, max_wait_ms: u64) -> Self {
+        self.max_wait_ms = Some(max_wait_ms);
+        self
+    }
+}
+
+impl SuitabilityHttpRequest {
+    pub fn new(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            detectors: HashMap::new(),
+        }
+    }
+}
+
+impl ChunksHttpRequest {
+    pub fn new(chunker_id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            chunker_id: chunker_id.into(),
+            content: content.into(),
+        }
+    }
+}
+
+impl GenerationWithDetectionHttpRequest {
+    pub fn new(model_id: impl Into<String>, prompt: impl Into<String>) -> Self {
+        Self {
+            model_id: model_id.into(),
+            prompt: prompt.into(),
+            detectors: HashMap::new(),
+            text_gen_parameters: None,
+        }
+    }
+
+    /// Sets the parameters sent to the LLM.
+    pub fn with_text_gen_parameters(mut self, params: GuardrailsTextGenerationParameters) -> Self {
+        self.text_gen_parameters = Some(params);
+        self
+    }
+}
+
+impl ContextDocsHttpRequest {
+    pub fn new(
+        content: impl Into<String>,
+        context_type: ContextType,
+        context: Vec<String>,
+    ) -> Self {
+        Self {
+            detectors: HashMap::new(),
+            content: content.into(),
+            context_type,
+            context,
+        }
+    }
+}
+
+impl ChatDetectionHttpRequest {
+    pub fn new(messages: Vec<Message>) -> Self {
+        Self {
+            detectors: HashMap::new(),
+            messages,
+            tools: Vec::new(),
+        }
+    }
+
+    /// Sets the tool definitions analyzed with messages.
+    pub fn with_tools(mut self, tools: Vec<Tool>) -> Self {
+        self.tools = tools;
+        self
+    }
+}
+
+impl DetectionOnGeneratedHttpRequest {
+    pub fn new(prompt: impl Into<String>, generated_text: impl Into<String>) -> Self {
+        Self {
+            prompt: prompt.into(),
+            generated_text: generated_text.into(),
+            detectors: HashMap::new(),
+        }
+    }
+}
+
+impl ClassificationWithGenerationHttpRequest {
+    pub fn new(model_id: impl Into<String>, prompt: impl Into<String>) -> Self {
+        Self {
+            model_id: model_id.into(),
+            prompt: prompt.into(),
+            detectors: GuardrailsConfig::default(),
+            text_gen_parameters: None,
+        }
+    }
+
+    /// Adds the detector `detector_id` with `params` for detection on the prompt.
+    pub fn with_input_detector(
+        mut self,
+        detector_id: impl Into<String>,
+        params: DetectorParams,
+    ) -> Self {
+        self.detectors
+            .input
+            .get_or_insert_with(|| GuardrailsConfigInput {
+                models: HashMap::new(),
+                masks: None,
+            })
+            .models
+            .insert(detector_id.into(), params);
+        self
+    }
+
+    /// Adds the detector `detector_id` with `params` for detection on generated text.
+    pub fn with_output_detector(
+        mut self,
+        detector_id: impl Into<String>,
+        params: DetectorParams,
+    ) -> Self {
+        self.detectors
+            .output
+            .get_or_insert_with(|| GuardrailsConfigOutput {
+                models: HashMap::new(),
+            })
+            .models
+            .insert(detector_id.into(), params);
+        self
+    }
+
+    /// Sets the spans of the prompt, as `(start, end)`, on which to run input detection.
+    pub fn with_input_masks(mut self, masks: Vec<(usize, usize)>) -> Self {
+        self.detectors
+            .input
+            .get_or_insert_with(|| GuardrailsConfigInput {
+                models: HashMap::new(),
+                masks: None,
+            })
+            .masks = Some(masks);
+        self
+    }
+
+    /// Sets the parameters sent to the LLM.
+    pub fn with_text_gen_parameters(mut self, params: GuardrailsTextGenerationParameters) -> Self {
+        self.text_gen_parameters = Some(params);
+        self
+    }
+}
+
+impl Message {
+    /// Creates a message with text content.
+    pub fn new(role: Role, text: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: Some(Content::Text(text.into())),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classification_with_generation_builder() {
+        let request = ClassificationWithGenerationHttpRequest::new("model", "The cow jumped")
+            .with_input_detector("hap", DetectorParams::new().with_threshold(0.5))
+            .with_input_masks(vec![(4, 7)])
+            .with_output_detector("pii", DetectorParams::new().with_chunker_id("sentence"));
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "model_id": "model",
+                "prompt": "The cow jumped",
+                "detectors": {
+                    "input": {"models": {"hap": {"threshold": 0.5}}, "masks": [[4, 7]]},
+                    "output": {"models": {"pii": {"chunker_id": "sentence"}}},
+                },
+            })
+        );
+        assert!(GuardrailsHttpRequest::try_from(request).is_ok());
+    }
+}
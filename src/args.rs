@@ -15,10 +15,12 @@
 
 */
 
-use std::{fmt::Display, path::PathBuf};
+use std::{collections::HashMap, fmt::Display, path::PathBuf};
 
-use clap::Parser;
-use tracing::{error, warn};
+use clap::{Parser, Subcommand};
+use tracing::warn;
+
+use crate::config::{OtlpConfig, OtlpExport, OtlpProtocol};
 
 #[derive(Parser, Debug, Clone)]
 #[clap(author, version, about, long_about = None)]
@@ -39,6 +41,13 @@ pub struct Args {
     pub tls_key_path: Option<PathBuf>,
     #[clap(long, env)]
     pub tls_client_ca_cert_path: Option<PathBuf>,
+    /// Certificate revocation lists checked against client certificates, for mTLS.
+    #[clap(long, env)]
+    pub tls_client_crl_path: Option<PathBuf>,
+    /// Interval in seconds at which the client CA cert and revocation lists are re-loaded.
+    /// Not re-loaded if not set.
+    #[clap(long, env)]
+    pub tls_reload_interval: Option<u64>,
     #[clap(default_value = "false", long, env)]
     pub start_up_health_check: bool,
     #[clap(long, env, value_delimiter = ',')]
@@ -55,6 +64,8 @@ pub struct Args {
     pub otlp_traces_endpoint: Option<String>,
     #[clap(long, env = "OTEL_EXPORTER_OTLP_METRICS_ENDPOINT")]
     pub otlp_metrics_endpoint: Option<String>,
+    #[clap(long, env = "OTEL_EXPORTER_OTLP_LOGS_ENDPOINT")]
+    pub otlp_logs_endpoint: Option<String>,
     #[clap(
         default_value_t = OtlpProtocol::Grpc,
         long,
@@ -65,77 +76,55 @@ pub struct Args {
     pub otlp_traces_protocol: Option<OtlpProtocol>,
     #[clap(long, env = "OTEL_EXPORTER_OTLP_METRICS_PROTOCOL")]
     pub otlp_metrics_protocol: Option<OtlpProtocol>,
-    // TODO: Add timeout and header OTLP variables
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum OtlpExport {
-    Traces,
-    Metrics,
-}
-
-impl Display for OtlpExport {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            OtlpExport::Traces => write!(f, "traces"),
-            OtlpExport::Metrics => write!(f, "metrics"),
-        }
-    }
-}
-
-impl From<String> for OtlpExport {
-    fn from(s: String) -> Self {
-        match s.to_lowercase().as_str() {
-            "traces" => OtlpExport::Traces,
-            "metrics" => OtlpExport::Metrics,
-            _ => panic!(
-                "Invalid OTLP export type {}, orchestrator only supports exporting traces and metrics via OTLP",
-                s
-            ),
-        }
-    }
-}
-
-#[derive(Debug, Clone, Copy, Default)]
-pub enum OtlpProtocol {
-    #[default]
-    Grpc,
-    Http,
-}
-
-impl Display for OtlpProtocol {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            OtlpProtocol::Grpc => write!(f, "grpc"),
-            OtlpProtocol::Http => write!(f, "http"),
-        }
-    }
+    #[clap(long, env = "OTEL_EXPORTER_OTLP_LOGS_PROTOCOL")]
+    pub otlp_logs_protocol: Option<OtlpProtocol>,
+    /// Headers added to OTLP export requests, as comma-separated `key=value` pairs.
+    #[clap(long, env = "OTEL_EXPORTER_OTLP_HEADERS", value_delimiter = ',')]
+    pub otlp_headers: Vec<String>,
+    /// CA certificate used to verify the OTLP collector.
+    #[clap(long, env = "OTEL_EXPORTER_OTLP_CERTIFICATE")]
+    pub otlp_ca_cert_path: Option<PathBuf>,
+    /// Client certificate for mutual TLS with the OTLP collector.
+    #[clap(long, env = "OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE")]
+    pub otlp_client_cert_path: Option<PathBuf>,
+    /// Client key for mutual TLS with the OTLP collector.
+    #[clap(long, env = "OTEL_EXPORTER_OTLP_CLIENT_KEY")]
+    pub otlp_client_key_path: Option<PathBuf>,
+    // TODO: Add timeout OTLP variables
+    /// Serves metrics in the Prometheus text format on `/metrics` of the health server.
+    #[clap(default_value_t = false, long, env)]
+    pub prometheus_metrics: bool,
+    /// Bearer token required for admin endpoints on the health server, including the
+    /// CPU profiling endpoint of the `pprof` feature; heap profiles are not available.
+    /// Admin endpoints are disabled if not set.
+    #[clap(long, env)]
+    pub admin_token: Option<String>,
+    /// Runs against built-in mock detectors at a fixed request rate and reports latency percentiles.
+    #[clap(default_value_t = false, long)]
+    pub bench_mode: bool,
+    /// Requests per second to send in bench mode.
+    #[clap(default_value_t = 100, long)]
+    pub bench_qps: u32,
+    /// Duration of the bench mode run in seconds.
+    #[clap(default_value_t = 30, long)]
+    pub bench_duration: u64,
+    /// Number of mock detectors applied to each request in bench mode.
+    #[clap(default_value_t = 3, long)]
+    pub bench_detectors: usize,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
 }
 
-impl From<String> for OtlpProtocol {
-    fn from(s: String) -> Self {
-        match s.to_lowercase().as_str() {
-            "grpc" => OtlpProtocol::Grpc,
-            "http" => OtlpProtocol::Http,
-            _ => {
-                error!(
-                    "Invalid OTLP protocol {}, defaulting to {}",
-                    s,
-                    OtlpProtocol::default()
-                );
-                OtlpProtocol::default()
-            }
-        }
-    }
-}
-
-impl OtlpProtocol {
-    pub fn default_endpoint(&self) -> &str {
-        match self {
-            OtlpProtocol::Grpc => "http://localhost:4317",
-            OtlpProtocol::Http => "http://localhost:4318",
-        }
-    }
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Loads the config, sends canned inputs to each configured service, verifies
+    /// responses and prints a pass/fail matrix. Exits with an error if any check fails.
+    SelfTest {
+        /// Model sent generation requests. Generation services are only health checked
+        /// if not set.
+        #[clap(long)]
+        model_id: Option<String>,
+    },
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq)]
@@ -177,11 +166,24 @@ impl From<String> for LogFormat {
     }
 }
 
+/// TLS config for OTLP export, paths to PEM files.
+#[derive(Debug, Clone, Default)]
+pub struct OtlpTlsConfig {
+    pub ca_cert_path: Option<PathBuf>,
+    pub client_cert_path: Option<PathBuf>,
+    pub client_key_path: Option<PathBuf>,
+}
+
 #[derive(Debug, Clone)]
 pub struct TracingConfig {
     pub service_name: String,
     pub traces: Option<(OtlpProtocol, String)>,
     pub metrics: Option<(OtlpProtocol, String)>,
+    pub logs: Option<(OtlpProtocol, String)>,
+    pub prometheus_metrics: bool,
+    pub headers: HashMap<String, String>,
+    pub baggage: Vec<String>,
+    pub tls: Option<OtlpTlsConfig>,
     pub log_format: LogFormat,
     pub quiet: bool,
 }
@@ -194,8 +196,30 @@ impl From<Args> for TracingConfig {
             .unwrap_or(otlp_protocol.default_endpoint().to_string());
         let otlp_traces_endpoint = args.otlp_traces_endpoint.unwrap_or(otlp_endpoint.clone());
         let otlp_metrics_endpoint = args.otlp_metrics_endpoint.unwrap_or(otlp_endpoint.clone());
+        let otlp_logs_endpoint = args.otlp_logs_endpoint.unwrap_or(otlp_endpoint.clone());
         let otlp_traces_protocol = args.otlp_traces_protocol.unwrap_or(otlp_protocol);
         let otlp_metrics_protocol = args.otlp_metrics_protocol.unwrap_or(otlp_protocol);
+        let otlp_logs_protocol = args.otlp_logs_protocol.unwrap_or(otlp_protocol);
+        let otlp_headers = args
+            .otlp_headers
+            .iter()
+            .filter_map(|header| match header.split_once('=') {
+                Some((key, value)) => Some((key.trim().to_string(), value.trim().to_string())),
+                None => {
+                    warn!("Invalid OTLP header {}, expected `key=value`", header);
+                    None
+                }
+            })
+            .collect();
+        let otlp_tls =
+            match args.otlp_ca_cert_path.is_some() || args.otlp_client_cert_path.is_some() {
+                true => Some(OtlpTlsConfig {
+                    ca_cert_path: args.otlp_ca_cert_path,
+                    client_cert_path: args.otlp_client_cert_path,
+                    client_key_path: args.otlp_client_key_path,
+                }),
+                false => None,
+            };
 
         TracingConfig {
             service_name: args.otlp_service_name,
@@ -207,8 +231,39 @@ impl From<Args> for TracingConfig {
                 true => Some((otlp_metrics_protocol, otlp_metrics_endpoint)),
                 false => None,
             },
+            logs: match args.otlp_export.contains(&OtlpExport::Logs) {
+                true => Some((otlp_logs_protocol, otlp_logs_endpoint)),
+                false => None,
+            },
+            prometheus_metrics: args.prometheus_metrics,
+            headers: otlp_headers,
+            baggage: Vec::new(),
+            tls: otlp_tls,
             log_format: args.log_format,
             quiet: args.quiet,
         }
     }
 }
+
+impl TracingConfig {
+    /// Applies OTLP export settings of the config file. Exports and headers set by
+    /// command line arguments or environment variables take precedence.
+    pub fn with_otlp_config(mut self, otlp: OtlpConfig) -> Self {
+        let endpoint = otlp
+            .endpoint
+            .unwrap_or(otlp.protocol.default_endpoint().to_string());
+        for export in otlp.export {
+            let target = match export {
+                OtlpExport::Traces => &mut self.traces,
+                OtlpExport::Metrics => &mut self.metrics,
+                OtlpExport::Logs => &mut self.logs,
+            };
+            target.get_or_insert_with(|| (otlp.protocol, endpoint.clone()));
+        }
+        for (key, value) in otlp.headers {
+            self.headers.entry(key).or_insert(value);
+        }
+        self.baggage = otlp.baggage;
+        self
+    }
+}
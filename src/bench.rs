@@ -0,0 +1,256 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Synthetic load generation mode.
+//!
+//! Runs the orchestrator against built-in mock detector servers and drives
+//! requests at a fixed rate, reporting latency percentiles.
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use axum::{Json, Router, routing::post};
+use tokio::{
+    net::TcpListener,
+    task::JoinSet,
+    time::{Instant, MissedTickBehavior},
+};
+use tracing::info;
+
+use crate::{
+    clients::{
+        chunker::DEFAULT_CHUNKER_ID,
+        detector::{ContentAnalysisRequest, ContentAnalysisResponse},
+    },
+    config::{DetectorConfig, DetectorType, OrchestratorConfig, ServiceConfig},
+    models::{DetectorParams, TextContentDetectionHttpRequest},
+    orchestrator::Orchestrator,
+    server,
+};
+
+const CONTENT: &str = "Lorem ipsum dolor sit amet, consectetuer adipiscing elit. \
+    Aenean commodo ligula eget dolor. Cum sociis natoque penatibus et magnis \
+    dis parturient montes, nascetur ridiculus mus.";
+
+/// Bench mode errors.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Orchestrator(#[from] crate::orchestrator::Error),
+    #[error(transparent)]
+    Server(#[from] server::Error),
+}
+
+/// Bench mode configuration.
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Requests per second to send
+    pub qps: u32,
+    /// Duration of the run
+    pub duration: Duration,
+    /// Number of mock detectors applied to each request
+    pub n_detectors: usize,
+    /// Guardrails server address
+    pub guardrails_addr: SocketAddr,
+    /// Health server address
+    pub health_addr: SocketAddr,
+}
+
+/// Bench mode results.
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub requests: usize,
+    pub errors: usize,
+    pub elapsed: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl std::fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "requests: {} ({} errors)", self.requests, self.errors)?;
+        writeln!(
+            f,
+            "throughput: {:.2} req/s",
+            self.requests as f64 / self.elapsed.as_secs_f64()
+        )?;
+        write!(
+            f,
+            "latency: p50={:.2?} p90={:.2?} p99={:.2?} max={:.2?}",
+            self.p50, self.p90, self.p99, self.max
+        )
+    }
+}
+
+/// Runs the orchestrator against mock detectors and drives load through the guardrails server.
+pub async fn run(config: BenchConfig) -> Result<BenchReport, Error> {
+    let detector_addr = run_mock_detector().await?;
+    let orchestrator_config = mock_config(detector_addr, config.n_detectors);
+    let detectors = orchestrator_config
+        .detectors
+        .keys()
+        .map(|detector_id| (detector_id.clone(), DetectorParams::new()))
+        .collect();
+    let orchestrator = Orchestrator::new(orchestrator_config, false).await?;
+    let _handles = server::run(
+        config.guardrails_addr,
+        config.health_addr,
+        server::ServerTlsConfig::default(),
+        None,
+        orchestrator,
+    )
+    .await?;
+
+    let url = format!(
+        "http://localhost:{}/api/v2/text/detection/content",
+        config.guardrails_addr.port()
+    );
+    let request = TextContentDetectionHttpRequest {
+        content: CONTENT.into(),
+        detectors,
+        max_wait_ms: None,
+    };
+    let client = reqwest::Client::new();
+    info!(
+        qps = config.qps,
+        duration = ?config.duration,
+        n_detectors = config.n_detectors,
+        "starting bench mode"
+    );
+    let mut interval = tokio::time::interval(Duration::from_secs(1) / config.qps.max(1));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Burst);
+    let mut tasks = JoinSet::new();
+    let start = Instant::now();
+    while start.elapsed() < config.duration {
+        interval.tick().await;
+        let client = client.clone();
+        let url = url.clone();
+        let request = request.clone();
+        tasks.spawn(async move {
+            let now = Instant::now();
+            let success = client
+                .post(url)
+                .json(&request)
+                .send()
+                .await
+                .is_ok_and(|response| response.status().is_success());
+            (now.elapsed(), success)
+        });
+    }
+    let mut latencies = Vec::with_capacity(tasks.len());
+    let mut errors = 0;
+    while let Some(Ok((latency, success))) = tasks.join_next().await {
+        latencies.push(latency);
+        if !success {
+            errors += 1;
+        }
+    }
+    let elapsed = start.elapsed();
+    latencies.sort_unstable();
+    Ok(BenchReport {
+        requests: latencies.len(),
+        errors,
+        elapsed,
+        p50: percentile(&latencies, 50.0),
+        p90: percentile(&latencies, 90.0),
+        p99: percentile(&latencies, 99.0),
+        max: latencies.last().copied().unwrap_or_default(),
+    })
+}
+
+/// Returns the nearest-rank percentile of sorted values.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Starts a mock text contents detector returning a detection for each content.
+async fn run_mock_detector() -> Result<SocketAddr, Error> {
+    let app = Router::new().route(
+        "/api/v1/text/contents",
+        post(|Json(request): Json<ContentAnalysisRequest>| async move {
+            let detections = request
+                .contents
+                .iter()
+                .map(|content| {
+                    vec![ContentAnalysisResponse {
+                        start: 0,
+                        end: content.chars().count(),
+                        text: content.clone(),
+                        detection: "bench".into(),
+                        detection_type: "bench".into(),
+                        detector_id: None,
+                        score: 0.9,
+                        evidence: None,
+                        metadata: Default::default(),
+                        chunk: None,
+                        category: None,
+                    }]
+                })
+                .collect::<Vec<_>>();
+            Json(detections)
+        }),
+    );
+    let listener = TcpListener::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    Ok(addr)
+}
+
+/// Builds an orchestrator config with mock detectors.
+fn mock_config(detector_addr: SocketAddr, n_detectors: usize) -> OrchestratorConfig {
+    let mut config = OrchestratorConfig::default();
+    for i in 0..n_detectors {
+        config.detectors.insert(
+            format!("bench_detector_{i}"),
+            DetectorConfig {
+                service: ServiceConfig {
+                    hostname: "localhost".into(),
+                    port: Some(detector_addr.port()),
+                    ..Default::default()
+                },
+                chunker_id: DEFAULT_CHUNKER_ID.into(),
+                default_threshold: 0.5,
+                r#type: DetectorType::TextContents,
+                ..Default::default()
+            },
+        );
+    }
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let latencies = (1..=100).map(Duration::from_millis).collect::<Vec<_>>();
+        assert_eq!(percentile(&latencies, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&latencies, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&latencies, 100.0), Duration::from_millis(100));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+    }
+}
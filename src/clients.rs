@@ -17,41 +17,77 @@
 
 #![allow(dead_code)]
 use std::{
-    any::TypeId,
     collections::{HashMap, hash_map},
     fmt::Debug,
     pin::Pin,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
     time::Duration,
 };
 
 use async_trait::async_trait;
-use axum::http::{Extensions, HeaderMap};
-use futures::Stream;
+use axum::http::{Extensions, HeaderMap, HeaderValue};
+use futures::{Stream, future::join_all};
 use ginepro::LoadBalancedChannel;
 use hyper_timeout::TimeoutConnector;
-use hyper_util::rt::TokioExecutor;
+use hyper_util::{
+    client::legacy::connect::HttpConnector,
+    rt::{TokioExecutor, TokioTimer},
+};
+use rustls::pki_types::ServerName;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tonic::{Request, metadata::MetadataMap};
 use tower::{ServiceBuilder, timeout::TimeoutLayer};
-use tracing::Span;
+use tracing::{Span, debug};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 use url::Url;
 
 use crate::{
-    config::{ServiceConfig, Tls},
-    health::HealthCheckResult,
+    config::{
+        BackendConfig, CanaryConfig, DetectorType, GenerationProvider, OrchestratorConfig,
+        ServiceConfig, SizeLimits, Tls,
+    },
+    health::{HealthCheckCache, HealthCheckResult, HealthStatus},
     utils::{tls, trace::with_traceparent_header},
 };
+use dns::{GrpcResolver, HttpResolver};
 
 pub mod errors;
 pub use errors::Error;
 
+pub mod dns;
+pub use dns::{set_egress_policy, set_static_hosts};
+
+pub mod headers;
+pub use headers::HeaderTemplates;
+
 pub mod http;
 pub use http::{HttpClient, http_trace_layer};
 
+pub mod pool;
+use pool::{ConnectionPool, CountedConnector};
+
+pub mod retry;
+use retry::RetryPolicy;
+
+pub mod breaker;
+use breaker::{CircuitBreaker, CircuitState};
+
+pub mod routing;
+
+pub mod canary;
+use canary::Canary;
+
 pub mod chunker;
+use chunker::ChunkerClient;
 
 pub mod detector;
 pub use detector::TextContentsDetectorClient;
+use detector::{
+    TextChatDetectorClient, TextContextDocDetectorClient, TextGenerationDetectorClient,
+};
 
 pub mod tgis;
 pub use tgis::TgisClient;
@@ -62,19 +98,138 @@ pub use nlp::NlpClient;
 pub mod generation;
 pub use generation::GenerationClient;
 
+pub mod tokenizer;
+pub use tokenizer::LocalTokenizers;
+
 pub mod otel_grpc;
 pub use otel_grpc::{OtelGrpcLayer, OtelGrpcService};
 
 pub mod openai;
+use openai::OpenAiClient;
+
+#[cfg(feature = "orchestrator-client")]
+pub mod orchestrator;
+#[cfg(feature = "orchestrator-client")]
+pub use orchestrator::OrchestratorClient;
 
 const DEFAULT_CONNECT_TIMEOUT_SEC: u64 = 60;
 const DEFAULT_REQUEST_TIMEOUT_SEC: u64 = 600;
-const DEFAULT_GRPC_PROBE_INTERVAL_SEC: u64 = 10;
+const DEFAULT_DNS_PROBE_INTERVAL_SEC: u64 = 10;
 
 pub type BoxStream<T> = Pin<Box<dyn Stream<Item = T> + Send>>;
 
-mod private {
-    pub struct Seal;
+/// Limits the number of outstanding downstream requests across all clients.
+///
+/// Streaming requests are not limited: streams are long-lived and wait on requests of
+/// their own, e.g. detector requests of streamed text, which they must not hold up.
+///
+/// Clones share the limit. Unlimited by default.
+#[derive(Debug, Clone, Default)]
+pub struct RequestLimiter(Option<Arc<Semaphore>>);
+
+impl RequestLimiter {
+    /// Creates a limiter of `max_concurrent_requests`, unlimited if not set.
+    pub fn new(max_concurrent_requests: Option<usize>) -> Self {
+        Self(max_concurrent_requests.map(|permits| Arc::new(Semaphore::new(permits))))
+    }
+
+    /// Waits for a request permit, if limited. The request is outstanding until the
+    /// permit is dropped, so it must be held until its response is fully received.
+    pub async fn acquire(&self) -> Option<RequestPermit> {
+        let permits = self.0.clone()?;
+        permits
+            .acquire_owned()
+            .await
+            .ok()
+            .map(|permit| RequestPermit(Arc::new(permit)))
+    }
+}
+
+/// Permit of an outstanding downstream request, released when all clones are dropped.
+#[derive(Debug, Clone)]
+pub struct RequestPermit(Arc<OwnedSemaphorePermit>);
+
+/// Samples downstream requests for logging.
+///
+/// Clones share the sample. Disabled by default.
+#[derive(Debug, Clone, Default)]
+pub struct LogSampler(Option<Arc<Sample>>);
+
+#[derive(Debug)]
+struct Sample {
+    /// Fraction of requests logged
+    rate: f64,
+    /// Number of requests considered for logging
+    requests: AtomicU64,
+}
+
+impl LogSampler {
+    /// Creates a sampler logging `sample_rate` of requests, disabled if not set.
+    pub fn new(sample_rate: Option<f64>) -> Self {
+        Self(sample_rate.map(|rate| {
+            Arc::new(Sample {
+                rate,
+                requests: AtomicU64::new(0),
+            })
+        }))
+    }
+
+    /// Returns whether the next request is logged.
+    /// Requests are selected evenly at the sample rate.
+    pub fn sample(&self) -> bool {
+        let Some(sample) = &self.0 else {
+            return false;
+        };
+        let n = sample.requests.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * sample.rate).floor() > (n * sample.rate).floor()
+    }
+}
+
+/// State shared by all clients of an orchestrator.
+#[derive(Debug, Clone, Default)]
+pub struct SharedClientState {
+    /// Limit of outstanding downstream requests
+    pub request_limiter: RequestLimiter,
+    /// Sampler of logged downstream requests
+    pub log_sampler: LogSampler,
+}
+
+impl SharedClientState {
+    pub fn new(config: &OrchestratorConfig) -> Self {
+        Self {
+            request_limiter: RequestLimiter::new(config.max_concurrent_requests),
+            log_sampler: LogSampler::new(config.downstream_log_sample_rate),
+        }
+    }
+}
+
+/// A client transport that can be closed.
+///
+/// Clones share the transport. Closing drops it for all clones, so its
+/// connections and background tasks end once in-flight requests complete.
+#[derive(Debug)]
+pub struct Closeable<T>(Arc<RwLock<Option<T>>>);
+
+impl<T> Clone for Closeable<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Clone> Closeable<T> {
+    pub fn new(inner: T) -> Self {
+        Self(Arc::new(RwLock::new(Some(inner))))
+    }
+
+    /// Returns a handle to the transport, or an error if it has been closed.
+    pub fn get(&self) -> Result<T, Error> {
+        self.0.read().unwrap().clone().ok_or(Error::ClientShutdown)
+    }
+
+    /// Closes the transport.
+    pub fn close(&self) {
+        self.0.write().unwrap().take();
+    }
 }
 
 #[async_trait]
@@ -82,54 +237,104 @@ pub trait Client: Send + Sync + 'static {
     /// Returns the name of the client type.
     fn name(&self) -> &str;
 
-    /// Returns the `TypeId` of the client type. Sealed to prevent overrides.
-    fn type_id(&self, _: private::Seal) -> TypeId {
-        TypeId::of::<Self>()
-    }
-
     /// Performs a client health check.
     async fn health(&self) -> HealthCheckResult;
-}
 
-impl dyn Client {
-    pub fn is<T: 'static>(&self) -> bool {
-        TypeId::of::<T>() == self.type_id(private::Seal)
-    }
+    /// Closes connections and stops background tasks owned by the client.
+    /// Subsequent requests fail with [`Error::ClientShutdown`].
+    async fn shutdown(&self) {}
 
-    pub fn downcast<T: 'static>(self: Box<Self>) -> Result<Box<T>, Box<Self>> {
-        if (*self).is::<T>() {
-            let ptr = Box::into_raw(self) as *mut T;
-            // SAFETY: guaranteed by `is`
-            unsafe { Ok(Box::from_raw(ptr)) }
-        } else {
-            Err(self)
-        }
+    /// Returns the circuit breaker of the client's service, if configured.
+    fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        None
     }
+}
+
+/// A client registered in a [`ClientMap`].
+pub enum ClientEntry {
+    Generation(GenerationClient),
+    ChatGeneration(OpenAiClient),
+    Chunker(ChunkerClient),
+    TextContentsDetector(TextContentsDetectorClient),
+    TextGenerationDetector(TextGenerationDetectorClient),
+    TextChatDetector(TextChatDetectorClient),
+    TextContextDocDetector(TextContextDocDetectorClient),
+}
 
-    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
-        if (*self).is::<T>() {
-            let ptr = self as *const dyn Client as *const T;
-            // SAFETY: guaranteed by `is`
-            unsafe { Some(&*ptr) }
-        } else {
-            None
+impl ClientEntry {
+    /// Returns a reference to the client trait object.
+    pub fn as_client(&self) -> &dyn Client {
+        match self {
+            Self::Generation(client) => client,
+            Self::ChatGeneration(client) => client,
+            Self::Chunker(client) => client,
+            Self::TextContentsDetector(client) => client,
+            Self::TextGenerationDetector(client) => client,
+            Self::TextChatDetector(client) => client,
+            Self::TextContextDocDetector(client) => client,
         }
     }
 
-    pub fn downcast_mut<T: 'static>(&mut self) -> Option<&mut T> {
-        if (*self).is::<T>() {
-            let ptr = self as *mut dyn Client as *mut T;
-            // SAFETY: guaranteed by `is`
-            unsafe { Some(&mut *ptr) }
-        } else {
-            None
-        }
+    /// Shuts down the client.
+    pub async fn shutdown(&self) {
+        self.as_client().shutdown().await
     }
 }
 
-/// A map containing different types of clients.
+/// A client type that can be registered in a [`ClientMap`].
+pub trait RegisteredClient: Client + Sized {
+    /// Wraps the client in its [`ClientEntry`] variant.
+    fn into_entry(self) -> ClientEntry;
+
+    /// Returns a reference to the client if the entry is of this type.
+    fn from_entry(entry: &ClientEntry) -> Option<&Self>;
+
+    /// Returns a mutable reference to the client if the entry is of this type.
+    fn from_entry_mut(entry: &mut ClientEntry) -> Option<&mut Self>;
+}
+
+macro_rules! impl_registered_client {
+    ($($client:ty => $variant:ident),+ $(,)?) => {
+        $(
+            impl RegisteredClient for $client {
+                fn into_entry(self) -> ClientEntry {
+                    ClientEntry::$variant(self)
+                }
+
+                fn from_entry(entry: &ClientEntry) -> Option<&Self> {
+                    match entry {
+                        ClientEntry::$variant(client) => Some(client),
+                        _ => None,
+                    }
+                }
+
+                fn from_entry_mut(entry: &mut ClientEntry) -> Option<&mut Self> {
+                    match entry {
+                        ClientEntry::$variant(client) => Some(client),
+                        _ => None,
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_registered_client!(
+    GenerationClient => Generation,
+    OpenAiClient => ChatGeneration,
+    ChunkerClient => Chunker,
+    TextContentsDetectorClient => TextContentsDetector,
+    TextGenerationDetectorClient => TextGenerationDetector,
+    TextChatDetectorClient => TextChatDetector,
+    TextContextDocDetectorClient => TextContextDocDetector,
+);
+
+/// A typed registry of clients, keyed by service id.
+///
+/// Provides the client lifecycle: creation from config, health checks,
+/// and replacing clients when config is reloaded.
 #[derive(Default)]
-pub struct ClientMap(HashMap<String, Box<dyn Client>>);
+pub struct ClientMap(HashMap<String, ClientEntry>);
 
 impl ClientMap {
     /// Creates an empty `ClientMap`.
@@ -138,57 +343,201 @@ impl ClientMap {
         Self(HashMap::new())
     }
 
-    /// Inserts a client into the map.
+    /// Creates clients for all services in the config, sharing `shared`.
+    pub async fn create(
+        config: &OrchestratorConfig,
+        shared: &SharedClientState,
+    ) -> Result<Self, Error> {
+        let mut clients = Self::new();
+
+        // Create generation client
+        if let Some(generation) = &config.generation {
+            let generation_client = match generation.provider {
+                GenerationProvider::Tgis => GenerationClient::tgis(
+                    TgisClient::new(&generation.service)
+                        .await
+                        .with_request_limiter(shared.request_limiter.clone()),
+                ),
+                GenerationProvider::Nlp => GenerationClient::nlp(
+                    NlpClient::new(&generation.service)
+                        .await
+                        .with_request_limiter(shared.request_limiter.clone()),
+                ),
+                GenerationProvider::OpenAi => GenerationClient::openai(
+                    OpenAiClient::new(&generation.service, None, shared).await?,
+                ),
+            }
+            .with_tokenizers(LocalTokenizers::from_files(&generation.tokenizers)?);
+            clients.insert("generation".to_string(), generation_client);
+        }
+
+        // Create chat generation client
+        if let Some(chat_generation) = &config.chat_generation {
+            let openai_client = OpenAiClient::new(
+                &chat_generation.service,
+                chat_generation.health_service.as_ref(),
+                shared,
+            )
+            .await?;
+            clients.insert("chat_generation".to_string(), openai_client);
+        }
+
+        // Create chunker clients
+        if let Some(chunkers) = &config.chunkers {
+            for (chunker_id, chunker) in chunkers {
+                let chunker_client = ChunkerClient::new(&chunker.service)
+                    .await
+                    .with_request_limiter(shared.request_limiter.clone());
+                clients.insert(chunker_id.to_string(), chunker_client);
+            }
+        }
+
+        // Create detector clients
+        for (detector_id, detector) in &config.detectors {
+            let service = &detector.service;
+            let health_service = detector.health_service.as_ref();
+            let endpoint_path = detector.endpoint_path.as_deref();
+            let backends = &detector.backends;
+            let canary = detector.canary.as_ref();
+            let size_limits = detector.size_limits;
+            let response_format = detector.response_format;
+            let tokenizer_path = detector.tokenizer_path.as_deref();
+            let entry = match detector.r#type {
+                DetectorType::TextContents => TextContentsDetectorClient::new(
+                    service,
+                    health_service,
+                    endpoint_path,
+                    backends,
+                    canary,
+                    size_limits,
+                    response_format,
+                    tokenizer_path,
+                    shared,
+                )
+                .await?
+                .into_entry(),
+                DetectorType::TextGeneration => TextGenerationDetectorClient::new(
+                    service,
+                    health_service,
+                    endpoint_path,
+                    backends,
+                    canary,
+                    size_limits,
+                    shared,
+                )
+                .await?
+                .into_entry(),
+                DetectorType::TextChat => TextChatDetectorClient::new(
+                    service,
+                    health_service,
+                    endpoint_path,
+                    backends,
+                    canary,
+                    size_limits,
+                    shared,
+                )
+                .await?
+                .into_entry(),
+                DetectorType::TextContextDoc => TextContextDocDetectorClient::new(
+                    service,
+                    health_service,
+                    endpoint_path,
+                    backends,
+                    canary,
+                    size_limits,
+                    shared,
+                )
+                .await?
+                .into_entry(),
+            };
+            clients.insert_entry(detector_id.clone(), entry);
+        }
+        Ok(clients)
+    }
+
+    /// Inserts a client into the map, returning the entry it replaced.
     #[inline]
-    pub fn insert<V: Client>(&mut self, key: String, value: V) {
-        self.0.insert(key, Box::new(value));
+    pub fn insert<V: RegisteredClient>(&mut self, key: String, value: V) -> Option<ClientEntry> {
+        self.insert_entry(key, value.into_entry())
     }
 
-    /// Returns a reference to the client trait object.
+    /// Inserts a client entry into the map, returning the entry it replaced.
     #[inline]
-    pub fn get(&self, key: &str) -> Option<&dyn Client> {
-        self.0.get(key).map(|v| v.as_ref())
+    pub fn insert_entry(&mut self, key: String, entry: ClientEntry) -> Option<ClientEntry> {
+        self.0.insert(key, entry)
     }
 
-    /// Returns a mutable reference to the client trait object.
+    /// Returns a reference to the client trait object.
     #[inline]
-    pub fn get_mut(&mut self, key: &str) -> Option<&mut dyn Client> {
-        self.0.get_mut(key).map(|v| v.as_mut())
+    pub fn get(&self, key: &str) -> Option<&dyn Client> {
+        self.0.get(key).map(|entry| entry.as_client())
     }
 
-    /// Downcasts and returns a reference to the concrete client type.
+    /// Returns a reference to the client of the given type.
     #[inline]
-    pub fn get_as<V: Client>(&self, key: &str) -> Option<&V> {
-        self.0.get(key)?.downcast_ref::<V>()
+    pub fn get_as<V: RegisteredClient>(&self, key: &str) -> Option<&V> {
+        V::from_entry(self.0.get(key)?)
     }
 
-    /// Downcasts and returns a mutable reference to the concrete client type.
+    /// Returns a mutable reference to the client of the given type.
     #[inline]
-    pub fn get_mut_as<V: Client>(&mut self, key: &str) -> Option<&mut V> {
-        self.0.get_mut(key)?.downcast_mut::<V>()
+    pub fn get_mut_as<V: RegisteredClient>(&mut self, key: &str) -> Option<&mut V> {
+        V::from_entry_mut(self.0.get_mut(key)?)
     }
 
     /// Removes a client from the map.
     #[inline]
-    pub fn remove(&mut self, key: &str) -> Option<Box<dyn Client>> {
+    pub fn remove(&mut self, key: &str) -> Option<ClientEntry> {
         self.0.remove(key)
     }
 
+    /// Replaces all clients with `clients`, e.g. on config reload,
+    /// and shuts down the previous clients.
+    pub async fn replace(&mut self, clients: ClientMap) {
+        let previous = std::mem::replace(&mut self.0, clients.0);
+        join_all(previous.values().map(|entry| entry.shutdown())).await;
+    }
+
+    /// Shuts down all clients concurrently.
+    pub async fn shutdown(&self) {
+        debug!("shutting down clients");
+        join_all(self.0.values().map(|entry| entry.shutdown())).await;
+    }
+
+    /// Performs health checks for all clients concurrently.
+    pub async fn health(&self) -> HealthCheckCache {
+        let results = join_all(self.0.iter().map(|(key, entry)| async move {
+            let client = entry.as_client();
+            let mut result = client.health().await;
+            // Services with an open circuit are failing requests without sending them
+            let circuit_state = client.circuit_breaker().map(|breaker| breaker.state());
+            if circuit_state == Some(CircuitState::Open) {
+                result.status = HealthStatus::Unhealthy;
+                result.reason = Some("circuit breaker open".into());
+            }
+            (key.clone(), result)
+        }))
+        .await;
+        let mut health = HealthCheckCache::with_capacity(results.len());
+        health.extend(results);
+        health
+    }
+
     /// An iterator visiting all key-value pairs in arbitrary order.
     #[inline]
-    pub fn iter(&self) -> hash_map::Iter<'_, String, Box<dyn Client>> {
+    pub fn iter(&self) -> hash_map::Iter<'_, String, ClientEntry> {
         self.0.iter()
     }
 
     /// An iterator visiting all keys in arbitrary order.
     #[inline]
-    pub fn keys(&self) -> hash_map::Keys<'_, String, Box<dyn Client>> {
+    pub fn keys(&self) -> hash_map::Keys<'_, String, ClientEntry> {
         self.0.keys()
     }
 
     /// An iterator visiting all values in arbitrary order.
     #[inline]
-    pub fn values(&self) -> hash_map::Values<'_, String, Box<dyn Client>> {
+    pub fn values(&self) -> hash_map::Values<'_, String, ClientEntry> {
         self.0.values()
     }
 
@@ -220,13 +569,66 @@ pub async fn create_http_client(
         .set_port(Some(port))
         .unwrap_or_else(|_| panic!("error setting port: {}", port));
 
-    let connect_timeout = Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SEC);
     let request_timeout = Duration::from_secs(
         service_config
             .request_timeout
             .unwrap_or(DEFAULT_REQUEST_TIMEOUT_SEC),
     );
+    let dns_probe_interval = Duration::from_secs(
+        service_config
+            .dns_probe_interval
+            .unwrap_or(DEFAULT_DNS_PROBE_INTERVAL_SEC),
+    );
+    // Clients of services with HTTP/2 configured share pools with clients of the
+    // same endpoint and connection settings, so their requests are multiplexed
+    let pool_key = service_config.http2.map(|http2| {
+        format!(
+            "{base_url} {:?} {:?} {} {http2:?}",
+            service_config.tls,
+            service_config.sni_hostname,
+            dns_probe_interval.as_secs()
+        )
+    });
+    let pool = match pool_key.as_deref().and_then(ConnectionPool::shared) {
+        Some(pool) => pool,
+        None => {
+            let pool = create_connection_pool(service_config, dns_probe_interval).await?;
+            if let Some(pool_key) = pool_key {
+                pool.share(pool_key);
+            }
+            pool
+        }
+    };
+    let client = ServiceBuilder::new()
+        .layer(http_trace_layer())
+        .layer(TimeoutLayer::new(request_timeout))
+        .service(pool);
+    let mut client = HttpClient::new(
+        base_url,
+        client,
+        service_config.headers.clone(),
+        service_config.health_check.clone(),
+    )
+    .with_retry(RetryPolicy::from(service_config.retry.as_ref()));
+    if let Some(circuit_breaker) = CircuitBreaker::from_config(service_config) {
+        client = client.with_circuit_breaker(circuit_breaker);
+    }
+    if let Some(host_header) = &service_config.host_header {
+        let host_header = HeaderValue::from_str(host_header).map_err(|e| Error::Http {
+            code: http::StatusCode::INTERNAL_SERVER_ERROR,
+            message: format!("invalid host header `{host_header}`: {e}"),
+        })?;
+        client = client.with_host_header(host_header);
+    }
+    Ok(client)
+}
 
+/// Creates the connection pool of an HTTP client.
+async fn create_connection_pool(
+    service_config: &ServiceConfig,
+    dns_probe_interval: Duration,
+) -> Result<ConnectionPool, Error> {
+    let connect_timeout = Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SEC);
     let https_conn_builder = match &service_config.tls {
         Some(Tls::Config(tls)) => hyper_rustls::HttpsConnectorBuilder::new().with_tls_config(
             tls::build_client_config(tls)
@@ -237,22 +639,79 @@ pub async fn create_http_client(
         None => hyper_rustls::HttpsConnectorBuilder::new()
             .with_tls_config(tls::build_insecure_client_config()),
     };
+    let mut http_conn = HttpConnector::new_with_resolver(HttpResolver::new(dns_probe_interval));
+    http_conn.enforce_http(false);
+    let mut https_conn_builder = https_conn_builder.https_or_http();
+    if let Some(sni_hostname) = &service_config.sni_hostname {
+        let server_name = ServerName::try_from(sni_hostname.clone()).map_err(|e| Error::Http {
+            code: http::StatusCode::INTERNAL_SERVER_ERROR,
+            message: format!("invalid sni hostname `{sni_hostname}`: {e}"),
+        })?;
+        https_conn_builder = https_conn_builder
+            .with_server_name_resolver(hyper_rustls::FixedServerNameResolver::new(server_name));
+    }
     let https_conn = https_conn_builder
-        .https_or_http()
         .enable_http1()
         .enable_http2()
-        .build();
+        .wrap_connector(CountedConnector::new(http_conn));
 
     let mut timeout_conn = TimeoutConnector::new(https_conn);
     timeout_conn.set_connect_timeout(Some(connect_timeout));
 
-    let client =
-        hyper_util::client::legacy::Client::builder(TokioExecutor::new()).build(timeout_conn);
-    let client = ServiceBuilder::new()
-        .layer(http_trace_layer())
-        .layer(TimeoutLayer::new(request_timeout))
-        .service(client);
-    Ok(HttpClient::new(base_url, client))
+    // Idle connections are closed at the probe interval, so new connections
+    // pick up re-resolved addresses
+    let mut builder = hyper_util::client::legacy::Client::builder(TokioExecutor::new());
+    builder.pool_idle_timeout(dns_probe_interval);
+    if let Some(http2) = &service_config.http2 {
+        builder
+            .http2_only(http2.prior_knowledge)
+            .http2_initial_max_send_streams(http2.max_concurrent_streams)
+            .http2_keep_alive_interval(http2.keep_alive_interval.map(Duration::from_secs))
+            .timer(TokioTimer::new());
+    }
+    // Connections are replaced at the probe interval, also while busy
+    Ok(ConnectionPool::new(
+        builder.build(timeout_conn),
+        dns_probe_interval,
+    ))
+}
+
+/// Creates an HTTP client routing requests between a service and its equivalent `backends`,
+/// splitting a share of requests to a `canary` deployment if configured. `size_limits` and
+/// `shared` state apply to requests of all deployments.
+pub async fn create_routed_http_client(
+    default_port: u16,
+    service_config: &ServiceConfig,
+    backends: &[BackendConfig],
+    canary: Option<&CanaryConfig>,
+    size_limits: SizeLimits,
+    shared: &SharedClientState,
+) -> Result<HttpClient, Error> {
+    let client = create_http_client(default_port, service_config)
+        .await?
+        .with_size_limits(size_limits)
+        .with_shared_state(shared.clone());
+    let mut backend_clients = Vec::with_capacity(backends.len());
+    for backend in backends {
+        let backend_client = create_http_client(default_port, &backend.service)
+            .await?
+            .with_size_limits(size_limits)
+            .with_shared_state(shared.clone());
+        backend_clients.push((backend.name.clone(), backend_client));
+    }
+    let mut client = client.with_backends(backend_clients);
+    if let Some(canary) = canary {
+        let canary_client = create_http_client(default_port, &canary.service)
+            .await?
+            .with_size_limits(size_limits)
+            .with_shared_state(shared.clone());
+        client = client.with_canary(Canary::new(
+            canary_client,
+            canary.traffic_percent,
+            canary.mode,
+        ));
+    }
+    Ok(client)
 }
 
 pub async fn create_grpc_client<C: Debug + Clone>(
@@ -273,37 +732,42 @@ pub async fn create_grpc_client<C: Debug + Clone>(
             .request_timeout
             .unwrap_or(DEFAULT_REQUEST_TIMEOUT_SEC),
     );
-    let grpc_dns_probe_interval = Duration::from_secs(
+    let dns_probe_interval = Duration::from_secs(
         service_config
-            .grpc_dns_probe_interval
-            .unwrap_or(DEFAULT_GRPC_PROBE_INTERVAL_SEC),
+            .dns_probe_interval
+            .unwrap_or(DEFAULT_DNS_PROBE_INTERVAL_SEC),
     );
     let mut builder = LoadBalancedChannel::builder((service_config.hostname.clone(), port))
-        .dns_probe_interval(grpc_dns_probe_interval)
+        .lookup_service(GrpcResolver)
+        .dns_probe_interval(dns_probe_interval)
         .connect_timeout(connect_timeout)
         .timeout(request_timeout);
 
     let client_tls_config = if let Some(Tls::Config(tls_config)) = &service_config.tls {
-        let cert_path = tls_config.cert_path.as_ref().unwrap().as_path();
-        let key_path = tls_config.key_path.as_ref().unwrap().as_path();
-        let cert_pem = tokio::fs::read(cert_path)
-            .await
-            .unwrap_or_else(|error| panic!("error reading cert from {cert_path:?}: {error}"));
-        let key_pem = tokio::fs::read(key_path)
-            .await
-            .unwrap_or_else(|error| panic!("error reading key from {key_path:?}: {error}"));
-        let identity = tonic::transport::Identity::from_pem(cert_pem, key_pem);
-        let mut client_tls_config = tonic::transport::ClientTlsConfig::new()
-            .identity(identity)
-            .with_native_roots()
-            .with_webpki_roots();
-        if let Some(client_ca_cert_path) = &tls_config.client_ca_cert_path {
-            let client_ca_cert_pem =
-                tokio::fs::read(client_ca_cert_path)
-                    .await
-                    .unwrap_or_else(|error| {
-                        panic!("error reading client ca cert from {client_ca_cert_path:?}: {error}")
-                    });
+        let mut client_tls_config = tonic::transport::ClientTlsConfig::new();
+        if tls_config.ca_only != Some(true) {
+            client_tls_config = client_tls_config.with_native_roots().with_webpki_roots();
+        }
+        // Secrets were loaded by config validation
+        if let (Some(cert), Some(key)) = (&tls_config.cert, &tls_config.key) {
+            let cert_pem = cert
+                .load()
+                .await
+                .unwrap_or_else(|error| panic!("error reading cert: {error}"));
+            let key_pem = tls::load_key(key, tls_config.key_passphrase.as_ref())
+                .await
+                .unwrap_or_else(|error| panic!("error reading key: {error}"));
+            let identity = tonic::transport::Identity::from_pem(cert_pem, key_pem);
+            client_tls_config = client_tls_config.identity(identity);
+        }
+        if let Some(sni_hostname) = &service_config.sni_hostname {
+            client_tls_config = client_tls_config.domain_name(sni_hostname);
+        }
+        if let Some(client_ca_cert) = &tls_config.client_ca_cert {
+            let client_ca_cert_pem = client_ca_cert
+                .load()
+                .await
+                .unwrap_or_else(|error| panic!("error reading client ca cert: {error}"));
             client_tls_config = client_tls_config
                 .ca_certificate(tonic::transport::Certificate::from_pem(client_ca_cert_pem));
         }
@@ -362,6 +826,7 @@ fn grpc_request_with_headers<T>(request: T, headers: HeaderMap) -> Request<T> {
 #[cfg(test)]
 mod tests {
     use errors::grpc_to_http_code;
+    use futures::{FutureExt, StreamExt};
 
     use super::*;
     use crate::{
@@ -369,6 +834,125 @@ mod tests {
         pb::grpc::health::v1::{HealthCheckResponse, health_check_response::ServingStatus},
     };
 
+    #[tokio::test]
+    async fn test_request_limiter() {
+        let request_limiter = RequestLimiter::new(Some(1));
+        let permit = request_limiter.acquire().await;
+        assert!(permit.is_some());
+        assert!(request_limiter.acquire().now_or_never().is_none());
+        drop(permit);
+        assert!(request_limiter.acquire().now_or_never().is_some());
+
+        // Requests are unlimited by default
+        let request_limiter = RequestLimiter::default();
+        assert!(request_limiter.acquire().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_request_limiter_streams() {
+        use std::convert::Infallible;
+
+        use axum::response::sse::{Event, Sse};
+        use mocktail::prelude::*;
+
+        use crate::{
+            pb::fmaas::{GenerationResponse, SingleGenerationRequest},
+            utils::test_server::serve,
+        };
+
+        let request_limiter = RequestLimiter::new(Some(2));
+
+        // Generation stream
+        let request = SingleGenerationRequest {
+            model_id: "tgis-model".into(),
+            ..Default::default()
+        };
+        let mut mocks = MockSet::new();
+        mocks.mock(|when, then| {
+            when.path("/fmaas.GenerationService/GenerateStream")
+                .pb(request.clone());
+            then.pb_stream(vec![GenerationResponse {
+                text: "Hi".into(),
+                ..Default::default()
+            }]);
+        });
+        let tgis_server = MockServer::new("tgis").grpc().with_mocks(mocks);
+        tgis_server.start().await.unwrap();
+        let tgis_client = TgisClient::new(&ServiceConfig::new(
+            "localhost".into(),
+            tgis_server.addr().unwrap().port(),
+        ))
+        .await
+        .with_request_limiter(request_limiter.clone());
+
+        // Server-sent events stream that never ends, and a detector
+        let app = axum::Router::new()
+            .route(
+                "/stream",
+                axum::routing::post(|| async {
+                    let event = Ok::<_, Infallible>(Event::default().data("Hi"));
+                    Sse::new(
+                        futures::stream::once(async { event }).chain(futures::stream::pending()),
+                    )
+                }),
+            )
+            .route("/detect", axum::routing::post(|| async { "\"ok\"" }));
+        let port = serve(app).await;
+        let http_client = create_http_client(port, &ServiceConfig::new("localhost".into(), port))
+            .await
+            .unwrap()
+            .with_shared_state(SharedClientState {
+                request_limiter: request_limiter.clone(),
+                ..Default::default()
+            });
+
+        // Open as many streams as the limit
+        let _generation_stream = tgis_client
+            .generate_stream(request, HeaderMap::new())
+            .await
+            .unwrap();
+        let _event_stream = http_client
+            .post(http_client.endpoint("/stream"), HeaderMap::new(), "a")
+            .await
+            .unwrap();
+
+        // Detector requests of the streams are not held up by them
+        let response = tokio::time::timeout(
+            Duration::from_secs(5),
+            http_client.post(http_client.endpoint("/detect"), HeaderMap::new(), "a"),
+        )
+        .await
+        .expect("detector request blocked by open streams")
+        .unwrap();
+        assert_eq!(response.json::<String>().await.unwrap(), "ok");
+    }
+
+    #[test]
+    fn test_log_sampler() {
+        // Requests are selected evenly
+        let sampler = LogSampler::new(Some(0.25));
+        let sampled = (0..100).map(|_| sampler.sample()).collect::<Vec<_>>();
+        assert_eq!(sampled.iter().filter(|sampled| **sampled).count(), 25);
+        assert!(
+            sampled
+                .chunks(4)
+                .all(|chunk| chunk == [false, false, false, true])
+        );
+
+        // Clones share the sample
+        let sampler = LogSampler::new(Some(0.5));
+        let clone = sampler.clone();
+        assert!(!sampler.sample());
+        assert!(clone.sample());
+
+        let sampler = LogSampler::new(Some(1.0));
+        assert!((0..10).all(|_| sampler.sample()));
+
+        // Requests are not logged by default
+        let sampler = LogSampler::default();
+        assert!((0..10).all(|_| !sampler.sample()));
+    }
+
     async fn mock_grpc_response(
         health_status: Option<i32>,
         tonic_status: Option<tonic::Status>,
@@ -472,4 +1056,14 @@ mod tests {
             assert!(!is_valid_hostname(hostname));
         }
     }
+
+    #[test]
+    fn test_closeable() {
+        let closeable = Closeable::new(1);
+        let clone = closeable.clone();
+        assert_eq!(clone.get(), Ok(1));
+        // Closing affects all clones
+        closeable.close();
+        assert_eq!(clone.get(), Err(Error::ClientShutdown));
+    }
 }
@@ -0,0 +1,230 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use hyper::StatusCode;
+use tracing::{info, warn};
+
+use super::Error;
+use crate::config::{CircuitBreakerConfig, ServiceConfig};
+
+/// State of a circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CircuitState {
+    /// Requests are sent.
+    Closed,
+    /// Requests fail without being sent until the cool-down period elapses.
+    Open,
+    /// The cool-down period elapsed, a trial request decides whether the circuit closes.
+    HalfOpen,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: usize,
+    /// When the circuit opened, unset while closed
+    opened_at: Option<Instant>,
+    /// When the latest trial request was let through while open
+    trial_at: Option<Instant>,
+}
+
+/// Permit of a request by a [`CircuitBreaker`], recording its outcome.
+#[derive(Debug, Clone, Copy)]
+pub struct Permit {
+    /// When the trial request was let through, if permitted as the trial of an open circuit
+    trial_at: Option<Instant>,
+}
+
+/// Circuit breaker of a client, failing requests without sending them after
+/// consecutive failures, so an unhealthy service does not add its full timeout
+/// latency to every request.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    service: String,
+    failure_threshold: usize,
+    cool_down: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(service: String, config: &CircuitBreakerConfig) -> Self {
+        Self {
+            service,
+            failure_threshold: config.failure_threshold.max(1),
+            cool_down: Duration::from_secs(config.cool_down),
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Creates the circuit breaker of `service`, if configured.
+    pub fn from_config(service: &ServiceConfig) -> Option<Arc<Self>> {
+        service
+            .circuit_breaker
+            .as_ref()
+            .map(|config| Arc::new(Self::new(service.hostname.clone(), config)))
+    }
+
+    /// Returns the state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        let state = self.state.lock().unwrap();
+        match state.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if opened_at.elapsed() < self.cool_down => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Permits a request, failing if the circuit is open. Once the cool-down period
+    /// elapses, a single trial request is permitted per cool-down period.
+    pub fn permit(&self) -> Result<Permit, Error> {
+        let mut state = self.state.lock().unwrap();
+        let Some(opened_at) = state.opened_at else {
+            return Ok(Permit { trial_at: None });
+        };
+        let trial_due = opened_at.elapsed() >= self.cool_down
+            && state
+                .trial_at
+                .is_none_or(|trial_at| trial_at.elapsed() >= self.cool_down);
+        if trial_due {
+            let trial_at = Instant::now();
+            state.trial_at = Some(trial_at);
+            return Ok(Permit {
+                trial_at: Some(trial_at),
+            });
+        }
+        Err(Error::Http {
+            code: StatusCode::SERVICE_UNAVAILABLE,
+            message: format!("circuit breaker of service `{}` is open", self.service),
+        })
+    }
+
+    /// Records the outcome of a permitted request. Server errors and timeouts are failures.
+    /// While the circuit is open, only the outcome of the latest trial request is recorded,
+    /// so requests permitted before the circuit opened neither close nor re-open it.
+    pub fn record(&self, permit: Permit, code: StatusCode) {
+        let failed = code.is_server_error() || code == StatusCode::REQUEST_TIMEOUT;
+        let mut state = self.state.lock().unwrap();
+        if state.opened_at.is_some()
+            && (permit.trial_at.is_none() || permit.trial_at != state.trial_at)
+        {
+            return;
+        }
+        if !failed {
+            if state.opened_at.is_some() {
+                info!(service = self.service, "circuit breaker closed");
+            }
+            *state = BreakerState::default();
+            return;
+        }
+        state.consecutive_failures += 1;
+        let reopen = state.opened_at.is_some();
+        if reopen || state.consecutive_failures == self.failure_threshold {
+            warn!(
+                service = self.service,
+                failures = state.consecutive_failures,
+                monotonic_counter.circuit_breaker_open_count = 1,
+                "circuit breaker opened"
+            );
+            state.opened_at = Some(Instant::now());
+            state.trial_at = None;
+        }
+    }
+
+    /// Runs `request` if permitted, recording its outcome. Shut down clients are not recorded.
+    pub async fn call<T, Fut>(&self, request: Fut) -> Result<T, Error>
+    where
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let permit = self.permit()?;
+        let result = request.await;
+        match &result {
+            Ok(_) => self.record(permit, StatusCode::OK),
+            Err(Error::ClientShutdown) => (),
+            Err(error) => self.record(permit, error.status_code()),
+        }
+        result
+    }
+}
+
+/// Runs `request` through `breaker`, if configured.
+pub async fn call<T, Fut>(breaker: Option<&CircuitBreaker>, request: Fut) -> Result<T, Error>
+where
+    Fut: Future<Output = Result<T, Error>>,
+{
+    match breaker {
+        Some(breaker) => breaker.call(request).await,
+        None => request.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(
+            "hap".into(),
+            &CircuitBreakerConfig {
+                failure_threshold: 2,
+                cool_down: 0,
+            },
+        );
+        let record = |code| breaker.record(breaker.permit().unwrap(), code);
+        record(StatusCode::SERVICE_UNAVAILABLE);
+        record(StatusCode::OK);
+        record(StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        // Client errors are not failures
+        record(StatusCode::BAD_REQUEST);
+        record(StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        let stale = breaker.permit().unwrap();
+        record(StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        // A trial request is permitted per cool-down period, a failed trial re-opens
+        let trial = breaker.permit().unwrap();
+        breaker.record(trial, StatusCode::BAD_GATEWAY);
+        assert!(breaker.state.lock().unwrap().trial_at.is_none());
+        // Requests permitted before the circuit opened do not close it
+        let trial = breaker.permit().unwrap();
+        breaker.record(stale, StatusCode::OK);
+        assert_ne!(breaker.state(), CircuitState::Closed);
+        breaker.record(trial, StatusCode::OK);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        let breaker = CircuitBreaker::new(
+            "hap".into(),
+            &CircuitBreakerConfig {
+                failure_threshold: 1,
+                cool_down: 60,
+            },
+        );
+        let permit = breaker.permit().unwrap();
+        breaker.record(permit, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(matches!(
+            breaker.permit(),
+            Err(Error::Http { code, .. }) if code == StatusCode::SERVICE_UNAVAILABLE
+        ));
+    }
+}
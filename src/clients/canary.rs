@@ -0,0 +1,168 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+use http_body_util::BodyExt;
+use hyper::{HeaderMap, Method, body::Bytes};
+use tracing::{Instrument, Span, debug, info, warn};
+use url::Url;
+
+use super::{Error, HttpClient, http::Response};
+use crate::config::CanaryMode;
+
+/// Arm of a canary rollout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arm {
+    Stable,
+    Canary,
+}
+
+impl Arm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Arm::Stable => "stable",
+            Arm::Canary => "canary",
+        }
+    }
+}
+
+/// Splits traffic between a service and a canary deployment.
+///
+/// The split is deterministic: of every 100 requests, exactly `traffic_percent`
+/// are selected for the canary, spread evenly.
+pub struct Canary {
+    client: HttpClient,
+    traffic_percent: u8,
+    mode: CanaryMode,
+    requests: AtomicU64,
+}
+
+impl Canary {
+    pub fn new(client: HttpClient, traffic_percent: u8, mode: CanaryMode) -> Self {
+        Self {
+            client,
+            traffic_percent: traffic_percent.min(100),
+            mode,
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    pub fn mode(&self) -> CanaryMode {
+        self.mode
+    }
+
+    /// Returns whether the next request is selected for the canary.
+    pub fn select(&self) -> bool {
+        let n = self.requests.fetch_add(1, Ordering::Relaxed);
+        let percent = self.traffic_percent as u64;
+        (n % 100 + 1) * percent / 100 > (n % 100) * percent / 100
+    }
+
+    /// Sends a request to the canary.
+    pub async fn send(
+        &self,
+        url: Url,
+        method: Method,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Result<Response, Error> {
+        let start = Instant::now();
+        let result = self
+            .client
+            .send_bytes(self.client.rebase(&url), method, headers, body, 1)
+            .await;
+        record(Arm::Canary, &result, start);
+        result
+    }
+
+    /// Sends a request to the canary in the background, logging its result.
+    pub fn shadow(&self, url: Url, method: Method, headers: HeaderMap, body: Bytes) {
+        let client = self.client.clone();
+        let url = client.rebase(&url);
+        tokio::spawn(
+            async move {
+                let start = Instant::now();
+                let result = client.send_bytes(url, method, headers, body, 1).await;
+                record(Arm::Canary, &result, start);
+                match result {
+                    Ok(response) => {
+                        let status = response.status();
+                        info!(arm = Arm::Canary.as_str(), %status, "shadow canary response");
+                        // The body may contain user text, so it is logged at debug level only.
+                        let body = response.0.into_body().collect().await;
+                        if let Ok(body) = body {
+                            debug!(
+                                arm = Arm::Canary.as_str(),
+                                body = %String::from_utf8_lossy(&body.to_bytes()),
+                                "shadow canary response body"
+                            );
+                        }
+                    }
+                    Err(error) => {
+                        warn!(arm = Arm::Canary.as_str(), %error, "shadow canary request failed")
+                    }
+                }
+            }
+            .instrument(Span::current()),
+        );
+    }
+
+    /// Closes the canary client.
+    pub fn shutdown(&self) {
+        self.client.shutdown();
+    }
+}
+
+/// Emits per-arm request metrics.
+pub fn record(arm: Arm, result: &Result<Response, Error>, start: Instant) {
+    let status = match result {
+        Ok(response) => response.status(),
+        Err(error) => error.status_code(),
+    };
+    info!(
+        arm = arm.as_str(),
+        status = status.as_u16(),
+        monotonic_counter.canary_arm_request_count = 1,
+        histogram.canary_arm_request_duration = start.elapsed().as_millis() as u64,
+        "canary arm request completed"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clients::create_http_client, config::ServiceConfig,
+        utils::test_server::ensure_crypto_provider,
+    };
+
+    #[tokio::test]
+    async fn test_canary_split() {
+        ensure_crypto_provider();
+        let client = create_http_client(8080, &ServiceConfig::new("localhost".into(), 8080))
+            .await
+            .unwrap();
+        for (traffic_percent, expected) in [(0, 0), (10, 10), (25, 25), (100, 100)] {
+            let canary = Canary::new(client.clone(), traffic_percent, CanaryMode::Serve);
+            let selected = (0..100).filter(|_| canary.select()).count();
+            assert_eq!(selected, expected);
+        }
+    }
+}
@@ -15,18 +15,28 @@
 
 */
 
-use std::pin::Pin;
+use std::{collections::VecDeque, pin::Pin, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use axum::http::HeaderMap;
-use futures::{Future, StreamExt, TryStreamExt};
+use axum::http::{HeaderMap, StatusCode};
+use futures::{Future, StreamExt};
 use ginepro::LoadBalancedChannel;
+use tokio::{
+    sync::mpsc,
+    time::{Instant, interval_at, sleep},
+};
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Code, Request, Response, Status, Streaming};
-use tracing::Span;
+use tracing::{Instrument, Span, warn};
 
 use super::{
-    BoxStream, Client, Error, create_grpc_client, errors::grpc_to_http_code,
-    grpc_request_with_headers, otel_grpc::OtelGrpcService,
+    BoxStream, Client, Closeable, Error, HeaderTemplates, RequestLimiter,
+    breaker::{self, CircuitBreaker},
+    create_grpc_client,
+    errors::grpc_to_http_code,
+    grpc_request_with_headers,
+    otel_grpc::OtelGrpcService,
+    retry::RetryPolicy,
 };
 use crate::{
     config::ServiceConfig,
@@ -42,50 +52,134 @@ use crate::{
     utils::trace::trace_context_from_grpc_response,
 };
 
-const DEFAULT_PORT: u16 = 8085;
+pub const DEFAULT_PORT: u16 = 8085;
 pub const MODEL_ID_HEADER_NAME: &str = "mm-model-id";
 /// Default chunker that returns span for entire text
 pub const DEFAULT_CHUNKER_ID: &str = "whole_doc_chunker";
+/// Maximum number of reconnects of a streaming session.
+const MAX_STREAM_RECONNECTS: usize = 3;
+/// Delay before reconnecting a streaming session, multiplied by the reconnect attempt.
+const STREAM_RECONNECT_BACKOFF: Duration = Duration::from_millis(200);
+/// Idle duration of a streaming session after which the chunker is pinged.
+const STREAM_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+/// Number of requests and responses buffered by a streaming session.
+const STREAM_BUFFER_SIZE: usize = 32;
 
 type StreamingTokenizationResult =
     Result<Response<Streaming<ChunkerTokenizationStreamResult>>, Status>;
+type StreamRequestSender = mpsc::Sender<BidiStreamingChunkerTokenizationTaskRequest>;
 
 #[derive(Clone)]
 pub struct ChunkerClient {
-    client: ChunkersServiceClient<OtelGrpcService<LoadBalancedChannel>>,
-    health_client: HealthClient<OtelGrpcService<LoadBalancedChannel>>,
+    client: Closeable<ChunkersServiceClient<OtelGrpcService<LoadBalancedChannel>>>,
+    health_client: Closeable<HealthClient<OtelGrpcService<LoadBalancedChannel>>>,
+    headers: HeaderTemplates,
+    retry: RetryPolicy,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    request_limiter: RequestLimiter,
 }
 
 impl ChunkerClient {
     pub async fn new(config: &ServiceConfig) -> Self {
-        let client = create_grpc_client(DEFAULT_PORT, config, ChunkersServiceClient::new).await;
-        let health_client = create_grpc_client(DEFAULT_PORT, config, HealthClient::new).await;
+        let client = Closeable::new(
+            create_grpc_client(DEFAULT_PORT, config, ChunkersServiceClient::new).await,
+        );
+        let health_client =
+            Closeable::new(create_grpc_client(DEFAULT_PORT, config, HealthClient::new).await);
         Self {
             client,
             health_client,
+            headers: config.headers.clone(),
+            retry: RetryPolicy::from(config.retry.as_ref()),
+            circuit_breaker: CircuitBreaker::from_config(config),
+            request_limiter: RequestLimiter::default(),
         }
     }
 
+    /// Waits for a permit of `request_limiter` before sending requests.
+    pub fn with_request_limiter(mut self, request_limiter: RequestLimiter) -> Self {
+        self.request_limiter = request_limiter;
+        self
+    }
+
     pub async fn tokenization_task_predict(
         &self,
         model_id: &str,
         request: ChunkerTokenizationTaskRequest,
     ) -> Result<TokenizationResults, Error> {
-        let mut client = self.client.clone();
-        let request = request_with_headers(request, model_id);
-        let response = client.chunker_tokenization_task_predict(request).await?;
+        let headers = self.headers.apply(HeaderMap::new());
+        let response = breaker::call(
+            self.circuit_breaker.as_deref(),
+            self.retry.run(|_| {
+                let request = request_with_headers(request.clone(), model_id, headers.clone());
+                let client = self.client.get();
+                let request_limiter = &self.request_limiter;
+                async move {
+                    let mut client = client?;
+                    let _permit = request_limiter.acquire().await;
+                    Ok(client.chunker_tokenization_task_predict(request).await?)
+                }
+            }),
+        )
+        .await?;
         let span = Span::current();
         trace_context_from_grpc_response(&span, &response);
         Ok(response.into_inner())
     }
 
+    /// Opens a bidi streaming session that reconnects on transient failures.
+    ///
+    /// Requests are buffered until the chunker has processed their text. If the
+    /// session fails, a new session is opened and unprocessed text is replayed, with
+    /// response offsets adjusted to the original session. The chunker is pinged
+    /// with health checks while the session is idle so dead connections are detected.
     pub async fn bidi_streaming_tokenization_task_predict(
         &self,
         model_id: &str,
         request_stream: BoxStream<BidiStreamingChunkerTokenizationTaskRequest>,
     ) -> Result<BoxStream<Result<ChunkerTokenizationStreamResult, Error>>, Error> {
-        let mut client = self.client.clone();
-        let request = request_with_headers(request_stream, model_id);
+        let (request_tx, response_stream) = self
+            .retry
+            .run(|_| self.open_stream(model_id, Vec::new()))
+            .await?;
+        let (response_tx, response_rx) = mpsc::channel(STREAM_BUFFER_SIZE);
+        let session = StreamSession {
+            client: self.clone(),
+            model_id: model_id.to_string(),
+            request_tx: Some(request_tx),
+            response_stream,
+            response_tx,
+            unprocessed: Unprocessed::default(),
+        };
+        tokio::spawn(session.run(request_stream).in_current_span());
+        Ok(ReceiverStream::new(response_rx).boxed())
+    }
+
+    /// Opens a bidi streaming session, sending `replay` requests first.
+    ///
+    /// Sessions are long-lived, so they are not limited by `request_limiter`.
+    async fn open_stream(
+        &self,
+        model_id: &str,
+        replay: Vec<BidiStreamingChunkerTokenizationTaskRequest>,
+    ) -> Result<
+        (
+            StreamRequestSender,
+            Streaming<ChunkerTokenizationStreamResult>,
+        ),
+        Error,
+    > {
+        let mut client = self.client.get()?;
+        let (request_tx, request_rx) = mpsc::channel(STREAM_BUFFER_SIZE);
+        let request_stream: BoxStream<BidiStreamingChunkerTokenizationTaskRequest> =
+            futures::stream::iter(replay)
+                .chain(ReceiverStream::new(request_rx))
+                .boxed();
+        let request = request_with_headers(
+            request_stream,
+            model_id,
+            self.headers.apply(HeaderMap::new()),
+        );
         // NOTE: this is an ugly workaround to avoid bogus higher-ranked lifetime errors.
         // https://github.com/rust-lang/rust/issues/110338
         let response_stream_fut: Pin<Box<dyn Future<Output = StreamingTokenizationResult> + Send>> =
@@ -93,20 +187,245 @@ impl ChunkerClient {
         let response_stream = response_stream_fut.await?;
         let span = Span::current();
         trace_context_from_grpc_response(&span, &response_stream);
-        Ok(response_stream.into_inner().map_err(Into::into).boxed())
+        Ok((request_tx, response_stream.into_inner()))
+    }
+}
+
+/// A bidi streaming chunker session that survives chunker restarts.
+struct StreamSession {
+    client: ChunkerClient,
+    model_id: String,
+    /// Request sender of the current session, unset once all requests are sent
+    request_tx: Option<StreamRequestSender>,
+    /// Response stream of the current session
+    response_stream: Streaming<ChunkerTokenizationStreamResult>,
+    response_tx: mpsc::Sender<Result<ChunkerTokenizationStreamResult, Error>>,
+    unprocessed: Unprocessed,
+}
+
+impl StreamSession {
+    async fn run(
+        mut self,
+        mut request_stream: BoxStream<BidiStreamingChunkerTokenizationTaskRequest>,
+    ) {
+        // Request waiting for room in the request channel of the current session
+        let mut pending = None;
+        let mut input_done = false;
+        let mut reconnects = 0;
+        let mut keepalive = interval_at(
+            Instant::now() + STREAM_KEEPALIVE_INTERVAL,
+            STREAM_KEEPALIVE_INTERVAL,
+        );
+        loop {
+            let mut error = tokio::select! {
+                request = request_stream.next(), if !input_done && pending.is_none() => {
+                    match request {
+                        Some(request) => {
+                            self.unprocessed.push(request.clone());
+                            pending = Some(request);
+                        }
+                        None => {
+                            // Close the session's request stream
+                            input_done = true;
+                            self.request_tx = None;
+                        }
+                    }
+                    continue;
+                }
+                // Responses are read while the session's request channel is full
+                permit = reserve(self.request_tx.as_ref()), if pending.is_some() => {
+                    // Requests of closed sessions are replayed on reconnect
+                    let request = pending.take().unwrap();
+                    if let Some(permit) = permit {
+                        permit.send(request);
+                    }
+                    continue;
+                }
+                result = self.response_stream.next() => {
+                    keepalive.reset();
+                    match result {
+                        Some(Ok(response)) => {
+                            if self.response_tx.send(Ok(self.unprocessed.process(response))).await.is_err() {
+                                // Receiver dropped
+                                return;
+                            }
+                            continue;
+                        }
+                        Some(Err(status)) if is_retryable(status.code()) => Error::from(status),
+                        // Rate limited sessions reconnect if the retry policy retries 429
+                        Some(Err(status))
+                            if status.code() == Code::ResourceExhausted
+                                && self.client.retry.retries(1, StatusCode::TOO_MANY_REQUESTS) =>
+                        {
+                            Error::from(status)
+                        }
+                        Some(Err(status)) => {
+                            let _ = self.response_tx.send(Err(status.into())).await;
+                            return;
+                        }
+                        None => return,
+                    }
+                }
+                _ = keepalive.tick() => {
+                    let health = self.client.health().await;
+                    if health.status == HealthStatus::Healthy {
+                        continue;
+                    }
+                    Error::Grpc {
+                        code: health.code,
+                        message: "chunker keepalive ping failed".into(),
+                    }
+                }
+            };
+            // Reconnect, replaying unprocessed text
+            loop {
+                if reconnects >= MAX_STREAM_RECONNECTS {
+                    let _ = self.response_tx.send(Err(error)).await;
+                    return;
+                }
+                reconnects += 1;
+                warn!(
+                    model_id = %self.model_id,
+                    reconnects,
+                    %error,
+                    "chunker stream failed, reconnecting"
+                );
+                // Wait at least for the Retry-After of rate limited sessions, failing if it
+                // exceeds the maximum backoff of the retry policy
+                let backoff = STREAM_RECONNECT_BACKOFF * reconnects as u32;
+                let delay = match error.retry_after() {
+                    Some(retry_after) => {
+                        match self.client.retry.delay(reconnects, Some(retry_after)) {
+                            Some(delay) => delay.max(backoff),
+                            None => {
+                                let _ = self.response_tx.send(Err(error)).await;
+                                return;
+                            }
+                        }
+                    }
+                    None => backoff,
+                };
+                sleep(delay).await;
+                let replay = self.unprocessed.replay();
+                match self.client.open_stream(&self.model_id, replay).await {
+                    Ok((request_tx, response_stream)) => {
+                        self.unprocessed.reconnected();
+                        // The pending request is replayed
+                        pending = None;
+                        self.request_tx = (!input_done).then_some(request_tx);
+                        self.response_stream = response_stream;
+                        keepalive.reset();
+                        break;
+                    }
+                    Err(reconnect_error) => error = reconnect_error,
+                }
+            }
+        }
+    }
+}
+
+/// Reserves room for a request in the request channel of a session, if open.
+async fn reserve(
+    request_tx: Option<&StreamRequestSender>,
+) -> Option<mpsc::Permit<'_, BidiStreamingChunkerTokenizationTaskRequest>> {
+    request_tx?.reserve().await.ok()
+}
+
+/// Requests of a streaming session with text not yet processed by the chunker.
+///
+/// Offsets are char offsets of the text of all requests of the original session.
+#[derive(Debug, Default)]
+struct Unprocessed {
+    /// Requests with text not yet processed, with their offsets (start, end)
+    requests: VecDeque<(usize, usize, BidiStreamingChunkerTokenizationTaskRequest)>,
+    /// Offset of all text sent
+    sent_offset: usize,
+    /// Offset up to which text has been processed
+    processed_offset: usize,
+    /// Offset at which the text of the current session begins
+    session_offset: usize,
+}
+
+impl Unprocessed {
+    /// Adds a request sent to the chunker.
+    fn push(&mut self, request: BidiStreamingChunkerTokenizationTaskRequest) {
+        let end = self.sent_offset + request.text_stream.chars().count();
+        self.requests.push_back((self.sent_offset, end, request));
+        self.sent_offset = end;
+    }
+
+    /// Adjusts the offsets of a response of the current session to the original session,
+    /// releasing requests with processed text.
+    fn process(
+        &mut self,
+        mut response: ChunkerTokenizationStreamResult,
+    ) -> ChunkerTokenizationStreamResult {
+        let session_offset = self.session_offset as i64;
+        response.start_index += session_offset;
+        response.processed_index += session_offset;
+        for token in &mut response.results {
+            token.start += session_offset;
+            token.end += session_offset;
+        }
+        self.processed_offset = self
+            .processed_offset
+            .max(response.processed_index.max(0) as usize);
+        while self
+            .requests
+            .front()
+            .is_some_and(|(_, end, _)| *end <= self.processed_offset)
+        {
+            self.requests.pop_front();
+        }
+        response
+    }
+
+    /// Returns the requests replaying unprocessed text. The text of the first request is
+    /// trimmed to the processed offset, so processed text is not chunked again.
+    fn replay(&self) -> Vec<BidiStreamingChunkerTokenizationTaskRequest> {
+        self.requests
+            .iter()
+            .map(|(start, _, request)| {
+                let processed = self.processed_offset.saturating_sub(*start);
+                BidiStreamingChunkerTokenizationTaskRequest {
+                    text_stream: request.text_stream.chars().skip(processed).collect(),
+                    input_index_stream: request.input_index_stream,
+                }
+            })
+            .collect()
+    }
+
+    /// Starts a new session at the processed offset, as replayed.
+    fn reconnected(&mut self) {
+        self.session_offset = self.processed_offset;
     }
 }
 
+/// Returns whether a streaming session failure is transient.
+fn is_retryable(code: Code) -> bool {
+    matches!(code, Code::Unavailable | Code::Unknown | Code::Aborted)
+}
+
 #[async_trait]
 impl Client for ChunkerClient {
     fn name(&self) -> &str {
         "chunker"
     }
 
+    fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.circuit_breaker.as_deref()
+    }
+
     async fn health(&self) -> HealthCheckResult {
-        let mut client = self.health_client.clone();
+        let mut client = match self.health_client.get() {
+            Ok(client) => client,
+            Err(error) => return error.into(),
+        };
         let response = client
-            .check(HealthCheckRequest { service: "".into() })
+            .check(grpc_request_with_headers(
+                HealthCheckRequest { service: "".into() },
+                self.headers.apply(HeaderMap::new()),
+            ))
             .await;
         let code = match response {
             Ok(_) => Code::Ok,
@@ -126,14 +445,120 @@ impl Client for ChunkerClient {
             reason: None,
         }
     }
+
+    async fn shutdown(&self) {
+        self.client.close();
+        self.health_client.close();
+    }
 }
 
-/// Turns a chunker client gRPC request body of type `T` into a `tonic::Request<T>` with headers.
+/// Turns a chunker client gRPC request body of type `T` and headers into a `tonic::Request<T>`.
 /// Adds the provided `model_id` as a header as well as injects `traceparent` from the current span.
-fn request_with_headers<T>(request: T, model_id: &str) -> Request<T> {
-    let mut request = grpc_request_with_headers(request, HeaderMap::new());
+fn request_with_headers<T>(request: T, model_id: &str, headers: HeaderMap) -> Request<T> {
+    let mut request = grpc_request_with_headers(request, headers);
     request
         .metadata_mut()
         .insert(MODEL_ID_HEADER_NAME, model_id.parse().unwrap());
     request
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::caikit_data_model::nlp::Token;
+
+    fn request(text: &str, index: i64) -> BidiStreamingChunkerTokenizationTaskRequest {
+        BidiStreamingChunkerTokenizationTaskRequest {
+            text_stream: text.into(),
+            input_index_stream: index,
+        }
+    }
+
+    fn response(start: i64, end: i64, text: &str) -> ChunkerTokenizationStreamResult {
+        ChunkerTokenizationStreamResult {
+            results: vec![Token {
+                start,
+                end,
+                text: text.into(),
+            }],
+            token_count: 1,
+            processed_index: end,
+            start_index: start,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_unprocessed_releases_processed_requests() {
+        let mut unprocessed = Unprocessed::default();
+        unprocessed.push(request("Hi there. ", 0));
+        unprocessed.push(request("How are ", 1));
+        unprocessed.push(request("you?", 2));
+        assert_eq!(unprocessed.requests.len(), 3);
+
+        // Processed text within a request does not release it
+        unprocessed.process(response(0, 3, "Hi "));
+        assert_eq!(unprocessed.requests.len(), 3);
+
+        let processed = unprocessed.process(response(0, 10, "Hi there. "));
+        assert_eq!(processed, response(0, 10, "Hi there. "));
+        assert_eq!(unprocessed.requests.len(), 2);
+        assert_eq!(unprocessed.requests[0].2, request("How are ", 1));
+    }
+
+    #[test]
+    fn test_unprocessed_replay() {
+        let mut unprocessed = Unprocessed::default();
+        unprocessed.push(request("Hi there. ", 0));
+        unprocessed.push(request("Héllo wörld. ", 1));
+        unprocessed.push(request("Bye.", 2));
+        unprocessed.process(response(0, 10, "Hi there. "));
+        unprocessed.process(response(10, 16, "Héllo "));
+
+        // Processed text of the first unprocessed request is not replayed
+        let replay = unprocessed.replay();
+        assert_eq!(replay, vec![request("wörld. ", 1), request("Bye.", 2)]);
+        unprocessed.reconnected();
+
+        // Responses of the new session are offset to the original session
+        let processed = unprocessed.process(response(0, 7, "wörld. "));
+        assert_eq!(processed, response(16, 23, "wörld. "));
+        let processed = unprocessed.process(response(7, 11, "Bye."));
+        assert_eq!(processed, response(23, 27, "Bye."));
+        assert!(unprocessed.requests.is_empty());
+        assert!(unprocessed.replay().is_empty());
+    }
+
+    #[test]
+    fn test_unprocessed_replay_after_reconnect() {
+        let mut unprocessed = Unprocessed::default();
+        unprocessed.push(request("One. ", 0));
+        unprocessed.push(request("Two. ", 1));
+        unprocessed.push(request("Three.", 2));
+        unprocessed.process(response(0, 5, "One. "));
+        unprocessed.replay();
+        unprocessed.reconnected();
+
+        // A second reconnect replays relative to the original session
+        unprocessed.process(response(0, 5, "Two. "));
+        let replay = unprocessed.replay();
+        assert_eq!(replay, vec![request("Three.", 2)]);
+        unprocessed.reconnected();
+        let processed = unprocessed.process(response(0, 6, "Three."));
+        assert_eq!(processed, response(10, 16, "Three."));
+    }
+
+    #[test]
+    fn test_unprocessed_replay_all_processed() {
+        let mut unprocessed = Unprocessed::default();
+        unprocessed.push(request("Done.", 0));
+        unprocessed.process(response(0, 5, "Done."));
+        assert!(unprocessed.replay().is_empty());
+        unprocessed.reconnected();
+
+        // Text sent after reconnecting begins at the end of all text sent
+        unprocessed.push(request("More.", 1));
+        let processed = unprocessed.process(response(0, 5, "More."));
+        assert_eq!(processed, response(5, 10, "More."));
+    }
+}
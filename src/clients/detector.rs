@@ -15,17 +15,18 @@
 
 */
 
-use std::fmt::Debug;
+use std::{fmt::Debug, time::Instant};
 
 use axum::http::HeaderMap;
 use http::header::CONTENT_TYPE;
 use hyper::StatusCode;
 use serde::Deserialize;
+use tracing::info;
 use url::Url;
 
 use super::{
     Error,
-    http::{HttpClientExt, JSON_CONTENT_TYPE, RequestBody, ResponseBody},
+    http::{HttpClientExt, JSON_CONTENT_TYPE, RequestBody, Response, ResponseBody},
 };
 
 pub mod text_contents;
@@ -36,8 +37,10 @@ pub mod text_context_doc;
 pub use text_context_doc::*;
 pub mod text_generation;
 pub use text_generation::*;
+pub mod token_offsets;
+pub use token_offsets::*;
 
-const DEFAULT_PORT: u16 = 8080;
+pub const DEFAULT_PORT: u16 = 8080;
 pub const DETECTOR_ID_HEADER_NAME: &str = "detector-id";
 const MODEL_HEADER_NAME: &str = "x-model-name";
 
@@ -73,6 +76,16 @@ pub trait DetectorClientExt: HttpClientExt {
         request: impl RequestBody,
     ) -> Result<U, Error>;
 
+    /// Like `post_to_detector`, but also accepts `206 Partial Content` responses.
+    /// Returns the response headers of partial responses alongside the body.
+    async fn post_to_detector_allow_partial<U: ResponseBody>(
+        &self,
+        model_id: &str,
+        url: Url,
+        headers: HeaderMap,
+        request: impl RequestBody,
+    ) -> Result<(U, Option<HeaderMap>), Error>;
+
     /// Wraps call to inner HTTP client endpoint function.
     fn endpoint(&self, path: &str) -> Url;
 }
@@ -82,31 +95,96 @@ impl<C: DetectorClient + HttpClientExt> DetectorClientExt for C {
         &self,
         model_id: &str,
         url: Url,
-        mut headers: HeaderMap,
+        headers: HeaderMap,
         request: impl RequestBody,
     ) -> Result<U, Error> {
-        headers.append(DETECTOR_ID_HEADER_NAME, model_id.parse().unwrap());
-        headers.append(CONTENT_TYPE, JSON_CONTENT_TYPE);
-        // Header used by a router component, if available
-        headers.append(MODEL_HEADER_NAME, model_id.parse().unwrap());
-
-        let response = self.inner().post(url, headers, request).await?;
-
-        let status = response.status();
-        match status {
-            StatusCode::OK => Ok(response.json().await?),
-            _ => Err(response
-                .json::<DetectorError>()
-                .await
-                .unwrap_or(DetectorError {
-                    code: status.as_u16(),
-                    message: "".into(),
-                })
-                .into()),
+        let start = Instant::now();
+        let result = async {
+            let response = send_to_detector(self, model_id, url, headers, request).await?;
+            let status = response.status();
+            match status {
+                StatusCode::OK => Ok(response.json().await?),
+                _ => Err(detector_error(response).await),
+            }
+        }
+        .await;
+        record_detector_request(model_id, start, &result);
+        result
+    }
+
+    async fn post_to_detector_allow_partial<U: ResponseBody>(
+        &self,
+        model_id: &str,
+        url: Url,
+        headers: HeaderMap,
+        request: impl RequestBody,
+    ) -> Result<(U, Option<HeaderMap>), Error> {
+        let start = Instant::now();
+        let result = async {
+            let response = send_to_detector(self, model_id, url, headers, request).await?;
+            let status = response.status();
+            match status {
+                StatusCode::OK => Ok((response.json().await?, None)),
+                StatusCode::PARTIAL_CONTENT => {
+                    let headers = response.headers().clone();
+                    Ok((response.json().await?, Some(headers)))
+                }
+                _ => Err(detector_error(response).await),
+            }
         }
+        .await;
+        record_detector_request(model_id, start, &result);
+        result
     }
 
     fn endpoint(&self, path: &str) -> Url {
         self.inner().endpoint(path)
     }
 }
+
+/// Sends a request to a detector, injecting detector headers.
+async fn send_to_detector<C: HttpClientExt>(
+    client: &C,
+    model_id: &str,
+    url: Url,
+    mut headers: HeaderMap,
+    request: impl RequestBody,
+) -> Result<Response, Error> {
+    headers.append(DETECTOR_ID_HEADER_NAME, model_id.parse().unwrap());
+    headers.append(CONTENT_TYPE, JSON_CONTENT_TYPE);
+    // Header used by a router component, if available
+    headers.append(MODEL_HEADER_NAME, model_id.parse().unwrap());
+    client.inner().post(url, headers, request).await
+}
+
+/// Records the latency of a detector request, counting failed requests by status code.
+fn record_detector_request<T>(detector_id: &str, start: Instant, result: &Result<T, Error>) {
+    let duration = start.elapsed().as_millis() as u64;
+    match result {
+        Ok(_) => info!(
+            monotonic_counter.detector_request_count = 1,
+            histogram.detector_request_duration = duration,
+            detector_id,
+        ),
+        Err(error) => info!(
+            monotonic_counter.detector_request_count = 1,
+            monotonic_counter.detector_request_error_count = 1,
+            histogram.detector_request_duration = duration,
+            detector_id,
+            code = error.status_code().as_u16(),
+        ),
+    }
+}
+
+/// Converts a detector error response to an error.
+async fn detector_error(response: Response) -> Error {
+    let status = response.status();
+    response
+        .json::<DetectorError>()
+        .await
+        .unwrap_or(DetectorError {
+            code: status.as_u16(),
+            message: "".into(),
+        })
+        .into()
+}
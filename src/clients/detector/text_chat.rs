@@ -23,11 +23,13 @@ use tracing::info;
 use super::{DEFAULT_PORT, DetectorClient, DetectorClientExt};
 use crate::{
     clients::{
-        Client, Error, HttpClient, create_http_client,
+        Client, Error, HttpClient, SharedClientState,
+        breaker::CircuitBreaker,
+        create_http_client, create_routed_http_client,
         http::HttpClientExt,
         openai::{Message, Tool},
     },
-    config::ServiceConfig,
+    config::{BackendConfig, CanaryConfig, ServiceConfig, SizeLimits},
     health::HealthCheckResult,
     models::{DetectionResult, DetectorParams},
 };
@@ -38,14 +40,22 @@ const CHAT_DETECTOR_ENDPOINT: &str = "/api/v1/text/chat";
 pub struct TextChatDetectorClient {
     client: HttpClient,
     health_client: Option<HttpClient>,
+    endpoint_path: String,
 }
 
 impl TextChatDetectorClient {
     pub async fn new(
         config: &ServiceConfig,
         health_config: Option<&ServiceConfig>,
+        endpoint_path: Option<&str>,
+        backends: &[BackendConfig],
+        canary: Option<&CanaryConfig>,
+        size_limits: SizeLimits,
+        shared: &SharedClientState,
     ) -> Result<Self, Error> {
-        let client = create_http_client(DEFAULT_PORT, config).await?;
+        let client =
+            create_routed_http_client(DEFAULT_PORT, config, backends, canary, size_limits, shared)
+                .await?;
         let health_client = if let Some(health_config) = health_config {
             Some(create_http_client(DEFAULT_PORT, health_config).await?)
         } else {
@@ -54,6 +64,7 @@ impl TextChatDetectorClient {
         Ok(Self {
             client,
             health_client,
+            endpoint_path: endpoint_path.unwrap_or(CHAT_DETECTOR_ENDPOINT).to_string(),
         })
     }
 
@@ -67,7 +78,7 @@ impl TextChatDetectorClient {
         request: ChatDetectionRequest,
         headers: HeaderMap,
     ) -> Result<Vec<DetectionResult>, Error> {
-        let url = self.endpoint(CHAT_DETECTOR_ENDPOINT);
+        let url = self.endpoint(&self.endpoint_path);
         info!("sending text chat detector request to {}", url);
         self.post_to_detector(model_id, url, headers, request).await
     }
@@ -79,6 +90,10 @@ impl Client for TextChatDetectorClient {
         "text_chat_detector"
     }
 
+    fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.client.circuit_breaker()
+    }
+
     async fn health(&self) -> HealthCheckResult {
         if let Some(health_client) = &self.health_client {
             health_client.health().await
@@ -86,6 +101,13 @@ impl Client for TextChatDetectorClient {
             self.client.health().await
         }
     }
+
+    async fn shutdown(&self) {
+        self.client.shutdown();
+        if let Some(health_client) = &self.health_client {
+            health_client.shutdown();
+        }
+    }
 }
 
 impl DetectorClient for TextChatDetectorClient {}
@@ -15,43 +15,75 @@
 
 */
 
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, path::Path, sync::Arc, time::Instant};
 
 use async_trait::async_trait;
-use hyper::HeaderMap;
+use hyper::{HeaderMap, StatusCode};
 use serde::{Deserialize, Serialize};
 use tracing::info;
 
-use super::{DEFAULT_PORT, DetectorClient, DetectorClientExt};
+use super::{DEFAULT_PORT, DetectorClient, DetectorClientExt, TokenOffsets};
 use crate::{
-    clients::{Client, Error, HttpClient, create_http_client, http::HttpClientExt},
-    config::ServiceConfig,
+    clients::{
+        Client, Error, HttpClient, SharedClientState, breaker::CircuitBreaker, create_http_client,
+        create_routed_http_client, http::HttpClientExt,
+    },
+    config::{BackendConfig, CanaryConfig, DetectorResponseFormat, ServiceConfig, SizeLimits},
     health::HealthCheckResult,
-    models::{DetectorParams, EvidenceObj, Metadata},
+    models::{DetectionChunk, DetectorParams, EvidenceObj, Metadata},
+    utils::{debug_info, json::deserialize_score, single_flight::SingleFlight},
 };
 
 const CONTENTS_DETECTOR_ENDPOINT: &str = "/api/v1/text/contents";
+/// Header of `206 Partial Content` responses with the number of leading
+/// chars of each content analyzed, comma-separated.
+pub const ANALYZED_LENGTHS_HEADER_NAME: &str = "x-analyzed-lengths";
+
+type TextContentsResult = Result<ContentAnalysisResult, Error>;
 
 #[derive(Clone)]
 pub struct TextContentsDetectorClient {
     client: HttpClient,
     health_client: Option<HttpClient>,
+    endpoint_path: String,
+    response_format: DetectorResponseFormat,
+    /// Translation of token spans, for detectors returning token indices
+    token_offsets: Option<TokenOffsets>,
+    /// In-flight requests, keyed by detector, request and headers
+    in_flight: Arc<SingleFlight<Vec<u8>, TextContentsResult>>,
 }
 
 impl TextContentsDetectorClient {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         config: &ServiceConfig,
         health_config: Option<&ServiceConfig>,
+        endpoint_path: Option<&str>,
+        backends: &[BackendConfig],
+        canary: Option<&CanaryConfig>,
+        size_limits: SizeLimits,
+        response_format: DetectorResponseFormat,
+        tokenizer_path: Option<&Path>,
+        shared: &SharedClientState,
     ) -> Result<Self, Error> {
-        let client = create_http_client(DEFAULT_PORT, config).await?;
+        let client =
+            create_routed_http_client(DEFAULT_PORT, config, backends, canary, size_limits, shared)
+                .await?;
         let health_client = if let Some(health_config) = health_config {
             Some(create_http_client(DEFAULT_PORT, health_config).await?)
         } else {
             None
         };
+        let token_offsets = tokenizer_path.map(TokenOffsets::from_file).transpose()?;
         Ok(Self {
             client,
             health_client,
+            endpoint_path: endpoint_path
+                .unwrap_or(CONTENTS_DETECTOR_ENDPOINT)
+                .to_string(),
+            response_format,
+            token_offsets,
+            in_flight: Arc::new(SingleFlight::new()),
         })
     }
 
@@ -64,10 +96,61 @@ impl TextContentsDetectorClient {
         model_id: &str,
         request: ContentAnalysisRequest,
         headers: HeaderMap,
-    ) -> Result<Vec<Vec<ContentAnalysisResponse>>, Error> {
-        let url = self.endpoint(CONTENTS_DETECTOR_ENDPOINT);
+    ) -> TextContentsResult {
+        let url = self.endpoint(&self.endpoint_path);
         info!("sending text content detector request to {}", url);
-        self.post_to_detector(model_id, url, headers, request).await
+        // Identical concurrent requests are coalesced into a single call
+        let key = coalescing_key(model_id, &request, &headers);
+        let client = self.clone();
+        let detector_id = model_id;
+        let model_id = model_id.to_string();
+        let start = Instant::now();
+        let (result, coalesced) = self
+            .in_flight
+            .run(key, async move {
+                let n_contents = request.contents.len();
+                let contents = client
+                    .token_offsets
+                    .is_some()
+                    .then(|| request.contents.clone());
+                let (mut detections, partial_headers) = match client.response_format {
+                    DetectorResponseFormat::Canonical => {
+                        client
+                            .post_to_detector_allow_partial(&model_id, url, headers, request)
+                            .await?
+                    }
+                    DetectorResponseFormat::LegacyTokenClassification => {
+                        let (results, partial_headers): (
+                            Vec<Vec<LegacyTokenClassificationResponse>>,
+                            _,
+                        ) = client
+                            .post_to_detector_allow_partial(&model_id, url, headers, request)
+                            .await?;
+                        (from_legacy_responses(&model_id, results)?, partial_headers)
+                    }
+                };
+                if let (Some(token_offsets), Some(contents)) = (&client.token_offsets, contents) {
+                    for (content, detections) in contents.iter().zip(detections.iter_mut()) {
+                        token_offsets.translate(&model_id, content, detections)?;
+                    }
+                }
+                let analyzed_lengths = partial_headers
+                    .map(|headers| analyzed_lengths(&headers, n_contents))
+                    .transpose()?;
+                Ok(ContentAnalysisResult {
+                    detections,
+                    analyzed_lengths,
+                })
+            })
+            .await;
+        debug_info::record_detector_call(detector_id, start.elapsed(), coalesced);
+        if coalesced {
+            info!(
+                monotonic_counter.coalesced_detector_request_count = 1,
+                "coalesced text content detector request"
+            );
+        }
+        result
     }
 }
 
@@ -77,6 +160,10 @@ impl Client for TextContentsDetectorClient {
         "text_contents_detector"
     }
 
+    fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.client.circuit_breaker()
+    }
+
     async fn health(&self) -> HealthCheckResult {
         if let Some(health_client) = &self.health_client {
             health_client.health().await
@@ -84,6 +171,13 @@ impl Client for TextContentsDetectorClient {
             self.client.health().await
         }
     }
+
+    async fn shutdown(&self) {
+        self.client.shutdown();
+        if let Some(health_client) = &self.health_client {
+            health_client.shutdown();
+        }
+    }
 }
 
 impl DetectorClient for TextContentsDetectorClient {}
@@ -94,20 +188,85 @@ impl HttpClientExt for TextContentsDetectorClient {
     }
 }
 
+/// Builds a key identifying a text contents request.
+/// Header values are included as-is so requests with different credentials are never coalesced.
+fn coalescing_key(
+    model_id: &str,
+    request: &ContentAnalysisRequest,
+    headers: &HeaderMap,
+) -> Vec<u8> {
+    let mut key = serde_json::to_vec(&(model_id, request)).unwrap();
+    for (name, value) in headers {
+        key.push(b'\n');
+        key.extend_from_slice(name.as_str().as_bytes());
+        key.push(b':');
+        key.extend_from_slice(value.as_bytes());
+    }
+    key
+}
+
+/// Parses the analyzed lengths of a partial response.
+pub fn analyzed_lengths(headers: &HeaderMap, n_contents: usize) -> Result<Vec<usize>, Error> {
+    headers
+        .get(ANALYZED_LENGTHS_HEADER_NAME)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value
+                .split(',')
+                .map(|length| length.trim().parse::<usize>().ok())
+                .collect::<Option<Vec<_>>>()
+        })
+        .filter(|lengths| lengths.len() == n_contents)
+        .ok_or_else(|| Error::Http {
+            code: StatusCode::INTERNAL_SERVER_ERROR,
+            message: format!(
+                "partial detector response has a missing or invalid `{ANALYZED_LENGTHS_HEADER_NAME}` header"
+            ),
+        })
+}
+
+/// Maps legacy token classification results to canonical detections, checking spans are valid.
+fn from_legacy_responses(
+    detector_id: &str,
+    results: Vec<Vec<LegacyTokenClassificationResponse>>,
+) -> Result<Vec<Vec<ContentAnalysisResponse>>, Error> {
+    results
+        .into_iter()
+        .map(|results| {
+            results
+                .into_iter()
+                .map(|result| {
+                    if result.start > result.end {
+                        return Err(Error::Http {
+                            code: StatusCode::INTERNAL_SERVER_ERROR,
+                            message: format!(
+                                "legacy response of detector `{detector_id}` has an invalid span: start {} is after end {}",
+                                result.start, result.end
+                            ),
+                        });
+                    }
+                    Ok(result.into())
+                })
+                .collect()
+        })
+        .collect()
+}
+
 /// Request for text content analysis
 /// Results of this request will contain analysis / detection of each of the provided documents
 /// in the order they are present in the `contents` object.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct ContentAnalysisRequest {
-    /// Field allowing users to provide list of documents for analysis
-    pub contents: Vec<String>,
+    /// Field allowing users to provide list of documents for analysis, shared with the
+    /// chunks they are sent for
+    pub contents: Vec<Arc<str>>,
 
     /// Detector parameters (available parameters depend on the detector)
     pub detector_params: DetectorParams,
 }
 
 impl ContentAnalysisRequest {
-    pub fn new(contents: Vec<String>, detector_params: DetectorParams) -> ContentAnalysisRequest {
+    pub fn new(contents: Vec<Arc<str>>, detector_params: DetectorParams) -> ContentAnalysisRequest {
         ContentAnalysisRequest {
             contents,
             detector_params,
@@ -115,6 +274,15 @@ impl ContentAnalysisRequest {
     }
 }
 
+/// Result of a text contents detector request.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ContentAnalysisResult {
+    /// Detections for each content
+    pub detections: Vec<Vec<ContentAnalysisResponse>>,
+    /// For partial results, number of leading chars of each content analyzed
+    pub analyzed_lengths: Option<Vec<usize>>,
+}
+
 /// Response of text content analysis endpoint
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct ContentAnalysisResponse {
@@ -131,6 +299,7 @@ pub struct ContentAnalysisResponse {
     /// Optional, ID of Detector
     pub detector_id: Option<String>,
     /// Score of detection
+    #[serde(deserialize_with = "deserialize_score")]
     pub score: f64,
     /// Optional, any applicable evidence for detection
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -138,6 +307,52 @@ pub struct ContentAnalysisResponse {
     // Optional metadata block
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub metadata: Metadata,
+    /// Chunk that produced the detection, set by the orchestrator for detections on streamed text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<DetectionChunk>,
+    /// Normalized category of detection, set by the orchestrator from configured categories
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+}
+
+/// Token classification result returned by detectors with the
+/// `legacy_token_classification` response format
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct LegacyTokenClassificationResponse {
+    /// Start index of detection
+    pub start: usize,
+    /// End index of detection
+    pub end: usize,
+    /// Text corresponding to detection
+    pub word: String,
+    /// Relevant detection class
+    pub entity: String,
+    /// Aggregate detection label
+    pub entity_group: String,
+    /// Optional, ID of Detector
+    #[serde(default)]
+    pub detector_id: Option<String>,
+    /// Score of detection
+    #[serde(deserialize_with = "deserialize_score")]
+    pub score: f64,
+}
+
+impl From<LegacyTokenClassificationResponse> for ContentAnalysisResponse {
+    fn from(value: LegacyTokenClassificationResponse) -> Self {
+        Self {
+            start: value.start,
+            end: value.end,
+            text: value.word,
+            detection: value.entity,
+            detection_type: value.entity_group,
+            detector_id: value.detector_id,
+            score: value.score,
+            evidence: None,
+            metadata: Metadata::new(),
+            chunk: None,
+            category: None,
+        }
+    }
 }
 
 impl From<ContentAnalysisResponse> for crate::models::TokenClassificationResult {
@@ -151,6 +366,8 @@ impl From<ContentAnalysisResponse> for crate::models::TokenClassificationResult
             detector_id: value.detector_id,
             score: value.score,
             token_count: None,
+            chunk: value.chunk,
+            category: value.category,
         }
     }
 }
@@ -22,8 +22,11 @@ use tracing::info;
 
 use super::{DEFAULT_PORT, DetectorClient, DetectorClientExt};
 use crate::{
-    clients::{Client, Error, HttpClient, create_http_client, http::HttpClientExt},
-    config::ServiceConfig,
+    clients::{
+        Client, Error, HttpClient, SharedClientState, breaker::CircuitBreaker, create_http_client,
+        create_routed_http_client, http::HttpClientExt,
+    },
+    config::{BackendConfig, CanaryConfig, ServiceConfig, SizeLimits},
     health::HealthCheckResult,
     models::{DetectionResult, DetectorParams},
 };
@@ -34,14 +37,22 @@ const GENERATION_DETECTOR_ENDPOINT: &str = "/api/v1/text/generation";
 pub struct TextGenerationDetectorClient {
     client: HttpClient,
     health_client: Option<HttpClient>,
+    endpoint_path: String,
 }
 
 impl TextGenerationDetectorClient {
     pub async fn new(
         config: &ServiceConfig,
         health_config: Option<&ServiceConfig>,
+        endpoint_path: Option<&str>,
+        backends: &[BackendConfig],
+        canary: Option<&CanaryConfig>,
+        size_limits: SizeLimits,
+        shared: &SharedClientState,
     ) -> Result<Self, Error> {
-        let client = create_http_client(DEFAULT_PORT, config).await?;
+        let client =
+            create_routed_http_client(DEFAULT_PORT, config, backends, canary, size_limits, shared)
+                .await?;
         let health_client = if let Some(health_config) = health_config {
             Some(create_http_client(DEFAULT_PORT, health_config).await?)
         } else {
@@ -50,6 +61,9 @@ impl TextGenerationDetectorClient {
         Ok(Self {
             client,
             health_client,
+            endpoint_path: endpoint_path
+                .unwrap_or(GENERATION_DETECTOR_ENDPOINT)
+                .to_string(),
         })
     }
 
@@ -63,7 +77,7 @@ impl TextGenerationDetectorClient {
         request: GenerationDetectionRequest,
         headers: HeaderMap,
     ) -> Result<Vec<DetectionResult>, Error> {
-        let url = self.endpoint(GENERATION_DETECTOR_ENDPOINT);
+        let url = self.endpoint(&self.endpoint_path);
         info!("sending text generation detector request to {}", url);
         self.post_to_detector(model_id, url, headers, request).await
     }
@@ -75,6 +89,10 @@ impl Client for TextGenerationDetectorClient {
         "text_context_doc_detector"
     }
 
+    fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.client.circuit_breaker()
+    }
+
     async fn health(&self) -> HealthCheckResult {
         if let Some(health_client) = &self.health_client {
             health_client.health().await
@@ -82,6 +100,13 @@ impl Client for TextGenerationDetectorClient {
             self.client.health().await
         }
     }
+
+    async fn shutdown(&self) {
+        self.client.shutdown();
+        if let Some(health_client) = &self.health_client {
+            health_client.shutdown();
+        }
+    }
 }
 
 impl DetectorClient for TextGenerationDetectorClient {}
@@ -0,0 +1,145 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Translation of token index spans returned by detectors to character spans.
+use std::{path::Path, sync::Arc};
+
+use hyper::StatusCode;
+use tokenizers::Tokenizer;
+
+use super::ContentAnalysisResponse;
+use crate::clients::{Error, tokenizer::load_tokenizer};
+
+/// Translates detection spans in token indices to character offsets, with the
+/// tokenizer of the detector.
+#[derive(Clone)]
+pub struct TokenOffsets {
+    tokenizer: Arc<Tokenizer>,
+}
+
+impl TokenOffsets {
+    /// Loads the tokenizer from a Hugging Face tokenizer file.
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        Ok(Self {
+            tokenizer: Arc::new(load_tokenizer(path)?),
+        })
+    }
+
+    /// Translates `start` and `end` of detections on `content` from token indices,
+    /// with `end` exclusive, to character offsets, and sets their text accordingly.
+    pub fn translate(
+        &self,
+        detector_id: &str,
+        content: &str,
+        detections: &mut [ContentAnalysisResponse],
+    ) -> Result<(), Error> {
+        if detections.is_empty() {
+            return Ok(());
+        }
+        let error = |message: String| Error::Http {
+            code: StatusCode::INTERNAL_SERVER_ERROR,
+            message: format!("detector `{detector_id}` {message}"),
+        };
+        let encoding = self
+            .tokenizer
+            .encode_char_offsets(content, false)
+            .map_err(|e| error(format!("content could not be tokenized: {e}")))?;
+        let offsets = encoding.get_offsets();
+        let n_chars = content.chars().count();
+        for detection in detections {
+            let (start, end) = (detection.start, detection.end);
+            if start > end || end > offsets.len() {
+                return Err(error(format!(
+                    "returned an invalid token span {start}..{end} for {} tokens",
+                    offsets.len()
+                )));
+            }
+            let char_start = offsets.get(start).map_or(n_chars, |offset| offset.0);
+            let char_end = if start == end {
+                char_start
+            } else {
+                offsets[end - 1].1
+            };
+            detection.start = char_start;
+            detection.end = char_end;
+            detection.text = content
+                .chars()
+                .skip(char_start)
+                .take(char_end - char_start)
+                .collect();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const TOKENIZER: &str = r#"{
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": null,
+        "pre_tokenizer": { "type": "Whitespace" },
+        "post_processor": null,
+        "decoder": null,
+        "model": { "type": "WordLevel", "vocab": { "[UNK]": 0, "john": 1 }, "unk_token": "[UNK]" }
+    }"#;
+
+    fn detection(start: usize, end: usize) -> ContentAnalysisResponse {
+        ContentAnalysisResponse {
+            start,
+            end,
+            text: String::new(),
+            detection: "person".into(),
+            detection_type: "pii".into(),
+            detector_id: None,
+            score: 0.9,
+            evidence: None,
+            metadata: Default::default(),
+            chunk: None,
+            category: None,
+        }
+    }
+
+    #[test]
+    fn test_translate() -> Result<(), Error> {
+        let path =
+            std::env::temp_dir().join(format!("{}-tokenizer.json", uuid::Uuid::new_v4().simple()));
+        fs::write(&path, TOKENIZER).unwrap();
+        let offsets = TokenOffsets::from_file(&path)?;
+        fs::remove_file(&path).unwrap();
+
+        // Tokens: "Héllo", ",", "John", "Smith", "!"
+        let content = "Héllo, John Smith!";
+        let mut detections = vec![detection(2, 4), detection(0, 1)];
+        offsets.translate("pii", content, &mut detections)?;
+        let spans = detections
+            .iter()
+            .map(|d| (d.start, d.end, d.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, [(7, 17, "John Smith"), (0, 5, "Héllo")]);
+
+        let mut detections = vec![detection(4, 6)];
+        assert!(offsets.translate("pii", content, &mut detections).is_err());
+        Ok(())
+    }
+}
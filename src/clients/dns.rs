@@ -0,0 +1,257 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Hostname resolution of clients, with static overrides, periodic re-resolution and
+//! egress policy enforcement.
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use ginepro::{LookupService, ServiceDefinition};
+use hyper_util::client::legacy::connect::dns::Name;
+use tracing::debug;
+
+use crate::config::EgressPolicy;
+
+/// Static hostname overrides, like `/etc/hosts`.
+static STATIC_HOSTS: RwLock<Option<HashMap<String, Vec<IpAddr>>>> = RwLock::new(None);
+
+/// Sets static hostname overrides, replacing previous overrides, e.g. on config reload.
+/// Clients pick up the overrides when they next resolve hostnames.
+pub fn set_static_hosts(hosts: HashMap<String, Vec<IpAddr>>) {
+    *STATIC_HOSTS.write().unwrap() = Some(hosts);
+}
+
+/// Returns the static addresses of `hostname`, if overridden.
+fn static_host(hostname: &str) -> Option<Vec<IpAddr>> {
+    STATIC_HOSTS
+        .read()
+        .unwrap()
+        .as_ref()?
+        .get(hostname)
+        .cloned()
+}
+
+/// Policy restricting the hosts clients connect to.
+static EGRESS_POLICY: RwLock<Option<EgressPolicy>> = RwLock::new(None);
+
+/// Sets the egress policy, replacing the previous policy, e.g. on config reload.
+/// Egress is unrestricted if not set.
+pub fn set_egress_policy(policy: Option<EgressPolicy>) {
+    *EGRESS_POLICY.write().unwrap() = policy;
+}
+
+/// Returns `true` if egress to `hostname` is allowed by the egress policy, if any, or
+/// may be allowed by its resolved addresses, checked on resolution.
+pub fn egress_may_allow(hostname: &str) -> bool {
+    EGRESS_POLICY
+        .read()
+        .unwrap()
+        .as_ref()
+        .is_none_or(|policy| policy.may_allow_host(hostname))
+}
+
+/// Resolves `hostname` from static overrides, falling back to the system resolver.
+/// Fails if a resolved address is not allowed by the egress policy.
+async fn lookup(hostname: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = match static_host(hostname) {
+        Some(ips) => ips
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect(),
+        None => tokio::net::lookup_host((hostname, port)).await?.collect(),
+    };
+    if let Some(policy) = EGRESS_POLICY.read().unwrap().as_ref() {
+        check_egress(policy, hostname, &addrs)?;
+    }
+    Ok(addrs)
+}
+
+/// Checks that egress to `hostname` resolved to `addrs` is allowed by `policy`.
+fn check_egress(policy: &EgressPolicy, hostname: &str, addrs: &[SocketAddr]) -> io::Result<()> {
+    if policy.allows_host(hostname) {
+        return Ok(());
+    }
+    match addrs.iter().find(|addr| !policy.allows_addr(addr.ip())) {
+        Some(addr) => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "egress to `{hostname}` resolved to {} is not allowed by the egress policy",
+                addr.ip()
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Resolver of `reqwest` clients, e.g. sending job callbacks, resolving hostnames like
+/// service clients and checking resolved addresses against the egress policy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReqwestResolver;
+
+impl reqwest::dns::Resolve for ReqwestResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs = lookup(name.as_str(), 0).await?;
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// Resolver of gRPC clients, probed by `ginepro` at the DNS probe interval.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrpcResolver;
+
+#[async_trait]
+impl LookupService for GrpcResolver {
+    async fn resolve_service_endpoints(
+        &self,
+        definition: &ServiceDefinition,
+    ) -> Result<HashSet<SocketAddr>, anyhow::Error> {
+        let addrs = lookup(definition.hostname(), definition.port()).await?;
+        Ok(addrs.into_iter().collect())
+    }
+}
+
+/// Resolver of HTTP clients.
+///
+/// Addresses are cached and re-resolved once older than the refresh interval. Each
+/// resolution rotates the order of addresses, so new connections are spread across
+/// all addresses of a hostname rather than the first one returned.
+#[derive(Debug, Clone)]
+pub struct HttpResolver {
+    refresh_interval: Duration,
+    cache: Arc<Mutex<HashMap<String, (Instant, Vec<SocketAddr>)>>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl HttpResolver {
+    pub fn new(refresh_interval: Duration) -> Self {
+        Self {
+            refresh_interval,
+            cache: Arc::default(),
+            next: Arc::default(),
+        }
+    }
+
+    async fn resolve(&self, hostname: &str) -> io::Result<Vec<SocketAddr>> {
+        let cached = self
+            .cache
+            .lock()
+            .unwrap()
+            .get(hostname)
+            .filter(|(resolved_at, _)| resolved_at.elapsed() < self.refresh_interval)
+            .map(|(_, addrs)| addrs.clone());
+        let mut addrs = match cached {
+            Some(addrs) => addrs,
+            None => {
+                // Port is set by the connector
+                let addrs = lookup(hostname, 0).await?;
+                debug!(%hostname, ?addrs, "resolved hostname");
+                self.cache
+                    .lock()
+                    .unwrap()
+                    .insert(hostname.to_string(), (Instant::now(), addrs.clone()));
+                addrs
+            }
+        };
+        if !addrs.is_empty() {
+            let n = self.next.fetch_add(1, Ordering::Relaxed) % addrs.len();
+            addrs.rotate_left(n);
+        }
+        Ok(addrs)
+    }
+}
+
+impl tower::Service<Name> for HttpResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let resolver = self.clone();
+        Box::pin(async move { Ok(resolver.resolve(name.as_str()).await?.into_iter()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_egress() {
+        let policy: EgressPolicy = serde_yml::from_str(
+            r#"
+hosts:
+    - "*.svc.cluster.local"
+cidrs:
+    - 10.0.0.0/8
+"#,
+        )
+        .unwrap();
+        let internal: SocketAddr = "10.1.2.3:8080".parse().unwrap();
+        let external: SocketAddr = "203.0.113.1:8080".parse().unwrap();
+        assert!(check_egress(&policy, "hap.svc.cluster.local", &[external]).is_ok());
+        assert!(check_egress(&policy, "hap.example.com", &[internal]).is_ok());
+        let error = check_egress(&policy, "hap.example.com", &[internal, external]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_static_hosts() {
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+        set_static_hosts(HashMap::from([("static-detector".into(), vec![a])]));
+        let addrs = lookup("static-detector", 8080).await.unwrap();
+        assert_eq!(addrs, [SocketAddr::new(a, 8080)]);
+
+        // Overrides are replaced, e.g. on config reload
+        set_static_hosts(HashMap::from([("static-detector".into(), vec![b])]));
+        let addrs = lookup("static-detector", 8080).await.unwrap();
+        assert_eq!(addrs, [SocketAddr::new(b, 8080)]);
+    }
+
+    #[tokio::test]
+    async fn test_http_resolver_rotates_addresses() {
+        let a: SocketAddr = "10.0.0.1:0".parse().unwrap();
+        let b: SocketAddr = "10.0.0.2:0".parse().unwrap();
+        let resolver = HttpResolver::new(Duration::from_secs(60));
+        resolver
+            .cache
+            .lock()
+            .unwrap()
+            .insert("detector".into(), (Instant::now(), vec![a, b]));
+        assert_eq!(resolver.resolve("detector").await.unwrap(), [a, b]);
+        assert_eq!(resolver.resolve("detector").await.unwrap(), [b, a]);
+        assert_eq!(resolver.resolve("detector").await.unwrap(), [a, b]);
+    }
+}
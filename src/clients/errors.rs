@@ -14,10 +14,14 @@
  limitations under the License.
 
 */
+use std::time::{Duration, Instant};
 
 use hyper::StatusCode;
+use prost::Message;
 use tracing::error;
 
+use super::retry::parse_retry_after;
+
 /// Client errors.
 #[derive(Debug, Clone, PartialEq, thiserror::Error)]
 pub enum Error {
@@ -27,6 +31,19 @@ pub enum Error {
     Http { code: StatusCode, message: String },
     #[error("model not found: {model_id}")]
     ModelNotFound { model_id: String },
+    #[error("{}", .message)]
+    RateLimited {
+        message: String,
+        /// Time the backend asked to wait until before retrying, if any
+        retry_at: Option<Instant>,
+    },
+    #[error("client is shut down")]
+    ClientShutdown,
+    #[error("{backend} generation backend does not support parameters: {}", .params.join(", "))]
+    UnsupportedParameters {
+        backend: String,
+        params: Vec<String>,
+    },
 }
 
 impl Error {
@@ -38,8 +55,25 @@ impl Error {
             // Return http status code for error responses
             // and 500 for other errors
             Error::Http { code, .. } => *code,
+            // Return 429 for rate limited requests
+            Error::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
             // Return 404 for model not found
             Error::ModelNotFound { .. } => StatusCode::NOT_FOUND,
+            // Return 503 for clients that have been shut down
+            Error::ClientShutdown => StatusCode::SERVICE_UNAVAILABLE,
+            // Return 422 for parameters the backend cannot honor
+            Error::UnsupportedParameters { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    /// Returns the delay the backend asked to wait before retrying, if rate limited,
+    /// less the time elapsed since.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::RateLimited { retry_at, .. } => {
+                retry_at.map(|retry_at| retry_at.saturating_duration_since(Instant::now()))
+            }
+            _ => None,
         }
     }
 }
@@ -55,6 +89,18 @@ impl From<hyper::Error> for Error {
 
 impl From<tonic::Status> for Error {
     fn from(value: tonic::Status) -> Self {
+        if value.code() == tonic::Code::ResourceExhausted {
+            let retry_after = retry_info(value.details()).or_else(|| {
+                value
+                    .metadata()
+                    .get("retry-after")
+                    .and_then(|value| parse_retry_after(value.as_encoded_bytes()))
+            });
+            return Self::RateLimited {
+                message: value.message().to_string(),
+                retry_at: retry_after.map(|retry_after| Instant::now() + retry_after),
+            };
+        }
         Self::Grpc {
             code: grpc_to_http_code(value.code()),
             message: value.message().to_string(),
@@ -74,7 +120,104 @@ pub fn grpc_to_http_code(value: tonic::Code) -> StatusCode {
         Unauthenticated => StatusCode::UNAUTHORIZED,
         PermissionDenied => StatusCode::FORBIDDEN,
         Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
         Ok => StatusCode::OK,
         _ => StatusCode::INTERNAL_SERVER_ERROR,
     }
 }
+
+const RETRY_INFO_TYPE_URL: &str = "type.googleapis.com/google.rpc.RetryInfo";
+
+/// `google.rpc.Status`, the rich error model of gRPC status details.
+#[derive(Clone, PartialEq, Message)]
+struct RpcStatus {
+    #[prost(int32, tag = "1")]
+    code: i32,
+    #[prost(string, tag = "2")]
+    message: String,
+    #[prost(message, repeated, tag = "3")]
+    details: Vec<Any>,
+}
+
+/// `google.protobuf.Any`
+#[derive(Clone, PartialEq, Message)]
+struct Any {
+    #[prost(string, tag = "1")]
+    type_url: String,
+    #[prost(bytes = "vec", tag = "2")]
+    value: Vec<u8>,
+}
+
+/// `google.rpc.RetryInfo`
+#[derive(Clone, PartialEq, Message)]
+struct RetryInfo {
+    #[prost(message, optional, tag = "1")]
+    retry_delay: Option<ProtoDuration>,
+}
+
+/// `google.protobuf.Duration`
+#[derive(Clone, PartialEq, Message)]
+struct ProtoDuration {
+    #[prost(int64, tag = "1")]
+    seconds: i64,
+    #[prost(int32, tag = "2")]
+    nanos: i32,
+}
+
+/// Returns the retry delay of the `RetryInfo` of gRPC status `details`, if any.
+fn retry_info(details: &[u8]) -> Option<Duration> {
+    let status = RpcStatus::decode(details).ok()?;
+    let detail = status
+        .details
+        .iter()
+        .find(|detail| detail.type_url == RETRY_INFO_TYPE_URL)?;
+    let delay = RetryInfo::decode(detail.value.as_slice())
+        .ok()?
+        .retry_delay?;
+    Some(Duration::new(
+        u64::try_from(delay.seconds).ok()?,
+        u32::try_from(delay.nanos).ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limited_grpc_status() {
+        let retry_info = RetryInfo {
+            retry_delay: Some(ProtoDuration {
+                seconds: 2,
+                nanos: 500_000_000,
+            }),
+        };
+        let details = RpcStatus {
+            code: tonic::Code::ResourceExhausted as i32,
+            message: "slow down".into(),
+            details: vec![Any {
+                type_url: RETRY_INFO_TYPE_URL.into(),
+                value: retry_info.encode_to_vec(),
+            }],
+        };
+        let status = tonic::Status::with_details(
+            tonic::Code::ResourceExhausted,
+            "slow down",
+            details.encode_to_vec().into(),
+        );
+        let error = Error::from(status);
+        assert_eq!(error.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after = error.retry_after().unwrap();
+        assert!(retry_after <= Duration::from_millis(2500));
+        assert!(retry_after > Duration::from_secs(2));
+
+        // Falls back to the `retry-after` metadata
+        let mut status = tonic::Status::resource_exhausted("slow down");
+        status
+            .metadata_mut()
+            .insert("retry-after", "3".parse().unwrap());
+        let retry_after = Error::from(status).retry_after().unwrap();
+        assert!(retry_after <= Duration::from_secs(3));
+        assert!(retry_after > Duration::from_millis(2500));
+    }
+}
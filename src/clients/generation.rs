@@ -17,15 +17,22 @@
 
 use async_trait::async_trait;
 use futures::{StreamExt, TryStreamExt};
-use hyper::HeaderMap;
+use hyper::{HeaderMap, StatusCode};
+use serde_json::json;
+use tokio_stream::wrappers::ReceiverStream;
 
-use super::{BoxStream, Client, Error, NlpClient, TgisClient};
+use super::{
+    BoxStream, Client, Error, LocalTokenizers, NlpClient, TgisClient,
+    breaker::CircuitBreaker,
+    openai::{ChatCompletionsRequest, ChatCompletionsResponse, Message, OpenAiClient, Role},
+};
 use crate::{
     health::HealthCheckResult,
     models::{
         ClassifiedGeneratedTextResult, ClassifiedGeneratedTextStreamResult,
         GuardrailsTextGenerationParameters,
     },
+    orchestrator,
     pb::{
         caikit::runtime::nlp::{
             ServerStreamingTextGenerationTaskRequest, TextGenerationTaskRequest,
@@ -38,26 +45,75 @@ use crate::{
     },
 };
 
+const OPENAI_BACKEND: &str = "OpenAI";
+
+/// Converts byte offsets of tokens of `text` to char offsets.
+/// Offsets within a multibyte char are rounded up to the end of the char.
+fn char_offsets(
+    text: &str,
+    offsets: impl IntoIterator<Item = (usize, usize)>,
+) -> Vec<(usize, usize)> {
+    // Char offset of each byte offset
+    let mut char_offsets = Vec::with_capacity(text.len() + 1);
+    for (char_offset, ch) in text.chars().enumerate() {
+        char_offsets.push(char_offset);
+        char_offsets.extend(std::iter::repeat_n(char_offset + 1, ch.len_utf8() - 1));
+    }
+    char_offsets.push(text.chars().count());
+    let char_offset = |byte_offset: usize| char_offsets[byte_offset.min(text.len())];
+    offsets
+        .into_iter()
+        .map(|(start, end)| (char_offset(start), char_offset(end)))
+        .collect()
+}
+
 #[derive(Clone)]
-pub struct GenerationClient(Option<GenerationClientInner>);
+pub struct GenerationClient {
+    inner: Option<GenerationClientInner>,
+    /// Tokenizers of models tokenized locally instead of by the service
+    tokenizers: LocalTokenizers,
+}
 
 #[derive(Clone)]
 enum GenerationClientInner {
     Tgis(TgisClient),
     Nlp(NlpClient),
+    OpenAi(OpenAiClient),
 }
 
 impl GenerationClient {
     pub fn tgis(client: TgisClient) -> Self {
-        Self(Some(GenerationClientInner::Tgis(client)))
+        Self {
+            inner: Some(GenerationClientInner::Tgis(client)),
+            tokenizers: LocalTokenizers::default(),
+        }
     }
 
     pub fn nlp(client: NlpClient) -> Self {
-        Self(Some(GenerationClientInner::Nlp(client)))
+        Self {
+            inner: Some(GenerationClientInner::Nlp(client)),
+            tokenizers: LocalTokenizers::default(),
+        }
+    }
+
+    pub fn openai(client: OpenAiClient) -> Self {
+        Self {
+            inner: Some(GenerationClientInner::OpenAi(client)),
+            tokenizers: LocalTokenizers::default(),
+        }
     }
 
     pub fn not_configured() -> Self {
-        Self(None)
+        Self {
+            inner: None,
+            tokenizers: LocalTokenizers::default(),
+        }
+    }
+
+    /// Sets local tokenizers, used to tokenize text of their models instead of the service.
+    pub fn with_tokenizers(mut self, tokenizers: LocalTokenizers) -> Self {
+        self.tokenizers = tokenizers;
+        self
     }
 
     pub async fn tokenize(
@@ -66,7 +122,10 @@ impl GenerationClient {
         text: String,
         headers: HeaderMap,
     ) -> Result<(u32, Vec<String>), Error> {
-        match &self.0 {
+        if let Some(result) = self.tokenizers.tokenize(&model_id, &text) {
+            return result;
+        }
+        match &self.inner {
             Some(GenerationClientInner::Tgis(client)) => {
                 let request = BatchedTokenizeRequest {
                     model_id: model_id.clone(),
@@ -91,6 +150,57 @@ impl GenerationClient {
                     .collect::<Vec<_>>();
                 Ok((response.token_count as u32, tokens))
             }
+            // Token counts of models without a local tokenizer are unknown
+            Some(GenerationClientInner::OpenAi(_)) => Ok((0, Vec::new())),
+            None => Err(Error::ModelNotFound { model_id }),
+        }
+    }
+
+    /// Returns the char offsets (start, end) of tokens of `text`.
+    pub async fn tokenize_offsets(
+        &self,
+        model_id: String,
+        text: String,
+        headers: HeaderMap,
+    ) -> Result<Vec<(usize, usize)>, Error> {
+        if let Some(result) = self.tokenizers.offsets(&model_id, &text) {
+            return result;
+        }
+        match &self.inner {
+            Some(GenerationClientInner::Tgis(client)) => {
+                let request = BatchedTokenizeRequest {
+                    model_id: model_id.clone(),
+                    requests: vec![TokenizeRequest { text: text.clone() }],
+                    return_tokens: true,
+                    return_offsets: true,
+                    truncate_input_tokens: 0,
+                };
+                let mut response = client.tokenize(request, headers).await?;
+                let response = response.responses.swap_remove(0);
+                // TGIS returns byte offsets
+                Ok(char_offsets(
+                    &text,
+                    response
+                        .offsets
+                        .into_iter()
+                        .map(|offset| (offset.start as usize, offset.end as usize)),
+                ))
+            }
+            Some(GenerationClientInner::Nlp(client)) => {
+                let request = TokenizationTaskRequest { text };
+                let response = client
+                    .tokenization_task_predict(&model_id, request, headers)
+                    .await?;
+                Ok(response
+                    .results
+                    .into_iter()
+                    .map(|token| (token.start as usize, token.end as usize))
+                    .collect())
+            }
+            Some(GenerationClientInner::OpenAi(_)) => Err(Error::UnsupportedParameters {
+                backend: OPENAI_BACKEND.into(),
+                params: vec!["truncate_input_tokens".into()],
+            }),
             None => Err(Error::ModelNotFound { model_id }),
         }
     }
@@ -102,8 +212,9 @@ impl GenerationClient {
         params: Option<GuardrailsTextGenerationParameters>,
         headers: HeaderMap,
     ) -> Result<ClassifiedGeneratedTextResult, Error> {
-        match &self.0 {
+        match &self.inner {
             Some(GenerationClientInner::Tgis(client)) => {
+                check_supported_params("TGIS", params.as_ref())?;
                 let params = params.map(Into::into);
                 let request = BatchedGenerationRequest {
                     model_id: model_id.clone(),
@@ -115,6 +226,7 @@ impl GenerationClient {
                 Ok(response.into())
             }
             Some(GenerationClientInner::Nlp(client)) => {
+                check_supported_params("NLP", params.as_ref())?;
                 let request = if let Some(params) = params {
                     TextGenerationTaskRequest {
                         text,
@@ -151,6 +263,16 @@ impl GenerationClient {
                     .await?;
                 Ok(response.into())
             }
+            Some(GenerationClientInner::OpenAi(client)) => {
+                check_supported_params(OPENAI_BACKEND, params.as_ref())?;
+                let request = chat_completions_request(model_id, text, params, false);
+                match client.chat_completions(request, headers).await? {
+                    ChatCompletionsResponse::Unary(chat_completion) => {
+                        Ok((*chat_completion).into())
+                    }
+                    ChatCompletionsResponse::Streaming(_) => unreachable!(),
+                }
+            }
             None => Err(Error::ModelNotFound { model_id }),
         }
     }
@@ -162,8 +284,9 @@ impl GenerationClient {
         params: Option<GuardrailsTextGenerationParameters>,
         headers: HeaderMap,
     ) -> Result<BoxStream<Result<ClassifiedGeneratedTextStreamResult, Error>>, Error> {
-        match &self.0 {
+        match &self.inner {
             Some(GenerationClientInner::Tgis(client)) => {
+                check_supported_params("TGIS", params.as_ref())?;
                 let params = params.map(Into::into);
                 let request = SingleGenerationRequest {
                     model_id: model_id.clone(),
@@ -179,6 +302,7 @@ impl GenerationClient {
                 Ok(response_stream)
             }
             Some(GenerationClientInner::Nlp(client)) => {
+                check_supported_params("NLP", params.as_ref())?;
                 let request = if let Some(params) = params {
                     ServerStreamingTextGenerationTaskRequest {
                         text,
@@ -217,22 +341,112 @@ impl GenerationClient {
                     .boxed();
                 Ok(response_stream)
             }
+            Some(GenerationClientInner::OpenAi(client)) => {
+                check_supported_params(OPENAI_BACKEND, params.as_ref())?;
+                let request = chat_completions_request(model_id, text, params, true);
+                let rx = match client.chat_completions(request, headers).await? {
+                    ChatCompletionsResponse::Streaming(rx) => rx,
+                    ChatCompletionsResponse::Unary(_) => unreachable!(),
+                };
+                // The stream ends with `None` once the server sends `[DONE]`
+                let response_stream = ReceiverStream::new(rx)
+                    .filter_map(|result| async move {
+                        match result {
+                            Ok(chunk) => chunk.map(|chunk| Ok(chunk.into())),
+                            Err(orchestrator::Error::Client(error)) => Some(Err(error)),
+                            Err(error) => Some(Err(Error::Http {
+                                code: StatusCode::INTERNAL_SERVER_ERROR,
+                                message: error.to_string(),
+                            })),
+                        }
+                    })
+                    .boxed();
+                Ok(response_stream)
+            }
             None => Err(Error::ModelNotFound { model_id }),
         }
     }
 }
 
+/// Builds a single-turn chat completions request generating a response to `text`.
+fn chat_completions_request(
+    model_id: String,
+    text: String,
+    params: Option<GuardrailsTextGenerationParameters>,
+    stream: bool,
+) -> ChatCompletionsRequest {
+    let mut extra = params
+        .map(|params| params.to_openai_params())
+        .unwrap_or_default();
+    if stream {
+        // Request token usage, included in the last chunk
+        extra.insert("stream_options".into(), json!({ "include_usage": true }));
+    }
+    ChatCompletionsRequest {
+        stream: Some(stream),
+        model: model_id,
+        messages: vec![Message {
+            role: Role::User,
+            content: Some(text.into()),
+            ..Default::default()
+        }],
+        extra,
+        ..Default::default()
+    }
+}
+
+/// Fails if parameters not supported by the generation backend are set,
+/// instead of silently dropping them.
+fn check_supported_params(
+    backend: &str,
+    params: Option<&GuardrailsTextGenerationParameters>,
+) -> Result<(), Error> {
+    let unsupported = params
+        .map(|params| match backend {
+            OPENAI_BACKEND => params.unsupported_by_openai_backends(),
+            _ => params.unsupported_by_grpc_backends(),
+        })
+        .unwrap_or_default();
+    if unsupported.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::UnsupportedParameters {
+            backend: backend.into(),
+            params: unsupported.into_iter().map(Into::into).collect(),
+        })
+    }
+}
+
 #[async_trait]
 impl Client for GenerationClient {
     fn name(&self) -> &str {
         "generation"
     }
 
+    fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        match &self.inner {
+            Some(GenerationClientInner::Tgis(client)) => client.circuit_breaker(),
+            Some(GenerationClientInner::Nlp(client)) => client.circuit_breaker(),
+            Some(GenerationClientInner::OpenAi(client)) => client.circuit_breaker(),
+            None => None,
+        }
+    }
+
     async fn health(&self) -> HealthCheckResult {
-        match &self.0 {
+        match &self.inner {
             Some(GenerationClientInner::Tgis(client)) => client.health().await,
             Some(GenerationClientInner::Nlp(client)) => client.health().await,
+            Some(GenerationClientInner::OpenAi(client)) => client.health().await,
             None => unimplemented!(),
         }
     }
+
+    async fn shutdown(&self) {
+        match &self.inner {
+            Some(GenerationClientInner::Tgis(client)) => client.shutdown().await,
+            Some(GenerationClientInner::Nlp(client)) => client.shutdown().await,
+            Some(GenerationClientInner::OpenAi(client)) => client.shutdown().await,
+            None => (),
+        }
+    }
 }
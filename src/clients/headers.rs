@@ -0,0 +1,163 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+use std::{collections::HashMap, sync::Arc};
+
+use http::{HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+
+/// Headers added to every request sent to a service.
+///
+/// Values are either static or templates referencing propagated request
+/// headers as `${header-name}`, e.g. `tenant-${x-tenant-id}`. Configured
+/// headers override propagated headers of the same name.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "HashMap<String, String>")]
+pub struct HeaderTemplates(Arc<Vec<(HeaderName, Template)>>);
+
+impl HeaderTemplates {
+    /// Returns `true` if no headers are configured.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Merges the configured headers into `headers`.
+    ///
+    /// Headers referencing a header missing from `headers` are skipped.
+    pub fn apply(&self, mut headers: HeaderMap) -> HeaderMap {
+        if self.is_empty() {
+            return headers;
+        }
+        let rendered = self
+            .0
+            .iter()
+            .filter_map(|(name, template)| Some((name.clone(), template.render(&headers)?)))
+            .collect::<Vec<_>>();
+        for (name, value) in rendered {
+            headers.insert(name, value);
+        }
+        headers
+    }
+}
+
+impl TryFrom<HashMap<String, String>> for HeaderTemplates {
+    type Error = String;
+
+    fn try_from(value: HashMap<String, String>) -> Result<Self, Self::Error> {
+        let templates = value
+            .into_iter()
+            .map(|(name, value)| {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| format!("invalid header name `{name}`"))?;
+                let template = Template::parse(&value)
+                    .map_err(|error| format!("invalid value for header `{name}`: {error}"))?;
+                Ok((name, template))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self(Arc::new(templates)))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Header(HeaderName),
+}
+
+/// A parsed header value template.
+#[derive(Debug, Clone)]
+struct Template(Vec<Segment>);
+
+impl Template {
+    fn parse(value: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut rest = value;
+        while let Some(start) = rest.find("${") {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| "unterminated `${`".to_string())?;
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            let name = &rest[start + 2..start + end];
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("invalid header reference `{name}`"))?;
+            segments.push(Segment::Header(name));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+        for segment in &segments {
+            if let Segment::Literal(literal) = segment {
+                HeaderValue::from_str(literal).map_err(|error| error.to_string())?;
+            }
+        }
+        Ok(Self(segments))
+    }
+
+    fn render(&self, headers: &HeaderMap) -> Option<HeaderValue> {
+        let mut value = Vec::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(literal) => value.extend_from_slice(literal.as_bytes()),
+                Segment::Header(name) => value.extend_from_slice(headers.get(name)?.as_bytes()),
+            }
+        }
+        HeaderValue::from_bytes(&value).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn templates(values: &[(&str, &str)]) -> Result<HeaderTemplates, String> {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>()
+            .try_into()
+    }
+
+    #[test]
+    fn test_header_templates() {
+        let templates = templates(&[
+            ("x-route", "hap"),
+            ("X-Tenant", "tenant-${x-tenant-id}"),
+            ("x-user", "${x-user-id}"),
+        ])
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant-id", HeaderValue::from_static("abc"));
+        headers.insert("x-route", HeaderValue::from_static("propagated"));
+        let headers = templates.apply(headers);
+        // Configured headers override propagated headers
+        assert_eq!(headers.get("x-route").unwrap(), "hap");
+        assert_eq!(headers.get("x-tenant").unwrap(), "tenant-abc");
+        // Missing references skip the header
+        assert!(headers.get("x-user").is_none());
+    }
+
+    #[test]
+    fn test_invalid_header_templates() {
+        assert!(templates(&[("x-route", "${x-tenant-id")]).is_err());
+        assert!(templates(&[("x-route", "${not a header}")]).is_err());
+        assert!(templates(&[("not a header", "value")]).is_err());
+        assert!(templates(&[("x-route", "line\nbreak")]).is_err());
+    }
+}
@@ -15,17 +15,19 @@
 
 */
 
-use std::{fmt::Debug, ops::Deref, time::Duration};
+use std::{
+    fmt::Debug,
+    ops::Deref,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use http::header::HeaderValue;
-use http_body_util::{BodyExt, Full, combinators::BoxBody};
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited, combinators::BoxBody};
 use hyper::{
     HeaderMap, Method, Request, StatusCode,
     body::{Bytes, Incoming},
 };
-use hyper_rustls::HttpsConnector;
-use hyper_timeout::TimeoutConnector;
-use hyper_util::client::legacy::connect::HttpConnector;
 use serde::{Serialize, de::DeserializeOwned};
 use tower::{Service, timeout::Timeout};
 use tower_http::{
@@ -37,14 +39,22 @@ use tower_http::{
         Trace, TraceLayer,
     },
 };
-use tracing::{Span, error, info, info_span};
+use tracing::{Span, debug, error, info, info_span};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 use url::Url;
 
-use super::{Client, Error};
+use super::{
+    Client, Closeable, Error, HeaderTemplates, RequestPermit, SharedClientState,
+    breaker::CircuitBreaker,
+    canary::{self, Arm, Canary},
+    pool::ConnectionPool,
+    retry::{RetryPolicy, parse_retry_after},
+    routing::{DEFAULT_BACKEND_NAME, LatencyRouter},
+};
 use crate::{
+    config::{CanaryMode, HealthCheckConfig, SizeLimits},
     health::{HealthCheckResult, HealthStatus, OptionalHealthCheckResponseBody},
-    utils::{AsUriExt, trace},
+    utils::{AsUriExt, buffer_pool::BUFFER_POOL, exemplars, trace},
 };
 
 pub const JSON_CONTENT_TYPE: HeaderValue = HeaderValue::from_static("application/json");
@@ -64,21 +74,99 @@ pub struct Response(pub hyper::http::Response<BoxBody<Bytes, hyper::Error>>);
 
 impl Response {
     /// Deserializes the response body as JSON into type `T`.
+    /// Fails if the body exceeds the response size limit of the client, if any.
     pub async fn json<T: DeserializeOwned>(self) -> Result<T, Error> {
-        let data = self
-            .0
-            .into_body()
-            .collect()
-            .await
-            .expect("unexpected infallible error")
-            .to_bytes();
+        let limit = self.0.extensions().get::<ResponseSizeLimit>().copied();
+        let body = self.0.into_body();
+        let data = match limit {
+            Some(ResponseSizeLimit(limit)) => Limited::new(body, limit)
+                .collect()
+                .await
+                .map_err(|e| Error::Http {
+                    code: StatusCode::INTERNAL_SERVER_ERROR,
+                    message: match e.downcast_ref::<LengthLimitError>() {
+                        Some(_) => format!("client response body exceeds limit of {limit} bytes"),
+                        None => format!("client response body read failed: {e}"),
+                    },
+                })?
+                .to_bytes(),
+            None => body
+                .collect()
+                .await
+                .expect("unexpected infallible error")
+                .to_bytes(),
+        };
         serde_json::from_slice::<T>(&data).map_err(|e| Error::Http {
             code: StatusCode::INTERNAL_SERVER_ERROR,
             message: format!("client response deserialization failed: {}", e),
         })
     }
+
+    /// Returns the delay of the `Retry-After` header, if any.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.0
+            .headers()
+            .get(hyper::header::RETRY_AFTER)
+            .and_then(|value| parse_retry_after(value.as_bytes()))
+    }
+
+    /// Holds `permit` until the body of the response is dropped, so the request is
+    /// outstanding until its body is read.
+    fn with_permit(self, permit: Option<RequestPermit>) -> Self {
+        match permit {
+            Some(permit) => Self(self.0.map(|body| {
+                body.map_frame(move |frame| {
+                    let _permit = &permit;
+                    frame
+                })
+                .boxed()
+            })),
+            None => self,
+        }
+    }
+
+    /// Converts a 429 response to a rate limited error, with the message of its body
+    /// if it is a JSON error, e.g. `{"message": ..}` or `{"error": {"message": ..}}`.
+    async fn into_rate_limited(self) -> Error {
+        let retry_at = self
+            .retry_after()
+            .map(|retry_after| Instant::now() + retry_after);
+        let message = self
+            .json::<serde_json::Value>()
+            .await
+            .ok()
+            .and_then(|body| error_message(&body))
+            .unwrap_or_else(|| "too many requests".into());
+        Error::RateLimited { message, retry_at }
+    }
+}
+
+/// Returns the message of a JSON error body, if any.
+fn error_message(body: &serde_json::Value) -> Option<String> {
+    if let Some(error) = body.get("error") {
+        return match error {
+            serde_json::Value::String(message) => Some(message.clone()),
+            error => error_message(error),
+        };
+    }
+    ["message", "details"]
+        .into_iter()
+        .find_map(|field| body.get(field)?.as_str().map(String::from))
 }
 
+/// Returns true if `response` is a stream of server-sent events.
+fn is_event_stream<B>(response: &hyper::http::Response<B>) -> bool {
+    response
+        .headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"))
+}
+
+/// Maximum size in bytes of a response body, set as a response extension.
+#[derive(Debug, Clone, Copy)]
+struct ResponseSizeLimit(usize);
+
 impl Deref for Response {
     type Target = hyper::http::response::Response<BoxBody<Bytes, hyper::Error>>;
 
@@ -94,12 +182,7 @@ impl From<TracedResponse> for Response {
 }
 
 pub type HttpClientInner = Trace<
-    Timeout<
-        hyper_util::client::legacy::Client<
-            TimeoutConnector<HttpsConnector<HttpConnector>>,
-            BoxBody<Bytes, hyper::Error>,
-        >,
-    >,
+    Timeout<ConnectionPool>,
     SharedClassifier<ServerErrorsAsFailures>,
     ClientMakeSpan,
     ClientOnRequest,
@@ -119,16 +202,124 @@ pub trait HttpClientExt: Client {
 pub struct HttpClient {
     base_url: Url,
     health_url: Url,
-    inner: HttpClientInner,
+    inner: Closeable<HttpClientInner>,
+    headers: HeaderTemplates,
+    health_check: HealthCheckConfig,
+    /// Router between this client and equivalent backends, if configured
+    router: Option<Arc<LatencyRouter>>,
+    /// Canary deployment receiving a share of requests, if configured
+    canary: Option<Arc<Canary>>,
+    /// `Host` header overriding the host of the base url, if configured
+    host_header: Option<HeaderValue>,
+    /// Limits of request and response bodies
+    size_limits: SizeLimits,
+    /// Retries of failed requests
+    retry: RetryPolicy,
+    /// Circuit breaker of requests to this client's service, if configured
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    /// Limit of outstanding requests and sampler of logged requests
+    shared: SharedClientState,
 }
 
 impl HttpClient {
-    pub fn new(base_url: Url, inner: HttpClientInner) -> Self {
-        let health_url = base_url.join("health").unwrap();
+    pub fn new(
+        base_url: Url,
+        inner: HttpClientInner,
+        headers: HeaderTemplates,
+        health_check: HealthCheckConfig,
+    ) -> Self {
+        let health_url = base_url
+            .join(health_check.path.as_deref().unwrap_or("health"))
+            .unwrap();
         Self {
             base_url,
             health_url,
-            inner,
+            inner: Closeable::new(inner),
+            headers,
+            health_check,
+            router: None,
+            canary: None,
+            host_header: None,
+            size_limits: SizeLimits::default(),
+            retry: RetryPolicy::default(),
+            circuit_breaker: None,
+            shared: SharedClientState::default(),
+        }
+    }
+
+    /// Routes requests between this client and equivalent `backends` by rolling latency.
+    pub fn with_backends(mut self, backends: Vec<(String, HttpClient)>) -> Self {
+        if !backends.is_empty() {
+            let mut clients = vec![(DEFAULT_BACKEND_NAME.to_string(), self.clone())];
+            clients.extend(backends);
+            self.router = Some(Arc::new(LatencyRouter::new(clients)));
+        }
+        self
+    }
+
+    /// Splits requests between this client and a canary deployment.
+    pub fn with_canary(mut self, canary: Canary) -> Self {
+        self.canary = Some(Arc::new(canary));
+        self
+    }
+
+    /// Sends `host_header` as the `Host` header of requests.
+    pub fn with_host_header(mut self, host_header: HeaderValue) -> Self {
+        self.host_header = Some(host_header);
+        self
+    }
+
+    /// Limits the size of request and response bodies.
+    pub fn with_size_limits(mut self, size_limits: SizeLimits) -> Self {
+        self.size_limits = size_limits;
+        self
+    }
+
+    /// Retries failed requests according to `retry`.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Fails requests without sending them while `circuit_breaker` is open.
+    pub fn with_circuit_breaker(mut self, circuit_breaker: Arc<CircuitBreaker>) -> Self {
+        self.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+
+    /// Limits and samples requests for logging with the state shared by all clients.
+    pub fn with_shared_state(mut self, shared: SharedClientState) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    /// Returns the circuit breaker of this client's service, if configured. Clients routing
+    /// between backends have none, as the health of their backends' circuits is reported
+    /// by [`LatencyRouter::health`].
+    pub fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        match &self.router {
+            Some(_) => None,
+            None => self.circuit_breaker.as_deref(),
+        }
+    }
+
+    /// Applies configured headers to `headers`.
+    fn apply_headers(&self, headers: HeaderMap) -> HeaderMap {
+        let mut headers = self.headers.apply(headers);
+        if let Some(host_header) = &self.host_header {
+            headers.insert(hyper::header::HOST, host_header.clone());
+        }
+        headers
+    }
+
+    /// Closes the client's connection pool.
+    pub fn shutdown(&self) {
+        self.inner.close();
+        if let Some(router) = &self.router {
+            router.shutdown();
+        }
+        if let Some(canary) = &self.canary {
+            canary.shutdown();
         }
     }
 
@@ -140,6 +331,14 @@ impl HttpClient {
         self.base_url.join(path).unwrap()
     }
 
+    /// Returns `url` with the scheme, host and port of this client's base url.
+    pub fn rebase(&self, url: &Url) -> Url {
+        let mut rebased = self.base_url.clone();
+        rebased.set_path(url.path());
+        rebased.set_query(url.query());
+        rebased
+    }
+
     pub async fn get(
         &self,
         url: Url,
@@ -158,6 +357,8 @@ impl HttpClient {
         self.send(url, Method::POST, headers, body).await
     }
 
+    /// Sends a request. 429 responses fail with a rate limited error, carrying the
+    /// `Retry-After` delay of the response.
     pub async fn send(
         &self,
         url: Url,
@@ -165,22 +366,208 @@ impl HttpClient {
         headers: HeaderMap,
         body: impl RequestBody,
     ) -> Result<Response, Error> {
+        let response = self.send_split(url, method, headers, body).await?;
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            return Err(response.into_rate_limited().await);
+        }
+        Ok(response)
+    }
+
+    /// Sends a request to this client's service or, if selected, its canary deployment.
+    async fn send_split(
+        &self,
+        url: Url,
+        method: Method,
+        headers: HeaderMap,
+        body: impl RequestBody,
+    ) -> Result<Response, Error> {
+        let body = BUFFER_POOL.to_json_bytes(&body).map_err(|e| Error::Http {
+            code: StatusCode::INTERNAL_SERVER_ERROR,
+            message: format!("client request serialization failed: {}", e),
+        })?;
+        let max_request_bytes = self.size_limits.max_request_bytes;
+        if let Some(limit) = max_request_bytes.filter(|limit| body.len() > *limit) {
+            return Err(Error::Http {
+                code: StatusCode::PAYLOAD_TOO_LARGE,
+                message: format!(
+                    "client request body of {} bytes exceeds limit of {limit} bytes",
+                    body.len()
+                ),
+            });
+        }
+        let Some(canary) = &self.canary else {
+            return self.send_stable(url, method, headers, body).await;
+        };
+        if canary.select() {
+            match canary.mode() {
+                CanaryMode::Serve => {
+                    return self
+                        .send_with_retry(|_| {
+                            canary.send(url.clone(), method.clone(), headers.clone(), body.clone())
+                        })
+                        .await;
+                }
+                CanaryMode::Shadow => {
+                    canary.shadow(url.clone(), method.clone(), headers.clone(), body.clone())
+                }
+            }
+        }
+        let start = Instant::now();
+        let result = self.send_stable(url, method, headers, body).await;
+        canary::record(Arm::Stable, &result, start);
+        result
+    }
+
+    /// Sends a request to this client's service, or its equivalent backends if configured.
+    async fn send_stable(
+        &self,
+        url: Url,
+        method: Method,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Result<Response, Error> {
+        match &self.router {
+            // Backends are guarded by their own circuit breakers, see `LatencyRouter::send`
+            Some(router) => {
+                self.send_with_retry(|_| {
+                    router.send(url.clone(), method.clone(), headers.clone(), body.clone())
+                })
+                .await
+            }
+            None => {
+                self.guarded(self.send_with_retry(|attempt| {
+                    self.send_bytes(
+                        url.clone(),
+                        method.clone(),
+                        headers.clone(),
+                        body.clone(),
+                        attempt,
+                    )
+                }))
+                .await
+            }
+        }
+    }
+
+    /// Runs `request` through this client's circuit breaker, if configured, failing
+    /// without sending it while the circuit is open.
+    pub(crate) async fn guarded(
+        &self,
+        request: impl Future<Output = Result<Response, Error>>,
+    ) -> Result<Response, Error> {
+        let Some(circuit_breaker) = &self.circuit_breaker else {
+            return request.await;
+        };
+        let permit = circuit_breaker.permit()?;
+        let result = request.await;
+        match &result {
+            Ok(response) => circuit_breaker.record(permit, response.status()),
+            Err(Error::ClientShutdown) => (),
+            Err(error) => circuit_breaker.record(permit, error.status_code()),
+        }
+        result
+    }
+
+    /// Sends a request with `send`, given the 1-based attempt number, retrying failed
+    /// requests and error responses with retriable status codes, waiting at least for the
+    /// `Retry-After` delay of responses. Requests routed between backends are retried once
+    /// all backends fail.
+    async fn send_with_retry<F, Fut>(&self, mut send: F) -> Result<Response, Error>
+    where
+        F: FnMut(usize) -> Fut,
+        Fut: Future<Output = Result<Response, Error>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let result = send(attempt).await;
+            let (code, retry_after) = match &result {
+                Ok(response) => (response.status(), response.retry_after()),
+                Err(Error::ClientShutdown) => return result,
+                Err(error) => (error.status_code(), error.retry_after()),
+            };
+            if !self.retry.retries(attempt, code) {
+                return result;
+            }
+            // Rate limited requests asking to wait longer than the maximum backoff fail
+            let Some(delay) = self.retry.delay(attempt, retry_after) else {
+                return result;
+            };
+            // Release the failed response's request permit and connection while waiting
+            drop(result);
+            self.retry.wait(attempt, code, delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Sends a request with a serialized body to this client's service.
+    /// `attempt` is the 1-based attempt number of the request, e.g. when failing over.
+    pub(crate) async fn send_bytes(
+        &self,
+        url: Url,
+        method: Method,
+        headers: HeaderMap,
+        body: Bytes,
+        attempt: usize,
+    ) -> Result<Response, Error> {
+        if !self.shared.log_sampler.sample() {
+            return self.dispatch(url, method, headers, body).await;
+        }
+        let endpoint = url.to_string();
+        let method_name = method.to_string();
+        let request_bytes = body.len();
+        let start = Instant::now();
+        let result = self.dispatch(url, method, headers, body).await;
+        let duration_ms = start.elapsed().as_millis() as u64;
+        match &result {
+            Ok(response) => info!(
+                endpoint,
+                method = method_name,
+                attempt,
+                request_bytes,
+                response_bytes = response
+                    .headers()
+                    .get(hyper::header::CONTENT_LENGTH)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse::<u64>().ok()),
+                status = response.status().as_u16(),
+                duration_ms,
+                "downstream request"
+            ),
+            Err(error) => info!(
+                endpoint,
+                method = method_name,
+                attempt,
+                request_bytes,
+                status = error.status_code().as_u16(),
+                duration_ms,
+                %error,
+                "downstream request"
+            ),
+        }
+        result
+    }
+
+    async fn dispatch(
+        &self,
+        url: Url,
+        method: Method,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Result<Response, Error> {
+        debug!(%url, %method, request_body_bytes = body.len(), "sending client request");
         let ctx = Span::current().context();
-        let headers = trace::with_traceparent_header(&ctx, headers);
+        let headers = trace::with_traceparent_header(&ctx, self.apply_headers(headers));
+        let client = match (url.host_str(), url.port_or_known_default()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            _ => url.origin().ascii_serialization(),
+        };
         let mut builder = hyper::http::request::Builder::new()
             .method(method)
             .uri(url.as_uri());
         match builder.headers_mut() {
             Some(headers_mut) => {
                 headers_mut.extend(headers);
-                let body =
-                    Full::new(Bytes::from(serde_json::to_vec(&body).map_err(|e| {
-                        Error::Http {
-                            code: StatusCode::INTERNAL_SERVER_ERROR,
-                            message: format!("client request serialization failed: {}", e)
-                        }
-                    })?))
-                        .map_err(|err| match err {});
+                let body = Full::new(body).map_err(|err| match err {});
                 let request = builder
                     .body(body.boxed())
                     .map_err(|e| {
@@ -189,11 +576,12 @@ impl HttpClient {
                             message: format!("client request serialization failed: {}", e)
                         }
                     })?;
-                let response = match self
-                    .inner
-                    .clone()
-                    .call(request)
-                    .await {
+                let mut inner = self.inner.get()?;
+                let permit = self.shared.request_limiter.acquire().await;
+                let start = Instant::now();
+                let result = inner.call(request).await;
+                exemplars::observe_client(&client, start.elapsed());
+                let response = match result {
                         Ok(response) => Ok(response.map_err(|e| {
                             Error::Http {
                                 code: StatusCode::INTERNAL_SERVER_ERROR,
@@ -205,9 +593,20 @@ impl HttpClient {
                             message: format!("client request timeout: {}", e),
                         }),
                 }?;
+                debug!(
+                    status = %response.status(),
+                    response_content_length = ?response.headers().get(hyper::header::CONTENT_LENGTH),
+                    "received client response"
+                );
                 let span = Span::current();
                 trace::trace_context_from_http_response(&span, &response);
-                Ok(response.into())
+                // Streamed responses are long-lived, so they are not limited once started
+                let permit = permit.filter(|_| !is_event_stream(&response));
+                let mut response = Response::from(response).with_permit(permit);
+                if let Some(limit) = self.size_limits.max_response_bytes {
+                    response.0.extensions_mut().insert(ResponseSizeLimit(limit));
+                }
+                Ok(response)
             }
             None => Err(builder.body(body).err().map_or_else(
                 || panic!("unexpected request builder error - headers missing in builder but no errors found"),
@@ -220,56 +619,81 @@ impl HttpClient {
     }
 
     pub async fn health(&self) -> HealthCheckResult {
-        let req = Request::get(self.health_url.as_uri())
+        if let Some(router) = &self.router {
+            return router.health().await;
+        }
+        let method = self.health_check.method.clone().unwrap_or(Method::GET);
+        let mut req = Request::builder()
+            .method(method)
+            .uri(self.health_url.as_uri())
             .body(BoxBody::default())
             .unwrap();
-        let res = self.inner.clone().call(req).await;
+        *req.headers_mut() = self.apply_headers(HeaderMap::new());
+        let mut inner = match self.inner.get() {
+            Ok(inner) => inner,
+            Err(error) => return error.into(),
+        };
+        let res = inner.call(req).await;
         match res {
             Ok(response) => {
                 let response = Response::from(response);
-                if response.status() == StatusCode::OK {
-                    if let Ok(body) = response.json::<OptionalHealthCheckResponseBody>().await {
-                        // If the service provided a body, we only anticipate a minimal health status and optional reason.
+                let code = response.status();
+                match self.health_check.expected_status {
+                    Some(expected_status) if code != expected_status => {
+                        return HealthCheckResult {
+                            status: HealthStatus::Unhealthy,
+                            code,
+                            reason: Some(format!(
+                                "unexpected status code, expected {expected_status}"
+                            )),
+                        };
+                    }
+                    None if code != StatusCode::OK => return status_code_health_result(code),
+                    _ => (),
+                }
+                if let Some(expected_body) = &self.health_check.expected_body {
+                    let body = response.0.into_body().collect().await;
+                    let healthy = body.is_ok_and(|body| {
+                        String::from_utf8_lossy(&body.to_bytes()).contains(expected_body.as_str())
+                    });
+                    return if healthy {
                         HealthCheckResult {
-                            status: body.status.clone(),
-                            code: StatusCode::OK,
-                            reason: match body.status {
-                                HealthStatus::Healthy => None,
-                                _ => body.reason,
-                            },
+                            status: HealthStatus::Healthy,
+                            code,
+                            reason: None,
                         }
                     } else {
-                        // If the service did not provide a body, we assume it is healthy.
                         HealthCheckResult {
-                            status: HealthStatus::Healthy,
-                            code: StatusCode::OK,
-                            reason: None,
+                            status: HealthStatus::Unhealthy,
+                            code,
+                            reason: Some("response body does not contain expected content".into()),
                         }
+                    };
+                }
+                if code != StatusCode::OK {
+                    // Expected non-200 status code
+                    return HealthCheckResult {
+                        status: HealthStatus::Healthy,
+                        code,
+                        reason: None,
+                    };
+                }
+                if let Ok(body) = response.json::<OptionalHealthCheckResponseBody>().await {
+                    // If the service provided a body, we only anticipate a minimal health status and optional reason.
+                    HealthCheckResult {
+                        status: body.status.clone(),
+                        code: StatusCode::OK,
+                        reason: match body.status {
+                            HealthStatus::Healthy => None,
+                            _ => body.reason,
+                        },
                     }
                 } else {
+                    // If the service did not provide a body, we assume it is healthy.
                     HealthCheckResult {
-                        // The most we can presume is that 5xx errors are likely indicating service issues, implying the service is unhealthy.
-                        // and that 4xx errors are more likely indicating health check failures, i.e. due to configuration/implementation issues.
-                        // Regardless we can't be certain, so the reason is also provided.
-                        // TODO: We will likely circle back to re-evaluate this logic in the future
-                        // when we know more about how the client health results will be used.
-                        status: if response.status().as_u16() >= 500
-                            && response.status().as_u16() < 600
-                        {
-                            HealthStatus::Unhealthy
-                        } else if response.status().as_u16() >= 400
-                            && response.status().as_u16() < 500
-                        {
-                            HealthStatus::Unknown
-                        } else {
-                            error!(
-                                "unexpected http health check status code: {}",
-                                response.status()
-                            );
-                            HealthStatus::Unknown
-                        },
-                        code: response.status(),
-                        reason: response.status().canonical_reason().map(|v| v.to_string()),
+                        status: HealthStatus::Healthy,
+                        code: StatusCode::OK,
+                        reason: None,
                     }
                 }
             }
@@ -285,6 +709,27 @@ impl HttpClient {
     }
 }
 
+/// Returns a health check result for a non-200 status code.
+fn status_code_health_result(code: StatusCode) -> HealthCheckResult {
+    HealthCheckResult {
+        // The most we can presume is that 5xx errors are likely indicating service issues, implying the service is unhealthy.
+        // and that 4xx errors are more likely indicating health check failures, i.e. due to configuration/implementation issues.
+        // Regardless we can't be certain, so the reason is also provided.
+        // TODO: We will likely circle back to re-evaluate this logic in the future
+        // when we know more about how the client health results will be used.
+        status: if code.is_server_error() {
+            HealthStatus::Unhealthy
+        } else if code.is_client_error() {
+            HealthStatus::Unknown
+        } else {
+            error!("unexpected http health check status code: {}", code);
+            HealthStatus::Unknown
+        },
+        code,
+        reason: code.canonical_reason().map(|v| v.to_string()),
+    }
+}
+
 pub type TracedResponse = hyper::Response<
     tower_http::trace::ResponseBody<
         Incoming,
@@ -449,7 +894,17 @@ pub fn extract_base_url(url: &Url) -> Option<Url> {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::response::IntoResponse;
+    use futures::FutureExt;
+
     use super::*;
+    use crate::{
+        clients::{RequestLimiter, SharedClientState, create_http_client},
+        config::{CanaryMode, RetryConfig, ServiceConfig},
+        utils::test_server::serve,
+    };
 
     #[test]
     fn test_extract_base_url() {
@@ -466,4 +921,258 @@ mod tests {
             health_url
         );
     }
+
+    #[tokio::test]
+    async fn test_health_check_config() {
+        let app = axum::Router::new().route(
+            "/ready",
+            axum::routing::post(|| async { (StatusCode::ACCEPTED, "status: ok") }),
+        );
+        let port = serve(app).await;
+
+        let health = |health_check: HealthCheckConfig| async move {
+            let service_config = ServiceConfig {
+                health_check,
+                ..ServiceConfig::new("localhost".into(), port)
+            };
+            create_http_client(port, &service_config)
+                .await
+                .unwrap()
+                .health()
+                .await
+        };
+        let mut health_check = HealthCheckConfig {
+            path: Some("/ready".into()),
+            method: Some(Method::POST),
+            expected_status: Some(StatusCode::ACCEPTED),
+            expected_body: Some("ok".into()),
+        };
+        let result = health(health_check.clone()).await;
+        assert!(matches!(result.status, HealthStatus::Healthy));
+
+        health_check.expected_body = Some("ready".into());
+        let result = health(health_check.clone()).await;
+        assert!(matches!(result.status, HealthStatus::Unhealthy));
+
+        // Default `/health` endpoint is not found
+        let result = health(HealthCheckConfig::default()).await;
+        assert!(matches!(result.status, HealthStatus::Unknown));
+        assert_eq!(result.code, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_size_limits() {
+        let app = axum::Router::new().route(
+            "/detect",
+            axum::routing::post(|| async { "x".repeat(1000) }),
+        );
+        let port = serve(app).await;
+
+        let client = create_http_client(port, &ServiceConfig::new("localhost".into(), port))
+            .await
+            .unwrap()
+            .with_size_limits(SizeLimits {
+                max_request_bytes: Some(10),
+                max_response_bytes: Some(100),
+            });
+        let url = client.endpoint("/detect");
+        let error = client
+            .post(url.clone(), HeaderMap::new(), "a".repeat(20))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = client.post(url, HeaderMap::new(), "a").await.unwrap();
+        let error = response.json::<String>().await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "client response body exceeds limit of 100 bytes"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_limiter() {
+        let app = axum::Router::new().route("/detect", axum::routing::post(|| async { "\"ok\"" }));
+        let port = serve(app).await;
+        let request_limiter = RequestLimiter::new(Some(1));
+        let client = create_http_client(port, &ServiceConfig::new("localhost".into(), port))
+            .await
+            .unwrap()
+            .with_shared_state(SharedClientState {
+                request_limiter: request_limiter.clone(),
+                ..Default::default()
+            });
+        let url = client.endpoint("/detect");
+
+        // The request is outstanding until its body is read
+        let response = client.post(url, HeaderMap::new(), "a").await.unwrap();
+        assert!(request_limiter.acquire().now_or_never().is_none());
+        assert_eq!(response.json::<String>().await.unwrap(), "ok");
+        assert!(request_limiter.acquire().now_or_never().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        // Serves 503 to the first two requests across clients
+        let requests = Arc::new(AtomicUsize::new(0));
+        let app = axum::Router::new().route(
+            "/detect",
+            axum::routing::post({
+                let requests = requests.clone();
+                move || async move {
+                    match requests.fetch_add(1, Ordering::Relaxed) {
+                        0 | 1 => StatusCode::SERVICE_UNAVAILABLE,
+                        _ => StatusCode::OK,
+                    }
+                }
+            }),
+        );
+        let port = serve(app).await;
+        let client = || async {
+            create_http_client(port, &ServiceConfig::new("localhost".into(), port))
+                .await
+                .unwrap()
+        };
+        let retry = RetryPolicy::from(Some(&RetryConfig {
+            max_attempts: 2,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 10,
+            jitter: false,
+            retriable_status_codes: vec![503],
+        }));
+
+        // Requests routed between backends are retried once all backends fail
+        let routed = client()
+            .await
+            .with_retry(retry.clone())
+            .with_backends(vec![("backup".into(), client().await)]);
+        let url = routed.endpoint("/detect");
+        let response = routed
+            .post(url.clone(), HeaderMap::new(), "a")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.load(Ordering::Relaxed), 3);
+
+        // Requests served by a canary are retried
+        requests.store(1, Ordering::Relaxed);
+        let canary = client().await.with_retry(retry).with_canary(Canary::new(
+            client().await,
+            100,
+            CanaryMode::Serve,
+        ));
+        let response = canary.post(url, HeaderMap::new(), "a").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_releases_permit() {
+        // Serves 503 to the first request
+        let requests = Arc::new(AtomicUsize::new(0));
+        let app = axum::Router::new().route(
+            "/detect",
+            axum::routing::post({
+                let requests = requests.clone();
+                move || async move {
+                    match requests.fetch_add(1, Ordering::Relaxed) {
+                        0 => StatusCode::SERVICE_UNAVAILABLE,
+                        _ => StatusCode::OK,
+                    }
+                }
+            }),
+        );
+        let port = serve(app).await;
+        let client = create_http_client(port, &ServiceConfig::new("localhost".into(), port))
+            .await
+            .unwrap()
+            .with_shared_state(SharedClientState {
+                request_limiter: RequestLimiter::new(Some(1)),
+                ..Default::default()
+            })
+            .with_retry(RetryPolicy::from(Some(&RetryConfig {
+                max_attempts: 2,
+                initial_backoff_ms: 1,
+                max_backoff_ms: 10,
+                jitter: false,
+                retriable_status_codes: vec![503],
+            })));
+
+        // The failed response releases its permit before the request is retried
+        let url = client.endpoint("/detect");
+        let response = tokio::time::timeout(
+            Duration::from_secs(5),
+            client.post(url, HeaderMap::new(), "a"),
+        )
+        .await
+        .expect("retry blocked by the permit of the failed response")
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_after() {
+        // Rate limits the first request, asking to retry after a second, then serves
+        // requests of the `/ok` route and rate limits all others for a minute
+        let requests = Arc::new(AtomicUsize::new(0));
+        let rate_limited = |retry_after: &'static str| {
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(hyper::header::RETRY_AFTER, retry_after)],
+                axum::Json(serde_json::json!({ "error": { "message": "slow down" } })),
+            )
+                .into_response()
+        };
+        let app = axum::Router::new()
+            .route(
+                "/ok",
+                axum::routing::post({
+                    let requests = requests.clone();
+                    move || async move {
+                        match requests.fetch_add(1, Ordering::Relaxed) {
+                            0 => rate_limited("1"),
+                            _ => StatusCode::OK.into_response(),
+                        }
+                    }
+                }),
+            )
+            .route(
+                "/busy",
+                axum::routing::post(move || async move { rate_limited("60") }),
+            );
+        let port = serve(app).await;
+        let client = create_http_client(port, &ServiceConfig::new("localhost".into(), port))
+            .await
+            .unwrap()
+            .with_retry(RetryPolicy::from(Some(&RetryConfig {
+                max_attempts: 2,
+                initial_backoff_ms: 1,
+                max_backoff_ms: 2000,
+                jitter: false,
+                retriable_status_codes: vec![429],
+            })));
+
+        // Retries wait for the Retry-After delay rather than the backoff
+        let start = Instant::now();
+        let response = client
+            .post(client.endpoint("/ok"), HeaderMap::new(), "a")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.load(Ordering::Relaxed), 2);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+
+        // Requests asking to wait longer than the maximum backoff fail without retrying,
+        // with the Retry-After delay and the message of OpenAI error bodies
+        let error = client
+            .post(client.endpoint("/busy"), HeaderMap::new(), "a")
+            .await
+            .unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(error.to_string(), "slow down");
+        let retry_after = error.retry_after().unwrap();
+        assert!(retry_after > Duration::from_secs(59) && retry_after <= Duration::from_secs(60));
+    }
 }
@@ -15,6 +15,8 @@
 
 */
 
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use axum::http::HeaderMap;
 use futures::{StreamExt, TryStreamExt};
@@ -23,8 +25,13 @@ use tonic::{Code, Request};
 use tracing::{Span, debug, instrument};
 
 use super::{
-    BoxStream, Client, Error, create_grpc_client, errors::grpc_to_http_code,
-    grpc_request_with_headers, otel_grpc::OtelGrpcService,
+    BoxStream, Client, Closeable, Error, HeaderTemplates, RequestLimiter,
+    breaker::{self, CircuitBreaker},
+    create_grpc_client,
+    errors::grpc_to_http_code,
+    grpc_request_with_headers,
+    otel_grpc::OtelGrpcService,
+    retry::RetryPolicy,
 };
 use crate::{
     config::ServiceConfig,
@@ -44,25 +51,41 @@ use crate::{
     utils::trace::trace_context_from_grpc_response,
 };
 
-const DEFAULT_PORT: u16 = 8085;
+pub const DEFAULT_PORT: u16 = 8085;
 const MODEL_ID_HEADER_NAME: &str = "mm-model-id";
 
 #[derive(Clone)]
 pub struct NlpClient {
-    client: NlpServiceClient<OtelGrpcService<LoadBalancedChannel>>,
-    health_client: HealthClient<OtelGrpcService<LoadBalancedChannel>>,
+    client: Closeable<NlpServiceClient<OtelGrpcService<LoadBalancedChannel>>>,
+    health_client: Closeable<HealthClient<OtelGrpcService<LoadBalancedChannel>>>,
+    headers: HeaderTemplates,
+    retry: RetryPolicy,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    request_limiter: RequestLimiter,
 }
 
 impl NlpClient {
     pub async fn new(config: &ServiceConfig) -> Self {
-        let client = create_grpc_client(DEFAULT_PORT, config, NlpServiceClient::new).await;
-        let health_client = create_grpc_client(DEFAULT_PORT, config, HealthClient::new).await;
+        let client =
+            Closeable::new(create_grpc_client(DEFAULT_PORT, config, NlpServiceClient::new).await);
+        let health_client =
+            Closeable::new(create_grpc_client(DEFAULT_PORT, config, HealthClient::new).await);
         Self {
             client,
             health_client,
+            headers: config.headers.clone(),
+            retry: RetryPolicy::from(config.retry.as_ref()),
+            circuit_breaker: CircuitBreaker::from_config(config),
+            request_limiter: RequestLimiter::default(),
         }
     }
 
+    /// Waits for a permit of `request_limiter` before sending requests.
+    pub fn with_request_limiter(mut self, request_limiter: RequestLimiter) -> Self {
+        self.request_limiter = request_limiter;
+        self
+    }
+
     #[instrument(skip_all, fields(model_id))]
     pub async fn tokenization_task_predict(
         &self,
@@ -70,10 +93,22 @@ impl NlpClient {
         request: TokenizationTaskRequest,
         headers: HeaderMap,
     ) -> Result<TokenizationResults, Error> {
-        let mut client = self.client.clone();
-        let request = request_with_headers(request, model_id, headers);
+        let headers = self.headers.apply(headers);
         debug!(?request, "sending request to NLP gRPC service");
-        let response = client.tokenization_task_predict(request).await?;
+        let response = breaker::call(
+            self.circuit_breaker.as_deref(),
+            self.retry.run(|_| {
+                let request = request_with_headers(request.clone(), model_id, headers.clone());
+                let client = self.client.get();
+                let request_limiter = &self.request_limiter;
+                async move {
+                    let mut client = client?;
+                    let _permit = request_limiter.acquire().await;
+                    Ok(client.tokenization_task_predict(request).await?)
+                }
+            }),
+        )
+        .await?;
         let span = Span::current();
         trace_context_from_grpc_response(&span, &response);
         Ok(response.into_inner())
@@ -87,10 +122,22 @@ impl NlpClient {
         headers: HeaderMap,
     ) -> Result<TokenClassificationResults, Error> {
         let span = Span::current();
-        let mut client = self.client.clone();
-        let request = request_with_headers(request, model_id, headers);
+        let headers = self.headers.apply(headers);
         debug!(?request, "sending request to NLP gRPC service");
-        let response = client.token_classification_task_predict(request).await?;
+        let response = breaker::call(
+            self.circuit_breaker.as_deref(),
+            self.retry.run(|_| {
+                let request = request_with_headers(request.clone(), model_id, headers.clone());
+                let client = self.client.get();
+                let request_limiter = &self.request_limiter;
+                async move {
+                    let mut client = client?;
+                    let _permit = request_limiter.acquire().await;
+                    Ok(client.token_classification_task_predict(request).await?)
+                }
+            }),
+        )
+        .await?;
         trace_context_from_grpc_response(&span, &response);
         Ok(response.into_inner())
     }
@@ -102,10 +149,22 @@ impl NlpClient {
         request: TextGenerationTaskRequest,
         headers: HeaderMap,
     ) -> Result<GeneratedTextResult, Error> {
-        let mut client = self.client.clone();
-        let request = request_with_headers(request, model_id, headers);
+        let headers = self.headers.apply(headers);
         debug!(?request, "sending request to NLP gRPC service");
-        let response = client.text_generation_task_predict(request).await?;
+        let response = breaker::call(
+            self.circuit_breaker.as_deref(),
+            self.retry.run(|_| {
+                let request = request_with_headers(request.clone(), model_id, headers.clone());
+                let client = self.client.get();
+                let request_limiter = &self.request_limiter;
+                async move {
+                    let mut client = client?;
+                    let _permit = request_limiter.acquire().await;
+                    Ok(client.text_generation_task_predict(request).await?)
+                }
+            }),
+        )
+        .await?;
         let span: Span = Span::current();
         trace_context_from_grpc_response(&span, &response);
         Ok(response.into_inner())
@@ -118,12 +177,22 @@ impl NlpClient {
         request: ServerStreamingTextGenerationTaskRequest,
         headers: HeaderMap,
     ) -> Result<BoxStream<Result<GeneratedTextStreamResult, Error>>, Error> {
-        let mut client = self.client.clone();
-        let request = request_with_headers(request, model_id, headers);
+        let headers = self.headers.apply(headers);
         debug!(?request, "sending stream request to NLP gRPC service");
-        let response = client
-            .server_streaming_text_generation_task_predict(request)
-            .await?;
+        let response = breaker::call(
+            self.circuit_breaker.as_deref(),
+            self.retry.run(|_| {
+                let request = request_with_headers(request.clone(), model_id, headers.clone());
+                let client = self.client.get();
+                // Streams are long-lived, so they are not limited by `request_limiter`
+                async move {
+                    Ok(client?
+                        .server_streaming_text_generation_task_predict(request)
+                        .await?)
+                }
+            }),
+        )
+        .await?;
         let span = Span::current();
         trace_context_from_grpc_response(&span, &response);
         let response_stream = response.into_inner().map_err(Into::into).boxed();
@@ -137,10 +206,20 @@ impl Client for NlpClient {
         "nlp"
     }
 
+    fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.circuit_breaker.as_deref()
+    }
+
     async fn health(&self) -> HealthCheckResult {
-        let mut client = self.health_client.clone();
+        let mut client = match self.health_client.get() {
+            Ok(client) => client,
+            Err(error) => return error.into(),
+        };
         let response = client
-            .check(HealthCheckRequest { service: "".into() })
+            .check(grpc_request_with_headers(
+                HealthCheckRequest { service: "".into() },
+                self.headers.apply(HeaderMap::new()),
+            ))
             .await;
         let code = match response {
             Ok(_) => Code::Ok,
@@ -160,6 +239,11 @@ impl Client for NlpClient {
             reason: None,
         }
     }
+
+    async fn shutdown(&self) {
+        self.client.close();
+        self.health_client.close();
+    }
 }
 
 /// Turns an NLP client gRPC request body of type `T` and headers into a `tonic::Request<T>`.
@@ -28,22 +28,32 @@ use tokio::sync::mpsc;
 use url::Url;
 
 use super::{
-    Client, Error, HttpClient, create_http_client,
+    Client, Error, HttpClient, SharedClientState,
+    breaker::CircuitBreaker,
+    create_http_client,
     detector::ContentAnalysisResponse,
     http::{HttpClientExt, RequestBody},
 };
 use crate::{
-    config::ServiceConfig,
+    config::{
+        FlaggedChoicesPolicy, FlaggedToolsPolicy, ImagePartsPolicy, ServiceConfig,
+        StructuredOutputPolicy,
+    },
     health::HealthCheckResult,
-    models::{DetectionWarningReason, DetectorParams, ValidationError},
+    models::{
+        ContentProvenance, DetectionWarningReason, DetectorParams, THRESHOLD_PARAM, ValidationError,
+    },
     orchestrator,
 };
 
-const DEFAULT_PORT: u16 = 8080;
+pub const DEFAULT_PORT: u16 = 8080;
 
 const CHAT_COMPLETIONS_ENDPOINT: &str = "/v1/chat/completions";
 const COMPLETIONS_ENDPOINT: &str = "/v1/completions";
 
+/// Maximum number of choices `n` of a chat completions request, each detected separately.
+pub const MAX_CHOICES: u64 = 16;
+
 #[derive(Clone)]
 pub struct OpenAiClient {
     client: HttpClient,
@@ -54,8 +64,11 @@ impl OpenAiClient {
     pub async fn new(
         config: &ServiceConfig,
         health_config: Option<&ServiceConfig>,
+        shared: &SharedClientState,
     ) -> Result<Self, Error> {
-        let client = create_http_client(DEFAULT_PORT, config).await?;
+        let client = create_http_client(DEFAULT_PORT, config)
+            .await?
+            .with_shared_state(shared.clone());
         let health_client = if let Some(health_config) = health_config {
             Some(create_http_client(DEFAULT_PORT, health_config).await?)
         } else {
@@ -181,6 +194,10 @@ impl Client for OpenAiClient {
         "openai"
     }
 
+    fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.client.circuit_breaker()
+    }
+
     async fn health(&self) -> HealthCheckResult {
         if let Some(health_client) = &self.health_client {
             health_client.health().await
@@ -188,6 +205,13 @@ impl Client for OpenAiClient {
             self.client.health().await
         }
     }
+
+    async fn shutdown(&self) {
+        self.client.shutdown();
+        if let Some(health_client) = &self.health_client {
+            health_client.shutdown();
+        }
+    }
 }
 
 impl HttpClientExt for OpenAiClient {
@@ -244,12 +268,77 @@ pub struct ChatCompletionsRequest {
     pub model: String,
     /// Messages.
     pub messages: Vec<Message>,
+    /// Guardrails extension.
+    #[serde(default, skip_serializing)]
+    pub guardrails: GuardrailsExtension,
     /// Extra fields not captured above.
     #[serde(flatten)]
     pub extra: Map<String, Value>,
 }
 
 impl ChatCompletionsRequest {
+    /// Applies detectors and thresholds of the guardrails extension to `detectors`.
+    pub fn apply_guardrails(&mut self) {
+        if let Some(detectors) = self.guardrails.detectors.take() {
+            self.detectors = detectors;
+        }
+        for (detector_id, threshold) in &self.guardrails.thresholds {
+            for detectors in [&mut self.detectors.input, &mut self.detectors.output] {
+                if let Some(params) = detectors.get_mut(detector_id) {
+                    params.insert(THRESHOLD_PARAM.into(), (*threshold).into());
+                }
+            }
+        }
+    }
+
+    /// Returns the tool definitions of the request.
+    pub fn tools(&self) -> Result<Vec<Tool>, ValidationError> {
+        match self.extra.get("tools") {
+            Some(tools) => Vec::<Tool>::deserialize(tools)
+                .map_err(|error| ValidationError::Invalid(format!("invalid `tools`: {error}"))),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the number of choices requested with `n`, defaulting to 1.
+    pub fn n(&self) -> u32 {
+        self.extra
+            .get("n")
+            .and_then(Value::as_u64)
+            .map(|n| n as u32)
+            .unwrap_or(1)
+    }
+
+    /// Returns the response format of the request.
+    pub fn response_format(&self) -> Result<Option<ResponseFormat>, ValidationError> {
+        self.extra
+            .get("response_format")
+            .map(ResponseFormat::deserialize)
+            .transpose()
+            .map_err(|error| {
+                ValidationError::Invalid(format!("invalid `response_format`: {error}"))
+            })
+    }
+
+    /// Retains only the tool definitions for which `f` returns `true`,
+    /// given the index of the tool. Tool definitions are otherwise passed through as-is.
+    ///
+    /// If no tools remain, `tools` and `tool_choice` are removed from the request.
+    pub fn retain_tools(&mut self, mut f: impl FnMut(usize) -> bool) {
+        if let Some(Value::Array(tools)) = self.extra.get_mut("tools") {
+            let mut index = 0;
+            tools.retain(|_| {
+                let keep = f(index);
+                index += 1;
+                keep
+            });
+            if tools.is_empty() {
+                self.extra.remove("tools");
+                self.extra.remove("tool_choice");
+            }
+        }
+    }
+
     pub fn validate(&self) -> Result<(), ValidationError> {
         if self.model.is_empty() {
             return Err(ValidationError::Invalid("`model` must not be empty".into()));
@@ -259,6 +348,40 @@ impl ChatCompletionsRequest {
                 "`messages` must not be empty".into(),
             ));
         }
+        if let Some(n) = self.extra.get("n") {
+            if !n.as_u64().is_some_and(|n| (1..=MAX_CHOICES).contains(&n)) {
+                return Err(ValidationError::Invalid(format!(
+                    "`n` must be between 1 and {MAX_CHOICES}"
+                )));
+            }
+        }
+        self.validate_guardrails()
+    }
+
+    fn validate_guardrails(&self) -> Result<(), ValidationError> {
+        let detectors = match &self.guardrails.detectors {
+            Some(_) if !self.detectors.input.is_empty() || !self.detectors.output.is_empty() => {
+                return Err(ValidationError::Invalid(
+                    "`detectors` and `guardrails.detectors` must not both be set".into(),
+                ));
+            }
+            Some(detectors) => detectors,
+            None => &self.detectors,
+        };
+        for (detector_id, threshold) in &self.guardrails.thresholds {
+            if !detectors.input.contains_key(detector_id)
+                && !detectors.output.contains_key(detector_id)
+            {
+                return Err(ValidationError::Invalid(format!(
+                    "`guardrails.thresholds` detector `{detector_id}` is not a requested detector"
+                )));
+            }
+            if !threshold.is_finite() {
+                return Err(ValidationError::Invalid(format!(
+                    "`guardrails.thresholds` detector `{detector_id}` threshold must be a number"
+                )));
+            }
+        }
         Ok(())
     }
 }
@@ -311,6 +434,35 @@ pub struct DetectorConfig {
     pub output: HashMap<String, DetectorParams>,
 }
 
+/// Guardrails extension of OpenAI-compatible requests, allowing SDK users
+/// to configure guardrails per request, e.g. with `extra_body={"guardrails": {...}}`.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuardrailsExtension {
+    /// Detectors for input and output, alternative to top-level `detectors`.
+    pub detectors: Option<DetectorConfig>,
+    /// Score thresholds by detector id, overriding detector `threshold` params.
+    #[serde(default)]
+    pub thresholds: HashMap<String, f64>,
+    /// Overrides of configured actions.
+    #[serde(default)]
+    pub actions: GuardrailsActions,
+}
+
+/// Guardrails action overrides, defaulting to the orchestrator config.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuardrailsActions {
+    /// Handling of image content parts.
+    pub image_parts: Option<ImagePartsPolicy>,
+    /// Handling of choices flagged by output detectors.
+    pub flagged_choices: Option<FlaggedChoicesPolicy>,
+    /// Handling of tool definitions flagged by input detectors.
+    pub flagged_tools: Option<FlaggedToolsPolicy>,
+    /// Validation of output requested with a JSON response format.
+    pub structured_output: Option<StructuredOutputPolicy>,
+}
+
 /// Response format.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResponseFormat {
@@ -321,6 +473,22 @@ pub struct ResponseFormat {
     pub json_schema: HashMap<String, serde_json::Value>,
 }
 
+impl ResponseFormat {
+    /// Returns `true` if the response format requires JSON output.
+    pub fn is_json(&self) -> bool {
+        matches!(self.r#type.as_str(), "json_object" | "json_schema")
+    }
+
+    /// Returns the JSON schema of a `json_schema` response format.
+    pub fn schema(&self) -> Option<&serde_json::Value> {
+        if self.r#type == "json_schema" {
+            self.json_schema.get("schema")
+        } else {
+            None
+        }
+    }
+}
+
 /// Tool.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tool {
@@ -330,6 +498,23 @@ pub struct Tool {
     pub function: ToolFunction,
 }
 
+impl Tool {
+    /// Returns the text of the tool definition to run detection on,
+    /// including the function name, description and parameters schema.
+    pub fn text(&self) -> String {
+        let mut text = self.function.name.clone();
+        if let Some(description) = &self.function.description {
+            text.push('\n');
+            text.push_str(description);
+        }
+        if !self.function.parameters.is_empty() {
+            text.push('\n');
+            text.push_str(&serde_json::to_string(&self.function.parameters).unwrap_or_default());
+        }
+        text
+    }
+}
+
 /// Tool function.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolFunction {
@@ -406,6 +591,9 @@ pub struct Message {
     /// The refusal message by the assistant. (assistant message only)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub refusal: Option<String>,
+    /// The reasoning content by the assistant. (assistant message only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
     /// The tool calls generated by the model, such as function calls. (assistant message only)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>,
@@ -424,6 +612,13 @@ pub enum Content {
     Array(Vec<ContentPart>),
 }
 
+impl Content {
+    /// Returns `true` if the content contains image parts.
+    pub fn has_images(&self) -> bool {
+        matches!(self, Content::Array(parts) if parts.iter().any(|part| part.r#type == ContentType::ImageUrl))
+    }
+}
+
 impl From<String> for Content {
     fn from(value: String) -> Self {
         Content::Text(value)
@@ -460,7 +655,7 @@ impl From<Vec<String>> for Content {
 }
 
 /// Content type.
-#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub enum ContentType {
     #[serde(rename = "text")]
     #[default]
@@ -548,6 +743,9 @@ pub struct ChatCompletion {
     /// Warnings
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub warnings: Vec<OrchestratorWarning>,
+    /// Provenance of generated choices, if configured. Streamed in the last chunk
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provenance: Vec<ChoiceProvenance>,
 }
 
 /// Chat completion choice.
@@ -578,6 +776,9 @@ pub struct ChatCompletionMessage {
     /// The refusal message generated by the model.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub refusal: Option<String>,
+    /// The reasoning content generated by the model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
 }
 
 /// Chat completion logprobs.
@@ -597,6 +798,7 @@ pub struct ChatCompletionLogprob {
     pub token: String,
     /// The log probability of this token.
     pub logprob: f32,
+    /// The UTF-8 bytes representation of the token.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bytes: Option<Vec<u8>>,
     /// List of the most likely tokens and their log probability, at this token position.
@@ -611,6 +813,9 @@ pub struct ChatCompletionTopLogprob {
     pub token: String,
     /// The log probability of this token.
     pub logprob: f32,
+    /// The UTF-8 bytes representation of the token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<Vec<u8>>,
 }
 
 /// Streaming chat completion chunk.
@@ -633,6 +838,8 @@ pub struct ChatCompletionChunk {
     /// This field is only included if the service_tier parameter is specified in the request.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub service_tier: Option<String>,
+    /// Usage statistics for the completion request.
+    /// Only included in the last chunk when requested with `stream_options.include_usage`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<Usage>,
     /// Detections
@@ -641,6 +848,9 @@ pub struct ChatCompletionChunk {
     /// Warnings
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub warnings: Vec<OrchestratorWarning>,
+    /// Provenance of generated choices, if configured. Streamed in the last chunk
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provenance: Vec<ChoiceProvenance>,
 }
 
 /// Streaming chat completion chunk choice.
@@ -670,6 +880,9 @@ pub struct ChatCompletionDelta {
     /// The refusal message generated by the model.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub refusal: Option<String>,
+    /// The reasoning content generated by the model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
     /// The tool calls generated by the model, such as function calls.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub tool_calls: Vec<ToolCall>,
@@ -747,25 +960,39 @@ pub struct Usage {
     /// Number of tokens in the generated completion.
     pub completion_tokens: u32,
     /// Breakdown of tokens used in the prompt.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub prompt_token_details: Option<PromptTokenDetails>,
+    #[serde(
+        alias = "prompt_token_details",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub prompt_tokens_details: Option<PromptTokenDetails>,
     /// Breakdown of tokens used in a completion.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub completion_token_details: Option<CompletionTokenDetails>,
+    #[serde(
+        alias = "completion_token_details",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub completion_tokens_details: Option<CompletionTokenDetails>,
 }
 
 /// Completion token details.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
 pub struct CompletionTokenDetails {
-    pub audio_tokens: u32,
-    pub reasoning_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accepted_prediction_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejected_prediction_tokens: Option<u32>,
 }
 
 /// Prompt token details.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
 pub struct PromptTokenDetails {
-    pub audio_tokens: u32,
-    pub cached_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached_tokens: Option<u32>,
 }
 
 /// Stop tokens.
@@ -787,6 +1014,14 @@ pub struct OpenAiError {
     pub code: u16,
 }
 
+/// Provenance of the message content of a chat completion choice.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChoiceProvenance {
+    pub choice_index: u32,
+    #[serde(flatten)]
+    pub provenance: ContentProvenance,
+}
+
 /// Guardrails chat detections.
 #[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ChatDetections {
@@ -794,20 +1029,53 @@ pub struct ChatDetections {
     pub input: Vec<InputDetectionResult>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub output: Vec<OutputDetectionResult>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolDetectionResult>,
 }
 
 /// Guardrails chat input detections.
+/// Detection spans are relative to the message field or content part.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct InputDetectionResult {
     pub message_index: u32,
+    #[serde(default, skip_serializing_if = "MessageField::is_content")]
+    pub field: MessageField,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub part_index: Option<u32>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub results: Vec<ContentAnalysisResponse>,
 }
 
 /// Guardrails chat output detections.
+/// Detection spans are relative to the message field.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct OutputDetectionResult {
     pub choice_index: u32,
+    #[serde(default, skip_serializing_if = "MessageField::is_content")]
+    pub field: MessageField,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub results: Vec<ContentAnalysisResponse>,
+}
+
+/// Chat message field containing detected text.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageField {
+    #[default]
+    Content,
+    ReasoningContent,
+}
+
+impl MessageField {
+    pub fn is_content(&self) -> bool {
+        matches!(self, MessageField::Content)
+    }
+}
+
+/// Guardrails chat tool definition detections.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ToolDetectionResult {
+    pub tool_index: u32,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub results: Vec<ContentAnalysisResponse>,
 }
@@ -861,6 +1129,7 @@ mod test {
                 stream: None,
                 model: "test".into(),
                 messages: messages.clone(),
+                guardrails: GuardrailsExtension::default(),
                 extra,
             }
         );
@@ -878,6 +1147,7 @@ mod test {
                 stream: None,
                 model: "test".into(),
                 messages: messages.clone(),
+                guardrails: GuardrailsExtension::default(),
                 extra: Map::new(),
             }
         );
@@ -937,4 +1207,214 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_chat_completion_logprobs_and_usage() -> Result<(), serde_json::Error> {
+        let json_completion = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "test",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hi"},
+                "logprobs": {
+                    "content": [{
+                        "token": "Hi",
+                        "logprob": -0.5,
+                        "bytes": [72, 105],
+                        "top_logprobs": [{"token": "Hi", "logprob": -0.5, "bytes": [72, 105]}],
+                    }],
+                },
+                "finish_reason": "stop",
+                "stop_reason": null,
+            }],
+            "usage": {
+                "prompt_tokens": 5,
+                "total_tokens": 6,
+                "completion_tokens": 1,
+                "prompt_tokens_details": {"cached_tokens": 2},
+                "completion_tokens_details": {"reasoning_tokens": 0},
+            },
+            "prompt_logprobs": null,
+        });
+        let completion = ChatCompletion::deserialize(&json_completion)?;
+        assert_eq!(serde_json::to_value(&completion)?, json_completion);
+
+        let json_chunk = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 1,
+            "model": "test",
+            "choices": [],
+            "usage": {"prompt_tokens": 5, "total_tokens": 6, "completion_tokens": 1},
+        });
+        let chunk = ChatCompletionChunk::deserialize(&json_chunk)?;
+        assert_eq!(serde_json::to_value(&chunk)?, json_chunk);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chat_completions_request_tools() -> Result<(), serde_json::Error> {
+        let mut request = ChatCompletionsRequest::deserialize(&json!({
+            "model": "test",
+            "messages": [{"role": "user", "content": "Hi there!"}],
+            "tools": [
+                {
+                    "type": "function",
+                    "function": {
+                        "name": "get_weather",
+                        "description": "Get the weather.",
+                        "parameters": {"type": "object"},
+                        "x-custom": true,
+                    },
+                },
+                {"type": "function", "function": {"name": "ignore_instructions"}},
+            ],
+            "tool_choice": "auto",
+        }))?;
+        let tools = request.tools().unwrap();
+        assert_eq!(tools.len(), 2);
+        assert_eq!(
+            tools[0].text(),
+            "get_weather\nGet the weather.\n{\"type\":\"object\"}"
+        );
+        assert_eq!(tools[1].text(), "ignore_instructions");
+
+        // Retained tools are passed through as-is
+        request.retain_tools(|index| index == 0);
+        assert_eq!(
+            request.extra["tools"],
+            json!([{
+                "type": "function",
+                "function": {
+                    "name": "get_weather",
+                    "description": "Get the weather.",
+                    "parameters": {"type": "object"},
+                    "x-custom": true,
+                },
+            }])
+        );
+        request.retain_tools(|_| false);
+        assert!(!request.extra.contains_key("tools") && !request.extra.contains_key("tool_choice"));
+
+        let request = ChatCompletionsRequest::deserialize(&json!({
+            "model": "test",
+            "messages": [{"role": "user", "content": "Hi there!"}],
+            "tools": ["invalid"],
+        }))?;
+        assert!(request.tools().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chat_completions_request_response_format() -> Result<(), serde_json::Error> {
+        let schema = json!({"type": "object", "required": ["name"]});
+        let request = ChatCompletionsRequest::deserialize(&json!({
+            "model": "test",
+            "messages": [{"role": "user", "content": "Hi there!"}],
+            "response_format": {
+                "type": "json_schema",
+                "json_schema": {"name": "person", "schema": schema},
+            },
+        }))?;
+        let response_format = request.response_format().unwrap().unwrap();
+        assert!(response_format.is_json());
+        assert_eq!(response_format.schema(), Some(&schema));
+        // Response format is passed through as-is
+        assert_eq!(
+            serde_json::to_value(&request)?["response_format"]["json_schema"]["schema"],
+            schema
+        );
+
+        let request = ChatCompletionsRequest::deserialize(&json!({
+            "model": "test",
+            "messages": [{"role": "user", "content": "Hi there!"}],
+            "response_format": {"type": "text"},
+        }))?;
+        let response_format = request.response_format().unwrap().unwrap();
+        assert!(!response_format.is_json() && response_format.schema().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chat_completions_request_guardrails() -> Result<(), serde_json::Error> {
+        let messages = json!([{"role": "user", "content": "Hi there!"}]);
+        let mut request = ChatCompletionsRequest::deserialize(&json!({
+            "model": "test",
+            "messages": messages,
+            "guardrails": {
+                "detectors": {
+                    "input": {"hap": {}},
+                    "output": {"hap": {}, "pii": {"threshold": 0.2}},
+                },
+                "thresholds": {"hap": 0.8},
+                "actions": {"flagged_choices": "drop"},
+            },
+        }))?;
+        assert!(request.validate().is_ok());
+        assert_eq!(
+            request.guardrails.actions.flagged_choices,
+            Some(FlaggedChoicesPolicy::Drop)
+        );
+        request.apply_guardrails();
+        assert_eq!(request.detectors.input["hap"][THRESHOLD_PARAM], 0.8);
+        assert_eq!(request.detectors.output["hap"][THRESHOLD_PARAM], 0.8);
+        assert_eq!(request.detectors.output["pii"][THRESHOLD_PARAM], 0.2);
+        // Guardrails extension is not sent to the generation backend
+        assert!(serde_json::to_value(&request)?.get("guardrails").is_none());
+
+        // Test validation errors
+        let request = ChatCompletionsRequest::deserialize(&json!({
+            "model": "test",
+            "messages": messages,
+            "detectors": {"input": {"hap": {}}},
+            "guardrails": {"detectors": {"input": {"hap": {}}}},
+        }))?;
+        assert!(request.validate().is_err());
+
+        let request = ChatCompletionsRequest::deserialize(&json!({
+            "model": "test",
+            "messages": messages,
+            "detectors": {"input": {"hap": {}}},
+            "guardrails": {"thresholds": {"pii": 0.5}},
+        }))?;
+        assert!(
+            request
+                .validate()
+                .is_err_and(|error| error.to_string().contains("`pii`"))
+        );
+
+        let result = ChatCompletionsRequest::deserialize(&json!({
+            "model": "test",
+            "messages": messages,
+            "guardrails": {"actions": {"unknown": "drop"}},
+        }));
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_parts() {
+        let content = Content::Array(vec![
+            "Describe this image.".to_string().into(),
+            ContentPart {
+                r#type: ContentType::ImageUrl,
+                image_url: Some(ImageUrl {
+                    url: "https://example.com/image.png".into(),
+                    detail: None,
+                }),
+                ..Default::default()
+            },
+            "Be brief.".to_string().into(),
+        ]);
+        assert!(content.has_images());
+
+        let content = Content::Text("Hi there!".into());
+        assert!(!content.has_images());
+    }
 }
@@ -0,0 +1,311 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Client of the orchestrator API, for Rust services calling the orchestrator.
+use async_trait::async_trait;
+use eventsource_stream::Eventsource;
+use futures::StreamExt;
+use http_body_util::BodyExt;
+use hyper::{HeaderMap, StatusCode};
+use serde::{Deserialize, de::DeserializeOwned};
+use tracing::info;
+
+use super::{
+    BoxStream, Client, Error, HttpClient, create_http_client,
+    http::{HttpClientExt, RequestBody},
+};
+use crate::{
+    api::{
+        ChatDetectionHttpRequest, ChatDetectionResult, ChunksHttpRequest, ChunksResult,
+        ClassificationWithGenerationHttpRequest, ClassifiedGeneratedTextResult,
+        ClassifiedGeneratedTextStreamResult, ContextDocsHttpRequest, ContextDocsResult,
+        DetectionOnGeneratedHttpRequest, DetectionOnGenerationResult,
+        GenerationWithDetectionHttpRequest, GenerationWithDetectionResult, SuitabilityHttpRequest,
+        SuitabilityResult, TextContentDetectionHttpRequest, TextContentDetectionResult,
+    },
+    config::ServiceConfig,
+    health::HealthCheckResult,
+};
+
+pub const DEFAULT_PORT: u16 = 8033;
+
+const CLASSIFICATION_WITH_GENERATION_ENDPOINT: &str = "/api/v2/text/classification-generation";
+const STREAM_CLASSIFICATION_WITH_GENERATION_ENDPOINT: &str =
+    "/api/v2/text/classification-generation/stream";
+const GENERATION_WITH_DETECTION_ENDPOINT: &str = "/api/v2/text/generation-detection";
+const TEXT_CONTENT_DETECTION_ENDPOINT: &str = "/api/v2/text/detection/content";
+const CHAT_DETECTION_ENDPOINT: &str = "/api/v2/text/detection/chat";
+const CONTEXT_DOCS_DETECTION_ENDPOINT: &str = "/api/v2/text/detection/context";
+const DETECTION_ON_GENERATED_ENDPOINT: &str = "/api/v2/text/detection/generated";
+const SUITABILITY_ENDPOINT: &str = "/api/v2/text/suitability";
+const CHUNKS_ENDPOINT: &str = "/api/v2/text/chunks";
+
+/// Error response of the orchestrator.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OrchestratorError {
+    pub code: u16,
+    pub details: String,
+}
+
+#[derive(Clone)]
+pub struct OrchestratorClient {
+    client: HttpClient,
+    health_client: Option<HttpClient>,
+}
+
+impl OrchestratorClient {
+    /// Creates a client of the orchestrator served by `config`, probing health with
+    /// `health_config`, i.e. the orchestrator's health server, if set.
+    pub async fn new(
+        config: &ServiceConfig,
+        health_config: Option<&ServiceConfig>,
+    ) -> Result<Self, Error> {
+        let client = create_http_client(DEFAULT_PORT, config).await?;
+        let health_client = if let Some(health_config) = health_config {
+            Some(create_http_client(DEFAULT_PORT, health_config).await?)
+        } else {
+            None
+        };
+        Ok(Self {
+            client,
+            health_client,
+        })
+    }
+
+    pub fn client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    pub async fn classification_with_generation(
+        &self,
+        request: ClassificationWithGenerationHttpRequest,
+        headers: HeaderMap,
+    ) -> Result<ClassifiedGeneratedTextResult, Error> {
+        self.handle_unary(CLASSIFICATION_WITH_GENERATION_ENDPOINT, request, headers)
+            .await
+    }
+
+    /// Returns a stream of results, ending with the first error.
+    pub async fn stream_classification_with_generation(
+        &self,
+        request: ClassificationWithGenerationHttpRequest,
+        headers: HeaderMap,
+    ) -> Result<BoxStream<Result<ClassifiedGeneratedTextStreamResult, Error>>, Error> {
+        self.handle_streaming(
+            STREAM_CLASSIFICATION_WITH_GENERATION_ENDPOINT,
+            request,
+            headers,
+        )
+        .await
+    }
+
+    pub async fn generation_with_detection(
+        &self,
+        request: GenerationWithDetectionHttpRequest,
+        headers: HeaderMap,
+    ) -> Result<GenerationWithDetectionResult, Error> {
+        self.handle_unary(GENERATION_WITH_DETECTION_ENDPOINT, request, headers)
+            .await
+    }
+
+    pub async fn text_content_detection(
+        &self,
+        request: TextContentDetectionHttpRequest,
+        headers: HeaderMap,
+    ) -> Result<TextContentDetectionResult, Error> {
+        self.handle_unary(TEXT_CONTENT_DETECTION_ENDPOINT, request, headers)
+            .await
+    }
+
+    pub async fn chat_detection(
+        &self,
+        request: ChatDetectionHttpRequest,
+        headers: HeaderMap,
+    ) -> Result<ChatDetectionResult, Error> {
+        self.handle_unary(CHAT_DETECTION_ENDPOINT, request, headers)
+            .await
+    }
+
+    pub async fn context_docs_detection(
+        &self,
+        request: ContextDocsHttpRequest,
+        headers: HeaderMap,
+    ) -> Result<ContextDocsResult, Error> {
+        self.handle_unary(CONTEXT_DOCS_DETECTION_ENDPOINT, request, headers)
+            .await
+    }
+
+    pub async fn detection_on_generated(
+        &self,
+        request: DetectionOnGeneratedHttpRequest,
+        headers: HeaderMap,
+    ) -> Result<DetectionOnGenerationResult, Error> {
+        self.handle_unary(DETECTION_ON_GENERATED_ENDPOINT, request, headers)
+            .await
+    }
+
+    pub async fn suitability(
+        &self,
+        request: SuitabilityHttpRequest,
+        headers: HeaderMap,
+    ) -> Result<SuitabilityResult, Error> {
+        self.handle_unary(SUITABILITY_ENDPOINT, request, headers)
+            .await
+    }
+
+    pub async fn chunks(
+        &self,
+        request: ChunksHttpRequest,
+        headers: HeaderMap,
+    ) -> Result<ChunksResult, Error> {
+        self.handle_unary(CHUNKS_ENDPOINT, request, headers).await
+    }
+
+    async fn handle_unary<R, S>(
+        &self,
+        path: &str,
+        request: R,
+        headers: HeaderMap,
+    ) -> Result<S, Error>
+    where
+        R: RequestBody,
+        S: DeserializeOwned,
+    {
+        let url = self.client.endpoint(path);
+        info!("sending orchestrator request to {}", url);
+        let response = self.client.post(url, headers, request).await?;
+        match response.status() {
+            StatusCode::OK => response.json::<S>().await,
+            code => Err(error_response(
+                code,
+                response.json::<OrchestratorError>().await,
+            )),
+        }
+    }
+
+    async fn handle_streaming<R, S>(
+        &self,
+        path: &str,
+        request: R,
+        headers: HeaderMap,
+    ) -> Result<BoxStream<Result<S, Error>>, Error>
+    where
+        R: RequestBody,
+        S: DeserializeOwned + Send + 'static,
+    {
+        let url = self.client.endpoint(path);
+        info!("sending orchestrator streaming request to {}", url);
+        let response = self.client.post(url, headers, request).await?;
+        if response.status() != StatusCode::OK {
+            let code = response.status();
+            return Err(error_response(
+                code,
+                response.json::<OrchestratorError>().await,
+            ));
+        }
+        let event_stream = response
+            .0
+            .into_data_stream()
+            .eventsource()
+            .map(|result| match result {
+                Ok(event) if event.event == "error" => {
+                    let error = serde_json::from_str::<OrchestratorError>(&event.data);
+                    Err(error_response(StatusCode::INTERNAL_SERVER_ERROR, error))
+                }
+                Ok(event) => serde_json::from_str::<S>(&event.data).map_err(|e| Error::Http {
+                    code: StatusCode::INTERNAL_SERVER_ERROR,
+                    message: format!("deserialization error: {e}"),
+                }),
+                Err(error) => Err(Error::Http {
+                    code: StatusCode::INTERNAL_SERVER_ERROR,
+                    message: error.to_string(),
+                }),
+            });
+        // Errors are terminal, as for the orchestrator's own event streams
+        let mut failed = false;
+        let stream = event_stream.take_while(move |result| {
+            let take = !failed;
+            failed |= result.is_err();
+            futures::future::ready(take)
+        });
+        Ok(Box::pin(stream))
+    }
+}
+
+/// Converts an orchestrator error response to a client error, falling back to `code`
+/// if the response is not an orchestrator error.
+fn error_response<E>(code: StatusCode, error: Result<OrchestratorError, E>) -> Error {
+    match error {
+        Ok(error) => Error::Http {
+            code: StatusCode::from_u16(error.code).unwrap_or(code),
+            message: error.details,
+        },
+        Err(_) => Error::Http {
+            code,
+            message: "unknown error occurred".into(),
+        },
+    }
+}
+
+#[async_trait]
+impl Client for OrchestratorClient {
+    fn name(&self) -> &str {
+        "orchestrator"
+    }
+
+    async fn health(&self) -> HealthCheckResult {
+        if let Some(health_client) = &self.health_client {
+            health_client.health().await
+        } else {
+            self.client.health().await
+        }
+    }
+
+    async fn shutdown(&self) {
+        self.client.shutdown();
+        if let Some(health_client) = &self.health_client {
+            health_client.shutdown();
+        }
+    }
+}
+
+impl HttpClientExt for OrchestratorClient {
+    fn inner(&self) -> &HttpClient {
+        self.client()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_response() {
+        let error = error_response::<()>(
+            StatusCode::BAD_REQUEST,
+            Ok(OrchestratorError {
+                code: 422,
+                details: "`prompt` is required".into(),
+            }),
+        );
+        assert_eq!(error.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.to_string(), "`prompt` is required");
+
+        let error = error_response(StatusCode::BAD_GATEWAY, Err(()));
+        assert_eq!(error.status_code(), StatusCode::BAD_GATEWAY);
+    }
+}
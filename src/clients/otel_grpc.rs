@@ -30,7 +30,10 @@ use tower::Layer;
 use tracing::{Span, error, info, info_span};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-use crate::utils::trace::{current_trace_id, with_traceparent_header};
+use crate::utils::{
+    exemplars,
+    trace::{current_trace_id, with_traceparent_header},
+};
 
 // Adapted from https://github.com/davidB/tracing-opentelemetry-instrumentation-sdk/tree/main/tonic-tracing-opentelemetry
 /// Layer for grpc (tonic client):
@@ -140,6 +143,11 @@ where
             monotonic_counter.incoming_request_count = 1,
             "started processing request",
         );
+        let client = req
+            .uri()
+            .authority()
+            .map(|authority| authority.to_string())
+            .unwrap_or_default();
         let future = {
             let _enter = span.enter();
             self.inner.call(req)
@@ -147,6 +155,8 @@ where
         ResponseFuture {
             inner: future,
             span: span.clone(),
+            client,
+            start: Instant::now(),
         }
     }
 }
@@ -159,6 +169,9 @@ pin_project! {
         #[pin]
         pub(crate) inner: F,
         pub(crate) span: Span,
+        // Authority of the request, labeling its latency
+        pub(crate) client: String,
+        pub(crate) start: Instant,
     }
 }
 
@@ -179,6 +192,7 @@ where
             .unwrap()
             .as_millis();
         log_on_response_or_error(request_duration_ms, &result);
+        exemplars::observe_client(this.client, this.start.elapsed());
         Poll::Ready(result)
     }
 }
@@ -0,0 +1,323 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Connection pools of HTTP clients, shared between clients of the same endpoint,
+//! with metrics of connection counts and a maximum connection age.
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, OnceLock, Weak},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use http_body_util::combinators::BoxBody;
+use hyper::{
+    Request, Response, Uri,
+    body::{Bytes, Incoming},
+    rt::{Read, ReadBufCursor, Write},
+};
+use hyper_rustls::HttpsConnector;
+use hyper_timeout::TimeoutConnector;
+use hyper_util::client::legacy::{
+    self,
+    connect::{Connected, Connection, HttpConnector, capture_connection},
+};
+use tracing::info;
+
+use super::dns::HttpResolver;
+
+pub type PooledClient = legacy::Client<
+    TimeoutConnector<HttpsConnector<CountedConnector<HttpConnector<HttpResolver>>>>,
+    BoxBody<Bytes, hyper::Error>,
+>;
+
+/// Pools shared between clients, by endpoint and connection settings.
+/// Pools are dropped with the last client using them.
+static SHARED_POOLS: OnceLock<Mutex<HashMap<String, Weak<Pool>>>> = OnceLock::new();
+
+struct Pool {
+    client: PooledClient,
+    /// Age after which connections are not reused
+    max_connection_age: Duration,
+}
+
+/// Connection pool of an HTTP client.
+///
+/// Connections are not reused once older than the maximum connection age, so busy
+/// connections are also replaced by connections to re-resolved addresses, rebalancing
+/// requests across scaled deployments.
+#[derive(Clone)]
+pub struct ConnectionPool(Arc<Pool>);
+
+impl ConnectionPool {
+    pub fn new(client: PooledClient, max_connection_age: Duration) -> Self {
+        Self(Arc::new(Pool {
+            client,
+            max_connection_age,
+        }))
+    }
+
+    /// Returns the pool shared under `key`, if any client still uses it.
+    pub fn shared(key: &str) -> Option<Self> {
+        SHARED_POOLS
+            .get_or_init(Mutex::default)
+            .lock()
+            .unwrap()
+            .get(key)
+            .and_then(Weak::upgrade)
+            .map(Self)
+    }
+
+    /// Shares this pool under `key`.
+    pub fn share(&self, key: String) {
+        let mut pools = SHARED_POOLS.get_or_init(Mutex::default).lock().unwrap();
+        pools.retain(|_, pool| pool.strong_count() > 0);
+        pools.insert(key, Arc::downgrade(&self.0));
+    }
+}
+
+impl tower::Service<Request<BoxBody<Bytes, hyper::Error>>> for ConnectionPool {
+    type Response = Response<Incoming>;
+    type Error = legacy::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, mut request: Request<BoxBody<Bytes, hyper::Error>>) -> Self::Future {
+        let connection = capture_connection(&mut request);
+        let max_connection_age = self.0.max_connection_age;
+        let response = self.0.client.request(request);
+        Box::pin(async move {
+            let response = response.await?;
+            let expired = response
+                .extensions()
+                .get::<ConnectedAt>()
+                .is_some_and(|connected_at| connected_at.0.elapsed() >= max_connection_age);
+            if expired {
+                if let Some(connected) = connection.connection_metadata().as_ref() {
+                    connected.poison();
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// Time a connection was established, set as a response extension.
+#[derive(Debug, Clone, Copy)]
+struct ConnectedAt(Instant);
+
+/// Connector recording the number of open connections per host, as the
+/// `http_client_open_connections` counter.
+#[derive(Debug, Clone)]
+pub struct CountedConnector<C> {
+    inner: C,
+}
+
+impl<C> CountedConnector<C> {
+    pub fn new(inner: C) -> Self {
+        Self { inner }
+    }
+}
+
+impl<C> tower::Service<Uri> for CountedConnector<C>
+where
+    C: tower::Service<Uri>,
+    C::Future: Send + 'static,
+{
+    type Response = CountedConnection<C::Response>;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let host = uri.host().unwrap_or_default().to_string();
+        let connecting = self.inner.call(uri);
+        Box::pin(async move {
+            let io = connecting.await?;
+            Ok(CountedConnection::new(io, host))
+        })
+    }
+}
+
+/// Connection counted as open until dropped.
+#[derive(Debug)]
+pub struct CountedConnection<T> {
+    io: T,
+    host: String,
+    connected_at: Instant,
+}
+
+impl<T> CountedConnection<T> {
+    fn new(io: T, host: String) -> Self {
+        info!(
+            monotonic_counter.http_client_connection_count = 1,
+            counter.http_client_open_connections = 1,
+            host = %host,
+        );
+        Self {
+            io,
+            host,
+            connected_at: Instant::now(),
+        }
+    }
+}
+
+impl<T> Drop for CountedConnection<T> {
+    fn drop(&mut self) {
+        info!(
+            counter.http_client_open_connections = -1,
+            host = %self.host,
+        );
+    }
+}
+
+impl<T: Connection> Connection for CountedConnection<T> {
+    fn connected(&self) -> Connected {
+        self.io.connected().extra(ConnectedAt(self.connected_at))
+    }
+}
+
+impl<T: Read + Unpin> Read for CountedConnection<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_read(cx, buf)
+    }
+}
+
+impl<T: Write + Unpin> Write for CountedConnection<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write_vectored(cx, bufs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::extract::ConnectInfo;
+    use http_body_util::BodyExt;
+
+    use super::*;
+    use crate::{
+        clients::create_connection_pool,
+        config::{Http2Config, ServiceConfig},
+        utils::test_server::ensure_crypto_provider,
+    };
+
+    #[tokio::test]
+    async fn test_shared_pool_dropped_with_last_client() {
+        ensure_crypto_provider();
+        let service_config = ServiceConfig {
+            http2: Some(Http2Config::default()),
+            ..ServiceConfig::new("localhost".into(), 8080)
+        };
+        let pool = create_connection_pool(&service_config, Duration::from_secs(10))
+            .await
+            .unwrap();
+        pool.share("localhost:8080".into());
+        let shared = ConnectionPool::shared("localhost:8080").unwrap();
+        assert!(Arc::ptr_eq(&shared.0, &pool.0));
+
+        drop((pool, shared));
+        assert!(ConnectionPool::shared("localhost:8080").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_max_connection_age() {
+        // Responds with the client address of the connection
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::get(|ConnectInfo(addr): ConnectInfo<SocketAddr>| async move {
+                addr.to_string()
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+        });
+        ensure_crypto_provider();
+        // Requests share a single HTTP/2 connection, unless it is not reused
+        let service_config = ServiceConfig {
+            http2: Some(Http2Config {
+                prior_knowledge: true,
+                ..Default::default()
+            }),
+            ..ServiceConfig::new("localhost".into(), port)
+        };
+        let client_addr = |mut pool: ConnectionPool| async move {
+            let request = Request::get(format!("http://localhost:{port}/"))
+                .body(BoxBody::default())
+                .unwrap();
+            let response = tower::Service::call(&mut pool, request).await.unwrap();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+
+        // Connections are reused until the maximum connection age
+        let pool = create_connection_pool(&service_config, Duration::from_secs(60))
+            .await
+            .unwrap();
+        let addr = client_addr(pool.clone()).await;
+        assert_eq!(client_addr(pool.clone()).await, addr);
+
+        // Expired connections serve their current request, then are replaced
+        let expiring = ConnectionPool::new(pool.0.client.clone(), Duration::ZERO);
+        assert_eq!(client_addr(expiring.clone()).await, addr);
+        assert_ne!(client_addr(expiring).await, addr);
+    }
+}
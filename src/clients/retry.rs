@@ -0,0 +1,240 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+use std::{
+    future::Future,
+    time::{Duration, SystemTime},
+};
+
+use hyper::StatusCode;
+use tracing::{debug, info};
+
+use super::Error;
+use crate::config::RetryConfig;
+
+/// Retry policy of a client, sending each request once unless configured.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: usize,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    retriable_status_codes: Vec<StatusCode>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            jitter: false,
+            retriable_status_codes: Vec::new(),
+        }
+    }
+}
+
+impl From<Option<&RetryConfig>> for RetryPolicy {
+    fn from(config: Option<&RetryConfig>) -> Self {
+        match config {
+            Some(config) => Self {
+                max_attempts: config.max_attempts.max(1),
+                initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+                max_backoff: Duration::from_millis(config.max_backoff_ms),
+                jitter: config.jitter,
+                retriable_status_codes: config
+                    .retriable_status_codes
+                    .iter()
+                    .filter_map(|code| StatusCode::from_u16(*code).ok())
+                    .collect(),
+            },
+            None => Self::default(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns `true` if a request failing with `code` after `attempt` attempts is retried.
+    pub fn retries(&self, attempt: usize, code: StatusCode) -> bool {
+        attempt < self.max_attempts && self.retriable_status_codes.contains(&code)
+    }
+
+    /// Returns the delay before the retry following `attempt`, doubling from the initial
+    /// backoff up to the maximum backoff, randomized between zero and the backoff with jitter.
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1) as u32))
+            .min(self.max_backoff);
+        if self.jitter && !backoff.is_zero() {
+            Duration::from_millis(rand::random_range(0..=backoff.as_millis() as u64))
+        } else {
+            backoff
+        }
+    }
+
+    /// Returns the delay before the retry following `attempt`: the backoff, or the
+    /// `retry_after` hint of a rate limited backend if longer. Returns `None` if the hint
+    /// exceeds the maximum backoff, failing the request instead of holding it.
+    pub fn delay(&self, attempt: usize, retry_after: Option<Duration>) -> Option<Duration> {
+        let backoff = self.backoff(attempt);
+        match retry_after {
+            Some(retry_after) if retry_after > self.max_backoff => None,
+            Some(retry_after) => Some(backoff.max(retry_after)),
+            None => Some(backoff),
+        }
+    }
+
+    /// Waits for `delay` following a failed `attempt` with `code`.
+    pub async fn wait(&self, attempt: usize, code: StatusCode, delay: Duration) {
+        debug!(attempt, %code, ?delay, "request failed, retrying");
+        info!(
+            monotonic_counter.client_request_retry_count = 1,
+            code = code.as_u16()
+        );
+        tokio::time::sleep(delay).await;
+    }
+
+    /// Runs `request` with its 1-based attempt number until it succeeds, fails with a
+    /// non-retriable error or attempts are exhausted. Shut down clients are not retried,
+    /// nor rate limited requests asking to wait longer than the maximum backoff.
+    pub async fn run<T, F, Fut>(&self, mut request: F) -> Result<T, Error>
+    where
+        F: FnMut(usize) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let error = match request(attempt).await {
+                Err(error)
+                    if !matches!(error, Error::ClientShutdown)
+                        && self.retries(attempt, error.status_code()) =>
+                {
+                    error
+                }
+                result => return result,
+            };
+            let Some(delay) = self.delay(attempt, error.retry_after()) else {
+                return Err(error);
+            };
+            self.wait(attempt, error.status_code(), delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Parses a `Retry-After` value, in seconds or as an HTTP date, into the delay from now.
+pub fn parse_retry_after(value: &[u8]) -> Option<Duration> {
+    let value = std::str::from_utf8(value).ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_retry_policy() {
+        let policy = RetryPolicy::from(Some(&RetryConfig {
+            max_attempts: 3,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 3,
+            jitter: false,
+            retriable_status_codes: vec![503],
+        }));
+        assert_eq!(policy.backoff(1), Duration::from_millis(1));
+        assert_eq!(policy.backoff(2), Duration::from_millis(2));
+        assert_eq!(policy.backoff(3), Duration::from_millis(3));
+
+        let unavailable = Error::Grpc {
+            code: StatusCode::SERVICE_UNAVAILABLE,
+            message: "unavailable".into(),
+        };
+        let mut attempts = Vec::new();
+        let result: Result<(), Error> = policy
+            .run(|attempt| {
+                attempts.push(attempt);
+                let error = unavailable.clone();
+                async move { Err(error) }
+            })
+            .await;
+        assert_eq!(result, Err(unavailable.clone()));
+        assert_eq!(attempts, [1, 2, 3]);
+
+        // Succeeding attempts and other errors are not retried
+        let mut attempts = 0;
+        let result = policy
+            .run(|attempt| {
+                attempts += 1;
+                let error = unavailable.clone();
+                async move { if attempt < 2 { Err(error) } else { Ok(attempt) } }
+            })
+            .await;
+        assert_eq!(result, Ok(2));
+        assert_eq!(attempts, 2);
+        let mut attempts = 0;
+        let result: Result<(), Error> = policy
+            .run(|_| {
+                attempts += 1;
+                async { Err(Error::ClientShutdown) }
+            })
+            .await;
+        assert_eq!(result, Err(Error::ClientShutdown));
+        assert_eq!(attempts, 1);
+
+        // Rate limited requests wait for the hint of the backend, unless it exceeds the
+        // maximum backoff
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_millis(2))),
+            Some(Duration::from_millis(2))
+        );
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(60))), None);
+        let policy = RetryPolicy::from(Some(&RetryConfig {
+            retriable_status_codes: vec![429],
+            ..RetryConfig::default()
+        }));
+        let rate_limited = Error::RateLimited {
+            message: "slow down".into(),
+            retry_at: Some(std::time::Instant::now() + Duration::from_secs(60)),
+        };
+        let mut attempts = 0;
+        let result: Result<(), Error> = policy
+            .run(|_| {
+                attempts += 1;
+                let error = rate_limited.clone();
+                async move { Err(error) }
+            })
+            .await;
+        assert_eq!(result, Err(rate_limited));
+        assert_eq!(attempts, 1);
+        assert_eq!(parse_retry_after(b"3"), Some(Duration::from_secs(3)));
+        assert_eq!(
+            parse_retry_after(b"Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after(b"soon"), None);
+
+        // Requests are sent once by default
+        assert!(!RetryPolicy::default().retries(1, StatusCode::SERVICE_UNAVAILABLE));
+    }
+}
@@ -0,0 +1,270 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use futures::future::join_all;
+use hyper::{HeaderMap, Method, body::Bytes};
+use tracing::{Span, info, warn};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use url::Url;
+
+use super::{Error, HttpClient, breaker::CircuitState, http::Response};
+use crate::health::{HealthCheckResult, HealthStatus};
+
+/// Name of the backend configured as the service itself.
+pub const DEFAULT_BACKEND_NAME: &str = "default";
+/// Weight of the latest sample in a backend's rolling latency.
+const LATENCY_SMOOTHING: f64 = 0.3;
+/// Duration a backend is deprioritized for after a failure.
+const FAILURE_COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+struct BackendStats {
+    /// Rolling latency in milliseconds, unset until the first successful request
+    latency_ms: Option<f64>,
+    /// Time until which the backend is considered unhealthy
+    unhealthy_until: Option<Instant>,
+}
+
+/// An equivalent deployment of a service.
+struct Backend {
+    name: String,
+    client: HttpClient,
+    stats: Mutex<BackendStats>,
+}
+
+impl Backend {
+    fn record_success(&self, latency: Duration) {
+        let latency = latency.as_secs_f64() * 1000.0;
+        let mut stats = self.stats.lock().unwrap();
+        stats.latency_ms = Some(match stats.latency_ms {
+            Some(rolling) => rolling + LATENCY_SMOOTHING * (latency - rolling),
+            None => latency,
+        });
+        stats.unhealthy_until = None;
+    }
+
+    fn record_failure(&self) {
+        self.stats.lock().unwrap().unhealthy_until = Some(Instant::now() + FAILURE_COOLDOWN);
+    }
+}
+
+/// Routes requests between equivalent backends of a service.
+///
+/// Requests go to the healthy backend with the lowest rolling latency, failing over
+/// to the next backend on request errors and server error responses. Backends without
+/// latency samples are tried first so all backends are measured.
+pub struct LatencyRouter {
+    backends: Vec<Backend>,
+}
+
+impl LatencyRouter {
+    /// Creates a router from named backend clients, which must not be empty.
+    pub fn new(backends: Vec<(String, HttpClient)>) -> Self {
+        assert!(!backends.is_empty(), "router requires at least one backend");
+        Self {
+            backends: backends
+                .into_iter()
+                .map(|(name, client)| Backend {
+                    name,
+                    client,
+                    stats: Mutex::new(BackendStats::default()),
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns backends in routing order, healthy backends by rolling latency first.
+    fn ranked(&self) -> Vec<&Backend> {
+        let now = Instant::now();
+        let mut ranked = self
+            .backends
+            .iter()
+            .map(|backend| {
+                let stats = backend.stats.lock().unwrap();
+                let unhealthy = stats.unhealthy_until.is_some_and(|until| until > now);
+                (backend, unhealthy, stats.latency_ms.unwrap_or_default())
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by(|(_, a_unhealthy, a_latency), (_, b_unhealthy, b_latency)| {
+            a_unhealthy
+                .cmp(b_unhealthy)
+                .then(a_latency.total_cmp(b_latency))
+        });
+        ranked.into_iter().map(|(backend, ..)| backend).collect()
+    }
+
+    /// Sends a request to the best backend, failing over to the others.
+    pub async fn send(
+        &self,
+        url: Url,
+        method: Method,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Result<Response, Error> {
+        let mut backends = self.ranked().into_iter().enumerate().peekable();
+        while let Some((i, backend)) = backends.next() {
+            let start = Instant::now();
+            let result = backend
+                .client
+                .guarded(backend.client.send_bytes(
+                    backend.client.rebase(&url),
+                    method.clone(),
+                    headers.clone(),
+                    body.clone(),
+                    i + 1,
+                ))
+                .await;
+            let failed = match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(_) => true,
+            };
+            if failed {
+                backend.record_failure();
+            } else {
+                backend.record_success(start.elapsed());
+            }
+            if !failed || backends.peek().is_none() {
+                Span::current().set_attribute("backend", backend.name.clone());
+                info!(backend = %backend.name, failed, "request served by backend");
+                return result;
+            }
+            warn!(backend = %backend.name, "backend request failed, failing over");
+        }
+        unreachable!("router has at least one backend")
+    }
+
+    /// Performs health checks for all backends, updating their health. Backends with an
+    /// open circuit are unhealthy. Returns the first healthy result, or the result of the
+    /// first backend.
+    pub async fn health(&self) -> HealthCheckResult {
+        let results = join_all(self.backends.iter().map(|backend| async {
+            let mut result = backend.client.health().await;
+            let circuit_state = backend
+                .client
+                .circuit_breaker()
+                .map(|breaker| breaker.state());
+            if circuit_state == Some(CircuitState::Open) {
+                result.status = HealthStatus::Unhealthy;
+                result.reason = Some("circuit breaker open".into());
+            }
+            result
+        }))
+        .await;
+        for (backend, result) in self.backends.iter().zip(&results) {
+            if result.status == HealthStatus::Healthy {
+                backend.stats.lock().unwrap().unhealthy_until = None;
+            } else {
+                backend.record_failure();
+            }
+        }
+        results
+            .iter()
+            .find(|result| result.status == HealthStatus::Healthy)
+            .unwrap_or(&results[0])
+            .clone()
+    }
+
+    /// Closes all backend clients.
+    pub fn shutdown(&self) {
+        for backend in &self.backends {
+            backend.client.shutdown();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, http::StatusCode, routing::post};
+
+    use super::*;
+    use crate::{clients::create_http_client, config::ServiceConfig, utils::test_server::serve};
+
+    async fn backend(status: StatusCode) -> HttpClient {
+        let app = Router::new().route("/detect", post(move || async move { status }));
+        let port = serve(app).await;
+        create_http_client(port, &ServiceConfig::new("localhost".into(), port))
+            .await
+            .unwrap()
+    }
+
+    fn names(router: &LatencyRouter) -> Vec<&str> {
+        router
+            .ranked()
+            .into_iter()
+            .map(|backend| backend.name.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_latency_router_ranking() {
+        let router = LatencyRouter::new(vec![
+            ("slow".into(), backend(StatusCode::OK).await),
+            ("fast".into(), backend(StatusCode::OK).await),
+            ("failing".into(), backend(StatusCode::OK).await),
+            ("unmeasured".into(), backend(StatusCode::OK).await),
+        ]);
+        let [slow, fast, failing, _] = &router.backends[..] else {
+            unreachable!()
+        };
+        slow.record_success(Duration::from_millis(50));
+        fast.record_success(Duration::from_millis(5));
+        failing.record_success(Duration::from_millis(1));
+        failing.record_failure();
+
+        // Unmeasured backends first, then healthy backends by latency
+        assert_eq!(names(&router), ["unmeasured", "fast", "slow", "failing"]);
+
+        // Rolling latency moves towards the latest sample
+        fast.record_success(Duration::from_millis(205));
+        let latency_ms = fast.stats.lock().unwrap().latency_ms.unwrap();
+        assert!((latency_ms - 65.0).abs() < 1e-6);
+        assert_eq!(names(&router), ["unmeasured", "slow", "fast", "failing"]);
+
+        // A success clears the failure
+        failing.record_success(Duration::from_millis(1));
+        assert_eq!(names(&router), ["unmeasured", "failing", "slow", "fast"]);
+    }
+
+    #[tokio::test]
+    async fn test_latency_router_failover() {
+        let failing = backend(StatusCode::SERVICE_UNAVAILABLE).await;
+        let ok = backend(StatusCode::OK).await;
+        let url = failing.endpoint("/detect");
+        let router = LatencyRouter::new(vec![("failing".into(), failing), ("ok".into(), ok)]);
+        let send = || router.send(url.clone(), Method::POST, HeaderMap::new(), Bytes::new());
+
+        // The failing backend fails over and is deprioritized
+        assert_eq!(send().await.unwrap().status(), StatusCode::OK);
+        assert_eq!(names(&router), ["ok", "failing"]);
+        assert_eq!(send().await.unwrap().status(), StatusCode::OK);
+
+        // The last backend's response is returned once all backends fail
+        let router = LatencyRouter::new(vec![(
+            "failing".into(),
+            backend(StatusCode::SERVICE_UNAVAILABLE).await,
+        )]);
+        let response = router
+            .send(url.clone(), Method::POST, HeaderMap::new(), Bytes::new())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -15,6 +15,8 @@
 
 */
 
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use axum::http::HeaderMap;
 use futures::{StreamExt, TryStreamExt};
@@ -23,8 +25,13 @@ use tonic::Code;
 use tracing::Span;
 
 use super::{
-    BoxStream, Client, Error, create_grpc_client, errors::grpc_to_http_code,
-    grpc_request_with_headers, otel_grpc::OtelGrpcService,
+    BoxStream, Client, Closeable, Error, HeaderTemplates, RequestLimiter,
+    breaker::{self, CircuitBreaker},
+    create_grpc_client,
+    errors::grpc_to_http_code,
+    grpc_request_with_headers,
+    otel_grpc::OtelGrpcService,
+    retry::RetryPolicy,
 };
 use crate::{
     config::ServiceConfig,
@@ -37,17 +44,35 @@ use crate::{
     utils::trace::trace_context_from_grpc_response,
 };
 
-const DEFAULT_PORT: u16 = 8033;
+pub const DEFAULT_PORT: u16 = 8033;
 
 #[derive(Clone)]
 pub struct TgisClient {
-    client: GenerationServiceClient<OtelGrpcService<LoadBalancedChannel>>,
+    client: Closeable<GenerationServiceClient<OtelGrpcService<LoadBalancedChannel>>>,
+    headers: HeaderTemplates,
+    retry: RetryPolicy,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    request_limiter: RequestLimiter,
 }
 
 impl TgisClient {
     pub async fn new(config: &ServiceConfig) -> Self {
-        let client = create_grpc_client(DEFAULT_PORT, config, GenerationServiceClient::new).await;
-        Self { client }
+        let client = Closeable::new(
+            create_grpc_client(DEFAULT_PORT, config, GenerationServiceClient::new).await,
+        );
+        Self {
+            client,
+            headers: config.headers.clone(),
+            retry: RetryPolicy::from(config.retry.as_ref()),
+            circuit_breaker: CircuitBreaker::from_config(config),
+            request_limiter: RequestLimiter::default(),
+        }
+    }
+
+    /// Waits for a permit of `request_limiter` before sending requests.
+    pub fn with_request_limiter(mut self, request_limiter: RequestLimiter) -> Self {
+        self.request_limiter = request_limiter;
+        self
     }
 
     pub async fn generate(
@@ -55,9 +80,21 @@ impl TgisClient {
         request: BatchedGenerationRequest,
         headers: HeaderMap,
     ) -> Result<BatchedGenerationResponse, Error> {
-        let request = grpc_request_with_headers(request, headers);
-        let mut client = self.client.clone();
-        let response = client.generate(request).await?;
+        let headers = self.headers.apply(headers);
+        let response = breaker::call(
+            self.circuit_breaker.as_deref(),
+            self.retry.run(|_| {
+                let request = grpc_request_with_headers(request.clone(), headers.clone());
+                let client = self.client.get();
+                let request_limiter = &self.request_limiter;
+                async move {
+                    let mut client = client?;
+                    let _permit = request_limiter.acquire().await;
+                    Ok(client.generate(request).await?)
+                }
+            }),
+        )
+        .await?;
         let span = Span::current();
         trace_context_from_grpc_response(&span, &response);
         Ok(response.into_inner())
@@ -68,9 +105,17 @@ impl TgisClient {
         request: SingleGenerationRequest,
         headers: HeaderMap,
     ) -> Result<BoxStream<Result<GenerationResponse, Error>>, Error> {
-        let request = grpc_request_with_headers(request, headers);
-        let mut client = self.client.clone();
-        let response = client.generate_stream(request).await?;
+        let headers = self.headers.apply(headers);
+        let response = breaker::call(
+            self.circuit_breaker.as_deref(),
+            self.retry.run(|_| {
+                let request = grpc_request_with_headers(request.clone(), headers.clone());
+                let client = self.client.get();
+                // Streams are long-lived, so they are not limited by `request_limiter`
+                async move { Ok(client?.generate_stream(request).await?) }
+            }),
+        )
+        .await?;
         let span = Span::current();
         trace_context_from_grpc_response(&span, &response);
         Ok(response.into_inner().map_err(Into::into).boxed())
@@ -81,18 +126,42 @@ impl TgisClient {
         request: BatchedTokenizeRequest,
         headers: HeaderMap,
     ) -> Result<BatchedTokenizeResponse, Error> {
-        let mut client = self.client.clone();
-        let request = grpc_request_with_headers(request, headers);
-        let response = client.tokenize(request).await?;
+        let headers = self.headers.apply(headers);
+        let response = breaker::call(
+            self.circuit_breaker.as_deref(),
+            self.retry.run(|_| {
+                let request = grpc_request_with_headers(request.clone(), headers.clone());
+                let client = self.client.get();
+                let request_limiter = &self.request_limiter;
+                async move {
+                    let mut client = client?;
+                    let _permit = request_limiter.acquire().await;
+                    Ok(client.tokenize(request).await?)
+                }
+            }),
+        )
+        .await?;
         let span = Span::current();
         trace_context_from_grpc_response(&span, &response);
         Ok(response.into_inner())
     }
 
     pub async fn model_info(&self, request: ModelInfoRequest) -> Result<ModelInfoResponse, Error> {
-        let request = grpc_request_with_headers(request, HeaderMap::new());
-        let mut client = self.client.clone();
-        let response = client.model_info(request).await?;
+        let headers = self.headers.apply(HeaderMap::new());
+        let response = breaker::call(
+            self.circuit_breaker.as_deref(),
+            self.retry.run(|_| {
+                let request = grpc_request_with_headers(request.clone(), headers.clone());
+                let client = self.client.get();
+                let request_limiter = &self.request_limiter;
+                async move {
+                    let mut client = client?;
+                    let _permit = request_limiter.acquire().await;
+                    Ok(client.model_info(request).await?)
+                }
+            }),
+        )
+        .await?;
         let span = Span::current();
         trace_context_from_grpc_response(&span, &response);
         Ok(response.into_inner())
@@ -105,12 +174,22 @@ impl Client for TgisClient {
         "tgis"
     }
 
+    fn circuit_breaker(&self) -> Option<&CircuitBreaker> {
+        self.circuit_breaker.as_deref()
+    }
+
     async fn health(&self) -> HealthCheckResult {
-        let mut client = self.client.clone();
+        let mut client = match self.client.get() {
+            Ok(client) => client,
+            Err(error) => return error.into(),
+        };
         let response = client
-            .model_info(ModelInfoRequest {
-                model_id: "".into(),
-            })
+            .model_info(grpc_request_with_headers(
+                ModelInfoRequest {
+                    model_id: "".into(),
+                },
+                self.headers.apply(HeaderMap::new()),
+            ))
             .await;
         let code = match response {
             Ok(_) => Code::Ok,
@@ -130,4 +209,8 @@ impl Client for TgisClient {
             reason: None,
         }
     }
+
+    async fn shutdown(&self) {
+        self.client.close();
+    }
 }
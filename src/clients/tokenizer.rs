@@ -0,0 +1,133 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Local Hugging Face tokenizers, to tokenize text without calling a service.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use hyper::StatusCode;
+use tokenizers::Tokenizer;
+use tracing::debug;
+
+use super::Error;
+
+/// Loads a tokenizer from a Hugging Face tokenizer file.
+pub fn load_tokenizer(path: &Path) -> Result<Tokenizer, Error> {
+    Tokenizer::from_file(path).map_err(|error| Error::Http {
+        code: StatusCode::INTERNAL_SERVER_ERROR,
+        message: format!("failed to load tokenizer `{}`: {error}", path.display()),
+    })
+}
+
+/// Registry of local tokenizers by model ID.
+#[derive(Clone, Default)]
+pub struct LocalTokenizers(HashMap<String, Arc<Tokenizer>>);
+
+impl LocalTokenizers {
+    /// Loads tokenizers from files by model ID.
+    pub fn from_files(paths: &HashMap<String, PathBuf>) -> Result<Self, Error> {
+        paths
+            .iter()
+            .map(|(model_id, path)| Ok((model_id.clone(), Arc::new(load_tokenizer(path)?))))
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    /// Returns the token count and tokens of `text`, or `None` if there is no
+    /// local tokenizer for the model.
+    pub fn tokenize(
+        &self,
+        model_id: &str,
+        text: &str,
+    ) -> Option<Result<(u32, Vec<String>), Error>> {
+        let tokenizer = self.0.get(model_id)?;
+        debug!(%model_id, "tokenizing with local tokenizer");
+        let result = tokenizer
+            .encode(text, false)
+            .map(|encoding| {
+                let tokens = encoding.get_tokens().to_vec();
+                (tokens.len() as u32, tokens)
+            })
+            .map_err(|error| Error::Http {
+                code: StatusCode::INTERNAL_SERVER_ERROR,
+                message: format!("model `{model_id}` local tokenizer failed: {error}"),
+            });
+        Some(result)
+    }
+
+    /// Returns the char offsets (start, end) of tokens of `text`, or `None` if there
+    /// is no local tokenizer for the model.
+    pub fn offsets(
+        &self,
+        model_id: &str,
+        text: &str,
+    ) -> Option<Result<Vec<(usize, usize)>, Error>> {
+        let tokenizer = self.0.get(model_id)?;
+        debug!(%model_id, "computing token offsets with local tokenizer");
+        let result = tokenizer
+            .encode_char_offsets(text, false)
+            .map(|encoding| encoding.get_offsets().to_vec())
+            .map_err(|error| Error::Http {
+                code: StatusCode::INTERNAL_SERVER_ERROR,
+                message: format!("model `{model_id}` local tokenizer failed: {error}"),
+            });
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const TOKENIZER: &str = r#"{
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": null,
+        "pre_tokenizer": { "type": "Whitespace" },
+        "post_processor": null,
+        "decoder": null,
+        "model": { "type": "WordLevel", "vocab": { "[UNK]": 0, "hello": 1 }, "unk_token": "[UNK]" }
+    }"#;
+
+    #[test]
+    fn test_tokenize() -> Result<(), Error> {
+        let path =
+            std::env::temp_dir().join(format!("{}-tokenizer.json", uuid::Uuid::new_v4().simple()));
+        fs::write(&path, TOKENIZER).unwrap();
+        let tokenizers =
+            LocalTokenizers::from_files(&HashMap::from([("granite".into(), path.clone())]))?;
+        fs::remove_file(&path).unwrap();
+
+        let (token_count, tokens) = tokenizers
+            .tokenize("granite", "hello there, world")
+            .unwrap()?;
+        assert_eq!(token_count, 4);
+        assert_eq!(tokens, ["hello", "[UNK]", "[UNK]", "[UNK]"]);
+        assert!(tokenizers.tokenize("llama", "hello").is_none());
+
+        let offsets = tokenizers.offsets("granite", "héllo hello").unwrap()?;
+        assert_eq!(offsets, [(0, 5), (6, 11)]);
+        Ok(())
+    }
+}
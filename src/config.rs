@@ -17,17 +17,39 @@
 
 use std::{
     collections::{HashMap, HashSet},
+    net::IpAddr,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
-use serde::Deserialize;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use http::{HeaderValue, Method, StatusCode};
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info, warn};
 
-use crate::clients::{chunker::DEFAULT_CHUNKER_ID, is_valid_hostname};
+use crate::{
+    clients::{
+        HeaderTemplates, chunker::DEFAULT_CHUNKER_ID, is_valid_hostname, openai,
+        routing::DEFAULT_BACKEND_NAME,
+    },
+    utils::{secrets::SecretSource, tls},
+};
 
 /// Default allowed headers to passthrough to clients.
 const DEFAULT_ALLOWED_HEADERS: &[&str] = &[];
 
+/// Default number of days before expiry at which loaded TLS certificates are warned about.
+const fn default_tls_expiry_warning_days() -> u64 {
+    30
+}
+/// Default timeout in seconds of requests to services other than detectors.
+const fn default_request_timeout() -> u64 {
+    600
+}
+/// Default timeout in seconds of requests to detectors.
+const fn default_detector_request_timeout() -> u64 {
+    600
+}
 /// Default number of detector requests to send concurrently for a task.
 const fn default_detector_concurrent_requests() -> usize {
     5
@@ -36,6 +58,107 @@ const fn default_detector_concurrent_requests() -> usize {
 const fn default_chunker_concurrent_requests() -> usize {
     5
 }
+/// Default number of generated messages buffered for paced generation streams.
+const fn default_pacing_buffer_size() -> usize {
+    16
+}
+/// Default number of generation jobs run concurrently.
+const fn default_jobs_max_concurrent() -> usize {
+    4
+}
+/// Default number of generation jobs waiting to run.
+const fn default_jobs_max_queued() -> usize {
+    100
+}
+/// Default time in seconds results of generation jobs are retained for.
+const fn default_jobs_result_ttl() -> u64 {
+    3600
+}
+/// Default time in seconds cached responses are retained for.
+const fn default_response_cache_ttl() -> u64 {
+    300
+}
+/// Default number of cached responses.
+const fn default_response_cache_max_entries() -> usize {
+    1000
+}
+/// Default time in seconds streams remain resumable for once completed or idle.
+const fn default_stream_resumption_window() -> u64 {
+    60
+}
+/// Default number of streamed messages between resumption checkpoints.
+const fn default_stream_resumption_checkpoint_interval() -> usize {
+    10
+}
+/// Default maximum number of buffered resumable streams.
+const fn default_stream_resumption_max_streams() -> usize {
+    1000
+}
+/// Default maximum number of buffered messages of a resumable stream.
+const fn default_stream_resumption_max_messages() -> usize {
+    10000
+}
+/// Default state of feature flags.
+const fn default_feature_enabled() -> bool {
+    true
+}
+/// Default number of retries of failed alert deliveries.
+const fn default_alert_max_retries() -> usize {
+    3
+}
+/// Default maximum size in bytes of the spool of each sink.
+const fn default_spool_max_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+/// Default interval in seconds at which delivery of spooled records is retried.
+const fn default_spool_retry_interval() -> u64 {
+    5
+}
+/// Default maximum number of attempts of retried requests.
+const fn default_retry_max_attempts() -> usize {
+    3
+}
+/// Default delay in milliseconds before the first retry of a request.
+const fn default_retry_initial_backoff_ms() -> u64 {
+    100
+}
+/// Default maximum delay in milliseconds between retries of a request.
+const fn default_retry_max_backoff_ms() -> u64 {
+    2000
+}
+/// Default of whether retry backoffs are randomized.
+const fn default_retry_jitter() -> bool {
+    true
+}
+/// Default status codes of retried responses and errors.
+fn default_retriable_status_codes() -> Vec<u16> {
+    vec![502, 503, 504]
+}
+/// Default number of consecutive failures opening a circuit breaker.
+const fn default_circuit_breaker_failure_threshold() -> usize {
+    5
+}
+/// Default time in seconds an open circuit breaker fails requests for.
+const fn default_circuit_breaker_cool_down() -> u64 {
+    30
+}
+/// Default header identifying the tenant of requests for payload capture.
+fn default_capture_tenant_header() -> String {
+    "x-tenant-id".into()
+}
+/// Default interval in seconds at which the JWKS of a JWT issuer is re-fetched.
+const fn default_jwks_refresh_interval() -> u64 {
+    300
+}
+/// Default claim naming the consumer of JWT-authenticated requests.
+fn default_jwt_consumer_claim() -> String {
+    "azp".into()
+}
+/// Default signing algorithms of JWTs signed with keys without an `alg`.
+fn default_jwt_algorithms() -> Vec<jsonwebtoken::Algorithm> {
+    use jsonwebtoken::Algorithm::*;
+    vec![RS256, ES256, EdDSA]
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -60,6 +183,62 @@ pub enum Error {
     InvalidGenerationProvider(String),
     #[error("invalid hostname: {0}")]
     InvalidHostname(String),
+    #[error("`host_header` is not supported for gRPC services: {0}")]
+    UnsupportedHostHeader(String),
+    #[error("`max_concurrent_requests` must be greater than 0")]
+    InvalidMaxConcurrentRequests,
+    #[error("`{0}` must be greater than 0")]
+    InvalidConcurrentRequests(&'static str),
+    #[error("invalid endpoint path: {0}")]
+    InvalidEndpointPath(String),
+    #[error("invalid detections filter: {0}")]
+    InvalidDetectionsFilter(String),
+    #[error("invalid backend: {0}")]
+    InvalidBackend(String),
+    #[error("invalid canary: {0}")]
+    InvalidCanary(String),
+    #[error("invalid chunk size: {0}")]
+    InvalidChunkSize(String),
+    #[error("invalid batch size: {0}")]
+    InvalidBatchSize(String),
+    #[error("invalid score normalization: {0}")]
+    InvalidScoreNormalization(String),
+    #[error("invalid response format: {0}")]
+    InvalidResponseFormat(String),
+    #[error("invalid tokenizer: {0}")]
+    InvalidTokenizer(String),
+    #[error("invalid stream ordering: `max_buffered_chunks` must be greater than 0")]
+    InvalidStreamOrdering,
+    #[error("`downstream_log_sample_rate` must be greater than 0 and at most 1")]
+    InvalidDownstreamLogSampleRate,
+    #[error("invalid slo config: {0}")]
+    InvalidSlo(String),
+    #[error("invalid defaults config: {0}")]
+    InvalidDefaults(String),
+    #[error("invalid jobs config: {0}")]
+    InvalidJobs(String),
+    #[error("invalid stream resumption config: {0}")]
+    InvalidStreamResumption(String),
+    #[error("invalid response cache config: {0}")]
+    InvalidResponseCache(String),
+    #[error("invalid alerts config: {0}")]
+    InvalidAlerts(String),
+    #[error("invalid auth config: {0}")]
+    InvalidAuth(String),
+    #[error("invalid capture config: {0}")]
+    InvalidCapture(String),
+    #[error("invalid spool config: {0}")]
+    InvalidSpool(String),
+    #[error("invalid TLS config: {0}")]
+    InvalidTls(String),
+    #[error("invalid retry config: {0}")]
+    InvalidRetry(String),
+    #[error("invalid circuit breaker config: {0}")]
+    InvalidCircuitBreaker(String),
+    #[error("invalid detector group: {0}")]
+    InvalidDetectorGroup(String),
+    #[error("egress to `{0}` is not allowed by the egress policy")]
+    EgressNotAllowed(String),
 }
 
 /// Configuration for service needed for
@@ -74,8 +253,32 @@ pub struct ServiceConfig {
     pub request_timeout: Option<u64>,
     /// TLS provider info
     pub tls: Option<Tls>,
-    /// gRPC probe interval in seconds
-    pub grpc_dns_probe_interval: Option<u64>,
+    /// Interval in seconds at which the hostname is re-resolved, defaults to 10.
+    /// gRPC clients balance requests across the resolved addresses. HTTP clients
+    /// spread new connections across them and close connections idle for this long
+    #[serde(alias = "grpc_dns_probe_interval")]
+    pub dns_probe_interval: Option<u64>,
+    /// Headers added to every request, static or templated from propagated headers
+    #[serde(default)]
+    pub headers: HeaderTemplates,
+    /// HTTP health check overrides
+    #[serde(default)]
+    pub health_check: HealthCheckConfig,
+    /// TLS server name (SNI) sent instead of `hostname`, e.g. for services behind a
+    /// shared ingress. Also used to verify the server certificate
+    pub sni_hostname: Option<String>,
+    /// `Host` header sent instead of `hostname`, for HTTP services. Not supported for
+    /// gRPC services
+    pub host_header: Option<String>,
+    /// HTTP/2 connection settings, for HTTP services. If set, requests of all clients
+    /// of the endpoint with the same connection settings are multiplexed over shared
+    /// connections
+    pub http2: Option<Http2Config>,
+    /// Retries of failed requests, with exponential backoff. Not retried if not set
+    pub retry: Option<RetryConfig>,
+    /// Circuit breaker failing requests without sending them after consecutive
+    /// failures. Disabled if not set
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
 }
 
 impl ServiceConfig {
@@ -85,11 +288,144 @@ impl ServiceConfig {
             port: Some(port),
             request_timeout: None,
             tls: None,
-            grpc_dns_probe_interval: None,
+            dns_probe_interval: None,
+            headers: HeaderTemplates::default(),
+            health_check: HealthCheckConfig::default(),
+            sni_hostname: None,
+            host_header: None,
+            http2: None,
+            retry: None,
+            circuit_breaker: None,
+        }
+    }
+
+    /// Returns `true` if `hostname` and its overrides are valid.
+    pub fn has_valid_hostnames(&self) -> bool {
+        is_valid_hostname(&self.hostname)
+            && self.sni_hostname.as_deref().is_none_or(is_valid_hostname)
+            && self
+                .host_header
+                .as_deref()
+                .is_none_or(|host| HeaderValue::from_str(host).is_ok())
+    }
+}
+
+/// HTTP/2 connection settings of an HTTP service.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Http2Config {
+    /// Use HTTP/2 without negotiation, required for HTTP/2 without TLS (h2c)
+    #[serde(default)]
+    pub prior_knowledge: bool,
+    /// Maximum concurrent streams per connection until the server advertises its own
+    /// limit, defaults to 100
+    pub max_concurrent_streams: Option<usize>,
+    /// Interval in seconds of keep-alive pings on idle connections, disabled if unset
+    pub keep_alive_interval: Option<u64>,
+}
+
+/// Retries of failed requests to a service, with exponential backoff.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Maximum number of attempts of a request, including the first, defaults to 3
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: usize,
+    /// Delay in milliseconds before the first retry, doubled for each further retry,
+    /// defaults to 100
+    #[serde(default = "default_retry_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// Maximum delay in milliseconds between retries, defaults to 2000. Rate limited
+    /// requests asking to wait longer are not retried
+    #[serde(default = "default_retry_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Randomize each delay between zero and the backoff, spreading retries of
+    /// concurrent requests, defaults to true
+    #[serde(default = "default_retry_jitter")]
+    pub jitter: bool,
+    /// Status codes of retried responses and errors, defaults to 502, 503 and 504.
+    /// gRPC status codes are matched by their HTTP equivalent, e.g. `UNAVAILABLE` as 503
+    #[serde(default = "default_retriable_status_codes")]
+    pub retriable_status_codes: Vec<u16>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_retry_max_attempts(),
+            initial_backoff_ms: default_retry_initial_backoff_ms(),
+            max_backoff_ms: default_retry_max_backoff_ms(),
+            jitter: default_retry_jitter(),
+            retriable_status_codes: default_retriable_status_codes(),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Validates attempts are non-zero, backoffs are ordered and status codes are errors.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_attempts == 0 {
+            return Err("`max_attempts` must be greater than 0".into());
+        }
+        if self.initial_backoff_ms > self.max_backoff_ms {
+            return Err("`initial_backoff_ms` must be at most `max_backoff_ms`".into());
+        }
+        for code in &self.retriable_status_codes {
+            if !StatusCode::from_u16(*code)
+                .is_ok_and(|code| code.is_client_error() || code.is_server_error())
+            {
+                return Err(format!("`{code}` is not an error status code"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Circuit breaker of a service.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CircuitBreakerConfig {
+    /// Number of consecutive failed requests opening the circuit, defaults to 5.
+    /// Server errors and timeouts are failures
+    #[serde(default = "default_circuit_breaker_failure_threshold")]
+    pub failure_threshold: usize,
+    /// Time in seconds requests fail without being sent once the circuit opens,
+    /// defaults to 30. A trial request is then sent, closing the circuit if it succeeds
+    #[serde(default = "default_circuit_breaker_cool_down")]
+    pub cool_down: u64,
+}
+
+impl CircuitBreakerConfig {
+    /// Validates the failure threshold and cool-down period are non-zero.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.failure_threshold == 0 {
+            return Err("`failure_threshold` must be greater than 0".into());
+        }
+        if self.cool_down == 0 {
+            return Err("`cool_down` must be greater than 0".into());
         }
+        Ok(())
     }
 }
 
+/// HTTP health check configuration for a service.
+/// Not applicable to gRPC services, which use the gRPC health checking protocol.
+#[derive(Default, Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HealthCheckConfig {
+    /// Health endpoint path, defaults to `/health`
+    pub path: Option<String>,
+    /// Request method, defaults to `GET`
+    #[serde(default, with = "http_serde::option::method")]
+    pub method: Option<Method>,
+    /// Response status code indicating the service is healthy.
+    /// If unset, `200` is healthy, `5xx` unhealthy and other codes unknown.
+    #[serde(default, with = "http_serde::option::status_code")]
+    pub expected_status: Option<StatusCode>,
+    /// Substring the response body must contain for the service to be healthy
+    pub expected_body: Option<String>,
+}
+
 /// TLS provider
 #[derive(Clone, Debug, Deserialize)]
 #[serde(untagged)]
@@ -101,10 +437,24 @@ pub enum Tls {
 /// Client TLS configuration
 #[derive(Default, Clone, Debug, Deserialize)]
 pub struct TlsConfig {
-    pub cert_path: Option<PathBuf>,
-    pub key_path: Option<PathBuf>,
-    pub client_ca_cert_path: Option<PathBuf>,
+    /// Client certificate, a path or secret source
+    #[serde(alias = "cert_path")]
+    pub cert: Option<SecretSource>,
+    /// Client private key, a path or secret source
+    #[serde(alias = "key_path")]
+    pub key: Option<SecretSource>,
+    /// Passphrase of an encrypted PKCS#8 private key, a path or secret source
+    pub key_passphrase: Option<SecretSource>,
+    /// CA certificate verifying the server, a path or secret source
+    #[serde(alias = "client_ca_cert_path")]
+    pub client_ca_cert: Option<SecretSource>,
     pub insecure: Option<bool>,
+    /// Trust only `client_ca_cert`, never the native or webpki roots, for both gRPC and
+    /// HTTP clients. Defaults to false
+    pub ca_only: Option<bool>,
+    /// Interval in seconds at which the certificate and key are re-loaded. Rotated client
+    /// certificates are used by new connections of HTTP clients. Not re-loaded if not set
+    pub refresh_interval: Option<u64>,
 }
 
 /// Generation service provider
@@ -115,6 +465,9 @@ pub enum GenerationProvider {
     Tgis,
     #[serde(rename = "nlp")]
     Nlp,
+    /// OpenAI-compatible chat completions server, e.g. vLLM or llama.cpp server
+    #[serde(rename = "openai")]
+    OpenAi,
 }
 
 /// Generation service configuration
@@ -124,6 +477,24 @@ pub struct GenerationConfig {
     pub provider: GenerationProvider,
     /// Generation service connection information
     pub service: ServiceConfig,
+    /// Pacing of generation streams consumed slower than generated, unpaced if unset
+    pub pacing: Option<GenerationPacing>,
+    /// Local Hugging Face tokenizer files by model ID. Tokens of these models are
+    /// counted locally instead of by the generation service
+    #[serde(default)]
+    pub tokenizers: HashMap<String, PathBuf>,
+}
+
+/// Pacing of generation streams.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GenerationPacing {
+    /// Maximum number of responses waiting for the client, reads from the generation
+    /// server are paused while as many responses wait
+    #[serde(default = "default_pacing_buffer_size")]
+    pub buffer_size: usize,
+    /// Seconds reads may be paused before generation is aborted, never aborted if unset
+    pub stall_timeout: Option<u64>,
 }
 
 /// Chat generation service configuration
@@ -151,6 +522,11 @@ pub struct ChunkerConfig {
     pub r#type: ChunkerType,
     /// Chunker service connection information
     pub service: ServiceConfig,
+    /// Maximum chunk size in chars, larger chunks are split at word boundaries where possible
+    pub max_chunk_size: Option<usize>,
+    /// Minimum chunk size in chars, smaller chunks are merged with neighboring chunks.
+    /// Not applied to streaming requests, as merging would hold back chunks.
+    pub min_chunk_size: Option<usize>,
 }
 
 /// Configuration for each detector
@@ -167,598 +543,3222 @@ pub struct DetectorConfig {
     /// Type of detection this detector performs
     #[serde(rename = "type")]
     pub r#type: DetectorType,
+    /// Detector endpoint path, overrides the default path for the detector type,
+    /// e.g. to reach detectors mounted behind a gateway with a path prefix
+    pub endpoint_path: Option<String>,
+    /// Handling of partial results, applicable to text contents detectors
+    #[serde(default)]
+    pub partial_results: PartialResultsPolicy,
+    /// Handling of detector failures during streaming requests, applicable to text contents detectors
+    #[serde(default)]
+    pub stream_failure: StreamFailurePolicy,
+    /// Map of detections to categories for this detector, takes precedence over `categories`
+    #[serde(default)]
+    pub categories: HashMap<String, String>,
+    /// Action suggested for text with detections from this detector, applicable to suitability verdicts
+    #[serde(default)]
+    pub action: DetectionAction,
+    /// Equivalent deployments of this detector, e.g. on GPU and CPU. Requests are routed
+    /// between `service` and these backends by rolling latency, failing over on errors
+    #[serde(default)]
+    pub backends: Vec<BackendConfig>,
+    /// Canary deployment of this detector, e.g. a retrained version, receiving a share of requests
+    pub canary: Option<CanaryConfig>,
+    /// Maximum number of chunks per request, applicable to text contents detectors.
+    /// Chunks are sent in concurrent batches, with detections reassembled in chunk order
+    pub max_batch_size: Option<usize>,
+    /// Limits of request and response bodies exchanged with this detector
+    #[serde(default)]
+    pub size_limits: SizeLimits,
+    /// Normalization of scores returned by this detector, for detectors not scoring in `[0, 1]`
+    pub score_normalization: Option<ScoreNormalization>,
+    /// Format of responses returned by this detector, applicable to text contents detectors.
+    /// Responses in legacy formats are mapped to canonical detections
+    #[serde(default)]
+    pub response_format: DetectorResponseFormat,
+    /// Local Hugging Face tokenizer file, for detectors returning token indices instead of
+    /// character offsets, applicable to text contents detectors. Token spans are translated
+    /// to character spans with the tokenizer
+    pub tokenizer_path: Option<PathBuf>,
 }
 
-#[derive(Default, Clone, Debug, Deserialize, PartialEq)]
+/// Format of text contents detector responses.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
-#[non_exhaustive]
-pub enum DetectorType {
+pub enum DetectorResponseFormat {
+    /// Detections with `start`, `end`, `text`, `detection` and `detection_type` fields
     #[default]
-    TextContents,
-    TextGeneration,
-    TextChat,
-    TextContextDoc,
+    Canonical,
+    /// Token classification results with `start`, `end`, `word`, `entity` and
+    /// `entity_group` fields, returned by older detectors
+    LegacyTokenClassification,
 }
 
-/// Overall orchestrator server configuration
-#[derive(Clone, Debug, Deserialize)]
-pub struct OrchestratorConfig {
-    /// Generation service and associated configuration, can be omitted if configuring for generation is not wanted
-    pub generation: Option<GenerationConfig>,
-    /// Chat generation service and associated configuration, can be omitted if configuring for chat generation is not wanted
-    pub chat_generation: Option<ChatGenerationConfig>,
-    /// Chunker services and associated configurations, if omitted the default value "whole_doc_chunker" is used
-    pub chunkers: Option<HashMap<String, ChunkerConfig>>,
-    /// Detector services and associated configurations
-    pub detectors: HashMap<String, DetectorConfig>,
-    /// Map of TLS connections, allowing reuse across services
-    /// that may require the same TLS information
-    pub tls: Option<HashMap<String, TlsConfig>>,
-    // List of header keys allowed to be passed to downstream servers
+/// Normalization of detector scores to `[0, 1]`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(tag = "rule", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ScoreNormalization {
+    /// Clamp scores to `[0, 1]`
+    Clamp,
+    /// Divide scores by `max`, e.g. 100 for percentages, clamping to `[0, 1]`
+    Scale { max: f64 },
+}
+
+impl ScoreNormalization {
+    /// Returns the normalized `score`.
+    pub fn apply(&self, score: f64) -> f64 {
+        match self {
+            ScoreNormalization::Clamp => score.clamp(0.0, 1.0),
+            ScoreNormalization::Scale { max } => (score / max).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// Limits of request and response bodies exchanged with a service.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SizeLimits {
+    /// Maximum size in bytes of request bodies, larger requests are not sent
+    pub max_request_bytes: Option<usize>,
+    /// Maximum size in bytes of response bodies, larger responses are discarded
+    pub max_response_bytes: Option<usize>,
+}
+
+/// Configuration for a canary deployment of a service
+#[derive(Default, Clone, Debug, Deserialize)]
+pub struct CanaryConfig {
+    /// Canary service connection information
+    pub service: ServiceConfig,
+    /// Percentage of requests selected for the canary, from 0 to 100
+    pub traffic_percent: u8,
+    /// Handling of requests selected for the canary
     #[serde(default)]
-    pub passthrough_headers: HashSet<String>,
-    /// Number of detector requests to send concurrently for a task.
-    #[serde(default = "default_detector_concurrent_requests")]
-    pub detector_concurrent_requests: usize,
-    /// Number of chunker requests to send concurrently for a task.
-    #[serde(default = "default_chunker_concurrent_requests")]
-    pub chunker_concurrent_requests: usize,
+    pub mode: CanaryMode,
 }
 
-impl OrchestratorConfig {
-    /// Loads config
-    pub async fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
-        let path = path.as_ref();
-        let config_yaml = tokio::fs::read_to_string(path).await.map_err(|error| {
-            Error::FailedToReadConfigFile {
-                path: path.to_string_lossy().to_string(),
-                error,
-            }
-        })?;
-        let mut config: OrchestratorConfig =
-            serde_yml::from_str(&config_yaml).map_err(Error::InvalidConfigFile)?;
-        debug!(?config, "loaded orchestrator config");
+/// Handling of requests selected for a canary deployment.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CanaryMode {
+    /// Send requests to both deployments, logging canary results and serving stable results
+    #[default]
+    Shadow,
+    /// Send requests to the canary only, serving canary results
+    Serve,
+}
 
-        if config.generation.is_none() {
-            warn!("no generation config provided");
-        }
-        if config.chunkers.is_none() {
-            warn!("no chunker configs provided");
+/// Configuration for an equivalent deployment of a service
+#[derive(Default, Clone, Debug, Deserialize)]
+pub struct BackendConfig {
+    /// Backend name, reported in traces
+    pub name: String,
+    /// Backend service connection information
+    pub service: ServiceConfig,
+}
+
+/// Action suggested for text with detections from a detector.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DetectionAction {
+    /// Block the text
+    #[default]
+    Block,
+    /// Redact the detected spans
+    Redact,
+}
+
+/// Handling of partial results from detectors that analyzed only part of the text.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PartialResultsPolicy {
+    /// Return detections for the analyzed text with a warning for the remainder
+    #[default]
+    Warn,
+    /// Send the remainder to the detector again
+    Redispatch,
+}
+
+/// Handling of a detector failing mid-stream.
+///
+/// Chunks a detector does not analyze are reported as partial detection warnings.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamFailurePolicy {
+    /// Fail the stream with the detector error
+    #[default]
+    Fail,
+    /// Stop sending chunks to the detector, continuing with the remaining detectors
+    Continue,
+    /// Skip the failed chunk, sending subsequent chunks to the detector
+    Retry,
+}
+
+/// Parts of chat messages sent to detectors for messages carrying
+/// reasoning content, e.g. `reasoning_content` emitted by reasoning models.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasoningDetection {
+    /// Final content only, reasoning content is not sent to detectors
+    #[default]
+    Content,
+    /// Reasoning content only
+    Reasoning,
+    /// Both reasoning content and final content
+    Both,
+}
+
+impl ReasoningDetection {
+    /// Returns whether the content and reasoning content of a message
+    /// are sent to detectors, respectively.
+    ///
+    /// Content of messages without reasoning content is always sent to detectors.
+    pub fn fields(&self, has_reasoning_content: bool) -> (bool, bool) {
+        match (self, has_reasoning_content) {
+            (_, false) | (ReasoningDetection::Content, true) => (true, false),
+            (ReasoningDetection::Reasoning, true) => (false, true),
+            (ReasoningDetection::Both, true) => (true, true),
         }
+    }
 
-        if config.passthrough_headers.is_empty() {
-            info!("No allowed headers specified");
+    /// Applies the policy to a message sent to chat detectors.
+    pub fn apply(&self, message: &mut openai::Message) {
+        match self {
+            ReasoningDetection::Content => message.reasoning_content = None,
+            ReasoningDetection::Reasoning if message.reasoning_content.is_some() => {
+                message.content = None
+            }
+            _ => (),
         }
+    }
+}
 
-        // Add default headers to allowed_headers list
-        debug!(
-            "Adding default headers: [{}]. ",
-            DEFAULT_ALLOWED_HEADERS.join(", ")
-        );
+/// Handling of image content parts, which are not sent to detectors.
+/// The generation backend always receives the full message contents.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImagePartsPolicy {
+    /// Run detection on text parts only
+    #[default]
+    Strip,
+    /// Reject requests containing image parts
+    Reject,
+}
 
-        // Lowercase all header for case-insensitive comparison
-        config.passthrough_headers = config
-            .passthrough_headers
-            .into_iter()
-            .map(|h| h.to_lowercase())
-            .collect::<HashSet<String>>();
+/// Handling of chat completion choices flagged by output detectors,
+/// applicable when multiple choices are requested with `n > 1`.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlaggedChoicesPolicy {
+    /// Return flagged choices along with their detections
+    #[default]
+    Keep,
+    /// Remove flagged choices from the response, detections are still returned.
+    /// Choices are kept with a warning if all are flagged
+    Drop,
+}
 
-        config
-            .passthrough_headers
-            .extend(DEFAULT_ALLOWED_HEADERS.iter().map(|h| h.to_lowercase()));
+/// Handling of chat completions tool definitions flagged by input detectors.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlaggedToolsPolicy {
+    /// Reject the request, returning the tool detections
+    #[default]
+    Reject,
+    /// Remove flagged tools from the request sent to the generation backend
+    Strip,
+}
 
-        config.apply_named_tls_configs()?;
-        config.validate()?;
+/// Validation of chat completions output requested with a JSON `response_format`,
+/// i.e. `json_object` or `json_schema`.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StructuredOutputPolicy {
+    /// Return output without validation
+    #[default]
+    Passthrough,
+    /// Return output with a warning if invalid
+    Warn,
+    /// Request a new chat completion if output is invalid, with a warning if retries are exhausted
+    Retry,
+}
 
-        Ok(config)
-    }
+/// Parsing of request bodies.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestParsing {
+    /// Ignore unknown fields of nested objects
+    #[default]
+    Lenient,
+    /// Reject requests with unknown fields, listing them
+    Strict,
+}
 
-    /// Applies named TLS configs to services.
-    fn apply_named_tls_configs(&mut self) -> Result<(), Error> {
-        if let Some(tls_configs) = &self.tls {
-            // Generation
-            if let Some(generation) = &mut self.generation {
-                apply_named_tls_config(&mut generation.service, tls_configs)?;
-            }
-            // Chat generation
-            if let Some(chat_generation) = &mut self.chat_generation {
-                apply_named_tls_config(&mut chat_generation.service, tls_configs)?;
-            }
-            // Chunkers
-            if let Some(chunkers) = &mut self.chunkers {
-                for chunker in chunkers.values_mut() {
-                    apply_named_tls_config(&mut chunker.service, tls_configs)?;
-                }
-            }
-            // Detectors
-            for detector in self.detectors.values_mut() {
-                apply_named_tls_config(&mut detector.service, tls_configs)?;
-            }
+/// Shaping of detections returned in responses.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DetectionsFilter {
+    /// Minimum score of returned detections
+    pub min_score: Option<f64>,
+    /// Maximum number of returned detections per category, highest scores first.
+    /// Detections without a category are grouped by detection type. Streaming responses are
+    /// bounded over all their messages
+    pub top_k: Option<usize>,
+}
+
+/// Ordering of streamed detection results.
+///
+/// Results are emitted in chunk order once detections from all detectors are
+/// received for a chunk, so results of later chunks are buffered while an
+/// earlier chunk is pending.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct StreamOrdering {
+    /// Maximum number of chunks buffered while waiting on an earlier chunk.
+    /// Unlimited if not set.
+    pub max_buffered_chunks: Option<usize>,
+    /// Handling of a full buffer
+    #[serde(default)]
+    pub overflow: StreamOverflowPolicy,
+}
+
+/// Handling of a full stream ordering buffer.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamOverflowPolicy {
+    /// Fail the stream with an error
+    #[default]
+    Error,
+    /// Emit the earliest pending chunk with the detections received so far.
+    /// Detections received for it afterwards are dropped
+    Flush,
+}
+
+impl DetectionsFilter {
+    /// Validates the filter values.
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .min_score
+            .is_some_and(|min_score| !(0.0..=1.0).contains(&min_score))
+        {
+            return Err("`min_score` must be between 0 and 1".into());
+        }
+        if self.top_k == Some(0) {
+            return Err("`top_k` must be greater than 0".into());
         }
         Ok(())
     }
+}
 
-    fn validate(&self) -> Result<(), Error> {
-        // Detectors are configured
-        if self.detectors.is_empty() {
-            return Err(Error::NoDetectorsConfigured);
-        }
+/// Service level objectives of guardrails server routes, used to count good and bad
+/// requests for SLO burn-rate alerting.
+#[derive(Default, Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SloConfig {
+    /// Objectives of all routes
+    #[serde(default)]
+    pub default: SloObjectives,
+    /// Objectives by route path, e.g. `/api/v2/text/detection/content`.
+    /// Objectives not set for a route fall back to `default`
+    #[serde(default)]
+    pub routes: HashMap<String, SloObjectives>,
+}
 
-        // Apply validation rules
-        self.validate_generation_config()?;
-        self.validate_chat_generation_config()?;
-        self.validate_detector_configs()?;
-        self.validate_chunker_configs()?;
+/// Service level objectives of a route.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SloObjectives {
+    /// Latency objective in milliseconds. Requests taking longer to respond are bad
+    pub latency_ms: Option<u64>,
+    /// Availability objective as the fraction of good requests, e.g. `0.999`.
+    /// Reported with SLO metrics to compute burn rates
+    pub availability: Option<f64>,
+}
 
-        Ok(())
+impl SloConfig {
+    /// Returns the objectives of a route.
+    pub fn objectives(&self, route: &str) -> SloObjectives {
+        let objectives = self.routes.get(route).copied().unwrap_or_default();
+        SloObjectives {
+            latency_ms: objectives.latency_ms.or(self.default.latency_ms),
+            availability: objectives.availability.or(self.default.availability),
+        }
     }
 
-    /// Validates generation config.
-    fn validate_generation_config(&self) -> Result<(), Error> {
-        if let Some(generation) = &self.generation {
-            // Hostname is valid
-            if !is_valid_hostname(&generation.service.hostname) {
-                return Err(Error::InvalidHostname(
-                    "`generation` has an invalid hostname".into(),
-                ));
+    /// Validates the objectives.
+    pub fn validate(&self) -> Result<(), String> {
+        for (route, objectives) in std::iter::once(("default", &self.default))
+            .chain(self.routes.iter().map(|(k, v)| (k.as_str(), v)))
+        {
+            if objectives.latency_ms == Some(0) {
+                return Err(format!("`{route}`: `latency_ms` must be greater than 0"));
+            }
+            if objectives
+                .availability
+                .is_some_and(|availability| !(availability > 0.0 && availability < 1.0))
+            {
+                return Err(format!("`{route}`: `availability` must be between 0 and 1"));
             }
         }
         Ok(())
     }
+}
 
-    /// Validates chat generation config.
-    fn validate_chat_generation_config(&self) -> Result<(), Error> {
-        if let Some(chat_generation) = &self.chat_generation {
-            // Hostname is valid
-            if !is_valid_hostname(&chat_generation.service.hostname) {
-                return Err(Error::InvalidHostname(
-                    "`chat_generation` has an invalid hostname".into(),
-                ));
+/// Policy restricting the downstream hosts clients connect to.
+///
+/// A service is allowed if its hostname matches `hosts`, or if all of its resolved
+/// addresses are within `cidrs`.
+#[derive(Default, Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct EgressPolicy {
+    /// Allowed hostnames, exact or with a leading wildcard label, e.g. `*.svc.cluster.local`
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// Allowed address ranges in CIDR notation, e.g. `10.0.0.0/8`
+    #[serde(default)]
+    pub cidrs: Vec<Cidr>,
+}
+
+impl EgressPolicy {
+    /// Returns `true` if `hostname` matches an allowed hostname. Hostnames are matched
+    /// case-insensitively.
+    pub fn allows_host(&self, hostname: &str) -> bool {
+        let hostname = hostname.to_ascii_lowercase();
+        self.hosts.iter().any(|host| match host.strip_prefix("*.") {
+            Some(domain) => hostname
+                .strip_suffix(&domain.to_ascii_lowercase())
+                .is_some_and(|prefix| prefix.len() > 1 && prefix.ends_with('.')),
+            None => host.eq_ignore_ascii_case(&hostname),
+        })
+    }
+
+    /// Returns `true` if `addr` is within an allowed address range.
+    pub fn allows_addr(&self, addr: IpAddr) -> bool {
+        self.cidrs.iter().any(|cidr| cidr.contains(addr))
+    }
+
+    /// Returns `true` if egress to `hostname` is allowed, or may be allowed by its
+    /// resolved addresses.
+    pub fn may_allow_host(&self, hostname: &str) -> bool {
+        self.allows_host(hostname)
+            || match hostname
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<IpAddr>()
+            {
+                Ok(addr) => self.allows_addr(addr),
+                // Resolved addresses are checked on resolution
+                Err(_) => !self.cidrs.is_empty(),
             }
+    }
+
+    /// Validates that egress to `hostname` is allowed, or may be allowed by its
+    /// resolved addresses.
+    fn validate_host(&self, hostname: &str) -> Result<(), Error> {
+        match self.may_allow_host(hostname) {
+            true => Ok(()),
+            false => Err(Error::EgressNotAllowed(hostname.into())),
+        }
+    }
+}
+
+/// Defaults of services not overriding them.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DefaultsConfig {
+    /// Timeout in seconds of requests to generation, chunker and other services without
+    /// a `request_timeout`, defaults to 600
+    #[serde(default = "default_request_timeout")]
+    pub request_timeout: u64,
+    /// Timeout in seconds of requests to detectors without a `request_timeout`,
+    /// defaults to 600
+    #[serde(default = "default_detector_request_timeout")]
+    pub detector_request_timeout: u64,
+}
+
+impl Default for DefaultsConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout: default_request_timeout(),
+            detector_request_timeout: default_detector_request_timeout(),
+        }
+    }
+}
+
+impl DefaultsConfig {
+    /// Validates timeouts are non-zero.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.request_timeout == 0 {
+            return Err("`request_timeout` must be greater than 0".into());
+        }
+        if self.detector_request_timeout == 0 {
+            return Err("`detector_request_timeout` must be greater than 0".into());
         }
         Ok(())
     }
+}
 
-    /// Validates detector configs.
-    fn validate_detector_configs(&self) -> Result<(), Error> {
-        for (detector_id, detector) in &self.detectors {
-            // Hostname is valid
-            if !is_valid_hostname(&detector.service.hostname) {
-                return Err(Error::InvalidHostname(format!(
-                    "detector `{detector_id}` has an invalid hostname"
-                )));
-            }
-            // Chunker is valid
-            let valid_chunker = detector.chunker_id == DEFAULT_CHUNKER_ID
-                || self
-                    .chunkers
-                    .as_ref()
-                    .is_some_and(|chunkers| chunkers.contains_key(&detector.chunker_id));
-            if !valid_chunker {
-                return Err(Error::DetectorChunkerNotFound {
-                    detector_id: detector_id.clone(),
-                    chunker_id: detector.chunker_id.clone(),
-                });
+/// Asynchronous generation jobs.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct JobsConfig {
+    /// Number of jobs run concurrently, defaults to 4
+    #[serde(default = "default_jobs_max_concurrent")]
+    pub max_concurrent: usize,
+    /// Number of jobs waiting to run, defaults to 100. Jobs submitted while the queue
+    /// is full are rejected
+    #[serde(default = "default_jobs_max_queued")]
+    pub max_queued: usize,
+    /// Time in seconds results of completed jobs are retained for, defaults to 3600
+    #[serde(default = "default_jobs_result_ttl")]
+    pub result_ttl: u64,
+    /// Hosts completion callbacks may be sent to. Callback URLs with a hostname must
+    /// match `hosts`, URLs with an IP address must be within `cidrs`. Callbacks are
+    /// also subject to the egress policy. Jobs with a `callback_url` are rejected if not set
+    pub callbacks: Option<EgressPolicy>,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: default_jobs_max_concurrent(),
+            max_queued: default_jobs_max_queued(),
+            result_ttl: default_jobs_result_ttl(),
+            callbacks: None,
+        }
+    }
+}
+
+impl JobsConfig {
+    /// Validates the limits of jobs.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_concurrent == 0 {
+            return Err("`max_concurrent` must be greater than 0".into());
+        }
+        if self.result_ttl == 0 {
+            return Err("`result_ttl` must be greater than 0".into());
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if completion callbacks may be sent to `hostname`.
+    pub fn allows_callback(&self, hostname: &str) -> bool {
+        self.callbacks.as_ref().is_some_and(|callbacks| {
+            callbacks.allows_host(hostname)
+                || hostname
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .parse::<IpAddr>()
+                    .is_ok_and(|addr| callbacks.allows_addr(addr))
+        })
+    }
+}
+
+/// Subsystems that can be disabled at runtime, e.g. to shed detection during incidents
+/// without redeploying. Flags are updated with the admin API.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FeaturesConfig {
+    /// Detection on request inputs, defaults to true
+    #[serde(default = "default_feature_enabled")]
+    pub input_detection: bool,
+    /// Detection on generated outputs, defaults to true
+    #[serde(default = "default_feature_enabled")]
+    pub output_detection: bool,
+    /// Detection on streamed generated outputs, defaults to true. Streamed outputs are
+    /// not analyzed if either this or `output_detection` is disabled
+    #[serde(default = "default_feature_enabled")]
+    pub streaming_detection: bool,
+    /// Payload capture records, defaults to true
+    #[serde(default = "default_feature_enabled")]
+    pub audit_logging: bool,
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            input_detection: default_feature_enabled(),
+            output_detection: default_feature_enabled(),
+            streaming_detection: default_feature_enabled(),
+            audit_logging: default_feature_enabled(),
+        }
+    }
+}
+
+/// Resumption of streaming classification with text generation responses. Streams are
+/// buffered server-side, with periodic checkpoints a client reconnecting within the
+/// window resumes from instead of restarting generation.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct StreamResumptionConfig {
+    /// Time in seconds streams remain resumable for after they completed, or after their
+    /// last message while incomplete, defaults to 60
+    #[serde(default = "default_stream_resumption_window")]
+    pub window: u64,
+    /// Number of streamed messages between checkpoints, defaults to 10
+    #[serde(default = "default_stream_resumption_checkpoint_interval")]
+    pub checkpoint_interval: usize,
+    /// Maximum number of buffered streams, defaults to 1000. Streams are not resumable
+    /// while full
+    #[serde(default = "default_stream_resumption_max_streams")]
+    pub max_streams: usize,
+    /// Maximum number of buffered messages of a stream, defaults to 10000. Earliest
+    /// messages are dropped when full, so earlier checkpoints are no longer resumable
+    #[serde(default = "default_stream_resumption_max_messages")]
+    pub max_messages: usize,
+}
+
+impl StreamResumptionConfig {
+    /// Validates the window, checkpoint interval and limits.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window == 0 {
+            return Err("`window` must be greater than 0".into());
+        }
+        if self.checkpoint_interval == 0 {
+            return Err("`checkpoint_interval` must be greater than 0".into());
+        }
+        if self.max_streams == 0 {
+            return Err("`max_streams` must be greater than 0".into());
+        }
+        if self.max_messages == 0 {
+            return Err("`max_messages` must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+/// Caching of guarded responses of deterministic generation requests, i.e. requests
+/// with a seed and greedy decoding or zero temperature.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseCacheConfig {
+    /// Time in seconds responses are cached for, defaults to 300
+    #[serde(default = "default_response_cache_ttl")]
+    pub ttl: u64,
+    /// Maximum number of cached responses, defaults to 1000. The entry expiring
+    /// soonest is evicted when full
+    #[serde(default = "default_response_cache_max_entries")]
+    pub max_entries: usize,
+}
+
+impl ResponseCacheConfig {
+    /// Validates the TTL and size.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.ttl == 0 {
+            return Err("`ttl` must be greater than 0".into());
+        }
+        if self.max_entries == 0 {
+            return Err("`max_entries` must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+/// Webhooks notified of policy violations, i.e. blocked requests and high-severity detections.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AlertsConfig {
+    /// Webhooks sent each violation record
+    pub webhooks: Vec<AlertWebhook>,
+    /// Minimum score of high-severity detections. Only blocked requests are alerted on if not set
+    pub min_score: Option<f64>,
+    /// Retries of failed deliveries, with exponential backoff, defaults to 3
+    #[serde(default = "default_alert_max_retries")]
+    pub max_retries: usize,
+}
+
+/// Webhook notified of policy violations.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AlertWebhook {
+    /// URL violation records are sent to in POST requests
+    pub url: String,
+    /// Secret signing `<timestamp>.<body>` of requests with HMAC-SHA256, sent in the
+    /// `x-guardrails-signature` header as `sha256=<hex digest>`, with the Unix timestamp
+    /// in seconds in the `x-guardrails-timestamp` header
+    pub secret: Option<SecretSource>,
+}
+
+impl AlertsConfig {
+    /// Validates webhook URLs and the minimum score.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.webhooks.is_empty() {
+            return Err("no webhooks configured".into());
+        }
+        for webhook in &self.webhooks {
+            let url = url::Url::parse(&webhook.url)
+                .map_err(|error| format!("invalid url `{}`: {error}", webhook.url))?;
+            if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+                return Err(format!(
+                    "url `{}` must be an http or https URL",
+                    webhook.url
+                ));
             }
         }
+        if self
+            .min_score
+            .is_some_and(|min_score| !(0.0..=1.0).contains(&min_score))
+        {
+            return Err("`min_score` must be between 0 and 1".into());
+        }
         Ok(())
     }
+}
 
-    /// Validates chunker configs.
-    fn validate_chunker_configs(&self) -> Result<(), Error> {
-        if let Some(chunkers) = &self.chunkers {
-            for (chunker_id, chunker) in chunkers {
-                // Hostname is valid
-                if !is_valid_hostname(&chunker.service.hostname) {
-                    return Err(Error::InvalidHostname(format!(
-                        "chunker `{chunker_id}` has an invalid hostname"
-                    )));
-                }
+/// Provenance metadata of generated text, letting downstream systems verify text
+/// passed through guardrails unmodified.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProvenanceConfig {
+    /// Key signing the content hash, policy decision, trace ID and timestamp with
+    /// HMAC-SHA256, loaded on start-up. Provenance is unsigned if not set
+    pub signing_key: Option<SecretSource>,
+}
+
+/// API key and JWT authentication of guardrails server requests.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    /// API keys by consumer name, sent in the `Authorization: Bearer <key>` or
+    /// `x-api-key` header. Consumer names label request logs and route metrics
+    #[serde(default)]
+    pub api_keys: HashMap<String, SecretSource>,
+    /// Interval in seconds at which API keys are re-loaded, e.g. to accept rotated keys.
+    /// Keys are loaded once on start-up if not set
+    pub refresh_interval: Option<u64>,
+    /// Validation of OIDC bearer tokens. JWTs are not accepted if not set
+    pub jwt: Option<JwtConfig>,
+}
+
+impl AuthConfig {
+    /// Validates API keys or JWT validation are configured.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.api_keys.is_empty() && self.jwt.is_none() {
+            return Err("no API keys or JWT validation configured".into());
+        }
+        if let Some(jwt) = &self.jwt {
+            jwt.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// Validation of OIDC bearer tokens, signed with keys of the JWKS of their issuer.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct JwtConfig {
+    /// Issuer, matched against the `iss` claim, e.g.
+    /// `https://keycloak.example.com/realms/guardrails`
+    pub issuer: String,
+    /// Audiences, one of which must match the `aud` claim. The audience is not validated if empty
+    #[serde(default)]
+    pub audiences: Vec<String>,
+    /// URL of the JWKS. Discovered from `<issuer>/.well-known/openid-configuration` if not set,
+    /// rejected if not allowed by the egress policy
+    pub jwks_uri: Option<String>,
+    /// Interval in seconds at which the JWKS is re-fetched, defaults to 300. Tokens signed
+    /// with unknown keys re-fetch it sooner
+    #[serde(default = "default_jwks_refresh_interval")]
+    pub jwks_refresh_interval: u64,
+    /// Signing algorithms of tokens signed with keys without an `alg`, defaults to
+    /// `RS256`, `ES256` and `EdDSA`. Tokens signed with keys with an `alg` must use it
+    #[serde(default = "default_jwt_algorithms")]
+    pub algorithms: Vec<jsonwebtoken::Algorithm>,
+    /// Claim naming the consumer of requests, defaults to `azp`, falling back to `sub`
+    #[serde(default = "default_jwt_consumer_claim")]
+    pub consumer_claim: String,
+    /// Request headers set to claims, by claim, e.g. to route tenants by the header of
+    /// payload capture. Headers sent by clients are removed, also if the claim is missing
+    #[serde(default)]
+    pub claim_headers: HashMap<String, String>,
+}
+
+impl JwtConfig {
+    /// Validates the issuer, JWKS URL, algorithms and claim headers.
+    pub fn validate(&self) -> Result<(), String> {
+        for url in std::iter::once(&self.issuer).chain(&self.jwks_uri) {
+            let url =
+                url::Url::parse(url).map_err(|error| format!("invalid url `{url}`: {error}"))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(format!("url `{url}` must be an http or https URL"));
             }
         }
+        if let Some(algorithm) = self.algorithms.iter().find(|algorithm| {
+            use jsonwebtoken::Algorithm::*;
+            // Keys of JWKS are public, HMAC keys would be shared secrets
+            matches!(algorithm, HS256 | HS384 | HS512)
+        }) {
+            return Err(format!("unsupported algorithm `{algorithm:?}`"));
+        }
+        if let Some(header) = self
+            .claim_headers
+            .values()
+            .find(|header| http::HeaderName::try_from(header.as_str()).is_err())
+        {
+            return Err(format!("invalid claim header `{header}`"));
+        }
         Ok(())
     }
+}
 
-    /// Get ID of chunker associated with a particular detector
-    pub fn get_chunker_id(&self, detector_id: &str) -> Option<String> {
-        self.detectors
-            .get(detector_id)
-            .map(|detector_config| detector_config.chunker_id.clone())
+/// Telemetry exported with OTLP.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtlpExport {
+    Traces,
+    Metrics,
+    Logs,
+}
+
+impl std::fmt::Display for OtlpExport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OtlpExport::Traces => write!(f, "traces"),
+            OtlpExport::Metrics => write!(f, "metrics"),
+            OtlpExport::Logs => write!(f, "logs"),
+        }
     }
+}
 
-    /// Gets a chunker config.
-    pub fn chunker(&self, chunker_id: &str) -> Option<&ChunkerConfig> {
-        if let Some(chunkers) = &self.chunkers {
-            chunkers.get(chunker_id)
-        } else {
-            None
+impl From<String> for OtlpExport {
+    fn from(s: String) -> Self {
+        match s.to_lowercase().as_str() {
+            "traces" => OtlpExport::Traces,
+            "metrics" => OtlpExport::Metrics,
+            "logs" => OtlpExport::Logs,
+            _ => panic!(
+                "Invalid OTLP export type {}, orchestrator only supports exporting traces, metrics and logs via OTLP",
+                s
+            ),
         }
     }
+}
 
-    /// Gets a detector config.
-    pub fn detector(&self, detector_id: &str) -> Option<&DetectorConfig> {
-        self.detectors.get(detector_id)
+/// Protocol of OTLP export.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtlpProtocol {
+    #[default]
+    Grpc,
+    Http,
+}
+
+impl std::fmt::Display for OtlpProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OtlpProtocol::Grpc => write!(f, "grpc"),
+            OtlpProtocol::Http => write!(f, "http"),
+        }
     }
 }
 
-impl Default for OrchestratorConfig {
-    fn default() -> Self {
-        Self {
-            generation: None,
-            chat_generation: None,
-            chunkers: None,
-            detectors: HashMap::default(),
-            tls: None,
-            passthrough_headers: HashSet::default(),
-            detector_concurrent_requests: default_detector_concurrent_requests(),
-            chunker_concurrent_requests: default_chunker_concurrent_requests(),
+impl From<String> for OtlpProtocol {
+    fn from(s: String) -> Self {
+        match s.to_lowercase().as_str() {
+            "grpc" => OtlpProtocol::Grpc,
+            "http" => OtlpProtocol::Http,
+            _ => {
+                error!(
+                    "Invalid OTLP protocol {}, defaulting to {}",
+                    s,
+                    OtlpProtocol::default()
+                );
+                OtlpProtocol::default()
+            }
         }
     }
 }
 
-/// Applies named TLS config to a service.
-fn apply_named_tls_config(
-    service: &mut ServiceConfig,
-    tls_configs: &HashMap<String, TlsConfig>,
-) -> Result<(), Error> {
-    if let Some(Tls::Name(name)) = &service.tls {
-        let tls_config = tls_configs
-            .get(name)
-            .ok_or(Error::TlsConfigNotFound {
-                name: name.clone(),
-                host: service.hostname.clone(),
-                port: service.port.unwrap_or(0).to_string(),
-            })?
-            .clone();
-        service.tls = Some(Tls::Config(tls_config));
+impl OtlpProtocol {
+    pub fn default_endpoint(&self) -> &str {
+        match self {
+            OtlpProtocol::Grpc => "http://localhost:4317",
+            OtlpProtocol::Http => "http://localhost:4318",
+        }
+    }
+}
+
+/// OTLP export of telemetry, applied on start-up before the rest of the config is loaded.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct OtlpConfig {
+    /// Telemetry exported: `traces`, `metrics` and/or `logs`
+    pub export: Vec<OtlpExport>,
+    /// Collector endpoint, defaults to the default endpoint of the protocol
+    pub endpoint: Option<String>,
+    /// Export protocol, `grpc` or `http`, defaults to `grpc`
+    #[serde(default)]
+    pub protocol: OtlpProtocol,
+    /// Headers added to export requests, e.g. for collector authentication
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Baggage keys propagated from incoming requests to downstream requests, other
+    /// baggage is dropped
+    #[serde(default)]
+    pub baggage: Vec<String>,
+}
+
+/// Sampled capture of request payloads, generated outputs and detections, e.g. for
+/// offline detector evaluation and threshold tuning.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// Header identifying the tenant of requests, defaults to `x-tenant-id`. With
+    /// authentication, tenants are API consumers, unless the header is a JWT claim header
+    #[serde(default = "default_capture_tenant_header")]
+    pub tenant_header: String,
+    /// Fraction of requests captured by tenant. Requests of other tenants are not captured
+    pub tenants: HashMap<String, f64>,
+    /// JSON lines file captured payloads are appended to
+    pub path: PathBuf,
+    /// Additional regular expressions of text redacted from captured payloads. Email
+    /// addresses, long digit sequences and spans detected by detectors with the `redact`
+    /// action are always redacted
+    #[serde(default)]
+    pub redact_patterns: Vec<String>,
+    /// Envelope encryption of captured payloads with per-tenant keys. Payloads are
+    /// written in plain text if not set
+    pub encryption: Option<CaptureEncryptionConfig>,
+}
+
+/// Envelope encryption of captured payloads: each record is encrypted with a random data
+/// key, itself encrypted with the key of the record's tenant, so a single capture file
+/// holds the payloads of multiple tenants with cryptographic isolation.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CaptureEncryptionConfig {
+    /// Key encryption keys by tenant. Every captured tenant must have a key
+    pub tenant_keys: HashMap<String, TenantKey>,
+}
+
+/// Key encryption key of a tenant.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TenantKey {
+    /// Key ID written to records, identifying the key to decrypt them with, e.g. after rotation
+    pub key_id: String,
+    /// Base64-encoded 256-bit AES key
+    pub key: SecretSource,
+}
+
+impl TenantKey {
+    /// Loads the key, decoded from base64.
+    pub async fn load(&self) -> Result<Vec<u8>, String> {
+        let key = self.key.load().await.map_err(|error| error.to_string())?;
+        STANDARD
+            .decode(key.trim_ascii())
+            .ok()
+            .filter(|key| key.len() == 32)
+            .ok_or_else(|| format!("key `{}` must be a base64-encoded 256-bit key", self.key_id))
+    }
+}
+
+impl CaptureConfig {
+    /// Returns the fraction of requests of `tenant` captured.
+    pub fn sample_rate(&self, tenant: &str) -> f64 {
+        self.tenants.get(tenant).copied().unwrap_or_default()
+    }
+
+    /// Validates sample rates, redaction patterns and encryption keys, loading the keys.
+    pub async fn validate(&self) -> Result<(), String> {
+        if let Some((tenant, _)) = self
+            .tenants
+            .iter()
+            .find(|(_, rate)| !(**rate > 0.0 && **rate <= 1.0))
+        {
+            return Err(format!(
+                "sample rate of tenant `{tenant}` must be greater than 0 and at most 1"
+            ));
+        }
+        for pattern in &self.redact_patterns {
+            regex::Regex::new(pattern)
+                .map_err(|error| format!("invalid redact pattern `{pattern}`: {error}"))?;
+        }
+        if let Some(encryption) = &self.encryption {
+            if let Some(tenant) = self
+                .tenants
+                .keys()
+                .find(|tenant| !encryption.tenant_keys.contains_key(*tenant))
+            {
+                return Err(format!(
+                    "no encryption key configured for tenant `{tenant}`"
+                ));
+            }
+            for (tenant, tenant_key) in &encryption.tenant_keys {
+                tenant_key.load().await.map_err(|error| {
+                    format!("invalid encryption key of tenant `{tenant}`: {error}")
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// On-disk queue of records of JSON lines sinks, i.e. payload capture and detector
+/// feedback, buffering records while their files cannot be written.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SpoolConfig {
+    /// Directory of spooled records, with a subdirectory per sink file
+    pub dir: PathBuf,
+    /// Maximum size in bytes of the spool of each sink, defaults to 100 MiB
+    #[serde(default = "default_spool_max_bytes")]
+    pub max_bytes: u64,
+    /// Records dropped while the spool is full
+    #[serde(default)]
+    pub overflow: SpoolOverflow,
+    /// Interval in seconds at which writing spooled records to the sink is retried,
+    /// defaults to 5
+    #[serde(default = "default_spool_retry_interval")]
+    pub retry_interval: u64,
+    /// Wait for buffer space instead of dropping records while the in-memory write
+    /// buffer is full, delaying responses
+    #[serde(default)]
+    pub backpressure: bool,
+}
+
+impl SpoolConfig {
+    /// Validates size and retry interval are non-zero.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_bytes == 0 {
+            return Err("`max_bytes` must be greater than 0".into());
+        }
+        if self.retry_interval == 0 {
+            return Err("`retry_interval` must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+/// Records dropped while a spool is full.
+#[derive(Default, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpoolOverflow {
+    /// Drop new records
+    #[default]
+    DropNewest,
+    /// Drop the oldest spooled records to make room for new records
+    DropOldest,
+}
+
+/// Persistence of detector feedback.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FeedbackConfig {
+    /// JSON lines file feedback records are appended to
+    pub path: PathBuf,
+}
+
+/// An address range in CIDR notation, e.g. `10.0.0.0/8`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// Returns `true` if `addr` is within the range.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid cidr `{value}`");
+        let (addr, prefix_len) = match value.split_once('/') {
+            Some((addr, prefix_len)) => (
+                addr.parse::<IpAddr>().map_err(|_| invalid())?,
+                Some(prefix_len.parse::<u8>().map_err(|_| invalid())?),
+            ),
+            None => (value.parse::<IpAddr>().map_err(|_| invalid())?, None),
+        };
+        let max_prefix_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix_len = prefix_len.unwrap_or(max_prefix_len);
+        if prefix_len > max_prefix_len {
+            return Err(invalid());
+        }
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum DetectorType {
+    #[default]
+    TextContents,
+    TextGeneration,
+    TextChat,
+    TextContextDoc,
+}
+
+/// Overall orchestrator server configuration
+#[derive(Clone, Debug, Deserialize)]
+pub struct OrchestratorConfig {
+    /// Generation service and associated configuration, can be omitted if configuring for generation is not wanted
+    pub generation: Option<GenerationConfig>,
+    /// Chat generation service and associated configuration, can be omitted if configuring for chat generation is not wanted
+    pub chat_generation: Option<ChatGenerationConfig>,
+    /// Chunker services and associated configurations, if omitted the default value "whole_doc_chunker" is used
+    pub chunkers: Option<HashMap<String, ChunkerConfig>>,
+    /// Detector services and associated configurations
+    pub detectors: HashMap<String, DetectorConfig>,
+    /// Defaults of services not overriding them
+    #[serde(default)]
+    pub defaults: DefaultsConfig,
+    /// Map of TLS connections, allowing reuse across services
+    /// that may require the same TLS information
+    pub tls: Option<HashMap<String, TlsConfig>>,
+    /// Number of days before expiry at which loaded client and server TLS certificates
+    /// are logged as warnings
+    #[serde(default = "default_tls_expiry_warning_days")]
+    pub tls_expiry_warning_days: u64,
+    // List of header keys allowed to be passed to downstream servers
+    #[serde(default)]
+    pub passthrough_headers: HashSet<String>,
+    /// Header keys passed to the generation service only, never to detectors or
+    /// chunkers, e.g. routing hints for a model gateway
+    #[serde(default)]
+    pub generation_passthrough_headers: HashSet<String>,
+    /// Number of detectors a task sends requests to concurrently, and number of concurrent
+    /// batch requests per detector. Detectors beyond this limit wait for a slot, adding
+    /// round-trips to requests with more detectors.
+    #[serde(default = "default_detector_concurrent_requests")]
+    pub detector_concurrent_requests: usize,
+    /// Number of chunker requests to send concurrently for a task.
+    #[serde(default = "default_chunker_concurrent_requests")]
+    pub chunker_concurrent_requests: usize,
+    /// Maximum number of outstanding downstream requests across all clients.
+    /// Requests beyond this limit are queued. Streaming requests are not limited.
+    /// Unlimited if not set.
+    pub max_concurrent_requests: Option<usize>,
+    /// Parts of chat messages sent to detectors when reasoning content is present
+    #[serde(default)]
+    pub reasoning_detection: ReasoningDetection,
+    /// Handling of image content parts in chat completions requests with input detectors
+    #[serde(default)]
+    pub image_parts: ImagePartsPolicy,
+    /// Handling of chat completion choices flagged by output detectors
+    #[serde(default)]
+    pub flagged_choices: FlaggedChoicesPolicy,
+    /// Handling of chat completions tool definitions flagged by input detectors
+    #[serde(default)]
+    pub flagged_tools: FlaggedToolsPolicy,
+    /// Validation of chat completions output requested with a JSON `response_format`
+    #[serde(default)]
+    pub structured_output: StructuredOutputPolicy,
+    /// Parsing of request bodies
+    #[serde(default)]
+    pub request_parsing: RequestParsing,
+    /// Map of detections to normalized categories, e.g. `hate`, `pii`, `jailbreak`.
+    /// Keys are `<detection_type>/<detection>`, `<detection>` or `<detection_type>`,
+    /// matched in that order.
+    #[serde(default)]
+    pub categories: HashMap<String, String>,
+    /// Shaping of detections returned in responses, overridable per request
+    #[serde(default)]
+    pub detections_filter: DetectionsFilter,
+    /// Ordering of streamed detection results
+    #[serde(default)]
+    pub stream_ordering: StreamOrdering,
+    /// Number of chunks by which streamed generation results are held back. Each result is
+    /// emitted once detections of the following chunks complete, trading latency for
+    /// withholding the text of held results and of results with detections
+    #[serde(default)]
+    pub stream_holdback_chunks: usize,
+    /// Fraction of downstream requests logged with their endpoint, attempt, payload and
+    /// response sizes, status and latency. Disabled if not set.
+    pub downstream_log_sample_rate: Option<f64>,
+    /// Service level objectives of guardrails server routes. SLO metrics are not
+    /// emitted if not set
+    pub slo: Option<SloConfig>,
+    /// Static hostname overrides, like `/etc/hosts`. Hostnames listed here resolve to
+    /// the given addresses instead of querying DNS
+    #[serde(default)]
+    pub static_hosts: HashMap<String, Vec<IpAddr>>,
+    /// Downstream hosts clients may connect to. Unrestricted if not set
+    pub egress: Option<EgressPolicy>,
+    /// Asynchronous generation jobs
+    #[serde(default)]
+    pub jobs: JobsConfig,
+    /// Subsystems enabled on start-up, all enabled by default
+    #[serde(default)]
+    pub features: FeaturesConfig,
+    /// Resumption of streaming responses from checkpoints. Disabled if not set
+    pub stream_resumption: Option<StreamResumptionConfig>,
+    /// Caching of responses of deterministic generation requests. Disabled if not set
+    pub response_cache: Option<ResponseCacheConfig>,
+    /// Webhooks notified of policy violations. Disabled if not set
+    pub alerts: Option<AlertsConfig>,
+    /// Content hash, policy decision and signature of generated text in responses.
+    /// Disabled if not set
+    pub provenance: Option<ProvenanceConfig>,
+    /// Sampled capture of payloads for offline evaluation. Disabled if not set
+    pub capture: Option<CaptureConfig>,
+    /// Persistence of detector feedback. Feedback is only counted in metrics if not set
+    pub feedback: Option<FeedbackConfig>,
+    /// On-disk queue of payload capture and feedback records while their files cannot
+    /// be written. Records that cannot be written are dropped if not set
+    pub spool: Option<SpoolConfig>,
+    /// Groups of interchangeable detectors, by group name. Requests for an unhealthy
+    /// member are served by the first healthy member of its group, in listed order
+    #[serde(default)]
+    pub detector_groups: HashMap<String, Vec<String>>,
+    /// API key authentication of guardrails server requests. Disabled if not set
+    pub auth: Option<AuthConfig>,
+    /// OTLP export of traces, metrics and logs. Command line arguments and environment
+    /// variables take precedence
+    pub otlp: Option<OtlpConfig>,
+}
+
+impl OrchestratorConfig {
+    /// Loads the OTLP export config only, as telemetry is initialized before the config
+    /// is loaded.
+    pub async fn load_otlp(path: impl AsRef<Path>) -> Result<Option<OtlpConfig>, Error> {
+        #[derive(Deserialize)]
+        struct OtlpSection {
+            otlp: Option<OtlpConfig>,
+        }
+        let path = path.as_ref();
+        let config_yaml = tokio::fs::read_to_string(path).await.map_err(|error| {
+            Error::FailedToReadConfigFile {
+                path: path.to_string_lossy().to_string(),
+                error,
+            }
+        })?;
+        let section: OtlpSection =
+            serde_yml::from_str(&config_yaml).map_err(Error::InvalidConfigFile)?;
+        Ok(section.otlp)
+    }
+
+    /// Loads config
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let config_yaml = tokio::fs::read_to_string(path).await.map_err(|error| {
+            Error::FailedToReadConfigFile {
+                path: path.to_string_lossy().to_string(),
+                error,
+            }
+        })?;
+        let mut config: OrchestratorConfig =
+            serde_yml::from_str(&config_yaml).map_err(Error::InvalidConfigFile)?;
+        debug!(?config, "loaded orchestrator config");
+
+        if config.generation.is_none() {
+            warn!("no generation config provided");
+        }
+        if config.chunkers.is_none() {
+            warn!("no chunker configs provided");
+        }
+
+        if config.passthrough_headers.is_empty() {
+            info!("No allowed headers specified");
+        }
+
+        // Add default headers to allowed_headers list
+        debug!(
+            "Adding default headers: [{}]. ",
+            DEFAULT_ALLOWED_HEADERS.join(", ")
+        );
+
+        // Lowercase all header for case-insensitive comparison
+        config.passthrough_headers = config
+            .passthrough_headers
+            .into_iter()
+            .map(|h| h.to_lowercase())
+            .collect::<HashSet<String>>();
+
+        config
+            .passthrough_headers
+            .extend(DEFAULT_ALLOWED_HEADERS.iter().map(|h| h.to_lowercase()));
+        config.generation_passthrough_headers = config
+            .generation_passthrough_headers
+            .into_iter()
+            .map(|h| h.to_lowercase())
+            .collect::<HashSet<String>>();
+
+        config.validate_tls_configs().await?;
+        if let Some(capture) = &config.capture {
+            capture.validate().await.map_err(Error::InvalidCapture)?;
+        }
+        config.apply_named_tls_configs()?;
+        config.apply_default_timeouts();
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Period before expiry at which loaded TLS certificates are logged as warnings.
+    pub fn tls_expiry_warning_period(&self) -> Duration {
+        Duration::from_secs(self.tls_expiry_warning_days * 24 * 60 * 60)
+    }
+
+    /// Validates named and inline TLS configs, loading their secrets. Problems of all
+    /// configs are reported together, expired certificates are logged as warnings.
+    async fn validate_tls_configs(&self) -> Result<(), Error> {
+        let mut tls_configs = self
+            .tls
+            .iter()
+            .flatten()
+            .map(|(name, tls_config)| (format!("`{name}`"), tls_config))
+            .collect::<Vec<_>>();
+        tls_configs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for service in self.services() {
+            if let Some(Tls::Config(tls_config)) = &service.tls {
+                let label = format!("of service `{}`", service.hostname);
+                tls_configs.push((label, tls_config));
+            }
+        }
+        let mut errors = Vec::new();
+        for (label, tls_config) in tls_configs {
+            let report =
+                tls::validate_client_config(tls_config, self.tls_expiry_warning_period()).await;
+            for warning in report.warnings {
+                warn!("TLS config {label}: {warning}");
+            }
+            errors.extend(
+                report
+                    .errors
+                    .into_iter()
+                    .map(|error| format!("TLS config {label}: {error}")),
+            );
+        }
+        if !errors.is_empty() {
+            return Err(Error::InvalidTls(errors.join("; ")));
+        }
+        Ok(())
+    }
+
+    /// Applies named TLS configs to services.
+    fn apply_named_tls_configs(&mut self) -> Result<(), Error> {
+        if let Some(tls_configs) = &self.tls {
+            // Generation
+            if let Some(generation) = &mut self.generation {
+                apply_named_tls_config(&mut generation.service, tls_configs)?;
+            }
+            // Chat generation
+            if let Some(chat_generation) = &mut self.chat_generation {
+                apply_named_tls_config(&mut chat_generation.service, tls_configs)?;
+            }
+            // Chunkers
+            if let Some(chunkers) = &mut self.chunkers {
+                for chunker in chunkers.values_mut() {
+                    apply_named_tls_config(&mut chunker.service, tls_configs)?;
+                }
+            }
+            // Detectors
+            for detector in self.detectors.values_mut() {
+                apply_named_tls_config(&mut detector.service, tls_configs)?;
+                for backend in &mut detector.backends {
+                    apply_named_tls_config(&mut backend.service, tls_configs)?;
+                }
+                if let Some(canary) = &mut detector.canary {
+                    apply_named_tls_config(&mut canary.service, tls_configs)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies default request timeouts to services without a `request_timeout`.
+    fn apply_default_timeouts(&mut self) {
+        let request_timeout = self.defaults.request_timeout;
+        let detector_request_timeout = self.defaults.detector_request_timeout;
+        let apply = |service: &mut ServiceConfig, timeout: u64| {
+            service.request_timeout.get_or_insert(timeout);
+        };
+        if let Some(generation) = &mut self.generation {
+            apply(&mut generation.service, request_timeout);
+        }
+        if let Some(chat_generation) = &mut self.chat_generation {
+            apply(&mut chat_generation.service, request_timeout);
+            if let Some(health_service) = &mut chat_generation.health_service {
+                apply(health_service, request_timeout);
+            }
+        }
+        for chunker in self
+            .chunkers
+            .iter_mut()
+            .flat_map(|chunkers| chunkers.values_mut())
+        {
+            apply(&mut chunker.service, request_timeout);
+        }
+        for detector in self.detectors.values_mut() {
+            apply(&mut detector.service, detector_request_timeout);
+            if let Some(health_service) = &mut detector.health_service {
+                apply(health_service, detector_request_timeout);
+            }
+            for backend in &mut detector.backends {
+                apply(&mut backend.service, detector_request_timeout);
+            }
+            if let Some(canary) = &mut detector.canary {
+                apply(&mut canary.service, detector_request_timeout);
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        // Detectors are configured
+        if self.detectors.is_empty() {
+            return Err(Error::NoDetectorsConfigured);
+        }
+
+        // Downstream request limit is non-zero
+        if self.max_concurrent_requests == Some(0) {
+            return Err(Error::InvalidMaxConcurrentRequests);
+        }
+
+        // Per-task fan-out limits are non-zero, as no requests would be sent
+        if self.detector_concurrent_requests == 0 {
+            return Err(Error::InvalidConcurrentRequests(
+                "detector_concurrent_requests",
+            ));
+        }
+        if self.chunker_concurrent_requests == 0 {
+            return Err(Error::InvalidConcurrentRequests(
+                "chunker_concurrent_requests",
+            ));
+        }
+
+        // Detections filter is valid
+        self.detections_filter
+            .validate()
+            .map_err(Error::InvalidDetectionsFilter)?;
+
+        // Stream ordering buffer is non-zero
+        if self.stream_ordering.max_buffered_chunks == Some(0) {
+            return Err(Error::InvalidStreamOrdering);
+        }
+
+        // Downstream log sample rate is in (0, 1]
+        if self
+            .downstream_log_sample_rate
+            .is_some_and(|rate| !(rate > 0.0 && rate <= 1.0))
+        {
+            return Err(Error::InvalidDownstreamLogSampleRate);
+        }
+
+        // Service level objectives are valid
+        if let Some(slo) = &self.slo {
+            slo.validate().map_err(Error::InvalidSlo)?;
+        }
+
+        // Service defaults are valid
+        self.defaults.validate().map_err(Error::InvalidDefaults)?;
+
+        // Job limits are valid
+        self.jobs.validate().map_err(Error::InvalidJobs)?;
+
+        // Stream resumption is valid
+        if let Some(stream_resumption) = &self.stream_resumption {
+            stream_resumption
+                .validate()
+                .map_err(Error::InvalidStreamResumption)?;
+        }
+
+        // Response cache is valid
+        if let Some(response_cache) = &self.response_cache {
+            response_cache
+                .validate()
+                .map_err(Error::InvalidResponseCache)?;
+        }
+
+        // Alert webhooks are valid
+        if let Some(alerts) = &self.alerts {
+            alerts.validate().map_err(Error::InvalidAlerts)?;
+        }
+
+        // API keys are valid
+        if let Some(auth) = &self.auth {
+            auth.validate().map_err(Error::InvalidAuth)?;
+        }
+
+        // Spool is valid
+        if let Some(spool) = &self.spool {
+            spool.validate().map_err(Error::InvalidSpool)?;
+        }
+
+        // Retry policies and circuit breakers are valid
+        for service in self.services() {
+            if let Some(retry) = &service.retry {
+                retry.validate().map_err(|error| {
+                    Error::InvalidRetry(format!("service `{}`: {error}", service.hostname))
+                })?;
+            }
+            if let Some(circuit_breaker) = &service.circuit_breaker {
+                circuit_breaker.validate().map_err(|error| {
+                    Error::InvalidCircuitBreaker(format!("service `{}`: {error}", service.hostname))
+                })?;
+            }
+        }
+
+        // Services are allowed by the egress policy
+        if let Some(egress) = &self.egress {
+            for service in self.services() {
+                egress.validate_host(&service.hostname)?;
+            }
+            let webhook_hosts = self
+                .alerts
+                .iter()
+                .flat_map(|alerts| &alerts.webhooks)
+                .filter_map(|webhook| url::Url::parse(&webhook.url).ok())
+                .filter_map(|url| url.host_str().map(String::from));
+            for host in webhook_hosts {
+                egress.validate_host(&host)?;
+            }
+        }
+
+        // Apply validation rules
+        self.validate_generation_config()?;
+        self.validate_chat_generation_config()?;
+        self.validate_detector_configs()?;
+        self.validate_detector_groups()?;
+        self.validate_chunker_configs()?;
+
+        Ok(())
+    }
+
+    /// Returns all configured services.
+    fn services(&self) -> Vec<&ServiceConfig> {
+        let mut services = Vec::new();
+        if let Some(generation) = &self.generation {
+            services.push(&generation.service);
+        }
+        if let Some(chat_generation) = &self.chat_generation {
+            services.push(&chat_generation.service);
+            services.extend(&chat_generation.health_service);
+        }
+        for chunker in self.chunkers.iter().flat_map(|chunkers| chunkers.values()) {
+            services.push(&chunker.service);
+        }
+        for detector in self.detectors.values() {
+            services.push(&detector.service);
+            services.extend(&detector.health_service);
+            services.extend(detector.backends.iter().map(|backend| &backend.service));
+            services.extend(detector.canary.iter().map(|canary| &canary.service));
+        }
+        services
+    }
+
+    /// Validates generation config.
+    fn validate_generation_config(&self) -> Result<(), Error> {
+        if let Some(generation) = &self.generation {
+            // Hostname is valid
+            if !generation.service.has_valid_hostnames() {
+                return Err(Error::InvalidHostname(
+                    "`generation` has an invalid hostname".into(),
+                ));
+            }
+            // Host header is not set for gRPC providers, whose request authorities are
+            // set per resolved endpoint
+            if !matches!(generation.provider, GenerationProvider::OpenAi)
+                && generation.service.host_header.is_some()
+            {
+                return Err(Error::UnsupportedHostHeader("`generation`".into()));
+            }
+            // Tokenizer files exist
+            for (model_id, path) in &generation.tokenizers {
+                if !path.is_file() {
+                    return Err(Error::InvalidTokenizer(format!(
+                        "model `{model_id}` tokenizer file `{}` does not exist",
+                        path.display()
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates chat generation config.
+    fn validate_chat_generation_config(&self) -> Result<(), Error> {
+        if let Some(chat_generation) = &self.chat_generation {
+            // Hostname is valid
+            if !chat_generation.service.has_valid_hostnames() {
+                return Err(Error::InvalidHostname(
+                    "`chat_generation` has an invalid hostname".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates detector configs.
+    fn validate_detector_configs(&self) -> Result<(), Error> {
+        for (detector_id, detector) in &self.detectors {
+            // Hostname is valid
+            if !detector.service.has_valid_hostnames() {
+                return Err(Error::InvalidHostname(format!(
+                    "detector `{detector_id}` has an invalid hostname"
+                )));
+            }
+            // Chunker is valid
+            let valid_chunker = detector.chunker_id == DEFAULT_CHUNKER_ID
+                || self
+                    .chunkers
+                    .as_ref()
+                    .is_some_and(|chunkers| chunkers.contains_key(&detector.chunker_id));
+            if !valid_chunker {
+                return Err(Error::DetectorChunkerNotFound {
+                    detector_id: detector_id.clone(),
+                    chunker_id: detector.chunker_id.clone(),
+                });
+            }
+            // Endpoint path is absolute
+            if detector
+                .endpoint_path
+                .as_ref()
+                .is_some_and(|path| !path.starts_with('/'))
+            {
+                return Err(Error::InvalidEndpointPath(format!(
+                    "detector `{detector_id}` endpoint path must start with `/`"
+                )));
+            }
+            // Batch size is positive
+            if detector.max_batch_size == Some(0) {
+                return Err(Error::InvalidBatchSize(format!(
+                    "detector `{detector_id}` `max_batch_size` must be greater than 0"
+                )));
+            }
+            // Scale is positive
+            if matches!(
+                detector.score_normalization,
+                Some(ScoreNormalization::Scale { max }) if max.is_nan() || max <= 0.0
+            ) {
+                return Err(Error::InvalidScoreNormalization(format!(
+                    "detector `{detector_id}` scale `max` must be greater than 0"
+                )));
+            }
+            // Legacy formats are only mapped for text contents detectors
+            if detector.response_format != DetectorResponseFormat::Canonical
+                && detector.r#type != DetectorType::TextContents
+            {
+                return Err(Error::InvalidResponseFormat(format!(
+                    "detector `{detector_id}` response format is only applicable to text contents detectors"
+                )));
+            }
+            // Tokenizer file exists, for text contents detectors
+            if let Some(path) = &detector.tokenizer_path {
+                if detector.r#type != DetectorType::TextContents {
+                    return Err(Error::InvalidTokenizer(format!(
+                        "detector `{detector_id}` tokenizer is only applicable to text contents detectors"
+                    )));
+                }
+                if !path.is_file() {
+                    return Err(Error::InvalidTokenizer(format!(
+                        "detector `{detector_id}` tokenizer file `{}` does not exist",
+                        path.display()
+                    )));
+                }
+            }
+            // Backends have valid hostnames and unique names
+            let mut backend_names = HashSet::from([DEFAULT_BACKEND_NAME]);
+            for backend in &detector.backends {
+                if !backend_names.insert(backend.name.as_str()) {
+                    return Err(Error::InvalidBackend(format!(
+                        "detector `{detector_id}` has a duplicate or reserved backend name `{}`",
+                        backend.name
+                    )));
+                }
+                if !backend.service.has_valid_hostnames() {
+                    return Err(Error::InvalidHostname(format!(
+                        "detector `{detector_id}` backend `{}` has an invalid hostname",
+                        backend.name
+                    )));
+                }
+            }
+            // Canary has a valid hostname and traffic percentage
+            if let Some(canary) = &detector.canary {
+                if !is_valid_hostname(&canary.service.hostname) {
+                    return Err(Error::InvalidHostname(format!(
+                        "detector `{detector_id}` canary has an invalid hostname"
+                    )));
+                }
+                if canary.traffic_percent > 100 {
+                    return Err(Error::InvalidCanary(format!(
+                        "detector `{detector_id}` canary `traffic_percent` must be between 0 and 100"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates detector groups have at least two members of the same type and
+    /// chunker, each in a single group.
+    fn validate_detector_groups(&self) -> Result<(), Error> {
+        let mut grouped = HashSet::new();
+        for (group, members) in &self.detector_groups {
+            if members.len() < 2 {
+                return Err(Error::InvalidDetectorGroup(format!(
+                    "group `{group}` must have at least two members"
+                )));
+            }
+            let mut first: Option<&DetectorConfig> = None;
+            for detector_id in members {
+                let detector = self.detector(detector_id).ok_or_else(|| {
+                    Error::InvalidDetectorGroup(format!(
+                        "detector `{detector_id}` of group `{group}` not found"
+                    ))
+                })?;
+                if !grouped.insert(detector_id.as_str()) {
+                    return Err(Error::InvalidDetectorGroup(format!(
+                        "detector `{detector_id}` is listed more than once"
+                    )));
+                }
+                let first = first.get_or_insert(detector);
+                if detector.r#type != first.r#type || detector.chunker_id != first.chunker_id {
+                    return Err(Error::InvalidDetectorGroup(format!(
+                        "members of group `{group}` must have the same type and chunker"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates chunker configs.
+    fn validate_chunker_configs(&self) -> Result<(), Error> {
+        if let Some(chunkers) = &self.chunkers {
+            for (chunker_id, chunker) in chunkers {
+                // Hostname is valid
+                if !is_valid_hostname(&chunker.service.hostname) {
+                    return Err(Error::InvalidHostname(format!(
+                        "chunker `{chunker_id}` has an invalid hostname"
+                    )));
+                }
+                // Host header is not set, as chunkers are gRPC services
+                if chunker.service.host_header.is_some() {
+                    return Err(Error::UnsupportedHostHeader(format!(
+                        "chunker `{chunker_id}`"
+                    )));
+                }
+                // Chunk size bounds are valid
+                if chunker.max_chunk_size == Some(0) {
+                    return Err(Error::InvalidChunkSize(format!(
+                        "chunker `{chunker_id}` `max_chunk_size` must be greater than 0"
+                    )));
+                }
+                if chunker
+                    .min_chunk_size
+                    .zip(chunker.max_chunk_size)
+                    .is_some_and(|(min, max)| min > max)
+                {
+                    return Err(Error::InvalidChunkSize(format!(
+                        "chunker `{chunker_id}` `min_chunk_size` must not be greater than `max_chunk_size`"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get ID of chunker associated with a particular detector
+    pub fn get_chunker_id(&self, detector_id: &str) -> Option<String> {
+        self.detectors
+            .get(detector_id)
+            .map(|detector_config| detector_config.chunker_id.clone())
+    }
+
+    /// Gets a chunker config.
+    pub fn chunker(&self, chunker_id: &str) -> Option<&ChunkerConfig> {
+        if let Some(chunkers) = &self.chunkers {
+            chunkers.get(chunker_id)
+        } else {
+            None
+        }
+    }
+
+    /// Gets a detector config.
+    pub fn detector(&self, detector_id: &str) -> Option<&DetectorConfig> {
+        self.detectors.get(detector_id)
+    }
+
+    /// Gets the members of the detector group of a detector, if grouped.
+    pub fn detector_group(&self, detector_id: &str) -> Option<&[String]> {
+        self.detector_groups
+            .values()
+            .find(|members| members.iter().any(|member| member == detector_id))
+            .map(Vec::as_slice)
+    }
+
+    /// Gets the category of a detection.
+    pub fn category(
+        &self,
+        detector_id: &str,
+        detection_type: &str,
+        detection: &str,
+    ) -> Option<&str> {
+        let keys = [
+            format!("{detection_type}/{detection}"),
+            detection.into(),
+            detection_type.into(),
+        ];
+        let detector_categories = self.detector(detector_id).map(|config| &config.categories);
+        detector_categories
+            .into_iter()
+            .chain([&self.categories])
+            .find_map(|categories| keys.iter().find_map(|key| categories.get(key)))
+            .map(String::as_str)
+    }
+}
+
+impl Default for OrchestratorConfig {
+    fn default() -> Self {
+        Self {
+            generation: None,
+            chat_generation: None,
+            chunkers: None,
+            detectors: HashMap::default(),
+            defaults: DefaultsConfig::default(),
+            tls: None,
+            tls_expiry_warning_days: default_tls_expiry_warning_days(),
+            passthrough_headers: HashSet::default(),
+            generation_passthrough_headers: HashSet::default(),
+            detector_concurrent_requests: default_detector_concurrent_requests(),
+            chunker_concurrent_requests: default_chunker_concurrent_requests(),
+            max_concurrent_requests: None,
+            reasoning_detection: ReasoningDetection::default(),
+            image_parts: ImagePartsPolicy::default(),
+            flagged_choices: FlaggedChoicesPolicy::default(),
+            flagged_tools: FlaggedToolsPolicy::default(),
+            structured_output: StructuredOutputPolicy::default(),
+            request_parsing: RequestParsing::default(),
+            categories: HashMap::default(),
+            detections_filter: DetectionsFilter::default(),
+            stream_ordering: StreamOrdering::default(),
+            stream_holdback_chunks: 0,
+            downstream_log_sample_rate: None,
+            slo: None,
+            static_hosts: HashMap::default(),
+            egress: None,
+            jobs: JobsConfig::default(),
+            features: FeaturesConfig::default(),
+            stream_resumption: None,
+            response_cache: None,
+            alerts: None,
+            capture: None,
+            feedback: None,
+            spool: None,
+            detector_groups: HashMap::new(),
+        }
+    }
+}
+
+/// Applies named TLS config to a service.
+fn apply_named_tls_config(
+    service: &mut ServiceConfig,
+    tls_configs: &HashMap<String, TlsConfig>,
+) -> Result<(), Error> {
+    if let Some(Tls::Name(name)) = &service.tls {
+        let tls_config = tls_configs
+            .get(name)
+            .ok_or(Error::TlsConfigNotFound {
+                name: name.clone(),
+                host: service.hostname.clone(),
+                port: service.port.unwrap_or(0).to_string(),
+            })?
+            .clone();
+        service.tls = Some(Tls::Config(tls_config));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+impl Default for Tls {
+    fn default() -> Self {
+        Tls::Name("dummy_tls".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_config() -> Result<(), Error> {
+        let s = r#"
+generation:
+    provider: tgis
+    service:
+        hostname: localhost
+        port: 8000
+chunkers:
+    sentence-en:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+    sentence-ja:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+detectors:
+    hap-en:
+        type: text_contents
+        service:
+            hostname: localhost
+            port: 9000
+        chunker_id: sentence-en
+        default_threshold: 0.5
+tls: {}
+        "#;
+        let config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(
+            config
+                .chunkers
+                .expect("chunkers should have been configured")
+                .len()
+                == 2
+                && config.detectors.len() == 1
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_config_detector_tls_signed() -> Result<(), Error> {
+        let s = r#"
+generation:
+    provider: tgis
+    service:
+        hostname: localhost
+        port: 8000
+chunkers:
+    sentence-en:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+    sentence-ja:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: localhost
+            port: 9000
+            tls: detector
+        chunker_id: sentence-en
+        default_threshold: 0.5
+tls:
+    detector:
+        cert_path: /certs/client.pem
+        "#;
+        let config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(
+            config
+                .chunkers
+                .expect("chunkers should have been configured")
+                .len()
+                == 2
+                && config.detectors.len() == 1
+        );
+        assert!(
+            config
+                .tls
+                .as_ref()
+                .expect("tls should have been configured")
+                .len()
+                == 1
+                && config.tls.as_ref().unwrap().contains_key("detector")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_config_detector_tls_insecure() -> Result<(), Error> {
+        let s = r#"
+generation:
+    provider: tgis
+    service:
+        hostname: localhost
+        port: 8000
+chunkers:
+    sentence-en:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+    sentence-ja:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: localhost
+            port: 9000
+            tls: detector
+        chunker_id: sentence-en
+        default_threshold: 0.5
+tls:
+    detector:
+        client_ca_cert_path: /certs/ca.pem
+        cert_path: /certs/client.pem
+        key_path: /certs/client-key.pem
+        insecure: true
+        "#;
+        let config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(
+            config
+                .chunkers
+                .expect("chunkers should have been configured")
+                .len()
+                == 2
+                && config.detectors.len() == 1
+        );
+        assert!(
+            config
+                .tls
+                .as_ref()
+                .expect("tls should have been configured")
+                .len()
+                == 1
+                && config
+                    .tls
+                    .as_ref()
+                    .unwrap()
+                    .get("detector")
+                    .unwrap()
+                    .insecure
+                    == Some(true)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_config_no_detectors() {
+        let s = r#"
+generation:
+    provider: tgis
+    service:
+        hostname: localhost
+        port: 8000
+chunkers:
+    sentence-en:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+    sentence-ja:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+detectors: {}
+tls: {}
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        config
+            .apply_named_tls_configs()
+            .expect("Apply named TLS configs should have succeeded");
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::NoDetectorsConfigured))
+    }
+
+    #[test]
+    fn test_deserialize_config_tls_not_found() {
+        let s = r#"
+generation:
+    provider: tgis
+    service:
+        hostname: localhost
+        port: 8000
+chunkers:
+    sentence-en:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+    sentence-ja:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: localhost
+            port: 9000
+            tls: notadetector
+        chunker_id: sentence-en
+        default_threshold: 0.5
+tls:
+    detector:
+        client_ca_cert_path: /certs/ca.pem
+        cert_path: /certs/client.pem
+        key_path: /certs/client-key.pem
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        let error = config
+            .apply_named_tls_configs()
+            .expect_err("Apply named TLS configs should have failed");
+        assert!(matches!(error, Error::TlsConfigNotFound { .. }))
+    }
+
+    #[test]
+    fn test_deserialize_config_chunker_found() {
+        let s = r#"
+generation:
+    provider: tgis
+    service:
+        hostname: localhost
+        port: 8000
+chunkers:
+    sentence-en:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+    sentence-ja:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: localhost
+            port: 9000
+            tls: detector
+        chunker_id: sentence-fr
+        default_threshold: 0.5
+tls:
+    detector:
+        client_ca_cert_path: /certs/ca.pem
+        cert_path: /certs/client.pem
+        key_path: /certs/client-key.pem
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        config
+            .apply_named_tls_configs()
+            .expect("Apply named TLS configs should have succeeded");
+        config
+            .validate()
+            .expect_err("Config should not have been validated");
+    }
+
+    #[test]
+    fn test_passthrough_headers_empty_config() -> Result<(), Error> {
+        let s = r#"
+generation:
+    provider: tgis
+    service:
+        hostname: localhost
+        port: 8000
+chunkers:
+    sentence-en:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: localhost
+            port: 9000
+            tls: detector
+        chunker_id: sentence-fr
+        default_threshold: 0.5
+        "#;
+        let config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(config.passthrough_headers.is_empty());
+        Ok(())
+    }
+    #[test]
+    fn test_allowed_headers_passthrough_in_config() -> Result<(), Error> {
+        let s = r#"
+generation:
+    provider: tgis
+    service:
+        hostname: localhost
+        port: 8000
+chunkers:
+    sentence-en:
+        type: sentence
+        service:
+            hostname: localhost
+            port: 9000
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: localhost
+            port: 9000
+            tls: detector
+        chunker_id: sentence-fr
+        default_threshold: 0.5
+
+passthrough_headers:
+        - test-header
+generation_passthrough_headers:
+        - x-gateway-route
+        "#;
+        let config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert_eq!(
+            config.passthrough_headers,
+            HashSet::from(["test-header".to_string()])
+        );
+        assert_eq!(
+            config.generation_passthrough_headers,
+            HashSet::from(["x-gateway-route".to_string()])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_concurrent_requests_config() {
+        let s = r#"
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: localhost
+            port: 9000
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+max_concurrent_requests: 0
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert_eq!(config.max_concurrent_requests, Some(0));
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidMaxConcurrentRequests));
+
+        config.max_concurrent_requests = Some(100);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_slo_config() {
+        let s = r#"
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: localhost
+            port: 9000
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+slo:
+    default:
+        latency_ms: 2000
+        availability: 0.999
+    routes:
+        /api/v2/text/detection/content:
+            latency_ms: 500
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(config.validate().is_ok());
+        let slo = config.slo.as_mut().unwrap();
+        assert_eq!(
+            slo.objectives("/api/v2/text/detection/content"),
+            SloObjectives {
+                latency_ms: Some(500),
+                availability: Some(0.999),
+            }
+        );
+        assert_eq!(
+            slo.objectives("/api/v2/chat/completions-detection"),
+            slo.default
+        );
+
+        slo.default.availability = Some(1.0);
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidSlo(_)));
+    }
+
+    #[test]
+    fn test_dns_config() {
+        let s = r#"
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: hap.localhost
+            grpc_dns_probe_interval: 30
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+    pii:
+        type: text_contents
+        service:
+            hostname: pii.localhost
+            dns_probe_interval: 5
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+static_hosts:
+    hap.localhost:
+        - 10.0.0.1
+        - 10.0.0.2
+        "#;
+        let config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert_eq!(config.detectors["hap"].service.dns_probe_interval, Some(30));
+        assert_eq!(config.detectors["pii"].service.dns_probe_interval, Some(5));
+        assert_eq!(
+            config.static_hosts["hap.localhost"],
+            [
+                "10.0.0.1".parse::<IpAddr>().unwrap(),
+                "10.0.0.2".parse().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_service_hostname_overrides() {
+        let s = r#"
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: 10.0.0.1
+            sni_hostname: hap.example.com
+            host_header: hap.example.com
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(config.validate().is_ok());
+
+        let service = &mut config.detectors.get_mut("hap").unwrap().service;
+        service.sni_hostname = Some("-hap.example.com".into());
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidHostname(_)));
+    }
+
+    #[test]
+    fn test_grpc_host_header() {
+        let s = r#"
+generation:
+    provider: tgis
+    service:
+        hostname: 10.0.0.1
+        host_header: tgis.example.com
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: localhost
+        chunker_id: sentence_chunker
+        default_threshold: 0.5
+chunkers:
+    sentence_chunker:
+        type: sentence
+        service:
+            hostname: 10.0.0.2
+            host_header: chunker.example.com
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::UnsupportedHostHeader(_)));
+
+        let generation = config.generation.as_mut().unwrap();
+        generation.service.host_header = None;
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::UnsupportedHostHeader(_)));
+
+        let chunkers = config.chunkers.as_mut().unwrap();
+        chunkers
+            .get_mut("sentence_chunker")
+            .unwrap()
+            .service
+            .host_header = None;
+        assert!(config.validate().is_ok());
+
+        let generation = config.generation.as_mut().unwrap();
+        generation.provider = GenerationProvider::OpenAi;
+        generation.service.host_header = Some("openai.example.com".into());
+        assert!(config.validate().is_ok());
     }
-    Ok(())
-}
 
-#[cfg(test)]
-impl Default for Tls {
-    fn default() -> Self {
-        Tls::Name("dummy_tls".to_string())
+    #[test]
+    fn test_retry_config() {
+        let s = r#"
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: hap.example.com
+            retry:
+                max_attempts: 5
+                retriable_status_codes: [429, 503]
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(config.validate().is_ok());
+        let retry = config.detectors["hap"].service.retry.clone().unwrap();
+        assert_eq!(
+            retry,
+            RetryConfig {
+                max_attempts: 5,
+                retriable_status_codes: vec![429, 503],
+                ..Default::default()
+            }
+        );
+
+        let retry = config
+            .detectors
+            .get_mut("hap")
+            .unwrap()
+            .service
+            .retry
+            .as_mut()
+            .unwrap();
+        retry.retriable_status_codes = vec![200];
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidRetry(_)));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_egress_config() {
+        let s = r#"
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: hap.detectors.svc.cluster.local
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+    pii:
+        type: text_contents
+        service:
+            hostname: 10.1.2.3
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+egress:
+    hosts:
+        - "*.svc.cluster.local"
+    cidrs:
+        - 10.0.0.0/8
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(config.validate().is_ok());
+        let egress = config.egress.as_mut().unwrap();
+        assert!(!egress.allows_host("svc.cluster.local"));
+        assert!(egress.allows_host("HAP.Detectors.SVC.cluster.local"));
+        egress.hosts.push("*.Example.COM".into());
+        assert!(egress.allows_host("hap.example.com"));
+        assert!(!egress.allows_host("example.com"));
+        assert!(!egress.allows_addr("192.168.0.1".parse().unwrap()));
+
+        egress.cidrs.clear();
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::EgressNotAllowed(host) if host == "10.1.2.3"));
+
+        assert!(serde_yml::from_str::<Cidr>("10.0.0.0/33").is_err());
+    }
 
     #[test]
-    fn test_deserialize_config() -> Result<(), Error> {
+    fn test_defaults_config() {
         let s = r#"
 generation:
     provider: tgis
     service:
         hostname: localhost
-        port: 8000
 chunkers:
-    sentence-en:
+    sentence:
         type: sentence
         service:
             hostname: localhost
-            port: 9000
-    sentence-ja:
-        type: sentence
+            request_timeout: 30
+detectors:
+    hap:
+        type: text_contents
         service:
             hostname: localhost
-            port: 9000
+        chunker_id: sentence
+        default_threshold: 0.5
+defaults:
+    detector_request_timeout: 10
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        config.apply_default_timeouts();
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.generation.as_ref().unwrap().service.request_timeout,
+            Some(600)
+        );
+        assert_eq!(
+            config.chunkers.as_ref().unwrap()["sentence"]
+                .service
+                .request_timeout,
+            Some(30)
+        );
+        assert_eq!(config.detectors["hap"].service.request_timeout, Some(10));
+
+        config.defaults.detector_request_timeout = 0;
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidDefaults(_)));
+    }
+
+    #[test]
+    fn test_jobs_config() {
+        let s = r#"
 detectors:
-    hap-en:
+    hap:
         type: text_contents
         service:
             hostname: localhost
-            port: 9000
-        chunker_id: sentence-en
+        chunker_id: whole_doc_chunker
         default_threshold: 0.5
-tls: {}
+jobs:
+    max_concurrent: 2
+    callbacks:
+        hosts:
+            - "*.example.com"
+        cidrs:
+            - 10.0.0.0/8
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.jobs.max_queued, 100);
+        assert_eq!(config.jobs.result_ttl, 3600);
+        assert!(config.jobs.allows_callback("hooks.example.com"));
+        assert!(config.jobs.allows_callback("10.1.2.3"));
+        assert!(!config.jobs.allows_callback("localhost"));
+
+        config.jobs.max_concurrent = 0;
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidJobs(_)));
+        assert!(!JobsConfig::default().allows_callback("hooks.example.com"));
+    }
+
+    #[test]
+    fn test_auth_config() {
+        let s = r#"
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: localhost
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+auth:
+    api_keys:
+        team-a:
+            env: TEAM_A_API_KEY
+        team-b:
+            file: /etc/guardrails/team-b.key
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(config.validate().is_ok());
+        let auth = config.auth.as_mut().unwrap();
+        assert_eq!(
+            auth.api_keys["team-a"],
+            SecretSource::Env("TEAM_A_API_KEY".into())
+        );
+
+        auth.api_keys.clear();
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidAuth(_)));
+    }
+
+    #[test]
+    fn test_jwt_config() {
+        let s = r#"
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: localhost
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+auth:
+    jwt:
+        issuer: https://keycloak.example.com/realms/guardrails
+        audiences:
+            - guardrails
+        claim_headers:
+            tenant: x-tenant-id
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(config.validate().is_ok());
+        let jwt = config.auth.as_mut().unwrap().jwt.as_mut().unwrap();
+        assert_eq!(jwt.jwks_refresh_interval, 300);
+        assert_eq!(jwt.consumer_claim, "azp");
+        assert_eq!(jwt.algorithms, default_jwt_algorithms());
+
+        jwt.algorithms.push(jsonwebtoken::Algorithm::HS256);
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidAuth(_)));
+
+        let jwt = config.auth.as_mut().unwrap().jwt.as_mut().unwrap();
+        jwt.algorithms = default_jwt_algorithms();
+        jwt.claim_headers
+            .insert("role".into(), "invalid header".into());
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidAuth(_)));
+    }
+
+    #[test]
+    fn test_otlp_config() {
+        let s = r#"
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: localhost
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+otlp:
+    export:
+        - traces
+        - logs
+    protocol: http
+    headers:
+        authorization: Bearer token
+    baggage:
+        - tenant
         "#;
         let config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
-        assert!(
-            config
-                .chunkers
-                .expect("chunkers should have been configured")
-                .len()
-                == 2
-                && config.detectors.len() == 1
+        assert_eq!(
+            config.otlp,
+            Some(OtlpConfig {
+                export: vec![OtlpExport::Traces, OtlpExport::Logs],
+                endpoint: None,
+                protocol: OtlpProtocol::Http,
+                headers: HashMap::from([("authorization".into(), "Bearer token".into())]),
+                baggage: vec!["tenant".into()],
+            })
         );
-        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_load_otlp() {
+        let path =
+            std::env::temp_dir().join(format!("{}-config.yaml", uuid::Uuid::new_v4().simple()));
+        tokio::fs::write(&path, "otlp:\n    export: [traces]\n    protocol: http\n")
+            .await
+            .unwrap();
+        let otlp = OrchestratorConfig::load_otlp(&path).await.unwrap();
+        assert_eq!(otlp.map(|otlp| otlp.protocol), Some(OtlpProtocol::Http));
+
+        tokio::fs::write(&path, "otlp:\n    export: [spans]\n")
+            .await
+            .unwrap();
+        let error = OrchestratorConfig::load_otlp(&path)
+            .await
+            .expect_err("OTLP config should not have been loaded");
+        assert!(matches!(error, Error::InvalidConfigFile(_)));
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        let error = OrchestratorConfig::load_otlp(&path)
+            .await
+            .expect_err("OTLP config should not have been loaded");
+        assert!(matches!(error, Error::FailedToReadConfigFile { .. }));
     }
 
     #[test]
-    fn test_deserialize_config_detector_tls_signed() -> Result<(), Error> {
+    fn test_alerts_config() {
         let s = r#"
-generation:
-    provider: tgis
-    service:
-        hostname: localhost
-        port: 8000
-chunkers:
-    sentence-en:
-        type: sentence
+detectors:
+    hap:
+        type: text_contents
         service:
             hostname: localhost
-            port: 9000
-    sentence-ja:
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+alerts:
+    min_score: 0.9
+    webhooks:
+        - url: https://alerts.example.com/guardrails
+          secret:
+              env: ALERTS_SECRET
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(config.validate().is_ok());
+        let alerts = config.alerts.as_mut().unwrap();
+        assert_eq!(alerts.max_retries, 3);
+        assert_eq!(
+            alerts.webhooks[0].secret,
+            Some(SecretSource::Env("ALERTS_SECRET".into()))
+        );
+
+        alerts.webhooks[0].url = "ftp://alerts.example.com".into();
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidAlerts(_)));
+    }
+
+    #[tokio::test]
+    async fn test_capture_config() {
+        let s = r#"
+path: /tmp/capture.jsonl
+tenants:
+    acme: 0.1
+        "#;
+        let mut capture: CaptureConfig = serde_yml::from_str(s).unwrap();
+        assert!(capture.validate().await.is_ok());
+        assert_eq!(capture.tenant_header, "x-tenant-id");
+        assert_eq!(capture.sample_rate("acme"), 0.1);
+        assert_eq!(capture.sample_rate("other"), 0.0);
+
+        capture.redact_patterns.push("(unclosed".into());
+        assert!(capture.validate().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_capture_encryption_config() {
+        let path =
+            std::env::temp_dir().join(format!("{}-tenant-key", uuid::Uuid::new_v4().simple()));
+        let s = format!(
+            r#"
+path: /tmp/capture.jsonl
+tenants:
+    acme: 0.1
+    globex: 0.1
+encryption:
+    tenant_keys:
+        acme:
+            key_id: acme-2026-10
+            key:
+                file: {}
+        "#,
+            path.display()
+        );
+        let mut capture: CaptureConfig = serde_yml::from_str(&s).unwrap();
+        std::fs::write(&path, STANDARD.encode([7u8; 32])).unwrap();
+        let error = capture
+            .validate()
+            .await
+            .expect_err("Config should not have been validated");
+        assert_eq!(error, "no encryption key configured for tenant `globex`");
+
+        capture.tenants.remove("globex");
+        assert!(capture.validate().await.is_ok());
+
+        // Keys that cannot be loaded or are not 256 bits are rejected on start-up
+        let tenant_keys = &mut capture.encryption.as_mut().unwrap().tenant_keys;
+        tenant_keys.get_mut("acme").unwrap().key =
+            SecretSource::File(path.with_extension("missing"));
+        assert!(capture.validate().await.is_err());
+        let short_path = path.with_extension("short");
+        std::fs::write(&short_path, STANDARD.encode([7u8; 16])).unwrap();
+        let tenant_keys = &mut capture.encryption.as_mut().unwrap().tenant_keys;
+        tenant_keys.get_mut("acme").unwrap().key = SecretSource::File(short_path.clone());
+        let error = capture
+            .validate()
+            .await
+            .expect_err("Config should not have been validated");
+        assert!(error.contains("must be a base64-encoded 256-bit key"));
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(short_path).unwrap();
+    }
+
+    #[test]
+    fn test_detector_groups_config() {
+        let s = r#"
+detectors:
+    hap-a:
+        type: text_contents
+        service:
+            hostname: localhost
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+    hap-b:
+        type: text_contents
+        service:
+            hostname: localhost
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.6
+    pii:
+        type: text_contents
+        service:
+            hostname: localhost
+        chunker_id: sentence_chunker
+        default_threshold: 0.5
+chunkers:
+    sentence_chunker:
         type: sentence
         service:
             hostname: localhost
-            port: 9000
+detector_groups:
+    hap: [hap-a, hap-b]
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.detector_group("hap-b"),
+            Some(["hap-a".to_string(), "hap-b".to_string()].as_slice())
+        );
+        assert_eq!(config.detector_group("pii"), None);
+
+        config
+            .detector_groups
+            .insert("mixed".into(), vec!["pii".into(), "unknown".into()]);
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidDetectorGroup(_)));
+
+        config
+            .detector_groups
+            .insert("mixed".into(), vec!["pii".into(), "hap-a".into()]);
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidDetectorGroup(_)));
+    }
+
+    #[test]
+    fn test_spool_config() {
+        let s = r#"
 detectors:
     hap:
         type: text_contents
         service:
             hostname: localhost
-            port: 9000
-            tls: detector
-        chunker_id: sentence-en
+        chunker_id: whole_doc_chunker
         default_threshold: 0.5
-tls:
-    detector:
-        cert_path: /certs/client.pem
+spool:
+    dir: /var/spool/guardrails
+    overflow: drop_oldest
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(config.validate().is_ok());
+        let spool = config.spool.as_mut().unwrap();
+        assert_eq!(spool.max_bytes, 100 * 1024 * 1024);
+        assert_eq!(spool.overflow, SpoolOverflow::DropOldest);
+        assert_eq!(spool.retry_interval, 5);
+        assert!(!spool.backpressure);
+
+        spool.retry_interval = 0;
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidSpool(_)));
+    }
+
+    #[test]
+    fn test_generation_pacing_config() {
+        let s = r#"
+generation:
+    provider: tgis
+    service:
+        hostname: localhost
+    pacing:
+        stall_timeout: 60
+detectors: {}
         "#;
         let config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
-        assert!(
-            config
-                .chunkers
-                .expect("chunkers should have been configured")
-                .len()
-                == 2
-                && config.detectors.len() == 1
-        );
-        assert!(
-            config
-                .tls
-                .as_ref()
-                .expect("tls should have been configured")
-                .len()
-                == 1
-                && config.tls.as_ref().unwrap().contains_key("detector")
+        assert_eq!(
+            config.generation.unwrap().pacing,
+            Some(GenerationPacing {
+                buffer_size: 16,
+                stall_timeout: Some(60),
+            })
         );
-        Ok(())
     }
 
     #[test]
-    fn test_deserialize_config_detector_tls_insecure() -> Result<(), Error> {
+    fn test_generation_tokenizers_config() {
         let s = r#"
 generation:
     provider: tgis
     service:
         hostname: localhost
-        port: 8000
-chunkers:
-    sentence-en:
-        type: sentence
-        service:
-            hostname: localhost
-            port: 9000
-    sentence-ja:
-        type: sentence
+    tokenizers:
+        granite: /nonexistent/tokenizer.json
+detectors: {}
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidTokenizer(_)));
+
+        config
+            .generation
+            .as_mut()
+            .unwrap()
+            .tokenizers
+            .insert("granite".into(), "Cargo.toml".into());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_downstream_log_sample_rate_config() {
+        let s = r#"
+detectors:
+    hap:
+        type: text_contents
         service:
             hostname: localhost
             port: 9000
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+downstream_log_sample_rate: 1.5
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert_eq!(config.downstream_log_sample_rate, Some(1.5));
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidDownstreamLogSampleRate));
+
+        config.downstream_log_sample_rate = Some(0.0);
+        assert!(config.validate().is_err());
+
+        config.downstream_log_sample_rate = Some(0.1);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_reasoning_detection() {
+        assert_eq!(ReasoningDetection::Content.fields(true), (true, false));
+        assert_eq!(ReasoningDetection::Reasoning.fields(true), (false, true));
+        assert_eq!(ReasoningDetection::Both.fields(true), (true, true));
+        // Messages without reasoning content fall back to content
+        assert_eq!(ReasoningDetection::Reasoning.fields(false), (true, false));
+
+        let message = openai::Message {
+            role: openai::Role::Assistant,
+            content: Some("answer".into()),
+            reasoning_content: Some("thinking".into()),
+            ..Default::default()
+        };
+        let mut content_only = message.clone();
+        ReasoningDetection::Content.apply(&mut content_only);
+        assert!(content_only.content.is_some() && content_only.reasoning_content.is_none());
+        let mut reasoning_only = message.clone();
+        ReasoningDetection::Reasoning.apply(&mut reasoning_only);
+        assert!(reasoning_only.content.is_none() && reasoning_only.reasoning_content.is_some());
+        let mut both = message.clone();
+        ReasoningDetection::Both.apply(&mut both);
+        assert_eq!(both, message);
+
+        let config: OrchestratorConfig = serde_yml::from_str(
+            r#"
+detectors: {}
+reasoning_detection: both
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.reasoning_detection, ReasoningDetection::Both);
+    }
+
+    #[test]
+    fn test_detector_endpoint_path_config() {
+        let s = r#"
 detectors:
     hap:
         type: text_contents
         service:
             hostname: localhost
             port: 9000
-            tls: detector
-        chunker_id: sentence-en
+        chunker_id: whole_doc_chunker
         default_threshold: 0.5
-tls:
-    detector:
-        client_ca_cert_path: /certs/ca.pem
-        cert_path: /certs/client.pem
-        key_path: /certs/client-key.pem
-        insecure: true
+        endpoint_path: guard/hap/api/v1/text/contents
         "#;
-        let config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
-        assert!(
-            config
-                .chunkers
-                .expect("chunkers should have been configured")
-                .len()
-                == 2
-                && config.detectors.len() == 1
-        );
-        assert!(
-            config
-                .tls
-                .as_ref()
-                .expect("tls should have been configured")
-                .len()
-                == 1
-                && config
-                    .tls
-                    .as_ref()
-                    .unwrap()
-                    .get("detector")
-                    .unwrap()
-                    .insecure
-                    == Some(true)
-        );
-        Ok(())
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidEndpointPath(_)));
+
+        config.detectors.get_mut("hap").unwrap().endpoint_path =
+            Some("/guard/hap/api/v1/text/contents".into());
+        assert!(config.validate().is_ok());
     }
 
     #[test]
-    fn test_deserialize_config_no_detectors() {
+    fn test_detector_backends_config() {
         let s = r#"
-generation:
-    provider: tgis
-    service:
-        hostname: localhost
-        port: 8000
-chunkers:
-    sentence-en:
-        type: sentence
+detectors:
+    hap:
+        type: text_contents
         service:
-            hostname: localhost
+            hostname: hap-gpu
             port: 9000
-    sentence-ja:
-        type: sentence
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+        backends:
+            - name: cpu
+              service:
+                  hostname: hap-cpu
+                  port: 9000
+            - name: cpu
+              service:
+                  hostname: hap-cpu-2
+                  port: 9000
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidBackend(_)));
+
+        let backends = &mut config.detectors.get_mut("hap").unwrap().backends;
+        backends[1].name = DEFAULT_BACKEND_NAME.into();
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidBackend(_)));
+
+        let backends = &mut config.detectors.get_mut("hap").unwrap().backends;
+        backends[1].name = "cpu-2".into();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_detector_canary_config() {
+        let s = r#"
+detectors:
+    hap:
+        type: text_contents
         service:
-            hostname: localhost
+            hostname: hap-v1
             port: 9000
-detectors: {}
-tls: {}
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+        canary:
+            service:
+                hostname: hap-v2
+                port: 9000
+            traffic_percent: 120
         "#;
         let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
-        config
-            .apply_named_tls_configs()
-            .expect("Apply named TLS configs should have succeeded");
+        let canary = config.detectors["hap"].canary.as_ref().unwrap();
+        assert_eq!(canary.mode, CanaryMode::Shadow);
         let error = config
             .validate()
             .expect_err("Config should not have been validated");
-        assert!(matches!(error, Error::NoDetectorsConfigured))
+        assert!(matches!(error, Error::InvalidCanary(_)));
+
+        let canary = config.detectors.get_mut("hap").unwrap().canary.as_mut();
+        canary.unwrap().traffic_percent = 10;
+        assert!(config.validate().is_ok());
     }
 
     #[test]
-    fn test_deserialize_config_tls_not_found() {
+    fn test_chunk_size_config() {
         let s = r#"
-generation:
-    provider: tgis
-    service:
-        hostname: localhost
-        port: 8000
 chunkers:
     sentence-en:
         type: sentence
         service:
             hostname: localhost
             port: 9000
-    sentence-ja:
-        type: sentence
-        service:
-            hostname: localhost
-            port: 9000
+        min_chunk_size: 200
+        max_chunk_size: 100
 detectors:
     hap:
         type: text_contents
         service:
             hostname: localhost
             port: 9000
-            tls: notadetector
         chunker_id: sentence-en
         default_threshold: 0.5
-tls:
-    detector:
-        client_ca_cert_path: /certs/ca.pem
-        cert_path: /certs/client.pem
-        key_path: /certs/client-key.pem
         "#;
         let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
         let error = config
-            .apply_named_tls_configs()
-            .expect_err("Apply named TLS configs should have failed");
-        assert!(matches!(error, Error::TlsConfigNotFound { .. }))
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidChunkSize(_)));
+
+        let chunker = config.chunkers.as_mut().unwrap().get_mut("sentence-en");
+        chunker.unwrap().min_chunk_size = Some(20);
+        assert!(config.validate().is_ok());
     }
 
     #[test]
-    fn test_deserialize_config_chunker_found() {
+    fn test_batch_size_config() {
         let s = r#"
-generation:
-    provider: tgis
-    service:
-        hostname: localhost
-        port: 8000
-chunkers:
-    sentence-en:
-        type: sentence
-        service:
-            hostname: localhost
-            port: 9000
-    sentence-ja:
-        type: sentence
-        service:
-            hostname: localhost
-            port: 9000
 detectors:
     hap:
         type: text_contents
         service:
             hostname: localhost
             port: 9000
-            tls: detector
-        chunker_id: sentence-fr
+        chunker_id: whole_doc_chunker
         default_threshold: 0.5
-tls:
-    detector:
-        client_ca_cert_path: /certs/ca.pem
-        cert_path: /certs/client.pem
-        key_path: /certs/client-key.pem
+        max_batch_size: 0
         "#;
         let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
-        config
-            .apply_named_tls_configs()
-            .expect("Apply named TLS configs should have succeeded");
-        config
+        let error = config
             .validate()
             .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidBatchSize(_)));
+
+        config.detectors.get_mut("hap").unwrap().max_batch_size = Some(8);
+        assert!(config.validate().is_ok());
     }
 
     #[test]
-    fn test_passthrough_headers_empty_config() -> Result<(), Error> {
+    fn test_score_normalization_config() {
         let s = r#"
-generation:
-    provider: tgis
-    service:
-        hostname: localhost
-        port: 8000
-chunkers:
-    sentence-en:
-        type: sentence
+detectors:
+    hap:
+        type: text_contents
         service:
             hostname: localhost
             port: 9000
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+        score_normalization:
+            rule: scale
+            max: 100
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(config.validate().is_ok());
+        let normalization = config.detectors["hap"].score_normalization.unwrap();
+        assert_eq!(normalization.apply(87.0), 0.87);
+        assert_eq!(normalization.apply(120.0), 1.0);
+        assert_eq!(ScoreNormalization::Clamp.apply(-0.5), 0.0);
+
+        config.detectors.get_mut("hap").unwrap().score_normalization =
+            Some(ScoreNormalization::Scale { max: 0.0 });
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidScoreNormalization(_)));
+    }
+
+    #[test]
+    fn test_response_format_config() {
+        let s = r#"
 detectors:
-    hap:
+    legacy_pii:
         type: text_contents
         service:
             hostname: localhost
             port: 9000
-            tls: detector
-        chunker_id: sentence-fr
+        chunker_id: whole_doc_chunker
         default_threshold: 0.5
+        response_format: legacy_token_classification
         "#;
-        let config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
-        assert!(config.passthrough_headers.is_empty());
-        Ok(())
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.detectors["legacy_pii"].response_format,
+            DetectorResponseFormat::LegacyTokenClassification
+        );
+
+        config.detectors.get_mut("legacy_pii").unwrap().r#type = DetectorType::TextChat;
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidResponseFormat(_)));
     }
+
     #[test]
-    fn test_allowed_headers_passthrough_in_config() -> Result<(), Error> {
+    fn test_tokenizer_config() {
         let s = r#"
-generation:
-    provider: tgis
-    service:
-        hostname: localhost
-        port: 8000
-chunkers:
-    sentence-en:
-        type: sentence
+detectors:
+    token_pii:
+        type: text_contents
         service:
             hostname: localhost
             port: 9000
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+        tokenizer_path: /nonexistent/tokenizer.json
+        "#;
+        let mut config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidTokenizer(_)));
+
+        config
+            .detectors
+            .get_mut("token_pii")
+            .unwrap()
+            .tokenizer_path = Some("Cargo.toml".into());
+        assert!(config.validate().is_ok());
+        config.detectors.get_mut("token_pii").unwrap().r#type = DetectorType::TextGeneration;
+        let error = config
+            .validate()
+            .expect_err("Config should not have been validated");
+        assert!(matches!(error, Error::InvalidTokenizer(_)));
+    }
+
+    #[test]
+    fn test_categories_config() {
+        let s = r#"
 detectors:
     hap:
         type: text_contents
         service:
             hostname: localhost
             port: 9000
-            tls: detector
-        chunker_id: sentence-fr
+        chunker_id: whole_doc_chunker
         default_threshold: 0.5
-
-passthrough_headers:
-        - test-header
+        categories:
+            has_HAP: hate
+    pii:
+        type: text_contents
+        service:
+            hostname: localhost
+            port: 9001
+        chunker_id: whole_doc_chunker
+        default_threshold: 0.5
+categories:
+    has_HAP: toxicity
+    pii/email: contact_info
+    pii: pii
         "#;
         let config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
-        assert_eq!(
-            config.passthrough_headers,
-            HashSet::from(["test-header".to_string()])
-        );
-        Ok(())
+        // Detector categories take precedence
+        assert_eq!(config.category("hap", "hap", "has_HAP"), Some("hate"));
+        assert_eq!(config.category("other", "hap", "has_HAP"), Some("toxicity"));
+        // Most specific key wins
+        assert_eq!(config.category("pii", "pii", "email"), Some("contact_info"));
+        assert_eq!(config.category("pii", "pii", "phone"), Some("pii"));
+        assert_eq!(config.category("pii", "secrets", "api_key"), None);
     }
 }
@@ -1,13 +1,25 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    sync::{LazyLock, Mutex, OnceLock},
+};
 
 use axum::http::StatusCode;
+use opentelemetry::{KeyValue, global, metrics::ObservableGauge};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    clients::errors::grpc_to_http_code,
+    clients::{self, errors::grpc_to_http_code},
     pb::grpc::health::v1::{HealthCheckResponse, health_check_response::ServingStatus},
 };
 
+/// Latest health status of client services by service, 1 if healthy, 0 if unhealthy
+/// and -1 if unknown.
+static CLIENT_HEALTH: LazyLock<Mutex<BTreeMap<String, i64>>> = LazyLock::new(Default::default);
+
+/// Gauge reporting [`CLIENT_HEALTH`], registered with the first health check.
+static CLIENT_HEALTH_GAUGE: OnceLock<ObservableGauge<i64>> = OnceLock::new();
+
 /// Health status determined for or returned by a client service.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
@@ -71,6 +83,35 @@ impl HealthCheckCache {
             .iter()
             .any(|(_, value)| matches!(value.status, HealthStatus::Unhealthy))
     }
+
+    /// Records the health status of each service in the `client_health` gauge.
+    pub fn record(&self) {
+        CLIENT_HEALTH_GAUGE.get_or_init(|| {
+            global::meter("fms-guardrails-orchestr8")
+                .i64_observable_gauge("client_health")
+                .with_description(
+                    "Latest health status of client services, 1 if healthy, 0 if unhealthy and -1 if unknown",
+                )
+                .with_callback(|observer| {
+                    for (service, status) in CLIENT_HEALTH.lock().unwrap().iter() {
+                        observer.observe(*status, &[KeyValue::new("service", service.clone())]);
+                    }
+                })
+                .build()
+        });
+        *CLIENT_HEALTH.lock().unwrap() = self
+            .0
+            .iter()
+            .map(|(service, result)| {
+                let status = match result.status {
+                    HealthStatus::Healthy => 1,
+                    HealthStatus::Unhealthy => 0,
+                    HealthStatus::Unknown => -1,
+                };
+                (service.clone(), status)
+            })
+            .collect();
+    }
 }
 
 impl std::ops::Deref for HealthCheckCache {
@@ -147,6 +188,16 @@ impl From<Result<tonic::Response<HealthCheckResponse>, tonic::Status>> for Healt
     }
 }
 
+impl From<clients::Error> for HealthCheckResult {
+    fn from(error: clients::Error) -> Self {
+        Self {
+            status: HealthStatus::Unhealthy,
+            code: error.status_code(),
+            reason: Some(error.to_string()),
+        }
+    }
+}
+
 /// An optional response body that can be interpreted from an HTTP health check response.
 /// This is a minimal contract that allows HTTP health requests to opt in to more detailed health check responses than just the status code.
 /// If the body omitted, the health check response is considered successful if the status code is `HTTP 200 OK`.
@@ -17,7 +17,9 @@
 
 #![allow(clippy::iter_kv_map, clippy::enum_variant_names, async_fn_in_trait)]
 
+pub mod api;
 pub mod args;
+pub mod bench;
 pub mod clients;
 pub mod config;
 pub mod health;
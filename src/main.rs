@@ -15,11 +15,18 @@
 
 */
 
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
 
 use clap::Parser;
 use fms_guardrails_orchestr8::{
-    args::Args, config::OrchestratorConfig, orchestrator::Orchestrator, server, utils,
+    args::{Args, Command, TracingConfig},
+    bench::{self, BenchConfig},
+    config::OrchestratorConfig,
+    orchestrator::Orchestrator,
+    server, utils,
 };
 use tracing::info;
 
@@ -35,6 +42,12 @@ fn main() -> Result<(), anyhow::Error> {
     if args.tls_client_ca_cert_path.is_some() && args.tls_cert_path.is_none() {
         panic!("tls: cannot provide client ca cert without keypair")
     }
+    if args.tls_client_crl_path.is_some() && args.tls_client_ca_cert_path.is_none() {
+        panic!("tls: cannot provide client crl without client ca cert")
+    }
+    if args.otlp_client_key_path.is_some() != args.otlp_client_cert_path.is_some() {
+        panic!("otlp tls: must provide both client cert and key")
+    }
 
     let http_addr: SocketAddr =
         SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), args.http_port);
@@ -47,23 +60,60 @@ fn main() -> Result<(), anyhow::Error> {
         .build()
         .unwrap()
         .block_on(async {
-            let trace_shutdown = utils::trace::init_tracing(args.clone().into())?;
+            let mut tracing_config = TracingConfig::from(args.clone());
+            if !args.bench_mode {
+                if let Some(otlp) = OrchestratorConfig::load_otlp(&args.config_path).await? {
+                    tracing_config = tracing_config.with_otlp_config(otlp);
+                }
+            }
+            let trace_shutdown = utils::trace::init_tracing(tracing_config)?;
+            if args.bench_mode {
+                let report = bench::run(BenchConfig {
+                    qps: args.bench_qps,
+                    duration: Duration::from_secs(args.bench_duration),
+                    n_detectors: args.bench_detectors,
+                    guardrails_addr: http_addr,
+                    health_addr: health_http_addr,
+                })
+                .await?;
+                info!("bench mode completed\n{report}");
+                return Ok(trace_shutdown()?);
+            }
             let config = OrchestratorConfig::load(args.config_path).await?;
+            if let Some(Command::SelfTest { model_id }) = &args.command {
+                let orchestrator = Orchestrator::new(config, false).await?;
+                let report = orchestrator.self_test(model_id.as_deref()).await;
+                orchestrator.shutdown().await;
+                println!("{report}");
+                trace_shutdown()?;
+                if !report.passed() {
+                    anyhow::bail!("self-test failed");
+                }
+                return Ok(());
+            }
+            let tls_expiry_warning_period = config.tls_expiry_warning_period();
             let orchestrator = Orchestrator::new(config, args.start_up_health_check).await?;
 
             let (health_handle, guardrails_handle) = server::run(
                 http_addr,
                 health_http_addr,
-                args.tls_cert_path,
-                args.tls_key_path,
-                args.tls_client_ca_cert_path,
-                orchestrator,
+                server::ServerTlsConfig {
+                    cert_path: args.tls_cert_path,
+                    key_path: args.tls_key_path,
+                    client_ca_cert_path: args.tls_client_ca_cert_path,
+                    client_crl_path: args.tls_client_crl_path,
+                    reload_interval: args.tls_reload_interval.map(Duration::from_secs),
+                    expiry_warning_period: tls_expiry_warning_period,
+                },
+                args.admin_token,
+                orchestrator.clone(),
             )
             .await
             .unwrap_or_else(|e| panic!("failed to run server: {e}"));
 
             // Await server shutdown
             let _ = tokio::join!(health_handle, guardrails_handle);
+            orchestrator.shutdown().await;
             info!("shutdown complete");
 
             Ok(trace_shutdown()?)
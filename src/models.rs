@@ -27,17 +27,34 @@ use crate::{
         detector::{ContentAnalysisResponse, ContextType},
         openai::{Content, ContentType},
     },
+    config::{DetectionsFilter, DetectorType},
     health::HealthCheckCache,
     pb,
+    utils::json::deserialize_score,
 };
 
 pub const THRESHOLD_PARAM: &str = "threshold";
+pub const CHUNKER_ID_PARAM: &str = "chunker_id";
 
 #[derive(Clone, Debug, Serialize)]
 pub struct InfoResponse {
     pub services: HealthCheckCache,
 }
 
+/// Detectors configured on the orchestrator.
+#[derive(Clone, Debug, Serialize)]
+pub struct DetectorsResponse {
+    pub detectors: Vec<DetectorInfo>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DetectorInfo {
+    pub detector_id: String,
+    #[serde(rename = "type")]
+    pub r#type: DetectorType,
+    pub default_threshold: f64,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct InfoParams {
     /// Whether to probe the client services' health checks or just return the latest health status.
@@ -45,6 +62,52 @@ pub struct InfoParams {
     pub probe: bool,
 }
 
+/// Query parameters for dry run requests.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DryRunParams {
+    /// Whether to run input detection only, reporting detections without
+    /// performing generation or enforcing actions
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Query parameters for requests returning processing metadata.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DebugParams {
+    /// Whether to include a `debug` block with processing metadata in the response
+    #[serde(default)]
+    pub debug: bool,
+}
+
+/// Query parameters shaping detections returned in responses.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DetectionsParams {
+    /// Minimum score of returned detections, overrides the configured value
+    pub min_score: Option<f64>,
+    /// Maximum number of returned detections per category, overrides the configured value
+    pub top_k: Option<usize>,
+    /// Whether to ignore the configured filter and return all detections
+    #[serde(default)]
+    pub raw: bool,
+}
+
+impl DetectionsParams {
+    /// Returns the detections filter for the request, falling back to `default`.
+    pub fn filter(&self, default: DetectionsFilter) -> Result<DetectionsFilter, ValidationError> {
+        let default = if self.raw {
+            DetectionsFilter::default()
+        } else {
+            default
+        };
+        let filter = DetectionsFilter {
+            min_score: self.min_score.or(default.min_score),
+            top_k: self.top_k.or(default.top_k),
+        };
+        filter.validate().map_err(ValidationError::Invalid)?;
+        Ok(filter)
+    }
+}
+
 /// Parameters relevant to each detector
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct DetectorParams(BTreeMap<String, serde_json::Value>);
@@ -61,6 +124,18 @@ impl DetectorParams {
     pub fn pop_threshold(&mut self) -> Option<f64> {
         self.0.remove(THRESHOLD_PARAM).and_then(|v| v.as_f64())
     }
+
+    /// Chunker overriding the chunker configured for the detector.
+    pub fn chunker_id(&self) -> Option<&str> {
+        self.0.get(CHUNKER_ID_PARAM).and_then(|v| v.as_str())
+    }
+
+    /// Removes the chunker override, as it is not sent to detectors.
+    pub fn pop_chunker_id(&mut self) -> Option<String> {
+        self.0
+            .remove(CHUNKER_ID_PARAM)
+            .and_then(|v| v.as_str().map(Into::into))
+    }
 }
 
 impl std::ops::Deref for DetectorParams {
@@ -116,6 +191,11 @@ impl GuardrailsHttpRequest {
             return Err(ValidationError::Required("inputs".into()));
         }
 
+        // Validate text generation parameters
+        if let Some(params) = &self.text_gen_parameters {
+            params.validate()?;
+        }
+
         let guardrail_config = self.guardrail_config.as_ref();
 
         // Validate masks
@@ -149,6 +229,95 @@ impl GuardrailsHttpRequest {
     }
 }
 
+/// The request format expected in the /api/v2/text/classification-generation endpoints.
+///
+/// Served by the same tasks as [`GuardrailsHttpRequest`], with fields named consistently
+/// with other v2 requests. v1 field names are accepted as aliases.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClassificationWithGenerationHttpRequest {
+    /// The model_id of the LLM to be invoked.
+    pub model_id: String,
+
+    /// The prompt to be sent to the LLM.
+    #[serde(alias = "inputs")]
+    pub prompt: String,
+
+    /// Detectors to be used on the prompt and generated text, along with their respective parameters
+    #[serde(default, alias = "guardrail_config")]
+    pub detectors: GuardrailsConfig,
+
+    /// Parameters to be sent to the LLM
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_gen_parameters: Option<GuardrailsTextGenerationParameters>,
+}
+
+impl TryFrom<ClassificationWithGenerationHttpRequest> for GuardrailsHttpRequest {
+    type Error = ValidationError;
+
+    /// Converts and validates a v2 request, reporting required fields by their v2 names.
+    fn try_from(request: ClassificationWithGenerationHttpRequest) -> Result<Self, Self::Error> {
+        if request.model_id.is_empty() {
+            return Err(ValidationError::Required("model_id".into()));
+        }
+        if request.prompt.is_empty() {
+            return Err(ValidationError::Required("prompt".into()));
+        }
+        let request = GuardrailsHttpRequest {
+            model_id: request.model_id,
+            inputs: request.prompt,
+            guardrail_config: Some(request.detectors),
+            text_gen_parameters: request.text_gen_parameters,
+        };
+        request.validate()?;
+        Ok(request)
+    }
+}
+
+/// The request format expected in the /api/v2/jobs/generation endpoint.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenerationJobHttpRequest {
+    /// The classification with text generation request run by the job
+    pub request: ClassificationWithGenerationHttpRequest,
+
+    /// URL the job is sent to in a POST request once it completes or fails
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+}
+
+/// Status of an asynchronous generation job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+/// The response format of the /api/v2/jobs endpoints.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenerationJob {
+    /// ID of the job
+    pub id: String,
+    /// Status of the job
+    pub status: JobStatus,
+    /// Result of the job, once completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<ClassifiedGeneratedTextResult>,
+    /// Error of the job, once failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JobError>,
+}
+
+/// Error of a failed job, as returned by the equivalent synchronous endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobError {
+    pub code: u16,
+    pub details: String,
+}
+
 /// Configuration of guardrails models for either or both input to a text generation model
 /// (e.g. user prompt) and output of a text generation model
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -216,6 +385,11 @@ pub struct GuardrailsTextGenerationParameters {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub truncate_input_tokens: Option<u32>,
 
+    /// Part of the input removed when it exceeds `truncate_input_tokens`.
+    /// Defaults to removing the start of the input.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncation_strategy: Option<TruncationStrategy>,
+
     /// The high level decoding strategy for picking
     /// tokens during text generation
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -296,6 +470,184 @@ pub struct GuardrailsTextGenerationParameters {
     /// If not specified, default behavior depends on server setting
     #[serde(skip_serializing_if = "Option::is_none")]
     pub include_stop_sequence: Option<bool>,
+
+    /// Penalty between -2.0 and 2.0 applied to tokens in proportion to how often
+    /// they have already been generated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+
+    /// Penalty between -2.0 and 2.0 applied to tokens that have already been generated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+
+    /// Map of token IDs to a bias between -100 and 100 added to their logits before sampling
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logit_bias: Option<BTreeMap<String, f64>>,
+
+    /// JSON schema the generated text is constrained to (guided decoding)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guided_json: Option<serde_json::Value>,
+
+    /// Regular expression the generated text is constrained to (guided decoding)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guided_regex: Option<String>,
+}
+
+impl GuardrailsTextGenerationParameters {
+    /// Validates parameters independently of the generation backend.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.truncation_strategy.is_some() && self.truncate_input_tokens.is_none() {
+            return Err(ValidationError::Invalid(
+                "`truncation_strategy` requires `truncate_input_tokens`".into(),
+            ));
+        }
+        for (name, penalty) in [
+            ("frequency_penalty", self.frequency_penalty),
+            ("presence_penalty", self.presence_penalty),
+        ] {
+            if penalty.is_some_and(|v| !(-2.0..=2.0).contains(&v)) {
+                return Err(ValidationError::Invalid(format!(
+                    "`{name}` must be between -2.0 and 2.0"
+                )));
+            }
+        }
+        if let Some(logit_bias) = &self.logit_bias {
+            for (token_id, bias) in logit_bias {
+                if token_id.parse::<u32>().is_err() {
+                    return Err(ValidationError::Invalid(format!(
+                        "`logit_bias` keys must be token IDs, got `{token_id}`"
+                    )));
+                }
+                if !(-100.0..=100.0).contains(bias) {
+                    return Err(ValidationError::Invalid(
+                        "`logit_bias` values must be between -100 and 100".into(),
+                    ));
+                }
+            }
+        }
+        if self.guided_json.is_some() && self.guided_regex.is_some() {
+            return Err(ValidationError::Invalid(
+                "only one of `guided_json` and `guided_regex` may be set".into(),
+            ));
+        }
+        if let Some(schema) = &self.guided_json {
+            if !schema.is_object() {
+                return Err(ValidationError::Invalid(
+                    "`guided_json` must be a JSON schema object".into(),
+                ));
+            }
+        }
+        if let Some(pattern) = &self.guided_regex {
+            if let Err(error) = regex::Regex::new(pattern) {
+                return Err(ValidationError::Invalid(format!(
+                    "`guided_regex` is not a valid regular expression: {error}"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if generation is deterministic, i.e. a seed is set and decoding
+    /// is greedy or has zero temperature.
+    pub fn is_deterministic(&self) -> bool {
+        let greedy = self
+            .decoding_method
+            .as_deref()
+            .is_some_and(|method| method.eq_ignore_ascii_case("greedy"));
+        self.seed.is_some() && (greedy || self.temperature == Some(0.0))
+    }
+
+    /// Returns names of set parameters that TGIS and caikit-nlp backends do not support.
+    pub fn unsupported_by_grpc_backends(&self) -> Vec<&'static str> {
+        [
+            ("frequency_penalty", self.frequency_penalty.is_some()),
+            ("presence_penalty", self.presence_penalty.is_some()),
+            ("logit_bias", self.logit_bias.is_some()),
+            ("guided_json", self.guided_json.is_some()),
+            ("guided_regex", self.guided_regex.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, is_set)| is_set.then_some(name))
+        .collect()
+    }
+
+    /// Returns names of set parameters that OpenAI-compatible backends do not support.
+    pub fn unsupported_by_openai_backends(&self) -> Vec<&'static str> {
+        let enabled = |value: Option<bool>| value.unwrap_or_default();
+        [
+            ("typical_p", self.typical_p.is_some()),
+            ("max_time", self.max_time.is_some()),
+            (
+                "exponential_decay_length_penalty",
+                self.exponential_decay_length_penalty.is_some(),
+            ),
+            ("preserve_input_text", enabled(self.preserve_input_text)),
+            ("input_tokens", enabled(self.input_tokens)),
+            ("generated_tokens", enabled(self.generated_tokens)),
+            ("token_logprobs", enabled(self.token_logprobs)),
+            ("token_ranks", enabled(self.token_ranks)),
+            ("include_stop_sequence", enabled(self.include_stop_sequence)),
+        ]
+        .into_iter()
+        .filter_map(|(name, is_set)| is_set.then_some(name))
+        .collect()
+    }
+
+    /// Converts parameters to fields of an OpenAI-compatible completions request.
+    ///
+    /// Guided decoding parameters are passed as vLLM extensions.
+    pub fn to_openai_params(&self) -> serde_json::Map<String, serde_json::Value> {
+        use serde_json::json;
+        let mut params = serde_json::Map::new();
+        let mut insert = |name: &str, value: Option<serde_json::Value>| {
+            if let Some(value) = value {
+                params.insert(name.into(), value);
+            }
+        };
+        insert("max_tokens", self.max_new_tokens.map(|v| json!(v)));
+        insert("min_tokens", self.min_new_tokens.map(|v| json!(v)));
+        // Greedy decoding is requested with a temperature of 0
+        let greedy = self
+            .decoding_method
+            .as_deref()
+            .is_some_and(|method| method.eq_ignore_ascii_case("GREEDY"));
+        let temperature = self.temperature.or(greedy.then_some(0.0));
+        insert("temperature", temperature.map(|v| json!(v)));
+        insert("top_p", self.top_p.map(|v| json!(v)));
+        insert("top_k", self.top_k.map(|v| json!(v)));
+        insert(
+            "repetition_penalty",
+            self.repetition_penalty.map(|v| json!(v)),
+        );
+        insert(
+            "frequency_penalty",
+            self.frequency_penalty.map(|v| json!(v)),
+        );
+        insert("presence_penalty", self.presence_penalty.map(|v| json!(v)));
+        insert("logit_bias", self.logit_bias.as_ref().map(|v| json!(v)));
+        insert("stop", self.stop_sequences.as_ref().map(|v| json!(v)));
+        insert("seed", self.seed.map(|v| json!(v)));
+        insert(
+            "truncate_prompt_tokens",
+            self.truncate_input_tokens.map(|v| json!(v)),
+        );
+        insert("guided_json", self.guided_json.clone());
+        insert("guided_regex", self.guided_regex.as_ref().map(|v| json!(v)));
+        params
+    }
+}
+
+/// Part of the input removed when it exceeds the input token limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TruncationStrategy {
+    /// Remove tokens from the start of the input, keeping its end
+    #[default]
+    Head,
+    /// Remove tokens from the end of the input, keeping its start
+    Tail,
+    /// Remove tokens from the middle of the input, keeping its start and end
+    Middle,
 }
 
 /// Parameters to exponentially increase the likelihood of the text generation
@@ -350,6 +702,51 @@ pub struct ClassifiedGeneratedTextResult {
     /// Input tokens and associated details, if requested
     #[serde(skip_serializing_if = "Option::is_none")]
     pub input_tokens: Option<Vec<GeneratedToken>>,
+
+    /// Provenance of generated text, if configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<ContentProvenance>,
+}
+
+/// Policy decision of the orchestrator on generated text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyDecision {
+    /// No output detections returned
+    Passed,
+    /// Output detections returned
+    Flagged,
+}
+
+impl std::fmt::Display for PolicyDecision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyDecision::Passed => write!(f, "passed"),
+            PolicyDecision::Flagged => write!(f, "flagged"),
+        }
+    }
+}
+
+/// Provenance of generated text, letting downstream systems verify text passed through
+/// guardrails unmodified.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContentProvenance {
+    /// SHA-256 hash of the generated text, as `sha256=<hex digest>`
+    pub content_hash: String,
+
+    /// Policy decision of the orchestrator on the generated text
+    pub decision: PolicyDecision,
+
+    /// Trace ID of the request generating the text
+    pub trace_id: String,
+
+    /// Unix timestamp in seconds of when the provenance was created
+    pub timestamp: u64,
+
+    /// HMAC-SHA256 of `<content_hash>:<decision>:<trace_id>:<timestamp>`, as
+    /// `sha256=<hex digest>`, if a signing key is configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 /// The request format expected in the /api/v2/text/detection/content endpoint.
@@ -361,6 +758,11 @@ pub struct TextContentDetectionHttpRequest {
 
     /// The map of detectors to be used, along with their respective parameters, e.g. thresholds.
     pub detectors: HashMap<String, DetectorParams>,
+
+    /// Maximum time in milliseconds to wait for detectors. Detections of detectors still running
+    /// are pending, retrievable with the continuation token of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_wait_ms: Option<u64>,
 }
 
 impl TextContentDetectionHttpRequest {
@@ -386,7 +788,204 @@ impl TextContentDetectionHttpRequest {
 pub struct TextContentDetectionResult {
     /// Detection results
     pub detections: Vec<ContentAnalysisResponse>,
+    /// Warnings, e.g. for text not analyzed by detectors returning partial results
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<DetectionWarning>,
+    /// Detectors still running when `max_wait_ms` elapsed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending: Option<PendingDetectors>,
+}
+
+/// Detectors still running when a time-boxed request returned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingDetectors {
+    /// IDs of detectors still running
+    pub detectors: Vec<String>,
+    /// Token to retrieve their detections from the
+    /// /api/v2/text/detection/content/pending/{continuation_token} endpoint
+    pub continuation_token: String,
+}
+
+/// Query parameters of the /api/v2/text/detection/content/pending/{continuation_token} endpoint.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PendingDetectionsParams {
+    /// Maximum time in milliseconds to wait for pending detectors, waits for all if unset
+    pub max_wait_ms: Option<u64>,
+}
+
+/// The request format expected in the /api/v2/text/suitability endpoint.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SuitabilityHttpRequest {
+    /// The content to run detectors on
+    pub content: String,
+
+    /// The map of detectors to be used, along with their respective parameters, e.g. thresholds.
+    pub detectors: HashMap<String, DetectorParams>,
+}
+
+impl SuitabilityHttpRequest {
+    /// Upfront validation of user request
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        // Validate required parameters
+        if self.content.is_empty() {
+            return Err(ValidationError::Required("content".into()));
+        }
+        if self.detectors.is_empty() {
+            return Err(ValidationError::Required("detectors".into()));
+        }
+
+        // Validate detector params
+        validate_detector_params(&self.detectors)?;
+
+        Ok(())
+    }
+}
+
+/// The request format expected in the /api/v2/feedback endpoint.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DetectorFeedbackHttpRequest {
+    /// ID of the request the feedback is on, i.e. its trace ID
+    pub request_id: String,
+
+    /// ID of the detector the feedback is on
+    pub detector_id: String,
+
+    /// Reference of the detection within the request, e.g. its span as `<start>-<end>`.
+    /// Omitted for false negatives
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detection_id: Option<String>,
+
+    /// Kind of feedback
+    pub kind: FeedbackKind,
+
+    /// Free-text comment, e.g. the expected detection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+impl DetectorFeedbackHttpRequest {
+    /// Upfront validation of user request
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.request_id.is_empty() {
+            return Err(ValidationError::Required("request_id".into()));
+        }
+        if self.detector_id.is_empty() {
+            return Err(ValidationError::Required("detector_id".into()));
+        }
+        Ok(())
+    }
 }
+
+/// Kind of detector feedback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackKind {
+    /// A detection of text that should not have been detected
+    FalsePositive,
+    /// Text that should have been detected
+    FalseNegative,
+}
+
+/// The response format of the /api/v2/feedback endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectorFeedbackResponse {
+    /// ID of the feedback record
+    pub id: String,
+}
+
+/// Suitability verdict for content.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// No detections
+    #[default]
+    Allow,
+    /// Detections from detectors configured to block
+    Block,
+    /// Detections only from detectors configured to redact
+    Redact,
+}
+
+/// Span of content suggested for redaction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Redaction {
+    /// Start index of the span
+    pub start: usize,
+    /// End index of the span
+    pub end: usize,
+    /// ID of the detector
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detector_id: Option<String>,
+    /// Normalized category of the detection, from configured categories
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+}
+
+/// The response format of the /api/v2/text/suitability endpoint
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SuitabilityResult {
+    /// Verdict for the content
+    pub verdict: Verdict,
+    /// Categories of detections, falling back to detection types for uncategorized detections
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    /// Maximum detection score per detector
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub max_scores: BTreeMap<String, f64>,
+    /// Spans suggested for redaction, for `redact` verdicts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redactions: Vec<Redaction>,
+    /// Warnings, e.g. for text not analyzed by detectors returning partial results
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<DetectionWarning>,
+}
+
+/// The request format expected in the /api/v2/text/chunks endpoint.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChunksHttpRequest {
+    /// The chunker to run
+    pub chunker_id: String,
+
+    /// The content to chunk
+    pub content: String,
+}
+
+impl ChunksHttpRequest {
+    /// Upfront validation of user request
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        // Validate required parameters
+        if self.chunker_id.is_empty() {
+            return Err(ValidationError::Required("chunker_id".into()));
+        }
+        if self.content.is_empty() {
+            return Err(ValidationError::Required("content".into()));
+        }
+        Ok(())
+    }
+}
+
+/// Span of content produced by a chunker.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunkSpan {
+    /// Start index of the span
+    pub start: usize,
+    /// End index of the span
+    pub end: usize,
+    /// Text of the span
+    pub text: String,
+}
+
+/// The response format of the /api/v2/text/chunks endpoint
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunksResult {
+    /// Chunks in order of position in the content
+    pub chunks: Vec<ChunkSpan>,
+}
+
 /// Streaming classification result on text produced by a text generation model, containing
 /// information from the original text generation output as well as the result of
 /// classification on the generated text. Also indicates where in stream is processed.
@@ -433,6 +1032,10 @@ pub struct ClassifiedGeneratedTextStreamResult {
     /// Result start index for processed text
     #[serde(skip_serializing_if = "Option::is_none")]
     pub start_index: Option<u32>,
+
+    /// Provenance of the generated text of the stream, in its last message, if configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<ContentProvenance>,
 }
 
 /// Results of classification on input to a text generation model (e.g. user prompt)
@@ -480,6 +1083,28 @@ pub struct TokenClassificationResult {
     /// Length of tokens in the text
     #[serde(skip_serializing_if = "Option::is_none")]
     pub token_count: Option<u32>,
+
+    /// Chunk that produced the result, for results on streamed text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<DetectionChunk>,
+
+    /// Normalized category of the result, from configured categories
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+}
+
+/// Chunk of streamed text that produced a detection.
+///
+/// Allows detections to be reconciled with the exact text region analyzed,
+/// as batches of detections may span multiple chunks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectionChunk {
+    /// ID of the chunker
+    pub chunker_id: String,
+    /// Start index of the chunk
+    pub start_index: u32,
+    /// End index of the chunk
+    pub processed_index: u32,
 }
 
 /// Enumeration of reasons why text generation stopped
@@ -512,6 +1137,28 @@ pub const UNSUITABLE_INPUT_MESSAGE: &str = "Unsuitable input detected. \
 
 pub const UNSUITABLE_OUTPUT_MESSAGE: &str = "Unsuitable output detected.";
 
+pub const ALL_CHOICES_FLAGGED_MESSAGE: &str =
+    "Unsuitable output detected in all choices, flagged choices were not dropped.";
+
+pub const UNSUITABLE_TOOLS_MESSAGE: &str = "Unsuitable tool definitions detected. \
+    Please check the detected entities on your tools and try again \
+    with the unsuitable tools removed.";
+
+pub const REMOVED_TOOLS_MESSAGE: &str =
+    "Unsuitable tool definitions detected and removed from the request.";
+
+pub const DRY_RUN_MESSAGE: &str = "Dry run: generation was not performed, \
+    output detectors were not applied and no actions were enforced.";
+
+pub const INPUT_DETECTION_DISABLED_MESSAGE: &str =
+    "Input detection is disabled, input detectors were not applied.";
+
+pub const OUTPUT_DETECTION_DISABLED_MESSAGE: &str =
+    "Output detection is disabled, output detectors were not applied.";
+
+pub const STREAMING_DETECTION_DISABLED_MESSAGE: &str =
+    "Streaming detection is disabled, detectors were not applied to streamed text.";
+
 /// Detection warning reason and message.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DetectionWarning {
@@ -538,6 +1185,80 @@ impl DetectionWarning {
             message: Some(UNSUITABLE_OUTPUT_MESSAGE.to_string()),
         }
     }
+
+    pub fn dry_run() -> Self {
+        DetectionWarning {
+            id: Some(DetectionWarningReason::DryRun),
+            message: Some(DRY_RUN_MESSAGE.to_string()),
+        }
+    }
+
+    pub fn input_detection_disabled() -> Self {
+        DetectionWarning {
+            id: Some(DetectionWarningReason::DetectionDisabled),
+            message: Some(INPUT_DETECTION_DISABLED_MESSAGE.to_string()),
+        }
+    }
+
+    pub fn output_detection_disabled() -> Self {
+        DetectionWarning {
+            id: Some(DetectionWarningReason::DetectionDisabled),
+            message: Some(OUTPUT_DETECTION_DISABLED_MESSAGE.to_string()),
+        }
+    }
+
+    pub fn streaming_detection_disabled() -> Self {
+        DetectionWarning {
+            id: Some(DetectionWarningReason::DetectionDisabled),
+            message: Some(STREAMING_DETECTION_DISABLED_MESSAGE.to_string()),
+        }
+    }
+
+    pub fn partial_detection(detector_id: &str, start: usize, end: usize) -> Self {
+        DetectionWarning {
+            id: Some(DetectionWarningReason::PartialDetection),
+            message: Some(partial_detection_message(detector_id, start, end)),
+        }
+    }
+
+    pub fn output_withheld(start: u32, end: u32) -> Self {
+        DetectionWarning {
+            id: Some(DetectionWarningReason::UnsuitableOutput),
+            message: Some(format!(
+                "Generated text from {start} to {end} was withheld, as unsuitable output was detected within the hold-back window."
+            )),
+        }
+    }
+
+    pub fn input_truncated(max_tokens: u32, start: usize, end: usize) -> Self {
+        DetectionWarning {
+            id: Some(DetectionWarningReason::InputTruncated),
+            message: Some(format!(
+                "Input exceeded {max_tokens} tokens, text from {start} to {end} was removed before generation."
+            )),
+        }
+    }
+
+    pub fn detector_substituted(detector_id: &str, substitute_id: &str) -> Self {
+        DetectionWarning {
+            id: Some(DetectionWarningReason::DetectorSubstituted),
+            message: Some(format!(
+                "Detector `{detector_id}` is unavailable, detections are from interchangeable detector `{substitute_id}`."
+            )),
+        }
+    }
+}
+
+/// Returns the warning message for a span not analyzed by a detector.
+pub fn partial_detection_message(detector_id: &str, start: usize, end: usize) -> String {
+    format!(
+        "Detector `{detector_id}` returned partial results, text from {start} to {end} was not analyzed."
+    )
+}
+
+/// Returns the warning message for choices not matching the requested response format.
+pub fn invalid_structured_output_message(choice_indices: &[u32]) -> String {
+    format!("Output of choices {choice_indices:?} does not match the requested response format.")
 }
 
 /// Enumeration of warning reasons on input detection
@@ -553,6 +1274,34 @@ pub enum DetectionWarningReason {
     /// Unsuitable text detected on output
     #[serde(rename = "UNSUITABLE_OUTPUT")]
     UnsuitableOutput,
+
+    /// Detector analyzed only part of the text
+    #[serde(rename = "PARTIAL_DETECTION")]
+    PartialDetection,
+
+    /// Unsuitable text detected on tool definitions
+    #[serde(rename = "UNSUITABLE_TOOLS")]
+    UnsuitableTools,
+
+    /// Generated text does not match the requested response format
+    #[serde(rename = "INVALID_STRUCTURED_OUTPUT")]
+    InvalidStructuredOutput,
+
+    /// Request was a dry run, input detections are reported but not enforced
+    #[serde(rename = "DRY_RUN")]
+    DryRun,
+
+    /// Detector was unavailable and substituted by a member of its detector group
+    #[serde(rename = "DETECTOR_SUBSTITUTED")]
+    DetectorSubstituted,
+
+    /// Input was truncated to the input token limit before generation
+    #[serde(rename = "INPUT_TRUNCATED")]
+    InputTruncated,
+
+    /// Detection phase was disabled by a feature flag, its detectors were not applied
+    #[serde(rename = "DETECTION_DISABLED")]
+    DetectionDisabled,
 }
 
 /// Generated token information
@@ -779,6 +1528,7 @@ impl From<pb::fmaas::GenerationResponse> for ClassifiedGeneratedTextStreamResult
             },
             processed_index: None,
             start_index: Some(0),
+            provenance: None,
         }
     }
 }
@@ -807,6 +1557,7 @@ impl From<pb::fmaas::BatchedGenerationResponse> for ClassifiedGeneratedTextResul
                 input: None,
                 output: None,
             },
+            provenance: None,
         }
     }
 }
@@ -841,6 +1592,7 @@ impl From<pb::caikit_data_model::nlp::GeneratedTextStreamResult>
             },
             processed_index: None,
             start_index: None,
+            provenance: None,
         }
     }
 }
@@ -868,6 +1620,79 @@ impl From<pb::caikit_data_model::nlp::GeneratedTextResult> for ClassifiedGenerat
                 input: None,
                 output: None,
             },
+            provenance: None,
+        }
+    }
+}
+
+impl FinishReason {
+    /// Maps the finish reason and stop reason of an OpenAI-compatible choice.
+    fn from_openai(finish_reason: &str, stop_reason: Option<&str>) -> Self {
+        match finish_reason {
+            "stop" if stop_reason.is_some() => FinishReason::StopSequence,
+            "stop" | "tool_calls" | "function_call" => FinishReason::EosToken,
+            "length" => FinishReason::MaxTokens,
+            "abort" | "content_filter" => FinishReason::Cancelled,
+            _ => FinishReason::Error,
+        }
+    }
+}
+
+impl From<clients::openai::ChatCompletion> for ClassifiedGeneratedTextResult {
+    fn from(mut value: clients::openai::ChatCompletion) -> Self {
+        let choice = (!value.choices.is_empty()).then(|| value.choices.swap_remove(0));
+        Self {
+            generated_text: choice
+                .as_ref()
+                .map(|choice| choice.message.content.clone().unwrap_or_default()),
+            finish_reason: choice.as_ref().map(|choice| {
+                FinishReason::from_openai(&choice.finish_reason, choice.stop_reason.as_deref())
+            }),
+            generated_token_count: Some(value.usage.completion_tokens),
+            seed: None,
+            input_token_count: value.usage.prompt_tokens,
+            warnings: None,
+            tokens: None,
+            input_tokens: None,
+            token_classification_results: TextGenTokenClassificationResults {
+                input: None,
+                output: None,
+            },
+            provenance: None,
+        }
+    }
+}
+
+impl From<clients::openai::ChatCompletionChunk> for ClassifiedGeneratedTextStreamResult {
+    fn from(mut value: clients::openai::ChatCompletionChunk) -> Self {
+        let choice = (!value.choices.is_empty()).then(|| value.choices.swap_remove(0));
+        Self {
+            generated_text: choice
+                .as_ref()
+                .map(|choice| choice.delta.content.clone().unwrap_or_default()),
+            finish_reason: choice.as_ref().and_then(|choice| {
+                choice.finish_reason.as_deref().map(|finish_reason| {
+                    FinishReason::from_openai(finish_reason, choice.stop_reason.as_deref())
+                })
+            }),
+            // Usage is only included in the last chunk
+            generated_token_count: value.usage.as_ref().map(|usage| usage.completion_tokens),
+            seed: None,
+            input_token_count: value
+                .usage
+                .as_ref()
+                .map(|usage| usage.prompt_tokens)
+                .unwrap_or_default(),
+            warnings: None,
+            tokens: None,
+            input_tokens: None,
+            token_classification_results: TextGenTokenClassificationResults {
+                input: None,
+                output: None,
+            },
+            processed_index: None,
+            start_index: None,
+            provenance: None,
         }
     }
 }
@@ -883,6 +1708,7 @@ pub struct GenerationWithDetectionHttpRequest {
     pub prompt: String,
 
     /// The map of detectors to be used, along with their respective parameters, e.g. thresholds.
+    /// If empty, the generated text is returned without detection.
     pub detectors: HashMap<String, DetectorParams>,
 
     /// Parameters to be sent to the LLM
@@ -900,8 +1726,10 @@ impl GenerationWithDetectionHttpRequest {
         if self.prompt.is_empty() {
             return Err(ValidationError::Required("prompt".into()));
         }
-        if self.detectors.is_empty() {
-            return Err(ValidationError::Required("detectors".into()));
+
+        // Validate text generation parameters
+        if let Some(params) = &self.text_gen_parameters {
+            params.validate()?;
         }
 
         // Validate detector params
@@ -922,6 +1750,14 @@ pub struct GenerationWithDetectionResult {
 
     /// Input length
     pub input_token_count: u32,
+
+    /// Warnings, e.g. for input truncated before generation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<DetectionWarning>,
+
+    /// Provenance of generated text, if configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<ContentProvenance>,
 }
 
 /// Detection format received from detectors
@@ -940,6 +1776,7 @@ pub struct DetectionResult {
     pub detector_id: Option<String>,
 
     // The confidence level in the detection class
+    #[serde(deserialize_with = "deserialize_score")]
     pub score: f64,
 
     // Optional evidence block
@@ -949,6 +1786,10 @@ pub struct DetectionResult {
     // Optional metadata block
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub metadata: Metadata,
+
+    // Normalized category of the detection, from configured categories
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1000,6 +1841,9 @@ impl ContextDocsHttpRequest {
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ContextDocsResult {
     pub detections: Vec<DetectionResult>,
+    /// Warnings, e.g. for detection phases disabled by feature flags
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<DetectionWarning>,
 }
 
 /// The request format expected in the /api/v2/text/detect/chat endpoint.
@@ -1082,6 +1926,9 @@ impl ChatDetectionHttpRequest {
 pub struct ChatDetectionResult {
     /// Detection results
     pub detections: Vec<DetectionResult>,
+    /// Warnings, e.g. for detection phases disabled by feature flags
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<DetectionWarning>,
 }
 
 /// The request format expected in the /api/v2/text/detect/generated endpoint.
@@ -1124,10 +1971,13 @@ impl DetectionOnGeneratedHttpRequest {
 pub struct DetectionOnGenerationResult {
     /// Detection results
     pub detections: Vec<DetectionResult>,
+    /// Warnings, e.g. for detection phases disabled by feature flags
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<DetectionWarning>,
 }
 
 /// Validates detector params.
-fn validate_detector_params(
+pub fn validate_detector_params(
     models: &HashMap<String, DetectorParams>,
 ) -> Result<(), ValidationError> {
     for (model_id, detector_params) in models {
@@ -1139,6 +1989,15 @@ fn validate_detector_params(
                 )));
             }
         }
+        // Validate chunker override is a string, if specified
+        if detector_params
+            .get(CHUNKER_ID_PARAM)
+            .is_some_and(|chunker_id| !chunker_id.is_string())
+        {
+            return Err(ValidationError::Invalid(format!(
+                "`chunker_id` parameter specified for model `{model_id}` must be a string"
+            )));
+        }
     }
     Ok(())
 }
@@ -1199,6 +2058,36 @@ pub struct StreamingContentDetectionResponse {
     pub detections: Vec<ContentAnalysisResponse>,
     pub processed_index: u32,
     pub start_index: u32,
+    /// Warnings, e.g. for text not analyzed by detectors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<DetectionWarning>,
+}
+
+/// Stream input detection response
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamingInputDetectionResponse {
+    pub detections: Vec<ContentAnalysisResponse>,
+    pub processed_index: u32,
+    pub start_index: u32,
+    /// Whether detections are interim, run on input not yet complete in a chunk.
+    /// Interim detections are superseded by the detections of later responses.
+    #[serde(default)]
+    pub interim: bool,
+    /// Warnings, e.g. for text not analyzed by detectors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<DetectionWarning>,
+}
+
+impl From<StreamingContentDetectionResponse> for StreamingInputDetectionResponse {
+    fn from(value: StreamingContentDetectionResponse) -> Self {
+        Self {
+            detections: value.detections,
+            processed_index: value.processed_index,
+            start_index: value.start_index,
+            interim: false,
+            warnings: value.warnings,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1394,6 +2283,123 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_classification_with_generation_request() {
+        let v2: ClassificationWithGenerationHttpRequest =
+            serde_json::from_value(serde_json::json!({
+                "model_id": "model",
+                "prompt": "The cow jumped over the moon!",
+                "detectors": {"input": {"models": {"hap": {}}}},
+            }))
+            .unwrap();
+        // v1 field names are accepted as aliases
+        let aliased: ClassificationWithGenerationHttpRequest =
+            serde_json::from_value(serde_json::json!({
+                "model_id": "model",
+                "inputs": "The cow jumped over the moon!",
+                "guardrail_config": {"input": {"models": {"hap": {}}}},
+            }))
+            .unwrap();
+        let request = GuardrailsHttpRequest::try_from(v2).unwrap();
+        assert_eq!(request.inputs, "The cow jumped over the moon!");
+        assert_eq!(
+            request.guardrail_config.unwrap().input_detectors(),
+            GuardrailsHttpRequest::try_from(aliased)
+                .unwrap()
+                .guardrail_config
+                .unwrap()
+                .input_detectors()
+        );
+
+        let error = GuardrailsHttpRequest::try_from(ClassificationWithGenerationHttpRequest {
+            model_id: "model".into(),
+            prompt: "".into(),
+            detectors: GuardrailsConfig::default(),
+            text_gen_parameters: None,
+        })
+        .unwrap_err();
+        assert_eq!(error.to_string(), "`prompt` is required");
+    }
+
+    #[test]
+    fn test_detector_feedback_request() {
+        let request: DetectorFeedbackHttpRequest = serde_json::from_value(serde_json::json!({
+            "request_id": "4bf92f3577b34da6a3ce929d0e0e4736",
+            "detector_id": "hap",
+            "detection_id": "0-12",
+            "kind": "false_positive",
+        }))
+        .unwrap();
+        assert_eq!(request.kind, FeedbackKind::FalsePositive);
+        assert!(request.validate().is_ok());
+
+        let request = DetectorFeedbackHttpRequest {
+            request_id: "".into(),
+            ..request
+        };
+        assert_eq!(
+            request.validate().unwrap_err().to_string(),
+            "`request_id` is required"
+        );
+    }
+
+    #[test]
+    fn test_openai_generation_conversion() {
+        let completion: clients::openai::ChatCompletion = serde_json::from_value(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "model",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hi!"},
+                "finish_reason": "stop",
+                "stop_reason": "\n",
+            }],
+            "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7},
+        }))
+        .unwrap();
+        let result = ClassifiedGeneratedTextResult::from(completion);
+        assert_eq!(result.generated_text.as_deref(), Some("Hi!"));
+        assert_eq!(result.finish_reason, Some(FinishReason::StopSequence));
+        assert_eq!(result.generated_token_count, Some(2));
+        assert_eq!(result.input_token_count, 5);
+
+        let chunk = |choices: serde_json::Value, usage: serde_json::Value| {
+            let chunk: clients::openai::ChatCompletionChunk = serde_json::from_value(json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1,
+                "model": "model",
+                "choices": choices,
+                "usage": usage,
+            }))
+            .unwrap();
+            ClassifiedGeneratedTextStreamResult::from(chunk)
+        };
+        let result = chunk(
+            json!([{"index": 0, "delta": {"content": "Hi"}, "finish_reason": null}]),
+            json!(null),
+        );
+        assert_eq!(result.generated_text.as_deref(), Some("Hi"));
+        assert_eq!(result.finish_reason, None);
+        assert_eq!(result.generated_token_count, None);
+        let result = chunk(
+            json!([{"index": 0, "delta": {}, "finish_reason": "length"}]),
+            json!(null),
+        );
+        assert_eq!(result.generated_text.as_deref(), Some(""));
+        assert_eq!(result.finish_reason, Some(FinishReason::MaxTokens));
+        // Usage is included in a last chunk without choices
+        let result = chunk(
+            json!([]),
+            json!({"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7}),
+        );
+        assert_eq!(result.generated_text, None);
+        assert_eq!(result.generated_token_count, Some(2));
+        assert_eq!(result.input_token_count, 5);
+    }
+
     #[test]
     fn test_detector_params() -> Result<(), serde_json::Error> {
         let value_json = r#"
@@ -1408,4 +2414,59 @@ mod tests {
         assert_eq!(value.pop_threshold(), None);
         Ok(())
     }
+
+    #[test]
+    fn test_text_gen_parameters_validate() {
+        let params: GuardrailsTextGenerationParameters =
+            serde_json::from_value(serde_json::json!({
+                "frequency_penalty": 0.5,
+                "presence_penalty": -0.5,
+                "logit_bias": { "50256": -100.0 },
+                "guided_regex": "[a-z]+",
+                "seed": 42,
+            }))
+            .unwrap();
+        assert!(params.validate().is_ok());
+        assert_eq!(
+            params.unsupported_by_grpc_backends(),
+            vec![
+                "frequency_penalty",
+                "presence_penalty",
+                "logit_bias",
+                "guided_regex"
+            ]
+        );
+        let openai_params = params.to_openai_params();
+        assert_eq!(openai_params["seed"], 42);
+        assert_eq!(openai_params["logit_bias"]["50256"], -100.0);
+        assert_eq!(openai_params["guided_regex"], "[a-z]+");
+        assert!(params.unsupported_by_openai_backends().is_empty());
+
+        let params: GuardrailsTextGenerationParameters =
+            serde_json::from_value(serde_json::json!({
+                "decoding_method": "GREEDY",
+                "typical_p": 0.5,
+                "token_logprobs": true,
+                "token_ranks": false,
+            }))
+            .unwrap();
+        assert_eq!(params.to_openai_params()["temperature"], 0.0);
+        assert_eq!(
+            params.unsupported_by_openai_backends(),
+            vec!["typical_p", "token_logprobs"]
+        );
+
+        let invalid = [
+            serde_json::json!({ "frequency_penalty": 2.5 }),
+            serde_json::json!({ "logit_bias": { "eos": 1.0 } }),
+            serde_json::json!({ "logit_bias": { "1": 101.0 } }),
+            serde_json::json!({ "guided_regex": "[a-z" }),
+            serde_json::json!({ "guided_json": {}, "guided_regex": ".*" }),
+        ];
+        for params in invalid {
+            let params: GuardrailsTextGenerationParameters =
+                serde_json::from_value(params).unwrap();
+            assert!(params.validate().is_err());
+        }
+    }
 }
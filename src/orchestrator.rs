@@ -18,43 +18,98 @@ pub mod errors;
 pub use errors::Error;
 pub mod common;
 pub mod handlers;
+pub mod self_test;
+pub mod startup;
 pub mod types;
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use tokio::{sync::RwLock, time::Instant};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use crate::{
-    clients::{
-        ClientMap, GenerationClient, NlpClient, TextContentsDetectorClient, TgisClient,
-        chunker::ChunkerClient,
-        detector::{
-            TextChatDetectorClient, TextContextDocDetectorClient, TextGenerationDetectorClient,
-        },
-        openai::OpenAiClient,
-    },
-    config::{DetectorType, GenerationProvider, OrchestratorConfig},
-    health::HealthCheckCache,
+    clients::{self, ClientMap, SharedClientState},
+    config::OrchestratorConfig,
+    health::{HealthCheckCache, HealthStatus},
+    models::ClassifiedGeneratedTextResult,
 };
+use common::{
+    alerts::Alerts, cache::ResponseCache, features::FeatureFlags, groups::DetectorGroupHealth,
+    pending::PendingDetectionsStore, provenance::Provenance,
+};
+use self_test::SelfTestReport;
+use startup::StartupReport;
 
 #[cfg_attr(test, derive(Default))]
 pub struct Context {
     config: OrchestratorConfig,
     clients: ClientMap,
+    /// Webhooks notified of policy violations, if configured, shared with requests
+    /// holding their alerts
+    alerts: Option<Arc<Alerts>>,
+    /// Health of detectors in detector groups
+    detector_health: DetectorGroupHealth,
+    /// Responses of deterministic generation requests, if configured
+    response_cache: Option<ResponseCache<ClassifiedGeneratedTextResult>>,
+    /// Subsystems enabled at runtime
+    features: FeatureFlags,
+    /// Provenance of generated text, if configured
+    provenance: Option<Provenance>,
+    /// Limit of outstanding downstream requests and sampler of logged requests, shared
+    /// by clients
+    client_state: SharedClientState,
 }
 
 impl Context {
     pub fn new(config: OrchestratorConfig, clients: ClientMap) -> Self {
-        Self { config, clients }
+        let alerts = config
+            .alerts
+            .clone()
+            .map(|config| Arc::new(Alerts::new(config)));
+        let response_cache = config.response_cache.as_ref().map(ResponseCache::new);
+        let features = FeatureFlags::new(config.features);
+        Self {
+            config,
+            clients,
+            alerts,
+            detector_health: DetectorGroupHealth::default(),
+            response_cache,
+            features,
+            provenance: None,
+            client_state: SharedClientState::default(),
+        }
+    }
+
+    /// Sets the state shared by `clients`.
+    pub fn with_client_state(mut self, client_state: SharedClientState) -> Self {
+        self.client_state = client_state;
+        self
+    }
+
+    /// Returns the state shared by clients, also by clients created on config reload.
+    pub fn client_state(&self) -> &SharedClientState {
+        &self.client_state
+    }
+
+    /// Sets the provenance of generated text.
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+        self
     }
 }
 
 /// Handles orchestrator tasks.
+#[derive(Clone)]
 #[cfg_attr(test, derive(Default))]
 pub struct Orchestrator {
     ctx: Arc<Context>,
     client_health: Arc<RwLock<HealthCheckCache>>,
+    /// Inconsistencies of chunkers that failed conformance checks
+    chunker_conformance: Arc<RwLock<HashMap<String, String>>>,
+    /// Summary of configured services, created on start-up
+    startup_report: Arc<RwLock<StartupReport>>,
+    /// Detections still running when time-boxed requests returned
+    pending_detections: Arc<PendingDetectionsStore>,
 }
 
 impl Orchestrator {
@@ -62,11 +117,27 @@ impl Orchestrator {
         config: OrchestratorConfig,
         start_up_health_check: bool,
     ) -> Result<Self, Error> {
-        let clients = create_clients(&config).await?;
-        let ctx = Arc::new(Context { config, clients });
+        clients::set_egress_policy(config.egress.clone());
+        clients::set_static_hosts(config.static_hosts.clone());
+        let client_state = SharedClientState::new(&config);
+        let clients = ClientMap::create(&config, &client_state).await?;
+        let provenance = match &config.provenance {
+            Some(provenance) => Some(Provenance::new(provenance).await.map_err(|error| {
+                Error::Other(format!("failed to load provenance signing key: {error}"))
+            })?),
+            None => None,
+        };
+        let mut ctx = Context::new(config, clients).with_client_state(client_state);
+        if let Some(provenance) = provenance {
+            ctx = ctx.with_provenance(provenance);
+        }
+        let ctx = Arc::new(ctx);
         let orchestrator = Self {
             ctx,
             client_health: Arc::new(RwLock::new(HealthCheckCache::default())),
+            chunker_conformance: Arc::new(RwLock::new(HashMap::new())),
+            startup_report: Arc::new(RwLock::new(StartupReport::default())),
+            pending_detections: Arc::new(PendingDetectionsStore::default()),
         };
         debug!("running start up checks");
         orchestrator.on_start_up(start_up_health_check).await?;
@@ -78,32 +149,69 @@ impl Orchestrator {
         &self.ctx.config
     }
 
+    /// Returns the feature flags of subsystems enabled at runtime.
+    pub fn features(&self) -> &FeatureFlags {
+        &self.ctx.features
+    }
+
     /// Perform any start-up actions required by the orchestrator.
     /// This should only error when the orchestrator is unable to start up.
-    /// Currently only performs client health probing to have results loaded into the cache.
+    /// Checks chunker conformance and optionally probes client health to have results
+    /// loaded into the cache.
     pub async fn on_start_up(&self, health_check: bool) -> Result<(), Error> {
         info!("Performing start-up actions for orchestrator...");
-        if health_check {
+        info!("Checking chunker conformance...");
+        let chunker_conformance = common::conformance::check_chunkers(&self.ctx).await;
+        for (chunker_id, reason) in &chunker_conformance {
+            warn!(%chunker_id, %reason, "chunker failed conformance check");
+        }
+        *self.chunker_conformance.write().await = chunker_conformance;
+        let client_health = if health_check {
             info!("Probing client health...");
             let client_health = self.client_health(true).await;
             // Results of probe do not affect orchestrator start-up.
             info!("Client health:\n{client_health}");
-        }
+            client_health
+        } else {
+            HealthCheckCache::default()
+        };
+        let startup_report = StartupReport::new(&self.ctx.config, &client_health);
+        info!(
+            startup_report = %serde_json::to_string(&startup_report).unwrap(),
+            "start-up report"
+        );
+        *self.startup_report.write().await = startup_report;
         Ok(())
     }
 
+    /// Sends canned inputs to each configured service, verifying responses.
+    pub async fn self_test(&self, model_id: Option<&str>) -> SelfTestReport {
+        self_test::run(&self.ctx, model_id).await
+    }
+
+    /// Returns the summary of configured services created on start-up.
+    pub async fn startup_report(&self) -> StartupReport {
+        self.startup_report.read().await.clone()
+    }
+
+    /// Shuts down all clients. Called on graceful shutdown.
+    pub async fn shutdown(&self) {
+        info!("shutting down orchestrator clients");
+        self.ctx.clients.shutdown().await;
+    }
+
     /// Returns client health state.
     pub async fn client_health(&self, probe: bool) -> HealthCheckCache {
         let initialized = !self.client_health.read().await.is_empty();
         if probe || !initialized {
             debug!("refreshing health cache");
             let now = Instant::now();
-            let mut health = HealthCheckCache::with_capacity(self.ctx.clients.len());
-            // TODO: perform health checks concurrently?
-            for (key, client) in self.ctx.clients.iter() {
-                let result = client.health().await;
-                health.insert(key.into(), result);
-            }
+            let mut health = self.ctx.clients.health().await;
+            self.apply_chunker_conformance(&mut health).await;
+            self.ctx
+                .detector_health
+                .apply_health_checks(&self.ctx.config, &health);
+            health.record();
             let mut client_health = self.client_health.write().await;
             *client_health = health;
             debug!(
@@ -113,89 +221,24 @@ impl Orchestrator {
         }
         self.client_health.read().await.clone()
     }
-}
-
-async fn create_clients(config: &OrchestratorConfig) -> Result<ClientMap, Error> {
-    let mut clients = ClientMap::new();
-
-    // Create generation client
-    if let Some(generation) = &config.generation {
-        match generation.provider {
-            GenerationProvider::Tgis => {
-                let tgis_client = TgisClient::new(&generation.service).await;
-                let generation_client = GenerationClient::tgis(tgis_client);
-                clients.insert("generation".to_string(), generation_client);
-            }
-            GenerationProvider::Nlp => {
-                let nlp_client = NlpClient::new(&generation.service).await;
-                let generation_client = GenerationClient::nlp(nlp_client);
-                clients.insert("generation".to_string(), generation_client);
-            }
-        }
-    }
-
-    // Create chat generation client
-    if let Some(chat_generation) = &config.chat_generation {
-        let openai_client = OpenAiClient::new(
-            &chat_generation.service,
-            chat_generation.health_service.as_ref(),
-        )
-        .await?;
-        clients.insert("chat_generation".to_string(), openai_client);
-    }
 
-    // Create chunker clients
-    if let Some(chunkers) = &config.chunkers {
-        for (chunker_id, chunker) in chunkers {
-            let chunker_client = ChunkerClient::new(&chunker.service).await;
-            clients.insert(chunker_id.to_string(), chunker_client);
-        }
-    }
-
-    // Create detector clients
-    for (detector_id, detector) in &config.detectors {
-        match detector.r#type {
-            DetectorType::TextContents => {
-                clients.insert(
-                    detector_id.into(),
-                    TextContentsDetectorClient::new(
-                        &detector.service,
-                        detector.health_service.as_ref(),
-                    )
-                    .await?,
-                );
-            }
-            DetectorType::TextGeneration => {
-                clients.insert(
-                    detector_id.into(),
-                    TextGenerationDetectorClient::new(
-                        &detector.service,
-                        detector.health_service.as_ref(),
-                    )
-                    .await?,
-                );
-            }
-            DetectorType::TextChat => {
-                clients.insert(
-                    detector_id.into(),
-                    TextChatDetectorClient::new(
-                        &detector.service,
-                        detector.health_service.as_ref(),
-                    )
-                    .await?,
-                );
-            }
-            DetectorType::TextContextDoc => {
-                clients.insert(
-                    detector_id.into(),
-                    TextContextDocDetectorClient::new(
-                        &detector.service,
-                        detector.health_service.as_ref(),
-                    )
-                    .await?,
-                );
+    /// Marks chunkers that failed conformance checks and detectors using them unhealthy.
+    async fn apply_chunker_conformance(&self, health: &mut HealthCheckCache) {
+        for (chunker_id, reason) in self.chunker_conformance.read().await.iter() {
+            let reason = format!("chunker `{chunker_id}` failed conformance check: {reason}");
+            let detector_ids = self
+                .ctx
+                .config
+                .detectors
+                .iter()
+                .filter(|(_, detector)| &detector.chunker_id == chunker_id)
+                .map(|(detector_id, _)| detector_id);
+            for client_id in std::iter::once(chunker_id).chain(detector_ids) {
+                if let Some(result) = health.get_mut(client_id) {
+                    result.status = HealthStatus::Unhealthy;
+                    result.reason = Some(reason.clone());
+                }
             }
         }
     }
-    Ok(clients)
 }
@@ -20,3 +20,12 @@ pub mod tasks;
 pub use tasks::*;
 pub mod client;
 pub use client::*;
+pub mod alerts;
+pub mod cache;
+pub mod conformance;
+pub mod features;
+pub mod groups;
+pub mod pending;
+pub mod provenance;
+pub mod scores;
+pub mod unfiltered;
@@ -0,0 +1,455 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Webhook notifications of policy violations, i.e. blocked requests and
+//! high-severity detections.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use opentelemetry::trace::TraceId;
+use serde::Serialize;
+use tracing::{debug, info, warn};
+
+use super::current_timestamp;
+use crate::{
+    clients::dns::ReqwestResolver,
+    config::{AlertWebhook, AlertsConfig},
+    orchestrator::types::Detections,
+};
+
+/// Header of the HMAC-SHA256 signature of alert request bodies.
+pub const SIGNATURE_HEADER: &str = "x-guardrails-signature";
+/// Header of the Unix timestamp in seconds of alert requests, signed with the body, so
+/// webhooks can reject replayed requests.
+pub const TIMESTAMP_HEADER: &str = "x-guardrails-timestamp";
+/// Timeout of alert requests.
+const ALERT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Delay before the first retry of a failed delivery, doubled for each further retry.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// Time requests are remembered for, so high-severity detections of a request, e.g. of
+/// each chunk of a stream, are alerted on once.
+const ALERTED_REQUEST_TTL: Duration = Duration::from_secs(600);
+
+/// Reason of a policy violation.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationReason {
+    /// The request was blocked due to detections
+    Blocked,
+    /// Detections scored at least the configured `min_score`
+    HighSeverity,
+}
+
+/// Record of a policy violation, sent to alert webhooks.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ViolationRecord {
+    /// ID of the record, e.g. to deduplicate retried deliveries
+    pub id: String,
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+    /// Trace ID of the request
+    pub trace_id: String,
+    pub reason: ViolationReason,
+    /// Task of the request, for blocked requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    pub detections: Vec<ViolationDetection>,
+}
+
+/// Detection of a policy violation. Detected text is omitted.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ViolationDetection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detector_id: Option<String>,
+    pub detection_type: String,
+    pub detection: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    pub score: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<usize>,
+}
+
+impl ViolationRecord {
+    pub fn new(
+        trace_id: TraceId,
+        reason: ViolationReason,
+        task: Option<&str>,
+        detections: &Detections,
+    ) -> Self {
+        let detections = detections
+            .iter()
+            .map(|detection| ViolationDetection {
+                detector_id: detection.detector_id.clone(),
+                detection_type: detection.detection_type.clone(),
+                detection: detection.detection.clone(),
+                category: detection.category.clone(),
+                score: detection.score,
+                start: detection.start,
+                end: detection.end,
+            })
+            .collect();
+        Self {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            timestamp: current_timestamp().as_secs(),
+            trace_id: trace_id.to_string(),
+            reason,
+            task: task.map(Into::into),
+            detections,
+        }
+    }
+}
+
+/// Sends violation records to alert webhooks.
+#[derive(Debug)]
+pub struct Alerts {
+    config: AlertsConfig,
+    client: reqwest::Client,
+    /// Requests alerted on for high-severity detections, or with alerts suppressed,
+    /// by the time they were added
+    alerted: Mutex<HashMap<TraceId, Instant>>,
+    /// Requests whose high-severity alerts are held, with their held detections
+    held: Mutex<HashMap<TraceId, Detections>>,
+}
+
+impl Alerts {
+    pub fn new(config: AlertsConfig) -> Self {
+        // Redirects are not followed, so records are only sent to configured webhooks
+        let client = reqwest::Client::builder()
+            .timeout(ALERT_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            // Resolved addresses are checked against the egress policy
+            .dns_resolver(Arc::new(ReqwestResolver))
+            .build()
+            .expect("alert client should build");
+        Self {
+            config,
+            client,
+            alerted: Mutex::new(HashMap::new()),
+            held: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Holds high-severity alerts of a request until it is released, e.g. while it is
+    /// not validated yet.
+    pub fn hold(&self, trace_id: TraceId) {
+        self.held.lock().unwrap().entry(trace_id).or_default();
+    }
+
+    /// Releases held high-severity alerts of a request, alerting on its held detections.
+    pub fn release(&self, trace_id: TraceId) {
+        let held = self.held.lock().unwrap().remove(&trace_id);
+        if let Some(detections) = held {
+            self.high_severity(trace_id, &detections);
+        }
+    }
+
+    /// Discards held high-severity alerts of a request, e.g. of a rejected request.
+    pub fn discard(&self, trace_id: TraceId) {
+        self.held.lock().unwrap().remove(&trace_id);
+    }
+
+    /// Suppresses high-severity alerts of a request, e.g. of a dry run.
+    pub fn suppress(&self, trace_id: TraceId) {
+        self.first_alert(trace_id);
+    }
+
+    /// Returns whether a request was not alerted on or suppressed yet, marking it alerted.
+    fn first_alert(&self, trace_id: TraceId) -> bool {
+        let mut alerted = self.alerted.lock().unwrap();
+        alerted.retain(|_, added| added.elapsed() < ALERTED_REQUEST_TTL);
+        alerted.insert(trace_id, Instant::now()).is_none()
+    }
+
+    /// Alerts on a request blocked due to `detections` by `task`.
+    pub fn blocked(&self, trace_id: TraceId, task: &str, detections: &Detections) {
+        if detections.is_empty() {
+            return;
+        }
+        self.notify(ViolationRecord::new(
+            trace_id,
+            ViolationReason::Blocked,
+            Some(task),
+            detections,
+        ));
+    }
+
+    /// Alerts on `detections` scoring at least the configured `min_score`, if any. Requests
+    /// are alerted on once, on their first high-severity detections.
+    pub fn high_severity(&self, trace_id: TraceId, detections: &Detections) {
+        let Some(min_score) = self.config.min_score else {
+            return;
+        };
+        let detections = detections
+            .iter()
+            .filter(|detection| detection.score >= min_score)
+            .cloned()
+            .collect::<Detections>();
+        if detections.is_empty() {
+            return;
+        }
+        if let Some(held) = self.held.lock().unwrap().get_mut(&trace_id) {
+            held.extend(detections);
+            return;
+        }
+        if !self.first_alert(trace_id) {
+            return;
+        }
+        self.notify(ViolationRecord::new(
+            trace_id,
+            ViolationReason::HighSeverity,
+            None,
+            &detections,
+        ));
+    }
+
+    /// Sends `record` to each webhook in spawned tasks.
+    pub fn notify(&self, record: ViolationRecord) {
+        info!(
+            alert_id = %record.id,
+            reason = ?record.reason,
+            monotonic_counter.alert_count = 1,
+            "policy violation alert"
+        );
+        let body = Bytes::from(serde_json::to_vec(&record).unwrap());
+        for webhook in &self.config.webhooks {
+            tokio::spawn(deliver(
+                self.client.clone(),
+                webhook.clone(),
+                body.clone(),
+                self.config.max_retries,
+            ));
+        }
+    }
+}
+
+/// Delivers an alert to a webhook, retrying failed requests with exponential backoff.
+async fn deliver(client: reqwest::Client, webhook: AlertWebhook, body: Bytes, max_retries: usize) {
+    let secret = match &webhook.secret {
+        Some(secret) => match secret.load().await {
+            Ok(secret) => Some(secret),
+            Err(error) => {
+                warn!(url = %webhook.url, %error, "alert not sent: failed to load secret");
+                return;
+            }
+        },
+        None => None,
+    };
+    let mut delay = INITIAL_RETRY_DELAY;
+    for attempt in 0..=max_retries {
+        let timestamp = current_timestamp().as_secs().to_string();
+        let mut request = client
+            .post(&webhook.url)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(TIMESTAMP_HEADER, &timestamp)
+            .body(body.clone());
+        if let Some(secret) = &secret {
+            let message = [timestamp.as_bytes(), b".", &body].concat();
+            request = request.header(SIGNATURE_HEADER, sign(secret, &message));
+        }
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                debug!(url = %webhook.url, attempt, "alert delivered");
+                return;
+            }
+            // Redirects and other client errors are not retried
+            Ok(response)
+                if response.status().is_redirection()
+                    || response.status().is_client_error()
+                        && response.status() != http::StatusCode::TOO_MANY_REQUESTS =>
+            {
+                warn!(url = %webhook.url, status = %response.status(), "alert rejected");
+                break;
+            }
+            Ok(response) => response.status().to_string(),
+            Err(error) => error.to_string(),
+        };
+        if attempt < max_retries {
+            debug!(url = %webhook.url, attempt, %error, "alert delivery failed, retrying");
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        } else {
+            warn!(url = %webhook.url, attempt, %error, "alert delivery failed");
+        }
+    }
+    info!(
+        url = %webhook.url,
+        monotonic_counter.alert_delivery_failure_count = 1,
+    );
+}
+
+/// Returns the `sha256=<hex digest>` HMAC-SHA256 signature of `body`.
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret);
+    let tag = ring::hmac::sign(&key, body);
+    let digest = tag
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("sha256={digest}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{
+        Router,
+        http::{HeaderMap, StatusCode},
+        response::Redirect,
+        routing::post,
+    };
+
+    use super::*;
+    use crate::{
+        orchestrator::types::Detection,
+        utils::{secrets::SecretSource, test_events::CapturedEvents, test_server::serve},
+    };
+
+    fn config(webhooks: Vec<AlertWebhook>) -> AlertsConfig {
+        AlertsConfig {
+            webhooks,
+            min_score: Some(0.9),
+            max_retries: 2,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deliver() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let signatures = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new()
+            .route(
+                "/webhook",
+                post({
+                    let requests = requests.clone();
+                    let signatures = signatures.clone();
+                    move |headers: HeaderMap, body: Bytes| async move {
+                        let timestamp = headers[TIMESTAMP_HEADER].to_str().unwrap().to_string();
+                        let signature = headers[SIGNATURE_HEADER].to_str().unwrap().to_string();
+                        signatures
+                            .lock()
+                            .unwrap()
+                            .push((timestamp, signature, body));
+                        // Fails the first attempt
+                        match requests.fetch_add(1, Ordering::SeqCst) {
+                            0 => StatusCode::SERVICE_UNAVAILABLE,
+                            _ => StatusCode::OK,
+                        }
+                    }
+                }),
+            )
+            .route(
+                "/redirect",
+                post(|| async { Redirect::temporary("/webhook") }),
+            );
+        let port = serve(app).await;
+        let alerts = Alerts::new(config(Vec::new()));
+        let path = std::env::temp_dir().join(format!("{}-secret", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&path, "secret").unwrap();
+        let secret = SecretSource::File(path);
+        let webhook = |path: &str| AlertWebhook {
+            url: format!("http://localhost:{port}{path}"),
+            secret: Some(secret.clone()),
+        };
+
+        // Failed deliveries are retried, signed with their timestamp
+        deliver(
+            alerts.client.clone(),
+            webhook("/webhook"),
+            Bytes::from("{}"),
+            2,
+        )
+        .await;
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        for (timestamp, signature, body) in signatures.lock().unwrap().iter() {
+            assert_eq!(body.as_ref(), b"{}");
+            let message = format!("{timestamp}.{{}}");
+            assert_eq!(*signature, sign(b"secret", message.as_bytes()));
+        }
+
+        // Redirects are neither followed nor retried
+        deliver(
+            alerts.client.clone(),
+            webhook("/redirect"),
+            Bytes::from("{}"),
+            2,
+        )
+        .await;
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_high_severity() {
+        let (events, _guard) = CapturedEvents::capture();
+        let alerts = Alerts::new(config(Vec::new()));
+        let detections = Detections::from(vec![
+            Detection {
+                score: 0.95,
+                ..Default::default()
+            },
+            Detection {
+                score: 0.5,
+                ..Default::default()
+            },
+        ]);
+
+        // Requests are alerted on once, e.g. not for each chunk of a stream
+        let trace_id = TraceId::from_bytes([1; 16]);
+        alerts.high_severity(trace_id, &detections);
+        alerts.high_severity(trace_id, &detections);
+        assert_eq!(events.with_field("monotonic_counter.alert_count").len(), 1);
+
+        // Suppressed requests, e.g. dry runs, are not alerted on
+        let trace_id = TraceId::from_bytes([2; 16]);
+        alerts.suppress(trace_id);
+        alerts.high_severity(trace_id, &detections);
+        assert_eq!(events.with_field("monotonic_counter.alert_count").len(), 1);
+
+        // Held alerts are sent once released
+        let trace_id = TraceId::from_bytes([3; 16]);
+        alerts.hold(trace_id);
+        alerts.high_severity(trace_id, &detections);
+        assert_eq!(events.with_field("monotonic_counter.alert_count").len(), 1);
+        alerts.release(trace_id);
+        assert_eq!(events.with_field("monotonic_counter.alert_count").len(), 2);
+
+        // Discarded alerts, e.g. of rejected requests, are not sent
+        let trace_id = TraceId::from_bytes([4; 16]);
+        alerts.hold(trace_id);
+        alerts.high_severity(trace_id, &detections);
+        alerts.discard(trace_id);
+        alerts.release(trace_id);
+        assert_eq!(events.with_field("monotonic_counter.alert_count").len(), 2);
+    }
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign(b"Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
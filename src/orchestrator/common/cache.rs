@@ -0,0 +1,169 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Cache of guarded responses of deterministic generation requests.
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use http::HeaderMap;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::info;
+
+use crate::config::ResponseCacheConfig;
+
+/// Responses by cache key, expiring after the configured TTL.
+#[derive(Debug)]
+pub struct ResponseCache<T> {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<String, (Instant, T)>>,
+}
+
+impl<T: Clone> ResponseCache<T> {
+    pub fn new(config: &ResponseCacheConfig) -> Self {
+        Self {
+            ttl: Duration::from_secs(config.ttl),
+            max_entries: config.max_entries,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Returns the cached response of `key`, if cached and not expired.
+    pub fn get(&self, key: &str) -> Option<T> {
+        let response = self
+            .entries
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|(expires_at, _)| *expires_at > Instant::now())
+            .map(|(_, response)| response.clone());
+        if response.is_some() {
+            info!(monotonic_counter.response_cache_hit_count = 1);
+        }
+        response
+    }
+
+    /// Caches `response` under `key`. Expired entries are dropped, and the entry
+    /// expiring soonest is evicted if the cache is full.
+    pub fn insert(&self, key: String, response: T) {
+        self.insert_at(key, response, Instant::now());
+    }
+
+    fn insert_at(&self, key: String, response: T, now: Instant) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (expires_at, _)| *expires_at > now);
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            let soonest = entries
+                .iter()
+                .min_by_key(|(_, (expires_at, _))| *expires_at)
+                .map(|(key, _)| key.clone());
+            if let Some(soonest) = soonest {
+                entries.remove(&soonest);
+            }
+        }
+        entries.insert(key, (now + self.ttl, response));
+    }
+}
+
+/// Returns the hex SHA-256 digest of the JSON serialization of `value`, used as a cache key.
+///
+/// Object keys are sorted, so maps serialize the same regardless of their order.
+pub fn cache_key(value: &impl Serialize) -> String {
+    let value = serde_json::to_value(value)
+        .map(canonical)
+        .unwrap_or_default();
+    let bytes = serde_json::to_vec(&value).unwrap_or_default();
+    ring::digest::digest(&ring::digest::SHA256, &bytes)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Returns the `(name, value)` pairs of `headers`, sorted.
+pub fn header_pairs(headers: &HeaderMap) -> Vec<(&str, &[u8])> {
+    let mut pairs = headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_bytes()))
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs
+}
+
+/// Returns `value` with the keys of its objects sorted.
+fn canonical(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries = map
+                .into_iter()
+                .map(|(key, value)| (key, canonical(value)))
+                .collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(entries.into_iter().collect())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(canonical).collect()),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_cache() {
+        let cache = ResponseCache::new(&ResponseCacheConfig {
+            ttl: 60,
+            max_entries: 2,
+        });
+        cache.insert(cache_key(&("model", "a")), "a".to_string());
+        assert_eq!(cache.get(&cache_key(&("model", "a"))).as_deref(), Some("a"));
+        assert!(cache.get(&cache_key(&("model", "b"))).is_none());
+
+        // Entry expiring soonest is evicted when full
+        let now = Instant::now();
+        cache.insert_at(cache_key(&("model", "b")), "b".to_string(), now);
+        cache.insert_at(
+            cache_key(&("model", "c")),
+            "c".to_string(),
+            now + Duration::from_millis(1),
+        );
+        assert!(cache.get(&cache_key(&("model", "a"))).is_none());
+        assert_eq!(cache.get(&cache_key(&("model", "b"))).as_deref(), Some("b"));
+        assert_eq!(cache.get(&cache_key(&("model", "c"))).as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let a = serde_json::json!({"threshold": 0.5, "labels": {"a": 1, "b": 2}});
+        let b = serde_json::json!({"labels": {"b": 2, "a": 1}, "threshold": 0.5});
+        assert_eq!(cache_key(&("model", &a)), cache_key(&("model", &b)));
+        assert_ne!(cache_key(&("model", &a)), cache_key(&("other", &a)));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-b", "2".parse().unwrap());
+        headers.insert("x-a", "1".parse().unwrap());
+        assert_eq!(
+            header_pairs(&headers),
+            [("x-a", &b"1"[..]), ("x-b", &b"2"[..])]
+        );
+    }
+}
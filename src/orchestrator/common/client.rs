@@ -15,11 +15,13 @@
 
 */
 //! Client helpers
+use std::time::{Duration, Instant};
+
 use futures::{StreamExt, TryStreamExt};
 use http::{HeaderMap, header::CONTENT_TYPE};
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc};
 use tokio_stream::wrappers::{BroadcastStream, ReceiverStream};
-use tracing::{debug, instrument};
+use tracing::{Instrument, debug, instrument, warn};
 
 use crate::{
     clients::{
@@ -33,14 +35,16 @@ use crate::{
         http::JSON_CONTENT_TYPE,
         openai::{self, OpenAiClient},
     },
+    config::{GenerationPacing, PartialResultsPolicy},
     models::{
-        ClassifiedGeneratedTextResult as GenerateResponse, DetectorParams,
+        ClassifiedGeneratedTextResult as GenerateResponse, DetectionWarning, DetectorParams,
         GuardrailsTextGenerationParameters as GenerateParams,
     },
-    orchestrator::{Error, types::*},
+    orchestrator::{Error, common::truncate_tokens, types::*},
     pb::caikit::runtime::chunkers::{
         BidiStreamingChunkerTokenizationTaskRequest, ChunkerTokenizationTaskRequest,
     },
+    utils::debug_info,
 };
 
 /// Sends request to chunker client.
@@ -96,7 +100,13 @@ pub async fn chunk_stream(
     Ok(output_stream)
 }
 
+/// Maximum number of times the unanalyzed remainder of partial results is sent again.
+const MAX_PARTIAL_REDISPATCHES: usize = 3;
+
 /// Sends request to text contents detector client.
+///
+/// For partial results, the unanalyzed remainder of each chunk is either sent again
+/// or returned as a partial span, depending on `partial_results`.
 #[instrument(skip_all, fields(detector_id))]
 pub async fn detect_text_contents(
     client: &TextContentsDetectorClient,
@@ -105,44 +115,84 @@ pub async fn detect_text_contents(
     params: DetectorParams,
     chunks: Chunks,
     apply_chunk_offset: bool,
+    partial_results: PartialResultsPolicy,
 ) -> Result<Detections, Error> {
     let detector_id = detector_id.clone();
-    let contents = chunks
-        .iter()
-        .map(|chunk| chunk.text.clone())
+    let mut detections = Detections::default();
+    // Chunks to send with their offset from the start of the original chunk
+    let mut pending = chunks
+        .into_iter()
+        .map(|chunk| (chunk, 0))
         .collect::<Vec<_>>();
-    if contents.is_empty() {
-        return Ok(Detections::default());
+    let mut attempt = 0;
+    while !pending.is_empty() {
+        let contents = pending
+            .iter()
+            .map(|(chunk, _)| chunk.text.clone())
+            .collect::<Vec<_>>();
+        let request = ContentAnalysisRequest::new(contents, params.clone());
+        debug!(%detector_id, ?request, "sending detector request");
+        let response = client
+            .text_contents(&detector_id, request, headers.clone())
+            .await
+            .map_err(|error| Error::DetectorRequestFailed {
+                id: detector_id.clone(),
+                error,
+            })?;
+        debug!(%detector_id, ?response, "received detector response");
+        let analyzed_lengths = response.analyzed_lengths.unwrap_or_default();
+        let mut remainders = Vec::new();
+        for (i, ((chunk, offset), chunk_detections)) in
+            pending.into_iter().zip(response.detections).enumerate()
+        {
+            let span_offset = if apply_chunk_offset {
+                chunk.start
+            } else {
+                offset
+            };
+            detections.extend(chunk_detections.into_iter().map(|detection| {
+                let mut detection: Detection = detection.into();
+                detection.detector_id = Some(detector_id.clone());
+                detection.start = detection.start.map(|start| start + span_offset);
+                detection.end = detection.end.map(|end| end + span_offset);
+                detection
+            }));
+            let len = chunk.text.chars().count();
+            let analyzed = analyzed_lengths.get(i).copied().unwrap_or(len).min(len);
+            if analyzed == len {
+                continue;
+            }
+            let redispatch = partial_results == PartialResultsPolicy::Redispatch
+                && attempt < MAX_PARTIAL_REDISPATCHES
+                && analyzed > 0;
+            if redispatch {
+                debug_info::record_decision(|| {
+                    format!(
+                        "detector `{detector_id}` analyzed {analyzed} of {len} chars, redispatching"
+                    )
+                });
+                let text = chunk.text.chars().skip(analyzed).collect::<String>();
+                let remainder = Chunk {
+                    start: chunk.start + analyzed,
+                    text: text.into(),
+                    ..chunk
+                };
+                remainders.push((remainder, offset + analyzed));
+            } else {
+                warn!(%detector_id, analyzed, len, "detector returned partial results");
+                debug_info::record_decision(|| {
+                    format!("detector `{detector_id}` analyzed {analyzed} of {len} chars")
+                });
+                detections.push_partial_span(PartialSpan {
+                    detector_id: detector_id.clone(),
+                    start: span_offset + analyzed,
+                    end: span_offset + len,
+                });
+            }
+        }
+        pending = remainders;
+        attempt += 1;
     }
-    let request = ContentAnalysisRequest::new(contents, params);
-    debug!(%detector_id, ?request, "sending detector request");
-    let response = client
-        .text_contents(&detector_id, request, headers)
-        .await
-        .map_err(|error| Error::DetectorRequestFailed {
-            id: detector_id.clone(),
-            error,
-        })?;
-    debug!(%detector_id, ?response, "received detector response");
-    let detections = chunks
-        .into_iter()
-        .zip(response)
-        .flat_map(|(chunk, detections)| {
-            detections
-                .into_iter()
-                .map(|detection| {
-                    let mut detection: Detection = detection.into();
-                    detection.detector_id = Some(detector_id.clone());
-                    if apply_chunk_offset {
-                        let offset = chunk.start;
-                        detection.start = detection.start.map(|start| start + offset);
-                        detection.end = detection.end.map(|end| end + offset);
-                    }
-                    detection
-                })
-                .collect::<Vec<_>>()
-        })
-        .collect::<Detections>();
     Ok(detections)
 }
 
@@ -159,6 +209,7 @@ pub async fn detect_text_generation(
     let detector_id = detector_id.clone();
     let request = GenerationDetectionRequest::new(prompt, generated_text, params);
     debug!(%detector_id, ?request, "sending detector request");
+    let start = Instant::now();
     let response = client
         .text_generation(&detector_id, request, headers)
         .await
@@ -166,6 +217,7 @@ pub async fn detect_text_generation(
             id: detector_id.clone(),
             error,
         })?;
+    debug_info::record_detector_call(&detector_id, start.elapsed(), false);
     debug!(%detector_id, ?response, "received detector response");
     let detections = response
         .into_iter()
@@ -191,6 +243,7 @@ pub async fn detect_text_chat(
     let detector_id = detector_id.clone();
     let request = ChatDetectionRequest::new(messages, tools, params);
     debug!(%detector_id, ?request, "sending detector request");
+    let start = Instant::now();
     let response = client
         .text_chat(&detector_id, request, headers)
         .await
@@ -198,6 +251,7 @@ pub async fn detect_text_chat(
             id: detector_id.clone(),
             error,
         })?;
+    debug_info::record_detector_call(&detector_id, start.elapsed(), false);
     debug!(%detector_id, ?response, "received detector response");
     let detections = response
         .into_iter()
@@ -224,6 +278,7 @@ pub async fn detect_text_context(
     let detector_id = detector_id.clone();
     let request = ContextDocsDetectionRequest::new(content, context_type, context, params.clone());
     debug!(%detector_id, ?request, "sending detector request");
+    let start = Instant::now();
     let response = client
         .text_context_doc(&detector_id, request, headers)
         .await
@@ -231,6 +286,7 @@ pub async fn detect_text_context(
             id: detector_id.clone(),
             error,
         })?;
+    debug_info::record_detector_call(&detector_id, start.elapsed(), false);
     debug!(%detector_id, ?response, "received detector response");
     let detections = response
         .into_iter()
@@ -253,6 +309,7 @@ pub async fn chat_completion(
     let model_id = request.model.clone();
     debug!(%model_id, ?request, "sending chat completions request");
     headers.append(CONTENT_TYPE, JSON_CONTENT_TYPE);
+    let start = Instant::now();
     let response = client
         .chat_completions(request, headers)
         .await
@@ -260,6 +317,7 @@ pub async fn chat_completion(
             id: model_id.clone(),
             error,
         })?;
+    debug_info::record_phase("chat_completion", start.elapsed());
     debug!(%model_id, ?response, "received chat completions response");
     Ok(response)
 }
@@ -300,6 +358,7 @@ pub async fn completion(
     let model_id = request.model.clone();
     debug!(%model_id, ?request, "sending completions request");
     headers.append(CONTENT_TYPE, JSON_CONTENT_TYPE);
+    let start = Instant::now();
     let response = client
         .completions(request, headers)
         .await
@@ -307,6 +366,7 @@ pub async fn completion(
             id: model_id.clone(),
             error,
         })?;
+    debug_info::record_phase("completion", start.elapsed());
     debug!(%model_id, ?response, "received completions response");
     Ok(response)
 }
@@ -358,6 +418,44 @@ pub async fn tokenize(
     Ok(response)
 }
 
+/// Truncates input text to the `truncate_input_tokens` generation parameter per its
+/// `truncation_strategy`, so that truncation is consistent across generation backends.
+///
+/// Returns the text and parameters to send to the generation client, with backend
+/// truncation disabled, and a warning with the removed span if the text was truncated.
+#[instrument(skip_all, fields(model_id))]
+pub async fn truncate_input(
+    client: &GenerationClient,
+    headers: HeaderMap,
+    model_id: String,
+    text: String,
+    params: Option<GenerateParams>,
+) -> Result<(String, Option<GenerateParams>, Option<DetectionWarning>), Error> {
+    let Some(mut params) = params else {
+        return Ok((text, None, None));
+    };
+    let Some(max_tokens) = params.truncate_input_tokens.take().filter(|&v| v > 0) else {
+        return Ok((text, Some(params), None));
+    };
+    let strategy = params.truncation_strategy.take().unwrap_or_default();
+    debug!(%model_id, "sending tokenize request for truncation");
+    let offsets = client
+        .tokenize_offsets(model_id.clone(), text.clone(), headers)
+        .await
+        .map_err(|error| Error::TokenizeRequestFailed {
+            id: model_id.clone(),
+            error,
+        })?;
+    match truncate_tokens(&text, &offsets, max_tokens as usize, strategy) {
+        Some((truncated, (start, end))) => {
+            debug!(%model_id, start, end, "truncated input");
+            let warning = DetectionWarning::input_truncated(max_tokens, start, end);
+            Ok((truncated, Some(params), Some(warning)))
+        }
+        None => Ok((text, Some(params), None)),
+    }
+}
+
 /// Sends generate request to generation client.
 #[instrument(skip_all, fields(model_id))]
 pub async fn generate(
@@ -368,6 +466,7 @@ pub async fn generate(
     params: Option<GenerateParams>,
 ) -> Result<GenerateResponse, Error> {
     debug!(%model_id, "sending generate request");
+    let start = Instant::now();
     let response = client
         .generate(model_id.clone(), text, params, headers)
         .await
@@ -375,6 +474,7 @@ pub async fn generate(
             id: model_id.clone(),
             error,
         })?;
+    debug_info::record_phase("generation", start.elapsed());
     debug!(%model_id, ?response, "received generate response");
     Ok(response)
 }
@@ -404,3 +504,358 @@ pub async fn generate_stream(
         .boxed();
     Ok(stream)
 }
+
+/// Adds warnings to the first message of a generation stream.
+pub fn warn_generation_stream(
+    generation_stream: GenerationStream,
+    warnings: Vec<DetectionWarning>,
+) -> GenerationStream {
+    generation_stream
+        .map(move |(index, result)| {
+            let result = result.map(|mut generation| {
+                if index == 0 {
+                    generation
+                        .warnings
+                        .get_or_insert_default()
+                        .extend(warnings.clone());
+                }
+                generation
+            });
+            (index, result)
+        })
+        .boxed()
+}
+
+/// Paces reads from a generation stream to the client consuming its responses.
+///
+/// Responses are sent to the client through `response_tx`, a channel of at most
+/// `buffer_size` responses. Reads from the generation server are paused while the channel
+/// is full, i.e. while the client falls behind, and stop once the client disconnects.
+/// Generation is aborted with an error if reads are paused for longer than `stall_timeout`.
+pub fn pace_generation_stream<T>(
+    model_id: String,
+    generation_stream: GenerationStream,
+    pacing: GenerationPacing,
+    response_tx: mpsc::WeakSender<T>,
+) -> GenerationStream
+where
+    T: Send + 'static,
+{
+    futures::stream::unfold(Some((generation_stream, 0)), move |state| {
+        let model_id = model_id.clone();
+        let response_tx = response_tx.clone();
+        async move {
+            let (mut generation_stream, index) = state?;
+            // Client disconnected
+            let response_tx = response_tx.upgrade()?;
+            let reserve = async { response_tx.reserve().await.map(drop) };
+            let reserved = match pacing.stall_timeout {
+                Some(stall_timeout) => {
+                    tokio::time::timeout(Duration::from_secs(stall_timeout), reserve).await
+                }
+                None => Ok(reserve.await),
+            };
+            drop(response_tx);
+            match reserved {
+                Ok(Ok(())) => (),
+                // Client disconnected
+                Ok(Err(_)) => return None,
+                Err(_) => {
+                    let stall_timeout = pacing.stall_timeout.unwrap_or_default();
+                    warn!(
+                        %model_id,
+                        stall_timeout,
+                        "generation stream consumer stalled, aborting generation"
+                    );
+                    // Dropping the stream cancels the generation request
+                    drop(generation_stream);
+                    let error = Error::GenerationStalled {
+                        id: model_id,
+                        stall_timeout,
+                    };
+                    return Some(((index, Err(error)), None));
+                }
+            }
+            let (index, result) = generation_stream.next().await?;
+            Some(((index, result), Some((generation_stream, index + 1))))
+        }
+    })
+    .boxed()
+}
+
+/// Relays responses to a paced response channel, see [`pace_generation_stream`].
+pub fn relay_paced_responses<T>(mut response_rx: mpsc::Receiver<T>, paced_tx: mpsc::Sender<T>)
+where
+    T: Send + 'static,
+{
+    tokio::spawn(
+        async move {
+            while let Some(response) = response_rx.recv().await {
+                if paced_tx.send(response).await.is_err() {
+                    return;
+                }
+            }
+        }
+        .in_current_span(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use axum::{Json, Router, http::StatusCode, routing::post};
+
+    use super::*;
+    use crate::{
+        clients::{
+            SharedClientState,
+            detector::{ANALYZED_LENGTHS_HEADER_NAME, ContentAnalysisResponse},
+        },
+        config::{DetectorResponseFormat, ServiceConfig, SizeLimits},
+        models::ClassifiedGeneratedTextStreamResult,
+        utils::test_server::serve,
+    };
+
+    /// Starts a detector that analyzes at most 10 chars of each content,
+    /// returning a detection on the first 2 chars.
+    async fn partial_detector() -> u16 {
+        let app = Router::new().route(
+            "/api/v1/text/contents",
+            post(|Json(request): Json<ContentAnalysisRequest>| async move {
+                let lengths = request
+                    .contents
+                    .iter()
+                    .map(|content| content.chars().count().min(10))
+                    .collect::<Vec<_>>();
+                let partial = request
+                    .contents
+                    .iter()
+                    .any(|content| content.chars().count() > 10);
+                let detections = request
+                    .contents
+                    .iter()
+                    .map(|content| {
+                        vec![ContentAnalysisResponse {
+                            start: 0,
+                            end: 2,
+                            text: content.chars().take(2).collect(),
+                            detection: "test".into(),
+                            detection_type: "test".into(),
+                            detector_id: None,
+                            score: 1.0,
+                            evidence: None,
+                            metadata: Default::default(),
+                            chunk: None,
+                            category: None,
+                        }]
+                    })
+                    .collect::<Vec<_>>();
+                let lengths = lengths
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let status = if partial {
+                    StatusCode::PARTIAL_CONTENT
+                } else {
+                    StatusCode::OK
+                };
+                (
+                    status,
+                    [(ANALYZED_LENGTHS_HEADER_NAME, lengths)],
+                    Json(detections),
+                )
+            }),
+        );
+        let port = serve(app).await;
+        port
+    }
+
+    #[tokio::test]
+    async fn test_detect_text_contents_partial_results() -> Result<(), Error> {
+        let port = partial_detector().await;
+        let client = TextContentsDetectorClient::new(
+            &ServiceConfig::new("localhost".into(), port),
+            None,
+            None,
+            &[],
+            None,
+            SizeLimits::default(),
+            DetectorResponseFormat::default(),
+            None,
+            &SharedClientState::default(),
+        )
+        .await
+        .unwrap();
+        let chunks: Chunks = vec![Chunk {
+            start: 5,
+            end: 30,
+            text: "abcdefghijklmnopqrstuvwxy".into(),
+            ..Default::default()
+        }]
+        .into();
+
+        // Remainder is returned as a partial span
+        let detections = detect_text_contents(
+            &client,
+            HeaderMap::new(),
+            "partial".into(),
+            DetectorParams::new(),
+            chunks.clone(),
+            true,
+            PartialResultsPolicy::Warn,
+        )
+        .await?;
+        assert_eq!(detections.len(), 1);
+        assert_eq!(
+            detections.partial_spans(),
+            [PartialSpan {
+                detector_id: "partial".into(),
+                start: 15,
+                end: 30,
+            }]
+        );
+
+        // Remainder is sent again until fully analyzed
+        let detections = detect_text_contents(
+            &client,
+            HeaderMap::new(),
+            "partial".into(),
+            DetectorParams::new(),
+            chunks,
+            true,
+            PartialResultsPolicy::Redispatch,
+        )
+        .await?;
+        let spans = detections
+            .iter()
+            .map(|detection| (detection.start.unwrap(), detection.text.clone().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [(5, "ab".into()), (15, "kl".into()), (25, "uv".into())]
+        );
+        assert!(detections.partial_spans().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_detect_text_contents_legacy_response_format() -> Result<(), Error> {
+        let app = Router::new().route(
+            "/api/v1/text/contents",
+            post(|| async {
+                Json(serde_json::json!([[{
+                    "start": 0,
+                    "end": 4,
+                    "word": "John",
+                    "entity": "PERSON",
+                    "entity_group": "pii",
+                    "score": 0.9,
+                    "token_count": 1
+                }]]))
+            }),
+        );
+        let port = serve(app).await;
+        let client = TextContentsDetectorClient::new(
+            &ServiceConfig::new("localhost".into(), port),
+            None,
+            None,
+            &[],
+            None,
+            SizeLimits::default(),
+            DetectorResponseFormat::LegacyTokenClassification,
+            None,
+            &SharedClientState::default(),
+        )
+        .await
+        .unwrap();
+        let chunks: Chunks = vec![Chunk {
+            start: 10,
+            end: 24,
+            text: "John is here.".into(),
+            ..Default::default()
+        }]
+        .into();
+
+        let detections = detect_text_contents(
+            &client,
+            HeaderMap::new(),
+            "legacy".into(),
+            DetectorParams::new(),
+            chunks,
+            true,
+            PartialResultsPolicy::Warn,
+        )
+        .await?;
+        let detection = &detections[0];
+        assert_eq!((detection.start, detection.end), (Some(10), Some(14)));
+        assert_eq!(detection.text.as_deref(), Some("John"));
+        assert_eq!(detection.detection_type, "pii");
+        assert_eq!(detection.detection, "PERSON");
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pace_generation_stream() {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let generation_stream = futures::stream::iter(0..)
+            .map({
+                let reads = reads.clone();
+                move |index| {
+                    reads.fetch_add(1, Ordering::SeqCst);
+                    (index, Ok(ClassifiedGeneratedTextStreamResult::default()))
+                }
+            })
+            .boxed();
+        let pacing = GenerationPacing {
+            buffer_size: 2,
+            stall_timeout: Some(1),
+        };
+        let (paced_tx, mut paced_rx) = mpsc::channel(pacing.buffer_size);
+        let mut stream = pace_generation_stream(
+            "model".into(),
+            generation_stream,
+            pacing,
+            paced_tx.downgrade(),
+        );
+        // Responses of the first messages fill the channel of the client
+        for expected in 0..2 {
+            let (index, result) = stream.next().await.unwrap();
+            assert_eq!(index, expected);
+            paced_tx.send(result.unwrap()).await.unwrap();
+        }
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+
+        // Reads resume as the client consumes responses
+        assert!(paced_rx.recv().await.is_some());
+        let (index, result) = stream.next().await.unwrap();
+        assert_eq!(index, 2);
+        paced_tx.send(result.unwrap()).await.unwrap();
+
+        // Generation is aborted once the client stalls, without reading further
+        assert!(matches!(
+            stream.next().await,
+            Some((3, Err(Error::GenerationStalled { .. })))
+        ));
+        assert!(stream.next().await.is_none());
+        assert_eq!(reads.load(Ordering::SeqCst), 3);
+
+        // Reads stop once the client disconnects
+        let generation_stream = futures::stream::iter(0..)
+            .map(|index| (index, Ok(ClassifiedGeneratedTextStreamResult::default())))
+            .boxed();
+        let (paced_tx, paced_rx) = mpsc::channel::<()>(2);
+        let mut stream = pace_generation_stream(
+            "model".into(),
+            generation_stream,
+            pacing,
+            paced_tx.downgrade(),
+        );
+        drop(paced_rx);
+        assert!(stream.next().await.is_none());
+    }
+}
@@ -0,0 +1,153 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+//! Chunker protocol conformance checks
+use std::collections::HashMap;
+
+use futures::future::join_all;
+use tracing::{info, warn};
+
+use super::{client::chunk, utils::slice_codepoints};
+use crate::{
+    clients::chunker::ChunkerClient,
+    orchestrator::{Context, types::Chunks},
+};
+
+/// Text sent to chunkers to check conformance, including multi-byte characters.
+pub const PROBE_TEXT: &str =
+    "This is the first sentence. Ceci est la deuxième phrase! 这是第三句。 Is this 🦀 the fourth?";
+
+/// Sends a probe to each configured chunker and validates the returned chunks.
+///
+/// Returns the inconsistency of each chunker that failed validation. Chunkers that
+/// cannot be reached are not included, as this is reported by health checks.
+pub async fn check_chunkers(ctx: &Context) -> HashMap<String, String> {
+    let chunker_ids = ctx
+        .config
+        .chunkers
+        .iter()
+        .flat_map(|chunkers| chunkers.keys());
+    let results = join_all(chunker_ids.map(|chunker_id| async move {
+        let client = ctx.clients.get_as::<ChunkerClient>(chunker_id)?;
+        match chunk(client, chunker_id.clone(), PROBE_TEXT.into()).await {
+            Ok(chunks) => match validate_chunks(PROBE_TEXT, &chunks) {
+                Ok(()) => {
+                    info!(%chunker_id, "chunker passed conformance check");
+                    None
+                }
+                Err(reason) => Some((chunker_id.clone(), reason)),
+            },
+            Err(error) => {
+                warn!(%chunker_id, %error, "chunker conformance probe failed");
+                None
+            }
+        }
+    }))
+    .await;
+    results.into_iter().flatten().collect()
+}
+
+/// Validates that chunk offsets are consistent with the chunked text.
+///
+/// Chunk offsets must be within bounds and in order, and chunk text must match the
+/// text at its offsets.
+pub fn validate_chunks(text: &str, chunks: &Chunks) -> Result<(), String> {
+    if chunks.is_empty() {
+        return Err("no chunks returned".into());
+    }
+    let len = text.chars().count();
+    let mut previous_start = 0;
+    for (index, chunk) in chunks.iter().enumerate() {
+        if chunk.start > chunk.end {
+            return Err(format!(
+                "chunk {index} start {} is after its end {}",
+                chunk.start, chunk.end
+            ));
+        }
+        if chunk.end > len {
+            return Err(format!(
+                "chunk {index} end {} is beyond text length {len}",
+                chunk.end
+            ));
+        }
+        if chunk.start < previous_start {
+            return Err(format!(
+                "chunk {index} start {} is before previous chunk start {previous_start}",
+                chunk.start
+            ));
+        }
+        let expected = slice_codepoints(text, chunk.start, chunk.end);
+        if *chunk.text != expected {
+            return Err(format!(
+                "chunk {index} text {:?} does not match text {expected:?} at {}..{}",
+                chunk.text, chunk.start, chunk.end
+            ));
+        }
+        previous_start = chunk.start;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orchestrator::types::Chunk;
+
+    fn chunks(spans: &[(usize, usize)]) -> Chunks {
+        spans
+            .iter()
+            .map(|&(start, end)| Chunk {
+                start,
+                end,
+                text: slice_codepoints(PROBE_TEXT, start, end).into(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_chunks() {
+        let len = PROBE_TEXT.chars().count();
+        assert!(validate_chunks(PROBE_TEXT, &chunks(&[(0, 27), (28, 56), (57, len)])).is_ok());
+        assert!(validate_chunks(PROBE_TEXT, &chunks(&[(0, len)])).is_ok());
+
+        assert_eq!(
+            validate_chunks(PROBE_TEXT, &Chunks::default()),
+            Err("no chunks returned".to_string())
+        );
+        assert!(
+            validate_chunks(PROBE_TEXT, &chunks(&[(28, 56), (0, 27)]))
+                .unwrap_err()
+                .contains("before previous chunk start")
+        );
+        let mut out_of_bounds = chunks(&[(0, len)]);
+        out_of_bounds[0].end = len + 1;
+        assert!(
+            validate_chunks(PROBE_TEXT, &out_of_bounds)
+                .unwrap_err()
+                .contains("beyond text length")
+        );
+        // Byte offsets instead of char offsets
+        let mut byte_offsets = chunks(&[(57, len)]);
+        byte_offsets[0].start = PROBE_TEXT.char_indices().nth(57).unwrap().0;
+        byte_offsets[0].end = PROBE_TEXT.len();
+        assert!(
+            validate_chunks(PROBE_TEXT, &byte_offsets)
+                .unwrap_err()
+                .contains("beyond text length")
+        );
+    }
+}
@@ -0,0 +1,195 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Feature flags disabling subsystems at runtime.
+use std::{collections::HashMap, sync::RwLock};
+
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    config::FeaturesConfig,
+    models::{DetectionWarning, DetectorParams},
+};
+
+/// Current state of feature flags, initialized from config.
+#[derive(Debug, Default)]
+pub struct FeatureFlags {
+    flags: RwLock<FeaturesConfig>,
+}
+
+/// Update of feature flags. Flags not set are unchanged.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeatureFlagsUpdate {
+    pub input_detection: Option<bool>,
+    pub output_detection: Option<bool>,
+    pub streaming_detection: Option<bool>,
+    pub audit_logging: Option<bool>,
+}
+
+impl FeatureFlags {
+    pub fn new(config: FeaturesConfig) -> Self {
+        let flags = Self {
+            flags: RwLock::new(config),
+        };
+        flags.warn_disabled();
+        flags
+    }
+
+    /// Returns the current feature flags.
+    pub fn get(&self) -> FeaturesConfig {
+        *self.flags.read().unwrap()
+    }
+
+    /// Applies `update`, returning the updated feature flags.
+    pub fn update(&self, update: FeatureFlagsUpdate) -> FeaturesConfig {
+        let flags = {
+            let mut guard = self.flags.write().unwrap();
+            let flags = &mut *guard;
+            let fields = [
+                (&mut flags.input_detection, update.input_detection),
+                (&mut flags.output_detection, update.output_detection),
+                (&mut flags.streaming_detection, update.streaming_detection),
+                (&mut flags.audit_logging, update.audit_logging),
+            ];
+            for (flag, enabled) in fields {
+                if let Some(enabled) = enabled {
+                    *flag = enabled;
+                }
+            }
+            *flags
+        };
+        warn!(?flags, "feature flags updated");
+        self.warn_disabled();
+        flags
+    }
+
+    fn warn_disabled(&self) {
+        let flags = self.get();
+        let features = [
+            ("input_detection", flags.input_detection),
+            ("output_detection", flags.output_detection),
+            ("streaming_detection", flags.streaming_detection),
+            ("audit_logging", flags.audit_logging),
+        ];
+        for (feature, _) in features.iter().filter(|(_, enabled)| !enabled) {
+            warn!(feature, "feature is disabled");
+        }
+    }
+}
+
+/// Removes detectors of detection phases disabled by `flags`, returning warnings
+/// for the phases skipped. Output detection of `streaming` requests is also
+/// disabled by the `streaming_detection` flag.
+pub fn apply_detection_flags(
+    flags: &FeaturesConfig,
+    streaming: bool,
+    input_detectors: &mut HashMap<String, DetectorParams>,
+    output_detectors: &mut HashMap<String, DetectorParams>,
+) -> Vec<DetectionWarning> {
+    let mut warnings = Vec::new();
+    if !flags.input_detection && !input_detectors.is_empty() {
+        input_detectors.clear();
+        warnings.push(DetectionWarning::input_detection_disabled());
+    }
+    if !output_detectors.is_empty() {
+        let warning = if !flags.output_detection {
+            Some(DetectionWarning::output_detection_disabled())
+        } else if streaming && !flags.streaming_detection {
+            Some(DetectionWarning::streaming_detection_disabled())
+        } else {
+            None
+        };
+        if let Some(warning) = warning {
+            output_detectors.clear();
+            warnings.push(warning);
+        }
+    }
+    warnings
+}
+
+/// Detection phase of detection endpoints analyzing text of a single phase.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DetectionPhase {
+    /// Detection on request inputs, e.g. content and chat messages
+    Input,
+    /// Detection on generated text
+    Output,
+}
+
+/// Removes `detectors` of `phase` if disabled by `flags`, returning a warning if the
+/// phase is skipped.
+pub fn apply_phase_flag(
+    flags: &FeaturesConfig,
+    phase: DetectionPhase,
+    detectors: &mut HashMap<String, DetectorParams>,
+) -> Vec<DetectionWarning> {
+    match phase {
+        DetectionPhase::Input => {
+            apply_detection_flags(flags, false, detectors, &mut HashMap::new())
+        }
+        DetectionPhase::Output => {
+            apply_detection_flags(flags, false, &mut HashMap::new(), detectors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_detection_flags() {
+        let flags = FeatureFlags::default();
+        let update = FeatureFlagsUpdate {
+            streaming_detection: Some(false),
+            ..Default::default()
+        };
+        let flags = flags.update(update);
+        let detectors = HashMap::from([("pii".to_string(), DetectorParams::new())]);
+
+        let (mut input, mut output) = (detectors.clone(), detectors.clone());
+        let warnings = apply_detection_flags(&flags, false, &mut input, &mut output);
+        assert!(warnings.is_empty());
+        assert_eq!(output, detectors);
+
+        let warnings = apply_detection_flags(&flags, true, &mut input, &mut output);
+        assert_eq!(warnings, [DetectionWarning::streaming_detection_disabled()]);
+        assert_eq!(input, detectors);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_apply_phase_flag() {
+        let flags = FeaturesConfig {
+            input_detection: false,
+            ..Default::default()
+        };
+        let detectors = HashMap::from([("pii".to_string(), DetectorParams::new())]);
+
+        let mut output = detectors.clone();
+        let warnings = apply_phase_flag(&flags, DetectionPhase::Output, &mut output);
+        assert!(warnings.is_empty());
+        assert_eq!(output, detectors);
+
+        let mut input = detectors.clone();
+        let warnings = apply_phase_flag(&flags, DetectionPhase::Input, &mut input);
+        assert_eq!(warnings, [DetectionWarning::input_detection_disabled()]);
+        assert!(input.is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Health-weighted selection of interchangeable detectors in detector groups.
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tracing::{info, warn};
+
+use crate::{
+    config::OrchestratorConfig,
+    health::{HealthCheckCache, HealthStatus},
+    orchestrator::{
+        Context, Error,
+        types::{Detections, DetectorSubstitution},
+    },
+};
+
+/// Duration a grouped detector is avoided for after a failure.
+const FAILURE_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Health of grouped detectors, tracked from request failures and health checks.
+#[derive(Debug, Default)]
+pub struct DetectorGroupHealth {
+    /// Time until which detectors are considered unhealthy, by detector ID
+    unhealthy_until: Mutex<HashMap<String, Instant>>,
+}
+
+impl DetectorGroupHealth {
+    fn is_healthy(&self, detector_id: &str) -> bool {
+        self.unhealthy_until
+            .lock()
+            .unwrap()
+            .get(detector_id)
+            .is_none_or(|until| *until <= Instant::now())
+    }
+
+    fn record_success(&self, detector_id: &str) {
+        self.unhealthy_until.lock().unwrap().remove(detector_id);
+    }
+
+    fn record_failure(&self, detector_id: &str) {
+        self.unhealthy_until
+            .lock()
+            .unwrap()
+            .insert(detector_id.to_string(), Instant::now() + FAILURE_COOLDOWN);
+    }
+
+    /// Updates the health of grouped detectors from health check results.
+    pub fn apply_health_checks(&self, config: &OrchestratorConfig, health: &HealthCheckCache) {
+        for detector_id in config.detector_groups.values().flatten() {
+            match health.get(detector_id).map(|result| &result.status) {
+                Some(HealthStatus::Healthy) => self.record_success(detector_id),
+                Some(HealthStatus::Unhealthy) => self.record_failure(detector_id),
+                _ => (),
+            }
+        }
+    }
+
+    /// Returns detectors to try for `detector_id`, in order: the detector itself if
+    /// healthy, healthy members of its group in listed order, then unhealthy members.
+    pub fn candidates<'a>(
+        &self,
+        config: &'a OrchestratorConfig,
+        detector_id: &'a str,
+    ) -> Vec<&'a str> {
+        let Some(members) = config.detector_group(detector_id) else {
+            return vec![detector_id];
+        };
+        let (mut healthy, unhealthy): (Vec<_>, Vec<_>) = std::iter::once(detector_id)
+            .chain(
+                members
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|member| *member != detector_id),
+            )
+            .partition(|member| self.is_healthy(member));
+        healthy.extend(unhealthy);
+        healthy
+    }
+}
+
+/// Runs `detect` with `detector_id`, or with a member of its detector group while it
+/// is unhealthy, failing over to the next member on detector request failures.
+/// Detections of a substitute record the substitution.
+pub async fn detect_with_group<F, Fut>(
+    ctx: &Context,
+    detector_id: &str,
+    detect: F,
+) -> Result<Detections, Error>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<Detections, Error>>,
+{
+    let candidates = ctx.detector_health.candidates(&ctx.config, detector_id);
+    if candidates.len() == 1 {
+        return detect(detector_id.to_string()).await;
+    }
+    let mut last_error = None;
+    for candidate in candidates {
+        match detect(candidate.to_string()).await {
+            Ok(mut detections) => {
+                ctx.detector_health.record_success(candidate);
+                if candidate != detector_id {
+                    info!(
+                        detector_id,
+                        substitute_id = candidate,
+                        monotonic_counter.detector_substitution_count = 1,
+                        "detector substituted by group member"
+                    );
+                    detections.push_substitution(DetectorSubstitution {
+                        detector_id: detector_id.to_string(),
+                        substitute_id: candidate.to_string(),
+                    });
+                }
+                return Ok(detections);
+            }
+            Err(Error::DetectorRequestFailed { id, error })
+                if error.status_code().is_server_error() =>
+            {
+                warn!(detector_id = %id, %error, "grouped detector failed, failing over");
+                ctx.detector_health.record_failure(candidate);
+                last_error = Some(Error::DetectorRequestFailed { id, error });
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Err(last_error.unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::StatusCode;
+
+    use super::*;
+    use crate::{
+        clients::{self, ClientMap},
+        models::DetectionWarningReason,
+        orchestrator::types::Detection,
+    };
+
+    fn group_config() -> OrchestratorConfig {
+        OrchestratorConfig {
+            detector_groups: HashMap::from([(
+                "hap".to_string(),
+                vec!["hap-a".to_string(), "hap-b".to_string()],
+            )]),
+            ..Default::default()
+        }
+    }
+
+    /// Returns a detection of `detector_id`, or an error with `status` for detectors in
+    /// `failing`, recording the detectors called.
+    async fn detect(
+        calls: &Mutex<Vec<String>>,
+        failing: &[(&str, StatusCode)],
+        detector_id: String,
+    ) -> Result<Detections, Error> {
+        calls.lock().unwrap().push(detector_id.clone());
+        match failing.iter().find(|(id, _)| *id == detector_id) {
+            Some((_, code)) => Err(Error::DetectorRequestFailed {
+                id: detector_id,
+                error: clients::Error::Http {
+                    code: *code,
+                    message: "failed".into(),
+                },
+            }),
+            None => Ok(vec![Detection {
+                detector_id: Some(detector_id),
+                ..Default::default()
+            }]
+            .into()),
+        }
+    }
+
+    #[test]
+    fn test_candidates() {
+        let config = OrchestratorConfig {
+            detector_groups: HashMap::from([(
+                "hap".to_string(),
+                vec![
+                    "hap-a".to_string(),
+                    "hap-b".to_string(),
+                    "hap-c".to_string(),
+                ],
+            )]),
+            ..Default::default()
+        };
+        let health = DetectorGroupHealth::default();
+        assert_eq!(health.candidates(&config, "pii"), ["pii"]);
+        assert_eq!(
+            health.candidates(&config, "hap-b"),
+            ["hap-b", "hap-a", "hap-c"]
+        );
+
+        health.record_failure("hap-b");
+        assert_eq!(
+            health.candidates(&config, "hap-b"),
+            ["hap-a", "hap-c", "hap-b"]
+        );
+        health.record_failure("hap-a");
+        assert_eq!(
+            health.candidates(&config, "hap-a"),
+            ["hap-c", "hap-a", "hap-b"]
+        );
+
+        health.record_success("hap-b");
+        assert_eq!(
+            health.candidates(&config, "hap-a"),
+            ["hap-b", "hap-c", "hap-a"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_detect_with_group_failover() {
+        let ctx = Context::new(group_config(), ClientMap::new());
+        let calls = Mutex::new(Vec::new());
+
+        // A failing member fails over to the next member
+        let failing = [("hap-a", StatusCode::SERVICE_UNAVAILABLE)];
+        let detections = detect_with_group(&ctx, "hap-a", |id| detect(&calls, &failing, id))
+            .await
+            .unwrap();
+        assert_eq!(*calls.lock().unwrap(), ["hap-a", "hap-b"]);
+        assert_eq!(detections[0].detector_id.as_deref(), Some("hap-b"));
+        let warnings = detections.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].id,
+            Some(DetectionWarningReason::DetectorSubstituted)
+        );
+
+        // The failed member is skipped while unhealthy
+        calls.lock().unwrap().clear();
+        let detections = detect_with_group(&ctx, "hap-a", |id| detect(&calls, &[], id))
+            .await
+            .unwrap();
+        assert_eq!(*calls.lock().unwrap(), ["hap-b"]);
+        assert_eq!(detections.warnings().len(), 1);
+
+        // A healthy member is not substituted
+        calls.lock().unwrap().clear();
+        let detections = detect_with_group(&ctx, "hap-b", |id| detect(&calls, &[], id))
+            .await
+            .unwrap();
+        assert_eq!(*calls.lock().unwrap(), ["hap-b"]);
+        assert!(detections.warnings().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_detect_with_group_errors() {
+        let ctx = Context::new(group_config(), ClientMap::new());
+        let calls = Mutex::new(Vec::new());
+
+        // Client errors are returned without failing over
+        let failing = [("hap-a", StatusCode::UNPROCESSABLE_ENTITY)];
+        let result = detect_with_group(&ctx, "hap-a", |id| detect(&calls, &failing, id)).await;
+        assert!(matches!(
+            result,
+            Err(Error::DetectorRequestFailed { ref id, .. }) if id == "hap-a"
+        ));
+        assert_eq!(*calls.lock().unwrap(), ["hap-a"]);
+
+        // The last error is returned once all members fail
+        calls.lock().unwrap().clear();
+        let failing = [
+            ("hap-a", StatusCode::SERVICE_UNAVAILABLE),
+            ("hap-b", StatusCode::BAD_GATEWAY),
+        ];
+        let result = detect_with_group(&ctx, "hap-a", |id| detect(&calls, &failing, id)).await;
+        assert!(matches!(
+            result,
+            Err(Error::DetectorRequestFailed { ref id, .. }) if id == "hap-b"
+        ));
+        assert_eq!(*calls.lock().unwrap(), ["hap-a", "hap-b"]);
+    }
+}
@@ -0,0 +1,450 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Short-lived store of detections still running when time-boxed requests returned.
+use std::{
+    collections::{BTreeSet, HashMap},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use opentelemetry::trace::TraceId;
+use tokio::{sync::mpsc, task::AbortHandle, time::Instant};
+use tracing::{Instrument, warn};
+
+use super::{alerts::Alerts, propagate_task_locals};
+use crate::{
+    config::DetectionsFilter,
+    models::DetectorParams,
+    orchestrator::{Error, types::Detections},
+    utils::trace::current_trace_id,
+};
+
+/// Time pending detections are retained for, from when they were stored.
+pub const PENDING_DETECTIONS_TTL: Duration = Duration::from_secs(300);
+/// Maximum number of pending detections stored.
+pub const MAX_PENDING_DETECTIONS: usize = 1000;
+
+/// Detections of detectors running in spawned tasks.
+#[derive(Debug)]
+pub struct PendingDetections {
+    /// IDs of detectors still running
+    detectors: BTreeSet<String>,
+    /// Detections of each detector, sent as it completes
+    results: mpsc::Receiver<(String, Result<Detections, Error>)>,
+    /// Tasks of running detectors, aborted when dropped
+    tasks: Vec<AbortHandle>,
+    /// Filter for detections returned in responses
+    pub detections_filter: DetectionsFilter,
+}
+
+impl PendingDetections {
+    pub fn new(
+        detectors: BTreeSet<String>,
+        results: mpsc::Receiver<(String, Result<Detections, Error>)>,
+        tasks: Vec<AbortHandle>,
+        detections_filter: DetectionsFilter,
+    ) -> Self {
+        Self {
+            detectors,
+            results,
+            tasks,
+            detections_filter,
+        }
+    }
+
+    /// Returns IDs of detectors still running.
+    pub fn detectors(&self) -> Vec<String> {
+        self.detectors.iter().cloned().collect()
+    }
+
+    /// Returns `true` if all detectors completed.
+    pub fn is_complete(&self) -> bool {
+        self.detectors.is_empty()
+    }
+
+    /// Receives detections of running detectors until all complete or `max_wait` elapses.
+    /// Fails with the first error of a detector.
+    pub async fn collect(&mut self, max_wait: Option<Duration>) -> Result<Detections, Error> {
+        let deadline = max_wait.map(|max_wait| Instant::now() + max_wait);
+        let mut detections = Detections::new();
+        while !self.detectors.is_empty() {
+            let received = match deadline {
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline, self.results.recv()).await {
+                        Ok(received) => received,
+                        Err(_) => break,
+                    }
+                }
+                None => self.results.recv().await,
+            };
+            let Some((detector_id, result)) = received else {
+                return Err(Error::Cancelled);
+            };
+            self.detectors.remove(&detector_id);
+            detections.append(result?);
+        }
+        Ok(detections)
+    }
+}
+
+impl Drop for PendingDetections {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Detectors dispatched in spawned tasks one at a time, e.g. as a request body is parsed.
+#[derive(Debug)]
+pub struct DispatchedDetections {
+    /// Parameters of dispatched detectors, by detector ID
+    detectors: HashMap<String, DetectorParams>,
+    results_tx: mpsc::Sender<(String, Result<Detections, Error>)>,
+    results_rx: mpsc::Receiver<(String, Result<Detections, Error>)>,
+    /// Tasks of dispatched detectors, aborted when dropped
+    tasks: Vec<AbortHandle>,
+    /// Alerts held for the request until its detections are collected
+    held_alerts: Option<(Arc<Alerts>, TraceId)>,
+}
+
+impl Default for DispatchedDetections {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DispatchedDetections {
+    pub fn new() -> Self {
+        // Detectors completed while results are not yet received wait for capacity
+        let (results_tx, results_rx) = mpsc::channel(32);
+        Self {
+            detectors: HashMap::new(),
+            results_tx,
+            results_rx,
+            tasks: Vec::new(),
+            held_alerts: None,
+        }
+    }
+
+    /// Holds high-severity alerts of the current request until its detections are
+    /// collected, e.g. while the request is not validated yet.
+    pub fn hold_alerts(&mut self, alerts: Option<&Arc<Alerts>>) {
+        if let (Some(alerts), None) = (alerts, &self.held_alerts) {
+            let trace_id = current_trace_id();
+            alerts.hold(trace_id);
+            self.held_alerts = Some((alerts.clone(), trace_id));
+        }
+    }
+
+    /// Rejects the request, aborting dispatched detectors and suppressing its alerts.
+    pub fn reject(mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        if let Some((alerts, trace_id)) = self.held_alerts.take() {
+            alerts.suppress(trace_id);
+            alerts.discard(trace_id);
+        }
+    }
+
+    /// Spawns `detection` of `detector_id` with `params`, unless already dispatched.
+    pub fn dispatch<F>(&mut self, detector_id: String, params: DetectorParams, detection: F)
+    where
+        F: Future<Output = Result<Detections, Error>> + Send + 'static,
+    {
+        if self.detectors.contains_key(&detector_id) {
+            return;
+        }
+        let results_tx = self.results_tx.clone();
+        let id = detector_id.clone();
+        let task = tokio::spawn(
+            propagate_task_locals(async move {
+                let _ = results_tx.send((id, detection.await)).await;
+            })
+            .in_current_span(),
+        );
+        self.tasks.push(task.abort_handle());
+        self.detectors.insert(detector_id, params);
+    }
+
+    /// Returns `true` if exactly `detectors` were dispatched, with the same parameters.
+    pub fn matches(&self, detectors: &HashMap<String, DetectorParams>) -> bool {
+        self.detectors == *detectors
+    }
+
+    /// Returns pending detections of the dispatched detectors.
+    pub fn into_pending(mut self, detections_filter: DetectionsFilter) -> PendingDetections {
+        if let Some((alerts, trace_id)) = self.held_alerts.take() {
+            alerts.release(trace_id);
+        }
+        let detectors = self.detectors.keys().cloned().collect();
+        let (_, closed) = mpsc::channel(1);
+        let results = std::mem::replace(&mut self.results_rx, closed);
+        PendingDetections::new(
+            detectors,
+            results,
+            std::mem::take(&mut self.tasks),
+            detections_filter,
+        )
+    }
+}
+
+impl Drop for DispatchedDetections {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        // Detections of aborted detectors are not alerted on
+        if let Some((alerts, trace_id)) = self.held_alerts.take() {
+            alerts.discard(trace_id);
+        }
+    }
+}
+
+/// Pending detections by continuation token, expiring after [`PENDING_DETECTIONS_TTL`].
+///
+/// Expired entries are dropped on each access, aborting their detectors. Once the store
+/// is full, the oldest entry is dropped for a new one. Entries are bound to the API
+/// consumer of the request that stored them.
+#[derive(Debug)]
+pub struct PendingDetectionsStore {
+    pending: Mutex<HashMap<String, PendingEntry>>,
+    /// Maximum number of entries
+    capacity: usize,
+}
+
+#[derive(Debug)]
+struct PendingEntry {
+    expires_at: Instant,
+    /// API consumer of the request, if authenticated
+    consumer: Option<String>,
+    pending: PendingDetections,
+}
+
+impl Default for PendingDetectionsStore {
+    fn default() -> Self {
+        Self::new(MAX_PENDING_DETECTIONS)
+    }
+}
+
+impl PendingDetectionsStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            pending: Mutex::default(),
+            capacity,
+        }
+    }
+
+    /// Stores `pending` of `consumer`, returning its continuation token.
+    pub fn insert(&self, pending: PendingDetections, consumer: Option<String>) -> String {
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let mut store = self.pending.lock().unwrap();
+        remove_expired(&mut store);
+        if store.len() >= self.capacity {
+            let oldest = store
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires_at)
+                .map(|(token, _)| token.clone());
+            if let Some(oldest) = oldest {
+                warn!("pending detections store is full, dropping oldest pending detections");
+                store.remove(&oldest);
+            }
+        }
+        store.insert(
+            token.clone(),
+            PendingEntry {
+                expires_at: Instant::now() + PENDING_DETECTIONS_TTL,
+                consumer,
+                pending,
+            },
+        );
+        token
+    }
+
+    /// Removes and returns the pending detections of `token` stored by `consumer`, if
+    /// stored and not expired. Pending detections of other consumers are not found,
+    /// and left stored.
+    pub fn take(&self, token: &str, consumer: Option<&str>) -> Option<PendingDetections> {
+        let mut store = self.pending.lock().unwrap();
+        remove_expired(&mut store);
+        if store.get(token)?.consumer.as_deref() != consumer {
+            return None;
+        }
+        store.remove(token).map(|entry| entry.pending)
+    }
+
+    /// Returns the number of stored entries, including expired ones not yet dropped.
+    pub fn len(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Returns `true` if no entries are stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Drops expired entries of `store`, aborting their detectors.
+fn remove_expired(store: &mut HashMap<String, PendingEntry>) {
+    let now = Instant::now();
+    store.retain(|_, entry| entry.expires_at > now);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::AlertsConfig, orchestrator::types::Detection, utils::test_events::CapturedEvents,
+    };
+
+    #[tokio::test]
+    async fn test_pending_detections() {
+        let (tx, rx) = mpsc::channel(2);
+        let detectors = BTreeSet::from(["fast".to_string(), "slow".to_string()]);
+        let mut pending =
+            PendingDetections::new(detectors, rx, vec![], DetectionsFilter::default());
+        tx.send(("fast".into(), Ok(Detections::new())))
+            .await
+            .unwrap();
+        pending
+            .collect(Some(Duration::from_millis(100)))
+            .await
+            .unwrap();
+        assert_eq!(pending.detectors(), ["slow"]);
+
+        let store = PendingDetectionsStore::default();
+        let token = store.insert(pending, Some("tenant-a".into()));
+        tx.send(("slow".into(), Ok(Detections::new())))
+            .await
+            .unwrap();
+        // Pending detections of other consumers are not found, nor removed
+        assert!(store.take(&token, Some("tenant-b")).is_none());
+        assert!(store.take(&token, None).is_none());
+        let mut pending = store.take(&token, Some("tenant-a")).unwrap();
+        pending.collect(None).await.unwrap();
+        assert!(pending.is_complete());
+        assert!(store.take(&token, Some("tenant-a")).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dispatched_detections() {
+        let params = DetectorParams::new();
+        let mut dispatched = DispatchedDetections::new();
+        dispatched.dispatch("fast".into(), params.clone(), async {
+            Ok(Detections::new())
+        });
+        dispatched.dispatch(
+            "slow".into(),
+            params.clone(),
+            std::future::pending::<Result<Detections, Error>>(),
+        );
+        // Detectors are dispatched once
+        dispatched.dispatch("fast".into(), params.clone(), async {
+            Err(Error::Cancelled)
+        });
+        assert!(dispatched.matches(&HashMap::from([
+            ("fast".to_string(), params.clone()),
+            ("slow".to_string(), params.clone()),
+        ])));
+        assert!(!dispatched.matches(&HashMap::from([("fast".to_string(), params.clone())])));
+
+        let mut pending = dispatched.into_pending(DetectionsFilter::default());
+        pending
+            .collect(Some(Duration::from_millis(100)))
+            .await
+            .unwrap();
+        assert_eq!(pending.detectors(), ["slow"]);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_dispatched_detections() {
+        let (events, _guard) = CapturedEvents::capture();
+        let alerts = Arc::new(Alerts::new(AlertsConfig {
+            webhooks: Vec::new(),
+            min_score: Some(0.9),
+            max_retries: 0,
+        }));
+        let detections = Detections::from(vec![Detection {
+            score: 0.95,
+            ..Default::default()
+        }]);
+        let mut dispatched = DispatchedDetections::new();
+        dispatched.hold_alerts(Some(&alerts));
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        let (dropped_tx, dropped_rx) = tokio::sync::oneshot::channel::<()>();
+        dispatched.dispatch("slow".into(), DetectorParams::new(), async move {
+            let _dropped = dropped_tx;
+            let _ = started_tx.send(());
+            std::future::pending::<Result<Detections, Error>>().await
+        });
+        started_rx.await.unwrap();
+        // High-severity detections of the request are held until it is validated
+        alerts.high_severity(current_trace_id(), &detections);
+
+        // Rejected requests abort their detectors and discard their alerts
+        dispatched.reject();
+        assert!(dropped_rx.await.is_err());
+        alerts.high_severity(current_trace_id(), &detections);
+        assert!(
+            events
+                .with_field("monotonic_counter.alert_count")
+                .is_empty()
+        );
+    }
+
+    fn pending() -> PendingDetections {
+        let (_, rx) = mpsc::channel(1);
+        PendingDetections::new(
+            BTreeSet::from(["slow".to_string()]),
+            rx,
+            vec![],
+            DetectionsFilter::default(),
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pending_detections_store_expiry() {
+        let store = PendingDetectionsStore::default();
+        let expired = store.insert(pending(), None);
+        tokio::time::advance(PENDING_DETECTIONS_TTL / 2).await;
+        let unexpired = store.insert(pending(), None);
+        assert_eq!(store.len(), 2);
+
+        // Expired entries are dropped on read
+        tokio::time::advance(PENDING_DETECTIONS_TTL / 2).await;
+        assert!(store.take(&expired, None).is_none());
+        assert_eq!(store.len(), 1);
+        assert!(store.take(&unexpired, None).is_some());
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pending_detections_store_capacity() {
+        // The oldest entry is dropped once full
+        let store = PendingDetectionsStore::new(2);
+        let oldest = store.insert(pending(), None);
+        let token = store.insert(pending(), None);
+        let newest = store.insert(pending(), None);
+        assert_eq!(store.len(), 2);
+        assert!(store.take(&oldest, None).is_none());
+        assert!(store.take(&token, None).is_some());
+        assert!(store.take(&newest, None).is_some());
+    }
+}
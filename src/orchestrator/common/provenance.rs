@@ -0,0 +1,410 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Provenance of generated text, letting downstream systems verify text passed
+//! through guardrails unmodified.
+//!
+//! The policy decision is `flagged` if a response returns output detections of the
+//! text, i.e. detections passing the request's `min_score` and `top_k` filters, and
+//! `passed` otherwise. Signatures bind the text and decision to the request's trace ID
+//! and a timestamp, so signed provenance cannot be replayed for other requests.
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use opentelemetry::trace::TraceId;
+use tokio::sync::mpsc;
+use tracing::Instrument;
+
+use super::alerts::sign;
+use crate::{
+    clients::openai::{ChatCompletion, ChatCompletionChunk, ChatDetections, ChoiceProvenance},
+    config::ProvenanceConfig,
+    models::{ClassifiedGeneratedTextStreamResult, ContentProvenance, PolicyDecision},
+    orchestrator::Error,
+    utils::secrets,
+};
+
+/// Returns the SHA-256 hash of `text`, as `sha256=<hex digest>`.
+pub fn content_hash(text: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, text.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("sha256={digest}")
+}
+
+/// Creates provenance of generated text, signed if a signing key is configured.
+#[derive(Clone)]
+pub struct Provenance {
+    /// Signing key, loaded once on start-up
+    signing_key: Option<Arc<[u8]>>,
+}
+
+impl Provenance {
+    /// Creates provenance of generated text, loading the configured signing key.
+    pub async fn new(config: &ProvenanceConfig) -> Result<Self, secrets::Error> {
+        let signing_key = match &config.signing_key {
+            Some(signing_key) => Some(signing_key.load().await?),
+            None => None,
+        };
+        Ok(Self { signing_key })
+    }
+
+    /// Returns the provenance of generated `text` of request `trace_id`, flagged if the
+    /// response returns output detections of the text.
+    pub fn provenance(&self, trace_id: TraceId, text: &str, flagged: bool) -> ContentProvenance {
+        let content_hash = content_hash(text);
+        let decision = if flagged {
+            PolicyDecision::Flagged
+        } else {
+            PolicyDecision::Passed
+        };
+        let trace_id = trace_id.to_string();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let signature = self.signing_key.as_ref().map(|signing_key| {
+            let message = format!("{content_hash}:{decision}:{trace_id}:{timestamp}");
+            sign(signing_key, message.as_bytes())
+        });
+        ContentProvenance {
+            content_hash,
+            decision,
+            trace_id,
+            timestamp,
+            signature,
+        }
+    }
+
+    /// Adds provenance of the message content of each choice to a chat completion.
+    pub fn add_to_chat_completion(&self, trace_id: TraceId, chat_completion: &mut ChatCompletion) {
+        let flagged = flagged_choices(chat_completion.detections.as_ref());
+        chat_completion.provenance = chat_completion
+            .choices
+            .iter()
+            .filter_map(|choice| {
+                let text = choice.message.content.as_deref()?;
+                Some(ChoiceProvenance {
+                    choice_index: choice.index,
+                    provenance: self.provenance(trace_id, text, flagged.contains(&choice.index)),
+                })
+            })
+            .collect();
+    }
+
+    /// Forwards a classification with text generation stream, adding provenance of the
+    /// generated text, concatenated from its messages, to the last message. The last
+    /// message is held back until the stream completes. Failed streams carry no provenance.
+    pub fn stream(
+        &self,
+        trace_id: TraceId,
+        mut response_rx: mpsc::Receiver<Result<ClassifiedGeneratedTextStreamResult, Error>>,
+    ) -> mpsc::Receiver<Result<ClassifiedGeneratedTextStreamResult, Error>> {
+        let provenance = self.clone();
+        let (response_tx, stream_rx) = mpsc::channel(128);
+        tokio::spawn(
+            async move {
+                let mut text = String::new();
+                let mut flagged = false;
+                let mut failed = false;
+                let mut held = None;
+                while let Some(result) = response_rx.recv().await {
+                    let result = match result {
+                        Ok(response) => {
+                            text.push_str(response.generated_text.as_deref().unwrap_or_default());
+                            flagged |= response
+                                .token_classification_results
+                                .output
+                                .as_ref()
+                                .is_some_and(|output| !output.is_empty());
+                            match held.replace(response) {
+                                Some(previous) => Ok(previous),
+                                None => continue,
+                            }
+                        }
+                        Err(error) => {
+                            failed = true;
+                            if let Some(previous) = held.take() {
+                                if response_tx.send(Ok(previous)).await.is_err() {
+                                    return;
+                                }
+                            }
+                            Err(error)
+                        }
+                    };
+                    if response_tx.send(result).await.is_err() {
+                        return;
+                    }
+                }
+                if let Some(mut last) = held {
+                    if !failed && !text.is_empty() {
+                        last.provenance = Some(provenance.provenance(trace_id, &text, flagged));
+                    }
+                    let _ = response_tx.send(Ok(last)).await;
+                }
+            }
+            .in_current_span(),
+        );
+        stream_rx
+    }
+
+    /// Forwards a chat completion chunk stream, adding provenance of the message content
+    /// of each choice, concatenated from its deltas, to the last chunk. The last chunk is
+    /// held back until the stream completes. Failed streams carry no provenance.
+    pub fn chat_stream(
+        &self,
+        trace_id: TraceId,
+        mut chunk_rx: mpsc::Receiver<Result<Option<ChatCompletionChunk>, Error>>,
+    ) -> mpsc::Receiver<Result<Option<ChatCompletionChunk>, Error>> {
+        let provenance = self.clone();
+        let (chunk_tx, stream_rx) = mpsc::channel(128);
+        tokio::spawn(
+            async move {
+                let mut texts = BTreeMap::<u32, String>::new();
+                let mut flagged = Vec::new();
+                let mut failed = false;
+                let mut held: Option<ChatCompletionChunk> = None;
+                while let Some(result) = chunk_rx.recv().await {
+                    let result = match result {
+                        Ok(Some(chunk)) => {
+                            for choice in &chunk.choices {
+                                texts
+                                    .entry(choice.index)
+                                    .or_default()
+                                    .push_str(choice.delta.content.as_deref().unwrap_or_default());
+                            }
+                            flagged.extend(flagged_choices(chunk.detections.as_ref()));
+                            match held.replace(chunk) {
+                                Some(previous) => Ok(Some(previous)),
+                                None => continue,
+                            }
+                        }
+                        // The stream completed
+                        Ok(None) => break,
+                        Err(error) => {
+                            failed = true;
+                            if let Some(previous) = held.take() {
+                                if chunk_tx.send(Ok(Some(previous))).await.is_err() {
+                                    return;
+                                }
+                            }
+                            Err(error)
+                        }
+                    };
+                    if chunk_tx.send(result).await.is_err() {
+                        return;
+                    }
+                }
+                if let Some(mut last) = held {
+                    if !failed {
+                        last.provenance = texts
+                            .iter()
+                            .filter(|(_, text)| !text.is_empty())
+                            .map(|(choice_index, text)| ChoiceProvenance {
+                                choice_index: *choice_index,
+                                provenance: provenance.provenance(
+                                    trace_id,
+                                    text,
+                                    flagged.contains(choice_index),
+                                ),
+                            })
+                            .collect();
+                    }
+                    if chunk_tx.send(Ok(Some(last))).await.is_err() {
+                        return;
+                    }
+                }
+                if !failed {
+                    let _ = chunk_tx.send(Ok(None)).await;
+                }
+            }
+            .in_current_span(),
+        );
+        stream_rx
+    }
+}
+
+/// Returns the indices of choices with output detections.
+fn flagged_choices(detections: Option<&ChatDetections>) -> Vec<u32> {
+    detections
+        .iter()
+        .flat_map(|detections| &detections.output)
+        .filter(|output| !output.results.is_empty())
+        .map(|output| output.choice_index)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::TokenClassificationResult, utils::secrets::SecretSource};
+
+    fn trace_id() -> TraceId {
+        TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_provenance() {
+        let provenance = Provenance::new(&ProvenanceConfig { signing_key: None })
+            .await
+            .unwrap();
+        let passed = provenance.provenance(trace_id(), "hello", false);
+        assert_eq!(
+            passed.content_hash,
+            "sha256=2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(passed.decision, PolicyDecision::Passed);
+        assert_eq!(passed.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert!(passed.timestamp > 0);
+        assert!(passed.signature.is_none());
+
+        // Signatures cover the content hash, decision, trace ID and timestamp
+        let path =
+            std::env::temp_dir().join(format!("{}-signing-key", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&path, "secret").unwrap();
+        let provenance = Provenance::new(&ProvenanceConfig {
+            signing_key: Some(SecretSource::File(path.clone())),
+        })
+        .await
+        .unwrap();
+        // The signing key is loaded once
+        std::fs::remove_file(&path).unwrap();
+        let passed = provenance.provenance(trace_id(), "hello", false);
+        let flagged = provenance.provenance(trace_id(), "hello", true);
+        assert_eq!(flagged.decision, PolicyDecision::Flagged);
+        assert_eq!(
+            passed.signature,
+            Some(sign(
+                b"secret",
+                format!(
+                    "{}:passed:{}:{}",
+                    passed.content_hash, passed.trace_id, passed.timestamp
+                )
+                .as_bytes()
+            ))
+        );
+        assert_ne!(passed.signature, flagged.signature);
+        let other = provenance.provenance(TraceId::from_hex("1").unwrap(), "hello", false);
+        assert_ne!(passed.signature, other.signature);
+    }
+
+    #[tokio::test]
+    async fn test_stream() {
+        let provenance = Provenance::new(&ProvenanceConfig { signing_key: None })
+            .await
+            .unwrap();
+        let (response_tx, response_rx) = mpsc::channel(4);
+        let mut stream_rx = provenance.stream(trace_id(), response_rx);
+        for (text, output) in [
+            ("Hello ", None),
+            (
+                "world",
+                Some(vec![TokenClassificationResult {
+                    start: 0,
+                    end: 5,
+                    word: "world".into(),
+                    entity: "has_world".into(),
+                    entity_group: "has_world".into(),
+                    detector_id: Some("detector".into()),
+                    score: 0.9,
+                    token_count: None,
+                    chunk: None,
+                    category: None,
+                }]),
+            ),
+        ] {
+            let mut response = ClassifiedGeneratedTextStreamResult {
+                generated_text: Some(text.into()),
+                ..Default::default()
+            };
+            response.token_classification_results.output = output;
+            response_tx.send(Ok(response)).await.unwrap();
+        }
+        drop(response_tx);
+        let first = stream_rx.recv().await.unwrap().unwrap();
+        assert!(first.provenance.is_none());
+        let last = stream_rx.recv().await.unwrap().unwrap();
+        let last_provenance = last.provenance.unwrap();
+        assert_eq!(last_provenance.content_hash, content_hash("Hello world"));
+        assert_eq!(last_provenance.decision, PolicyDecision::Flagged);
+        assert!(stream_rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream() {
+        let provenance = Provenance::new(&ProvenanceConfig { signing_key: None })
+            .await
+            .unwrap();
+        let (chunk_tx, chunk_rx) = mpsc::channel(4);
+        let mut stream_rx = provenance.chat_stream(trace_id(), chunk_rx);
+        for (index, text) in [(0, "Hello "), (1, "Hi"), (0, "world")] {
+            let mut chunk: ChatCompletionChunk = serde_json::from_value(serde_json::json!({
+                "id": "chatcmpl-test",
+                "object": "chat.completion.chunk",
+                "created": 1,
+                "model": "test",
+                "choices": [{ "index": index, "delta": { "content": text } }],
+            }))
+            .unwrap();
+            if index == 1 {
+                chunk.detections = serde_json::from_value(serde_json::json!({
+                    "output": [{
+                        "choice_index": 1,
+                        "results": [{
+                            "start": 0,
+                            "end": 2,
+                            "text": "Hi",
+                            "detection": "greeting",
+                            "detection_type": "greeting",
+                            "detector_id": "detector",
+                            "score": 0.9,
+                        }],
+                    }],
+                }))
+                .unwrap();
+            }
+            chunk_tx.send(Ok(Some(chunk))).await.unwrap();
+        }
+        chunk_tx.send(Ok(None)).await.unwrap();
+        for _ in 0..2 {
+            let chunk = stream_rx.recv().await.unwrap().unwrap().unwrap();
+            assert!(chunk.provenance.is_empty());
+        }
+        let last = stream_rx.recv().await.unwrap().unwrap().unwrap();
+        assert_eq!(last.provenance.len(), 2);
+        assert_eq!(last.provenance[0].choice_index, 0);
+        assert_eq!(
+            last.provenance[0].provenance.content_hash,
+            content_hash("Hello world")
+        );
+        assert_eq!(
+            last.provenance[0].provenance.decision,
+            PolicyDecision::Passed
+        );
+        assert_eq!(last.provenance[1].choice_index, 1);
+        assert_eq!(
+            last.provenance[1].provenance.decision,
+            PolicyDecision::Flagged
+        );
+        assert!(matches!(stream_rx.recv().await, Some(Ok(None))));
+    }
+}
@@ -0,0 +1,191 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{LazyLock, Mutex},
+};
+
+use serde::Serialize;
+
+/// Maximum number of recent samples retained per detector.
+const DEFAULT_MAX_SAMPLES: usize = 1000;
+/// Number of equal-width buckets in score histograms.
+const HISTOGRAM_BUCKETS: usize = 10;
+
+/// Global recorder of recent detector scores, used for threshold tuning.
+pub static SCORE_SAMPLES: LazyLock<ScoreSamples> = LazyLock::new(ScoreSamples::default);
+
+/// Records the maximum of a detector's scores for an input, or `0.0` if there are none.
+pub fn record_max_score(detector_id: &str, scores: impl IntoIterator<Item = f64>) {
+    SCORE_SAMPLES.record(detector_id, scores.into_iter().fold(0.0, f64::max));
+}
+
+/// Per-detector ring buffers of recent scores.
+///
+/// Each sample is the maximum score a detector produced for an input before
+/// thresholds were applied, or `0.0` if it produced no detections.
+#[derive(Debug)]
+pub struct ScoreSamples {
+    samples: Mutex<HashMap<String, VecDeque<f64>>>,
+    max_samples: usize,
+}
+
+impl Default for ScoreSamples {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_SAMPLES)
+    }
+}
+
+impl ScoreSamples {
+    pub fn new(max_samples: usize) -> Self {
+        Self {
+            samples: Mutex::new(HashMap::new()),
+            max_samples,
+        }
+    }
+
+    /// Records a sample for a detector, evicting the oldest sample if full.
+    pub fn record(&self, detector_id: &str, score: f64) {
+        let mut samples = self.samples.lock().unwrap();
+        let samples = samples.entry(detector_id.to_string()).or_default();
+        if samples.len() >= self.max_samples {
+            samples.pop_front();
+        }
+        samples.push_back(score);
+    }
+
+    /// Builds a report of score distributions and block rates at the given thresholds.
+    pub fn report(
+        &self,
+        thresholds: &[f64],
+        default_threshold: impl Fn(&str) -> Option<f64>,
+    ) -> ThresholdReport {
+        let samples = self.samples.lock().unwrap();
+        let detectors = samples
+            .iter()
+            .map(|(detector_id, samples)| {
+                let mut scores = samples.iter().copied().collect::<Vec<_>>();
+                scores.sort_by(f64::total_cmp);
+                let report =
+                    DetectorScoreReport::new(&scores, thresholds, default_threshold(detector_id));
+                (detector_id.clone(), report)
+            })
+            .collect();
+        ThresholdReport { detectors }
+    }
+}
+
+/// Score distributions and simulated block rates of detectors.
+#[derive(Debug, Clone, Serialize)]
+pub struct ThresholdReport {
+    pub detectors: BTreeMap<String, DetectorScoreReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectorScoreReport {
+    /// Number of samples
+    pub samples: usize,
+    /// Configured default threshold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_threshold: Option<f64>,
+    /// Score quantiles, keyed by `p50`, `p90`, `p95` and `p99`
+    pub quantiles: BTreeMap<&'static str, f64>,
+    /// Sample counts of equal-width score buckets over `[0, 1]`
+    pub histogram: Vec<usize>,
+    /// Fraction of samples that would be blocked at each threshold
+    pub block_rates: Vec<BlockRate>,
+}
+
+impl DetectorScoreReport {
+    /// Builds a report from sorted scores.
+    fn new(scores: &[f64], thresholds: &[f64], default_threshold: Option<f64>) -> Self {
+        let quantiles = if scores.is_empty() {
+            BTreeMap::new()
+        } else {
+            [("p50", 0.5), ("p90", 0.9), ("p95", 0.95), ("p99", 0.99)]
+                .into_iter()
+                .map(|(name, q)| {
+                    let index = ((scores.len() - 1) as f64 * q).round() as usize;
+                    (name, scores[index])
+                })
+                .collect()
+        };
+        let mut histogram = vec![0; HISTOGRAM_BUCKETS];
+        for score in scores {
+            let bucket = (score.clamp(0.0, 1.0) * HISTOGRAM_BUCKETS as f64) as usize;
+            histogram[bucket.min(HISTOGRAM_BUCKETS - 1)] += 1;
+        }
+        let block_rates = thresholds
+            .iter()
+            .map(|&threshold| {
+                // Detections are kept when their score is at or above the threshold
+                let blocked = scores.len() - scores.partition_point(|&score| score < threshold);
+                BlockRate {
+                    threshold,
+                    block_rate: if scores.is_empty() {
+                        0.0
+                    } else {
+                        blocked as f64 / scores.len() as f64
+                    },
+                }
+            })
+            .collect();
+        Self {
+            samples: scores.len(),
+            default_threshold,
+            quantiles,
+            histogram,
+            block_rates,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockRate {
+    pub threshold: f64,
+    pub block_rate: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold_report() {
+        let samples = ScoreSamples::new(10);
+        // Oldest samples are evicted
+        for _ in 0..5 {
+            samples.record("hap", 0.99);
+        }
+        for score in [0.0, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9] {
+            samples.record("hap", score);
+        }
+        let report = samples.report(&[0.0, 0.5, 0.85, 1.0], |_| Some(0.5));
+        let hap = &report.detectors["hap"];
+        assert_eq!(hap.samples, 10);
+        assert_eq!(hap.default_threshold, Some(0.5));
+        assert_eq!(hap.quantiles["p50"], 0.5);
+        assert_eq!(hap.quantiles["p99"], 0.9);
+        assert_eq!(hap.histogram, vec![1; 10]);
+        let block_rates = hap
+            .block_rates
+            .iter()
+            .map(|rate| rate.block_rate)
+            .collect::<Vec<_>>();
+        assert_eq!(block_rates, [1.0, 0.5, 0.1, 0.0]);
+    }
+}
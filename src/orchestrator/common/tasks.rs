@@ -15,15 +15,15 @@
 
 */
 //! Processing tasks
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
 use futures::{StreamExt, TryStreamExt, future::try_join_all, stream};
 use http::HeaderMap;
 use tokio::sync::{broadcast, mpsc};
 use tokio_stream::wrappers::ReceiverStream;
-use tracing::{Instrument, debug, instrument};
+use tracing::{Instrument, debug, info, instrument, warn};
 
-use super::{client::*, utils::*};
+use super::{client::*, groups::detect_with_group, scores::record_max_score, utils::*};
 use crate::{
     clients::{
         TextContentsDetectorClient,
@@ -34,11 +34,14 @@ use crate::{
         },
         openai,
     },
-    models::DetectorParams,
+    config::StreamFailurePolicy,
+    models::{DetectionChunk, DetectorParams},
     orchestrator::{Context, Error, types::*},
+    utils::{debug_info, trace::current_trace_id},
 };
 
 /// Spawns chunk tasks. Returns a map of chunks.
+#[instrument(skip_all)]
 pub async fn chunks(
     ctx: Arc<Context>,
     chunkers: Vec<ChunkerId>,
@@ -47,15 +50,18 @@ pub async fn chunks(
     if inputs.is_empty() {
         return Ok(HashMap::default());
     }
+    let start = Instant::now();
     let tasks = chunkers
         .into_iter()
         .map(|chunker_id| {
+            debug_info::record_chunker(&chunker_id);
             let ctx = ctx.clone();
             let inputs = inputs.clone();
             // Spawn task for chunker
             // Chunkers are processed in-parallel
             tokio::spawn(
                 async move {
+                    let start = Instant::now();
                     // Send concurrent requests for inputs
                     let chunks = stream::iter(inputs)
                         .map(|(offset, text)| {
@@ -71,15 +77,23 @@ pub async fn chunks(
                                     .clients
                                     .get_as::<ChunkerClient>(&chunker_id)
                                     .ok_or_else(|| Error::ChunkerNotFound(chunker_id.clone()))?;
-                                let chunks = chunk(client, chunker_id.clone(), text)
-                                    .await?
-                                    .into_iter()
-                                    .map(|mut chunk| {
-                                        chunk.start += offset;
-                                        chunk.end += offset;
-                                        chunk
-                                    })
-                                    .collect::<Chunks>();
+                                let chunks =
+                                    chunk(client, chunker_id.clone(), text.clone()).await?;
+                                let chunks = align_chunks(&chunker_id, &text, chunks);
+                                let (min_chunk_size, max_chunk_size) = ctx
+                                    .config
+                                    .chunker(&chunker_id)
+                                    .map(|chunker| (chunker.min_chunk_size, chunker.max_chunk_size))
+                                    .unwrap_or_default();
+                                let chunks =
+                                    normalize_chunks(&text, chunks, min_chunk_size, max_chunk_size)
+                                        .into_iter()
+                                        .map(|mut chunk| {
+                                            chunk.start += offset;
+                                            chunk.end += offset;
+                                            chunk
+                                        })
+                                        .collect::<Chunks>();
                                 Ok::<_, Error>(chunks)
                             }
                             .in_current_span()
@@ -90,6 +104,12 @@ pub async fn chunks(
                         .into_iter()
                         .flatten()
                         .collect::<Chunks>();
+                    debug!(
+                        %chunker_id,
+                        chunk_count = chunks.len(),
+                        duration_ms = start.elapsed().as_millis(),
+                        "chunking completed"
+                    );
                     Ok::<(ChunkerId, Chunks), Error>((chunker_id, chunks))
                 }
                 .in_current_span(),
@@ -100,11 +120,13 @@ pub async fn chunks(
         .await?
         .into_iter()
         .collect::<Result<HashMap<_, _>, Error>>()?;
+    debug_info::record_phase("chunking", start.elapsed());
     Ok(chunk_map)
 }
 
 /// Spawns chunk streaming tasks.
 /// Returns a map of chunk broadcast channels.
+#[instrument(skip_all)]
 pub async fn chunk_streams(
     ctx: Arc<Context>,
     chunkers: Vec<ChunkerId>,
@@ -131,6 +153,34 @@ pub async fn chunk_streams(
                 .ok_or_else(|| Error::ChunkerNotFound(chunker_id.clone()))?;
             chunk_stream(client, chunker_id.clone(), input_broadcast_rx).await
         }?;
+        // Split chunks exceeding the chunker's max chunk size
+        let max_chunk_size = ctx
+            .config
+            .chunker(&chunker_id)
+            .and_then(|chunker| chunker.max_chunk_size);
+        let chunk_stream = match max_chunk_size {
+            Some(max_chunk_size) => chunk_stream
+                .map_ok(move |chunk| {
+                    stream::iter(split_chunk(chunk, max_chunk_size).into_iter().map(Ok))
+                })
+                .try_flatten()
+                .boxed(),
+            None => chunk_stream,
+        };
+        // Count chunks and their chars for streaming chunk throughput
+        let chunk_stream = chunk_stream
+            .inspect_ok({
+                let chunker_id = chunker_id.clone();
+                move |chunk| {
+                    info!(
+                        monotonic_counter.streaming_chunk_count = 1,
+                        monotonic_counter.streaming_chunk_char_count =
+                            chunk.end.saturating_sub(chunk.start) as u64,
+                        chunker_id = %chunker_id,
+                    )
+                }
+            })
+            .boxed();
         // Create chunk broadcast channel
         let chunk_broadcast_tx = broadcast_stream(chunk_stream);
         streams.push((chunker_id, chunk_broadcast_tx));
@@ -144,7 +194,7 @@ fn whole_doc_chunk(offset: usize, text: String) -> Chunks {
     vec![Chunk {
         start: offset,
         end: text.chars().count() + offset,
-        text,
+        text: text.into(),
         ..Default::default()
     }]
     .into()
@@ -172,7 +222,7 @@ fn whole_doc_chunk_stream(
                 input_end_index: indices.last().copied().unwrap_or_default(),
                 start: 0,
                 end: text.chars().count(),
-                text,
+                text: text.into(),
             };
             // Send chunk to output channel
             let _ = output_tx.send(Ok::<_, Error>(chunk)).await;
@@ -198,48 +248,121 @@ pub async fn text_contents_detections(
     let inputs = detectors
         .iter()
         .map(|(detector_id, params)| {
-            let config = ctx
-                .config
-                .detector(detector_id)
+            let mut params = params.clone();
+            let chunker_id = get_chunker_id(&ctx, detector_id, &params)
                 .ok_or_else(|| Error::DetectorNotFound(detector_id.clone()))?;
-            let chunks = chunk_map.get(&config.chunker_id).unwrap().clone();
-            Ok::<_, Error>((detector_id.clone(), params.clone(), chunks))
+            params.pop_chunker_id();
+            let chunks = chunk_map.get(&chunker_id).unwrap().clone();
+            Ok::<_, Error>((detector_id.clone(), params, chunks))
         })
         .collect::<Result<Vec<_>, Error>>()?;
     // Send concurrent requests for inputs
-    let results = stream::iter(inputs)
-        .map(|(detector_id, mut params, chunks)| {
-            let ctx = ctx.clone();
-            let headers = headers.clone();
-            let default_threshold = ctx.config.detector(&detector_id).unwrap().default_threshold;
-            let threshold = params.pop_threshold().unwrap_or(default_threshold);
-            async move {
-                let client = ctx
-                    .clients
-                    .get_as::<TextContentsDetectorClient>(&detector_id)
-                    .unwrap();
-                let detections = detect_text_contents(
+    let results = try_collect_rate_limited(
+        stream::iter(inputs)
+            .map(|(requested_id, mut params, chunks)| {
+                let ctx = ctx.clone();
+                let headers = headers.clone();
+                let threshold = params.pop_threshold();
+                async move {
+                    detect_with_group(&ctx, &requested_id, |detector_id| {
+                        text_contents_detection(
+                            ctx.clone(),
+                            headers.clone(),
+                            detector_id,
+                            params.clone(),
+                            threshold,
+                            chunks.clone(),
+                        )
+                    })
+                    .await
+                }
+                .in_current_span()
+            })
+            .buffer_unordered(ctx.config.detector_concurrent_requests),
+    )
+    .await?;
+    let mut detections = Detections::new();
+    for result in results {
+        detections.append(result);
+    }
+    detections.sort_by_key(|detection| detection.start);
+    Ok((input_id, detections))
+}
+
+/// Sends text contents detection requests for chunks to a detector, filtering
+/// detections below `threshold`, or the detector's default threshold.
+#[instrument(skip_all, fields(detector_id))]
+async fn text_contents_detection(
+    ctx: Arc<Context>,
+    headers: HeaderMap,
+    detector_id: DetectorId,
+    params: DetectorParams,
+    threshold: Option<f64>,
+    chunks: Chunks,
+) -> Result<Detections, Error> {
+    let config = ctx.config.detector(&detector_id).unwrap();
+    let threshold = threshold.unwrap_or(config.default_threshold);
+    let partial_results = config.partial_results;
+    let max_batch_size = config.max_batch_size;
+    let client = ctx
+        .clients
+        .get_as::<TextContentsDetectorClient>(&detector_id)
+        .unwrap();
+    // Send chunks in concurrent batches, reassembled in chunk order
+    let batches = match max_batch_size {
+        Some(max_batch_size) => chunks
+            .chunks(max_batch_size)
+            .map(|batch| batch.to_vec().into())
+            .collect::<Vec<Chunks>>(),
+        None => vec![chunks],
+    };
+    let start = Instant::now();
+    let batch_count = batches.len();
+    let results = try_collect_rate_limited(
+        stream::iter(batches)
+            .map(|batch| {
+                detect_text_contents(
                     client,
-                    headers,
+                    headers.clone(),
                     detector_id.clone(),
-                    params,
-                    chunks.clone(),
+                    params.clone(),
+                    batch,
                     true,
+                    partial_results,
                 )
-                .await?
-                .into_iter()
-                .filter(|detection| detection.score >= threshold)
-                .collect::<Detections>();
-                Ok::<_, Error>(detections)
-            }
-            .in_current_span()
-        })
-        .buffer_unordered(ctx.config.detector_concurrent_requests)
-        .try_collect::<Vec<_>>()
-        .await?;
-    let mut detections = results.into_iter().flatten().collect::<Detections>();
-    detections.sort_by_key(|detection| detection.start);
-    Ok((input_id, detections))
+            })
+            .buffered(ctx.config.detector_concurrent_requests),
+    )
+    .await?;
+    let mut detections = Detections::new();
+    for result in results {
+        detections.append(result);
+    }
+    normalize_scores(&ctx, &detector_id, &mut detections);
+    record_max_score(&detector_id, detections.iter().map(|d| d.score));
+    let detection_count = detections.len();
+    detections.retain(|detection| detection.score >= threshold);
+    let filtered_count = detection_count - detections.len();
+    debug!(
+        %detector_id,
+        batch_count,
+        ?partial_results,
+        threshold,
+        detection_count,
+        filtered_count,
+        duration_ms = start.elapsed().as_millis(),
+        "detection completed"
+    );
+    if filtered_count > 0 {
+        debug_info::record_decision(|| {
+            format!(
+                "detector `{detector_id}`: {filtered_count} detections below threshold {threshold} filtered"
+            )
+        });
+    }
+    categorize(&ctx, &detector_id, &mut detections);
+    alert_high_severity(&ctx, &detections);
+    Ok(detections)
 }
 
 /// Spawns text contents detection stream tasks.
@@ -260,9 +383,12 @@ pub async fn text_contents_detection_streams(
     for (detector_id, mut params) in detectors {
         let ctx = ctx.clone();
         let headers = headers.clone();
-        let default_threshold = ctx.config.detector(&detector_id).unwrap().default_threshold;
-        let threshold = params.pop_threshold().unwrap_or(default_threshold);
-        let chunker_id = ctx.config.get_chunker_id(&detector_id).unwrap();
+        let config = ctx.config.detector(&detector_id).unwrap();
+        let threshold = params.pop_threshold().unwrap_or(config.default_threshold);
+        let partial_results = config.partial_results;
+        let stream_failure = config.stream_failure;
+        let chunker_id = get_chunker_id(&ctx, &detector_id, &params).unwrap();
+        params.pop_chunker_id();
         // Subscribe to chunk broadcast channel
         let mut chunk_rx = chunk_stream_map.get(&chunker_id).unwrap().subscribe();
         // Create detection channel
@@ -270,8 +396,28 @@ pub async fn text_contents_detection_streams(
         // Spawn detection task
         tokio::spawn(
             async move {
-                while let Ok(result) = chunk_rx.recv().await {
+                // Whether the detector failed and no longer receives chunks
+                let mut stopped = false;
+                loop {
+                    let result = match chunk_rx.recv().await {
+                        Ok(result) => result,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            // Skipped chunks cannot be recovered, fail rather than truncate
+                            let error = Error::Other(format!(
+                                "detection stream for `{detector_id}` skipped {skipped} chunks"
+                            ));
+                            let _ = detection_tx.send(Err(error)).await;
+                            break;
+                        }
+                    };
                     match result {
+                        Ok(chunk) if stopped => {
+                            let detections = unanalyzed_chunk_detections(&detector_id, &chunk);
+                            let _ = detection_tx
+                                .send(Ok((input_id, detector_id.clone(), chunk, detections)))
+                                .await;
+                        }
                         Ok(chunk) => {
                             let client = ctx
                                 .clients
@@ -284,15 +430,29 @@ pub async fn text_contents_detection_streams(
                                 params.clone(),
                                 vec![chunk.clone()].into(),
                                 false,
+                                partial_results,
                             )
                             .await
                             {
-                                Ok(detections) => {
+                                Ok(mut detections) => {
+                                    normalize_scores(&ctx, &detector_id, &mut detections);
+                                    record_max_score(
+                                        &detector_id,
+                                        detections.iter().map(|d| d.score),
+                                    );
                                     // Apply threshold
-                                    let detections = detections
-                                        .into_iter()
-                                        .filter(|detection| detection.score >= threshold)
-                                        .collect::<Detections>();
+                                    detections.retain(|detection| detection.score >= threshold);
+                                    categorize(&ctx, &detector_id, &mut detections);
+                                    alert_high_severity(&ctx, &detections);
+                                    // Attach chunk provenance
+                                    let detection_chunk = DetectionChunk {
+                                        chunker_id: chunker_id.clone(),
+                                        start_index: chunk.start as u32,
+                                        processed_index: chunk.end as u32,
+                                    };
+                                    for detection in detections.iter_mut() {
+                                        detection.chunk = Some(detection_chunk.clone());
+                                    }
                                     // Send to detection channel
                                     let _ = detection_tx
                                         .send(Ok((
@@ -303,10 +463,20 @@ pub async fn text_contents_detection_streams(
                                         )))
                                         .await;
                                 }
-                                Err(error) => {
+                                Err(error) if stream_failure == StreamFailurePolicy::Fail => {
                                     // Send error to detection channel
                                     let _ = detection_tx.send(Err(error)).await;
                                 }
+                                Err(error) => {
+                                    warn!(%detector_id, %error, ?stream_failure, "detector failed mid-stream");
+                                    stopped = stream_failure == StreamFailurePolicy::Continue;
+                                    // Report the chunk as not analyzed
+                                    let detections =
+                                        unanalyzed_chunk_detections(&detector_id, &chunk);
+                                    let _ = detection_tx
+                                        .send(Ok((input_id, detector_id.clone(), chunk, detections)))
+                                        .await;
+                                }
                             }
                         }
                         Err(error) => {
@@ -346,37 +516,43 @@ pub async fn text_generation_detections(
         })
         .collect::<Result<Vec<_>, Error>>()?;
     // Send concurrent requests for inputs
-    let results = stream::iter(inputs)
-        .map(|(detector_id, mut params, prompt, generated_text)| {
-            let ctx = ctx.clone();
-            let headers = headers.clone();
-            let default_threshold = ctx.config.detector(&detector_id).unwrap().default_threshold;
-            let threshold = params.pop_threshold().unwrap_or(default_threshold);
-            async move {
-                let client = ctx
-                    .clients
-                    .get_as::<TextGenerationDetectorClient>(&detector_id)
-                    .unwrap();
-                let detections = detect_text_generation(
-                    client,
-                    headers,
-                    detector_id.clone(),
-                    params,
-                    prompt,
-                    generated_text,
-                )
-                .await?
-                .into_iter()
-                .filter(|detection| detection.score >= threshold)
-                .collect::<Detections>();
-                Ok::<_, Error>(detections)
-            }
-            .in_current_span()
-        })
-        .buffer_unordered(ctx.config.detector_concurrent_requests)
-        .try_collect::<Vec<_>>()
-        .await?;
-    let detections = results.into_iter().flatten().collect::<Detections>();
+    let results = try_collect_rate_limited(
+        stream::iter(inputs)
+            .map(|(requested_id, mut params, prompt, generated_text)| {
+                let ctx = ctx.clone();
+                let headers = headers.clone();
+                let threshold = params.pop_threshold();
+                async move {
+                    let (ctx, headers, params) = (&ctx, &headers, &params);
+                    let (prompt, generated_text) = (&prompt, &generated_text);
+                    detect_with_group(ctx, &requested_id, |detector_id| async move {
+                        let client = ctx
+                            .clients
+                            .get_as::<TextGenerationDetectorClient>(&detector_id)
+                            .unwrap();
+                        let mut detections = detect_text_generation(
+                            client,
+                            headers.clone(),
+                            detector_id.clone(),
+                            params.clone(),
+                            prompt.clone(),
+                            generated_text.clone(),
+                        )
+                        .await?;
+                        filter_detections(ctx, &detector_id, threshold, &mut detections);
+                        Ok(detections)
+                    })
+                    .await
+                }
+                .in_current_span()
+            })
+            .buffer_unordered(ctx.config.detector_concurrent_requests),
+    )
+    .await?;
+    let mut detections = Detections::new();
+    for result in results {
+        detections.append(result);
+    }
     Ok(detections)
 }
 
@@ -387,9 +563,12 @@ pub async fn text_chat_detections(
     ctx: Arc<Context>,
     headers: HeaderMap,
     detectors: HashMap<DetectorId, DetectorParams>,
-    messages: Vec<openai::Message>,
+    mut messages: Vec<openai::Message>,
     tools: Vec<openai::Tool>,
 ) -> Result<Detections, Error> {
+    for message in messages.iter_mut() {
+        ctx.config.reasoning_detection.apply(message);
+    }
     let inputs = detectors
         .iter()
         .map(|(detector_id, params)| {
@@ -402,37 +581,43 @@ pub async fn text_chat_detections(
         })
         .collect::<Result<Vec<_>, Error>>()?;
     // Send concurrent requests for inputs
-    let results = stream::iter(inputs)
-        .map(|(detector_id, mut params, messages, tools)| {
-            let ctx = ctx.clone();
-            let headers = headers.clone();
-            let default_threshold = ctx.config.detector(&detector_id).unwrap().default_threshold;
-            let threshold = params.pop_threshold().unwrap_or(default_threshold);
-            async move {
-                let client = ctx
-                    .clients
-                    .get_as::<TextChatDetectorClient>(&detector_id)
-                    .unwrap();
-                let detections = detect_text_chat(
-                    client,
-                    headers,
-                    detector_id.clone(),
-                    params,
-                    messages,
-                    tools,
-                )
-                .await?
-                .into_iter()
-                .filter(|detection| detection.score >= threshold)
-                .collect::<Detections>();
-                Ok::<_, Error>(detections)
-            }
-            .in_current_span()
-        })
-        .buffer_unordered(ctx.config.detector_concurrent_requests)
-        .try_collect::<Vec<_>>()
-        .await?;
-    let detections = results.into_iter().flatten().collect::<Detections>();
+    let results = try_collect_rate_limited(
+        stream::iter(inputs)
+            .map(|(requested_id, mut params, messages, tools)| {
+                let ctx = ctx.clone();
+                let headers = headers.clone();
+                let threshold = params.pop_threshold();
+                async move {
+                    let (ctx, headers, params) = (&ctx, &headers, &params);
+                    let (messages, tools) = (&messages, &tools);
+                    detect_with_group(ctx, &requested_id, |detector_id| async move {
+                        let client = ctx
+                            .clients
+                            .get_as::<TextChatDetectorClient>(&detector_id)
+                            .unwrap();
+                        let mut detections = detect_text_chat(
+                            client,
+                            headers.clone(),
+                            detector_id.clone(),
+                            params.clone(),
+                            messages.clone(),
+                            tools.clone(),
+                        )
+                        .await?;
+                        filter_detections(ctx, &detector_id, threshold, &mut detections);
+                        Ok(detections)
+                    })
+                    .await
+                }
+                .in_current_span()
+            })
+            .buffer_unordered(ctx.config.detector_concurrent_requests),
+    )
+    .await?;
+    let mut detections = Detections::new();
+    for result in results {
+        detections.append(result);
+    }
     Ok(detections)
 }
 
@@ -460,44 +645,129 @@ pub async fn text_context_detections(
         })
         .collect::<Result<Vec<_>, Error>>()?;
     // Send concurrent requests for inputs
-    let results = stream::iter(inputs)
-        .map(
-            |(detector_id, mut params, content, context_type, context)| {
-                let ctx = ctx.clone();
-                let headers = headers.clone();
-                let default_threshold =
-                    ctx.config.detector(&detector_id).unwrap().default_threshold;
-                let threshold = params.pop_threshold().unwrap_or(default_threshold);
-                async move {
-                    let client = ctx
-                        .clients
-                        .get_as::<TextContextDocDetectorClient>(&detector_id)
-                        .unwrap();
-                    let detections = detect_text_context(
-                        client,
-                        headers,
-                        detector_id.clone(),
-                        params,
-                        content,
-                        context_type,
-                        context,
-                    )
-                    .await?
-                    .into_iter()
-                    .filter(|detection| detection.score >= threshold)
-                    .collect::<Detections>();
-                    Ok::<_, Error>(detections)
-                }
-                .in_current_span()
-            },
-        )
-        .buffer_unordered(ctx.config.detector_concurrent_requests)
-        .try_collect::<Vec<_>>()
-        .await?;
-    let detections = results.into_iter().flatten().collect::<Detections>();
+    let results = try_collect_rate_limited(
+        stream::iter(inputs)
+            .map(
+                |(requested_id, mut params, content, context_type, context)| {
+                    let ctx = ctx.clone();
+                    let headers = headers.clone();
+                    let threshold = params.pop_threshold();
+                    async move {
+                        let (ctx, headers, params) = (&ctx, &headers, &params);
+                        let (content, context_type, context) = (&content, &context_type, &context);
+                        detect_with_group(ctx, &requested_id, |detector_id| async move {
+                            let client = ctx
+                                .clients
+                                .get_as::<TextContextDocDetectorClient>(&detector_id)
+                                .unwrap();
+                            let mut detections = detect_text_context(
+                                client,
+                                headers.clone(),
+                                detector_id.clone(),
+                                params.clone(),
+                                content.clone(),
+                                context_type.clone(),
+                                context.clone(),
+                            )
+                            .await?;
+                            filter_detections(ctx, &detector_id, threshold, &mut detections);
+                            Ok(detections)
+                        })
+                        .await
+                    }
+                    .in_current_span()
+                },
+            )
+            .buffer_unordered(ctx.config.detector_concurrent_requests),
+    )
+    .await?;
+    let mut detections = Detections::new();
+    for result in results {
+        detections.append(result);
+    }
     Ok(detections)
 }
 
+/// Normalizes scores of detections and filters those below `threshold`, or the
+/// detector's default threshold, then categorizes and alerts on remaining detections.
+fn filter_detections(
+    ctx: &Context,
+    detector_id: &str,
+    threshold: Option<f64>,
+    detections: &mut Detections,
+) {
+    let threshold =
+        threshold.unwrap_or_else(|| ctx.config.detector(detector_id).unwrap().default_threshold);
+    normalize_scores(ctx, detector_id, detections);
+    detections.retain(|detection| detection.score >= threshold);
+    categorize(ctx, detector_id, detections);
+    alert_high_severity(ctx, detections);
+}
+
+/// Normalizes scores of detections as configured for the detector.
+fn normalize_scores(ctx: &Context, detector_id: &str, detections: &mut Detections) {
+    let Some(normalization) = ctx
+        .config
+        .detector(detector_id)
+        .and_then(|detector| detector.score_normalization)
+    else {
+        return;
+    };
+    let mut normalized_count = 0u64;
+    for detection in detections.iter_mut() {
+        let score = normalization.apply(detection.score);
+        if score != detection.score {
+            detection.score = score;
+            normalized_count += 1;
+        }
+    }
+    if normalized_count > 0 {
+        warn!(
+            detector_id,
+            normalized_count,
+            ?normalization,
+            monotonic_counter.normalized_score_count = normalized_count,
+            "detector scores normalized"
+        );
+        debug_info::record_decision(|| {
+            format!("detector `{detector_id}`: {normalized_count} scores normalized")
+        });
+    }
+}
+
+/// Assigns configured categories to detections and records detection counts by category.
+fn categorize(ctx: &Context, detector_id: &str, detections: &mut Detections) {
+    for detection in detections.iter_mut() {
+        detection.category = ctx
+            .config
+            .category(detector_id, &detection.detection_type, &detection.detection)
+            .map(Into::into);
+        info!(
+            detector_id,
+            category = detection.category.as_deref().unwrap_or("uncategorized"),
+            monotonic_counter.detection_count = 1,
+        );
+    }
+}
+
+/// Alerts on high-severity detections, if alerts are configured.
+fn alert_high_severity(ctx: &Context, detections: &Detections) {
+    if let Some(alerts) = &ctx.alerts {
+        alerts.high_severity(current_trace_id(), detections);
+    }
+}
+
+/// Returns empty detections with a partial span for a chunk not analyzed by a detector.
+fn unanalyzed_chunk_detections(detector_id: &str, chunk: &Chunk) -> Detections {
+    let mut detections = Detections::new();
+    detections.push_partial_span(PartialSpan {
+        detector_id: detector_id.to_string(),
+        start: chunk.start,
+        end: chunk.end,
+    });
+    detections
+}
+
 /// Fans-out a stream to a broadcast channel.
 pub fn broadcast_stream<T>(mut stream: BoxStream<T>) -> broadcast::Sender<T>
 where
@@ -523,16 +793,19 @@ mod test {
 
     use super::*;
     use crate::{
-        clients::detector::{ContentAnalysisRequest, ContentAnalysisResponse},
+        clients::{
+            ClientMap, SharedClientState,
+            detector::{ContentAnalysisRequest, ContentAnalysisResponse},
+        },
         config::OrchestratorConfig,
         models::Metadata,
-        orchestrator::create_clients,
         pb::{
             caikit::runtime::chunkers::{
                 BidiStreamingChunkerTokenizationTaskRequest, ChunkerTokenizationTaskRequest,
             },
             caikit_data_model::nlp::{ChunkerTokenizationStreamResult, Token, TokenizationResults},
         },
+        utils::test_server::ensure_crypto_provider,
     };
 
     static CONTEXT: OnceCell<Arc<Context>> = OnceCell::const_new();
@@ -551,7 +824,7 @@ mod test {
         vitae maxime est voluptatem itaque. ";
 
     async fn init_context() -> Arc<Context> {
-        let _ = rustls::crypto::ring::default_provider().install_default();
+        ensure_crypto_provider();
 
         // Create sentence_chunker
         let mut mocks = MockSet::new();
@@ -671,6 +944,8 @@ mod test {
                 score: 0.2,
                 evidence: None,
                 metadata: Metadata::new(),
+                chunk: None,
+                category: None,
             }]]);
         });
         mocks.mock(|when, then| {
@@ -692,18 +967,64 @@ mod test {
                 score: 0.2,
                 evidence: None,
                 metadata: Metadata::new(),
+                chunk: None,
+                category: None,
             }]]);
         });
 
         let fake_detector_server = MockServer::new("fake_detector").with_mocks(mocks);
         fake_detector_server.start().await.unwrap();
 
+        // Create batched detector, receiving at most 2 chunks per request
+        let detection = |start, end, text: &str, score| ContentAnalysisResponse {
+            start,
+            end,
+            text: text.into(),
+            detection: "nothing".into(),
+            detection_type: "fake".into(),
+            detector_id: None,
+            score,
+            evidence: None,
+            metadata: Metadata::new(),
+            chunk: None,
+            category: None,
+        };
+        let mut mocks = MockSet::new();
+        mocks.mock(|when, then| {
+            when.post()
+                .path(TEXT_CONTENTS_DETECTOR_PATH)
+                .json(ContentAnalysisRequest {
+                    contents: vec![
+                        "Lorem ipsum dolor sit amet, consectetuer adipiscing elit.".into(),
+                        " Aenean commodo ligula eget dolor.".into(),
+                    ],
+                    detector_params: Default::default(),
+                });
+            then.json(vec![
+                vec![detection(6, 11, "ipsum", 0.9)],
+                vec![detection(8, 15, "commodo", 0.8)],
+            ]);
+        });
+        mocks.mock(|when, then| {
+            when.post()
+                .path(TEXT_CONTENTS_DETECTOR_PATH)
+                .json(ContentAnalysisRequest {
+                    contents: vec![
+                        " Cum sociis natoque penatibus et magnis dis parturient montes, nascetur ridiculus mus.".into(),
+                    ],
+                    detector_params: Default::default(),
+                });
+            then.json(vec![vec![detection(5, 11, "sociis", 0.7)]]);
+        });
+        let batched_detector_server = MockServer::new("batched_detector").with_mocks(mocks);
+        batched_detector_server.start().await.unwrap();
+
         let mut config = OrchestratorConfig::default();
         configure_mock_servers(
             &mut config,
             None,
             None,
-            Some(vec![&fake_detector_server]),
+            Some(vec![&fake_detector_server, &batched_detector_server]),
             Some(vec![
                 &sentence_chunker_server,
                 &whole_doc_chunker_server,
@@ -714,9 +1035,15 @@ mod test {
         if let Some(config) = config.detectors.get_mut("fake_detector") {
             config.chunker_id = "sentence_chunker".into();
         }
+        if let Some(config) = config.detectors.get_mut("batched_detector") {
+            config.chunker_id = "sentence_chunker".into();
+            config.max_batch_size = Some(2);
+        }
 
         // Create clients
-        let clients = create_clients(&config).await.unwrap();
+        let clients = ClientMap::create(&config, &SharedClientState::default())
+            .await
+            .unwrap();
 
         Arc::new(Context::new(config, clients))
     }
@@ -875,6 +1202,30 @@ mod test {
             "should return detector not found error"
         );
 
+        // Batched detector, with detections reassembled in chunk order
+        let detectors = HashMap::from([("batched_detector".to_string(), DetectorParams::new())]);
+        let (_, detections) = text_contents_detections(
+            ctx.clone(),
+            HeaderMap::default(),
+            detectors,
+            0,
+            vec![(0, TEXT1.to_string())],
+        )
+        .await?;
+        let spans = detections
+            .iter()
+            .map(|detection| (detection.start, detection.end, detection.score))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [
+                (Some(6), Some(11), 0.9),
+                (Some(65), Some(72), 0.8),
+                (Some(97), Some(103), 0.7),
+            ],
+            "should have detections of all batches in chunk order"
+        );
+
         // TODO: add more cases
 
         Ok(())
@@ -0,0 +1,149 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Detections of a request before filtering by `min_score` and `top_k`, collected for
+//! payload capture, so captured payloads are redacted and evaluated on every detection.
+//!
+//! Detections are collected in a task-local, so they are recorded by code running in the
+//! request's task only. Recording outside of [`collect`] is a no-op, so futures spawned
+//! as separate tasks must be wrapped with [`propagate`].
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use crate::orchestrator::types::Detection;
+
+tokio::task_local! {
+    static UNFILTERED_DETECTIONS: Arc<Mutex<UnfilteredDetections>>;
+}
+
+/// Unfiltered detections of a request, by source text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UnfilteredDetections {
+    /// Detections of the input text
+    pub input: Vec<Detection>,
+    /// Detections of the generated text
+    pub output: Vec<Detection>,
+}
+
+/// Awaits `future`, collecting its unfiltered detections if `enabled`.
+pub async fn collect<F: Future>(
+    enabled: bool,
+    future: F,
+) -> (F::Output, Option<UnfilteredDetections>) {
+    if !enabled {
+        return (future.await, None);
+    }
+    let detections = Arc::new(Mutex::new(UnfilteredDetections::default()));
+    let output = UNFILTERED_DETECTIONS
+        .scope(detections.clone(), future)
+        .await;
+    let detections = detections.lock().unwrap().clone();
+    (output, Some(detections))
+}
+
+/// Wraps `future` to record to the unfiltered detections of the current request, if
+/// collected, when spawned as a separate task.
+pub fn propagate<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let detections = UNFILTERED_DETECTIONS.try_with(Arc::clone).ok();
+    async move {
+        match detections {
+            Some(detections) => UNFILTERED_DETECTIONS.scope(detections, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// Returns whether unfiltered detections of the current request are collected.
+pub fn is_collected() -> bool {
+    UNFILTERED_DETECTIONS.try_with(|_| ()).is_ok()
+}
+
+/// Records detections of the input text.
+pub fn record_input(detections: &[Detection]) {
+    let _ = UNFILTERED_DETECTIONS.try_with(|unfiltered| {
+        unfiltered
+            .lock()
+            .unwrap()
+            .input
+            .extend_from_slice(detections)
+    });
+}
+
+/// Records detections of the generated text.
+pub fn record_output(detections: &[Detection]) {
+    let _ = UNFILTERED_DETECTIONS.try_with(|unfiltered| {
+        unfiltered
+            .lock()
+            .unwrap()
+            .output
+            .extend_from_slice(detections)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_collect() {
+        let detection = Detection {
+            detection_type: "pii".into(),
+            score: 0.2,
+            ..Default::default()
+        };
+        let (collected, detections) = collect(true, async {
+            record_input(&[detection.clone()]);
+            record_output(&[detection.clone(), detection.clone()]);
+            is_collected()
+        })
+        .await;
+        assert!(collected);
+        let detections = detections.unwrap();
+        assert_eq!(detections.input.len(), 1);
+        assert_eq!(detections.output.len(), 2);
+
+        let (collected, detections) = collect(false, async {
+            record_input(&[detection]);
+            is_collected()
+        })
+        .await;
+        assert!(!collected && detections.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_propagate() {
+        let detection = Detection {
+            detection_type: "pii".into(),
+            score: 0.2,
+            ..Default::default()
+        };
+        let (collected, detections) = collect(true, async {
+            let spawned = detection.clone();
+            tokio::spawn(propagate(async move {
+                record_input(&[spawned]);
+                is_collected()
+            }))
+            .await
+            .unwrap()
+        })
+        .await;
+        assert!(collected);
+        assert_eq!(detections.unwrap().input, [detection]);
+    }
+}
@@ -14,23 +14,200 @@
  limitations under the License.
 
 */
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, future::Future, sync::Arc};
 
-use tracing::error;
+use futures::{Stream, StreamExt};
+use tracing::{debug, error, warn};
 
 use crate::{
     clients::chunker::DEFAULT_CHUNKER_ID,
     config::{DetectorConfig, DetectorType},
-    models::DetectorParams,
-    orchestrator::{Context, Error},
+    models::{DetectorParams, TruncationStrategy},
+    orchestrator::{
+        Context, Error,
+        common::unfiltered,
+        types::{Chunk, Chunks},
+    },
+    utils::debug_info,
 };
 
+/// Wraps `future` to record to the debug info and unfiltered detections of the
+/// current request when spawned as a separate task.
+pub fn propagate_task_locals<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    debug_info::propagate(unfiltered::propagate(future))
+}
+
 /// Slices chars between start and end indices.
+/// Returns an empty string if start is after end.
 pub fn slice_codepoints(text: &str, start: usize, end: usize) -> String {
-    let len = end - start;
+    let len = end.saturating_sub(start);
     text.chars().skip(start).take(len).collect()
 }
 
+/// Returns the largest char boundary at or before a byte index.
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Returns the smallest char boundary at or after a byte index.
+fn ceil_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+/// Aligns chunks returned by a chunker to char offsets of the chunked text.
+///
+/// Chunks are expected to have char offsets matching their text. Chunks that do not
+/// are treated as having byte offsets: offsets that fall inside a multi-byte char are
+/// snapped outwards to the nearest char boundaries, and chunk text is replaced with
+/// the text at the aligned offsets. Other inconsistent offsets are clamped to the text.
+pub fn align_chunks(chunker_id: &str, text: &str, chunks: Chunks) -> Chunks {
+    let char_len = text.chars().count();
+    let mut aligned = Chunks::new();
+    for chunk in chunks {
+        let chunk = align_chunk(chunker_id, text, char_len, chunk);
+        // Snapping may collapse neighboring chunks into the same span
+        if aligned
+            .last()
+            .is_some_and(|last| (last.start, last.end) == (chunk.start, chunk.end))
+        {
+            continue;
+        }
+        aligned.push(chunk);
+    }
+    aligned
+}
+
+fn align_chunk(chunker_id: &str, text: &str, char_len: usize, chunk: Chunk) -> Chunk {
+    if chunk.start <= chunk.end
+        && chunk.end <= char_len
+        && *chunk.text == slice_codepoints(text, chunk.start, chunk.end)
+    {
+        return chunk;
+    }
+    if chunk.start <= chunk.end && chunk.end <= text.len() {
+        let start = floor_char_boundary(text, chunk.start);
+        let end = ceil_char_boundary(text, chunk.end);
+        let splits_char = start != chunk.start || end != chunk.end;
+        if splits_char || text[start..end] == *chunk.text {
+            let char_start = text[..start].chars().count();
+            let char_end = char_start + text[start..end].chars().count();
+            if splits_char {
+                warn!(
+                    chunker_id,
+                    start = chunk.start,
+                    end = chunk.end,
+                    aligned_start = char_start,
+                    aligned_end = char_end,
+                    "chunk offsets split a multi-byte char, snapped to char boundaries"
+                );
+            } else {
+                debug!(
+                    chunker_id,
+                    start = chunk.start,
+                    end = chunk.end,
+                    "chunk offsets are byte offsets, converted to char offsets"
+                );
+            }
+            return Chunk {
+                start: char_start,
+                end: char_end,
+                text: text[start..end].into(),
+                ..chunk
+            };
+        }
+    }
+    let end = chunk.end.min(char_len);
+    let start = chunk.start.min(end);
+    warn!(
+        chunker_id,
+        start = chunk.start,
+        end = chunk.end,
+        aligned_start = start,
+        aligned_end = end,
+        "chunk offsets do not match chunk text, clamped to text"
+    );
+    Chunk {
+        start,
+        end,
+        ..chunk
+    }
+}
+
+/// Normalizes chunk sizes in chars.
+///
+/// Chunks smaller than `min_size` are merged with the following chunk, or the
+/// preceding chunk if last, then chunks larger than `max_size` are split.
+pub fn normalize_chunks(
+    text: &str,
+    chunks: Chunks,
+    min_size: Option<usize>,
+    max_size: Option<usize>,
+) -> Chunks {
+    let mut merged = Chunks::new();
+    for chunk in chunks {
+        let merge = merged.last().is_some_and(|last: &Chunk| {
+            min_size.is_some_and(|min| {
+                last.end.saturating_sub(last.start) < min
+                    || chunk.end.saturating_sub(chunk.start) < min
+            })
+        });
+        match merged.last_mut() {
+            Some(last) if merge => {
+                last.end = chunk.end;
+                last.input_end_index = chunk.input_end_index;
+                last.text = slice_codepoints(text, last.start, last.end).into();
+            }
+            _ => merged.push(chunk),
+        }
+    }
+    match max_size {
+        Some(max_size) => merged
+            .into_iter()
+            .flat_map(|chunk| split_chunk(chunk, max_size))
+            .collect(),
+        None => merged,
+    }
+}
+
+/// Splits a chunk into chunks of at most `max_size` chars.
+///
+/// Chunks are split after the last whitespace within `max_size` chars,
+/// or at `max_size` chars if there is none.
+pub fn split_chunk(chunk: Chunk, max_size: usize) -> Vec<Chunk> {
+    let chars = chunk.text.chars().collect::<Vec<_>>();
+    if max_size == 0 || chars.len() <= max_size {
+        return vec![chunk];
+    }
+    let mut chunks = Vec::with_capacity(chars.len().div_ceil(max_size));
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = (start + max_size).min(chars.len());
+        if end < chars.len() {
+            // Split after the last whitespace, if any
+            end = chars[start + 1..end]
+                .iter()
+                .rposition(|c| c.is_whitespace())
+                .map_or(end, |index| start + index + 2);
+        }
+        chunks.push(Chunk {
+            start: chunk.start + start,
+            end: chunk.start + end,
+            text: chars[start..end].iter().collect::<String>().into(),
+            ..chunk.clone()
+        });
+        start = end;
+    }
+    chunks
+}
+
 /// Applies masks to input text, returning (offset, masked_text) pairs.
 pub fn apply_masks(text: String, masks: Option<&[(usize, usize)]>) -> Vec<(usize, String)> {
     match masks {
@@ -45,23 +222,61 @@ pub fn apply_masks(text: String, masks: Option<&[(usize, usize)]>) -> Vec<(usize
     }
 }
 
+/// Truncates text to `max_tokens` tokens per `strategy`, given the char offsets of its tokens.
+/// Returns the truncated text and the char span (start, end) removed, or `None` if the text
+/// does not exceed `max_tokens`. Zero `max_tokens` means no truncation.
+pub fn truncate_tokens(
+    text: &str,
+    offsets: &[(usize, usize)],
+    max_tokens: usize,
+    strategy: TruncationStrategy,
+) -> Option<(String, (usize, usize))> {
+    if max_tokens == 0 || offsets.len() <= max_tokens {
+        return None;
+    }
+    let text_len = text.chars().count();
+    let (start, end) = match strategy {
+        TruncationStrategy::Head => (0, offsets[offsets.len() - max_tokens].0),
+        TruncationStrategy::Tail => (offsets[max_tokens - 1].1, text_len),
+        TruncationStrategy::Middle => {
+            let head = max_tokens.div_ceil(2);
+            let tail = max_tokens - head;
+            // Keep the separator between the kept start and end of the text
+            if tail > 0 {
+                (offsets[head].0, offsets[offsets.len() - tail].0)
+            } else {
+                (offsets[head - 1].1, text_len)
+            }
+        }
+    };
+    let end = end.min(text_len);
+    let start = start.min(end);
+    let mut truncated = slice_codepoints(text, 0, start);
+    truncated.push_str(&slice_codepoints(text, end, text_len));
+    Some((truncated, (start, end)))
+}
+
 /// Looks up chunker ids for detectors.
 pub fn get_chunker_ids(
     ctx: &Arc<Context>,
     detectors: &HashMap<String, DetectorParams>,
 ) -> Result<Vec<String>, Error> {
     detectors
-        .keys()
-        .map(|detector_id| {
-            let chunker_id = ctx
-                .config
-                .get_chunker_id(detector_id)
+        .iter()
+        .map(|(detector_id, params)| {
+            let chunker_id = get_chunker_id(ctx, detector_id, params)
                 .ok_or_else(|| Error::DetectorNotFound(detector_id.clone()))?;
             Ok::<String, Error>(chunker_id)
         })
         .collect::<Result<Vec<_>, Error>>()
 }
 
+/// Returns the chunker of a detector, overridden by its `chunker_id` parameter if specified.
+pub fn get_chunker_id(ctx: &Context, detector_id: &str, params: &DetectorParams) -> Option<String> {
+    let chunker_id = ctx.config.get_chunker_id(detector_id)?;
+    Some(params.chunker_id().map(Into::into).unwrap_or(chunker_id))
+}
+
 /// Returns the current unix timestamp.
 pub fn current_timestamp() -> std::time::Duration {
     std::time::SystemTime::now()
@@ -124,7 +339,7 @@ pub fn validate_detectors(
     allows_whole_doc_chunker: bool,
 ) -> Result<(), Error> {
     let whole_doc_chunker_id = DEFAULT_CHUNKER_ID;
-    for detector_id in detectors.keys() {
+    for (detector_id, params) in detectors {
         // validate detectors
         match orchestrator_detectors.get(detector_id) {
             Some(detector_config) => {
@@ -135,7 +350,18 @@ pub fn validate_detectors(
                     error!("{error}");
                     return Err(error);
                 }
-                if !allows_whole_doc_chunker && detector_config.chunker_id == whole_doc_chunker_id {
+                // Chunker overrides apply to text contents detectors only
+                if params.chunker_id().is_some()
+                    && detector_config.r#type != DetectorType::TextContents
+                {
+                    let error = Error::Validation(format!(
+                        "`chunker_id` parameter is not supported by detector `{detector_id}`"
+                    ));
+                    error!("{error}");
+                    return Err(error);
+                }
+                let chunker_id = params.chunker_id().unwrap_or(&detector_config.chunker_id);
+                if !allows_whole_doc_chunker && chunker_id == whole_doc_chunker_id {
                     let error = Error::Validation(format!(
                         "detector `{detector_id}` uses chunker `whole_doc_chunker`, which is not supported by this endpoint"
                     ));
@@ -153,8 +379,39 @@ pub fn validate_detectors(
     Ok(())
 }
 
+/// Collects the results of concurrent requests, failing with the first error. Once a
+/// request is rate limited, the others run to completion, so the error returned carries
+/// the latest Retry-After of all rate limited backends.
+pub async fn try_collect_rate_limited<T>(
+    results: impl Stream<Item = Result<T, Error>>,
+) -> Result<Vec<T>, Error> {
+    let mut results = std::pin::pin!(results);
+    let mut values = Vec::new();
+    let mut rate_limited: Option<Error> = None;
+    while let Some(result) = results.next().await {
+        match result {
+            Ok(value) => values.push(value),
+            Err(error) if error.is_rate_limited() => {
+                if rate_limited
+                    .as_ref()
+                    .is_none_or(|rate_limited| error.retry_after() > rate_limited.retry_after())
+                {
+                    rate_limited = Some(error);
+                }
+            }
+            Err(error) => return Err(rate_limited.unwrap_or(error)),
+        }
+    }
+    match rate_limited {
+        Some(error) => Err(error),
+        None => Ok(values),
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use super::*;
 
     #[test]
@@ -175,5 +432,385 @@ mod tests {
         assert_eq!(slice_codepoints(s, 0, 5), "Hello");
         let s = "哈囉世界";
         assert_eq!(slice_codepoints(s, 3, 4), "界");
+        assert_eq!(slice_codepoints(s, 4, 3), "");
+        assert_eq!(slice_codepoints(s, 2, 10), "世界");
+    }
+
+    #[test]
+    fn test_truncate_tokens() {
+        let text = "one two three four five";
+        let offsets = [(0, 3), (4, 7), (8, 13), (14, 18), (19, 23)];
+        assert_eq!(
+            truncate_tokens(text, &offsets, 2, TruncationStrategy::Head),
+            Some(("four five".into(), (0, 14)))
+        );
+        assert_eq!(
+            truncate_tokens(text, &offsets, 2, TruncationStrategy::Tail),
+            Some(("one two".into(), (7, 23)))
+        );
+        assert_eq!(
+            truncate_tokens(text, &offsets, 3, TruncationStrategy::Middle),
+            Some(("one two five".into(), (8, 19)))
+        );
+        assert_eq!(
+            truncate_tokens(text, &offsets, 1, TruncationStrategy::Middle),
+            Some(("one".into(), (3, 23)))
+        );
+        assert_eq!(
+            truncate_tokens(text, &offsets, 5, TruncationStrategy::Head),
+            None
+        );
+        assert_eq!(
+            truncate_tokens(text, &offsets, 0, TruncationStrategy::Head),
+            None
+        );
+    }
+
+    fn chunk(start: usize, end: usize, text: &str) -> Chunk {
+        Chunk {
+            start,
+            end,
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    fn spans(chunks: &Chunks) -> Vec<(usize, usize, &str)> {
+        chunks
+            .iter()
+            .map(|chunk| (chunk.start, chunk.end, &*chunk.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_align_chunks() {
+        let text = "Hi 🦀! 你好。";
+        // Char offsets are unchanged
+        let chunks = vec![chunk(0, 5, "Hi 🦀!"), chunk(6, 9, "你好。")].into();
+        assert_eq!(
+            spans(&align_chunks("chunker", text, chunks)),
+            [(0, 5, "Hi 🦀!"), (6, 9, "你好。")]
+        );
+        // Byte offsets are converted
+        let chunks = vec![chunk(0, 8, "Hi 🦀!"), chunk(9, 18, "你好。")].into();
+        assert_eq!(
+            spans(&align_chunks("chunker", text, chunks)),
+            [(0, 5, "Hi 🦀!"), (6, 9, "你好。")]
+        );
+        // Byte offsets inside multi-byte chars are snapped outwards
+        let chunks = vec![chunk(0, 5, "Hi \u{FFFD}"), chunk(10, 14, "\u{FFFD}好")].into();
+        assert_eq!(
+            spans(&align_chunks("chunker", text, chunks)),
+            [(0, 4, "Hi 🦀"), (6, 8, "你好")]
+        );
+        // Out of bounds offsets are clamped
+        let chunks = vec![chunk(7, 30, "好。?"), chunk(40, 35, "")].into();
+        assert_eq!(
+            spans(&align_chunks("chunker", text, chunks)),
+            [(7, 9, "好。?"), (9, 9, "")]
+        );
+    }
+
+    #[test]
+    fn test_normalize_chunks() {
+        let text = "Hi. Ok. This is a long sentence without many breaks. End.";
+        let chunks = vec![
+            chunk(0, 3, "Hi."),
+            chunk(4, 7, "Ok."),
+            chunk(8, 52, "This is a long sentence without many breaks."),
+            chunk(53, 57, "End."),
+        ]
+        .into();
+        let chunks = normalize_chunks(text, chunks, Some(5), Some(20));
+        assert_eq!(
+            spans(&chunks),
+            [
+                (0, 7, "Hi. Ok."),
+                (8, 23, "This is a long "),
+                (23, 40, "sentence without "),
+                (40, 57, "many breaks. End."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_chunk() {
+        let chunks = split_chunk(chunk(10, 23, "你好世界🦀🦀🦀🦀 ab c"), 5).into();
+        assert_eq!(
+            spans(&chunks),
+            [
+                (10, 15, "你好世界🦀"),
+                (15, 19, "🦀🦀🦀 "),
+                (19, 23, "ab c")
+            ]
+        );
+    }
+
+    /// Asserts chunks with random offsets over text with emoji and CJK chars are
+    /// aligned to char boundaries and batched in order without panicking.
+    #[test]
+    fn test_align_chunks_random_offsets() {
+        use rand::{Rng, SeedableRng, rngs::StdRng, seq::IndexedRandom};
+
+        use crate::orchestrator::types::{DetectionBatcher, Detections, MaxProcessedIndexBatcher};
+
+        let words = ["hello", "world", "🦀", "👩‍👩‍👧", "你好", "世界", "é", "。", " "];
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..500 {
+            let text = (0..rng.random_range(1..20))
+                .map(|_| *words.choose(&mut rng).unwrap())
+                .collect::<String>();
+            let char_len = text.chars().count();
+            // Random boundaries in bytes or chars, possibly inside chars or out of bounds
+            let max = if rng.random_bool(0.5) {
+                text.len()
+            } else {
+                char_len
+            } + 2;
+            let mut boundaries = (0..rng.random_range(0..6))
+                .map(|_| rng.random_range(0..=max))
+                .collect::<Vec<_>>();
+            boundaries.extend([0, max]);
+            boundaries.sort();
+            boundaries.dedup();
+            let chunks = boundaries
+                .windows(2)
+                .map(|span| chunk(span[0], span[1], "?"))
+                .collect::<Chunks>();
+
+            let chunks = align_chunks("chunker", &text, chunks);
+            let mut batcher = MaxProcessedIndexBatcher::new(2);
+            for chunk in chunks.iter() {
+                assert!(
+                    chunk.start <= chunk.end && chunk.end <= char_len,
+                    "{text:?}: {chunk:?}"
+                );
+                for detector_id in ["a", "b"] {
+                    batcher.push(0, detector_id.into(), chunk.clone(), Detections::new());
+                }
+            }
+            let mut batches = Vec::new();
+            while let Some((chunk, _)) = batcher.pop_batch() {
+                batches.push(chunk);
+            }
+            assert!(batcher.is_empty());
+            assert!(batches.is_sorted(), "{text:?}: {batches:?}");
+        }
+    }
+
+    /// Strategies generating unicode text and spans over it.
+    mod strategies {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        /// Text mixing ASCII, multi-byte and multi-codepoint chars.
+        pub fn text() -> impl Strategy<Value = String> {
+            let words = prop::sample::select(vec![
+                "hello",
+                "world",
+                "🦀",
+                "👩‍👩‍👧",
+                "你好",
+                "世界",
+                "é",
+                "e\u{301}",
+                "。",
+                " ",
+                "\n",
+            ]);
+            prop_oneof![
+                prop::collection::vec(words, 0..20).prop_map(|words| words.concat()),
+                any::<String>(),
+            ]
+        }
+
+        /// Text with contiguous chunk spans covering it, with char offsets.
+        pub fn chunked_text() -> impl Strategy<Value = (String, Chunks)> {
+            text().prop_flat_map(|text| {
+                let char_len = text.chars().count();
+                prop::collection::vec(0..=char_len, 0..6).prop_map(move |mut boundaries| {
+                    boundaries.extend([0, char_len]);
+                    boundaries.sort();
+                    boundaries.dedup();
+                    let chunks = boundaries
+                        .windows(2)
+                        .map(|span| {
+                            chunk(span[0], span[1], &slice_codepoints(&text, span[0], span[1]))
+                        })
+                        .collect::<Chunks>();
+                    (text.clone(), chunks)
+                })
+            })
+        }
+
+        /// Text with a chunk of it and a detection span within the chunk, in chars.
+        pub fn detection_in_chunk() -> impl Strategy<Value = (String, Chunk, (usize, usize))> {
+            chunked_text()
+                .prop_filter("no chunks", |(_, chunks)| !chunks.is_empty())
+                .prop_flat_map(|(text, chunks)| {
+                    let n_chunks = chunks.len();
+                    (Just(text), Just(chunks), 0..n_chunks)
+                })
+                .prop_flat_map(|(text, chunks, index)| {
+                    let chunk = chunks[index].clone();
+                    let len = chunk.end - chunk.start;
+                    (Just(text), Just(chunk), 0..=len, 0..=len)
+                })
+                .prop_map(|(text, chunk, a, b)| (text, chunk, (a.min(b), a.max(b))))
+        }
+    }
+
+    mod properties {
+        use proptest::prelude::*;
+
+        use super::{strategies::*, *};
+
+        proptest! {
+            #[test]
+            fn slice_codepoints_is_bounded(
+                text in text(),
+                start in 0..40usize,
+                end in 0..40usize,
+            ) {
+                let char_len = text.chars().count();
+                let expected = end.min(char_len).saturating_sub(start.min(char_len));
+                prop_assert_eq!(slice_codepoints(&text, start, end).chars().count(), expected);
+            }
+
+            #[test]
+            fn align_chunks_keeps_char_offsets((text, chunks) in chunked_text()) {
+                let aligned = align_chunks("chunker", &text, chunks.clone());
+                prop_assert_eq!(spans(&aligned), spans(&chunks));
+            }
+
+            #[test]
+            fn align_chunks_converts_byte_offsets(
+                text in text(),
+                boundaries in prop::collection::vec(0..64usize, 0..6),
+            ) {
+                // Byte offsets, possibly inside chars or out of bounds
+                let mut boundaries = boundaries
+                    .into_iter()
+                    .map(|boundary| boundary.min(text.len() + 2))
+                    .collect::<Vec<_>>();
+                boundaries.extend([0, text.len()]);
+                boundaries.sort();
+                boundaries.dedup();
+                let chunks = boundaries
+                    .windows(2)
+                    .map(|span| chunk(span[0], span[1], "?"))
+                    .collect::<Chunks>();
+                let char_len = text.chars().count();
+                let aligned = align_chunks("chunker", &text, chunks);
+                for chunk in aligned.iter() {
+                    prop_assert!(
+                        chunk.start <= chunk.end && chunk.end <= char_len,
+                        "{:?}",
+                        chunk
+                    );
+                }
+            }
+
+            #[test]
+            fn normalize_chunks_preserves_coverage(
+                (text, chunks) in chunked_text(),
+                min_size in prop::option::of(0..10usize),
+                max_size in prop::option::of(1..10usize),
+            ) {
+                let normalized = normalize_chunks(&text, chunks.clone(), min_size, max_size);
+                prop_assert_eq!(
+                    normalized.first().map(|c| c.start),
+                    chunks.first().map(|c| c.start)
+                );
+                prop_assert_eq!(
+                    normalized.last().map(|c| c.end),
+                    chunks.last().map(|c| c.end)
+                );
+                for pair in normalized.windows(2) {
+                    prop_assert_eq!(pair[0].end, pair[1].start);
+                }
+                for chunk in normalized.iter() {
+                    prop_assert_eq!(
+                        &*chunk.text,
+                        slice_codepoints(&text, chunk.start, chunk.end)
+                    );
+                    if let Some(max_size) = max_size {
+                        prop_assert!(chunk.end - chunk.start <= max_size, "{:?}", chunk);
+                    }
+                }
+            }
+
+            #[test]
+            fn split_chunk_concatenates_to_chunk(
+                (text, chunks) in chunked_text(),
+                max_size in 0..10usize,
+            ) {
+                for chunk in chunks {
+                    let split = split_chunk(chunk.clone(), max_size);
+                    let concatenated = split.iter().map(|c| &*c.text).collect::<String>();
+                    prop_assert_eq!(&concatenated, &*chunk.text);
+                    prop_assert_eq!(split.first().unwrap().start, chunk.start);
+                    prop_assert_eq!(split.last().unwrap().end, chunk.end);
+                    for part in &split {
+                        prop_assert_eq!(
+                            &*part.text,
+                            slice_codepoints(&text, part.start, part.end)
+                        );
+                    }
+                }
+            }
+
+            #[test]
+            fn detection_offsets_translate_to_text(
+                (text, chunk, (start, end)) in detection_in_chunk(),
+            ) {
+                // Detection spans are relative to the chunk, offset by the chunk start
+                prop_assert_eq!(
+                    slice_codepoints(&text, chunk.start + start, chunk.start + end),
+                    slice_codepoints(&chunk.text, start, end)
+                );
+            }
+
+            #[test]
+            fn apply_masks_offsets_translate_to_text(
+                (text, chunks) in chunked_text(),
+            ) {
+                let masks = chunks.iter().map(|c| (c.start, c.end)).collect::<Vec<_>>();
+                for (offset, masked_text) in apply_masks(text.clone(), Some(&masks)) {
+                    let end = offset + masked_text.chars().count();
+                    prop_assert_eq!(slice_codepoints(&text, offset, end), masked_text);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_try_collect_rate_limited() {
+        let rate_limited = |id: &str, retry_after: u64| Error::DetectorRequestFailed {
+            id: id.into(),
+            error: crate::clients::Error::RateLimited {
+                message: "too many requests".into(),
+                retry_at: Some(std::time::Instant::now() + Duration::from_secs(retry_after)),
+            },
+        };
+        let results = futures::stream::iter([
+            Err(rate_limited("a", 3)),
+            Ok(1),
+            Err(rate_limited("b", 7)),
+            Err(rate_limited("c", 5)),
+        ]);
+        let error = try_collect_rate_limited(results).await.unwrap_err();
+        assert!(matches!(&error, Error::DetectorRequestFailed { id, .. } if id == "b"));
+        assert!(error.retry_after().unwrap() > Duration::from_secs(6));
+
+        // Other errors fail fast
+        let results = futures::stream::iter([Ok(1), Err(Error::Cancelled), Ok(2)]);
+        assert_eq!(
+            try_collect_rate_limited(results).await,
+            Err(Error::Cancelled)
+        );
+        let results = futures::stream::iter([Ok::<_, Error>(1), Ok(2)]);
+        assert_eq!(try_collect_rate_limited(results).await, Ok(vec![1, 2]));
     }
 }
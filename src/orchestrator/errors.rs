@@ -14,6 +14,8 @@
  limitations under the License.
 
 */
+use std::time::Duration;
+
 use crate::{clients, models::ValidationError};
 
 /// Orchestrator errors.
@@ -25,6 +27,8 @@ pub enum Error {
     DetectorNotFound(String),
     #[error("chunker `{0}` not found")]
     ChunkerNotFound(String),
+    #[error("pending detections `{0}` not found")]
+    PendingDetectionsNotFound(String),
     #[error("detector request failed for `{id}`: {error}")]
     DetectorRequestFailed { id: String, error: clients::Error },
     #[error("chunker request failed for `{id}`: {error}")]
@@ -35,6 +39,10 @@ pub enum Error {
     ChatCompletionRequestFailed { id: String, error: clients::Error },
     #[error("completion request failed for `{id}`: {error}")]
     CompletionRequestFailed { id: String, error: clients::Error },
+    #[error(
+        "generation aborted for `{id}`: stream consumer stalled for more than {stall_timeout}s"
+    )]
+    GenerationStalled { id: String, stall_timeout: u64 },
     #[error("tokenize request failed for `{id}`: {error}")]
     TokenizeRequestFailed { id: String, error: clients::Error },
     #[error("validation error: {0}")]
@@ -47,6 +55,36 @@ pub enum Error {
     JsonError(String),
 }
 
+impl Error {
+    /// Returns the error of the client request that failed, if any.
+    pub fn client_error(&self) -> Option<&clients::Error> {
+        match self {
+            Error::Client(error)
+            | Error::DetectorRequestFailed { error, .. }
+            | Error::ChunkerRequestFailed { error, .. }
+            | Error::GenerateRequestFailed { error, .. }
+            | Error::ChatCompletionRequestFailed { error, .. }
+            | Error::CompletionRequestFailed { error, .. }
+            | Error::TokenizeRequestFailed { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Returns whether a client request was rate limited.
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(
+            self.client_error(),
+            Some(clients::Error::RateLimited { .. })
+        )
+    }
+
+    /// Returns the delay a rate limited backend asked to wait before retrying, less
+    /// the time elapsed since, if any.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.client_error().and_then(clients::Error::retry_after)
+    }
+}
+
 impl From<tokio::task::JoinError> for Error {
     fn from(error: tokio::task::JoinError) -> Self {
         if error.is_cancelled() {
@@ -24,6 +24,8 @@ pub use streaming_classification_with_gen::StreamingClassificationWithGenTask;
 pub mod chat_completions_detection;
 pub mod streaming_content_detection;
 pub use streaming_content_detection::StreamingContentDetectionTask;
+pub mod streaming_input_detection;
+pub use streaming_input_detection::StreamingInputDetectionTask;
 pub mod generation_with_detection;
 pub use generation_with_detection::GenerationWithDetectionTask;
 pub mod chat_detection;
@@ -33,7 +35,11 @@ pub use context_docs_detection::ContextDocsDetectionTask;
 pub mod detection_on_generation;
 pub use detection_on_generation::DetectionOnGenerationTask;
 pub mod text_content_detection;
-pub use text_content_detection::TextContentDetectionTask;
+pub use text_content_detection::{PendingContentDetectionTask, TextContentDetectionTask};
+pub mod suitability;
+pub use suitability::SuitabilityTask;
+pub mod chunks;
+pub use chunks::ChunksTask;
 
 use super::Error;
 
@@ -21,6 +21,7 @@ use tracing::instrument;
 use super::Handle;
 use crate::{
     clients::openai::{ChatCompletionsRequest, ChatCompletionsResponse},
+    config::DetectionsFilter,
     orchestrator::{Error, Orchestrator},
 };
 
@@ -37,10 +38,24 @@ impl Handle<ChatCompletionsDetectionTask> for Orchestrator {
     )]
     async fn handle(&self, task: ChatCompletionsDetectionTask) -> Result<Self::Response, Error> {
         let ctx = self.ctx.clone();
-        match task.request.stream {
-            Some(true) => streaming::handle_streaming(ctx, task).await,
+        let trace_id = task.trace_id;
+        let provenance = ctx.provenance.clone();
+        let response = match task.request.stream {
+            // Dry runs return a unary response as no chat completion is requested
+            Some(true) if !task.dry_run => streaming::handle_streaming(ctx, task).await,
             _ => unary::handle_unary(ctx, task).await,
-        }
+        }?;
+        // Add provenance of the generated choices, if configured
+        Ok(match (provenance, response) {
+            (Some(provenance), ChatCompletionsResponse::Unary(mut chat_completion)) => {
+                provenance.add_to_chat_completion(trace_id, &mut chat_completion);
+                ChatCompletionsResponse::Unary(chat_completion)
+            }
+            (Some(provenance), ChatCompletionsResponse::Streaming(response_rx)) => {
+                ChatCompletionsResponse::Streaming(provenance.chat_stream(trace_id, response_rx))
+            }
+            (None, response) => response,
+        })
     }
 }
 
@@ -52,14 +67,36 @@ pub struct ChatCompletionsDetectionTask {
     pub request: ChatCompletionsRequest,
     /// Headers
     pub headers: HeaderMap,
+    /// Headers sent to the generation service, including generation-only headers
+    pub generation_headers: HeaderMap,
+    /// Filter for detections returned in responses
+    pub detections_filter: DetectionsFilter,
+    /// Whether to run input detection only, without generation or enforcing actions
+    pub dry_run: bool,
 }
 
 impl ChatCompletionsDetectionTask {
-    pub fn new(trace_id: TraceId, request: ChatCompletionsRequest, headers: HeaderMap) -> Self {
+    pub fn new(
+        trace_id: TraceId,
+        mut request: ChatCompletionsRequest,
+        headers: HeaderMap,
+        detections_filter: DetectionsFilter,
+        dry_run: bool,
+    ) -> Self {
+        request.apply_guardrails();
         Self {
             trace_id,
             request,
+            generation_headers: headers.clone(),
             headers,
+            detections_filter,
+            dry_run,
         }
     }
+
+    /// Adds headers sent to the generation service only, never to detectors.
+    pub fn with_generation_headers(mut self, headers: HeaderMap) -> Self {
+        self.generation_headers.extend(headers);
+        self
+    }
 }
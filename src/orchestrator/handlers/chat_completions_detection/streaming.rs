@@ -14,42 +14,546 @@
  limitations under the License.
 
 */
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
 
+use futures::StreamExt;
+use opentelemetry::trace::TraceId;
 use tokio::sync::mpsc;
-use tracing::{Instrument, info};
+use tracing::{Instrument, error, info, instrument};
+use uuid::Uuid;
 
-use super::ChatCompletionsDetectionTask;
+use super::{ChatCompletionsDetectionTask, unary};
 use crate::{
     clients::openai::*,
-    orchestrator::{Context, Error},
+    config::{DetectionsFilter, DetectorType, FlaggedToolsPolicy, ImagePartsPolicy},
+    models::{
+        DetectionWarningReason, DetectorParams, REMOVED_TOOLS_MESSAGE, UNSUITABLE_OUTPUT_MESSAGE,
+        UNSUITABLE_TOOLS_MESSAGE,
+    },
+    orchestrator::{
+        Context, Error,
+        common::{self, features, validate_detectors},
+        types::{
+            ChatCompletionBatcher, ChatCompletionStream, DetectionBatchStream, Detections,
+            StreamingDetectionsFilter,
+        },
+    },
 };
 
+const CHAT_COMPLETION_CHUNK_OBJECT: &str = "chat.completion.chunk";
+
+type ResponseSender = mpsc::Sender<Result<Option<ChatCompletionChunk>, Error>>;
+
 pub async fn handle_streaming(
-    _ctx: Arc<Context>,
-    task: ChatCompletionsDetectionTask,
+    ctx: Arc<Context>,
+    mut task: ChatCompletionsDetectionTask,
 ) -> Result<ChatCompletionsResponse, Error> {
     let trace_id = task.trace_id;
     let detectors = task.request.detectors.clone();
     info!(%trace_id, config = ?detectors, "task started");
-    let _input_detectors = detectors.input;
-    let _output_detectors = detectors.output;
+    let mut input_detectors = detectors.input;
+    let mut output_detectors = detectors.output;
+
+    if input_detectors.is_empty() && output_detectors.is_empty() {
+        // No detectors, forward chat completion chunks as-is,
+        // including logprobs and usage chunks
+        let client = ctx
+            .clients
+            .get_as::<OpenAiClient>("chat_generation")
+            .unwrap();
+        return common::chat_completion(client, task.generation_headers, task.request).await;
+    }
+
+    validate_detectors(
+        &input_detectors,
+        &ctx.config.detectors,
+        &[DetectorType::TextContents],
+        true,
+    )?;
+
+    // Disallow `whole_doc_chunker` detectors on output detection,
+    // as generated output is processed in chunks as it is streamed
+    validate_detectors(
+        &output_detectors,
+        &ctx.config.detectors,
+        &[DetectorType::TextContents],
+        false,
+    )?;
+
+    // Detections are batched by chunk, which requires a single chunker
+    let mut chunker_ids = common::get_chunker_ids(&ctx, &output_detectors)?;
+    chunker_ids.sort_unstable();
+    chunker_ids.dedup();
+    if chunker_ids.len() > 1 {
+        return Err(Error::Validation(
+            "Output detectors of streaming chat completions must use the same chunker".into(),
+        ));
+    }
+
+    // Skip detection phases disabled by feature flags
+    let mut annotations = Annotations {
+        warnings: features::apply_detection_flags(
+            &ctx.features.get(),
+            true,
+            &mut input_detectors,
+            &mut output_detectors,
+        )
+        .into_iter()
+        .map(|warning| {
+            OrchestratorWarning::new(
+                DetectionWarningReason::DetectionDisabled,
+                &warning.message.unwrap_or_default(),
+            )
+        })
+        .collect(),
+        tools: Vec::new(),
+    };
+
+    let actions = task.request.guardrails.actions.clone();
+    if !input_detectors.is_empty() {
+        if actions.image_parts.unwrap_or(ctx.config.image_parts) == ImagePartsPolicy::Reject
+            && task
+                .request
+                .messages
+                .iter()
+                .any(|message| message.content.as_ref().is_some_and(Content::has_images))
+        {
+            return Err(Error::Validation(
+                "Image content parts are not supported by input detectors".into(),
+            ));
+        }
+        // Handle tool definitions detection
+        let detections =
+            unary::handle_tools_detection(ctx.clone(), &task, &input_detectors).await?;
+        if !detections.is_empty() {
+            match actions.flagged_tools.unwrap_or(ctx.config.flagged_tools) {
+                FlaggedToolsPolicy::Reject => {
+                    info!(%trace_id, "task completed: returning response with tool detections");
+                    let chat_completion = ChatCompletion {
+                        id: Uuid::new_v4().simple().to_string(),
+                        model: task.request.model.clone(),
+                        created: common::current_timestamp().as_secs() as i64,
+                        detections: Some(ChatDetections {
+                            tools: detections,
+                            ..Default::default()
+                        }),
+                        warnings: vec![OrchestratorWarning::new(
+                            DetectionWarningReason::UnsuitableTools,
+                            UNSUITABLE_TOOLS_MESSAGE,
+                        )],
+                        ..Default::default()
+                    };
+                    return Ok(single_chunk_response(chat_completion));
+                }
+                FlaggedToolsPolicy::Strip => {
+                    task.request.retain_tools(|index| {
+                        !detections
+                            .iter()
+                            .any(|result| result.tool_index as usize == index)
+                    });
+                    annotations.warnings.push(OrchestratorWarning::new(
+                        DetectionWarningReason::UnsuitableTools,
+                        REMOVED_TOOLS_MESSAGE,
+                    ));
+                    annotations.tools = detections;
+                }
+            }
+        }
+        // Handle input detection
+        if let Some(chat_completion) =
+            unary::handle_input_detection(ctx.clone(), &task, input_detectors).await?
+        {
+            info!(%trace_id, "task completed: returning response with input detections");
+            return Ok(single_chunk_response(chat_completion));
+        }
+    }
+
+    // Create chat completion stream
+    let client = ctx
+        .clients
+        .get_as::<OpenAiClient>("chat_generation")
+        .unwrap();
+    let chat_completion_stream = common::chat_completion_stream(
+        client,
+        task.generation_headers.clone(),
+        task.request.clone(),
+    )
+    .await?;
 
     // Create response channel
     let (response_tx, response_rx) =
         mpsc::channel::<Result<Option<ChatCompletionChunk>, Error>>(128);
 
+    if !output_detectors.is_empty() {
+        // Handle output detection
+        handle_output_detection(
+            ctx,
+            task,
+            output_detectors,
+            annotations,
+            chat_completion_stream,
+            response_tx,
+        )
+        .await?;
+    } else {
+        // No output detectors, forward chat completion stream to response stream
+        tokio::spawn(
+            forward_chat_completion_stream(
+                trace_id,
+                annotations,
+                chat_completion_stream,
+                response_tx,
+            )
+            .in_current_span(),
+        );
+    }
+
+    Ok(ChatCompletionsResponse::Streaming(response_rx))
+}
+
+/// Returns a streaming response with a single chunk built from `chat_completion`,
+/// which is returned in place of generated output, e.g. with input detections.
+fn single_chunk_response(chat_completion: ChatCompletion) -> ChatCompletionsResponse {
+    let chunk = ChatCompletionChunk {
+        id: chat_completion.id,
+        object: CHAT_COMPLETION_CHUNK_OBJECT.into(),
+        created: chat_completion.created,
+        model: chat_completion.model,
+        detections: chat_completion.detections,
+        warnings: chat_completion.warnings,
+        ..Default::default()
+    };
+    let (response_tx, response_rx) = mpsc::channel(2);
+    let _ = response_tx.try_send(Ok(Some(chunk)));
+    // Send None to signal that the stream completed
+    let _ = response_tx.try_send(Ok(None));
+    ChatCompletionsResponse::Streaming(response_rx)
+}
+
+#[instrument(skip_all)]
+async fn handle_output_detection(
+    ctx: Arc<Context>,
+    task: ChatCompletionsDetectionTask,
+    detectors: HashMap<String, DetectorParams>,
+    annotations: Annotations,
+    mut chat_completion_stream: ChatCompletionStream,
+    response_tx: ResponseSender,
+) -> Result<(), Error> {
+    let trace_id = task.trace_id;
+    let n_detectors = detectors.len();
+    // Create detection streams for each choice, using the choice index as input id
+    let mut input_txs = HashMap::new();
+    let mut detection_streams = Vec::new();
+    for choice_index in 0..task.request.n() {
+        let (input_tx, input_rx) = mpsc::channel(128);
+        let streams = common::text_contents_detection_streams(
+            ctx.clone(),
+            task.headers.clone(),
+            detectors.clone(),
+            choice_index,
+            input_rx,
+        )
+        .await?;
+        input_txs.insert(choice_index, input_tx);
+        detection_streams.extend(streams);
+    }
+    // Create detection batch stream, batching detections by choice and chunk
+    let detection_batch_stream =
+        DetectionBatchStream::new(ChatCompletionBatcher::new(n_detectors), detection_streams);
+    // Create shared chat completion chunks
+    let chunks = Arc::new(RwLock::new(ChoiceChunks::default()));
+
+    // Spawn task to process detection batch stream
+    tokio::spawn(
+        process_detection_batch_stream(
+            trace_id,
+            task.detections_filter,
+            chunks.clone(),
+            annotations,
+            detection_batch_stream,
+            response_tx,
+        )
+        .in_current_span(),
+    );
+
+    // Spawn task to consume chat completion chunks
     tokio::spawn(
         async move {
-            // TODO
-            let _ = response_tx
-                .send(Err(Error::Validation(
-                    "streaming is not yet supported".into(),
-                )))
-                .await;
+            while let Some((_index, result)) = chat_completion_stream.next().await {
+                match result {
+                    Ok(Some(chunk)) => {
+                        let inputs = chunks.write().unwrap().push(chunk);
+                        for (choice_index, message_index, text) in inputs {
+                            // Send content delta to input channel of the choice
+                            if let Some(input_tx) = input_txs.get(&choice_index) {
+                                let _ = input_tx.send(Ok((message_index, text))).await;
+                            }
+                        }
+                    }
+                    Ok(None) => break, // The stream completed
+                    Err(error) => {
+                        // Send error to the input channel of each choice, terminating the
+                        // detection batch stream whichever choice it is waiting on
+                        for input_tx in input_txs.values() {
+                            let _ = input_tx.send(Err(error.clone())).await;
+                        }
+                        break;
+                    }
+                }
+            }
         }
         .in_current_span(),
     );
 
-    Ok(ChatCompletionsResponse::Streaming(response_rx))
+    Ok(())
+}
+
+/// Consumes a chat completion stream, forwarding chunks to a response channel.
+#[instrument(skip_all)]
+async fn forward_chat_completion_stream(
+    trace_id: TraceId,
+    mut annotations: Annotations,
+    mut chat_completion_stream: ChatCompletionStream,
+    response_tx: ResponseSender,
+) {
+    while let Some((_index, result)) = chat_completion_stream.next().await {
+        match result {
+            Ok(Some(mut chunk)) => {
+                annotations.apply(&mut chunk);
+                // Send chunk to response channel
+                if response_tx.send(Ok(Some(chunk))).await.is_err() {
+                    info!(%trace_id, "task completed: client disconnected");
+                    return;
+                }
+            }
+            Ok(None) => break, // The stream completed
+            Err(error) => {
+                error!(%trace_id, %error, "task failed: error received from chat completion stream");
+                // Send error to response channel and terminate
+                let _ = response_tx.send(Err(error)).await;
+                return;
+            }
+        }
+    }
+    // Send None to signal that the stream completed
+    let _ = response_tx.send(Ok(None)).await;
+    info!(%trace_id, "task completed: chat completion stream closed");
+}
+
+/// Consumes a detection batch stream, builds chunks with output detections,
+/// and sends them to a response channel.
+#[instrument(skip_all)]
+async fn process_detection_batch_stream(
+    trace_id: TraceId,
+    detections_filter: DetectionsFilter,
+    chunks: Arc<RwLock<ChoiceChunks>>,
+    mut annotations: Annotations,
+    mut detection_batch_stream: DetectionBatchStream<ChatCompletionBatcher>,
+    response_tx: ResponseSender,
+) {
+    // Index of the next message of each choice not yet included in a response
+    let mut next_indices: HashMap<u32, usize> = HashMap::new();
+    // Filter of detections of each choice, as each choice is a response of its own
+    let mut detections_filters: HashMap<u32, StreamingDetectionsFilter> = HashMap::new();
+    while let Some(result) = detection_batch_stream.next().await {
+        match result {
+            Ok((chunk, choice_index, mut detections)) => {
+                detections_filters
+                    .entry(choice_index)
+                    .or_insert_with(|| StreamingDetectionsFilter::new(detections_filter))
+                    .apply(&mut detections);
+                let next_index = next_indices.entry(choice_index).or_default();
+                let response = chunks.read().unwrap().response(
+                    choice_index,
+                    *next_index,
+                    chunk.input_end_index,
+                    Some(chunk.text.to_string()),
+                );
+                *next_index = chunk.input_end_index + 1;
+                let Some(mut response) = response else {
+                    continue;
+                };
+                add_output_detections(&mut response, choice_index, detections);
+                annotations.apply(&mut response);
+                // Send response to response channel
+                if response_tx.send(Ok(Some(response))).await.is_err() {
+                    info!(%trace_id, "task completed: client disconnected");
+                    return;
+                }
+            }
+            Err(error) => {
+                error!(%trace_id, %error, "task failed: error received from detection batch stream");
+                // Send error to response channel and terminate
+                let _ = response_tx.send(Err(error)).await;
+                return;
+            }
+        }
+    }
+    // Send messages not included in a chunk, e.g. a final message with the
+    // finish reason, and the usage chunk, if requested
+    let remaining = chunks.read().unwrap().remaining(&next_indices);
+    for mut response in remaining {
+        annotations.apply(&mut response);
+        if response_tx.send(Ok(Some(response))).await.is_err() {
+            info!(%trace_id, "task completed: client disconnected");
+            return;
+        }
+    }
+    // Send None to signal that the stream completed
+    let _ = response_tx.send(Ok(None)).await;
+    info!(%trace_id, "task completed: detection batch stream closed");
+}
+
+/// Adds output detections of a choice to a response chunk.
+fn add_output_detections(
+    response: &mut ChatCompletionChunk,
+    choice_index: u32,
+    detections: Detections,
+) {
+    if detections.is_empty() {
+        return;
+    }
+    response.detections = Some(ChatDetections {
+        output: vec![OutputDetectionResult {
+            choice_index,
+            field: MessageField::Content,
+            results: detections.into(),
+        }],
+        ..Default::default()
+    });
+    response.warnings.push(OrchestratorWarning::new(
+        DetectionWarningReason::UnsuitableOutput,
+        UNSUITABLE_OUTPUT_MESSAGE,
+    ));
+}
+
+/// Warnings and tool detections included in the first chunk of a response.
+struct Annotations {
+    warnings: Vec<OrchestratorWarning>,
+    tools: Vec<ToolDetectionResult>,
+}
+
+impl Annotations {
+    /// Moves pending annotations to `chunk`.
+    fn apply(&mut self, chunk: &mut ChatCompletionChunk) {
+        chunk.warnings.append(&mut self.warnings);
+        if !self.tools.is_empty() {
+            chunk.detections.get_or_insert_default().tools = std::mem::take(&mut self.tools);
+        }
+    }
+}
+
+/// Chat completion chunks received from the chat generation service.
+#[derive(Default)]
+struct ChoiceChunks {
+    /// Chunks by choice index, each containing only that choice
+    choices: HashMap<u32, Vec<ChatCompletionChunk>>,
+    /// Chunk with usage statistics, if requested with `stream_options.include_usage`
+    usage: Option<ChatCompletionChunk>,
+}
+
+impl ChoiceChunks {
+    /// Stores a chunk, returning the choice index, message index and
+    /// content delta of each of its choices.
+    fn push(&mut self, chunk: ChatCompletionChunk) -> Vec<(u32, usize, String)> {
+        if chunk.choices.is_empty() {
+            if chunk.usage.is_some() {
+                self.usage = Some(chunk);
+            }
+            return Vec::new();
+        }
+        let mut inputs = Vec::with_capacity(chunk.choices.len());
+        for choice in &chunk.choices {
+            let chunks = self.choices.entry(choice.index).or_default();
+            let text = choice.delta.content.clone().unwrap_or_default();
+            inputs.push((choice.index, chunks.len(), text));
+            chunks.push(ChatCompletionChunk {
+                choices: vec![choice.clone()],
+                ..chunk.clone()
+            });
+        }
+        inputs
+    }
+
+    /// Builds a response chunk for messages `start..=end` of a choice, with
+    /// `content` replacing their content deltas.
+    fn response(
+        &self,
+        choice_index: u32,
+        start: usize,
+        end: usize,
+        content: Option<String>,
+    ) -> Option<ChatCompletionChunk> {
+        let chunks = self.choices.get(&choice_index)?;
+        let last = chunks.get(end)?;
+        let choice = last.choices.first()?;
+        let messages = chunks
+            .get(start..=end)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|chunk| chunk.choices.first())
+            .collect::<Vec<_>>();
+        let concat = |field: fn(&ChatCompletionDelta) -> Option<&str>| {
+            let text = messages
+                .iter()
+                .filter_map(|choice| field(&choice.delta))
+                .collect::<String>();
+            (!text.is_empty()).then_some(text)
+        };
+        let logprobs = messages
+            .iter()
+            .filter_map(|choice| choice.logprobs.as_ref()?.content.as_ref())
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        Some(ChatCompletionChunk {
+            id: last.id.clone(),
+            object: last.object.clone(),
+            created: last.created,
+            model: last.model.clone(),
+            system_fingerprint: last.system_fingerprint.clone(),
+            choices: vec![ChatCompletionChunkChoice {
+                index: choice_index,
+                delta: ChatCompletionDelta {
+                    role: messages.iter().find_map(|choice| choice.delta.role.clone()),
+                    content,
+                    refusal: concat(|delta| delta.refusal.as_deref()),
+                    reasoning_content: concat(|delta| delta.reasoning_content.as_deref()),
+                    tool_calls: messages
+                        .iter()
+                        .flat_map(|choice| choice.delta.tool_calls.iter().cloned())
+                        .collect(),
+                },
+                logprobs: (!logprobs.is_empty()).then_some(ChatCompletionLogprobs {
+                    content: Some(logprobs),
+                    refusal: None,
+                }),
+                finish_reason: choice.finish_reason.clone(),
+                stop_reason: choice.stop_reason.clone(),
+            }],
+            service_tier: last.service_tier.clone(),
+            ..Default::default()
+        })
+    }
+
+    /// Returns response chunks for messages not yet included in a response,
+    /// given the index of the next message of each choice, followed by the usage chunk.
+    fn remaining(&self, next_indices: &HashMap<u32, usize>) -> Vec<ChatCompletionChunk> {
+        let mut choice_indices = self.choices.keys().copied().collect::<Vec<_>>();
+        choice_indices.sort_unstable();
+        let mut responses = choice_indices
+            .into_iter()
+            .filter_map(|choice_index| {
+                let start = next_indices.get(&choice_index).copied().unwrap_or_default();
+                let end = self.choices[&choice_index].len().checked_sub(1)?;
+                // Content not included in a chunk was not analyzed and is omitted
+                (start <= end).then(|| self.response(choice_index, start, end, None))?
+            })
+            .collect::<Vec<_>>();
+        responses.extend(self.usage.clone());
+        responses
+    }
 }
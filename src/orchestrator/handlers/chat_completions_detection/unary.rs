@@ -17,32 +17,41 @@
 use std::{collections::HashMap, sync::Arc};
 
 use futures::future::try_join_all;
-use tracing::{Instrument, error, info, instrument};
+use http::HeaderMap;
+use tracing::{Instrument, error, info, instrument, warn};
 use uuid::Uuid;
 
 use super::ChatCompletionsDetectionTask;
 use crate::{
     clients::openai::*,
-    config::DetectorType,
+    config::{
+        DetectorType, FlaggedChoicesPolicy, FlaggedToolsPolicy, ImagePartsPolicy,
+        StructuredOutputPolicy,
+    },
     models::{
-        DetectionWarningReason, DetectorParams, UNSUITABLE_INPUT_MESSAGE, UNSUITABLE_OUTPUT_MESSAGE,
+        ALL_CHOICES_FLAGGED_MESSAGE, DRY_RUN_MESSAGE, DetectionWarningReason, DetectorParams,
+        REMOVED_TOOLS_MESSAGE, UNSUITABLE_INPUT_MESSAGE, UNSUITABLE_OUTPUT_MESSAGE,
+        UNSUITABLE_TOOLS_MESSAGE, invalid_structured_output_message,
     },
     orchestrator::{
         Context, Error,
-        common::{self, validate_detectors},
-        types::ChatMessageIterator,
+        common::{self, features, validate_detectors},
+        types::{ChatMessage, ChatMessageIterator, Detections, MessageSegment},
     },
 };
 
+/// Maximum number of chat completion retries for invalid structured output.
+const MAX_STRUCTURED_OUTPUT_RETRIES: usize = 2;
+
 pub async fn handle_unary(
     ctx: Arc<Context>,
-    task: ChatCompletionsDetectionTask,
+    mut task: ChatCompletionsDetectionTask,
 ) -> Result<ChatCompletionsResponse, Error> {
     let trace_id = task.trace_id;
     let detectors = task.request.detectors.clone();
     info!(%trace_id, config = ?detectors, "task started");
-    let input_detectors = detectors.input;
-    let output_detectors = detectors.output;
+    let mut input_detectors = detectors.input;
+    let mut output_detectors = detectors.output;
 
     validate_detectors(
         &input_detectors,
@@ -58,7 +67,92 @@ pub async fn handle_unary(
         true,
     )?;
 
+    // Skip detection phases disabled by feature flags
+    let disabled_warnings = features::apply_detection_flags(
+        &ctx.features.get(),
+        false,
+        &mut input_detectors,
+        &mut output_detectors,
+    )
+    .into_iter()
+    .map(|warning| {
+        OrchestratorWarning::new(
+            DetectionWarningReason::DetectionDisabled,
+            &warning.message.unwrap_or_default(),
+        )
+    })
+    .collect::<Vec<_>>();
+
+    let actions = task.request.guardrails.actions.clone();
+    let structured_output_policy = actions
+        .structured_output
+        .unwrap_or(ctx.config.structured_output);
+    let structured_output = match task.request.response_format()? {
+        Some(response_format)
+            if response_format.is_json()
+                && structured_output_policy != StructuredOutputPolicy::Passthrough =>
+        {
+            Some(StructuredOutput::new(&response_format)?)
+        }
+        _ => None,
+    };
+
+    if task.dry_run {
+        // Handle input detection only, without high-severity alerts
+        if let Some(alerts) = &ctx.alerts {
+            alerts.suppress(trace_id);
+        }
+        let mut chat_completion = handle_dry_run(ctx, &task, input_detectors).await?;
+        chat_completion.warnings.extend(disabled_warnings);
+        info!(%trace_id, "task completed: returning dry run response");
+        return Ok(chat_completion.into());
+    }
+
+    let mut tool_detections = Vec::new();
     if !input_detectors.is_empty() {
+        if actions.image_parts.unwrap_or(ctx.config.image_parts) == ImagePartsPolicy::Reject
+            && task
+                .request
+                .messages
+                .iter()
+                .any(|message| message.content.as_ref().is_some_and(Content::has_images))
+        {
+            return Err(Error::Validation(
+                "Image content parts are not supported by input detectors".into(),
+            ));
+        }
+        // Handle tool definitions detection
+        let detections = handle_tools_detection(ctx.clone(), &task, &input_detectors).await?;
+        if !detections.is_empty() {
+            match actions.flagged_tools.unwrap_or(ctx.config.flagged_tools) {
+                FlaggedToolsPolicy::Reject => {
+                    info!(%trace_id, "task completed: returning response with tool detections");
+                    let chat_completion = ChatCompletion {
+                        id: Uuid::new_v4().simple().to_string(),
+                        model: task.request.model.clone(),
+                        created: common::current_timestamp().as_secs() as i64,
+                        detections: Some(ChatDetections {
+                            tools: detections,
+                            ..Default::default()
+                        }),
+                        warnings: vec![OrchestratorWarning::new(
+                            DetectionWarningReason::UnsuitableTools,
+                            UNSUITABLE_TOOLS_MESSAGE,
+                        )],
+                        ..Default::default()
+                    };
+                    return Ok(chat_completion.into());
+                }
+                FlaggedToolsPolicy::Strip => {
+                    task.request.retain_tools(|index| {
+                        !detections
+                            .iter()
+                            .any(|result| result.tool_index as usize == index)
+                    });
+                    tool_detections = detections;
+                }
+            }
+        }
         // Handle input detection
         match handle_input_detection(ctx.clone(), &task, input_detectors).await {
             Ok(Some(completion)) => {
@@ -76,30 +170,213 @@ pub async fn handle_unary(
     }
 
     // Handle chat completion
+    let mut chat_completion = chat_completion(&ctx, &task).await?;
+
+    // Handle structured output validation
+    let mut invalid_choices = Vec::new();
+    if let Some(structured_output) = structured_output {
+        let mut retries = 0;
+        loop {
+            invalid_choices = structured_output.invalid_choices(&chat_completion);
+            if invalid_choices.is_empty()
+                || structured_output_policy != StructuredOutputPolicy::Retry
+                || retries == MAX_STRUCTURED_OUTPUT_RETRIES
+            {
+                break;
+            }
+            retries += 1;
+            warn!(%trace_id, ?invalid_choices, retries, "invalid structured output, retrying chat completion");
+            chat_completion = self::chat_completion(&ctx, &task).await?;
+        }
+    }
+
+    let mut chat_completion = if !output_detectors.is_empty() {
+        // Handle output detection
+        handle_output_detection(ctx.clone(), task, output_detectors, chat_completion).await?
+    } else {
+        // No output detectors, send chat completion response
+        chat_completion
+    };
+    if !invalid_choices.is_empty() {
+        chat_completion.warnings.push(OrchestratorWarning::new(
+            DetectionWarningReason::InvalidStructuredOutput,
+            &invalid_structured_output_message(&invalid_choices),
+        ));
+    }
+    if !tool_detections.is_empty() {
+        // Include detections of tools removed from the request
+        chat_completion.detections.get_or_insert_default().tools = tool_detections;
+        chat_completion.warnings.push(OrchestratorWarning::new(
+            DetectionWarningReason::UnsuitableTools,
+            REMOVED_TOOLS_MESSAGE,
+        ));
+    }
+    chat_completion.warnings.extend(disabled_warnings);
+    Ok(chat_completion.into())
+}
+
+/// Sends the chat completions request to the chat generation client.
+async fn chat_completion(
+    ctx: &Arc<Context>,
+    task: &ChatCompletionsDetectionTask,
+) -> Result<ChatCompletion, Error> {
     let client = ctx
         .clients
         .get_as::<OpenAiClient>("chat_generation")
         .unwrap();
-    let chat_completion =
-        match common::chat_completion(client, task.headers.clone(), task.request.clone()).await {
-            Ok(ChatCompletionsResponse::Unary(chat_completion)) => *chat_completion,
-            Ok(ChatCompletionsResponse::Streaming(_)) => unimplemented!(),
-            Err(error) => return Err(error),
-        };
+    match common::chat_completion(
+        client,
+        task.generation_headers.clone(),
+        task.request.clone(),
+    )
+    .await?
+    {
+        ChatCompletionsResponse::Unary(chat_completion) => Ok(*chat_completion),
+        ChatCompletionsResponse::Streaming(_) => unimplemented!(),
+    }
+}
 
-    if !output_detectors.is_empty() {
-        // Handle output detection
-        let chat_completion =
-            handle_output_detection(ctx.clone(), task, output_detectors, chat_completion).await?;
-        Ok(chat_completion.into())
-    } else {
-        // No output detectors, send chat completion response
-        Ok(chat_completion.into())
+/// Validator of chat completion output requested with a JSON response format.
+struct StructuredOutput {
+    validator: Option<jsonschema::Validator>,
+}
+
+impl StructuredOutput {
+    fn new(response_format: &ResponseFormat) -> Result<Self, Error> {
+        let validator = response_format
+            .schema()
+            .map(jsonschema::validator_for)
+            .transpose()
+            .map_err(|error| {
+                Error::Validation(format!("invalid `response_format` schema: {error}"))
+            })?;
+        Ok(Self { validator })
+    }
+
+    /// Returns the indices of choices with output not matching the response format.
+    fn invalid_choices(&self, chat_completion: &ChatCompletion) -> Vec<u32> {
+        chat_completion
+            .choices
+            .iter()
+            .filter(|choice| {
+                let text = choice.message.content.as_deref().unwrap_or_default();
+                !self.is_valid(text)
+            })
+            .map(|choice| choice.index)
+            .collect()
+    }
+
+    fn is_valid(&self, text: &str) -> bool {
+        match serde_json::from_str::<serde_json::Value>(text) {
+            Ok(value) => match &self.validator {
+                Some(validator) => validator.is_valid(&value),
+                None => value.is_object(),
+            },
+            Err(_) => false,
+        }
     }
 }
 
+/// Runs detection on each text segment of a message.
+/// Returns detections by segment, excluding segments without detections.
+async fn message_detections<'a>(
+    ctx: &Arc<Context>,
+    headers: &HeaderMap,
+    detectors: &HashMap<String, DetectorParams>,
+    message: &ChatMessage<'a>,
+) -> Result<Vec<(MessageSegment<'a>, Detections)>, Error> {
+    let segments = message.segments(ctx.config.reasoning_detection);
+    let detections = try_join_all(segments.iter().map(|segment| {
+        common::text_contents_detections(
+            ctx.clone(),
+            headers.clone(),
+            detectors.clone(),
+            message.index,
+            vec![(0, segment.text.to_string())],
+        )
+    }))
+    .await?;
+    Ok(segments
+        .into_iter()
+        .zip(detections)
+        .filter(|(_, (_, detections))| !detections.is_empty())
+        .map(|(segment, (_, detections))| (segment, detections))
+        .collect())
+}
+
+/// Handles tool definitions and input detection, reporting detections
+/// without enforcing actions or requesting a chat completion.
 #[instrument(skip_all)]
-async fn handle_input_detection(
+async fn handle_dry_run(
+    ctx: Arc<Context>,
+    task: &ChatCompletionsDetectionTask,
+    detectors: HashMap<String, DetectorParams>,
+) -> Result<ChatCompletion, Error> {
+    let mut chat_completion = ChatCompletion {
+        id: Uuid::new_v4().simple().to_string(),
+        model: task.request.model.clone(),
+        created: common::current_timestamp().as_secs() as i64,
+        ..Default::default()
+    };
+    if !detectors.is_empty() {
+        let tool_detections = handle_tools_detection(ctx.clone(), task, &detectors).await?;
+        if let Some(input_chat_completion) = handle_input_detection(ctx, task, detectors).await? {
+            chat_completion = input_chat_completion;
+        }
+        if !tool_detections.is_empty() {
+            chat_completion.detections.get_or_insert_default().tools = tool_detections;
+            chat_completion.warnings.push(OrchestratorWarning::new(
+                DetectionWarningReason::UnsuitableTools,
+                UNSUITABLE_TOOLS_MESSAGE,
+            ));
+        }
+    }
+    chat_completion.warnings.push(OrchestratorWarning::new(
+        DetectionWarningReason::DryRun,
+        DRY_RUN_MESSAGE,
+    ));
+    Ok(chat_completion)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn handle_tools_detection(
+    ctx: Arc<Context>,
+    task: &ChatCompletionsDetectionTask,
+    detectors: &HashMap<String, DetectorParams>,
+) -> Result<Vec<ToolDetectionResult>, Error> {
+    let tools = task.request.tools()?;
+    let mut tasks = Vec::with_capacity(tools.len());
+    for (index, tool) in tools.iter().enumerate() {
+        tasks.push(tokio::spawn(
+            common::text_contents_detections(
+                ctx.clone(),
+                task.headers.clone(),
+                detectors.clone(),
+                index as u32,
+                vec![(0, tool.text())],
+            )
+            .in_current_span(),
+        ));
+    }
+    let detections = try_join_all(tasks)
+        .await?
+        .into_iter()
+        .collect::<Result<Vec<_>, Error>>()?
+        .into_iter()
+        .filter(|(_, detections)| !detections.is_empty())
+        .map(|(index, mut detections)| {
+            detections.apply_filter(task.detections_filter);
+            ToolDetectionResult {
+                tool_index: index,
+                results: detections.into(),
+            }
+        })
+        .collect();
+    Ok(detections)
+}
+
+#[instrument(skip_all)]
+pub(super) async fn handle_input_detection(
     ctx: Arc<Context>,
     task: &ChatCompletionsDetectionTask,
     detectors: HashMap<String, DetectorParams>,
@@ -124,34 +401,40 @@ async fn handle_input_detection(
             "Last message role must be user, assistant, or system".into(),
         ));
     }
-    let input_id = message.index;
-    let input_text = message.text.map(|s| s.to_string()).unwrap_or_default();
-    let detections = match common::text_contents_detections(
-        ctx.clone(),
-        task.headers.clone(),
-        detectors.clone(),
-        input_id,
-        vec![(0, input_text)],
-    )
-    .await
-    {
-        Ok((_, detections)) => detections,
+    let detections = match message_detections(&ctx, &task.headers, &detectors, &message).await {
+        Ok(detections) => detections,
         Err(error) => {
             error!(%trace_id, %error, "task failed: error processing input detections");
             return Err(error);
         }
     };
     if !detections.is_empty() {
+        // Chat completion is skipped, blocking the request
+        if let Some(alerts) = ctx.alerts.as_ref().filter(|_| !task.dry_run) {
+            let detections = detections
+                .iter()
+                .flat_map(|(_, detections)| detections.iter().cloned())
+                .collect::<Detections>();
+            alerts.blocked(trace_id, "chat_completions_detection", &detections);
+        }
         // Build chat completion with input detections
         let chat_completion = ChatCompletion {
             id: Uuid::new_v4().simple().to_string(),
             model: model_id,
             created: common::current_timestamp().as_secs() as i64,
             detections: Some(ChatDetections {
-                input: vec![InputDetectionResult {
-                    message_index: message.index,
-                    results: detections.into(),
-                }],
+                input: detections
+                    .into_iter()
+                    .map(|(segment, mut detections)| {
+                        detections.apply_filter(task.detections_filter);
+                        InputDetectionResult {
+                            message_index: message.index,
+                            field: segment.field,
+                            part_index: segment.part_index,
+                            results: detections.into(),
+                        }
+                    })
+                    .collect(),
                 ..Default::default()
             }),
             warnings: vec![OrchestratorWarning::new(
@@ -174,45 +457,60 @@ async fn handle_output_detection(
     detectors: HashMap<String, DetectorParams>,
     mut chat_completion: ChatCompletion,
 ) -> Result<ChatCompletion, Error> {
-    let mut tasks = Vec::with_capacity(chat_completion.choices.len());
-    for choice in &chat_completion.choices {
-        let input_id = choice.index;
-        let input_text = choice.message.content.clone().unwrap_or_default();
-        tasks.push(tokio::spawn(
-            common::text_contents_detections(
-                ctx.clone(),
-                task.headers.clone(),
-                detectors.clone(),
-                input_id,
-                vec![(0, input_text)],
-            )
-            .in_current_span(),
-        ));
-    }
-    let detections = try_join_all(tasks)
-        .await?
-        .into_iter()
-        .collect::<Result<Vec<_>, Error>>()?;
-    if !detections.is_empty() {
-        // Update chat completion with detections
-        let output = detections
-            .into_iter()
-            .filter(|(_, detections)| !detections.is_empty())
-            .map(|(input_id, detections)| OutputDetectionResult {
-                choice_index: input_id,
-                results: detections.into(),
+    // Run detection on each choice independently
+    let messages = chat_completion.messages().collect::<Vec<_>>();
+    let detections = try_join_all(
+        messages
+            .iter()
+            .map(|message| message_detections(&ctx, &task.headers, &detectors, message)),
+    )
+    .await?;
+    let output = messages
+        .iter()
+        .zip(detections)
+        .flat_map(|(message, detections)| {
+            detections.into_iter().map(|(segment, mut detections)| {
+                detections.apply_filter(task.detections_filter);
+                OutputDetectionResult {
+                    choice_index: message.index,
+                    field: segment.field,
+                    results: detections.into(),
+                }
             })
-            .collect::<Vec<_>>();
-        if !output.is_empty() {
-            chat_completion.detections = Some(ChatDetections {
-                output,
-                ..Default::default()
-            });
-            chat_completion.warnings = vec![OrchestratorWarning::new(
-                DetectionWarningReason::UnsuitableOutput,
-                UNSUITABLE_OUTPUT_MESSAGE,
-            )];
+        })
+        .collect::<Vec<_>>();
+    if !output.is_empty() {
+        // Drop flagged choices, e.g. to return only suitable choices when `n > 1`
+        let flagged_choices = task
+            .request
+            .guardrails
+            .actions
+            .flagged_choices
+            .unwrap_or(ctx.config.flagged_choices);
+        chat_completion.warnings = vec![OrchestratorWarning::new(
+            DetectionWarningReason::UnsuitableOutput,
+            UNSUITABLE_OUTPUT_MESSAGE,
+        )];
+        if flagged_choices == FlaggedChoicesPolicy::Drop {
+            let is_flagged = |choice: &ChatCompletionChoice| {
+                output
+                    .iter()
+                    .any(|result| result.choice_index == choice.index && !result.results.is_empty())
+            };
+            if chat_completion.choices.iter().all(is_flagged) {
+                // Dropping every choice would leave an empty response
+                chat_completion.warnings.push(OrchestratorWarning::new(
+                    DetectionWarningReason::UnsuitableOutput,
+                    ALL_CHOICES_FLAGGED_MESSAGE,
+                ));
+            } else {
+                chat_completion.choices.retain(|choice| !is_flagged(choice));
+            }
         }
+        chat_completion.detections = Some(ChatDetections {
+            output,
+            ..Default::default()
+        });
     }
     Ok(chat_completion)
 }
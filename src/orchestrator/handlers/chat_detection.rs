@@ -23,11 +23,15 @@ use tracing::{info, instrument};
 use super::Handle;
 use crate::{
     clients::openai,
-    config::DetectorType,
+    config::{DetectionsFilter, DetectorType},
     models::{ChatDetectionHttpRequest, ChatDetectionResult, DetectorParams},
     orchestrator::{
         Error, Orchestrator,
-        common::{self, validate_detectors},
+        common::{
+            self,
+            features::{self, DetectionPhase},
+            validate_detectors,
+        },
     },
 };
 
@@ -39,7 +43,7 @@ impl Handle<ChatDetectionTask> for Orchestrator {
         skip_all,
         fields(trace_id = ?task.trace_id, headers = ?task.headers)
     )]
-    async fn handle(&self, task: ChatDetectionTask) -> Result<Self::Response, Error> {
+    async fn handle(&self, mut task: ChatDetectionTask) -> Result<Self::Response, Error> {
         let ctx = self.ctx.clone();
         let trace_id = task.trace_id;
         info!(%trace_id, config = ?task.detectors, "task started");
@@ -51,8 +55,22 @@ impl Handle<ChatDetectionTask> for Orchestrator {
             true,
         )?;
 
+        // Skip detection if input detection is disabled
+        let warnings = features::apply_phase_flag(
+            &ctx.features.get(),
+            DetectionPhase::Input,
+            &mut task.detectors,
+        );
+        if !warnings.is_empty() {
+            info!(%trace_id, "task completed: input detection is disabled");
+            return Ok(ChatDetectionResult {
+                detections: Vec::new(),
+                warnings,
+            });
+        }
+
         // Handle detection
-        let detections = common::text_chat_detections(
+        let mut detections = common::text_chat_detections(
             ctx,
             task.headers,
             task.detectors,
@@ -60,9 +78,11 @@ impl Handle<ChatDetectionTask> for Orchestrator {
             task.tools,
         )
         .await?;
+        detections.apply_filter(task.detections_filter);
 
         Ok(ChatDetectionResult {
             detections: detections.into(),
+            warnings,
         })
     }
 }
@@ -79,16 +99,24 @@ pub struct ChatDetectionTask {
     pub tools: Vec<openai::Tool>,
     /// Headers
     pub headers: HeaderMap,
+    /// Filter for detections returned in responses
+    pub detections_filter: DetectionsFilter,
 }
 
 impl ChatDetectionTask {
-    pub fn new(trace_id: TraceId, request: ChatDetectionHttpRequest, headers: HeaderMap) -> Self {
+    pub fn new(
+        trace_id: TraceId,
+        request: ChatDetectionHttpRequest,
+        headers: HeaderMap,
+        detections_filter: DetectionsFilter,
+    ) -> Self {
         Self {
             trace_id,
             detectors: request.detectors,
             messages: request.messages,
             tools: request.tools,
             headers,
+            detections_filter,
         }
     }
 }
@@ -0,0 +1,76 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+use opentelemetry::trace::TraceId;
+use tracing::{info, instrument};
+
+use super::Handle;
+use crate::{
+    models::{ChunkSpan, ChunksHttpRequest, ChunksResult},
+    orchestrator::{Error, Orchestrator, common},
+};
+
+impl Handle<ChunksTask> for Orchestrator {
+    type Response = ChunksResult;
+
+    #[instrument(
+        name = "chunks",
+        skip_all,
+        fields(trace_id = ?task.trace_id, chunker_id = %task.chunker_id)
+    )]
+    async fn handle(&self, task: ChunksTask) -> Result<Self::Response, Error> {
+        let ctx = self.ctx.clone();
+        let trace_id = task.trace_id;
+        let chunker_id = task.chunker_id;
+        info!(%trace_id, %chunker_id, "task started");
+
+        let mut chunk_map =
+            common::chunks(ctx, vec![chunker_id.clone()], vec![(0, task.content)]).await?;
+        let mut chunks = chunk_map.remove(&chunker_id).unwrap_or_default();
+        chunks.sort();
+        let chunks = chunks
+            .into_iter()
+            .map(|chunk| ChunkSpan {
+                start: chunk.start,
+                end: chunk.end,
+                text: chunk.text.to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        info!(%trace_id, chunks = chunks.len(), "task completed");
+        Ok(ChunksResult { chunks })
+    }
+}
+
+#[derive(Debug)]
+pub struct ChunksTask {
+    /// Trace ID
+    pub trace_id: TraceId,
+    /// Chunker ID
+    pub chunker_id: String,
+    /// Content text
+    pub content: String,
+}
+
+impl ChunksTask {
+    pub fn new(trace_id: TraceId, request: ChunksHttpRequest) -> Self {
+        Self {
+            trace_id,
+            chunker_id: request.chunker_id,
+            content: request.content,
+        }
+    }
+}
@@ -24,7 +24,7 @@ use tracing::{error, info, instrument};
 use super::Handle;
 use crate::{
     clients::GenerationClient,
-    config::DetectorType,
+    config::{DetectionsFilter, DetectorType},
     models::{
         ClassifiedGeneratedTextResult, DetectionWarning, DetectorParams, GuardrailsConfig,
         GuardrailsHttpRequest, GuardrailsTextGenerationParameters,
@@ -32,8 +32,9 @@ use crate::{
     },
     orchestrator::{
         Context, Error, Orchestrator,
-        common::{self, validate_detectors},
+        common::{self, cache, features, unfiltered, validate_detectors},
     },
+    utils::debug_info,
 };
 
 impl Handle<ClassificationWithGenTask> for Orchestrator {
@@ -48,8 +49,8 @@ impl Handle<ClassificationWithGenTask> for Orchestrator {
         let ctx = self.ctx.clone();
         let trace_id = task.trace_id;
         info!(%trace_id, config = ?task.guardrails_config, "task started");
-        let input_detectors = task.guardrails_config.input_detectors();
-        let output_detectors = task.guardrails_config.output_detectors();
+        let mut input_detectors = task.guardrails_config.input_detectors();
+        let mut output_detectors = task.guardrails_config.output_detectors();
 
         // input detectors validation
         validate_detectors(
@@ -66,47 +67,151 @@ impl Handle<ClassificationWithGenTask> for Orchestrator {
             true,
         )?;
 
-        if !input_detectors.is_empty() {
-            // Handle input detection
-            match handle_input_detection(ctx.clone(), &task, input_detectors).await {
-                Ok(Some(response)) => {
-                    info!(%trace_id, "task completed: returning response with input detections");
-                    // Return response with input detections and terminate
-                    return Ok(response);
-                }
-                Ok(None) => (), // No input detections
-                Err(error) => {
-                    // Input detections failed
-                    return Err(error);
-                }
+        // Skip detection phases disabled by feature flags
+        let warnings = features::apply_detection_flags(
+            &ctx.features.get(),
+            false,
+            &mut input_detectors,
+            &mut output_detectors,
+        );
+
+        if task.dry_run {
+            // Handle input detection only, without high-severity alerts
+            if let Some(alerts) = &ctx.alerts {
+                alerts.suppress(trace_id);
             }
+            debug_info::record_decision(|| "dry run: generation skipped".into());
+            let mut response = handle_dry_run(ctx, &task, input_detectors).await?;
+            response.warnings.get_or_insert_default().extend(warnings);
+            info!(%trace_id, "task completed: returning dry run response");
+            return Ok(response);
         }
 
-        // Handle generation
-        let client = ctx
-            .clients
-            .get_as::<GenerationClient>("generation")
-            .unwrap();
-        let generation = common::generate(
-            client,
-            task.headers.clone(),
-            task.model_id.clone(),
-            task.inputs.clone(),
-            task.text_gen_parameters.clone(),
-        )
-        .await?;
-
-        if !output_detectors.is_empty() {
-            // Handle output detection
-            handle_output_detection(ctx.clone(), task, output_detectors, generation).await
-        } else {
-            // No output detectors, return generation
-            info!(%trace_id, "task completed: returning generation response");
-            Ok(generation)
+        // Serve deterministic requests from the response cache, unless detection
+        // phases were skipped or unfiltered detections are collected for capture
+        let cache = ctx
+            .response_cache
+            .as_ref()
+            .filter(|_| warnings.is_empty() && !unfiltered::is_collected());
+        let cache_key = cache.and_then(|_| task.cache_key());
+        if let (Some(cache), Some(cache_key)) = (cache, &cache_key) {
+            if let Some(mut response) = cache.get(cache_key) {
+                debug_info::record_decision(|| "response cache hit: generation skipped".into());
+                add_provenance(&ctx, trace_id, &mut response);
+                info!(%trace_id, "task completed: returning cached response");
+                return Ok(response);
+            }
+        }
+
+        let mut response =
+            handle_generation(ctx.clone(), task, input_detectors, output_detectors).await?;
+        if let (Some(cache), Some(cache_key)) = (cache, cache_key) {
+            cache.insert(cache_key, response.clone());
+        }
+        add_provenance(&ctx, trace_id, &mut response);
+        if !warnings.is_empty() {
+            response.warnings.get_or_insert_default().extend(warnings);
         }
+        Ok(response)
     }
 }
 
+/// Adds provenance of the generated text to a response, if configured. Cached responses
+/// are stored without provenance, as it is bound to the trace ID of each request.
+fn add_provenance(ctx: &Context, trace_id: TraceId, response: &mut ClassifiedGeneratedTextResult) {
+    if let (Some(provenance), Some(generated_text)) = (&ctx.provenance, &response.generated_text) {
+        let flagged = response
+            .token_classification_results
+            .output
+            .as_ref()
+            .is_some_and(|output| !output.is_empty());
+        response.provenance = Some(provenance.provenance(trace_id, generated_text, flagged));
+    }
+}
+
+/// Handles input detection, generation and output detection.
+#[instrument(skip_all)]
+async fn handle_generation(
+    ctx: Arc<Context>,
+    task: ClassificationWithGenTask,
+    input_detectors: HashMap<String, DetectorParams>,
+    output_detectors: HashMap<String, DetectorParams>,
+) -> Result<ClassifiedGeneratedTextResult, Error> {
+    let trace_id = task.trace_id;
+    if !input_detectors.is_empty() {
+        // Handle input detection
+        match handle_input_detection(ctx.clone(), &task, input_detectors).await {
+            Ok(Some(response)) => {
+                info!(%trace_id, "task completed: returning response with input detections");
+                debug_info::record_decision(|| "input detections found: generation skipped".into());
+                // Return response with input detections and terminate
+                return Ok(response);
+            }
+            Ok(None) => (), // No input detections
+            Err(error) => {
+                // Input detections failed
+                return Err(error);
+            }
+        }
+    }
+
+    // Handle generation
+    let client = ctx
+        .clients
+        .get_as::<GenerationClient>("generation")
+        .unwrap();
+    let (inputs, params, truncation_warning) = common::truncate_input(
+        client,
+        task.generation_headers.clone(),
+        task.model_id.clone(),
+        task.inputs.clone(),
+        task.text_gen_parameters.clone(),
+    )
+    .await?;
+    let generation = common::generate(
+        client,
+        task.generation_headers.clone(),
+        task.model_id.clone(),
+        inputs,
+        params,
+    )
+    .await?;
+
+    let mut response = if !output_detectors.is_empty() {
+        // Handle output detection
+        handle_output_detection(ctx.clone(), task, output_detectors, generation).await?
+    } else {
+        // No output detectors, return generation
+        info!(%trace_id, "task completed: returning generation response");
+        generation
+    };
+    if let Some(warning) = truncation_warning {
+        response.warnings.get_or_insert_default().push(warning);
+    }
+    Ok(response)
+}
+
+/// Handles input detection, reporting detections without generation.
+#[instrument(skip_all)]
+async fn handle_dry_run(
+    ctx: Arc<Context>,
+    task: &ClassificationWithGenTask,
+    detectors: HashMap<String, DetectorParams>,
+) -> Result<ClassifiedGeneratedTextResult, Error> {
+    let mut response = if !detectors.is_empty() {
+        handle_input_detection(ctx, task, detectors)
+            .await?
+            .unwrap_or_default()
+    } else {
+        ClassifiedGeneratedTextResult::default()
+    };
+    response
+        .warnings
+        .get_or_insert_default()
+        .push(DetectionWarning::dry_run());
+    Ok(response)
+}
+
 #[instrument(skip_all)]
 async fn handle_input_detection(
     ctx: Arc<Context>,
@@ -115,7 +220,7 @@ async fn handle_input_detection(
 ) -> Result<Option<ClassifiedGeneratedTextResult>, Error> {
     let trace_id = task.trace_id;
     let inputs = common::apply_masks(task.inputs.clone(), task.guardrails_config.input_masks());
-    let detections = match common::text_contents_detections(
+    let mut detections = match common::text_contents_detections(
         ctx.clone(),
         task.headers.clone(),
         detectors.clone(),
@@ -138,7 +243,7 @@ async fn handle_input_detection(
             .unwrap();
         let input_token_count = match common::tokenize(
             client,
-            task.headers.clone(),
+            task.generation_headers.clone(),
             task.model_id.clone(),
             task.inputs.clone(),
         )
@@ -150,6 +255,14 @@ async fn handle_input_detection(
                 return Err(error);
             }
         };
+        // Generation is skipped, blocking the request
+        if let Some(alerts) = ctx.alerts.as_ref().filter(|_| !task.dry_run) {
+            alerts.blocked(trace_id, "classification_with_gen", &detections);
+        }
+        let mut warnings = vec![DetectionWarning::unsuitable_input()];
+        warnings.extend(detections.warnings());
+        unfiltered::record_input(&detections);
+        detections.apply_filter(task.detections_filter);
         // Build response with input detections
         let response = ClassifiedGeneratedTextResult {
             input_token_count,
@@ -157,7 +270,7 @@ async fn handle_input_detection(
                 input: Some(detections.into()),
                 output: None,
             },
-            warnings: Some(vec![DetectionWarning::unsuitable_input()]),
+            warnings: Some(warnings),
             ..Default::default()
         };
         Ok(Some(response))
@@ -176,7 +289,7 @@ async fn handle_output_detection(
 ) -> Result<ClassifiedGeneratedTextResult, Error> {
     let trace_id = task.trace_id;
     let generated_text = generation.generated_text.clone().unwrap_or_default();
-    let detections = match common::text_contents_detections(
+    let mut detections = match common::text_contents_detections(
         ctx,
         task.headers,
         detectors,
@@ -192,10 +305,19 @@ async fn handle_output_detection(
         }
     };
     let mut response = generation;
+    let detection_warnings = detections.warnings();
     if !detections.is_empty() {
+        unfiltered::record_output(&detections);
+        detections.apply_filter(task.detections_filter);
         response.token_classification_results.output = Some(detections.into());
         response.warnings = Some(vec![DetectionWarning::unsuitable_output()]);
     }
+    if !detection_warnings.is_empty() {
+        response
+            .warnings
+            .get_or_insert_default()
+            .extend(detection_warnings);
+    }
     info!(%trace_id, "task completed: returning response with output detections");
     Ok(response)
 }
@@ -214,17 +336,128 @@ pub struct ClassificationWithGenTask {
     pub text_gen_parameters: Option<GuardrailsTextGenerationParameters>,
     /// Headers
     pub headers: HeaderMap,
+    /// Headers sent to the generation service, including generation-only headers
+    pub generation_headers: HeaderMap,
+    /// Filter for detections returned in responses
+    pub detections_filter: DetectionsFilter,
+    /// Whether to run input detection only, without generation or enforcing actions
+    pub dry_run: bool,
+    /// Name of the authenticated consumer of the request, if any
+    pub consumer: Option<String>,
 }
 
 impl ClassificationWithGenTask {
-    pub fn new(trace_id: TraceId, request: GuardrailsHttpRequest, headers: HeaderMap) -> Self {
+    pub fn new(
+        trace_id: TraceId,
+        request: GuardrailsHttpRequest,
+        headers: HeaderMap,
+        detections_filter: DetectionsFilter,
+        dry_run: bool,
+    ) -> Self {
         Self {
             trace_id,
             model_id: request.model_id,
             inputs: request.inputs,
             guardrails_config: request.guardrail_config.unwrap_or_default(),
             text_gen_parameters: request.text_gen_parameters,
+            generation_headers: headers.clone(),
             headers,
+            detections_filter,
+            dry_run,
+            consumer: None,
         }
     }
+
+    /// Returns the response cache key of the task, or `None` if its generation
+    /// is not deterministic.
+    ///
+    /// Keys are scoped to the consumer of the request and the headers forwarded to the
+    /// generation service, which include those forwarded to detectors, e.g. passthrough
+    /// credentials or tenants, so responses are never served to other callers.
+    fn cache_key(&self) -> Option<String> {
+        let params = self
+            .text_gen_parameters
+            .as_ref()
+            .filter(|params| params.is_deterministic())?;
+        Some(cache::cache_key(&(
+            &self.consumer,
+            cache::header_pairs(&self.generation_headers),
+            &self.model_id,
+            &self.inputs,
+            params,
+            self.guardrails_config.input_masks(),
+            self.guardrails_config.input_detectors(),
+            self.guardrails_config.output_detectors(),
+            self.detections_filter.min_score,
+            self.detections_filter.top_k,
+        )))
+    }
+
+    /// Sets the name of the authenticated consumer of the request.
+    pub fn with_consumer(mut self, consumer: Option<String>) -> Self {
+        self.consumer = consumer;
+        self
+    }
+
+    /// Adds headers sent to the generation service only, never to detectors.
+    pub fn with_generation_headers(mut self, headers: HeaderMap) -> Self {
+        self.generation_headers.extend(headers);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(
+        request: serde_json::Value,
+        headers: &[(&'static str, &'static str)],
+    ) -> ClassificationWithGenTask {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (http::HeaderName::from_static(name), value.parse().unwrap()))
+            .collect::<HeaderMap>();
+        let request = serde_json::from_value(request).unwrap();
+        ClassificationWithGenTask::new(
+            TraceId::INVALID,
+            request,
+            headers,
+            DetectionsFilter::default(),
+            false,
+        )
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let request = |labels: serde_json::Value| {
+            serde_json::json!({
+                "model_id": "model",
+                "inputs": "Hi there!",
+                "guardrail_config": {
+                    "input": {"models": {"detector": {"labels": labels}}},
+                },
+                "text_gen_parameters": {"seed": 42, "decoding_method": "GREEDY"},
+            })
+        };
+        let a = request(serde_json::json!({"a": 1, "b": 2}));
+        let b = request(serde_json::json!({"b": 2, "a": 1}));
+        let key = task(a.clone(), &[]).cache_key().unwrap();
+
+        // Detector params are keyed regardless of their order
+        assert_eq!(task(b, &[]).cache_key().unwrap(), key);
+
+        // Keys are scoped to consumers and forwarded headers
+        let consumer = task(a.clone(), &[]).with_consumer(Some("team-a".into()));
+        assert_ne!(consumer.cache_key().unwrap(), key);
+        let forwarded = task(a.clone(), &[("authorization", "Bearer token-a")]);
+        assert_ne!(forwarded.cache_key().unwrap(), key);
+        let generation_headers = [(
+            http::HeaderName::from_static("x-tenant-id"),
+            "acme".parse().unwrap(),
+        )];
+        let generation =
+            task(a, &[]).with_generation_headers(generation_headers.into_iter().collect());
+        assert_ne!(generation.cache_key().unwrap(), key);
+    }
 }
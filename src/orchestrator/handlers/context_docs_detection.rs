@@ -23,11 +23,16 @@ use tracing::{info, instrument};
 use super::Handle;
 use crate::{
     clients::detector::ContextType,
-    config::DetectorType,
-    models::{ContextDocsHttpRequest, ContextDocsResult, DetectorParams},
+    config::{DetectionsFilter, DetectorType},
+    models::{ContextDocsHttpRequest, ContextDocsResult, DetectorParams, validate_detector_params},
     orchestrator::{
         Error, Orchestrator,
-        common::{self, validate_detectors},
+        common::{
+            self,
+            features::{self, DetectionPhase},
+            pending::DispatchedDetections,
+            validate_detectors,
+        },
     },
 };
 
@@ -39,7 +44,7 @@ impl Handle<ContextDocsDetectionTask> for Orchestrator {
         skip_all,
         fields(trace_id = ?task.trace_id, headers = ?task.headers)
     )]
-    async fn handle(&self, task: ContextDocsDetectionTask) -> Result<Self::Response, Error> {
+    async fn handle(&self, mut task: ContextDocsDetectionTask) -> Result<Self::Response, Error> {
         let ctx = self.ctx.clone();
         let trace_id = task.trace_id;
         info!(%trace_id, config = ?task.detectors, "task started");
@@ -51,8 +56,41 @@ impl Handle<ContextDocsDetectionTask> for Orchestrator {
             true,
         )?;
 
+        // Skip detection if output detection is disabled
+        let warnings = features::apply_phase_flag(
+            &ctx.features.get(),
+            DetectionPhase::Output,
+            &mut task.detectors,
+        );
+        if !warnings.is_empty() {
+            info!(%trace_id, "task completed: output detection is disabled");
+            return Ok(ContextDocsResult {
+                detections: Vec::new(),
+                warnings,
+            });
+        }
+
+        // Collect detections of detectors dispatched while the request body was received,
+        // unless the request changed them
+        if let Some(dispatched) = task
+            .dispatched
+            .take()
+            .filter(|dispatched| dispatched.matches(&task.detectors))
+        {
+            let mut detections = dispatched
+                .into_pending(task.detections_filter)
+                .collect(None)
+                .await?;
+            detections.sort_by_key(|detection| detection.start);
+            detections.apply_filter(task.detections_filter);
+            return Ok(ContextDocsResult {
+                detections: detections.into(),
+                warnings,
+            });
+        }
+
         // Handle detection
-        let detections = common::text_context_detections(
+        let mut detections = common::text_context_detections(
             ctx,
             task.headers,
             task.detectors,
@@ -61,13 +99,63 @@ impl Handle<ContextDocsDetectionTask> for Orchestrator {
             task.context,
         )
         .await?;
+        detections.apply_filter(task.detections_filter);
 
         Ok(ContextDocsResult {
             detections: detections.into(),
+            warnings,
         })
     }
 }
 
+impl Orchestrator {
+    /// Dispatches detection of `content` by `detector_id` of a context docs detection
+    /// request whose body is still received, if the detector is valid for the request.
+    /// Detectors dispatched for a request are collected by its [`ContextDocsDetectionTask`].
+    pub fn dispatch_context_docs_detector(
+        &self,
+        dispatched: &mut DispatchedDetections,
+        headers: &HeaderMap,
+        (content, context_type, context): &(String, ContextType, Vec<String>),
+        detector_id: String,
+        params: DetectorParams,
+    ) {
+        let mut detectors = HashMap::from([(detector_id.clone(), params.clone())]);
+        let valid = !content.is_empty()
+            && !context.is_empty()
+            && validate_detector_params(&detectors).is_ok()
+            && validate_detectors(
+                &detectors,
+                &self.ctx.config.detectors,
+                &[DetectorType::TextContextDoc],
+                true,
+            )
+            .is_ok();
+        // Detectors of disabled phases are not dispatched
+        if !valid
+            || !features::apply_phase_flag(
+                &self.ctx.features.get(),
+                DetectionPhase::Output,
+                &mut detectors,
+            )
+            .is_empty()
+        {
+            return;
+        }
+        // Alerts are held until the request is validated
+        dispatched.hold_alerts(self.ctx.alerts.as_ref());
+        let detection = common::text_context_detections(
+            self.ctx.clone(),
+            headers.clone(),
+            detectors,
+            content.clone(),
+            context_type.clone(),
+            context.clone(),
+        );
+        dispatched.dispatch(detector_id, params, detection);
+    }
+}
+
 #[derive(Debug)]
 pub struct ContextDocsDetectionTask {
     /// Trace ID
@@ -82,10 +170,19 @@ pub struct ContextDocsDetectionTask {
     pub detectors: HashMap<String, DetectorParams>,
     /// Headers
     pub headers: HeaderMap,
+    /// Filter for detections returned in responses
+    pub detections_filter: DetectionsFilter,
+    /// Detectors dispatched while the request body was received
+    pub dispatched: Option<DispatchedDetections>,
 }
 
 impl ContextDocsDetectionTask {
-    pub fn new(trace_id: TraceId, request: ContextDocsHttpRequest, headers: HeaderMap) -> Self {
+    pub fn new(
+        trace_id: TraceId,
+        request: ContextDocsHttpRequest,
+        headers: HeaderMap,
+        detections_filter: DetectionsFilter,
+    ) -> Self {
         Self {
             trace_id,
             content: request.content,
@@ -93,6 +190,14 @@ impl ContextDocsDetectionTask {
             context: request.context,
             detectors: request.detectors,
             headers,
+            detections_filter,
+            dispatched: None,
         }
     }
+
+    /// Sets detectors dispatched while the request body was received.
+    pub fn with_dispatched(mut self, dispatched: DispatchedDetections) -> Self {
+        self.dispatched = Some(dispatched);
+        self
+    }
 }
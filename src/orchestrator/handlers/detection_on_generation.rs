@@ -22,11 +22,15 @@ use tracing::{info, instrument};
 
 use super::Handle;
 use crate::{
-    config::DetectorType,
+    config::{DetectionsFilter, DetectorType},
     models::{DetectionOnGeneratedHttpRequest, DetectionOnGenerationResult, DetectorParams},
     orchestrator::{
         Error, Orchestrator,
-        common::{self, validate_detectors},
+        common::{
+            self,
+            features::{self, DetectionPhase},
+            validate_detectors,
+        },
     },
 };
 
@@ -38,7 +42,7 @@ impl Handle<DetectionOnGenerationTask> for Orchestrator {
         skip_all,
         fields(trace_id = ?task.trace_id, headers = ?task.headers)
     )]
-    async fn handle(&self, task: DetectionOnGenerationTask) -> Result<Self::Response, Error> {
+    async fn handle(&self, mut task: DetectionOnGenerationTask) -> Result<Self::Response, Error> {
         let ctx = self.ctx.clone();
         let trace_id = task.trace_id;
         info!(%trace_id, config = ?task.detectors, "task started");
@@ -50,8 +54,22 @@ impl Handle<DetectionOnGenerationTask> for Orchestrator {
             true,
         )?;
 
+        // Skip detection if output detection is disabled
+        let warnings = features::apply_phase_flag(
+            &ctx.features.get(),
+            DetectionPhase::Output,
+            &mut task.detectors,
+        );
+        if !warnings.is_empty() {
+            info!(%trace_id, "task completed: output detection is disabled");
+            return Ok(DetectionOnGenerationResult {
+                detections: Vec::new(),
+                warnings,
+            });
+        }
+
         // Handle detection
-        let detections = common::text_generation_detections(
+        let mut detections = common::text_generation_detections(
             ctx,
             task.headers,
             task.detectors,
@@ -59,9 +77,11 @@ impl Handle<DetectionOnGenerationTask> for Orchestrator {
             task.generated_text,
         )
         .await?;
+        detections.apply_filter(task.detections_filter);
 
         Ok(DetectionOnGenerationResult {
             detections: detections.into(),
+            warnings,
         })
     }
 }
@@ -78,6 +98,8 @@ pub struct DetectionOnGenerationTask {
     pub detectors: HashMap<String, DetectorParams>,
     /// Headers
     pub headers: HeaderMap,
+    /// Filter for detections returned in responses
+    pub detections_filter: DetectionsFilter,
 }
 
 impl DetectionOnGenerationTask {
@@ -85,6 +107,7 @@ impl DetectionOnGenerationTask {
         trace_id: TraceId,
         request: DetectionOnGeneratedHttpRequest,
         headers: HeaderMap,
+        detections_filter: DetectionsFilter,
     ) -> Self {
         Self {
             trace_id,
@@ -92,6 +115,7 @@ impl DetectionOnGenerationTask {
             generated_text: request.generated_text,
             detectors: request.detectors,
             headers,
+            detections_filter,
         }
     }
 }
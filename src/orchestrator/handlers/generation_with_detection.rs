@@ -23,15 +23,20 @@ use tracing::{info, instrument};
 use super::Handle;
 use crate::{
     clients::GenerationClient,
-    config::DetectorType,
+    config::{DetectionsFilter, DetectorType},
     models::{
         DetectorParams, GenerationWithDetectionHttpRequest, GenerationWithDetectionResult,
         GuardrailsTextGenerationParameters,
     },
     orchestrator::{
         Error, Orchestrator,
-        common::{self, validate_detectors},
+        common::{
+            self,
+            features::{self, DetectionPhase},
+            validate_detectors,
+        },
     },
+    utils::debug_info,
 };
 
 impl Handle<GenerationWithDetectionTask> for Orchestrator {
@@ -42,7 +47,7 @@ impl Handle<GenerationWithDetectionTask> for Orchestrator {
         skip_all,
         fields(trace_id = ?task.trace_id, headers = ?task.headers)
     )]
-    async fn handle(&self, task: GenerationWithDetectionTask) -> Result<Self::Response, Error> {
+    async fn handle(&self, mut task: GenerationWithDetectionTask) -> Result<Self::Response, Error> {
         let ctx = self.ctx.clone();
         let trace_id = task.trace_id;
         info!(%trace_id, config = ?task.detectors, "task started");
@@ -54,35 +59,77 @@ impl Handle<GenerationWithDetectionTask> for Orchestrator {
             true,
         )?;
 
+        // Skip detection if output detection is disabled
+        let disabled_warnings = features::apply_phase_flag(
+            &ctx.features.get(),
+            DetectionPhase::Output,
+            &mut task.detectors,
+        );
+
         // Handle generation
         let client = ctx
             .clients
             .get_as::<GenerationClient>("generation")
             .unwrap();
-        let generation = common::generate(
+        let (prompt, params, truncation_warning) = common::truncate_input(
             client,
-            task.headers.clone(),
+            task.generation_headers.clone(),
             task.model_id.clone(),
             task.prompt.clone(),
             task.text_gen_parameters.clone(),
         )
         .await?;
+        let generation = common::generate(
+            client,
+            task.generation_headers.clone(),
+            task.model_id.clone(),
+            prompt,
+            params,
+        )
+        .await?;
+        let warnings = truncation_warning
+            .into_iter()
+            .chain(disabled_warnings)
+            .collect::<Vec<_>>();
         let generated_text = generation.generated_text.unwrap_or_default();
 
+        if task.detectors.is_empty() {
+            // No detectors, return generated text as-is
+            info!(%trace_id, "task completed: returning generated text without detection");
+            debug_info::record_decision(|| "no detectors: detection skipped".into());
+            let provenance = ctx
+                .provenance
+                .as_ref()
+                .map(|provenance| provenance.provenance(trace_id, &generated_text, false));
+            return Ok(GenerationWithDetectionResult {
+                generated_text,
+                input_token_count: generation.input_token_count,
+                detections: Vec::new(),
+                warnings,
+                provenance,
+            });
+        }
+
         // Handle detection
-        let detections = common::text_generation_detections(
-            ctx,
+        let mut detections = common::text_generation_detections(
+            ctx.clone(),
             task.headers,
             task.detectors,
             task.prompt,
             generated_text.clone(),
         )
         .await?;
+        detections.apply_filter(task.detections_filter);
+        let provenance = ctx.provenance.as_ref().map(|provenance| {
+            provenance.provenance(trace_id, &generated_text, !detections.is_empty())
+        });
 
         Ok(GenerationWithDetectionResult {
             generated_text,
             input_token_count: generation.input_token_count,
             detections: detections.into(),
+            warnings,
+            provenance,
         })
     }
 }
@@ -101,6 +148,10 @@ pub struct GenerationWithDetectionTask {
     pub text_gen_parameters: Option<GuardrailsTextGenerationParameters>,
     /// Headers
     pub headers: HeaderMap,
+    /// Headers sent to the generation service, including generation-only headers
+    pub generation_headers: HeaderMap,
+    /// Filter for detections returned in responses
+    pub detections_filter: DetectionsFilter,
 }
 
 impl GenerationWithDetectionTask {
@@ -108,6 +159,7 @@ impl GenerationWithDetectionTask {
         trace_id: TraceId,
         request: GenerationWithDetectionHttpRequest,
         headers: HeaderMap,
+        detections_filter: DetectionsFilter,
     ) -> Self {
         Self {
             trace_id,
@@ -115,7 +167,209 @@ impl GenerationWithDetectionTask {
             prompt: request.prompt,
             detectors: request.detectors,
             text_gen_parameters: request.text_gen_parameters,
+            generation_headers: headers.clone(),
             headers,
+            detections_filter,
         }
     }
+
+    /// Adds headers sent to the generation service only, never to detectors.
+    pub fn with_generation_headers(mut self, headers: HeaderMap) -> Self {
+        self.generation_headers.extend(headers);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{Json, Router, routing::post};
+    use http::{HeaderName, HeaderValue};
+    use mocktail::prelude::*;
+
+    use super::*;
+    use crate::{
+        config::{DetectorConfig, OrchestratorConfig},
+        models::{DetectionResult, DetectionWarning, TruncationStrategy},
+        orchestrator::common::configure_mock_servers,
+        pb::fmaas::{
+            BatchedGenerationRequest, BatchedGenerationResponse, BatchedTokenizeRequest,
+            BatchedTokenizeResponse, GenerationRequest, GenerationResponse, TokenizeRequest,
+            TokenizeResponse, tokenize_response::Offset,
+        },
+        utils::test_server::{ensure_crypto_provider, serve},
+    };
+
+    const MODEL_ID: &str = "tgis-model";
+
+    #[tokio::test]
+    async fn test_truncate_multibyte_input() -> Result<(), Error> {
+        ensure_crypto_provider();
+        let prompt = "café naïve résumé";
+        let truncated_prompt = " naïve résumé";
+
+        // TGIS returns byte offsets of tokens
+        let mut mocks = MockSet::new();
+        mocks.mock(|when, then| {
+            when.path("/fmaas.GenerationService/Tokenize")
+                .pb(BatchedTokenizeRequest {
+                    model_id: MODEL_ID.into(),
+                    requests: vec![TokenizeRequest {
+                        text: prompt.into(),
+                    }],
+                    return_tokens: true,
+                    return_offsets: true,
+                    truncate_input_tokens: 0,
+                });
+            then.pb(BatchedTokenizeResponse {
+                responses: vec![TokenizeResponse {
+                    token_count: 3,
+                    tokens: vec!["café".into(), " naïve".into(), " résumé".into()],
+                    offsets: vec![
+                        Offset { start: 0, end: 5 },
+                        Offset { start: 5, end: 12 },
+                        Offset { start: 12, end: 21 },
+                    ],
+                }],
+            });
+        });
+        mocks.mock(|when, then| {
+            when.path("/fmaas.GenerationService/Generate")
+                .pb(BatchedGenerationRequest {
+                    model_id: MODEL_ID.into(),
+                    prefix_id: None,
+                    requests: vec![GenerationRequest {
+                        text: truncated_prompt.into(),
+                    }],
+                    params: Some(GuardrailsTextGenerationParameters::default().into()),
+                });
+            then.pb(BatchedGenerationResponse {
+                responses: vec![GenerationResponse {
+                    input_token_count: 2,
+                    generated_token_count: 1,
+                    text: " déjà vu".into(),
+                    ..Default::default()
+                }],
+            });
+        });
+        let tgis_server = MockServer::new("tgis").grpc().with_mocks(mocks);
+        tgis_server.start().await.unwrap();
+
+        let mut config = OrchestratorConfig::default();
+        configure_mock_servers(&mut config, Some(&tgis_server), None, None, None);
+        let orchestrator = Orchestrator::new(config, false).await?;
+
+        let request = GenerationWithDetectionHttpRequest {
+            model_id: MODEL_ID.into(),
+            prompt: prompt.into(),
+            detectors: HashMap::new(),
+            text_gen_parameters: Some(GuardrailsTextGenerationParameters {
+                truncate_input_tokens: Some(2),
+                truncation_strategy: Some(TruncationStrategy::Head),
+                ..Default::default()
+            }),
+        };
+        let task = GenerationWithDetectionTask::new(
+            TraceId::INVALID,
+            request,
+            HeaderMap::new(),
+            DetectionsFilter::default(),
+        );
+        let result = orchestrator.handle(task).await?;
+
+        // Removed span is in chars, excluding only the first token
+        assert_eq!(result.generated_text, " déjà vu");
+        assert_eq!(
+            result.warnings,
+            vec![DetectionWarning::input_truncated(2, 0, 4)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_generation_headers_not_sent_to_detectors() -> Result<(), Error> {
+        ensure_crypto_provider();
+        let prompt = "Hi there!";
+
+        // Generation service only responds when the generation-only header is sent
+        let mut mocks = MockSet::new();
+        mocks.mock(|when, then| {
+            when.path("/fmaas.GenerationService/Generate")
+                .header("x-gateway-route", "blue")
+                .pb(BatchedGenerationRequest {
+                    model_id: MODEL_ID.into(),
+                    prefix_id: None,
+                    requests: vec![GenerationRequest {
+                        text: prompt.into(),
+                    }],
+                    params: Some(GuardrailsTextGenerationParameters::default().into()),
+                });
+            then.pb(BatchedGenerationResponse {
+                responses: vec![GenerationResponse {
+                    input_token_count: 3,
+                    generated_token_count: 2,
+                    text: " How are you?".into(),
+                    ..Default::default()
+                }],
+            });
+        });
+        let tgis_server = MockServer::new("tgis").grpc().with_mocks(mocks);
+        tgis_server.start().await.unwrap();
+
+        // Detector records headers of requests it receives
+        let detector_headers = Arc::new(Mutex::new(Vec::<HeaderMap>::new()));
+        let app = Router::new().route(
+            "/api/v1/text/generation",
+            post({
+                let detector_headers = detector_headers.clone();
+                move |headers: HeaderMap| async move {
+                    detector_headers.lock().unwrap().push(headers);
+                    Json(Vec::<DetectionResult>::new())
+                }
+            }),
+        );
+        let port = serve(app).await;
+
+        let mut config = OrchestratorConfig::default();
+        configure_mock_servers(&mut config, Some(&tgis_server), None, None, None);
+        let mut detector_config = DetectorConfig::default();
+        detector_config.service.hostname = "localhost".into();
+        detector_config.service.port = Some(port);
+        detector_config.r#type = DetectorType::TextGeneration;
+        config
+            .detectors
+            .insert("answer_detector".into(), detector_config);
+        let orchestrator = Orchestrator::new(config, false).await?;
+
+        let request = GenerationWithDetectionHttpRequest {
+            model_id: MODEL_ID.into(),
+            prompt: prompt.into(),
+            detectors: HashMap::from([("answer_detector".into(), DetectorParams::default())]),
+            text_gen_parameters: None,
+        };
+        let headers = HeaderMap::from_iter([(
+            HeaderName::from_static("test-header"),
+            HeaderValue::from_static("test"),
+        )]);
+        let generation_headers = HeaderMap::from_iter([(
+            HeaderName::from_static("x-gateway-route"),
+            HeaderValue::from_static("blue"),
+        )]);
+        let task = GenerationWithDetectionTask::new(
+            TraceId::INVALID,
+            request,
+            headers,
+            DetectionsFilter::default(),
+        )
+        .with_generation_headers(generation_headers);
+        let result = orchestrator.handle(task).await?;
+
+        assert_eq!(result.generated_text, " How are you?");
+        let detector_headers = detector_headers.lock().unwrap();
+        assert_eq!(detector_headers.len(), 1);
+        assert_eq!(detector_headers[0]["test-header"], "test");
+        assert!(!detector_headers[0].contains_key("x-gateway-route"));
+        Ok(())
+    }
 }
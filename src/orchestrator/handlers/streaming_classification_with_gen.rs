@@ -16,8 +16,8 @@
 */
 
 use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock},
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex, RwLock},
 };
 
 use futures::StreamExt;
@@ -29,8 +29,8 @@ use tracing::{Instrument, error, info, instrument};
 
 use super::Handle;
 use crate::{
-    clients::GenerationClient,
-    config::DetectorType,
+    clients::{GenerationClient, chunker::DEFAULT_CHUNKER_ID},
+    config::{DetectionsFilter, DetectorType},
     models::{
         ClassifiedGeneratedTextStreamResult, DetectionWarning, DetectorParams, GuardrailsConfig,
         GuardrailsHttpRequest, GuardrailsTextGenerationParameters,
@@ -38,10 +38,10 @@ use crate::{
     },
     orchestrator::{
         Context, Error, Orchestrator,
-        common::{self, validate_detectors},
+        common::{self, features, validate_detectors},
         types::{
             Chunk, DetectionBatchStream, DetectionStream, Detections, GenerationStream,
-            MaxProcessedIndexBatcher,
+            MaxProcessedIndexBatcher, StreamingDetectionsFilter,
         },
     },
 };
@@ -63,16 +63,30 @@ impl Handle<StreamingClassificationWithGenTask> for Orchestrator {
         task: StreamingClassificationWithGenTask,
     ) -> Result<Self::Response, Error> {
         let ctx = self.ctx.clone();
+        let provenance = ctx
+            .provenance
+            .clone()
+            .map(|provenance| (task.trace_id, provenance));
 
         // Create response channel
         let (response_tx, response_rx) =
             mpsc::channel::<Result<ClassifiedGeneratedTextStreamResult, Error>>(128);
+        // Create paced response channel, pacing generation to the client, if configured
+        let pacing = ctx
+            .config
+            .generation
+            .as_ref()
+            .and_then(|generation| generation.pacing);
+        let paced_channel = pacing.map(|pacing| mpsc::channel(pacing.buffer_size.max(1)));
+        let paced_tx = paced_channel
+            .as_ref()
+            .map(|(paced_tx, _)| paced_tx.downgrade());
 
         tokio::spawn(async move {
             let trace_id = task.trace_id;
             info!(%trace_id, config = ?task.guardrails_config, "task started");
-            let input_detectors = task.guardrails_config.input_detectors();
-            let output_detectors = task.guardrails_config.output_detectors();
+            let mut input_detectors = task.guardrails_config.input_detectors();
+            let mut output_detectors = task.guardrails_config.output_detectors();
 
             // Input detectors validation
             // Allow `whole_doc_chunker` detectors on input detection
@@ -88,21 +102,50 @@ impl Handle<StreamingClassificationWithGenTask> for Orchestrator {
             }
 
             // Output detectors validation
-            // Disallow `whole_doc_chunker` detectors on output detection
-            // for now until results of these detectors are handled as
-            // planned for chat completions, with detection results
-            // provided separately at the end but not blocking other
-            // detection results that may be provided on smaller chunks
+            // Allow `whole_doc_chunker` detectors on output detection, run
+            // in a final pass on the complete generated text without
+            // blocking detections of other detectors on smaller chunks
             if let Err(error) = validate_detectors(
                 &output_detectors,
                 &ctx.config.detectors,
                 &[DetectorType::TextContents],
-                false,
+                true,
             ) {
                 let _ = response_tx.send(Err(error)).await;
                 return;
             }
 
+            // Skip detection phases disabled by feature flags
+            let mut warnings = features::apply_detection_flags(
+                &ctx.features.get(),
+                true,
+                &mut input_detectors,
+                &mut output_detectors,
+            );
+
+            if task.dry_run {
+                // Handle input detection only, without high-severity alerts
+                if let Some(alerts) = &ctx.alerts {
+                    alerts.suppress(trace_id);
+                }
+                let result = if !input_detectors.is_empty() {
+                    handle_input_detection(ctx.clone(), &task, input_detectors)
+                        .await
+                        .map(Option::unwrap_or_default)
+                } else {
+                    Ok(ClassifiedGeneratedTextStreamResult::default())
+                };
+                let result = result.map(|mut response| {
+                    let response_warnings = response.warnings.get_or_insert_default();
+                    response_warnings.push(DetectionWarning::dry_run());
+                    response_warnings.extend(warnings);
+                    response
+                });
+                info!(%trace_id, "task completed: returning dry run response");
+                let _ = response_tx.send(result).await;
+                return;
+            }
+
             if !input_detectors.is_empty() {
                 // Handle input detection
                 match handle_input_detection(ctx.clone(), &task, input_detectors).await {
@@ -127,16 +170,49 @@ impl Handle<StreamingClassificationWithGenTask> for Orchestrator {
                 .clients
                 .get_as::<GenerationClient>("generation")
                 .unwrap();
-            let generation_stream = match common::generate_stream(
+            let (inputs, params, truncation_warning) = match common::truncate_input(
                 client,
-                task.headers.clone(),
+                task.generation_headers.clone(),
                 task.model_id.clone(),
                 task.inputs.clone(),
                 task.text_gen_parameters.clone(),
             )
             .await
             {
-                Ok(stream) => stream,
+                Ok(truncated) => truncated,
+                Err(error) => {
+                    error!(%trace_id, %error, "task failed: error truncating input text");
+                    // Send error to response channel and terminate
+                    let _ = response_tx.send(Err(error)).await;
+                    return;
+                }
+            };
+            let generation_stream = match common::generate_stream(
+                client,
+                task.generation_headers.clone(),
+                task.model_id.clone(),
+                inputs,
+                params,
+            )
+            .await
+            {
+                Ok(stream) => {
+                    warnings.extend(truncation_warning);
+                    let stream = if !warnings.is_empty() {
+                        common::warn_generation_stream(stream, warnings)
+                    } else {
+                        stream
+                    };
+                    match (pacing, paced_tx) {
+                        (Some(pacing), Some(paced_tx)) => common::pace_generation_stream(
+                            task.model_id.clone(),
+                            stream,
+                            pacing,
+                            paced_tx,
+                        ),
+                        _ => stream,
+                    }
+                }
                 Err(error) => {
                     error!(%trace_id, %error, "task failed: error creating generation stream");
                     // Send error to response channel and terminate
@@ -147,10 +223,13 @@ impl Handle<StreamingClassificationWithGenTask> for Orchestrator {
 
             if !output_detectors.is_empty() {
                 // Handle output detection
+                let (final_detectors, output_detectors) =
+                    split_final_detectors(&ctx, output_detectors);
                 handle_output_detection(
                     ctx.clone(),
                     task,
                     output_detectors,
+                    final_detectors,
                     generation_stream,
                     response_tx,
                 )
@@ -161,6 +240,18 @@ impl Handle<StreamingClassificationWithGenTask> for Orchestrator {
             }
         }.in_current_span());
 
+        // Add provenance of the generated text to the last message, if configured
+        let response_rx = match provenance {
+            Some((trace_id, provenance)) => provenance.stream(trace_id, response_rx),
+            None => response_rx,
+        };
+        let response_rx = match paced_channel {
+            Some((paced_tx, paced_rx)) => {
+                common::relay_paced_responses(response_rx, paced_tx);
+                paced_rx
+            }
+            None => response_rx,
+        };
         Ok(ReceiverStream::new(response_rx))
     }
 }
@@ -173,7 +264,7 @@ async fn handle_input_detection(
 ) -> Result<Option<ClassifiedGeneratedTextStreamResult>, Error> {
     let trace_id = task.trace_id;
     let inputs = common::apply_masks(task.inputs.clone(), task.guardrails_config.input_masks());
-    let detections = match common::text_contents_detections(
+    let mut detections = match common::text_contents_detections(
         ctx.clone(),
         task.headers.clone(),
         detectors.clone(),
@@ -196,7 +287,7 @@ async fn handle_input_detection(
             .unwrap();
         let input_token_count = match common::tokenize(
             client,
-            task.headers.clone(),
+            task.generation_headers.clone(),
             task.model_id.clone(),
             task.inputs.clone(),
         )
@@ -208,6 +299,7 @@ async fn handle_input_detection(
                 return Err(error);
             }
         };
+        detections.apply_filter(task.detections_filter);
         // Build response with input detections
         let response = ClassifiedGeneratedTextStreamResult {
             input_token_count,
@@ -225,15 +317,76 @@ async fn handle_input_detection(
     }
 }
 
+/// Splits output detectors into detectors using `whole_doc_chunker`, run in a final pass
+/// on the complete generated text, and detectors run on chunks as text is generated.
+fn split_final_detectors(
+    ctx: &Context,
+    detectors: HashMap<String, DetectorParams>,
+) -> (
+    HashMap<String, DetectorParams>,
+    HashMap<String, DetectorParams>,
+) {
+    detectors.into_iter().partition(|(detector_id, params)| {
+        common::get_chunker_id(ctx, detector_id, params).as_deref() == Some(DEFAULT_CHUNKER_ID)
+    })
+}
+
+/// Handles output detection in two passes: `detectors` on chunks as text is generated,
+/// and `final_detectors` once on the complete generated text at the end of the stream.
 #[instrument(skip_all)]
 async fn handle_output_detection(
     ctx: Arc<Context>,
     task: StreamingClassificationWithGenTask,
     detectors: HashMap<String, DetectorParams>,
+    final_detectors: HashMap<String, DetectorParams>,
     mut generation_stream: GenerationStream,
     response_tx: mpsc::Sender<Result<ClassifiedGeneratedTextStreamResult, Error>>,
 ) {
     let trace_id = task.trace_id;
+    let detections_filter = task.detections_filter;
+    let stream_ordering = ctx.config.stream_ordering;
+    let holdback = ctx.config.stream_holdback_chunks;
+    // Create channel for responses of the streaming pass, followed by the final pass
+    let response_tx = if !final_detectors.is_empty() {
+        let (stream_tx, stream_rx) = mpsc::channel(128);
+        // Accumulate the generated text as streamed by the generation service, as
+        // responses of the streaming pass carry chunks, which may not cover it exactly
+        let generated_text = Arc::new(Mutex::new(String::new()));
+        generation_stream = generation_stream
+            .inspect({
+                let generated_text = generated_text.clone();
+                move |(_index, result)| {
+                    if let Ok(generation) = result {
+                        generated_text
+                            .lock()
+                            .unwrap()
+                            .push_str(generation.generated_text.as_deref().unwrap_or_default());
+                    }
+                }
+            })
+            .boxed();
+        tokio::spawn(
+            process_final_detection(
+                ctx.clone(),
+                trace_id,
+                task.headers.clone(),
+                detections_filter,
+                final_detectors,
+                generated_text,
+                stream_rx,
+                response_tx,
+            )
+            .in_current_span(),
+        );
+        stream_tx
+    } else {
+        response_tx
+    };
+    if detectors.is_empty() {
+        // No streaming pass, forward generation stream to the final pass
+        forward_generation_stream(trace_id, generation_stream, response_tx).await;
+        return;
+    }
     // Create input channel for detection pipeline
     let (input_tx, input_rx) = mpsc::channel(128);
     // Create shared generations
@@ -257,18 +410,28 @@ async fn handle_output_detection(
                 Ok(mut detection_streams) if detection_streams.len() == 1 => {
                     // Process single detection stream, batching not applicable
                     let detection_stream = detection_streams.swap_remove(0);
-                    process_detection_stream(trace_id, generations, detection_stream, response_tx)
-                        .await;
+                    process_detection_stream(
+                        trace_id,
+                        detections_filter,
+                        generations,
+                        holdback,
+                        detection_stream,
+                        response_tx,
+                    )
+                    .await;
                 }
                 Ok(detection_streams) => {
                     // Create detection batch stream
                     let detection_batch_stream = DetectionBatchStream::new(
-                        MaxProcessedIndexBatcher::new(detectors.len()),
+                        MaxProcessedIndexBatcher::new(detectors.len())
+                            .with_ordering(stream_ordering),
                         detection_streams,
                     );
                     process_detection_batch_stream(
                         trace_id,
+                        detections_filter,
                         generations,
+                        holdback,
                         detection_batch_stream,
                         response_tx,
                     )
@@ -339,17 +502,22 @@ async fn forward_generation_stream(
 #[instrument(skip_all)]
 async fn process_detection_stream(
     trace_id: TraceId,
+    detections_filter: DetectionsFilter,
     generations: Arc<RwLock<Vec<ClassifiedGeneratedTextStreamResult>>>,
+    holdback: usize,
     mut detection_stream: DetectionStream,
     response_tx: mpsc::Sender<Result<ClassifiedGeneratedTextStreamResult, Error>>,
 ) {
+    let mut detections_filter = StreamingDetectionsFilter::new(detections_filter);
+    let mut response_tx = HoldBackSender::new(response_tx, holdback);
     while let Some(result) = detection_stream.next().await {
         match result {
-            Ok((_, _detector_id, chunk, detections)) => {
+            Ok((_, _detector_id, chunk, mut detections)) => {
+                detections_filter.apply(&mut detections);
                 // Create response for this batch with output detections
                 let response = output_detection_response(&generations, chunk, detections).unwrap();
                 // Send message to response channel
-                if response_tx.send(Ok(response)).await.is_err() {
+                if !response_tx.send(response).await {
                     info!(%trace_id, "task completed: client disconnected");
                     return;
                 }
@@ -357,11 +525,12 @@ async fn process_detection_stream(
             Err(error) => {
                 error!(%trace_id, %error, "task failed: error received from detection stream");
                 // Send error to response channel and terminate
-                let _ = response_tx.send(Err(error)).await;
+                response_tx.send_error(error).await;
                 return;
             }
         }
     }
+    response_tx.flush().await;
     info!(%trace_id, "task completed: detection stream closed");
 }
 
@@ -369,17 +538,22 @@ async fn process_detection_stream(
 #[instrument(skip_all)]
 async fn process_detection_batch_stream(
     trace_id: TraceId,
+    detections_filter: DetectionsFilter,
     generations: Arc<RwLock<Vec<ClassifiedGeneratedTextStreamResult>>>,
+    holdback: usize,
     mut detection_batch_stream: DetectionBatchStream<MaxProcessedIndexBatcher>,
     response_tx: mpsc::Sender<Result<ClassifiedGeneratedTextStreamResult, Error>>,
 ) {
+    let mut detections_filter = StreamingDetectionsFilter::new(detections_filter);
+    let mut response_tx = HoldBackSender::new(response_tx, holdback);
     while let Some(result) = detection_batch_stream.next().await {
         match result {
-            Ok((chunk, detections)) => {
+            Ok((chunk, mut detections)) => {
+                detections_filter.apply(&mut detections);
                 // Create response for this batch with output detections
                 let response = output_detection_response(&generations, chunk, detections).unwrap();
                 // Send message to response channel
-                if response_tx.send(Ok(response)).await.is_err() {
+                if !response_tx.send(response).await {
                     info!(%trace_id, "task completed: client disconnected");
                     return;
                 }
@@ -387,14 +561,172 @@ async fn process_detection_batch_stream(
             Err(error) => {
                 error!(%trace_id, %error, "task failed: error received from detection batch stream");
                 // Send error to response channel and terminate
-                let _ = response_tx.send(Err(error)).await;
+                response_tx.send_error(error).await;
                 return;
             }
         }
     }
+    response_tx.flush().await;
     info!(%trace_id, "task completed: detection batch stream closed");
 }
 
+/// Forwards responses of the streaming pass to a response channel, then runs final
+/// detections on the complete generated text, accumulated from the generation stream,
+/// once the streaming pass completes.
+///
+/// The final response carries detections of the final pass, with an unsuitable output
+/// warning if either pass detected anything. It is not sent if the streaming pass failed.
+#[instrument(skip_all)]
+async fn process_final_detection(
+    ctx: Arc<Context>,
+    trace_id: TraceId,
+    headers: HeaderMap,
+    detections_filter: DetectionsFilter,
+    detectors: HashMap<String, DetectorParams>,
+    generated_text: Arc<Mutex<String>>,
+    mut stream_rx: mpsc::Receiver<Result<ClassifiedGeneratedTextStreamResult, Error>>,
+    response_tx: mpsc::Sender<Result<ClassifiedGeneratedTextStreamResult, Error>>,
+) {
+    let mut streaming_detections = false;
+    let mut last = ClassifiedGeneratedTextStreamResult::default();
+    while let Some(result) = stream_rx.recv().await {
+        let failed = result.is_err();
+        if let Ok(response) = &result {
+            streaming_detections |= response
+                .token_classification_results
+                .output
+                .as_ref()
+                .is_some_and(|output| !output.is_empty());
+            last = response.clone();
+        }
+        if response_tx.send(result).await.is_err() {
+            info!(%trace_id, "task completed: client disconnected");
+            return;
+        }
+        if failed {
+            return;
+        }
+    }
+
+    // The streaming pass completes once the generation stream is consumed
+    let generated_text = std::mem::take(&mut *generated_text.lock().unwrap());
+    let mut detections = match common::text_contents_detections(
+        ctx,
+        headers,
+        detectors,
+        0,
+        vec![(0, generated_text.clone())],
+    )
+    .await
+    {
+        Ok((_input_id, detections)) => detections,
+        Err(error) => {
+            error!(%trace_id, %error, "task failed: error processing final output detections");
+            let _ = response_tx.send(Err(error)).await;
+            return;
+        }
+    };
+    detections.apply_filter(detections_filter);
+    let mut warnings = detections.warnings();
+    if streaming_detections || !detections.is_empty() {
+        warnings.insert(0, DetectionWarning::unsuitable_output());
+    }
+    let response = ClassifiedGeneratedTextStreamResult {
+        token_classification_results: TextGenTokenClassificationResults {
+            input: None,
+            output: Some(detections.into()),
+        },
+        finish_reason: last.finish_reason,
+        generated_token_count: last.generated_token_count,
+        seed: last.seed,
+        input_token_count: last.input_token_count,
+        warnings: (!warnings.is_empty()).then_some(warnings),
+        start_index: Some(0),
+        processed_index: Some(generated_text.chars().count() as u32),
+        ..Default::default()
+    };
+    let _ = response_tx.send(Ok(response)).await;
+    info!(%trace_id, "task completed: final output detections sent");
+}
+
+/// A response sender holding back the most recent responses, releasing
+/// each once the detections of the following `size` chunks complete.
+///
+/// Once a response with detections is sent, the text of held responses and of the
+/// response itself is withheld, so text preceding flagged content within the window
+/// and the flagged content itself are never emitted.
+struct HoldBackSender {
+    response_tx: mpsc::Sender<Result<ClassifiedGeneratedTextStreamResult, Error>>,
+    held: VecDeque<ClassifiedGeneratedTextStreamResult>,
+    size: usize,
+}
+
+impl HoldBackSender {
+    fn new(
+        response_tx: mpsc::Sender<Result<ClassifiedGeneratedTextStreamResult, Error>>,
+        size: usize,
+    ) -> Self {
+        Self {
+            response_tx,
+            held: VecDeque::with_capacity(size + 1),
+            size,
+        }
+    }
+
+    /// Holds a response, sending responses released from the window. Held responses are
+    /// sent with their text withheld if the response has detections.
+    /// Returns `false` if the client disconnected.
+    async fn send(&mut self, response: ClassifiedGeneratedTextStreamResult) -> bool {
+        let flagged = self.size > 0
+            && response
+                .token_classification_results
+                .output
+                .as_ref()
+                .is_some_and(|output| !output.is_empty());
+        self.held.push_back(response);
+        if flagged {
+            self.held.iter_mut().for_each(withhold);
+        }
+        let size = if flagged { 0 } else { self.size };
+        while self.held.len() > size {
+            let response = self.held.pop_front().unwrap();
+            if self.response_tx.send(Ok(response)).await.is_err() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Sends all held responses.
+    async fn flush(&mut self) {
+        while let Some(response) = self.held.pop_front() {
+            if self.response_tx.send(Ok(response)).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Sends an error, discarding held responses.
+    async fn send_error(&mut self, error: Error) {
+        self.held.clear();
+        let _ = self.response_tx.send(Err(error)).await;
+    }
+}
+
+/// Removes the generated text and tokens of a response, keeping its detections, with a
+/// warning of the withheld span.
+fn withhold(response: &mut ClassifiedGeneratedTextStreamResult) {
+    if response.generated_text.take().is_none() {
+        return;
+    }
+    response.tokens = None;
+    let warning = DetectionWarning::output_withheld(
+        response.start_index.unwrap_or_default(),
+        response.processed_index.unwrap_or_default(),
+    );
+    response.warnings.get_or_insert_default().push(warning);
+}
+
 /// Builds a response with output detections.
 fn output_detection_response(
     generations: &Arc<RwLock<Vec<ClassifiedGeneratedTextStreamResult>>>,
@@ -402,30 +734,56 @@ fn output_detection_response(
     detections: Detections,
 ) -> Result<ClassifiedGeneratedTextStreamResult, Error> {
     // Get subset of generations relevant for this chunk
+    // Generations are borrowed by index under the read lock rather than cloned
+    let generations = generations.read().unwrap();
     let generations_slice = generations
-        .read()
-        .unwrap()
         .get(chunk.input_start_index..=chunk.input_end_index)
-        .unwrap_or_default()
-        .to_vec();
-    let last = generations_slice.last().cloned().unwrap_or_default();
+        .unwrap_or_default();
     let tokens = generations_slice
         .iter()
-        .flat_map(|generation| generation.tokens.clone().unwrap_or_default())
+        .filter_map(|generation| generation.tokens.as_deref())
+        .flatten()
+        .cloned()
         .collect::<Vec<_>>();
-    let mut response = ClassifiedGeneratedTextStreamResult {
-        generated_text: Some(chunk.text),
-        start_index: Some(chunk.start as u32),
-        processed_index: Some(chunk.end as u32),
-        tokens: Some(tokens),
-        ..last
+    let mut response = match generations_slice.last() {
+        Some(last) => ClassifiedGeneratedTextStreamResult {
+            finish_reason: last.finish_reason,
+            generated_token_count: last.generated_token_count,
+            seed: last.seed,
+            input_token_count: last.input_token_count,
+            warnings: last.warnings.clone(),
+            input_tokens: last.input_tokens.clone(),
+            token_classification_results: last.token_classification_results.clone(),
+            ..Default::default()
+        },
+        None => ClassifiedGeneratedTextStreamResult::default(),
     };
+    response.generated_text = Some(chunk.text.to_string());
+    response.start_index = Some(chunk.start as u32);
+    response.processed_index = Some(chunk.end as u32);
+    response.tokens = Some(tokens);
+    let detection_warnings = detections.warnings();
+    if !detection_warnings.is_empty() {
+        response
+            .warnings
+            .get_or_insert_with(Vec::new)
+            .extend(detection_warnings);
+    }
     response.token_classification_results.output = Some(detections.into());
     if chunk.input_start_index == 0 {
         // Get input_token_count and seed from first generation message
         let first = generations_slice.first().unwrap();
         response.input_token_count = first.input_token_count;
         response.seed = first.seed;
+        // Get warnings, e.g. on input truncation, from first generation message
+        if generations_slice.len() > 1 {
+            if let Some(warnings) = &first.warnings {
+                response
+                    .warnings
+                    .get_or_insert_default()
+                    .extend(warnings.iter().cloned());
+            }
+        }
         // Get input_tokens from second generation message (if specified)
         response.input_tokens = if let Some(second) = generations_slice.get(1) {
             second.input_tokens.clone()
@@ -450,17 +808,118 @@ pub struct StreamingClassificationWithGenTask {
     pub text_gen_parameters: Option<GuardrailsTextGenerationParameters>,
     /// Headers
     pub headers: HeaderMap,
+    /// Headers sent to the generation service, including generation-only headers
+    pub generation_headers: HeaderMap,
+    /// Filter for detections returned in responses
+    pub detections_filter: DetectionsFilter,
+    /// Whether to run input detection only, without generation or enforcing actions
+    pub dry_run: bool,
 }
 
 impl StreamingClassificationWithGenTask {
-    pub fn new(trace_id: TraceId, request: GuardrailsHttpRequest, headers: HeaderMap) -> Self {
+    pub fn new(
+        trace_id: TraceId,
+        request: GuardrailsHttpRequest,
+        headers: HeaderMap,
+        detections_filter: DetectionsFilter,
+        dry_run: bool,
+    ) -> Self {
         Self {
             trace_id,
             model_id: request.model_id,
             inputs: request.inputs,
             guardrails_config: request.guardrail_config.unwrap_or_default(),
             text_gen_parameters: request.text_gen_parameters,
+            generation_headers: headers.clone(),
             headers,
+            detections_filter,
+            dry_run,
+        }
+    }
+
+    /// Adds headers sent to the generation service only, never to detectors.
+    pub fn with_generation_headers(mut self, headers: HeaderMap) -> Self {
+        self.generation_headers.extend(headers);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenClassificationResult;
+
+    fn response(text: &str, start: u32, flagged: bool) -> ClassifiedGeneratedTextStreamResult {
+        let output = flagged.then(|| {
+            vec![TokenClassificationResult {
+                start,
+                end: start + text.chars().count() as u32,
+                word: text.into(),
+                entity: "has_angle_brackets".into(),
+                entity_group: "angle_brackets".into(),
+                detector_id: Some("angle_brackets".into()),
+                score: 1.0,
+                token_count: None,
+                chunk: None,
+                category: None,
+            }]
+        });
+        ClassifiedGeneratedTextStreamResult {
+            generated_text: Some(text.into()),
+            token_classification_results: TextGenTokenClassificationResults {
+                input: None,
+                output,
+            },
+            start_index: Some(start),
+            processed_index: Some(start + text.chars().count() as u32),
+            ..Default::default()
         }
     }
+
+    #[tokio::test]
+    async fn test_hold_back_sender() {
+        let (response_tx, mut response_rx) = mpsc::channel(8);
+        let mut sender = HoldBackSender::new(response_tx, 1);
+
+        // Responses are released once the detections of the following chunk complete
+        assert!(sender.send(response("Hi. ", 0, false)).await);
+        assert!(response_rx.try_recv().is_err());
+        assert!(sender.send(response("Here is ", 4, false)).await);
+        let released = response_rx.try_recv().unwrap().unwrap();
+        assert_eq!(released.generated_text.as_deref(), Some("Hi. "));
+        assert!(response_rx.try_recv().is_err());
+
+        // Text preceding flagged content within the window and the flagged content are withheld
+        assert!(sender.send(response("<bad>. ", 12, true)).await);
+        let withheld = response_rx.try_recv().unwrap().unwrap();
+        assert_eq!(withheld.generated_text, None);
+        assert_eq!(
+            withheld.warnings,
+            Some(vec![DetectionWarning::output_withheld(4, 12)])
+        );
+        let flagged = response_rx.try_recv().unwrap().unwrap();
+        assert_eq!(flagged.generated_text, None);
+        assert_eq!(
+            flagged.token_classification_results.output.unwrap().len(),
+            1
+        );
+        assert!(response_rx.try_recv().is_err());
+
+        // Held responses are sent once the stream ends
+        assert!(sender.send(response("Bye.", 19, false)).await);
+        assert!(response_rx.try_recv().is_err());
+        sender.flush().await;
+        let released = response_rx.try_recv().unwrap().unwrap();
+        assert_eq!(released.generated_text.as_deref(), Some("Bye."));
+    }
+
+    #[tokio::test]
+    async fn test_hold_back_sender_disabled() {
+        let (response_tx, mut response_rx) = mpsc::channel(8);
+        let mut sender = HoldBackSender::new(response_tx, 0);
+        assert!(sender.send(response("<bad>", 0, true)).await);
+        let sent = response_rx.try_recv().unwrap().unwrap();
+        assert_eq!(sent.generated_text.as_deref(), Some("<bad>"));
+        assert_eq!(sent.warnings, None);
+    }
 }
@@ -25,16 +25,22 @@ use tracing::{Instrument, error, info, instrument};
 
 use super::Handle;
 use crate::{
-    config::DetectorType,
-    models::{DetectorParams, StreamingContentDetectionRequest, StreamingContentDetectionResponse},
+    config::{DetectionsFilter, DetectorType},
+    models::{
+        DetectionWarning, DetectorParams, StreamingContentDetectionRequest,
+        StreamingContentDetectionResponse,
+    },
     orchestrator::{
         Context, Error, Orchestrator,
         common::{self, validate_detectors},
-        types::{BoxStream, DetectionBatchStream, DetectionStream, MaxProcessedIndexBatcher},
+        types::{
+            BoxStream, DetectionBatchStream, DetectionStream, MaxProcessedIndexBatcher,
+            StreamingDetectionsFilter,
+        },
     },
 };
 
-type InputStream =
+pub(super) type InputStream =
     Pin<Box<dyn Stream<Item = (usize, Result<StreamingContentDetectionRequest, Error>)> + Send>>;
 
 impl Handle<StreamingContentDetectionTask> for Orchestrator {
@@ -56,6 +62,7 @@ impl Handle<StreamingContentDetectionTask> for Orchestrator {
             async move {
                 let trace_id = task.trace_id;
                 let headers = task.headers;
+                let detections_filter = task.detections_filter;
                 let mut input_stream = Box::pin(task.input_stream.peekable());
                 let detectors = match extract_detectors(&mut input_stream).await {
                     Ok(detectors) => detectors,
@@ -77,8 +84,28 @@ impl Handle<StreamingContentDetectionTask> for Orchestrator {
                     return;
                 }
 
-                handle_detection(ctx, trace_id, headers, detectors, input_stream, response_tx)
-                    .await;
+                if !ctx.features.get().streaming_detection {
+                    info!(%trace_id, "task completed: streaming detection is disabled");
+                    let response = StreamingContentDetectionResponse {
+                        detections: Vec::new(),
+                        processed_index: 0,
+                        start_index: 0,
+                        warnings: vec![DetectionWarning::streaming_detection_disabled()],
+                    };
+                    let _ = response_tx.send(Ok(response)).await;
+                    return;
+                }
+
+                handle_detection(
+                    ctx,
+                    trace_id,
+                    headers,
+                    detectors,
+                    detections_filter,
+                    input_stream,
+                    response_tx,
+                )
+                .await;
             }
             .in_current_span(),
         );
@@ -88,7 +115,7 @@ impl Handle<StreamingContentDetectionTask> for Orchestrator {
 }
 
 /// Extracts detectors config from first message.
-async fn extract_detectors(
+pub(super) async fn extract_detectors(
     input_stream: &mut Peekable<InputStream>,
 ) -> Result<HashMap<String, DetectorParams>, Error> {
     // We can use Peekable to get a reference to it instead of consuming the message here
@@ -120,9 +147,11 @@ async fn handle_detection(
     trace_id: TraceId,
     headers: HeaderMap,
     detectors: HashMap<String, DetectorParams>,
+    detections_filter: DetectionsFilter,
     mut input_stream: InputStream,
     response_tx: mpsc::Sender<Result<StreamingContentDetectionResponse, Error>>,
 ) {
+    let stream_ordering = ctx.config.stream_ordering;
     // Create input channel for detection pipeline
     let (input_tx, input_rx) = mpsc::channel(128);
     // Create detection streams
@@ -136,16 +165,28 @@ async fn handle_detection(
                 Ok(mut detection_streams) if detection_streams.len() == 1 => {
                     // Process single detection stream, batching not applicable
                     let detection_stream = detection_streams.swap_remove(0);
-                    process_detection_stream(trace_id, detection_stream, response_tx).await;
+                    process_detection_stream(
+                        trace_id,
+                        detections_filter,
+                        detection_stream,
+                        response_tx,
+                    )
+                    .await;
                 }
                 Ok(detection_streams) => {
                     // Create detection batch stream
                     let detection_batch_stream = DetectionBatchStream::new(
-                        MaxProcessedIndexBatcher::new(detectors.len()),
+                        MaxProcessedIndexBatcher::new(detectors.len())
+                            .with_ordering(stream_ordering),
                         detection_streams,
                     );
-                    process_detection_batch_stream(trace_id, detection_batch_stream, response_tx)
-                        .await;
+                    process_detection_batch_stream(
+                        trace_id,
+                        detections_filter,
+                        detection_batch_stream,
+                        response_tx,
+                    )
+                    .await;
                 }
                 Err(error) => {
                     error!(%trace_id, %error, "task failed: error creating detection streams");
@@ -181,15 +222,19 @@ async fn handle_detection(
 #[instrument(skip_all)]
 async fn process_detection_stream(
     trace_id: TraceId,
+    detections_filter: DetectionsFilter,
     mut detection_stream: DetectionStream,
     response_tx: mpsc::Sender<Result<StreamingContentDetectionResponse, Error>>,
 ) {
+    let mut detections_filter = StreamingDetectionsFilter::new(detections_filter);
     while let Some(result) = detection_stream.next().await {
         match result {
-            Ok((_, _detector_id, chunk, detections)) => {
+            Ok((_, _detector_id, chunk, mut detections)) => {
+                detections_filter.apply(&mut detections);
                 let response = StreamingContentDetectionResponse {
                     start_index: chunk.start as u32,
                     processed_index: chunk.end as u32,
+                    warnings: detections.warnings(),
                     detections: detections.into(),
                 };
                 // Send message to response channel
@@ -213,15 +258,19 @@ async fn process_detection_stream(
 #[instrument(skip_all)]
 async fn process_detection_batch_stream(
     trace_id: TraceId,
+    detections_filter: DetectionsFilter,
     mut detection_batch_stream: DetectionBatchStream<MaxProcessedIndexBatcher>,
     response_tx: mpsc::Sender<Result<StreamingContentDetectionResponse, Error>>,
 ) {
+    let mut detections_filter = StreamingDetectionsFilter::new(detections_filter);
     while let Some(result) = detection_batch_stream.next().await {
         match result {
-            Ok((chunk, detections)) => {
+            Ok((chunk, mut detections)) => {
+                detections_filter.apply(&mut detections);
                 let response = StreamingContentDetectionResponse {
                     start_index: chunk.start as u32,
                     processed_index: chunk.end as u32,
+                    warnings: detections.warnings(),
                     detections: detections.into(),
                 };
                 // Send message to response channel
@@ -246,6 +295,8 @@ pub struct StreamingContentDetectionTask {
     pub trace_id: TraceId,
     /// Headers
     pub headers: HeaderMap,
+    /// Filter for detections returned in responses
+    pub detections_filter: DetectionsFilter,
     /// Detectors configuration
     pub detectors: HashMap<String, DetectorParams>,
     /// Input stream to run detections on
@@ -256,11 +307,13 @@ impl StreamingContentDetectionTask {
     pub fn new(
         trace_id: TraceId,
         headers: HeaderMap,
+        detections_filter: DetectionsFilter,
         input_stream: BoxStream<(usize, Result<StreamingContentDetectionRequest, Error>)>,
     ) -> Self {
         Self {
             trace_id,
             headers,
+            detections_filter,
             detectors: HashMap::default(),
             input_stream,
         }
@@ -0,0 +1,275 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+use std::collections::HashMap;
+
+use futures::StreamExt;
+use http::HeaderMap;
+use opentelemetry::trace::TraceId;
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{Instrument, error, info, instrument, warn};
+
+use super::{
+    Handle, StreamingContentDetectionTask,
+    streaming_content_detection::{InputStream, extract_detectors},
+};
+use crate::{
+    config::{DetectionsFilter, DetectorType},
+    models::{
+        DetectionWarning, DetectorParams, StreamingContentDetectionRequest,
+        StreamingInputDetectionResponse,
+    },
+    orchestrator::{
+        Error, Orchestrator,
+        common::{self, validate_detectors},
+        types::BoxStream,
+    },
+};
+
+impl Handle<StreamingInputDetectionTask> for Orchestrator {
+    type Response = ReceiverStream<Result<StreamingInputDetectionResponse, Error>>;
+
+    #[instrument(
+        name = "streaming_input_detection",
+        skip_all,
+        fields(trace_id = task.trace_id.to_string(), headers = ?task.headers)
+    )]
+    async fn handle(&self, task: StreamingInputDetectionTask) -> Result<Self::Response, Error> {
+        let orchestrator = self.clone();
+        let ctx = self.ctx.clone();
+
+        // Create response channel
+        let (response_tx, response_rx) =
+            mpsc::channel::<Result<StreamingInputDetectionResponse, Error>>(128);
+
+        tokio::spawn(
+            async move {
+                let trace_id = task.trace_id;
+                let headers = task.headers;
+                let detections_filter = task.detections_filter;
+                let mut input_stream = Box::pin(task.input_stream.peekable());
+                let detectors = match extract_detectors(&mut input_stream).await {
+                    Ok(detectors) => detectors,
+                    Err(error) => {
+                        error!(%error, "error extracting detectors from first message");
+                        let _ = response_tx.send(Err(error)).await;
+                        return;
+                    }
+                };
+                info!(%trace_id, config = ?detectors, "task started");
+
+                if let Err(error) = validate_detectors(
+                    &detectors,
+                    &ctx.config.detectors,
+                    &[DetectorType::TextContents],
+                    false,
+                ) {
+                    let _ = response_tx.send(Err(error)).await;
+                    return;
+                }
+
+                if !ctx.features.get().streaming_detection {
+                    info!(%trace_id, "task completed: streaming detection is disabled");
+                    let response = StreamingInputDetectionResponse {
+                        warnings: vec![DetectionWarning::streaming_detection_disabled()],
+                        ..Default::default()
+                    };
+                    let _ = response_tx.send(Ok(response)).await;
+                    return;
+                }
+
+                handle_detection(
+                    orchestrator,
+                    trace_id,
+                    headers,
+                    detectors,
+                    detections_filter,
+                    input_stream,
+                    response_tx,
+                )
+                .await;
+            }
+            .in_current_span(),
+        );
+
+        Ok(ReceiverStream::new(response_rx))
+    }
+}
+
+/// Runs final detections on complete chunks with a streaming content detection task,
+/// and interim detections on the input text following the latest complete chunk.
+///
+/// Interim detections run on the latest input text once the previous interim detections
+/// complete, so input received meanwhile is coalesced rather than queued.
+#[instrument(skip_all)]
+async fn handle_detection(
+    orchestrator: Orchestrator,
+    trace_id: TraceId,
+    headers: HeaderMap,
+    detectors: HashMap<String, DetectorParams>,
+    detections_filter: DetectionsFilter,
+    mut input_stream: InputStream,
+    response_tx: mpsc::Sender<Result<StreamingInputDetectionResponse, Error>>,
+) {
+    let ctx = orchestrator.ctx.clone();
+    // Create channels tracking the input text and the index up to which it is processed
+    // by final detections
+    let (text_tx, mut text_rx) = watch::channel(String::new());
+    let (processed_index_tx, _) = watch::channel(0usize);
+
+    // Create streaming content detection task for final detections
+    let (input_tx, input_rx) = mpsc::channel(128);
+    let task = StreamingContentDetectionTask::new(
+        trace_id,
+        headers.clone(),
+        detections_filter,
+        ReceiverStream::new(input_rx).boxed(),
+    );
+    let mut final_stream = match orchestrator.handle(task).await {
+        Ok(final_stream) => final_stream,
+        Err(error) => {
+            error!(%trace_id, %error, "task failed: error creating final detection stream");
+            let _ = response_tx.send(Err(error)).await;
+            return;
+        }
+    };
+
+    // Create channel of interim detections, sequenced with final detections
+    let (interim_tx, mut interim_rx) = mpsc::channel::<StreamingInputDetectionResponse>(1);
+
+    // Spawn task to process interim detections
+    tokio::spawn({
+        let processed_index_rx = processed_index_tx.subscribe();
+        async move {
+            // Completes once the input stream is consumed, as the remaining input text
+            // is processed by final detections
+            while text_rx.changed().await.is_ok() {
+                let text = text_rx.borrow_and_update().clone();
+                let start_index = *processed_index_rx.borrow();
+                let pending_text = text.chars().skip(start_index).collect::<String>();
+                if pending_text.trim().is_empty() {
+                    continue;
+                }
+                let processed_index = start_index + pending_text.chars().count();
+                match common::text_contents_detections(
+                    ctx.clone(),
+                    headers.clone(),
+                    detectors.clone(),
+                    0,
+                    vec![(start_index, pending_text)],
+                )
+                .await
+                {
+                    Ok((_, mut detections)) => {
+                        detections.apply_filter(detections_filter);
+                        let response = StreamingInputDetectionResponse {
+                            start_index: start_index as u32,
+                            processed_index: processed_index as u32,
+                            interim: true,
+                            warnings: detections.warnings(),
+                            detections: detections.into(),
+                        };
+                        if interim_tx.send(response).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(error) => {
+                        // Interim detections are best effort, errors are returned by final detections
+                        warn!(%trace_id, %error, "interim detections failed");
+                    }
+                }
+            }
+        }
+        .in_current_span()
+    });
+
+    // Spawn task to send interim and final detections. Interim detections are sent by this
+    // task only, so those superseded by final detections are dropped before they are sent.
+    tokio::spawn(
+        async move {
+            let mut final_stream_closed = false;
+            loop {
+                let result = tokio::select! {
+                    result = final_stream.next(), if !final_stream_closed => match result {
+                        Some(result) => result.map(|response| {
+                            processed_index_tx.send_replace(response.processed_index as usize);
+                            StreamingInputDetectionResponse::from(response)
+                        }),
+                        None => {
+                            final_stream_closed = true;
+                            continue;
+                        }
+                    },
+                    Some(response) = interim_rx.recv() => {
+                        if *processed_index_tx.borrow() > response.start_index as usize {
+                            // Superseded by final detections
+                            continue;
+                        }
+                        Ok(response)
+                    },
+                    else => break,
+                };
+                if response_tx.send(result).await.is_err() {
+                    info!(%trace_id, "task completed: client disconnected");
+                    return;
+                }
+            }
+            info!(%trace_id, "task completed: detection streams closed");
+        }
+        .in_current_span(),
+    );
+
+    // Spawn task to consume input stream
+    tokio::spawn(
+        async move {
+            while let Some((index, result)) = input_stream.next().await {
+                if let Ok(message) = &result {
+                    text_tx.send_modify(|text| text.push_str(&message.content));
+                }
+                let _ = input_tx.send((index, result)).await;
+            }
+        }
+        .in_current_span(),
+    );
+}
+
+pub struct StreamingInputDetectionTask {
+    /// Trace ID
+    pub trace_id: TraceId,
+    /// Headers
+    pub headers: HeaderMap,
+    /// Filter for detections returned in responses
+    pub detections_filter: DetectionsFilter,
+    /// Input stream of text as it is typed or dictated
+    pub input_stream: BoxStream<(usize, Result<StreamingContentDetectionRequest, Error>)>,
+}
+
+impl StreamingInputDetectionTask {
+    pub fn new(
+        trace_id: TraceId,
+        headers: HeaderMap,
+        detections_filter: DetectionsFilter,
+        input_stream: BoxStream<(usize, Result<StreamingContentDetectionRequest, Error>)>,
+    ) -> Self {
+        Self {
+            trace_id,
+            headers,
+            detections_filter,
+            input_stream,
+        }
+    }
+}
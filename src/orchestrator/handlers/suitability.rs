@@ -0,0 +1,173 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use http::HeaderMap;
+use opentelemetry::trace::TraceId;
+use tracing::{info, instrument};
+
+use super::Handle;
+use crate::{
+    config::{DetectionAction, DetectionsFilter, DetectorType, OrchestratorConfig},
+    models::{DetectorParams, Redaction, SuitabilityHttpRequest, SuitabilityResult, Verdict},
+    orchestrator::{
+        Error, Orchestrator,
+        common::{self, validate_detectors},
+        types::Detections,
+    },
+};
+
+impl Handle<SuitabilityTask> for Orchestrator {
+    type Response = SuitabilityResult;
+
+    #[instrument(
+        name = "suitability",
+        skip_all,
+        fields(trace_id = ?task.trace_id, headers = ?task.headers)
+    )]
+    async fn handle(&self, task: SuitabilityTask) -> Result<Self::Response, Error> {
+        let ctx = self.ctx.clone();
+        let trace_id = task.trace_id;
+        info!(%trace_id, config = ?task.detectors, "task started");
+
+        validate_detectors(
+            &task.detectors,
+            &ctx.config.detectors,
+            &[DetectorType::TextContents],
+            true,
+        )?;
+
+        // Handle detection
+        let (_, detections) = common::text_contents_detections(
+            ctx.clone(),
+            task.headers,
+            task.detectors,
+            0,
+            vec![(0, task.content)],
+        )
+        .await?;
+
+        let result = suitability(&ctx.config, detections.clone(), task.detections_filter);
+        if let Some(alerts) = ctx
+            .alerts
+            .as_ref()
+            .filter(|_| result.verdict == Verdict::Block)
+        {
+            alerts.blocked(trace_id, "suitability", &detections);
+        }
+        info!(%trace_id, verdict = ?result.verdict, "task completed");
+        Ok(result)
+    }
+}
+
+/// Builds a suitability result from detections.
+///
+/// Content is blocked if any detection comes from a detector configured to block,
+/// otherwise detected spans are suggested for redaction. The verdict and redactions
+/// cover all detections, `filter` only shapes the reported categories and scores.
+fn suitability(
+    config: &OrchestratorConfig,
+    detections: Detections,
+    filter: DetectionsFilter,
+) -> SuitabilityResult {
+    let warnings = detections.warnings();
+    if detections.is_empty() {
+        return SuitabilityResult {
+            warnings,
+            ..Default::default()
+        };
+    }
+    let block = detections.iter().any(|detection| {
+        let action = detection
+            .detector_id
+            .as_deref()
+            .and_then(|detector_id| config.detector(detector_id))
+            .map(|detector| detector.action)
+            .unwrap_or_default();
+        action == DetectionAction::Block
+    });
+    let mut filtered = detections.clone();
+    filtered.apply_filter(filter);
+    let mut categories = BTreeSet::new();
+    let mut max_scores: BTreeMap<String, f64> = BTreeMap::new();
+    for detection in filtered.iter() {
+        categories.insert(
+            detection
+                .category
+                .clone()
+                .unwrap_or_else(|| detection.detection_type.clone()),
+        );
+        if let Some(detector_id) = &detection.detector_id {
+            let max_score = max_scores.entry(detector_id.clone()).or_insert(0.0);
+            *max_score = max_score.max(detection.score);
+        }
+    }
+    let (verdict, redactions) = if block {
+        (Verdict::Block, Vec::new())
+    } else {
+        let redactions = detections
+            .into_iter()
+            .filter_map(|detection| {
+                Some(Redaction {
+                    start: detection.start?,
+                    end: detection.end?,
+                    detector_id: detection.detector_id,
+                    category: detection.category,
+                })
+            })
+            .collect();
+        (Verdict::Redact, redactions)
+    };
+    SuitabilityResult {
+        verdict,
+        categories: categories.into_iter().collect(),
+        max_scores,
+        redactions,
+        warnings,
+    }
+}
+
+#[derive(Debug)]
+pub struct SuitabilityTask {
+    /// Trace ID
+    pub trace_id: TraceId,
+    /// Content text
+    pub content: String,
+    /// Detectors configuration
+    pub detectors: HashMap<String, DetectorParams>,
+    /// Headers
+    pub headers: HeaderMap,
+    /// Filter for detections returned in responses
+    pub detections_filter: DetectionsFilter,
+}
+
+impl SuitabilityTask {
+    pub fn new(
+        trace_id: TraceId,
+        request: SuitabilityHttpRequest,
+        headers: HeaderMap,
+        detections_filter: DetectionsFilter,
+    ) -> Self {
+        Self {
+            trace_id,
+            content: request.content,
+            detectors: request.detectors,
+            headers,
+            detections_filter,
+        }
+    }
+}
@@ -14,7 +14,7 @@
  limitations under the License.
 
 */
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use http::HeaderMap;
 use opentelemetry::trace::TraceId;
@@ -22,11 +22,20 @@ use tracing::{info, instrument};
 
 use super::Handle;
 use crate::{
-    config::DetectorType,
-    models::{DetectorParams, TextContentDetectionHttpRequest, TextContentDetectionResult},
+    config::{DetectionsFilter, DetectorType},
+    models::{
+        DetectorParams, PendingDetectors, TextContentDetectionHttpRequest,
+        TextContentDetectionResult, validate_detector_params,
+    },
     orchestrator::{
-        Error, Orchestrator,
-        common::{self, validate_detectors},
+        Context, Error, Orchestrator,
+        common::{
+            self,
+            features::{self, DetectionPhase},
+            pending::{DispatchedDetections, PendingDetections},
+            unfiltered, validate_detectors,
+        },
+        types::Detections,
     },
 };
 
@@ -38,7 +47,7 @@ impl Handle<TextContentDetectionTask> for Orchestrator {
         skip_all,
         fields(trace_id = ?task.trace_id, headers = ?task.headers)
     )]
-    async fn handle(&self, task: TextContentDetectionTask) -> Result<Self::Response, Error> {
+    async fn handle(&self, mut task: TextContentDetectionTask) -> Result<Self::Response, Error> {
         let ctx = self.ctx.clone();
         let trace_id = task.trace_id;
         info!(%trace_id, config = ?task.detectors, "task started");
@@ -50,22 +59,183 @@ impl Handle<TextContentDetectionTask> for Orchestrator {
             true,
         )?;
 
-        // Handle detection
-        let (_, detections) = common::text_contents_detections(
-            ctx,
-            task.headers,
-            task.detectors,
-            0,
-            vec![(0, task.content)],
-        )
-        .await?;
+        // Skip detection if input detection is disabled
+        let warnings = features::apply_phase_flag(
+            &ctx.features.get(),
+            DetectionPhase::Input,
+            &mut task.detectors,
+        );
+        if !warnings.is_empty() {
+            info!(%trace_id, "task completed: input detection is disabled");
+            return Ok(TextContentDetectionResult {
+                warnings,
+                ..Default::default()
+            });
+        }
+
+        // Collect detections of detectors dispatched while the request body was received,
+        // unless the request changed them
+        if let Some(dispatched) = task
+            .dispatched
+            .take()
+            .filter(|dispatched| dispatched.matches(&task.detectors))
+        {
+            let pending = dispatched.into_pending(task.detections_filter);
+            return self
+                .collect_pending(pending, task.max_wait_ms, task.consumer)
+                .await;
+        }
+
+        let Some(max_wait_ms) = task.max_wait_ms else {
+            // Handle detection
+            let (_, mut detections) = common::text_contents_detections(
+                ctx,
+                task.headers,
+                task.detectors,
+                0,
+                vec![(0, task.content)],
+            )
+            .await?;
+            unfiltered::record_input(&detections);
+            detections.apply_filter(task.detections_filter);
 
+            return Ok(TextContentDetectionResult {
+                warnings: detections.warnings(),
+                detections: detections.into(),
+                pending: None,
+            });
+        };
+
+        // Handle detection of each detector in a task, returning detections of detectors
+        // completed within `max_wait_ms`
+        let mut dispatched = DispatchedDetections::new();
+        for (detector_id, params) in task.detectors {
+            let detection = content_detections(
+                ctx.clone(),
+                task.headers.clone(),
+                task.content.clone(),
+                detector_id.clone(),
+                params.clone(),
+            );
+            dispatched.dispatch(detector_id, params, detection);
+        }
+        let pending = dispatched.into_pending(task.detections_filter);
+        self.collect_pending(pending, Some(max_wait_ms), task.consumer)
+            .await
+    }
+}
+
+impl Handle<PendingContentDetectionTask> for Orchestrator {
+    type Response = TextContentDetectionResult;
+
+    #[instrument(
+        name = "pending_content_detection",
+        skip_all,
+        fields(trace_id = ?task.trace_id)
+    )]
+    async fn handle(&self, task: PendingContentDetectionTask) -> Result<Self::Response, Error> {
+        let trace_id = task.trace_id;
+        info!(%trace_id, continuation_token = task.continuation_token, "task started");
+        let pending = self
+            .pending_detections
+            .take(&task.continuation_token, task.consumer.as_deref())
+            .ok_or_else(|| Error::PendingDetectionsNotFound(task.continuation_token.clone()))?;
+        self.collect_pending(pending, task.max_wait_ms, task.consumer)
+            .await
+    }
+}
+
+impl Orchestrator {
+    /// Dispatches detection of `content` by `detector_id` of a text content detection
+    /// request whose body is still received, if the detector is valid for the request.
+    /// Detectors dispatched for a request are collected by its [`TextContentDetectionTask`].
+    pub fn dispatch_content_detector(
+        &self,
+        dispatched: &mut DispatchedDetections,
+        headers: &HeaderMap,
+        content: &str,
+        detector_id: String,
+        params: DetectorParams,
+    ) {
+        let mut detectors = HashMap::from([(detector_id.clone(), params.clone())]);
+        let valid = !content.is_empty()
+            && validate_detector_params(&detectors).is_ok()
+            && validate_detectors(
+                &detectors,
+                &self.ctx.config.detectors,
+                &[DetectorType::TextContents],
+                true,
+            )
+            .is_ok();
+        // Detectors of disabled phases are not dispatched
+        if !valid
+            || !features::apply_phase_flag(
+                &self.ctx.features.get(),
+                DetectionPhase::Input,
+                &mut detectors,
+            )
+            .is_empty()
+        {
+            return;
+        }
+        // Alerts are held until the request is validated
+        dispatched.hold_alerts(self.ctx.alerts.as_ref());
+        let detection = content_detections(
+            self.ctx.clone(),
+            headers.clone(),
+            content.to_string(),
+            detector_id.clone(),
+            params.clone(),
+        );
+        dispatched.dispatch(detector_id, params, detection);
+    }
+
+    /// Collects detections of `pending` detectors completed within `max_wait_ms`,
+    /// storing detectors still running for retrieval by `consumer` with a continuation token.
+    async fn collect_pending(
+        &self,
+        mut pending: PendingDetections,
+        max_wait_ms: Option<u64>,
+        consumer: Option<String>,
+    ) -> Result<TextContentDetectionResult, Error> {
+        let mut detections = pending
+            .collect(max_wait_ms.map(Duration::from_millis))
+            .await?;
+        detections.sort_by_key(|detection| detection.start);
+        unfiltered::record_input(&detections);
+        detections.apply_filter(pending.detections_filter);
+        let pending = if pending.is_complete() {
+            None
+        } else {
+            let detectors = pending.detectors();
+            info!(?detectors, "detectors pending");
+            Some(PendingDetectors {
+                detectors,
+                continuation_token: self.pending_detections.insert(pending, consumer),
+            })
+        };
         Ok(TextContentDetectionResult {
+            warnings: detections.warnings(),
             detections: detections.into(),
+            pending,
         })
     }
 }
 
+/// Returns detections of `content` by `detector_id`.
+async fn content_detections(
+    ctx: Arc<Context>,
+    headers: HeaderMap,
+    content: String,
+    detector_id: String,
+    params: DetectorParams,
+) -> Result<Detections, Error> {
+    let detectors = HashMap::from([(detector_id, params)]);
+    common::text_contents_detections(ctx, headers, detectors, 0, vec![(0, content)])
+        .await
+        .map(|(_, detections)| detections)
+}
+
 #[derive(Debug)]
 pub struct TextContentDetectionTask {
     /// Trace ID
@@ -76,6 +246,14 @@ pub struct TextContentDetectionTask {
     pub detectors: HashMap<String, DetectorParams>,
     /// Headers
     pub headers: HeaderMap,
+    /// Filter for detections returned in responses
+    pub detections_filter: DetectionsFilter,
+    /// Maximum time in milliseconds to wait for detectors
+    pub max_wait_ms: Option<u64>,
+    /// Detectors dispatched while the request body was received
+    pub dispatched: Option<DispatchedDetections>,
+    /// Name of the authenticated consumer of the request, if any
+    pub consumer: Option<String>,
 }
 
 impl TextContentDetectionTask {
@@ -83,12 +261,42 @@ impl TextContentDetectionTask {
         trace_id: TraceId,
         request: TextContentDetectionHttpRequest,
         headers: HeaderMap,
+        detections_filter: DetectionsFilter,
     ) -> Self {
         Self {
             trace_id,
             content: request.content,
             detectors: request.detectors,
             headers,
+            detections_filter,
+            max_wait_ms: request.max_wait_ms,
+            dispatched: None,
+            consumer: None,
         }
     }
+
+    /// Sets the name of the authenticated consumer of the request.
+    pub fn with_consumer(mut self, consumer: Option<String>) -> Self {
+        self.consumer = consumer;
+        self
+    }
+
+    /// Sets detectors dispatched while the request body was received.
+    pub fn with_dispatched(mut self, dispatched: DispatchedDetections) -> Self {
+        self.dispatched = Some(dispatched);
+        self
+    }
+}
+
+/// Retrieval of detections pending when a time-boxed text content detection request returned.
+#[derive(Debug)]
+pub struct PendingContentDetectionTask {
+    /// Trace ID
+    pub trace_id: TraceId,
+    /// Continuation token of the pending detections
+    pub continuation_token: String,
+    /// Maximum time in milliseconds to wait for pending detectors, waits for all if unset
+    pub max_wait_ms: Option<u64>,
+    /// Name of the authenticated consumer of the request, if any
+    pub consumer: Option<String>,
 }
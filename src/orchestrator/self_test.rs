@@ -0,0 +1,457 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Self-test of configured services, sending canned inputs to each service and
+//! verifying responses.
+use std::fmt::Display;
+
+use futures::future::join_all;
+use http::HeaderMap;
+use serde::Serialize;
+
+use super::{
+    Context, Error,
+    common::{
+        chat_completion, chunk,
+        conformance::{PROBE_TEXT, validate_chunks},
+        detect_text_chat, detect_text_contents, detect_text_context, detect_text_generation,
+        tokenize,
+    },
+    types::{Chunk, Detections},
+};
+use crate::{
+    clients::{
+        GenerationClient, TextContentsDetectorClient,
+        chunker::ChunkerClient,
+        detector::{
+            ContextType, TextChatDetectorClient, TextContextDocDetectorClient,
+            TextGenerationDetectorClient,
+        },
+        openai::{self, OpenAiClient},
+    },
+    config::DetectorType,
+    health::HealthStatus,
+    models::DetectorParams,
+};
+
+/// Prompt sent to generation services and text generation detectors.
+const PROMPT: &str = "Say hello.";
+
+/// Outcome of a self-test check.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+}
+
+/// Result of a self-test check of a service.
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    /// Client ID, e.g. a chunker or detector ID
+    pub id: String,
+    /// Check performed, e.g. `chunk` or `text_contents`
+    pub check: &'static str,
+    pub status: CheckStatus,
+    /// Reason of failures
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Results of a self-test, in order of generation, chat generation, chunkers and detectors.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SelfTestReport {
+    pub results: Vec<CheckResult>,
+}
+
+impl SelfTestReport {
+    /// Returns `true` if all checks passed.
+    pub fn passed(&self) -> bool {
+        self.results
+            .iter()
+            .all(|result| result.status == CheckStatus::Pass)
+    }
+}
+
+impl Display for SelfTestReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id_width = self
+            .results
+            .iter()
+            .map(|result| result.id.len())
+            .chain([2])
+            .max()
+            .unwrap();
+        writeln!(f, "{:id_width$}  {:16}  RESULT", "ID", "CHECK")?;
+        for result in &self.results {
+            let status = match result.status {
+                CheckStatus::Pass => "PASS",
+                CheckStatus::Fail => "FAIL",
+            };
+            write!(f, "{:id_width$}  {:16}  {status}", result.id, result.check)?;
+            if let Some(reason) = &result.reason {
+                write!(f, "  {reason}")?;
+            }
+            writeln!(f)?;
+        }
+        let failed = self
+            .results
+            .iter()
+            .filter(|result| result.status == CheckStatus::Fail)
+            .count();
+        write!(f, "{} checks, {failed} failed", self.results.len())
+    }
+}
+
+/// Service to check.
+enum Target<'a> {
+    Generation,
+    ChatGeneration,
+    Chunker(&'a str),
+    Detector(&'a str, &'a DetectorType),
+}
+
+/// Sends canned inputs to each configured service, verifying response shapes and offsets.
+///
+/// Generation services are health checked, or sent requests for `model_id` if set.
+pub async fn run(ctx: &Context, model_id: Option<&str>) -> SelfTestReport {
+    let mut targets = Vec::new();
+    if ctx.config.generation.is_some() {
+        targets.push(Target::Generation);
+    }
+    if ctx.config.chat_generation.is_some() {
+        targets.push(Target::ChatGeneration);
+    }
+    let mut chunker_ids = ctx.config.chunkers.iter().flatten().collect::<Vec<_>>();
+    chunker_ids.sort_by_key(|(chunker_id, _)| *chunker_id);
+    targets.extend(
+        chunker_ids
+            .into_iter()
+            .map(|(chunker_id, _)| Target::Chunker(chunker_id)),
+    );
+    let mut detectors = ctx.config.detectors.iter().collect::<Vec<_>>();
+    detectors.sort_by_key(|(detector_id, _)| *detector_id);
+    targets.extend(
+        detectors
+            .into_iter()
+            .map(|(detector_id, detector)| Target::Detector(detector_id, &detector.r#type)),
+    );
+    let results = join_all(targets.iter().map(|target| check(ctx, target, model_id))).await;
+    SelfTestReport { results }
+}
+
+async fn check(ctx: &Context, target: &Target<'_>, model_id: Option<&str>) -> CheckResult {
+    let (id, check, result) = match *target {
+        Target::Generation => match model_id {
+            Some(model_id) => (
+                "generation",
+                "tokenize",
+                check_tokenize(ctx, model_id).await,
+            ),
+            None => (
+                "generation",
+                "health",
+                check_health(ctx, "generation").await,
+            ),
+        },
+        Target::ChatGeneration => match model_id {
+            Some(model_id) => (
+                "chat_generation",
+                "chat_completion",
+                check_chat_completion(ctx, model_id).await,
+            ),
+            None => (
+                "chat_generation",
+                "health",
+                check_health(ctx, "chat_generation").await,
+            ),
+        },
+        Target::Chunker(chunker_id) => (chunker_id, "chunk", check_chunker(ctx, chunker_id).await),
+        Target::Detector(detector_id, detector_type) => {
+            let check = match detector_type {
+                DetectorType::TextContents => "text_contents",
+                DetectorType::TextGeneration => "text_generation",
+                DetectorType::TextChat => "text_chat",
+                DetectorType::TextContextDoc => "text_context_doc",
+            };
+            let result = check_detector(ctx, detector_id, detector_type)
+                .await
+                .and_then(|detections| validate_offsets(PROBE_TEXT, &detections));
+            (detector_id, check, result)
+        }
+    };
+    let (status, reason) = match result {
+        Ok(()) => (CheckStatus::Pass, None),
+        Err(reason) => (CheckStatus::Fail, Some(reason)),
+    };
+    CheckResult {
+        id: id.into(),
+        check,
+        status,
+        reason,
+    }
+}
+
+async fn check_health(ctx: &Context, client_id: &str) -> Result<(), String> {
+    let client = ctx
+        .clients
+        .get(client_id)
+        .ok_or_else(|| format!("client `{client_id}` not found"))?;
+    let result = client.health().await;
+    match result.status {
+        HealthStatus::Healthy => Ok(()),
+        _ => Err(format!("health check returned {result}")),
+    }
+}
+
+async fn check_tokenize(ctx: &Context, model_id: &str) -> Result<(), String> {
+    let client = ctx
+        .clients
+        .get_as::<GenerationClient>("generation")
+        .ok_or("generation client not found")?;
+    let (token_count, _) = tokenize(client, HeaderMap::new(), model_id.into(), PROMPT.into())
+        .await
+        .map_err(|error| error.to_string())?;
+    if token_count == 0 {
+        return Err("no tokens returned".into());
+    }
+    Ok(())
+}
+
+async fn check_chat_completion(ctx: &Context, model_id: &str) -> Result<(), String> {
+    let client = ctx
+        .clients
+        .get_as::<OpenAiClient>("chat_generation")
+        .ok_or("chat generation client not found")?;
+    let request = openai::ChatCompletionsRequest {
+        model: model_id.into(),
+        messages: vec![openai::Message {
+            content: Some(openai::Content::Text(PROMPT.into())),
+            ..Default::default()
+        }],
+        extra: [("max_tokens".to_string(), 1.into())].into_iter().collect(),
+        ..Default::default()
+    };
+    let response = chat_completion(client, HeaderMap::new(), request)
+        .await
+        .map_err(|error| error.to_string())?;
+    match response {
+        openai::ChatCompletionsResponse::Unary(completion) if !completion.choices.is_empty() => {
+            Ok(())
+        }
+        openai::ChatCompletionsResponse::Unary(_) => Err("no choices returned".into()),
+        openai::ChatCompletionsResponse::Streaming(_) => {
+            Err("streaming response returned for unary request".into())
+        }
+    }
+}
+
+async fn check_chunker(ctx: &Context, chunker_id: &str) -> Result<(), String> {
+    let client = ctx
+        .clients
+        .get_as::<ChunkerClient>(chunker_id)
+        .ok_or_else(|| format!("client `{chunker_id}` not found"))?;
+    let chunks = chunk(client, chunker_id.into(), PROBE_TEXT.into())
+        .await
+        .map_err(|error| error.to_string())?;
+    validate_chunks(PROBE_TEXT, &chunks)
+}
+
+/// Sends the probe text to a detector as input of its type.
+async fn check_detector(
+    ctx: &Context,
+    detector_id: &str,
+    detector_type: &DetectorType,
+) -> Result<Detections, String> {
+    let not_found = || format!("client `{detector_id}` not found");
+    let headers = HeaderMap::new();
+    let params = DetectorParams::new();
+    let result = match detector_type {
+        DetectorType::TextContents => {
+            let client = ctx
+                .clients
+                .get_as::<TextContentsDetectorClient>(detector_id)
+                .ok_or_else(not_found)?;
+            let chunk = Chunk {
+                start: 0,
+                end: PROBE_TEXT.chars().count(),
+                text: PROBE_TEXT.into(),
+                ..Default::default()
+            };
+            let partial_results = ctx
+                .config
+                .detector(detector_id)
+                .map(|detector| detector.partial_results)
+                .unwrap_or_default();
+            detect_text_contents(
+                client,
+                headers,
+                detector_id.into(),
+                params,
+                vec![chunk].into(),
+                true,
+                partial_results,
+            )
+            .await
+        }
+        DetectorType::TextGeneration => {
+            let client = ctx
+                .clients
+                .get_as::<TextGenerationDetectorClient>(detector_id)
+                .ok_or_else(not_found)?;
+            detect_text_generation(
+                client,
+                headers,
+                detector_id.into(),
+                params,
+                PROMPT.into(),
+                PROBE_TEXT.into(),
+            )
+            .await
+        }
+        DetectorType::TextChat => {
+            let client = ctx
+                .clients
+                .get_as::<TextChatDetectorClient>(detector_id)
+                .ok_or_else(not_found)?;
+            let messages = vec![openai::Message {
+                content: Some(openai::Content::Text(PROBE_TEXT.into())),
+                ..Default::default()
+            }];
+            detect_text_chat(
+                client,
+                headers,
+                detector_id.into(),
+                params,
+                messages,
+                vec![],
+            )
+            .await
+        }
+        DetectorType::TextContextDoc => {
+            let client = ctx
+                .clients
+                .get_as::<TextContextDocDetectorClient>(detector_id)
+                .ok_or_else(not_found)?;
+            detect_text_context(
+                client,
+                headers,
+                detector_id.into(),
+                params,
+                PROBE_TEXT.into(),
+                ContextType::Document,
+                vec![PROMPT.into()],
+            )
+            .await
+        }
+    };
+    result.map_err(|error: Error| error.to_string())
+}
+
+/// Validates that detection offsets, if any, are within bounds of `text` and that
+/// detected text matches the text at its offsets.
+fn validate_offsets(text: &str, detections: &Detections) -> Result<(), String> {
+    let len = text.chars().count();
+    for (index, detection) in detections.iter().enumerate() {
+        let (Some(start), Some(end)) = (detection.start, detection.end) else {
+            continue;
+        };
+        if start > end || end > len {
+            return Err(format!(
+                "detection {index} offsets {start}..{end} are out of bounds of text length {len}"
+            ));
+        }
+        let expected = text
+            .chars()
+            .skip(start)
+            .take(end - start)
+            .collect::<String>();
+        if detection
+            .text
+            .as_ref()
+            .is_some_and(|detected| *detected != expected)
+        {
+            return Err(format!(
+                "detection {index} text {:?} does not match text {expected:?} at {start}..{end}",
+                detection.text.as_deref().unwrap_or_default()
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orchestrator::types::Detection;
+
+    fn detections(spans: &[(usize, usize, &str)]) -> Detections {
+        spans
+            .iter()
+            .map(|&(start, end, text)| Detection {
+                start: Some(start),
+                end: Some(end),
+                text: Some(text.into()),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_offsets() {
+        assert!(validate_offsets(PROBE_TEXT, &detections(&[(0, 4, "This")])).is_ok());
+        assert!(validate_offsets(PROBE_TEXT, &Detections::new()).is_ok());
+        assert!(
+            validate_offsets(PROBE_TEXT, &detections(&[(0, 5, "This")]))
+                .unwrap_err()
+                .contains("does not match")
+        );
+        assert!(
+            validate_offsets(PROBE_TEXT, &detections(&[(0, 500, "This")]))
+                .unwrap_err()
+                .contains("out of bounds")
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let report = SelfTestReport {
+            results: vec![
+                CheckResult {
+                    id: "sentence".into(),
+                    check: "chunk",
+                    status: CheckStatus::Pass,
+                    reason: None,
+                },
+                CheckResult {
+                    id: "hap".into(),
+                    check: "text_contents",
+                    status: CheckStatus::Fail,
+                    reason: Some("connection refused".into()),
+                },
+            ],
+        };
+        assert!(!report.passed());
+        assert_eq!(
+            report.to_string(),
+            "ID        CHECK             RESULT\n\
+             sentence  chunk             PASS\n\
+             hap       text_contents     FAIL  connection refused\n\
+             2 checks, 1 failed"
+        );
+    }
+}
@@ -0,0 +1,212 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Summary of configured services, reported on start-up.
+use serde::Serialize;
+
+use crate::{
+    clients::{chunker, detector, nlp, openai, tgis},
+    config::{DetectorType, GenerationProvider, OrchestratorConfig, ServiceConfig, Tls},
+    health::{HealthCheckCache, HealthCheckResult},
+};
+
+/// Summary of configured services.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StartupReport {
+    /// Orchestrator version
+    pub version: String,
+    /// Configured services, in order of generation, chat generation, chunkers and detectors
+    pub services: Vec<ServiceReport>,
+}
+
+/// Summary of a configured service.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceReport {
+    /// Client ID, e.g. a chunker or detector ID
+    pub id: String,
+    /// Service type, e.g. `chunker` or `text_contents` for detectors
+    pub r#type: String,
+    /// Endpoint requests are sent to, with default ports resolved
+    pub endpoint: String,
+    pub tls: TlsMode,
+    pub protocol: Protocol,
+    /// Health at start-up, if probed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<HealthCheckResult>,
+}
+
+/// TLS mode of a client.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TlsMode {
+    /// Plaintext
+    None,
+    /// Server certificate verified
+    Tls,
+    /// Server certificate verified, client certificate presented
+    MutualTls,
+    /// Server certificate not verified
+    Insecure,
+}
+
+/// Protocol of a client. gRPC is served over HTTP/2, HTTP clients negotiate
+/// HTTP/1.1 or HTTP/2 per connection.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    Grpc,
+    Http,
+}
+
+impl StartupReport {
+    /// Creates a report of the services configured in `config`, with health from `health` if probed.
+    pub fn new(config: &OrchestratorConfig, health: &HealthCheckCache) -> Self {
+        let mut services = Vec::new();
+        let mut push = |id: &str, r#type: &str, service: &ServiceConfig, default_port, protocol| {
+            services.push(ServiceReport {
+                id: id.into(),
+                r#type: r#type.into(),
+                endpoint: endpoint(service, default_port),
+                tls: tls_mode(service),
+                protocol,
+                health: health.get(id).cloned(),
+            })
+        };
+        if let Some(generation) = &config.generation {
+            let (r#type, default_port, protocol) = match generation.provider {
+                GenerationProvider::Tgis => ("tgis", tgis::DEFAULT_PORT, Protocol::Grpc),
+                GenerationProvider::Nlp => ("nlp", nlp::DEFAULT_PORT, Protocol::Grpc),
+                GenerationProvider::OpenAi => ("openai", openai::DEFAULT_PORT, Protocol::Http),
+            };
+            push(
+                "generation",
+                r#type,
+                &generation.service,
+                default_port,
+                protocol,
+            );
+        }
+        if let Some(chat_generation) = &config.chat_generation {
+            push(
+                "chat_generation",
+                "openai",
+                &chat_generation.service,
+                openai::DEFAULT_PORT,
+                Protocol::Http,
+            );
+        }
+        let mut chunkers = config.chunkers.iter().flatten().collect::<Vec<_>>();
+        chunkers.sort_by_key(|(chunker_id, _)| *chunker_id);
+        for (chunker_id, chunker) in chunkers {
+            push(
+                chunker_id,
+                "chunker",
+                &chunker.service,
+                chunker::DEFAULT_PORT,
+                Protocol::Grpc,
+            );
+        }
+        let mut detectors = config.detectors.iter().collect::<Vec<_>>();
+        detectors.sort_by_key(|(detector_id, _)| *detector_id);
+        for (detector_id, detector) in detectors {
+            let r#type = match detector.r#type {
+                DetectorType::TextContents => "text_contents",
+                DetectorType::TextGeneration => "text_generation",
+                DetectorType::TextChat => "text_chat",
+                DetectorType::TextContextDoc => "text_context_doc",
+            };
+            push(
+                detector_id,
+                r#type,
+                &detector.service,
+                detector::DEFAULT_PORT,
+                Protocol::Http,
+            );
+        }
+        Self {
+            version: env!("CARGO_PKG_VERSION").into(),
+            services,
+        }
+    }
+}
+
+/// Returns the endpoint of a service, as resolved by clients.
+fn endpoint(service: &ServiceConfig, default_port: u16) -> String {
+    let scheme = match service.tls {
+        Some(_) => "https",
+        None => "http",
+    };
+    let port = service.port.unwrap_or(default_port);
+    format!("{scheme}://{}:{port}", service.hostname)
+}
+
+fn tls_mode(service: &ServiceConfig) -> TlsMode {
+    match &service.tls {
+        None => TlsMode::None,
+        Some(Tls::Config(tls)) if tls.insecure == Some(true) => TlsMode::Insecure,
+        Some(Tls::Config(tls)) if tls.cert.is_some() && tls.key.is_some() => TlsMode::MutualTls,
+        // Named TLS configs are resolved on config load
+        Some(_) => TlsMode::Tls,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_startup_report() {
+        let s = r#"
+chunkers:
+    sentence:
+        type: sentence
+        service:
+            hostname: chunker.localhost
+detectors:
+    hap:
+        type: text_contents
+        service:
+            hostname: hap.localhost
+            port: 9000
+            tls:
+                insecure: true
+        chunker_id: sentence
+        default_threshold: 0.5
+        "#;
+        let config: OrchestratorConfig = serde_yml::from_str(s).unwrap();
+        let report = StartupReport::new(&config, &HealthCheckCache::default());
+        assert_eq!(
+            serde_json::to_value(&report.services).unwrap(),
+            serde_json::json!([
+                {
+                    "id": "sentence",
+                    "type": "chunker",
+                    "endpoint": "http://chunker.localhost:8085",
+                    "tls": "none",
+                    "protocol": "grpc",
+                },
+                {
+                    "id": "hap",
+                    "type": "text_contents",
+                    "endpoint": "https://hap.localhost:9000",
+                    "tls": "insecure",
+                    "protocol": "http",
+                },
+            ])
+        );
+    }
+}
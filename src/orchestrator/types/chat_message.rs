@@ -14,7 +14,10 @@
  limitations under the License.
 
 */
-use crate::clients::openai;
+use crate::{
+    clients::openai::{self, MessageField},
+    config::ReasoningDetection,
+};
 
 /// A chat message.
 #[derive(Default, Clone, Debug, PartialEq)]
@@ -26,8 +29,60 @@ pub struct ChatMessage<'a> {
     pub role: Option<&'a openai::Role>,
     /// The text contents of the message.
     pub text: Option<&'a str>,
+    /// The content parts of the message, if content is an array of content parts.
+    pub content_parts: Option<&'a [openai::ContentPart]>,
     /// The refusal message.
     pub refusal: Option<&'a str>,
+    /// The reasoning content.
+    pub reasoning_content: Option<&'a str>,
+}
+
+impl<'a> ChatMessage<'a> {
+    /// Returns the text segments of the message to run detection on,
+    /// allowing detection spans to be mapped back to the message.
+    pub fn segments(&self, reasoning_detection: ReasoningDetection) -> Vec<MessageSegment<'a>> {
+        let (content, reasoning) = reasoning_detection.fields(self.reasoning_content.is_some());
+        let mut segments = Vec::new();
+        if let Some(reasoning_content) = self.reasoning_content.filter(|_| reasoning) {
+            segments.push(MessageSegment {
+                field: MessageField::ReasoningContent,
+                part_index: None,
+                text: reasoning_content,
+            });
+        }
+        if content {
+            match (self.content_parts, self.text) {
+                (Some(parts), _) => {
+                    segments.extend(parts.iter().enumerate().filter_map(|(index, part)| {
+                        (part.r#type == openai::ContentType::Text).then_some(MessageSegment {
+                            field: MessageField::Content,
+                            part_index: Some(index as u32),
+                            text: part.text.as_deref()?,
+                        })
+                    }))
+                }
+                (None, Some(text)) => segments.push(MessageSegment {
+                    field: MessageField::Content,
+                    part_index: None,
+                    text,
+                }),
+                _ => (),
+            }
+        }
+        segments
+    }
+}
+
+/// A text segment of a chat message.
+/// Detection spans on the segment text are relative to the segment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageSegment<'a> {
+    /// The message field containing the text.
+    pub field: MessageField,
+    /// The index of the content part containing the text.
+    pub part_index: Option<u32>,
+    /// The text.
+    pub text: &'a str,
 }
 
 /// An iterator over chat messages.
@@ -38,19 +93,23 @@ pub trait ChatMessageIterator {
 
 impl ChatMessageIterator for openai::ChatCompletionsRequest {
     fn messages(&self) -> impl Iterator<Item = ChatMessage> {
-        self.messages.iter().enumerate().map(|(index, message)| {
-            let text = if let Some(openai::Content::Text(text)) = &message.content {
-                Some(text.as_str())
-            } else {
-                None
-            };
-            ChatMessage {
+        self.messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| ChatMessage {
                 index: index as u32,
                 role: Some(&message.role),
-                text,
+                text: match &message.content {
+                    Some(openai::Content::Text(text)) => Some(text),
+                    _ => None,
+                },
+                content_parts: match &message.content {
+                    Some(openai::Content::Array(parts)) => Some(parts),
+                    _ => None,
+                },
                 refusal: message.refusal.as_deref(),
-            }
-        })
+                reasoning_content: message.reasoning_content.as_deref(),
+            })
     }
 }
 
@@ -60,7 +119,9 @@ impl ChatMessageIterator for openai::ChatCompletion {
             index: choice.index,
             role: Some(&choice.message.role),
             text: choice.message.content.as_deref(),
+            content_parts: None,
             refusal: choice.message.refusal.as_deref(),
+            reasoning_content: choice.message.reasoning_content.as_deref(),
         })
     }
 }
@@ -71,7 +132,60 @@ impl ChatMessageIterator for openai::ChatCompletionChunk {
             index: choice.index,
             role: choice.delta.role.as_ref(),
             text: choice.delta.content.as_deref(),
+            content_parts: None,
             refusal: choice.delta.refusal.as_deref(),
+            reasoning_content: choice.delta.reasoning_content.as_deref(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_segments() {
+        let request = openai::ChatCompletionsRequest {
+            model: "test".into(),
+            messages: vec![openai::Message {
+                role: openai::Role::Assistant,
+                content: Some(openai::Content::Array(vec![
+                    "Hi there!".to_string().into(),
+                    openai::ContentPart {
+                        r#type: openai::ContentType::ImageUrl,
+                        ..Default::default()
+                    },
+                    "How are you?".to_string().into(),
+                ])),
+                reasoning_content: Some("Greet the user.".into()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let message = request.messages().next().unwrap();
+        assert_eq!(
+            message.segments(ReasoningDetection::Content),
+            vec![
+                MessageSegment {
+                    field: MessageField::Content,
+                    part_index: Some(0),
+                    text: "Hi there!",
+                },
+                MessageSegment {
+                    field: MessageField::Content,
+                    part_index: Some(2),
+                    text: "How are you?",
+                },
+            ]
+        );
+        assert_eq!(
+            message.segments(ReasoningDetection::Reasoning),
+            vec![MessageSegment {
+                field: MessageField::ReasoningContent,
+                part_index: None,
+                text: "Greet the user.",
+            }]
+        );
+        assert_eq!(message.segments(ReasoningDetection::Both).len(), 3);
+    }
+}
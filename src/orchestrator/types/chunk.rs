@@ -14,9 +14,15 @@
  limitations under the License.
 
 */
+use std::sync::Arc;
+
 use crate::pb::caikit_data_model::nlp as pb;
 
 /// A chunk.
+///
+/// Chunk text is reference-counted as chunks are broadcast to
+/// each detector subscribed to a chunker and held by batchers,
+/// so cloning a chunk does not copy its text.
 #[derive(Default, Debug, Clone)]
 pub struct Chunk {
     /// Index of message where chunk begins
@@ -28,7 +34,7 @@ pub struct Chunk {
     /// Index of char where chunk ends
     pub end: usize,
     /// Text
-    pub text: String,
+    pub text: Arc<str>,
 }
 
 impl PartialOrd for Chunk {
@@ -138,12 +144,16 @@ impl From<pb::ChunkerTokenizationStreamResult> for Chunk {
             .results
             .into_iter()
             .map(|token| token.text)
-            .collect::<String>();
+            .collect::<String>()
+            .into();
+        // Negative and inverted indices are clamped so they are safe to slice with
+        let start = value.start_index.max(0) as usize;
+        let input_start_index = value.input_start_index.max(0) as usize;
         Chunk {
-            input_start_index: value.input_start_index as usize,
-            input_end_index: value.input_end_index as usize,
-            start: value.start_index as usize,
-            end: value.processed_index as usize,
+            input_start_index,
+            input_end_index: (value.input_end_index.max(0) as usize).max(input_start_index),
+            start,
+            end: (value.processed_index.max(0) as usize).max(start),
             text,
         }
     }
@@ -157,7 +167,7 @@ impl From<pb::TokenizationResults> for Chunks {
             .map(|token| Chunk {
                 start: token.start as usize,
                 end: token.end as usize,
-                text: token.text,
+                text: token.text.into(),
                 ..Default::default()
             })
             .collect()
@@ -14,7 +14,9 @@
  limitations under the License.
 
 */
-use crate::{clients::detector, models};
+use std::collections::{HashMap, HashSet};
+
+use crate::{clients::detector, config::DetectionsFilter, models};
 
 /// A detection.
 #[derive(Default, Debug, Clone, PartialEq)]
@@ -37,6 +39,10 @@ pub struct Detection {
     pub evidence: Vec<DetectionEvidence>,
     /// Detection metadata
     pub metadata: models::Metadata,
+    /// Chunk that produced the detection, for detections on streamed text
+    pub chunk: Option<models::DetectionChunk>,
+    /// Normalized category of the detection
+    pub category: Option<String>,
 }
 
 /// Detection evidence.
@@ -60,36 +66,173 @@ pub struct Evidence {
     pub score: Option<f64>,
 }
 
+/// A span of text a detector did not analyze as it returned partial results.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialSpan {
+    /// ID of the detector
+    pub detector_id: String,
+    /// Start index of the span
+    pub start: usize,
+    /// End index of the span
+    pub end: usize,
+}
+
+impl From<&PartialSpan> for models::DetectionWarning {
+    fn from(value: &PartialSpan) -> Self {
+        models::DetectionWarning::partial_detection(&value.detector_id, value.start, value.end)
+    }
+}
+
+/// A detector substituted by a member of its detector group as it was unhealthy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectorSubstitution {
+    /// ID of the requested detector
+    pub detector_id: String,
+    /// ID of the group member that served the request
+    pub substitute_id: String,
+}
+
+impl From<&DetectorSubstitution> for models::DetectionWarning {
+    fn from(value: &DetectorSubstitution) -> Self {
+        models::DetectionWarning::detector_substituted(&value.detector_id, &value.substitute_id)
+    }
+}
+
 /// An array of detections.
 #[derive(Default, Debug, Clone)]
-pub struct Detections(Vec<Detection>);
+pub struct Detections {
+    detections: Vec<Detection>,
+    /// Spans not analyzed by detectors returning partial results
+    partial_spans: Vec<PartialSpan>,
+    /// Detectors substituted by members of their detector groups
+    substitutions: Vec<DetectorSubstitution>,
+}
 
 impl Detections {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Returns spans not analyzed by detectors returning partial results.
+    pub fn partial_spans(&self) -> &[PartialSpan] {
+        &self.partial_spans
+    }
+
+    /// Adds a span not analyzed by a detector.
+    pub fn push_partial_span(&mut self, span: PartialSpan) {
+        self.partial_spans.push(span);
+    }
+
+    /// Adds a detector substituted by a member of its detector group.
+    pub fn push_substitution(&mut self, substitution: DetectorSubstitution) {
+        self.substitutions.push(substitution);
+    }
+
+    /// Moves all detections, partial spans and substitutions of `other` into `self`.
+    pub fn append(&mut self, mut other: Detections) {
+        self.detections.append(&mut other.detections);
+        self.partial_spans.append(&mut other.partial_spans);
+        self.substitutions.append(&mut other.substitutions);
+    }
+
+    /// Removes detections excluded by `filter`.
+    pub fn apply_filter(&mut self, filter: DetectionsFilter) {
+        self.apply_filter_with_counts(filter, &mut HashMap::new());
+    }
+
+    /// Removes detections excluded by `filter`, given `counts` of detections per category
+    /// already returned, which are updated with the retained detections.
+    fn apply_filter_with_counts(
+        &mut self,
+        filter: DetectionsFilter,
+        counts: &mut HashMap<String, usize>,
+    ) {
+        if let Some(min_score) = filter.min_score {
+            self.detections
+                .retain(|detection| detection.score >= min_score);
+        }
+        if let Some(top_k) = filter.top_k {
+            let mut ranked = self.detections.iter().enumerate().collect::<Vec<_>>();
+            ranked.sort_by(|(_, a), (_, b)| b.score.total_cmp(&a.score));
+            let keep = ranked
+                .into_iter()
+                .filter_map(|(index, detection)| {
+                    let group = detection
+                        .category
+                        .as_deref()
+                        .unwrap_or(&detection.detection_type);
+                    let count = counts.entry(group.to_string()).or_default();
+                    (*count < top_k).then(|| {
+                        *count += 1;
+                        index
+                    })
+                })
+                .collect::<HashSet<_>>();
+            let mut index = 0;
+            self.detections.retain(|_| {
+                index += 1;
+                keep.contains(&(index - 1))
+            });
+        }
+    }
+
+    /// Returns warnings for spans not analyzed by detectors and substituted detectors.
+    pub fn warnings(&self) -> Vec<models::DetectionWarning> {
+        self.partial_spans
+            .iter()
+            .map(Into::into)
+            .chain(self.substitutions.iter().map(Into::into))
+            .collect()
+    }
+}
+
+/// Filter of detections returned over the messages of a streaming response.
+///
+/// Detections returned per category are bounded by `top_k` over the whole response rather
+/// than per message, keeping the highest scores of each message within the remaining
+/// budget of its categories.
+#[derive(Debug, Clone, Default)]
+pub struct StreamingDetectionsFilter {
+    filter: DetectionsFilter,
+    /// Number of detections returned per category
+    counts: HashMap<String, usize>,
+}
+
+impl StreamingDetectionsFilter {
+    pub fn new(filter: DetectionsFilter) -> Self {
+        Self {
+            filter,
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Removes detections of a message excluded by the filter.
+    pub fn apply(&mut self, detections: &mut Detections) {
+        detections.apply_filter_with_counts(self.filter, &mut self.counts);
+    }
 }
 
 impl std::ops::Deref for Detections {
     type Target = Vec<Detection>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.detections
     }
 }
 
 impl std::ops::DerefMut for Detections {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.detections
     }
 }
 
+/// Iterates over detections, discarding partial spans and substitutions.
 impl IntoIterator for Detections {
     type Item = Detection;
     type IntoIter = <Vec<Detection> as IntoIterator>::IntoIter;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.0.into_iter()
+        self.detections.into_iter()
     }
 }
 
@@ -105,7 +248,11 @@ impl FromIterator<Detection> for Detections {
 
 impl From<Vec<Detection>> for Detections {
     fn from(value: Vec<Detection>) -> Self {
-        Self(value)
+        Self {
+            detections: value,
+            partial_spans: Vec::new(),
+            substitutions: Vec::new(),
+        }
     }
 }
 
@@ -126,6 +273,8 @@ impl From<detector::ContentAnalysisResponse> for Detection {
                 .map(|vs| vs.into_iter().map(Into::into).collect())
                 .unwrap_or_default(),
             metadata: value.metadata,
+            chunk: value.chunk,
+            category: value.category,
         }
     }
 }
@@ -202,6 +351,8 @@ impl From<models::DetectionResult> for Detection {
                 .map(|vs| vs.into_iter().map(Into::into).collect())
                 .unwrap_or_default(),
             metadata: value.metadata,
+            chunk: None,
+            category: value.category,
         }
     }
 }
@@ -217,6 +368,7 @@ impl From<Detection> for models::DetectionResult {
             score: value.score,
             evidence,
             metadata: value.metadata,
+            category: value.category,
         }
     }
 }
@@ -244,6 +396,8 @@ impl From<Detection> for models::TokenClassificationResult {
             detector_id: value.detector_id,
             score: value.score,
             token_count: None,
+            chunk: value.chunk,
+            category: value.category,
         }
     }
 }
@@ -268,6 +422,8 @@ impl From<Detection> for detector::ContentAnalysisResponse {
             score: value.score,
             evidence,
             metadata: value.metadata,
+            chunk: value.chunk,
+            category: value.category,
         }
     }
 }
@@ -277,3 +433,85 @@ impl From<Detections> for Vec<detector::ContentAnalysisResponse> {
         value.into_iter().map(Into::into).collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection(detection_type: &str, category: Option<&str>, score: f64) -> Detection {
+        Detection {
+            detection_type: detection_type.into(),
+            category: category.map(Into::into),
+            score,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_apply_filter() {
+        let detections: Detections = vec![
+            detection("hap", Some("hate"), 0.6),
+            detection("pii", None, 0.9),
+            detection("hap", Some("hate"), 0.95),
+            detection("pii", None, 0.4),
+            detection("toxicity", Some("hate"), 0.8),
+        ]
+        .into();
+
+        let mut filtered = detections.clone();
+        filtered.apply_filter(DetectionsFilter::default());
+        assert_eq!(filtered.len(), 5);
+
+        let mut filtered = detections.clone();
+        filtered.apply_filter(DetectionsFilter {
+            min_score: Some(0.7),
+            top_k: None,
+        });
+        let scores = filtered.iter().map(|d| d.score).collect::<Vec<_>>();
+        assert_eq!(scores, [0.9, 0.95, 0.8]);
+
+        // Top-k is per category, preserving order
+        let mut filtered = detections.clone();
+        filtered.apply_filter(DetectionsFilter {
+            min_score: None,
+            top_k: Some(1),
+        });
+        let scores = filtered.iter().map(|d| d.score).collect::<Vec<_>>();
+        assert_eq!(scores, [0.9, 0.95]);
+    }
+
+    #[test]
+    fn test_streaming_detections_filter() {
+        let mut filter = StreamingDetectionsFilter::new(DetectionsFilter {
+            min_score: Some(0.5),
+            top_k: Some(2),
+        });
+
+        let mut detections: Detections = vec![
+            detection("hap", Some("hate"), 0.6),
+            detection("pii", None, 0.4),
+            detection("hap", Some("hate"), 0.95),
+            detection("hap", Some("hate"), 0.7),
+        ]
+        .into();
+        filter.apply(&mut detections);
+        let scores = detections.iter().map(|d| d.score).collect::<Vec<_>>();
+        assert_eq!(scores, [0.95, 0.7]);
+
+        // Top-k is per response: the `hate` budget is spent by the previous message
+        let mut detections: Detections = vec![
+            detection("hap", Some("hate"), 0.99),
+            detection("pii", None, 0.8),
+            detection("pii", None, 0.9),
+            detection("pii", None, 0.6),
+        ]
+        .into();
+        filter.apply(&mut detections);
+        let scores = detections.iter().map(|d| d.score).collect::<Vec<_>>();
+        assert_eq!(scores, [0.8, 0.9]);
+
+        let mut detections: Detections = vec![detection("pii", None, 0.7)].into();
+        filter.apply(&mut detections);
+        assert!(detections.is_empty());
+    }
+}
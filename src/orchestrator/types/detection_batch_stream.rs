@@ -16,7 +16,7 @@
 */
 use futures::{Stream, StreamExt, stream};
 use tokio::sync::{mpsc, oneshot};
-use tracing::{debug, error};
+use tracing::{Instrument, debug, error, info_span};
 
 use super::{Chunk, DetectionBatcher, DetectionStream, Detections, DetectorId, InputId};
 use crate::orchestrator::Error;
@@ -41,7 +41,8 @@ where
         let mut stream_set = stream::select_all(streams);
         // Create batcher manager, an actor to manage the batcher instead of using locks
         let batcher_manager = DetectionBatcherManagerHandle::new(batcher);
-        // Spawn task to receive detections and process batches
+        // Spawn task to receive detections and process batches, aggregating detections
+        // of all detectors in a span of the current span
         tokio::spawn(async move {
             let mut stream_completed = false;
             loop {
@@ -70,9 +71,18 @@ where
                         }
                     },
                     // Pop batches and send them to batch channel
-                    Some(batch) = batcher_manager.pop() => {
-                        debug!(?batch, "sending batch to batch channel");
-                        let _ = batch_tx.send(Ok(batch)).await;
+                    Some(result) = batcher_manager.pop() => {
+                        match result {
+                            Ok(batch) => {
+                                debug!(?batch, "sending batch to batch channel");
+                                let _ = batch_tx.send(Ok(batch)).await;
+                            },
+                            Err(error) => {
+                                error!(?error, "sending batcher error to batch channel");
+                                let _ = batch_tx.send(Err(error)).await;
+                                break;
+                            },
+                        }
                     },
                     // Terminate task when stream is completed and batcher state is empty
                     empty = batcher_manager.is_empty(), if stream_completed => {
@@ -83,7 +93,7 @@ where
                 }
             }
             debug!("detection batch stream task has completed");
-        });
+        }.instrument(info_span!("detection_batching")));
 
         Self { batch_rx }
     }
@@ -111,7 +121,7 @@ enum DetectionBatcherMessage<Batch> {
         detections: Detections,
     },
     Pop {
-        response_tx: oneshot::Sender<Option<Batch>>,
+        response_tx: oneshot::Sender<Option<Result<Batch, Error>>>,
     },
     IsEmpty {
         response_tx: oneshot::Sender<bool>,
@@ -146,7 +156,10 @@ where
                 }
                 DetectionBatcherMessage::Pop { response_tx } => {
                     debug!("handling pop request");
-                    let batch = self.batcher.pop_batch();
+                    let batch = match self.batcher.take_error() {
+                        Some(error) => Some(Err(error)),
+                        None => self.batcher.pop_batch().map(Ok),
+                    };
                     debug!(?batch, "sending pop response");
                     let _ = response_tx.send(batch);
                 }
@@ -199,8 +212,9 @@ where
             .await;
     }
 
-    /// Removes the next batch of detections from the batcher, if ready.
-    pub async fn pop(&self) -> Option<B::Batch> {
+    /// Removes the next batch of detections from the batcher, if ready,
+    /// or an error that prevents further batches.
+    pub async fn pop(&self) -> Option<Result<B::Batch, Error>> {
         let (response_tx, response_rx) = oneshot::channel();
         let _ = self
             .tx
@@ -22,6 +22,7 @@ pub mod max_processed_index;
 pub use max_processed_index::*;
 
 use super::{Chunk, Detections, DetectorId, InputId};
+use crate::orchestrator::Error;
 
 /// A detection batcher.
 /// Implements pluggable batching logic for a [`DetectionBatchStream`].
@@ -42,4 +43,9 @@ pub trait DetectionBatcher: std::fmt::Debug + Clone + Send + 'static {
 
     /// Returns `true` if the batcher state is empty.
     fn is_empty(&self) -> bool;
+
+    /// Removes an error that prevents further batches, if any.
+    fn take_error(&mut self) -> Option<Error> {
+        None
+    }
 }
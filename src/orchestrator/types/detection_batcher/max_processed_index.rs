@@ -16,7 +16,13 @@
 */
 use std::collections::{BTreeMap, btree_map};
 
+use tracing::warn;
+
 use super::{Chunk, DetectionBatcher, Detections, DetectorId, InputId};
+use crate::{
+    config::{StreamOrdering, StreamOverflowPolicy},
+    orchestrator::Error,
+};
 
 /// A batcher based on the original "max processed index"
 /// aggregator.
@@ -32,11 +38,22 @@ use super::{Chunk, DetectionBatcher, Detections, DetectorId, InputId};
 /// detections from 3 detectors are received for chunk-2,
 /// and so on.
 ///
+/// The number of chunks buffered behind a pending chunk can be
+/// bounded with [`StreamOrdering`]. Batches are never returned
+/// out of order: detections for a chunk at or before the last
+/// returned chunk are dropped.
+///
 /// This batcher requires that all detectors use the same chunker.
 #[derive(Debug, Clone)]
 pub struct MaxProcessedIndexBatcher {
     n_detectors: usize,
     state: BTreeMap<Chunk, Vec<Detections>>,
+    ordering: StreamOrdering,
+    /// Last returned chunk
+    last_chunk: Option<Chunk>,
+    /// Whether a partial batch should be returned to make room in the buffer
+    flush: bool,
+    error: Option<Error>,
 }
 
 impl MaxProcessedIndexBatcher {
@@ -44,6 +61,45 @@ impl MaxProcessedIndexBatcher {
         Self {
             n_detectors,
             state: BTreeMap::default(),
+            ordering: StreamOrdering::default(),
+            last_chunk: None,
+            flush: false,
+            error: None,
+        }
+    }
+
+    /// Bounds the number of buffered chunks.
+    pub fn with_ordering(mut self, ordering: StreamOrdering) -> Self {
+        self.ordering = ordering;
+        self
+    }
+
+    /// Applies the overflow policy if the buffer is full.
+    fn check_overflow(&mut self) {
+        let Some(max_buffered_chunks) = self.ordering.max_buffered_chunks else {
+            return;
+        };
+        // The first chunk is pending, the rest are buffered behind it
+        if self.state.len() <= max_buffered_chunks + 1 {
+            return;
+        }
+        match self.ordering.overflow {
+            StreamOverflowPolicy::Error => {
+                self.error.get_or_insert_with(|| {
+                    Error::Other(format!(
+                        "stream ordering buffer exceeded {max_buffered_chunks} chunks"
+                    ))
+                });
+            }
+            StreamOverflowPolicy::Flush => {
+                if let Some((chunk, _)) = self.state.first_key_value() {
+                    warn!(
+                        ?chunk,
+                        "stream ordering buffer is full, flushing pending chunk"
+                    );
+                }
+                self.flush = true;
+            }
         }
     }
 }
@@ -58,6 +114,13 @@ impl DetectionBatcher for MaxProcessedIndexBatcher {
         chunk: Chunk,
         detections: Detections,
     ) {
+        if self.last_chunk.as_ref().is_some_and(|last| chunk <= *last) {
+            warn!(
+                ?chunk,
+                "dropping detections received after chunk was returned"
+            );
+            return;
+        }
         match self.state.entry(chunk) {
             btree_map::Entry::Vacant(entry) => {
                 // New chunk, insert entry
@@ -68,17 +131,22 @@ impl DetectionBatcher for MaxProcessedIndexBatcher {
                 entry.get_mut().push(detections);
             }
         }
+        self.check_overflow();
     }
 
     fn pop_batch(&mut self) -> Option<Self::Batch> {
-        // Check if we have all detections for the next chunk
-        if self
-            .state
-            .first_key_value()
-            .is_some_and(|(_, detections)| detections.len() == self.n_detectors)
+        // Check if we have all detections for the next chunk, or if it is flushed
+        if self.flush
+            || self
+                .state
+                .first_key_value()
+                .is_some_and(|(_, detections)| detections.len() == self.n_detectors)
         {
             // We have all detections for the chunk, remove and return it.
             if let Some((chunk, detections)) = self.state.pop_first() {
+                self.flush = false;
+                self.last_chunk = Some(chunk.clone());
+                self.check_overflow();
                 let detections = detections.into_iter().flatten().collect();
                 return Some((chunk, detections));
             }
@@ -89,6 +157,10 @@ impl DetectionBatcher for MaxProcessedIndexBatcher {
     fn is_empty(&self) -> bool {
         self.state.is_empty()
     }
+
+    fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
 }
 
 #[cfg(test)]
@@ -392,4 +464,79 @@ mod test {
 
         Ok(())
     }
+
+    fn sentence_chunks(n: usize) -> Vec<Chunk> {
+        (0..n)
+            .map(|i| Chunk {
+                input_start_index: i,
+                input_end_index: i,
+                start: i * 10,
+                end: (i + 1) * 10,
+                text: "sentence. ".into(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_batcher_with_buffer_overflow_error() {
+        let chunks = sentence_chunks(4);
+        let mut batcher = MaxProcessedIndexBatcher::new(2).with_ordering(StreamOrdering {
+            max_buffered_chunks: Some(2),
+            overflow: StreamOverflowPolicy::Error,
+        });
+
+        // The hap detector completes later chunks while the pii detector lags
+        for chunk in &chunks[..3] {
+            batcher.push(0, "hap".into(), chunk.clone(), Detections::default());
+        }
+        assert!(batcher.pop_batch().is_none());
+        assert!(batcher.take_error().is_none());
+
+        // A third chunk buffered behind the pending chunk overflows the buffer
+        batcher.push(0, "hap".into(), chunks[3].clone(), Detections::default());
+        assert!(batcher.take_error().is_some());
+    }
+
+    #[test]
+    fn test_batcher_with_buffer_overflow_flush() {
+        let chunks = sentence_chunks(4);
+        let mut batcher = MaxProcessedIndexBatcher::new(2).with_ordering(StreamOrdering {
+            max_buffered_chunks: Some(2),
+            overflow: StreamOverflowPolicy::Flush,
+        });
+        let detections = |detector_id: &str| -> Detections {
+            vec![Detection {
+                detector_id: Some(detector_id.into()),
+                ..Default::default()
+            }]
+            .into()
+        };
+
+        // The hap detector completes all chunks while the pii detector lags
+        for chunk in &chunks {
+            batcher.push(0, "hap".into(), chunk.clone(), detections("hap"));
+        }
+
+        // The pending chunk is flushed with hap detections only
+        let (chunk, batch) = batcher.pop_batch().unwrap();
+        assert_eq!(chunk, chunks[0]);
+        assert_eq!(batch.len(), 1);
+        assert!(batcher.take_error().is_none());
+        assert!(batcher.pop_batch().is_none());
+
+        // Late pii detections for the flushed chunk are dropped
+        batcher.push(0, "pii".into(), chunks[0].clone(), detections("pii"));
+        assert!(batcher.pop_batch().is_none());
+
+        // Remaining chunks are returned in order as they complete
+        for chunk in &chunks[1..] {
+            batcher.push(0, "pii".into(), chunk.clone(), detections("pii"));
+        }
+        for expected in &chunks[1..] {
+            let (chunk, batch) = batcher.pop_batch().unwrap();
+            assert_eq!(chunk, *expected);
+            assert_eq!(batch.len(), 2);
+        }
+        assert!(batcher.is_empty());
+    }
 }
@@ -14,7 +14,7 @@
  limitations under the License.
 
 */
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{net::SocketAddr, sync::Arc};
 
 use tokio::{net::TcpListener, signal};
 use tower_http::trace::TraceLayer;
@@ -22,41 +22,68 @@ use tracing::info;
 
 use crate::orchestrator::Orchestrator;
 
+mod admin;
+mod auth;
+mod capture;
+#[cfg(feature = "pprof")]
+mod debug;
+mod encryption;
 mod errors;
+mod extract;
+mod feedback;
+mod in_flight;
+mod jobs;
+mod jwt;
+mod metrics;
+#[cfg(feature = "playground")]
+mod playground;
+mod resumption;
 mod routes;
+mod sink;
+mod slo;
 mod tls;
+use capture::PayloadCapture;
 pub use errors::Error;
+use feedback::DetectorFeedback;
+use in_flight::InFlightRequests;
+use jobs::JobManager;
+use resumption::ResumableStreams;
+pub use tls::ServerTlsConfig;
 use tls::{configure_tls, serve_with_tls};
 
 /// Configures and runs orchestrator servers.
 pub async fn run(
     guardrails_addr: SocketAddr,
     health_addr: SocketAddr,
-    tls_cert_path: Option<PathBuf>,
-    tls_key_path: Option<PathBuf>,
-    tls_client_ca_cert_path: Option<PathBuf>,
+    tls: ServerTlsConfig,
+    admin_token: Option<String>,
     orchestrator: Orchestrator,
 ) -> Result<(tokio::task::JoinHandle<()>, tokio::task::JoinHandle<()>), Error> {
     let state = Arc::new(ServerState::new(orchestrator));
-    let health_handle = run_health_server(health_addr, state.clone()).await?;
-    let guardrails_handle = run_guardrails_server(
-        guardrails_addr,
-        tls_cert_path,
-        tls_key_path,
-        tls_client_ca_cert_path,
-        state,
-    )
-    .await?;
+    #[cfg(unix)]
+    handle_signals(state.clone())?;
+    let health_handle = run_health_server(health_addr, admin_token.clone(), state.clone()).await?;
+    let guardrails_handle = run_guardrails_server(guardrails_addr, tls, admin_token, state).await?;
     Ok((health_handle, guardrails_handle))
 }
 
 /// Configures and runs health server.
 async fn run_health_server(
     addr: SocketAddr,
+    admin_token: Option<String>,
     state: Arc<ServerState>,
 ) -> Result<tokio::task::JoinHandle<()>, Error> {
     info!("starting health server on {addr}");
-    let app = routes::health_router(state);
+    let mut app = routes::health_router(state.clone());
+    if let Some(admin_token) = admin_token {
+        info!("Enabling admin endpoints");
+        app = app.merge(admin::admin_router(admin_token.clone(), state));
+        #[cfg(feature = "pprof")]
+        {
+            info!("Enabling debug endpoints");
+            app = app.merge(debug::debug_router(admin_token));
+        }
+    }
     let listener = TcpListener::bind(&addr).await?;
     let server =
         axum::serve(listener, app.into_make_service()).with_graceful_shutdown(shutdown_signal());
@@ -68,13 +95,45 @@ async fn run_health_server(
 /// Configures and runs guardrails server.
 async fn run_guardrails_server(
     addr: SocketAddr,
-    tls_cert_path: Option<PathBuf>,
-    tls_key_path: Option<PathBuf>,
-    tls_client_ca_cert_path: Option<PathBuf>,
+    tls: ServerTlsConfig,
+    admin_token: Option<String>,
     state: Arc<ServerState>,
 ) -> Result<tokio::task::JoinHandle<()>, Error> {
     info!("starting guardrails server on {addr}");
-    let router = routes::guardrails_router(state);
+    let slo = state.orchestrator.config().slo.clone();
+    let auth = state.orchestrator.config().auth.clone();
+    let mut router = routes::guardrails_router(state.clone());
+    #[cfg(feature = "playground")]
+    {
+        info!("Enabling playground");
+        router = router.merge(playground::playground_router());
+    }
+    router = router.layer(axum::middleware::from_fn_with_state(
+        state,
+        in_flight::track_in_flight,
+    ));
+    router = router.layer(axum::middleware::from_fn(metrics::record_route_metrics));
+    if let Some(slo) = slo {
+        router = router.layer(axum::middleware::from_fn_with_state(
+            Arc::new(slo),
+            slo::record_slo,
+        ));
+    }
+    if let Some(auth) = auth {
+        info!("Enabling authentication");
+        // Within the trace layer, to label the request span with the consumer
+        router = router.layer(axum::middleware::from_fn_with_state(
+            Arc::new(auth::Authenticator::load(auth).await?),
+            auth::authenticate,
+        ));
+    }
+    if let Some(admin_token) = admin_token {
+        // Within the trace layer, to escalate tracing of the request span
+        router = router.layer(axum::middleware::from_fn_with_state(
+            Arc::new(admin_token),
+            admin::debug_trace,
+        ));
+    }
     let app = router.layer(
         TraceLayer::new_for_http()
             .make_span_with(crate::utils::trace::incoming_request_span)
@@ -83,7 +142,7 @@ async fn run_guardrails_server(
             .on_eos(crate::utils::trace::on_outgoing_eos),
     );
     let listener = TcpListener::bind(&addr).await?;
-    let tls_config = configure_tls(tls_cert_path, tls_key_path, tls_client_ca_cert_path);
+    let tls_config = configure_tls(tls);
     let shutdown_signal = shutdown_signal();
     if let Some(tls_config) = tls_config {
         Ok(serve_with_tls(app, listener, tls_config, shutdown_signal))
@@ -96,6 +155,37 @@ async fn run_guardrails_server(
     }
 }
 
+/// Spawns a task handling signals for targeted operations:
+/// - `SIGUSR1` probes client health immediately
+/// - `SIGUSR2` logs the in-flight request table
+#[cfg(unix)]
+fn handle_signals(state: Arc<ServerState>) -> Result<(), Error> {
+    use signal::unix::{SignalKind, signal};
+    let mut health_check = signal(SignalKind::user_defined1())?;
+    let mut dump_in_flight = signal(SignalKind::user_defined2())?;
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Some(_) = health_check.recv() => {
+                    info!("SIGUSR1 received: probing client health");
+                    let client_health = state.orchestrator.client_health(true).await;
+                    info!("Client health:\n{client_health}");
+                }
+                Some(_) = dump_in_flight.recv() => {
+                    let in_flight = state.in_flight.snapshot();
+                    info!(
+                        count = in_flight.len(),
+                        requests = %serde_json::to_string(&in_flight).unwrap(),
+                        "SIGUSR2 received: in-flight requests"
+                    );
+                }
+                else => break,
+            }
+        }
+    });
+    Ok(())
+}
+
 /// Shutdown signal handler
 async fn shutdown_signal() {
     let ctrl_c = async {
@@ -122,16 +212,51 @@ async fn shutdown_signal() {
 /// Server shared state
 pub struct ServerState {
     orchestrator: Orchestrator,
+    in_flight: Arc<InFlightRequests>,
+    jobs: Arc<JobManager>,
+    /// Sampled payload capture, if configured
+    capture: Option<PayloadCapture>,
+    feedback: DetectorFeedback,
+    /// Buffers of resumable streaming responses, if configured
+    resumable_streams: Option<ResumableStreams>,
 }
 
 impl ServerState {
     pub fn new(orchestrator: Orchestrator) -> Self {
-        Self { orchestrator }
+        let config = orchestrator.config();
+        let jobs = Arc::new(JobManager::new(config.jobs.clone()));
+        let capture = config.capture.clone().map(|capture| {
+            PayloadCapture::new(
+                capture,
+                &config.detectors,
+                config.auth.as_ref(),
+                config.spool.as_ref(),
+            )
+        });
+        let feedback = DetectorFeedback::new(config.feedback.as_ref(), config.spool.as_ref());
+        let resumable_streams = config.stream_resumption.as_ref().map(ResumableStreams::new);
+        Self {
+            orchestrator,
+            in_flight: Arc::new(InFlightRequests::default()),
+            jobs,
+            capture,
+            feedback,
+            resumable_streams,
+        }
+    }
+
+    /// Returns payload capture, if configured and not disabled by the `audit_logging` feature flag.
+    fn capture(&self) -> Option<&PayloadCapture> {
+        self.capture
+            .as_ref()
+            .filter(|_| self.orchestrator.features().get().audit_logging)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::path::PathBuf;
+
     use super::*;
 
     #[tokio::test]
@@ -142,8 +267,7 @@ mod tests {
         let result = run(
             guardrails_addr,
             health_addr,
-            None,
-            None,
+            ServerTlsConfig::default(),
             None,
             Orchestrator::default(),
         )
@@ -165,8 +289,11 @@ mod tests {
         let (_health_handle, guardrails_handle) = run(
             guardrails_addr,
             health_addr,
-            Some(tls_cert_path),
-            Some(tls_key_path),
+            ServerTlsConfig {
+                cert_path: Some(tls_cert_path),
+                key_path: Some(tls_key_path),
+                ..Default::default()
+            },
             None,
             Orchestrator::default(),
         )
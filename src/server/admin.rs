@@ -0,0 +1,306 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Admin endpoints, enabled when an admin token is configured.
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    extract::{Query, Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use ring::digest;
+use serde::Deserialize;
+use tracing::{Span, debug};
+
+use super::{Error, ServerState, extract::RequestJson, in_flight::InFlightRequest};
+use crate::{
+    config::FeaturesConfig,
+    health::HealthCheckCache,
+    orchestrator::common::{
+        features::FeatureFlagsUpdate,
+        scores::{SCORE_SAMPLES, ThresholdReport},
+    },
+    utils::trace::current_trace_id,
+};
+
+/// Request header enabling debug tracing for a request.
+pub const DEBUG_TRACE_HEADER: &str = "x-debug-trace";
+/// Response header carrying the trace id of a debug traced request.
+pub const TRACE_ID_HEADER: &str = "x-trace-id";
+
+/// Thresholds reported when none are requested.
+const DEFAULT_REPORT_THRESHOLDS: [f64; 9] = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThresholdReportParams {
+    /// Comma-separated thresholds to simulate block rates for
+    pub thresholds: Option<String>,
+}
+
+/// Creates admin router. All routes require the admin token.
+pub fn admin_router(admin_token: String, state: Arc<ServerState>) -> Router {
+    Router::new()
+        .route("/admin/threshold-report", get(threshold_report))
+        .route("/admin/health-check", post(health_check))
+        .route("/admin/in-flight", get(in_flight))
+        .route("/admin/features", get(features).patch(update_features))
+        .with_state(state)
+        .layer(middleware::from_fn_with_state(
+            Arc::new(admin_token),
+            require_admin_token,
+        ))
+}
+
+/// Rejects requests without a matching `Authorization: Bearer <admin_token>` header.
+pub async fn require_admin_token(
+    State(admin_token): State<Arc<String>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token_matches(token, &admin_token));
+    if authorized {
+        next.run(request).await
+    } else {
+        StatusCode::UNAUTHORIZED.into_response()
+    }
+}
+
+/// Returns whether `token` matches the admin token. Tokens are compared by SHA-256
+/// digest in constant time, so comparisons do not leak the admin token through timing.
+fn token_matches(token: &str, admin_token: &str) -> bool {
+    let token = digest::digest(&digest::SHA256, token.as_bytes());
+    let admin_token = digest::digest(&digest::SHA256, admin_token.as_bytes());
+    token
+        .as_ref()
+        .iter()
+        .zip(admin_token.as_ref())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// Escalates tracing of a request with `x-debug-trace: true` and a matching
+/// `Authorization: Bearer <admin_token>` header to debug level, returning its trace id in
+/// the `x-trace-id` response header. Other requests are unaffected.
+///
+/// The admin token is removed from debug traced requests, so it is not passed to
+/// downstream services. With authentication enabled, these requests authenticate with
+/// the `x-api-key` header.
+pub async fn debug_trace(
+    State(admin_token): State<Arc<String>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let headers = request.headers();
+    let enabled = headers
+        .get(DEBUG_TRACE_HEADER)
+        .is_some_and(|value| value == "true")
+        && headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| token_matches(token, &admin_token));
+    if !enabled {
+        return next.run(request).await;
+    }
+    request.headers_mut().remove(header::AUTHORIZATION);
+    // Enables debug events within the request span, see `utils::trace::init_tracing`
+    Span::current().record("debug_trace", true);
+    let trace_id = current_trace_id().to_string();
+    debug!(
+        %trace_id,
+        request_content_length = ?request.headers().get(header::CONTENT_LENGTH),
+        "debug tracing enabled for request"
+    );
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&trace_id) {
+        response.headers_mut().insert(TRACE_ID_HEADER, value);
+    }
+    response
+}
+
+/// Reports recent detector score distributions and the block rates
+/// different thresholds would produce.
+async fn threshold_report(
+    State(state): State<Arc<ServerState>>,
+    Query(params): Query<ThresholdReportParams>,
+) -> Result<Json<ThresholdReport>, Error> {
+    let thresholds = match params.thresholds {
+        Some(thresholds) => thresholds
+            .split(',')
+            .map(|threshold| {
+                threshold
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|threshold| (0.0..=1.0).contains(threshold))
+                    .ok_or_else(|| {
+                        Error::Validation(format!(
+                            "invalid threshold `{threshold}`: must be a number between 0 and 1"
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => DEFAULT_REPORT_THRESHOLDS.to_vec(),
+    };
+    let config = state.orchestrator.config();
+    let report = SCORE_SAMPLES.report(&thresholds, |detector_id| {
+        config
+            .detector(detector_id)
+            .map(|detector| detector.default_threshold)
+    });
+    Ok(Json(report))
+}
+
+/// Probes client health immediately, returning the results.
+async fn health_check(State(state): State<Arc<ServerState>>) -> Json<HealthCheckCache> {
+    Json(state.orchestrator.client_health(true).await)
+}
+
+/// Returns in-flight guardrails server requests, longest running first.
+async fn in_flight(State(state): State<Arc<ServerState>>) -> Json<Vec<InFlightRequest>> {
+    Json(state.in_flight.snapshot())
+}
+
+/// Returns the current feature flags.
+async fn features(State(state): State<Arc<ServerState>>) -> Json<FeaturesConfig> {
+    Json(state.orchestrator.features().get())
+}
+
+/// Enables or disables subsystems, returning the updated feature flags.
+async fn update_features(
+    State(state): State<Arc<ServerState>>,
+    RequestJson(update): RequestJson<FeatureFlagsUpdate>,
+) -> Json<FeaturesConfig> {
+    Json(state.orchestrator.features().update(update))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{orchestrator::Orchestrator, utils::test_server::serve};
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("token", "token"));
+        assert!(!token_matches("wrong", "token"));
+        assert!(!token_matches("tok", "token"));
+        assert!(!token_matches("", "token"));
+    }
+
+    #[tokio::test]
+    async fn test_debug_trace() {
+        let router = Router::new()
+            .route(
+                "/",
+                get(|headers: axum::http::HeaderMap| async move {
+                    // The admin token is not passed on
+                    headers.contains_key(header::AUTHORIZATION).to_string()
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                Arc::new("token".to_string()),
+                debug_trace,
+            ));
+        let port = serve(router).await;
+        let url = format!("http://localhost:{port}/");
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(&url)
+            .header(DEBUG_TRACE_HEADER, "true")
+            .bearer_auth("token")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(TRACE_ID_HEADER));
+        assert_eq!(response.text().await.unwrap(), "false");
+
+        // Requests without a matching admin token are traced as usual
+        for token in [None, Some("wrong")] {
+            let mut request = client.get(&url).header(DEBUG_TRACE_HEADER, "true");
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            let response = request.send().await.unwrap();
+            assert!(!response.headers().contains_key(TRACE_ID_HEADER));
+            assert_eq!(response.text().await.unwrap(), token.is_some().to_string());
+        }
+        let response = client.get(&url).bearer_auth("token").send().await.unwrap();
+        assert!(!response.headers().contains_key(TRACE_ID_HEADER));
+        assert_eq!(response.text().await.unwrap(), "true");
+    }
+
+    #[tokio::test]
+    async fn test_features() {
+        let state = Arc::new(ServerState::new(Orchestrator::default()));
+        let port = serve(admin_router("token".into(), state.clone())).await;
+        let url = format!("http://localhost:{port}/admin/features");
+        let client = reqwest::Client::new();
+
+        let response = client
+            .patch(&url)
+            .bearer_auth("token")
+            .json(&serde_json::json!({"input_detection": false}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let expected = FeaturesConfig {
+            input_detection: false,
+            ..Default::default()
+        };
+        assert_eq!(response.json::<FeaturesConfig>().await.unwrap(), expected);
+        assert_eq!(state.orchestrator.features().get(), expected);
+
+        let response = client.get(&url).bearer_auth("token").send().await.unwrap();
+        assert_eq!(response.json::<FeaturesConfig>().await.unwrap(), expected);
+
+        // Unknown flags are rejected
+        let response = client
+            .patch(&url)
+            .bearer_auth("token")
+            .json(&serde_json::json!({"pii_detection": false}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        // Requests without the admin token are rejected
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = client
+            .patch(&url)
+            .bearer_auth("wrong")
+            .json(&serde_json::json!({"input_detection": true}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(state.orchestrator.features().get(), expected);
+    }
+}
@@ -0,0 +1,347 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! API key and JWT authentication of guardrails server requests.
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ring::digest::{self, Digest};
+use tracing::{Span, debug, info};
+
+use super::{Error, jwt::JwtValidator};
+use crate::{
+    config::AuthConfig,
+    utils::secrets::{self, SecretSource},
+};
+
+/// Request header carrying an API key, as an alternative to `Authorization: Bearer <key>`.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Name of the consumer of an authenticated request, added to request extensions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiConsumer(pub String);
+
+/// Authenticates requests with API keys and, if configured, OIDC bearer tokens.
+#[derive(Debug)]
+pub struct Authenticator {
+    /// SHA-256 digests of API keys, with the name of their consumer
+    api_keys: Arc<RwLock<Vec<(String, Digest)>>>,
+    jwt: Option<JwtValidator>,
+}
+
+impl Authenticator {
+    /// Loads API keys, failing if any fails to load. Keys are re-loaded at the refresh
+    /// interval, if configured.
+    pub async fn load(config: AuthConfig) -> Result<Self, secrets::Error> {
+        for source in config.api_keys.values() {
+            source.load().await?;
+        }
+        let api_keys = Arc::new(RwLock::new(digests(&config.api_keys)));
+        if let Some(refresh_interval) = config.refresh_interval {
+            let sources = config.api_keys.clone();
+            let watched = api_keys.clone();
+            secrets::watch(
+                sources.values().cloned().collect(),
+                Duration::from_secs(refresh_interval),
+                move || {
+                    info!("API keys rotated");
+                    *watched.write().unwrap() = digests(&sources);
+                },
+            );
+        }
+        Ok(Self {
+            api_keys,
+            jwt: config.jwt.map(JwtValidator::new),
+        })
+    }
+
+    /// Returns the name of the consumer of `api_key`, if configured. Keys are compared by
+    /// SHA-256 digest, so comparisons do not leak the configured keys through timing.
+    fn consumer(&self, api_key: &[u8]) -> Option<String> {
+        let digest = digest::digest(&digest::SHA256, api_key);
+        self.api_keys
+            .read()
+            .unwrap()
+            .iter()
+            .find(|(_, key)| key.as_ref() == digest.as_ref())
+            .map(|(name, _)| name.clone())
+    }
+}
+
+/// Returns the SHA-256 digests of loaded API keys, with the name of their consumer.
+fn digests(api_keys: &HashMap<String, SecretSource>) -> Vec<(String, Digest)> {
+    api_keys
+        .iter()
+        .filter_map(|(name, source)| {
+            let key = source.cached()?;
+            Some((
+                name.clone(),
+                digest::digest(&digest::SHA256, key.trim_ascii()),
+            ))
+        })
+        .collect()
+}
+
+/// Rejects requests without a configured API key or a valid bearer token in the
+/// `Authorization: Bearer <key>` or `x-api-key` header with 401. Bearer tokens shaped
+/// like JWTs are validated as JWTs if JWT validation is configured.
+///
+/// Authenticated requests are labeled with the name of their consumer in the request
+/// span and the `ApiConsumer` request extension. Claims of JWTs are added to request
+/// extensions, and set claim headers. Claim headers sent by the client are removed, also
+/// from requests authenticated with API keys.
+pub async fn authenticate(
+    State(auth): State<Arc<Authenticator>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    // Removes claim headers sent by the client, e.g. spoofing a tenant
+    if let Some(jwt) = &auth.jwt {
+        let headers = request.headers_mut();
+        for name in jwt.claim_header_names() {
+            headers.remove(name);
+        }
+    }
+    let Some(credential) = api_key(request.headers()) else {
+        info!(
+            monotonic_counter.unauthorized_request_count = 1,
+            reason = "missing"
+        );
+        return Error::Unauthorized("missing API key".into()).into_response();
+    };
+    let jwt = auth
+        .jwt
+        .as_ref()
+        .filter(|_| credential.split('.').count() == 3);
+    let consumer = match jwt {
+        Some(jwt) => {
+            let claims = match jwt.validate(&credential).await {
+                Ok(claims) => claims,
+                Err(error) => {
+                    debug!(%error, "bearer token validation failed");
+                    info!(
+                        monotonic_counter.unauthorized_request_count = 1,
+                        reason = "invalid_token"
+                    );
+                    return Error::Unauthorized(format!("invalid bearer token: {error}"))
+                        .into_response();
+                }
+            };
+            let headers = request.headers_mut();
+            for (name, value) in jwt.claim_headers(&claims) {
+                let Ok(name) = HeaderName::try_from(name) else {
+                    continue;
+                };
+                if let Some(value) = value.and_then(|value| HeaderValue::try_from(value).ok()) {
+                    headers.insert(name, value);
+                }
+            }
+            let consumer = jwt.consumer(&claims).unwrap_or_default();
+            request.extensions_mut().insert(claims);
+            consumer
+        }
+        None => match auth.consumer(credential.as_bytes()) {
+            Some(consumer) => consumer,
+            None => {
+                info!(
+                    monotonic_counter.unauthorized_request_count = 1,
+                    reason = "invalid"
+                );
+                return Error::Unauthorized("invalid API key".into()).into_response();
+            }
+        },
+    };
+    Span::current().record("api_consumer", consumer.as_str());
+    request.extensions_mut().insert(ApiConsumer(consumer));
+    next.run(request).await
+}
+
+/// Returns the API key of the `Authorization: Bearer <key>` or `x-api-key` header.
+/// The authentication scheme is case-insensitive.
+fn api_key(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, key)| key)
+        .or_else(|| {
+            headers
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+        })
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, Router, middleware, routing::get};
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::{
+        server::{jwt::test_issuer::TestIssuer, metrics::record_route_metrics},
+        utils::{test_events::CapturedEvents, test_server::serve},
+    };
+
+    #[tokio::test]
+    async fn test_consumer() {
+        let path = std::env::temp_dir().join(format!("{}-api-key", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&path, "key-a\n").unwrap();
+        let mut config = AuthConfig {
+            api_keys: HashMap::from([("team-a".to_string(), SecretSource::File(path))]),
+            refresh_interval: None,
+            jwt: None,
+        };
+        let auth = Authenticator::load(config.clone()).await.unwrap();
+        assert_eq!(auth.consumer(b"key-a"), Some("team-a".into()));
+        assert_eq!(auth.consumer(b"key-b"), None);
+
+        // Keys failing to load fail start-up
+        config.api_keys.insert(
+            "team-b".to_string(),
+            SecretSource::Env("GUARDRAILS_TEST_MISSING_API_KEY".into()),
+        );
+        assert!(Authenticator::load(config).await.is_err());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(API_KEY_HEADER, "key-a".parse().unwrap());
+        assert_eq!(api_key(&headers), Some("key-a".into()));
+        headers.insert(header::AUTHORIZATION, "Bearer key-b".parse().unwrap());
+        assert_eq!(api_key(&headers), Some("key-b".into()));
+        headers.insert(header::AUTHORIZATION, "bearer key-c".parse().unwrap());
+        assert_eq!(api_key(&headers), Some("key-c".into()));
+        headers.insert(header::AUTHORIZATION, "Basic a2V5LWQ=".parse().unwrap());
+        assert_eq!(api_key(&headers), Some("key-a".into()));
+        assert_eq!(api_key(&HeaderMap::new()), None);
+    }
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let (events, _guard) = CapturedEvents::capture();
+        let path = std::env::temp_dir().join(format!("{}-api-key", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&path, "key-a").unwrap();
+        let auth = Authenticator::load(AuthConfig {
+            api_keys: HashMap::from([("team-a".to_string(), SecretSource::File(path))]),
+            refresh_interval: None,
+            jwt: None,
+        });
+        let auth = Arc::new(auth.await.unwrap());
+        // Layered as by the guardrails server, authenticating before recording metrics
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(middleware::from_fn(record_route_metrics))
+            .layer(middleware::from_fn_with_state(auth, authenticate));
+        let port = serve(app).await;
+        let client = reqwest::Client::new();
+        let url = format!("http://localhost:{port}/");
+
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), 401);
+        let body = response.json::<Value>().await.unwrap();
+        assert_eq!(body, json!({"code": 401, "details": "missing API key"}));
+
+        let response = client.get(&url).bearer_auth("key-b").send().await.unwrap();
+        assert_eq!(response.status(), 401);
+        let body = response.json::<Value>().await.unwrap();
+        assert_eq!(body, json!({"code": 401, "details": "invalid API key"}));
+
+        let response = client
+            .get(&url)
+            .header(header::AUTHORIZATION, "bearer key-a")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        let reasons = events
+            .with_field("monotonic_counter.unauthorized_request_count")
+            .into_iter()
+            .map(|fields| fields["reason"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(reasons, ["missing", "invalid"]);
+        // Rejected requests never reach the route, so only the authenticated one is recorded
+        let routes = events.with_field("monotonic_counter.route_request_count");
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0]["consumer"], "team-a");
+        assert_eq!(routes[0]["status"], "200");
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_claim_headers() {
+        let issuer = TestIssuer::start().await;
+        let path = std::env::temp_dir().join(format!("{}-api-key", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&path, "key-a").unwrap();
+        let auth = Authenticator::load(AuthConfig {
+            api_keys: HashMap::from([("team-a".to_string(), SecretSource::File(path))]),
+            refresh_interval: None,
+            jwt: Some(issuer.config()),
+        });
+        let auth = Arc::new(auth.await.unwrap());
+        let app = Router::new()
+            .route(
+                "/",
+                get(|headers: HeaderMap| async move {
+                    let tenants = headers
+                        .get_all("x-tenant-id")
+                        .iter()
+                        .map(|value| value.to_str().unwrap().to_string())
+                        .collect::<Vec<_>>();
+                    Json(tenants)
+                }),
+            )
+            .layer(middleware::from_fn_with_state(auth, authenticate));
+        let port = serve(app).await;
+        let client = reqwest::Client::new();
+        let tenants = |claims: Value| {
+            let token = issuer.token(&issuer.claims(claims));
+            let request = client
+                .get(format!("http://localhost:{port}/"))
+                .bearer_auth(token)
+                .header("x-tenant-id", "globex");
+            async move { request.send().await.unwrap().json::<Vec<String>>().await }
+        };
+
+        // Tenants spoofed by clients are replaced by the claim, or removed without one
+        let tenant = json!({"sub": "user-1", "tenant": "acme"});
+        assert_eq!(tenants(tenant).await.unwrap(), ["acme"]);
+        assert!(tenants(json!({"sub": "user-1"})).await.unwrap().is_empty());
+
+        // Tenants sent with API keys are removed
+        let tenants = client
+            .get(format!("http://localhost:{port}/"))
+            .header(API_KEY_HEADER, "key-a")
+            .header("x-tenant-id", "globex")
+            .send()
+            .await
+            .unwrap()
+            .json::<Vec<String>>()
+            .await
+            .unwrap();
+        assert!(tenants.is_empty());
+    }
+}
@@ -0,0 +1,573 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Sampled capture of request payloads, generated outputs and detections, with PII
+//! redacted, for offline detector evaluation and threshold tuning.
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use http::HeaderMap;
+use opentelemetry::trace::TraceId;
+use regex::Regex;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use super::{
+    encryption::{self, Envelope},
+    sink::JsonLinesSink,
+};
+use crate::{
+    config::{AuthConfig, CaptureConfig, DetectionAction, DetectorConfig, SpoolConfig},
+    models::ClassifiedGeneratedTextResult,
+    orchestrator::{
+        common::{current_timestamp, unfiltered::UnfilteredDetections},
+        types::Detection,
+    },
+};
+
+/// Patterns of PII always redacted: email addresses and digit sequences of phone,
+/// account and card numbers.
+const DEFAULT_REDACT_PATTERNS: &[&str] = &[r"[\w.+-]+@[\w-]+(\.[\w-]+)+", r"\+?\d[\d -]{7,}\d"];
+
+/// Source text of a captured detection.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureSource {
+    Input,
+    Output,
+}
+
+/// Captured request, appended to the capture file as a JSON line.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CaptureRecord {
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+    pub trace_id: String,
+    pub tenant: String,
+    pub task: &'static str,
+    /// Redacted input text
+    pub input: String,
+    /// Redacted generated text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    pub detections: Vec<CapturedDetection>,
+}
+
+/// Captured request encrypted with the key of its tenant, appended to the capture file
+/// as a JSON line. Its tenant and trace ID are authenticated as additional data, as
+/// `<tenant>:<trace_id>`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EncryptedCaptureRecord<'a> {
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+    pub trace_id: &'a str,
+    pub tenant: &'a str,
+    pub task: &'static str,
+    /// Encrypted `CaptureRecord`
+    pub encryption: Envelope,
+}
+
+/// Captured detection, before filtering by `min_score` and `top_k`. Offsets are unchanged
+/// by redaction.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CapturedDetection {
+    pub source: CaptureSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detector_id: Option<String>,
+    pub detection_type: String,
+    pub detection: String,
+    pub score: f64,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl CapturedDetection {
+    fn new(source: CaptureSource, detection: &Detection) -> Self {
+        Self {
+            source,
+            detector_id: detection.detector_id.clone(),
+            detection_type: detection.detection_type.clone(),
+            detection: detection.detection.clone(),
+            score: detection.score,
+            start: detection.start.unwrap_or_default(),
+            end: detection.end.unwrap_or_default(),
+        }
+    }
+}
+
+/// Input of a request sampled for capture.
+#[derive(Debug)]
+pub struct SampledRequest {
+    tenant: String,
+    input: String,
+}
+
+/// Captures sampled requests of tenants enabled in the `capture` config.
+#[derive(Debug)]
+pub struct PayloadCapture {
+    config: CaptureConfig,
+    /// Number of requests considered for capture, by tenant
+    requests: HashMap<String, AtomicU64>,
+    redact_patterns: Vec<Regex>,
+    /// Detectors with the `redact` action, whose detected spans are redacted
+    redact_detectors: HashSet<String>,
+    /// Whether tenants are identified by the API consumer of requests rather than the
+    /// tenant header, which is sent by clients unless set from a JWT claim
+    consumer_tenants: bool,
+    sink: JsonLinesSink,
+}
+
+impl PayloadCapture {
+    pub fn new(
+        config: CaptureConfig,
+        detectors: &HashMap<String, DetectorConfig>,
+        auth: Option<&AuthConfig>,
+        spool: Option<&SpoolConfig>,
+    ) -> Self {
+        let redact_patterns = DEFAULT_REDACT_PATTERNS
+            .iter()
+            .copied()
+            .chain(config.redact_patterns.iter().map(String::as_str))
+            .map(|pattern| Regex::new(pattern).expect("redact pattern should be validated"))
+            .collect();
+        let redact_detectors = detectors
+            .iter()
+            .filter(|(_, detector)| detector.action == DetectionAction::Redact)
+            .map(|(detector_id, _)| detector_id.clone())
+            .collect();
+        let requests = config
+            .tenants
+            .keys()
+            .map(|tenant| (tenant.clone(), AtomicU64::new(0)))
+            .collect();
+        let consumer_tenants = auth.is_some_and(|auth| {
+            !auth.jwt.as_ref().is_some_and(|jwt| {
+                jwt.claim_headers
+                    .values()
+                    .any(|header| header.eq_ignore_ascii_case(&config.tenant_header))
+            })
+        });
+        let sink = JsonLinesSink::new(config.path.clone(), spool.cloned());
+        Self {
+            config,
+            requests,
+            redact_patterns,
+            redact_detectors,
+            consumer_tenants,
+            sink,
+        }
+    }
+
+    /// Returns the sampled request, if a request with `headers` of `consumer` is selected
+    /// for capture. Requests of a tenant are selected evenly at its sample rate.
+    ///
+    /// With authentication, the tenant is the API consumer, unless the tenant header is
+    /// set from a JWT claim, so clients can't be captured under another tenant.
+    pub fn sample(
+        &self,
+        headers: &HeaderMap,
+        consumer: Option<&str>,
+        input: &str,
+    ) -> Option<SampledRequest> {
+        let tenant = if self.consumer_tenants {
+            consumer?
+        } else {
+            headers
+                .get(self.config.tenant_header.as_str())?
+                .to_str()
+                .ok()?
+        };
+        let requests = self.requests.get(tenant)?;
+        let sample_rate = self.config.sample_rate(tenant);
+        let n = requests.fetch_add(1, Ordering::Relaxed) as f64;
+        let sampled = ((n + 1.0) * sample_rate).floor() > (n * sample_rate).floor();
+        sampled.then(|| SampledRequest {
+            tenant: tenant.to_string(),
+            input: input.to_string(),
+        })
+    }
+
+    /// Captures a classification with text generation request, its result and its
+    /// unfiltered detections.
+    pub async fn capture_generation(
+        &self,
+        trace_id: TraceId,
+        request: SampledRequest,
+        result: &ClassifiedGeneratedTextResult,
+        detections: UnfilteredDetections,
+    ) {
+        self.capture(
+            trace_id,
+            "classification_with_gen",
+            request,
+            result.generated_text.as_deref(),
+            detections,
+        )
+        .await;
+    }
+
+    /// Captures a text content detection request and its unfiltered detections.
+    pub async fn capture_content_detection(
+        &self,
+        trace_id: TraceId,
+        request: SampledRequest,
+        detections: UnfilteredDetections,
+    ) {
+        self.capture(
+            trace_id,
+            "text_content_detection",
+            request,
+            None,
+            detections,
+        )
+        .await;
+    }
+
+    /// Redacts and writes a record to the capture file. Dropped if the write buffer is full.
+    async fn capture(
+        &self,
+        trace_id: TraceId,
+        task: &'static str,
+        request: SampledRequest,
+        output: Option<&str>,
+        detections: UnfilteredDetections,
+    ) {
+        let record = self.record(trace_id, task, request, output, detections);
+        let tenant_key = self
+            .config
+            .encryption
+            .as_ref()
+            .and_then(|encryption| encryption.tenant_keys.get(&record.tenant));
+        let written = match tenant_key {
+            Some(tenant_key) => {
+                let aad = format!("{}:{}", record.tenant, record.trace_id);
+                let plaintext = serde_json::to_vec(&record).unwrap();
+                match encryption::seal(tenant_key, aad.as_bytes(), &plaintext).await {
+                    Ok(envelope) => {
+                        let record = EncryptedCaptureRecord {
+                            timestamp: record.timestamp,
+                            trace_id: &record.trace_id,
+                            tenant: &record.tenant,
+                            task,
+                            encryption: envelope,
+                        };
+                        self.sink.write(&record).await
+                    }
+                    Err(error) => {
+                        warn!(
+                            tenant = %record.tenant,
+                            task,
+                            %error,
+                            monotonic_counter.dropped_payload_capture_count = 1,
+                            "payload capture encryption failed, record dropped"
+                        );
+                        return;
+                    }
+                }
+            }
+            None => self.sink.write(&record).await,
+        };
+        match written {
+            true => info!(
+                tenant = %record.tenant,
+                task,
+                monotonic_counter.captured_payload_count = 1,
+            ),
+            false => warn!(
+                tenant = %record.tenant,
+                task,
+                monotonic_counter.dropped_payload_capture_count = 1,
+                "payload capture buffer full, record dropped"
+            ),
+        }
+    }
+
+    /// Returns the record of a request, redacted on its unfiltered detections.
+    fn record(
+        &self,
+        trace_id: TraceId,
+        task: &'static str,
+        request: SampledRequest,
+        output: Option<&str>,
+        detections: UnfilteredDetections,
+    ) -> CaptureRecord {
+        let detections = [
+            (CaptureSource::Input, detections.input),
+            (CaptureSource::Output, detections.output),
+        ]
+        .into_iter()
+        .flat_map(|(source, detections)| {
+            detections
+                .into_iter()
+                .map(move |detection| CapturedDetection::new(source, &detection))
+        })
+        .collect::<Vec<_>>();
+        CaptureRecord {
+            timestamp: current_timestamp().as_secs(),
+            trace_id: trace_id.to_string(),
+            tenant: request.tenant,
+            task,
+            input: self.redact(&request.input, &detections, CaptureSource::Input),
+            output: output.map(|output| self.redact(output, &detections, CaptureSource::Output)),
+            detections,
+        }
+    }
+
+    /// Masks spans of `text` detected by redact detectors and matching redact patterns,
+    /// preserving the offsets of detections.
+    fn redact(
+        &self,
+        text: &str,
+        detections: &[CapturedDetection],
+        source: CaptureSource,
+    ) -> String {
+        let mut chars = text.chars().collect::<Vec<_>>();
+        let spans = detections.iter().filter(|detection| {
+            detection.source == source
+                && detection
+                    .detector_id
+                    .as_ref()
+                    .is_some_and(|detector_id| self.redact_detectors.contains(detector_id))
+        });
+        for detection in spans {
+            let end = detection.end.min(chars.len());
+            let start = detection.start.min(end);
+            chars[start..end].fill('*');
+        }
+        let mut text = chars.into_iter().collect::<String>();
+        for pattern in &self.redact_patterns {
+            text = pattern
+                .replace_all(&text, |captures: &regex::Captures| {
+                    "*".repeat(captures[0].chars().count())
+                })
+                .into_owned();
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use base64::{Engine, engine::general_purpose::STANDARD};
+
+    use super::*;
+    use crate::{
+        config::{CaptureEncryptionConfig, TenantKey},
+        utils::secrets::SecretSource,
+    };
+
+    fn capture(tenants: &[(&str, f64)], auth: Option<&AuthConfig>) -> PayloadCapture {
+        capture_with(tenants, auth, PathBuf::from("capture.jsonl"), None)
+    }
+
+    fn capture_with(
+        tenants: &[(&str, f64)],
+        auth: Option<&AuthConfig>,
+        path: PathBuf,
+        encryption: Option<CaptureEncryptionConfig>,
+    ) -> PayloadCapture {
+        let config = CaptureConfig {
+            tenant_header: "x-tenant-id".into(),
+            tenants: tenants
+                .iter()
+                .map(|(tenant, rate)| (tenant.to_string(), *rate))
+                .collect(),
+            path,
+            redact_patterns: vec![r"\bACME-\d+\b".into()],
+            encryption,
+        };
+        let detectors = HashMap::from([(
+            "pii".to_string(),
+            DetectorConfig {
+                action: DetectionAction::Redact,
+                ..Default::default()
+            },
+        )]);
+        PayloadCapture::new(config, &detectors, auth, None)
+    }
+
+    fn auth(claim_headers: &str) -> AuthConfig {
+        serde_yml::from_str(&format!(
+            r#"
+            jwt:
+                issuer: https://issuer.example.com
+                audiences:
+                    - guardrails
+                claim_headers: {claim_headers}
+            "#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sample() {
+        let capture = capture(&[("acme", 0.5)], None);
+        let mut headers = HeaderMap::new();
+        assert!(capture.sample(&headers, None, "hi").is_none());
+        headers.insert("x-tenant-id", "other".parse().unwrap());
+        assert!(capture.sample(&headers, None, "hi").is_none());
+        headers.insert("x-tenant-id", "acme".parse().unwrap());
+        let sampled = (0..4)
+            .filter(|_| capture.sample(&headers, None, "hi").is_some())
+            .count();
+        assert_eq!(sampled, 2);
+    }
+
+    #[test]
+    fn test_sample_authenticated() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant-id", "acme".parse().unwrap());
+
+        // Tenants are API consumers, unless set from a JWT claim
+        let capture = capture(&[("acme", 1.0)], Some(&auth("{}")));
+        assert!(capture.sample(&headers, Some("globex"), "hi").is_none());
+        assert!(capture.sample(&headers, None, "hi").is_none());
+        let sampled = capture.sample(&HeaderMap::new(), Some("acme"), "hi");
+        assert_eq!(sampled.unwrap().tenant, "acme");
+
+        let capture = capture(&[("acme", 1.0)], Some(&auth("{tenant: X-Tenant-Id}")));
+        let sampled = capture.sample(&headers, Some("globex"), "hi");
+        assert_eq!(sampled.unwrap().tenant, "acme");
+    }
+
+    #[test]
+    fn test_record() {
+        let capture = capture(&[], None);
+        let request = SampledRequest {
+            tenant: "acme".into(),
+            input: "I'm Jane".into(),
+        };
+        // Detections filtered out of the response by `min_score` are redacted
+        let detection = Detection {
+            start: Some(4),
+            end: Some(8),
+            detector_id: Some("pii".into()),
+            detection_type: "pii".into(),
+            detection: "name".into(),
+            score: 0.2,
+            ..Default::default()
+        };
+        let detections = UnfilteredDetections {
+            input: vec![detection.clone()],
+            output: vec![Detection {
+                start: Some(0),
+                end: Some(4),
+                ..detection
+            }],
+        };
+        let record = capture.record(
+            TraceId::INVALID,
+            "classification_with_gen",
+            request,
+            Some("John"),
+            detections,
+        );
+        assert_eq!(record.input, "I'm ****");
+        assert_eq!(record.output.as_deref(), Some("****"));
+        assert_eq!(record.detections.len(), 2);
+        assert_eq!(record.detections[1].source, CaptureSource::Output);
+    }
+
+    #[tokio::test]
+    async fn test_capture_encrypted() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().simple().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        let key = [7u8; 32];
+        std::fs::write(dir.join("acme-key"), STANDARD.encode(key)).unwrap();
+        let encryption = CaptureEncryptionConfig {
+            tenant_keys: HashMap::from([(
+                "acme".to_string(),
+                TenantKey {
+                    key_id: "acme-1".into(),
+                    key: SecretSource::File(dir.join("acme-key")),
+                },
+            )]),
+        };
+        let path = dir.join("capture.jsonl");
+        let capture = capture_with(&[("acme", 1.0)], None, path.clone(), Some(encryption));
+        let request = SampledRequest {
+            tenant: "acme".into(),
+            input: "ticket ACME-42 is urgent".into(),
+        };
+        let trace_id = TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap();
+        capture
+            .capture_content_detection(trace_id, request, UnfilteredDetections::default())
+            .await;
+
+        // Records are written by a background thread
+        let line = loop {
+            if let Ok(contents) = tokio::fs::read_to_string(&path).await {
+                if contents.ends_with('\n') {
+                    break contents;
+                }
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        };
+        assert!(!line.contains("urgent"));
+        let record: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(record["tenant"], "acme");
+        assert_eq!(record["trace_id"], trace_id.to_string());
+        let envelope: Envelope = serde_json::from_value(record["encryption"].clone()).unwrap();
+        assert_eq!(envelope.key_id, "acme-1");
+
+        // Records are read back with the tenant key, authenticated with their tenant and trace ID
+        let aad = format!("acme:{trace_id}");
+        let plaintext = encryption::open(&key, aad.as_bytes(), &envelope).unwrap();
+        let record: serde_json::Value = serde_json::from_slice(&plaintext).unwrap();
+        assert_eq!(record["input"], "ticket ******* is urgent");
+        assert_eq!(record["task"], "text_content_detection");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_redact() {
+        let capture = capture(&[], None);
+        let detections = vec![
+            CapturedDetection {
+                source: CaptureSource::Input,
+                detector_id: Some("pii".into()),
+                detection_type: "pii".into(),
+                detection: "name".into(),
+                score: 0.9,
+                start: 8,
+                end: 12,
+            },
+            CapturedDetection {
+                source: CaptureSource::Input,
+                detector_id: Some("hap".into()),
+                detection_type: "hap".into(),
+                detection: "hap".into(),
+                score: 0.9,
+                start: 0,
+                end: 2,
+            },
+        ];
+        let text = "Hi, I'm Jane, jane@example.com, +1 555 123 4567, ticket ACME-42";
+        assert_eq!(
+            capture.redact(text, &detections, CaptureSource::Input),
+            "Hi, I'm ****, ****************, ***************, ticket *******"
+        );
+        assert_eq!(
+            capture.redact("Jane", &detections, CaptureSource::Output),
+            "Jane"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Admin debug endpoints, enabled with the `pprof` feature.
+//!
+//! Only CPU profiles are collected. Heap profiles would require an allocator with
+//! profiling support in place of the system allocator.
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    Router, extract::Query, http::header, middleware, response::IntoResponse, routing::get,
+};
+use pprof::protos::Message;
+use serde::Deserialize;
+use tracing::{error, info};
+
+use super::{Error, admin::require_admin_token};
+
+/// Default CPU profile duration in seconds.
+const fn default_profile_seconds() -> u64 {
+    30
+}
+/// Default CPU profile sampling frequency in hertz.
+const fn default_profile_frequency() -> i32 {
+    100
+}
+/// Maximum CPU profile duration in seconds.
+const MAX_PROFILE_SECONDS: u64 = 300;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileParams {
+    #[serde(default = "default_profile_seconds")]
+    pub seconds: u64,
+    #[serde(default = "default_profile_frequency")]
+    pub frequency: i32,
+}
+
+/// Creates debug router. All routes require the admin token.
+pub fn debug_router(admin_token: String) -> Router {
+    Router::new()
+        .route("/debug/pprof/profile", get(cpu_profile))
+        .layer(middleware::from_fn_with_state(
+            Arc::new(admin_token),
+            require_admin_token,
+        ))
+}
+
+/// Collects a CPU profile and returns it in pprof protobuf format.
+async fn cpu_profile(Query(params): Query<ProfileParams>) -> Result<impl IntoResponse, Error> {
+    if params.seconds == 0 || params.seconds > MAX_PROFILE_SECONDS {
+        return Err(Error::Validation(format!(
+            "`seconds` must be between 1 and {MAX_PROFILE_SECONDS}"
+        )));
+    }
+    if params.frequency <= 0 {
+        return Err(Error::Validation(
+            "`frequency` must be greater than 0".into(),
+        ));
+    }
+    info!(
+        seconds = params.seconds,
+        frequency = params.frequency,
+        "collecting CPU profile"
+    );
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(params.frequency)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+        .map_err(|error| {
+            Error::ServiceUnavailable(format!("failed to start CPU profiler: {error}"))
+        })?;
+    tokio::time::sleep(Duration::from_secs(params.seconds)).await;
+    let profile = guard
+        .report()
+        .build()
+        .and_then(|report| report.pprof())
+        .map_err(|error| {
+            error!(%error, "failed to build CPU profile");
+            Error::Unexpected
+        })?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/octet-stream")],
+        profile.encode_to_vec(),
+    ))
+}
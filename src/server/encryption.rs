@@ -0,0 +1,134 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Envelope encryption of records at rest with per-tenant keys.
+use base64::{Engine, engine::general_purpose::STANDARD};
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::TenantKey;
+
+/// Algorithm of data and key encryption.
+pub const ALGORITHM: &str = "AES-256-GCM";
+
+/// Record encrypted with a random data key, itself encrypted with a tenant key.
+/// Sealed values are the base64 encoding of the nonce followed by the ciphertext and tag.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub algorithm: String,
+    /// ID of the tenant key the data key is encrypted with
+    pub key_id: String,
+    /// Data key sealed with the tenant key
+    pub wrapped_key: String,
+    /// Record sealed with the data key
+    pub ciphertext: String,
+}
+
+/// Encrypts `plaintext` with a random data key, encrypted with the tenant key. Both are
+/// authenticated with `aad`, binding the envelope to e.g. the tenant and trace ID of the record.
+pub async fn seal(
+    tenant_key: &TenantKey,
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Envelope, String> {
+    let key = tenant_key.load().await?;
+    let rng = SystemRandom::new();
+    let mut data_key = [0u8; 32];
+    rng.fill(&mut data_key)
+        .map_err(|_| "failed to generate data key".to_string())?;
+    Ok(Envelope {
+        algorithm: ALGORITHM.into(),
+        key_id: tenant_key.key_id.clone(),
+        wrapped_key: encrypt(&key, aad, &data_key, &rng)?,
+        ciphertext: encrypt(&data_key, aad, plaintext, &rng)?,
+    })
+}
+
+fn aead_key(key: &[u8]) -> Result<LessSafeKey, String> {
+    UnboundKey::new(&AES_256_GCM, key)
+        .map(LessSafeKey::new)
+        .map_err(|_| "key must be 256 bits".to_string())
+}
+
+/// Seals `plaintext` with a random nonce.
+fn encrypt(key: &[u8], aad: &[u8], plaintext: &[u8], rng: &SystemRandom) -> Result<String, String> {
+    let key = aead_key(key)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut nonce)
+        .map_err(|_| "failed to generate nonce".to_string())?;
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(aad),
+        &mut in_out,
+    )
+    .map_err(|_| "encryption failed".to_string())?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(in_out);
+    Ok(STANDARD.encode(sealed))
+}
+
+/// Decrypts the record of `envelope` with the tenant key it was sealed with, e.g. to read
+/// back captured records. `aad` must be the additional data the envelope was sealed with.
+pub fn open(key: &[u8], aad: &[u8], envelope: &Envelope) -> Result<Vec<u8>, String> {
+    let data_key = decrypt(key, aad, &envelope.wrapped_key)?;
+    decrypt(&data_key, aad, &envelope.ciphertext)
+}
+
+/// Opens a sealed value.
+fn decrypt(key: &[u8], aad: &[u8], sealed: &str) -> Result<Vec<u8>, String> {
+    let key = aead_key(key)?;
+    let mut sealed = STANDARD.decode(sealed).map_err(|error| error.to_string())?;
+    if sealed.len() < NONCE_LEN {
+        return Err("sealed value too short".into());
+    }
+    let mut in_out = sealed.split_off(NONCE_LEN);
+    let nonce =
+        Nonce::try_assume_unique_for_key(&sealed).map_err(|_| "invalid nonce".to_string())?;
+    let plaintext = key
+        .open_in_place(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| "decryption failed".to_string())?;
+    Ok(plaintext.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::secrets::SecretSource;
+
+    #[tokio::test]
+    async fn test_seal() {
+        let key = [7u8; 32];
+        let path =
+            std::env::temp_dir().join(format!("{}-tenant-key", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&path, STANDARD.encode(key)).unwrap();
+        let tenant_key = TenantKey {
+            key_id: "acme-1".into(),
+            key: SecretSource::File(path),
+        };
+        let envelope = seal(&tenant_key, b"acme", b"record").await.unwrap();
+        assert_eq!(envelope.key_id, "acme-1");
+        assert_eq!(open(&key, b"acme", &envelope).unwrap(), b"record");
+
+        // Envelopes cannot be opened with other keys or moved to other tenants
+        assert!(open(&[8u8; 32], b"acme", &envelope).is_err());
+        assert!(open(&key, b"globex", &envelope).is_err());
+    }
+}
@@ -14,16 +14,16 @@
  limitations under the License.
 
 */
-use std::error::Error as _;
+use std::{error::Error as _, time::Duration};
 
 use axum::{
     Json,
-    extract::rejection::JsonRejection,
+    extract::rejection::{JsonRejection, QueryRejection},
     response::{IntoResponse, Response},
 };
-use http::StatusCode;
+use http::{StatusCode, header};
 
-use crate::{models::ValidationError, orchestrator};
+use crate::{models::ValidationError, orchestrator, utils::secrets};
 
 /// High-level errors to return to clients.
 #[derive(Debug, thiserror::Error)]
@@ -33,34 +33,54 @@ pub enum Error {
     #[error("{0}")]
     NotFound(String),
     #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
     ServiceUnavailable(String),
+    #[error("{message}")]
+    TooManyRequests {
+        message: String,
+        /// Delay to wait before retrying, returned in the `Retry-After` header
+        retry_after: Option<Duration>,
+    },
     #[error("unexpected error occurred while processing request")]
     Unexpected,
     #[error(transparent)]
     JsonExtractorRejection(#[from] JsonRejection),
+    #[error(transparent)]
+    QueryExtractorRejection(#[from] QueryRejection),
     #[error("{0}")]
     JsonError(String),
+    #[error("{0}")]
+    InvalidRequestBody(String),
     #[error("unsupported content type: {0}")]
     UnsupportedContentType(String),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    SecretError(#[from] secrets::Error),
 }
 
 impl From<orchestrator::Error> for Error {
     fn from(value: orchestrator::Error) -> Self {
         use orchestrator::Error::*;
         match value {
-            DetectorNotFound(_) | ChunkerNotFound(_) => Self::NotFound(value.to_string()),
+            DetectorNotFound(_) | ChunkerNotFound(_) | PendingDetectionsNotFound(_) => {
+                Self::NotFound(value.to_string())
+            }
             DetectorRequestFailed { ref error, .. }
             | ChunkerRequestFailed { ref error, .. }
             | GenerateRequestFailed { ref error, .. }
             | ChatCompletionRequestFailed { ref error, .. }
             | TokenizeRequestFailed { ref error, .. } => match error.status_code() {
-                StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => {
-                    Self::Validation(value.to_string())
-                }
+                StatusCode::BAD_REQUEST
+                | StatusCode::UNPROCESSABLE_ENTITY
+                | StatusCode::PAYLOAD_TOO_LARGE => Self::Validation(value.to_string()),
                 StatusCode::NOT_FOUND => Self::NotFound(value.to_string()),
                 StatusCode::SERVICE_UNAVAILABLE => Self::ServiceUnavailable(value.to_string()),
+                StatusCode::TOO_MANY_REQUESTS => Self::TooManyRequests {
+                    message: value.to_string(),
+                    retry_after: error.retry_after(),
+                },
                 _ => Self::Unexpected,
             },
             JsonError(message) => Self::JsonError(message),
@@ -71,12 +91,15 @@ impl From<orchestrator::Error> for Error {
 }
 
 impl Error {
-    pub fn to_json(self) -> serde_json::Value {
+    /// Returns the status code and message of the error.
+    pub fn into_parts(self) -> (StatusCode, String) {
         use Error::*;
-        let (code, message) = match self {
+        match self {
             Validation(_) => (StatusCode::UNPROCESSABLE_ENTITY, self.to_string()),
             NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
+            Unauthorized(_) => (StatusCode::UNAUTHORIZED, self.to_string()),
             ServiceUnavailable(_) => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
+            TooManyRequests { .. } => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
             UnsupportedContentType(_) => (StatusCode::UNSUPPORTED_MEDIA_TYPE, self.to_string()),
             Unexpected => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
             JsonExtractorRejection(json_rejection) => match json_rejection {
@@ -87,9 +110,18 @@ impl Error {
                 }
                 _ => (json_rejection.status(), json_rejection.body_text()),
             },
+            QueryExtractorRejection(query_rejection) => {
+                (query_rejection.status(), query_rejection.body_text())
+            }
             JsonError(_) => (StatusCode::UNPROCESSABLE_ENTITY, self.to_string()),
+            InvalidRequestBody(_) => (StatusCode::BAD_REQUEST, self.to_string()),
             IoError(error) => (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
-        };
+            SecretError(error) => (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+        }
+    }
+
+    pub fn to_json(self) -> serde_json::Value {
+        let (code, message) = self.into_parts();
         serde_json::json!({
             "code": code.as_u16(),
             "details": message,
@@ -99,29 +131,26 @@ impl Error {
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
-        use Error::*;
-        let (code, message) = match self {
-            Validation(_) => (StatusCode::UNPROCESSABLE_ENTITY, self.to_string()),
-            NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            ServiceUnavailable(_) => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
-            UnsupportedContentType(_) => (StatusCode::UNSUPPORTED_MEDIA_TYPE, self.to_string()),
-            Unexpected => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            JsonExtractorRejection(json_rejection) => match json_rejection {
-                JsonRejection::JsonDataError(e) => {
-                    // Get lower-level serde error message
-                    let message = e.source().map(|e| e.to_string()).unwrap_or_default();
-                    (e.status(), message)
-                }
-                _ => (json_rejection.status(), json_rejection.body_text()),
-            },
-            JsonError(_) => (StatusCode::UNPROCESSABLE_ENTITY, self.to_string()),
-            IoError(error) => (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()),
+        // Rounded up to whole seconds, so callers do not retry early
+        let retry_after = match &self {
+            Error::TooManyRequests {
+                retry_after: Some(retry_after),
+                ..
+            } => Some(retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)),
+            _ => None,
         };
+        let (code, message) = self.into_parts();
         let error = serde_json::json!({
             "code": code.as_u16(),
             "details": message,
         });
-        (code, Json(error)).into_response()
+        let mut response = (code, Json(error)).into_response();
+        if let Some(retry_after) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after.into());
+        }
+        response
     }
 }
 
@@ -0,0 +1,838 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+use std::{
+    convert::Infallible,
+    fmt,
+    io::{self, BufReader, Read},
+    sync::Arc,
+};
+
+use axum::{
+    Json, RequestExt,
+    body::{Body, Bytes},
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{header, request::Parts},
+};
+use axum_extra::extract::WithRejection;
+use futures::TryStreamExt;
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{self, DeserializeOwned, DeserializeSeed, MapAccess, Visitor, value::StrDeserializer},
+};
+use serde_json::{Map, Value, error::Category};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_util::io::{StreamReader, SyncIoBridge};
+
+use super::{Error, ServerState};
+use crate::{
+    clients::detector::ContextType,
+    config::RequestParsing,
+    models::{ContextDocsHttpRequest, DetectorParams, TextContentDetectionHttpRequest},
+};
+
+/// Field of detection request bodies mapping detectors to their parameters.
+const DETECTORS_FIELD: &str = "detectors";
+
+/// Returns `true` if request bodies are parsed strictly.
+fn is_strict(state: &ServerState) -> bool {
+    state.orchestrator.config().request_parsing == RequestParsing::Strict
+}
+
+/// Deserializes `T` from `de`, collecting paths of unknown fields.
+fn deserialize_strict<'de, T, D>(de: D) -> Result<(T, Vec<String>), D::Error>
+where
+    T: serde::Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    let mut unknown_fields = Vec::new();
+    let value = serde_ignored::deserialize(de, |path| unknown_fields.push(path.to_string()))?;
+    Ok((value, unknown_fields))
+}
+
+/// Returns an error listing `unknown_fields`, if any.
+fn reject_unknown_fields(unknown_fields: Vec<String>) -> Result<(), Error> {
+    if unknown_fields.is_empty() {
+        return Ok(());
+    }
+    let unknown_fields = unknown_fields
+        .iter()
+        .map(|path| format!("`{path}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(Error::JsonError(format!(
+        "unknown fields: {unknown_fields}"
+    )))
+}
+
+/// JSON extractor rejecting requests with unknown fields if request parsing is strict.
+///
+/// Otherwise equivalent to `WithRejection<Json<T>, Error>`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestJson<T>(pub T);
+
+impl<T> FromRequest<Arc<ServerState>> for RequestJson<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: Request, state: &Arc<ServerState>) -> Result<Self, Self::Rejection> {
+        if !is_strict(state) {
+            let WithRejection(Json(value), _) =
+                WithRejection::<Json<T>, Error>::from_request(req, state).await?;
+            return Ok(Self(value));
+        }
+        // Buffer the body to parse it twice, keeping rejections of the `Json` extractor
+        let headers = req.headers().clone();
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|rejection| Error::JsonExtractorRejection(rejection.into()))?;
+        let mut buffered = Request::new(Body::from(bytes.clone()));
+        *buffered.headers_mut() = headers;
+        let WithRejection(Json(value), _) =
+            WithRejection::<Json<T>, Error>::from_request(buffered, state).await?;
+        let (_, unknown_fields) =
+            deserialize_strict::<T, _>(&mut serde_json::Deserializer::from_slice(&bytes))
+                .map_err(|error| Error::JsonError(error.to_string()))?;
+        reject_unknown_fields(unknown_fields)?;
+        Ok(Self(value))
+    }
+}
+
+/// Returns a blocking reader of the body of `req`, subject to the default body limit
+/// applied to other extractors.
+fn body_reader(req: Request) -> impl Read + Send + 'static {
+    let body_stream = req
+        .with_limited_body()
+        .into_body()
+        .into_data_stream()
+        .map_err(io::Error::other);
+    BufReader::new(SyncIoBridge::new(StreamReader::new(body_stream)))
+}
+
+/// Returns the rejection of a JSON body that failed to parse.
+fn parse_error(error: serde_json::Error) -> Error {
+    match error.classify() {
+        Category::Data => Error::JsonError(error.to_string()),
+        Category::Syntax | Category::Eof => {
+            Error::InvalidRequestBody(format!("failed to parse request body: {error}"))
+        }
+        Category::Io => Error::InvalidRequestBody(format!("failed to read request body: {error}")),
+    }
+}
+
+/// JSON extractor that deserializes the request body incrementally as it is received.
+///
+/// Unlike [`axum::Json`], the raw body is never buffered in full, so memory
+/// for large payloads is bounded by the deserialized value. Requests with unknown
+/// fields are rejected if request parsing is strict.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StreamingJson<T>(pub T);
+
+impl<T> FromRequest<Arc<ServerState>> for StreamingJson<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: Request, state: &Arc<ServerState>) -> Result<Self, Self::Rejection> {
+        let content_type = req
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+        match content_type {
+            Some(content_type) if content_type.starts_with("application/json") => (),
+            _ => {
+                return Err(Error::UnsupportedContentType(
+                    "expected application/json".into(),
+                ));
+            }
+        }
+        let strict = is_strict(state);
+        let reader = body_reader(req);
+        // Deserialize on a blocking thread as body frames arrive
+        let (value, unknown_fields) = tokio::task::spawn_blocking(move || {
+            let mut de = serde_json::Deserializer::from_reader(reader);
+            let (value, unknown_fields) = match strict {
+                true => deserialize_strict::<T, _>(&mut de)?,
+                false => (T::deserialize(&mut de)?, Vec::new()),
+            };
+            de.end()?;
+            Ok::<_, serde_json::Error>((value, unknown_fields))
+        })
+        .await
+        .map_err(|_| Error::Unexpected)?
+        .map_err(parse_error)?;
+        reject_unknown_fields(unknown_fields)?;
+        Ok(Self(value))
+    }
+}
+
+/// Encoding of request and response bodies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BodyFormat {
+    #[default]
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl BodyFormat {
+    /// Returns the format of a media type, ignoring its parameters.
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        let essence = media_type.split(';').next().unwrap_or_default().trim();
+        match essence.to_ascii_lowercase().as_str() {
+            "application/json" => Some(Self::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Self::MessagePack)
+            }
+            "application/cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Returns the `Content-Type` of bodies in this format.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::MessagePack => "application/msgpack",
+            Self::Cbor => "application/cbor",
+        }
+    }
+
+    /// Encodes `value` in this format. Structs are encoded as maps keyed by field name.
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Json => {
+                serde_json::to_vec(value).map_err(|error| Error::JsonError(error.to_string()))
+            }
+            Self::MessagePack => rmp_serde::to_vec_named(value).map_err(|_| Error::Unexpected),
+            Self::Cbor => {
+                let mut body = Vec::new();
+                ciborium::into_writer(value, &mut body).map_err(|_| Error::Unexpected)?;
+                Ok(body)
+            }
+        }
+    }
+
+    /// Decodes `T` from `body`, collecting paths of unknown fields if `strict`.
+    fn decode<T: DeserializeOwned>(
+        &self,
+        body: &[u8],
+        strict: bool,
+    ) -> Result<(T, Vec<String>), Error> {
+        let invalid = |error: &dyn std::fmt::Display| {
+            Error::InvalidRequestBody(format!("failed to parse request body: {error}"))
+        };
+        if strict {
+            // Decode to an intermediate value to track unknown fields
+            let value: serde_json::Value = match self {
+                Self::Json => serde_json::from_slice(body).map_err(|error| invalid(&error))?,
+                Self::MessagePack => {
+                    rmp_serde::from_slice(body).map_err(|error| invalid(&error))?
+                }
+                Self::Cbor => ciborium::from_reader(body).map_err(|error| invalid(&error))?,
+            };
+            return deserialize_strict(value).map_err(|error| Error::JsonError(error.to_string()));
+        }
+        let value = match self {
+            Self::Json => serde_json::from_slice(body).map_err(|error| match error.classify() {
+                Category::Data => Error::JsonError(error.to_string()),
+                _ => invalid(&error),
+            })?,
+            Self::MessagePack => rmp_serde::from_slice(body).map_err(|error| match error {
+                rmp_serde::decode::Error::Syntax(message) => Error::JsonError(message),
+                error => invalid(&error),
+            })?,
+            Self::Cbor => ciborium::from_reader(body).map_err(|error| match error {
+                ciborium::de::Error::Semantic(_, message) => Error::JsonError(message),
+                error => invalid(&error),
+            })?,
+        };
+        Ok((value, Vec::new()))
+    }
+}
+
+/// Response body format negotiated from the `Accept` header: the first supported
+/// media type listed, or JSON if none is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Accept(pub BodyFormat);
+
+impl<S: Send + Sync> FromRequestParts<S> for Accept {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let format = parts
+            .headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .find_map(BodyFormat::from_media_type)
+            .unwrap_or_default();
+        Ok(Self(format))
+    }
+}
+
+/// Request body extractor accepting JSON, MessagePack and CBOR bodies by `Content-Type`.
+///
+/// JSON bodies are deserialized as by [`StreamingJson`]. MessagePack and CBOR bodies are
+/// buffered, subject to the default body limit. Requests with unknown fields are
+/// rejected if request parsing is strict.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestBody<T>(pub T);
+
+impl<T> FromRequest<Arc<ServerState>> for RequestBody<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: Request, state: &Arc<ServerState>) -> Result<Self, Self::Rejection> {
+        let format = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(BodyFormat::from_media_type);
+        match format {
+            Some(BodyFormat::Json) => {
+                let StreamingJson(value) = StreamingJson::from_request(req, state).await?;
+                Ok(Self(value))
+            }
+            Some(format) => {
+                let strict = is_strict(state);
+                let bytes = Bytes::from_request(req, state)
+                    .await
+                    .map_err(|rejection| Error::JsonExtractorRejection(rejection.into()))?;
+                let (value, unknown_fields) = format.decode(&bytes, strict)?;
+                reject_unknown_fields(unknown_fields)?;
+                Ok(Self(value))
+            }
+            None => Err(Error::UnsupportedContentType(
+                "expected application/json, application/msgpack or application/cbor".into(),
+            )),
+        }
+    }
+}
+
+/// Part of a detection request body, received as soon as it is parsed.
+#[derive(Debug, PartialEq)]
+pub enum BodyPart<I> {
+    /// Inputs of detector calls, received before any detector
+    Inputs(I),
+    /// Detector and its parameters
+    Detector(String, DetectorParams),
+}
+
+/// Detection request whose detectors can be dispatched before its body is received in full.
+pub trait DispatchableRequest: DeserializeOwned + Send + 'static {
+    /// Inputs of detector calls, e.g. the content
+    type Inputs: Send + 'static;
+
+    /// Fields of the inputs of detector calls
+    const INPUT_FIELDS: &'static [&'static str];
+
+    /// Returns the inputs of detector calls from their parsed `fields`, unless invalid,
+    /// e.g. empty content. Detectors are not dispatched for invalid inputs.
+    fn inputs(fields: Map<String, Value>) -> Option<Self::Inputs>;
+}
+
+impl DispatchableRequest for TextContentDetectionHttpRequest {
+    type Inputs = String;
+
+    const INPUT_FIELDS: &'static [&'static str] = &["content"];
+
+    fn inputs(mut fields: Map<String, Value>) -> Option<Self::Inputs> {
+        let content = String::deserialize(fields.remove("content")?).ok()?;
+        (!content.is_empty()).then_some(content)
+    }
+}
+
+impl DispatchableRequest for ContextDocsHttpRequest {
+    type Inputs = (String, ContextType, Vec<String>);
+
+    const INPUT_FIELDS: &'static [&'static str] = &["content", "context_type", "context"];
+
+    fn inputs(mut fields: Map<String, Value>) -> Option<Self::Inputs> {
+        let content = String::deserialize(fields.remove("content")?).ok()?;
+        let context_type = ContextType::deserialize(fields.remove("context_type")?).ok()?;
+        let context = Vec::<String>::deserialize(fields.remove("context")?).ok()?;
+        (!content.is_empty() && !context.is_empty()).then_some((content, context_type, context))
+    }
+}
+
+/// Parts of a detection request body, sent as they are parsed.
+struct BodyParts<'a, T: DispatchableRequest> {
+    tx: &'a mpsc::Sender<BodyPart<T::Inputs>>,
+    /// Input fields parsed so far, unset once all are parsed
+    inputs: Option<Map<String, Value>>,
+    /// Whether the inputs were valid and sent, so detectors are sent
+    dispatching: bool,
+    /// Detectors parsed before the inputs, sent after them
+    queued: Vec<(String, DetectorParams)>,
+}
+
+impl<'a, T: DispatchableRequest> BodyParts<'a, T> {
+    fn new(tx: &'a mpsc::Sender<BodyPart<T::Inputs>>) -> Self {
+        Self {
+            tx,
+            inputs: Some(Map::new()),
+            dispatching: false,
+            queued: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if `key` is an input field not parsed yet.
+    fn is_input(&self, key: &str) -> bool {
+        self.inputs
+            .as_ref()
+            .is_some_and(|inputs| T::INPUT_FIELDS.contains(&key) && !inputs.contains_key(key))
+    }
+
+    /// Adds a parsed input field, sending the inputs and queued detectors once all input
+    /// fields are parsed.
+    fn input(&mut self, key: String, value: Value) {
+        let Some(inputs) = &mut self.inputs else {
+            return;
+        };
+        inputs.insert(key, value);
+        if !T::INPUT_FIELDS
+            .iter()
+            .all(|field| inputs.contains_key(*field))
+        {
+            return;
+        }
+        let inputs = self.inputs.take().unwrap_or_default();
+        let queued = std::mem::take(&mut self.queued);
+        if let Some(inputs) = T::inputs(inputs) {
+            self.dispatching = true;
+            let _ = self.tx.blocking_send(BodyPart::Inputs(inputs));
+            for (detector_id, params) in queued {
+                self.detector(detector_id, params);
+            }
+        }
+    }
+
+    /// Sends a parsed detector, or queues it while the inputs are not parsed.
+    fn detector(&mut self, detector_id: String, params: DetectorParams) {
+        if self.inputs.is_some() {
+            self.queued.push((detector_id, params));
+        } else if self.dispatching {
+            let _ = self
+                .tx
+                .blocking_send(BodyPart::Detector(detector_id, params));
+        }
+    }
+}
+
+/// Deserializer of a detection request body, sending its parts as they are parsed.
+///
+/// The request is deserialized directly from `de`. Only the inputs of detector calls
+/// are copied, to be sent, and detectors, small, are deserialized twice.
+struct PartsDeserializer<'a, D, T: DispatchableRequest> {
+    de: D,
+    parts: BodyParts<'a, T>,
+}
+
+impl<'de, D, T> Deserializer<'de> for PartsDeserializer<'_, D, T>
+where
+    D: Deserializer<'de>,
+    T: DispatchableRequest,
+{
+    type Error = D::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.de.deserialize_map(PartsVisitor {
+            visitor,
+            parts: self.parts,
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum
+        identifier ignored_any
+    }
+}
+
+/// Visitor of a detection request body, passing its fields to the visitor of the request.
+struct PartsVisitor<'a, V, T: DispatchableRequest> {
+    visitor: V,
+    parts: BodyParts<'a, T>,
+}
+
+impl<'de, V, T> Visitor<'de> for PartsVisitor<'_, V, T>
+where
+    V: Visitor<'de>,
+    T: DispatchableRequest,
+{
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.visitor.expecting(formatter)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_map(PartsMapAccess {
+            map,
+            parts: self.parts,
+            key: None,
+        })
+    }
+}
+
+/// Fields of a detection request body, sending detectors and inputs of detector calls
+/// as they are deserialized.
+struct PartsMapAccess<'a, A, T: DispatchableRequest> {
+    map: A,
+    parts: BodyParts<'a, T>,
+    /// Key of the field whose value is deserialized next
+    key: Option<String>,
+}
+
+impl<'de, A, T> MapAccess<'de> for PartsMapAccess<'_, A, T>
+where
+    A: MapAccess<'de>,
+    T: DispatchableRequest,
+{
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let Some(key) = self.map.next_key::<String>()? else {
+            return Ok(None);
+        };
+        let value = seed.deserialize(StrDeserializer::<'_, A::Error>::new(&key))?;
+        self.key = Some(key);
+        Ok(Some(value))
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<S::Value, Self::Error> {
+        let key = self.key.take().unwrap_or_default();
+        if key == DETECTORS_FIELD {
+            let detectors = self.map.next_value_seed(DetectorsSeed {
+                parts: &mut self.parts,
+            })?;
+            return seed
+                .deserialize(Value::Object(detectors))
+                .map_err(de::Error::custom);
+        }
+        if self.parts.is_input(&key) {
+            let value = self.map.next_value::<Value>()?;
+            self.parts.input(key, value.clone());
+            return seed.deserialize(value).map_err(de::Error::custom);
+        }
+        self.map.next_value_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.map.size_hint()
+    }
+}
+
+/// Seed of the detectors of a detection request body, sending each as it is parsed.
+struct DetectorsSeed<'a, 'b, T: DispatchableRequest> {
+    parts: &'a mut BodyParts<'b, T>,
+}
+
+impl<'de, T: DispatchableRequest> DeserializeSeed<'de> for DetectorsSeed<'_, '_, T> {
+    type Value = Map<String, Value>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, T: DispatchableRequest> Visitor<'de> for DetectorsSeed<'_, '_, T> {
+    type Value = Map<String, Value>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut detectors = Map::new();
+        while let Some((detector_id, params)) = map.next_entry::<String, DetectorParams>()? {
+            let value = serde_json::to_value(&params).map_err(de::Error::custom)?;
+            detectors.insert(detector_id.clone(), value);
+            self.parts.detector(detector_id, params);
+        }
+        Ok(detectors)
+    }
+}
+
+/// JSON detection request body parsed on a blocking thread as it is received.
+pub struct StreamingBody<T: DispatchableRequest> {
+    parts: mpsc::Receiver<BodyPart<T::Inputs>>,
+    parsed: JoinHandle<Result<(T, Vec<String>), serde_json::Error>>,
+}
+
+/// Detection request body extractor receiving detectors as they are parsed, so detector
+/// calls can be dispatched before the body is received in full.
+///
+/// JSON bodies are deserialized incrementally as by [`StreamingJson`], sending the inputs
+/// of detector calls once parsed and then each detector. Other bodies are extracted as
+/// by [`RequestBody`], without parts.
+pub enum DispatchingBody<T: DispatchableRequest> {
+    /// Body parsed in full
+    Parsed(T),
+    /// JSON body parsed as it is received
+    Streaming(StreamingBody<T>),
+}
+
+impl<T: DispatchableRequest> DispatchingBody<T> {
+    /// Receives the request, calling `on_part` with each part of the body as it is parsed.
+    pub async fn receive(self, mut on_part: impl FnMut(BodyPart<T::Inputs>)) -> Result<T, Error> {
+        let mut body = match self {
+            Self::Parsed(value) => return Ok(value),
+            Self::Streaming(body) => body,
+        };
+        while let Some(part) = body.parts.recv().await {
+            on_part(part);
+        }
+        let (value, unknown_fields) = body
+            .parsed
+            .await
+            .map_err(|_| Error::Unexpected)?
+            .map_err(parse_error)?;
+        reject_unknown_fields(unknown_fields)?;
+        Ok(value)
+    }
+}
+
+impl<T: DispatchableRequest> FromRequest<Arc<ServerState>> for DispatchingBody<T> {
+    type Rejection = Error;
+
+    async fn from_request(req: Request, state: &Arc<ServerState>) -> Result<Self, Self::Rejection> {
+        let format = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(BodyFormat::from_media_type);
+        if format != Some(BodyFormat::Json) {
+            let RequestBody(value) = RequestBody::from_request(req, state).await?;
+            return Ok(Self::Parsed(value));
+        }
+        let strict = is_strict(state);
+        let reader = body_reader(req);
+        let (parts_tx, parts) = mpsc::channel(32);
+        let parsed = tokio::task::spawn_blocking(move || {
+            let mut de = serde_json::Deserializer::from_reader(reader);
+            let body = PartsDeserializer {
+                de: &mut de,
+                parts: BodyParts::<T>::new(&parts_tx),
+            };
+            let parsed = match strict {
+                true => deserialize_strict::<T, _>(body)?,
+                false => (T::deserialize(body)?, Vec::new()),
+            };
+            de.end()?;
+            Ok(parsed)
+        });
+        Ok(Self::Streaming(StreamingBody { parts, parsed }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::orchestrator::Orchestrator;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct TestRequest {
+        content: String,
+    }
+
+    fn request(body: &'static str) -> Request {
+        Request::builder()
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_streaming_json() {
+        let state = Arc::new(ServerState::new(Orchestrator::default()));
+        let StreamingJson(value) =
+            StreamingJson::<TestRequest>::from_request(request(r#"{"content": "hello"}"#), &state)
+                .await
+                .unwrap();
+        assert_eq!(value.content, "hello");
+
+        let error =
+            StreamingJson::<TestRequest>::from_request(request(r#"{"text": "hello"}"#), &state)
+                .await
+                .unwrap_err();
+        assert!(
+            matches!(error, Error::JsonError(message) if message.starts_with("unknown field `text`"))
+        );
+
+        let error = StreamingJson::<TestRequest>::from_request(request(r#"{"content": "#), &state)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::InvalidRequestBody(_)));
+    }
+
+    #[tokio::test]
+    async fn test_request_body() {
+        let state = Arc::new(ServerState::new(Orchestrator::default()));
+        let request = |content_type: &str, body: Vec<u8>| {
+            Request::builder()
+                .header(http::header::CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let value = serde_json::json!({"content": "hello"});
+        for format in [BodyFormat::MessagePack, BodyFormat::Cbor] {
+            let body = format.encode(&value).unwrap();
+            let RequestBody(decoded) = RequestBody::<TestRequest>::from_request(
+                request(format.content_type(), body),
+                &state,
+            )
+            .await
+            .unwrap();
+            assert_eq!(decoded.content, "hello");
+
+            let body = format
+                .encode(&serde_json::json!({"text": "hello"}))
+                .unwrap();
+            let error = RequestBody::<TestRequest>::from_request(
+                request(format.content_type(), body),
+                &state,
+            )
+            .await
+            .unwrap_err();
+            assert!(matches!(error, Error::JsonError(_)));
+        }
+
+        let error =
+            RequestBody::<TestRequest>::from_request(request("text/plain", Vec::new()), &state)
+                .await
+                .unwrap_err();
+        assert!(matches!(error, Error::UnsupportedContentType(_)));
+    }
+
+    #[tokio::test]
+    async fn test_dispatching_body() {
+        let state = Arc::new(ServerState::new(Orchestrator::default()));
+
+        // Detectors are received as parsed, after the content
+        let (body_tx, body_rx) = mpsc::channel::<Result<Bytes, io::Error>>(1);
+        let req = Request::builder()
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from_stream(
+                tokio_stream::wrappers::ReceiverStream::new(body_rx),
+            ))
+            .unwrap();
+        let body = DispatchingBody::<TextContentDetectionHttpRequest>::from_request(req, &state)
+            .await
+            .unwrap();
+        let (parts_tx, mut parts) = mpsc::unbounded_channel();
+        let received = tokio::spawn(body.receive(move |part| parts_tx.send(part).unwrap()));
+        let chunk = r#"{"detectors": {"hap": {"threshold": 0.5}}, "content": "hi""#;
+        body_tx.send(Ok(Bytes::from(chunk))).await.unwrap();
+        assert_eq!(parts.recv().await, Some(BodyPart::Inputs("hi".to_string())));
+        let params: DetectorParams = serde_json::from_str(r#"{"threshold": 0.5}"#).unwrap();
+        assert_eq!(
+            parts.recv().await,
+            Some(BodyPart::Detector("hap".into(), params.clone()))
+        );
+        body_tx
+            .send(Ok(Bytes::from(r#", "detectors2": 1}"#)))
+            .await
+            .unwrap();
+        drop(body_tx);
+        // The unknown field is rejected once parsed
+        let error = received.await.unwrap().unwrap_err();
+        assert!(matches!(error, Error::JsonError(_)));
+
+        let body = DispatchingBody::<TextContentDetectionHttpRequest>::from_request(
+            request(r#"{"content": "hi", "detectors": {"hap": {"threshold": 0.5}}}"#),
+            &state,
+        )
+        .await
+        .unwrap();
+        let mut received = Vec::new();
+        let value = body.receive(|part| received.push(part)).await.unwrap();
+        assert_eq!(value.content, "hi");
+        assert_eq!(
+            value.detectors,
+            HashMap::from([("hap".into(), params.clone())])
+        );
+        assert_eq!(
+            received,
+            [
+                BodyPart::Inputs("hi".into()),
+                BodyPart::Detector("hap".into(), params)
+            ]
+        );
+
+        // Detectors are not dispatched for invalid inputs
+        let body = DispatchingBody::<TextContentDetectionHttpRequest>::from_request(
+            request(r#"{"detectors": {"hap": {}}, "content": ""}"#),
+            &state,
+        )
+        .await
+        .unwrap();
+        let mut received = Vec::new();
+        let value = body.receive(|part| received.push(part)).await.unwrap();
+        assert!(value.content.is_empty());
+        assert!(received.is_empty());
+
+        // Other formats are parsed in full
+        let format = BodyFormat::MessagePack;
+        let body = format
+            .encode(&serde_json::json!({"content": "hi", "detectors": {"hap": {}}}))
+            .unwrap();
+        let req = Request::builder()
+            .header(http::header::CONTENT_TYPE, format.content_type())
+            .body(Body::from(body))
+            .unwrap();
+        let body = DispatchingBody::<TextContentDetectionHttpRequest>::from_request(req, &state)
+            .await
+            .unwrap();
+        assert!(matches!(body, DispatchingBody::Parsed(_)));
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        #[derive(Debug, Deserialize)]
+        struct DetectionsRequest {
+            #[allow(dead_code)]
+            detectors: HashMap<String, HashMap<String, serde_json::Value>>,
+        }
+        let body = r#"{"detectors": {"hap": {}}, "detector_params": {"threshold": 0.5}}"#;
+        let (_, unknown_fields) = deserialize_strict::<DetectionsRequest, _>(
+            &mut serde_json::Deserializer::from_str(body),
+        )
+        .unwrap();
+        assert_eq!(unknown_fields, ["detector_params"]);
+        let error = reject_unknown_fields(unknown_fields).unwrap_err();
+        assert_eq!(error.to_string(), "unknown fields: `detector_params`");
+    }
+}
@@ -0,0 +1,80 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Feedback on detector results, counted in metrics and persisted for detector retraining.
+use serde::Serialize;
+use tracing::{info, warn};
+
+use super::sink::JsonLinesSink;
+use crate::{
+    config::{FeedbackConfig, SpoolConfig},
+    models::DetectorFeedbackHttpRequest,
+    orchestrator::common::current_timestamp,
+};
+
+/// Feedback record, appended to the feedback file as a JSON line.
+#[derive(Debug, Clone, Serialize)]
+struct FeedbackRecord<'a> {
+    id: &'a str,
+    /// Unix timestamp in seconds
+    timestamp: u64,
+    #[serde(flatten)]
+    feedback: &'a DetectorFeedbackHttpRequest,
+}
+
+/// Records detector feedback.
+#[derive(Debug)]
+pub struct DetectorFeedback {
+    sink: Option<JsonLinesSink>,
+}
+
+impl DetectorFeedback {
+    pub fn new(config: Option<&FeedbackConfig>, spool: Option<&SpoolConfig>) -> Self {
+        Self {
+            sink: config.map(|config| JsonLinesSink::new(config.path.clone(), spool.cloned())),
+        }
+    }
+
+    /// Records `feedback`, returning the ID of its record.
+    pub async fn record(&self, feedback: &DetectorFeedbackHttpRequest) -> String {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        info!(
+            feedback_id = %id,
+            detector_id = %feedback.detector_id,
+            kind = ?feedback.kind,
+            monotonic_counter.detector_feedback_count = 1,
+            "detector feedback received"
+        );
+        let record = FeedbackRecord {
+            id: &id,
+            timestamp: current_timestamp().as_secs(),
+            feedback,
+        };
+        let written = match &self.sink {
+            Some(sink) => sink.write(&record).await,
+            None => true,
+        };
+        if !written {
+            warn!(
+                feedback_id = %id,
+                monotonic_counter.dropped_feedback_count = 1,
+                "feedback buffer full, record dropped"
+            );
+        }
+        id
+    }
+}
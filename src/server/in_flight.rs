@@ -0,0 +1,144 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Table of in-flight guardrails server requests, for debugging stuck streams.
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Instant,
+};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use futures::StreamExt;
+use http_body::Body as _;
+use serde::Serialize;
+
+use super::ServerState;
+use crate::utils::trace::current_trace_id;
+
+/// Requests being handled by the guardrails server.
+#[derive(Debug, Default)]
+pub struct InFlightRequests {
+    next_id: AtomicU64,
+    requests: Mutex<HashMap<u64, InFlightEntry>>,
+}
+
+#[derive(Debug)]
+struct InFlightEntry {
+    trace_id: String,
+    method: String,
+    path: String,
+    started: Instant,
+    /// Whether response headers were sent, i.e. the response body is streaming
+    responding: bool,
+}
+
+/// An in-flight request.
+#[derive(Debug, Clone, Serialize)]
+pub struct InFlightRequest {
+    pub trace_id: String,
+    pub method: String,
+    pub path: String,
+    pub duration_ms: u64,
+    /// Whether response headers were sent, i.e. the response body is streaming
+    pub responding: bool,
+}
+
+impl InFlightRequests {
+    /// Returns in-flight requests, longest running first.
+    pub fn snapshot(&self) -> Vec<InFlightRequest> {
+        let mut requests = self
+            .requests
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| InFlightRequest {
+                trace_id: entry.trace_id.clone(),
+                method: entry.method.clone(),
+                path: entry.path.clone(),
+                duration_ms: entry.started.elapsed().as_millis() as u64,
+                responding: entry.responding,
+            })
+            .collect::<Vec<_>>();
+        requests.sort_by_key(|request| std::cmp::Reverse(request.duration_ms));
+        requests
+    }
+
+    fn insert(self: &Arc<Self>, entry: InFlightEntry) -> InFlightGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.requests.lock().unwrap().insert(id, entry);
+        InFlightGuard {
+            requests: self.clone(),
+            id,
+        }
+    }
+}
+
+/// Removes a request from the table when dropped.
+struct InFlightGuard {
+    requests: Arc<InFlightRequests>,
+    id: u64,
+}
+
+impl InFlightGuard {
+    fn set_responding(&self) {
+        if let Some(entry) = self.requests.requests.lock().unwrap().get_mut(&self.id) {
+            entry.responding = true;
+        }
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.requests.requests.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Tracks a request in the in-flight table until its response body completes or is dropped.
+pub async fn track_in_flight(
+    State(state): State<Arc<ServerState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let guard = state.in_flight.insert(InFlightEntry {
+        trace_id: current_trace_id().to_string(),
+        method: request.method().to_string(),
+        path: request.uri().path().to_string(),
+        started: Instant::now(),
+        responding: false,
+    });
+    let response = next.run(request).await;
+    if response.body().size_hint().exact().is_some() {
+        // Complete response, tracking ends
+        return response;
+    }
+    guard.set_responding();
+    response.map(|body| {
+        Body::from_stream(body.into_data_stream().map(move |chunk| {
+            let _guard = &guard;
+            chunk
+        }))
+    })
+}
@@ -0,0 +1,317 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! In-process manager of asynchronous generation jobs, run from a bounded queue
+//! with results retained for a limited time.
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::{sync::Semaphore, time::Instant};
+use tracing::{Instrument, info, info_span, warn};
+use url::Url;
+
+use super::Error;
+use crate::{
+    clients::dns::{ReqwestResolver, egress_may_allow},
+    config::JobsConfig,
+    models::{ClassifiedGeneratedTextResult, GenerationJob, JobError, JobStatus},
+    orchestrator,
+};
+
+/// Timeout of completion callback requests.
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Generation jobs submitted to the guardrails server.
+#[derive(Debug)]
+pub struct JobManager {
+    config: JobsConfig,
+    jobs: Mutex<HashMap<String, JobEntry>>,
+    /// Number of jobs waiting to run
+    queued: AtomicUsize,
+    /// Permits of running jobs
+    running: Arc<Semaphore>,
+    callback_client: reqwest::Client,
+}
+
+#[derive(Debug)]
+struct JobEntry {
+    job: GenerationJob,
+    /// API consumer that submitted the job, if authenticated
+    consumer: Option<String>,
+    /// Time the result expires, once the job completed or failed
+    expires_at: Option<Instant>,
+}
+
+impl JobManager {
+    pub fn new(config: JobsConfig) -> Self {
+        let callback_client = reqwest::Client::builder()
+            .timeout(CALLBACK_TIMEOUT)
+            // Redirects could lead callbacks to hosts not allowed
+            .redirect(reqwest::redirect::Policy::none())
+            // Resolved addresses are checked against the egress policy
+            .dns_resolver(Arc::new(ReqwestResolver))
+            .build()
+            .expect("callback client should build");
+        Self {
+            running: Arc::new(Semaphore::new(config.max_concurrent)),
+            config,
+            jobs: Mutex::default(),
+            queued: AtomicUsize::new(0),
+            callback_client,
+        }
+    }
+
+    /// Parses a callback URL, validating it is allowed by the `jobs.callbacks` config and
+    /// the egress policy.
+    pub fn callback_url(&self, callback_url: &str) -> Result<Url, Error> {
+        let url = Url::parse(callback_url)
+            .map_err(|error| Error::Validation(format!("invalid `callback_url`: {error}")))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(Error::Validation(
+                "`callback_url` must be an http or https URL".into(),
+            ));
+        }
+        match url.host_str() {
+            Some(host) if self.config.allows_callback(host) && egress_may_allow(host) => Ok(url),
+            _ => Err(Error::Validation(format!(
+                "callbacks to `{callback_url}` are not allowed"
+            ))),
+        }
+    }
+
+    /// Returns the job of `id` submitted by `consumer`, if found and its result not
+    /// expired. Jobs of other consumers are not found.
+    pub fn get(&self, id: &str, consumer: Option<&str>) -> Option<GenerationJob> {
+        let now = Instant::now();
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(id)
+            .filter(|entry| entry.expires_at.is_none_or(|expires_at| expires_at > now))
+            .filter(|entry| entry.consumer.as_deref() == consumer)
+            .map(|entry| entry.job.clone())
+    }
+
+    /// Queues a job of `consumer` running `run` once fewer than `max_concurrent` jobs are
+    /// running, sending the job to `callback_url` once it completes or fails.
+    /// Fails if `max_queued` jobs are already waiting.
+    pub fn submit<F>(
+        self: &Arc<Self>,
+        run: F,
+        callback_url: Option<Url>,
+        consumer: Option<String>,
+    ) -> Result<GenerationJob, Error>
+    where
+        F: Future<Output = Result<ClassifiedGeneratedTextResult, orchestrator::Error>>
+            + Send
+            + 'static,
+    {
+        let max_queued = self.config.max_queued;
+        self.queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                (queued < max_queued).then_some(queued + 1)
+            })
+            .map_err(|_| Error::ServiceUnavailable("job queue is full".into()))?;
+        let job = GenerationJob {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            status: JobStatus::Queued,
+            result: None,
+            error: None,
+        };
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            let now = Instant::now();
+            jobs.retain(|_, entry| entry.expires_at.is_none_or(|expires_at| expires_at > now));
+            jobs.insert(
+                job.id.clone(),
+                JobEntry {
+                    job: job.clone(),
+                    consumer,
+                    expires_at: None,
+                },
+            );
+        }
+        info!(job_id = %job.id, "generation job queued");
+        let span = info_span!("generation_job", job_id = %job.id);
+        let manager = self.clone();
+        let id = job.id.clone();
+        tokio::spawn(
+            async move {
+                let _permit = manager.running.clone().acquire_owned().await.unwrap();
+                manager.queued.fetch_sub(1, Ordering::SeqCst);
+                manager.update(&id, JobStatus::Running, None, None);
+                let job = match run.await {
+                    Ok(result) => manager.update(&id, JobStatus::Completed, Some(result), None),
+                    Err(error) => {
+                        let (code, details) = Error::from(error).into_parts();
+                        let error = JobError {
+                            code: code.as_u16(),
+                            details,
+                        };
+                        manager.update(&id, JobStatus::Failed, None, Some(error))
+                    }
+                };
+                info!(status = ?job.status, "generation job finished");
+                if let Some(callback_url) = callback_url {
+                    manager.send_callback(callback_url, &job).await;
+                }
+            }
+            .instrument(span),
+        );
+        Ok(job)
+    }
+
+    /// Updates the job of `id`, setting its result to expire once it completes or fails.
+    fn update(
+        &self,
+        id: &str,
+        status: JobStatus,
+        result: Option<ClassifiedGeneratedTextResult>,
+        error: Option<JobError>,
+    ) -> GenerationJob {
+        let job = GenerationJob {
+            id: id.to_string(),
+            status,
+            result,
+            error,
+        };
+        let expires_at = matches!(status, JobStatus::Completed | JobStatus::Failed)
+            .then(|| Instant::now() + Duration::from_secs(self.config.result_ttl));
+        if let Some(entry) = self.jobs.lock().unwrap().get_mut(id) {
+            entry.job = job.clone();
+            entry.expires_at = expires_at;
+        }
+        job
+    }
+
+    /// Sends `job` to `callback_url`. Failures are logged and not retried.
+    async fn send_callback(&self, callback_url: Url, job: &GenerationJob) {
+        let result = self
+            .callback_client
+            .post(callback_url.clone())
+            .json(job)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(error) = result {
+            warn!(%callback_url, %error, "generation job callback failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::EgressPolicy, utils::test_server::ensure_crypto_provider};
+
+    async fn wait_for(manager: &JobManager, id: &str, status: JobStatus) -> GenerationJob {
+        loop {
+            let job = manager.get(id, Some("tenant-a")).unwrap();
+            if job.status == status {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_job_manager() {
+        ensure_crypto_provider();
+        let manager = Arc::new(JobManager::new(JobsConfig {
+            max_concurrent: 1,
+            max_queued: 1,
+            ..Default::default()
+        }));
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let first = manager
+            .submit(
+                async move {
+                    let _ = rx.await;
+                    Ok(ClassifiedGeneratedTextResult::default())
+                },
+                None,
+                Some("tenant-a".into()),
+            )
+            .unwrap();
+        assert_eq!(first.status, JobStatus::Queued);
+        wait_for(&manager, &first.id, JobStatus::Running).await;
+
+        let second = manager
+            .submit(
+                async { Err(orchestrator::Error::DetectorNotFound("hap".into())) },
+                None,
+                Some("tenant-a".into()),
+            )
+            .unwrap();
+        // Queue is full while the second job waits for the first
+        let error = manager
+            .submit(
+                async { Ok(ClassifiedGeneratedTextResult::default()) },
+                None,
+                Some("tenant-a".into()),
+            )
+            .unwrap_err();
+        assert!(matches!(error, Error::ServiceUnavailable(_)));
+
+        tx.send(()).unwrap();
+        let first = wait_for(&manager, &first.id, JobStatus::Completed).await;
+        assert_eq!(first.result, Some(ClassifiedGeneratedTextResult::default()));
+        let second = wait_for(&manager, &second.id, JobStatus::Failed).await;
+        assert_eq!(
+            second.error,
+            Some(JobError {
+                code: 404,
+                details: "detector `hap` not found".into(),
+            })
+        );
+        assert!(manager.get("unknown", Some("tenant-a")).is_none());
+        // Jobs of other consumers are not found
+        assert!(manager.get(&first.id, Some("tenant-b")).is_none());
+        assert!(manager.get(&first.id, None).is_none());
+    }
+
+    #[test]
+    fn test_callback_url() {
+        ensure_crypto_provider();
+        let manager = JobManager::new(JobsConfig {
+            callbacks: Some(EgressPolicy {
+                hosts: vec!["hooks.example.com".into()],
+                cidrs: vec![],
+            }),
+            ..Default::default()
+        });
+        assert!(
+            manager
+                .callback_url("https://hooks.example.com/jobs")
+                .is_ok()
+        );
+        assert!(manager.callback_url("https://example.com/jobs").is_err());
+        assert!(
+            manager
+                .callback_url("file://hooks.example.com/jobs")
+                .is_err()
+        );
+        assert!(manager.callback_url("not a url").is_err());
+    }
+}
@@ -0,0 +1,473 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Validation of OIDC bearer tokens with keys of the JWKS of their issuer.
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    jwk::{Jwk, JwkSet},
+};
+use serde::Deserialize;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn};
+
+use crate::{
+    clients::dns::{ReqwestResolver, egress_may_allow},
+    config::JwtConfig,
+};
+
+/// Minimum interval between fetches of the JWKS for tokens signed with unknown keys.
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+/// Timeout of JWKS and OIDC discovery requests.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Claims of a validated token, added to request extensions.
+pub type Claims = serde_json::Map<String, serde_json::Value>;
+
+#[derive(Debug, Deserialize)]
+struct OidcDiscovery {
+    jwks_uri: String,
+}
+
+#[derive(Debug)]
+struct CachedJwks {
+    fetched_at: Instant,
+    jwks: JwkSet,
+}
+
+/// Validates tokens of an issuer, caching its JWKS.
+#[derive(Debug)]
+pub struct JwtValidator {
+    config: JwtConfig,
+    client: reqwest::Client,
+    jwks: RwLock<Option<CachedJwks>>,
+    /// Time of the last fetch of the JWKS, held while fetching
+    last_fetch: Mutex<Option<Instant>>,
+}
+
+impl JwtValidator {
+    pub fn new(config: JwtConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            // Resolved addresses are checked against the egress policy
+            .dns_resolver(Arc::new(ReqwestResolver))
+            .build()
+            .expect("JWKS client should build");
+        Self {
+            config,
+            client,
+            jwks: RwLock::default(),
+            last_fetch: Mutex::default(),
+        }
+    }
+
+    /// Returns the name of the consumer of a request with `claims`: the consumer
+    /// claim, falling back to `sub`.
+    pub fn consumer(&self, claims: &Claims) -> Option<String> {
+        [self.config.consumer_claim.as_str(), "sub"]
+            .into_iter()
+            .find_map(|claim| claims.get(claim)?.as_str())
+            .map(String::from)
+    }
+
+    /// Returns the names of request headers set to claims.
+    pub fn claim_header_names(&self) -> impl Iterator<Item = &str> {
+        self.config.claim_headers.values().map(String::as_str)
+    }
+
+    /// Returns request headers set to claims by header, `None` if the claim is missing.
+    pub fn claim_headers<'a>(
+        &'a self,
+        claims: &'a Claims,
+    ) -> impl Iterator<Item = (&'a str, Option<String>)> + 'a {
+        self.config.claim_headers.iter().map(|(claim, header)| {
+            let value = claims.get(claim).map(|value| match value {
+                serde_json::Value::String(value) => value.clone(),
+                value => value.to_string(),
+            });
+            (header.as_str(), value)
+        })
+    }
+
+    /// Validates the signature, expiry, issuer and audience of `token`, returning its claims.
+    pub async fn validate(&self, token: &str) -> Result<Claims, String> {
+        let header = decode_header(token).map_err(|error| error.to_string())?;
+        // Only asymmetric algorithms are accepted, as JWKS keys are public
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(format!("unsupported algorithm `{:?}`", header.alg));
+        }
+        let jwk = self
+            .key(header.kid.as_deref())
+            .await
+            .ok_or_else(|| "unknown signing key".to_string())?;
+        self.check_algorithm(&jwk, header.alg)?;
+        let key = DecodingKey::from_jwk(&jwk).map_err(|error| error.to_string())?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        if self.config.audiences.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&self.config.audiences[..]);
+        }
+        decode::<Claims>(token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|error| error.to_string())
+    }
+
+    /// Checks a token signed with `jwk` uses the algorithm of the key or, if the key has
+    /// none, an allowed algorithm, rather than trusting the algorithm of the token header.
+    fn check_algorithm(&self, jwk: &Jwk, alg: Algorithm) -> Result<(), String> {
+        let allowed = match &jwk.common.key_algorithm {
+            // Both name algorithms by their JWA names
+            Some(key_algorithm) => format!("{key_algorithm:?}") == format!("{alg:?}"),
+            None => self.config.algorithms.contains(&alg),
+        };
+        match allowed {
+            true => Ok(()),
+            false => Err(format!("algorithm `{alg:?}` not allowed for signing key")),
+        }
+    }
+
+    /// Returns the key with `kid`, or the only key if the token has no key ID. The JWKS is
+    /// re-fetched once the refresh interval elapses, or sooner for unknown keys.
+    ///
+    /// The JWKS is fetched without holding its lock, one fetch at a time. While it is
+    /// re-fetched, keys of the previous JWKS remain in use.
+    async fn key(&self, kid: Option<&str>) -> Option<Jwk> {
+        let refresh_interval = Duration::from_secs(self.config.jwks_refresh_interval);
+        let cached_key = match self.jwks.read().await.as_ref() {
+            Some(cached) => {
+                let key = find_key(&cached.jwks, kid);
+                let elapsed = cached.fetched_at.elapsed();
+                if elapsed < MIN_REFETCH_INTERVAL || (key.is_some() && elapsed < refresh_interval) {
+                    return key;
+                }
+                key
+            }
+            None => None,
+        };
+        let mut last_fetch = match &cached_key {
+            // Stale keys are used while another request re-fetches the JWKS
+            Some(key) => match self.last_fetch.try_lock() {
+                Ok(last_fetch) => last_fetch,
+                Err(_) => return Some(key.clone()),
+            },
+            None => self.last_fetch.lock().await,
+        };
+        // Fetched, or failed to, while waiting for the fetch of another request
+        if last_fetch.is_some_and(|last_fetch| last_fetch.elapsed() < MIN_REFETCH_INTERVAL) {
+            return self
+                .jwks
+                .read()
+                .await
+                .as_ref()
+                .and_then(|cached| find_key(&cached.jwks, kid));
+        }
+        *last_fetch = Some(Instant::now());
+        match self.fetch().await {
+            Ok(fetched) => {
+                info!(
+                    issuer = self.config.issuer,
+                    keys = fetched.keys.len(),
+                    "fetched JWKS"
+                );
+                let key = find_key(&fetched, kid);
+                *self.jwks.write().await = Some(CachedJwks {
+                    fetched_at: Instant::now(),
+                    jwks: fetched,
+                });
+                key
+            }
+            Err(error) => {
+                // Keeps the previous JWKS, if any
+                warn!(issuer = self.config.issuer, %error, "failed to fetch JWKS");
+                cached_key
+            }
+        }
+    }
+
+    /// Fetches the JWKS, discovering its URL from the issuer if not configured.
+    /// Discovered URLs must be allowed by the egress policy.
+    async fn fetch(&self) -> Result<JwkSet, String> {
+        let jwks_uri = match &self.config.jwks_uri {
+            Some(jwks_uri) => jwks_uri.clone(),
+            None => {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.config.issuer.trim_end_matches('/')
+                );
+                let jwks_uri = self
+                    .get::<OidcDiscovery>(&url)
+                    .await
+                    .map_err(|error| error.to_string())?
+                    .jwks_uri;
+                let allowed = url::Url::parse(&jwks_uri)
+                    .ok()
+                    .and_then(|url| url.host_str().map(egress_may_allow))
+                    .unwrap_or(false);
+                if !allowed {
+                    return Err(format!(
+                        "discovered `jwks_uri` `{jwks_uri}` is not allowed by the egress policy"
+                    ));
+                }
+                jwks_uri
+            }
+        };
+        self.get(&jwks_uri).await.map_err(|error| error.to_string())
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, reqwest::Error> {
+        self.client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+}
+
+fn find_key(jwks: &JwkSet, kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => jwks.find(kid).cloned(),
+        None if jwks.keys.len() == 1 => jwks.keys.first().cloned(),
+        None => None,
+    }
+}
+
+/// Issuer of tokens in tests, serving its OIDC discovery document and JWKS.
+#[cfg(test)]
+pub(super) mod test_issuer {
+    use std::{
+        collections::HashMap,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use axum::{Json, Router, extract::State, http::HeaderMap, routing::get};
+    use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+    use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+    use serde_json::{Value, json};
+
+    use crate::{config::JwtConfig, utils::test_server::serve};
+
+    #[derive(Default)]
+    struct IssuerState {
+        /// Signing keys by key ID, as PKCS#8 documents
+        keys: std::sync::Mutex<Vec<(String, Vec<u8>)>>,
+        jwks_fetches: AtomicUsize,
+    }
+
+    /// Issuer signing tokens with Ed25519 keys.
+    pub struct TestIssuer {
+        pub url: String,
+        state: Arc<IssuerState>,
+    }
+
+    impl TestIssuer {
+        /// Starts an issuer with the signing key `key-1`.
+        pub async fn start() -> Self {
+            let state = Arc::new(IssuerState::default());
+            let app = Router::new()
+                .route(
+                    "/.well-known/openid-configuration",
+                    get(|headers: HeaderMap| async move {
+                        let host = headers["host"].to_str().unwrap().to_string();
+                        Json(json!({ "jwks_uri": format!("http://{host}/certs") }))
+                    }),
+                )
+                .route("/certs", get(jwks))
+                .with_state(state.clone());
+            let port = serve(app).await;
+            let issuer = Self {
+                url: format!("http://localhost:{port}"),
+                state,
+            };
+            issuer.rotate("key-1");
+            issuer
+        }
+
+        /// Replaces the signing key with a new key `kid`.
+        pub fn rotate(&self, kid: &str) {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+            *self.state.keys.lock().unwrap() = vec![(kid.into(), pkcs8.as_ref().to_vec())];
+        }
+
+        /// Returns the number of fetches of the JWKS.
+        pub fn jwks_fetches(&self) -> usize {
+            self.state.jwks_fetches.load(Ordering::SeqCst)
+        }
+
+        /// Returns the config of validating tokens of this issuer, discovering its JWKS.
+        pub fn config(&self) -> JwtConfig {
+            JwtConfig {
+                issuer: self.url.clone(),
+                audiences: vec!["guardrails".into()],
+                jwks_uri: None,
+                jwks_refresh_interval: 300,
+                algorithms: vec![Algorithm::RS256],
+                consumer_claim: "azp".into(),
+                claim_headers: HashMap::from([("tenant".into(), "x-tenant-id".into())]),
+            }
+        }
+
+        /// Returns claims of a valid token for `guardrails`, with `claims`.
+        pub fn claims(&self, claims: Value) -> Value {
+            let exp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                + 60;
+            let mut valid = json!({ "iss": self.url, "aud": "guardrails", "exp": exp });
+            valid
+                .as_object_mut()
+                .unwrap()
+                .extend(claims.as_object().unwrap().clone());
+            valid
+        }
+
+        /// Signs a token with `claims` and the current signing key.
+        pub fn token(&self, claims: &Value) -> String {
+            let keys = self.state.keys.lock().unwrap();
+            let (kid, pkcs8) = &keys[0];
+            let mut header = Header::new(Algorithm::EdDSA);
+            header.kid = Some(kid.clone());
+            encode(&header, claims, &EncodingKey::from_ed_der(pkcs8)).unwrap()
+        }
+    }
+
+    async fn jwks(State(state): State<Arc<IssuerState>>) -> Json<Value> {
+        state.jwks_fetches.fetch_add(1, Ordering::SeqCst);
+        let keys = state
+            .keys
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(kid, pkcs8)| {
+                let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8).unwrap();
+                json!({
+                    "kty": "OKP",
+                    "crv": "Ed25519",
+                    "kid": kid,
+                    "alg": "EdDSA",
+                    "x": URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref()),
+                })
+            })
+            .collect::<Vec<_>>();
+        Json(json!({ "keys": keys }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{test_issuer::TestIssuer, *};
+
+    /// Moves the last fetch of the JWKS of `validator` back by `by`.
+    async fn expire(validator: &JwtValidator, by: Duration) {
+        let backdate = |instant: Instant| instant.checked_sub(by).unwrap();
+        if let Some(cached) = validator.jwks.write().await.as_mut() {
+            cached.fetched_at = backdate(cached.fetched_at);
+        }
+        let mut last_fetch = validator.last_fetch.lock().await;
+        *last_fetch = last_fetch.map(backdate);
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let issuer = TestIssuer::start().await;
+        let validator = JwtValidator::new(issuer.config());
+
+        let token = issuer.token(&issuer.claims(json!({"sub": "user-1", "tenant": "acme"})));
+        let claims = validator.validate(&token).await.unwrap();
+        assert_eq!(validator.consumer(&claims), Some("user-1".into()));
+        assert_eq!(
+            validator.claim_headers(&claims).collect::<Vec<_>>(),
+            [("x-tenant-id", Some("acme".to_string()))]
+        );
+        let claims = validator
+            .validate(&issuer.token(&issuer.claims(json!({}))))
+            .await
+            .unwrap();
+        assert_eq!(
+            validator.claim_headers(&claims).collect::<Vec<_>>(),
+            [("x-tenant-id", None)]
+        );
+
+        // Tokens signed with algorithms not allowed are rejected
+        validator.jwks.write().await.as_mut().unwrap().jwks.keys[0]
+            .common
+            .key_algorithm = None;
+        let error = validator.validate(&token).await.unwrap_err();
+        assert_eq!(error, "algorithm `EdDSA` not allowed for signing key");
+        expire(&validator, Duration::from_secs(300)).await;
+
+        // Tokens of other audiences, other issuers or expired are rejected
+        let valid = issuer.claims(json!({}));
+        for claims in [
+            json!({"aud": "other"}),
+            json!({"iss": "https://other.example.com"}),
+            json!({"exp": valid["exp"].as_u64().unwrap() - 3600}),
+        ] {
+            let token = issuer.token(&issuer.claims(claims));
+            assert!(validator.validate(&token).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jwks_discovery() {
+        let issuer = TestIssuer::start().await;
+        let validator = JwtValidator::new(issuer.config());
+        let claims = issuer.claims(json!({"sub": "user-1"}));
+
+        // The JWKS is discovered and fetched once, then cached
+        for _ in 0..2 {
+            assert!(validator.validate(&issuer.token(&claims)).await.is_ok());
+        }
+        assert_eq!(issuer.jwks_fetches(), 1);
+
+        // Tokens signed with unknown keys re-fetch the JWKS, at most once per interval
+        issuer.rotate("key-2");
+        let token = issuer.token(&claims);
+        let error = validator.validate(&token).await.unwrap_err();
+        assert_eq!(error, "unknown signing key");
+        assert_eq!(issuer.jwks_fetches(), 1);
+        expire(&validator, MIN_REFETCH_INTERVAL).await;
+        assert!(validator.validate(&token).await.is_ok());
+        assert_eq!(issuer.jwks_fetches(), 2);
+
+        // The JWKS is re-fetched once the refresh interval elapses
+        expire(&validator, Duration::from_secs(300)).await;
+        assert!(validator.validate(&token).await.is_ok());
+        assert_eq!(issuer.jwks_fetches(), 3);
+    }
+}
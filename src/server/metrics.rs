@@ -0,0 +1,65 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Request metrics of guardrails server routes.
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::info;
+
+use super::auth::ApiConsumer;
+use crate::utils::exemplars;
+
+/// Counts requests and records their latency per route, method and response status.
+///
+/// For streaming responses, latency is the time to the response headers. Requests to
+/// unknown routes are not recorded, to bound the cardinality of the route label.
+/// Requests authenticated with an API key are labeled with their consumer.
+///
+/// Latency is also recorded in a histogram with exemplars of the request trace.
+pub async fn record_route_metrics(request: Request, next: Next) -> Response {
+    let Some(route) = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+    else {
+        return next.run(request).await;
+    };
+    let method = request.method().to_string();
+    let consumer = request
+        .extensions()
+        .get::<ApiConsumer>()
+        .map(|consumer| consumer.0.clone())
+        .unwrap_or_default();
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let latency = start.elapsed();
+    exemplars::observe_route(&route, latency);
+    info!(
+        monotonic_counter.route_request_count = 1,
+        histogram.route_request_duration = latency.as_millis() as u64,
+        route = %route,
+        method = %method,
+        status = response.status().as_u16(),
+        consumer = %consumer,
+    );
+    response
+}
@@ -0,0 +1,28 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Playground web page to try detectors, enabled with the `playground` feature.
+//!
+//! The page is embedded in the binary and calls the guardrails API from the browser.
+use axum::{Router, response::Html, routing::get};
+
+const INDEX: &str = include_str!("playground/index.html");
+
+/// Creates playground router.
+pub fn playground_router<S: Clone + Send + Sync + 'static>() -> Router<S> {
+    Router::new().route("/playground", get(|| async { Html(INDEX) }))
+}
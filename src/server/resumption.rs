@@ -0,0 +1,434 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Server-side buffers of streaming responses, letting clients that reconnect within a
+//! window resume from a checkpoint instead of restarting generation.
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex, RwLock, Weak},
+    time::Duration,
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use futures::{
+    Stream, StreamExt,
+    stream::{self, BoxStream},
+};
+use tokio::{
+    sync::{mpsc, watch},
+    time::Instant,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{Instrument, debug, warn};
+
+use super::Error;
+use crate::{
+    config::StreamResumptionConfig, models::ClassifiedGeneratedTextStreamResult, orchestrator,
+};
+
+/// Message of a streaming classification with text generation response.
+pub type StreamMessage = Result<ClassifiedGeneratedTextStreamResult, orchestrator::Error>;
+
+/// Item of a resumable stream.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResumableItem {
+    /// Streamed message
+    Message(StreamMessage),
+    /// Token resuming the stream after the preceding message
+    Checkpoint(String),
+}
+
+/// Number of messages buffered for the client of the request, applying backpressure to
+/// generation while it is connected.
+const LIVE_BUFFER_SIZE: usize = 32;
+
+type Streams = Mutex<HashMap<String, Arc<BufferedStream>>>;
+
+/// Buffered streams by ID, expiring once the window elapsed after they completed or
+/// after their last message. Expired streams are pruned in the background.
+#[derive(Debug)]
+pub struct ResumableStreams {
+    window: Duration,
+    checkpoint_interval: usize,
+    max_streams: usize,
+    max_messages: usize,
+    streams: Arc<Streams>,
+}
+
+#[derive(Debug)]
+struct BufferedStream {
+    /// API consumer of the request, the only caller resuming the stream
+    consumer: Option<String>,
+    buffer: RwLock<Buffer>,
+    /// Number of messages streamed and whether the stream completed
+    progress: watch::Sender<(usize, bool)>,
+    /// Time the buffer expires
+    expires_at: Mutex<Instant>,
+}
+
+/// Latest messages of a stream.
+#[derive(Debug, Default)]
+struct Buffer {
+    /// Offset of the first buffered message, i.e. the number of dropped messages
+    start: usize,
+    messages: VecDeque<StreamMessage>,
+}
+
+impl BufferedStream {
+    fn is_expired(&self, now: Instant) -> bool {
+        *self.expires_at.lock().unwrap() <= now
+    }
+}
+
+impl ResumableStreams {
+    /// Creates stream buffers, pruning expired streams in a spawned task until dropped.
+    pub fn new(config: &StreamResumptionConfig) -> Self {
+        let window = Duration::from_secs(config.window);
+        let streams = Arc::new(Streams::default());
+        tokio::spawn(prune(Arc::downgrade(&streams), window));
+        Self {
+            window,
+            checkpoint_interval: config.checkpoint_interval,
+            max_streams: config.max_streams,
+            max_messages: config.max_messages,
+            streams,
+        }
+    }
+
+    /// Buffers `stream` of `consumer`, consuming it in a spawned task until it completes,
+    /// even if the client disconnects. Returns its messages interleaved with checkpoints,
+    /// sent before the message following each checkpoint interval. Streams are not
+    /// buffered while the maximum number of streams is buffered.
+    pub fn buffer(
+        &self,
+        mut stream: impl Stream<Item = StreamMessage> + Send + Unpin + 'static,
+        consumer: Option<String>,
+    ) -> BoxStream<'static, ResumableItem> {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let buffered = Arc::new(BufferedStream {
+            consumer,
+            buffer: RwLock::default(),
+            progress: watch::Sender::new((0, false)),
+            expires_at: Mutex::new(Instant::now() + self.window),
+        });
+        {
+            let mut streams = self.streams.lock().unwrap();
+            let now = Instant::now();
+            streams.retain(|_, buffered| !buffered.is_expired(now));
+            if streams.len() >= self.max_streams {
+                warn!(
+                    max_streams = self.max_streams,
+                    "resumable streams full, stream not resumable"
+                );
+                return stream.map(ResumableItem::Message).boxed();
+            }
+            streams.insert(id.clone(), buffered.clone());
+        }
+        let window = self.window;
+        let max_messages = self.max_messages;
+        let (live_tx, live_rx) = mpsc::channel(LIVE_BUFFER_SIZE);
+        tokio::spawn(
+            async move {
+                let mut live_tx = Some(live_tx);
+                while let Some(message) = stream.next().await {
+                    {
+                        let mut buffer = buffered.buffer.write().unwrap();
+                        if buffer.messages.len() == max_messages {
+                            buffer.messages.pop_front();
+                            buffer.start += 1;
+                        }
+                        buffer.messages.push_back(message.clone());
+                    }
+                    *buffered.expires_at.lock().unwrap() = Instant::now() + window;
+                    buffered.progress.send_modify(|(len, _)| *len += 1);
+                    // Stops sending once the client disconnected
+                    if let Some(tx) = &live_tx {
+                        if tx.send(message).await.is_err() {
+                            live_tx = None;
+                        }
+                    }
+                }
+                *buffered.expires_at.lock().unwrap() = Instant::now() + window;
+                buffered
+                    .progress
+                    .send_modify(|(_, completed)| *completed = true);
+            }
+            .in_current_span(),
+        );
+        let checkpoint_interval = self.checkpoint_interval;
+        ReceiverStream::new(live_rx)
+            .enumerate()
+            .flat_map(move |(offset, message)| {
+                let mut items = Vec::with_capacity(2);
+                if offset > 0 && offset % checkpoint_interval == 0 {
+                    items.push(ResumableItem::Checkpoint(token(&id, offset)));
+                }
+                items.push(ResumableItem::Message(message));
+                stream::iter(items)
+            })
+            .boxed()
+    }
+
+    /// Resumes a buffered stream of `consumer` after the checkpoint of `token`. Streams
+    /// of other consumers are not found.
+    pub fn resume(
+        &self,
+        token: &str,
+        consumer: Option<&str>,
+    ) -> Result<BoxStream<'static, ResumableItem>, Error> {
+        let invalid = || Error::Validation(format!("invalid resumption token `{token}`"));
+        let not_found =
+            || Error::NotFound(format!("stream of resumption token `{token}` not found"));
+        let (id, offset) = parse_token(token).ok_or_else(invalid)?;
+        let buffered = self
+            .streams
+            .lock()
+            .unwrap()
+            .get(&id)
+            .filter(|buffered| !buffered.is_expired(Instant::now()))
+            .filter(|buffered| buffered.consumer.as_deref() == consumer)
+            .cloned()
+            .ok_or_else(not_found)?;
+        if offset > buffered.progress.borrow().0 {
+            return Err(invalid());
+        }
+        // Messages after the checkpoint were dropped from the buffer
+        if offset < buffered.buffer.read().unwrap().start {
+            return Err(not_found());
+        }
+        Ok(self.replay(id, buffered, offset))
+    }
+
+    /// Returns buffered messages from `offset`, followed by messages as they are buffered.
+    /// Ends with an error if messages were dropped before they were returned, as the
+    /// client lagged the maximum number of messages behind.
+    fn replay(
+        &self,
+        id: String,
+        buffered: Arc<BufferedStream>,
+        offset: usize,
+    ) -> BoxStream<'static, ResumableItem> {
+        let checkpoint_interval = self.checkpoint_interval;
+        let max_messages = self.max_messages;
+        let first = offset;
+        let progress = buffered.progress.subscribe();
+        stream::unfold(Some((offset, progress)), move |state| {
+            let id = id.clone();
+            let buffered = buffered.clone();
+            async move {
+                let (offset, mut progress) = state?;
+                loop {
+                    let (len, completed) = *progress.borrow_and_update();
+                    if offset < len {
+                        let message = {
+                            let buffer = buffered.buffer.read().unwrap();
+                            offset
+                                .checked_sub(buffer.start)
+                                .map(|index| buffer.messages[index].clone())
+                        };
+                        let Some(message) = message else {
+                            let error = orchestrator::Error::Other(format!(
+                                "stream consumer lagged more than {max_messages} messages behind"
+                            ));
+                            let items = vec![ResumableItem::Message(Err(error))];
+                            return Some((stream::iter(items), None));
+                        };
+                        let mut items = Vec::with_capacity(2);
+                        if offset > first && offset % checkpoint_interval == 0 {
+                            items.push(ResumableItem::Checkpoint(token(&id, offset)));
+                        }
+                        items.push(ResumableItem::Message(message));
+                        return Some((stream::iter(items), Some((offset + 1, progress))));
+                    }
+                    if completed || progress.changed().await.is_err() {
+                        return None;
+                    }
+                }
+            }
+        })
+        .flatten()
+        .boxed()
+    }
+}
+
+/// Removes expired streams every `interval` until the streams are dropped.
+async fn prune(streams: Weak<Streams>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let Some(buffered) = streams.upgrade() else {
+            return;
+        };
+        let mut streams = buffered.lock().unwrap();
+        let now = Instant::now();
+        let len = streams.len();
+        streams.retain(|_, buffered| !buffered.is_expired(now));
+        if streams.len() < len {
+            debug!(
+                pruned = len - streams.len(),
+                "expired resumable streams pruned"
+            );
+        }
+    }
+}
+
+/// Returns the opaque token resuming stream `id` after `offset` messages.
+fn token(id: &str, offset: usize) -> String {
+    URL_SAFE_NO_PAD.encode(format!("{id}:{offset}"))
+}
+
+/// Parses a resumption token into its stream ID and offset.
+fn parse_token(token: &str) -> Option<(String, usize)> {
+    let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(token).ok()?).ok()?;
+    let (id, offset) = decoded.split_once(':')?;
+    Some((id.to_string(), offset.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str) -> StreamMessage {
+        Ok(ClassifiedGeneratedTextStreamResult {
+            generated_text: Some(text.into()),
+            ..Default::default()
+        })
+    }
+
+    fn config() -> StreamResumptionConfig {
+        StreamResumptionConfig {
+            window: 60,
+            checkpoint_interval: 2,
+            max_streams: 10,
+            max_messages: 10,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume() {
+        let streams = ResumableStreams::new(&config());
+        let messages = ["a", "b", "c", "d"].map(message);
+        let items = streams
+            .buffer(stream::iter(messages.clone()), Some("team-a".into()))
+            .collect::<Vec<_>>()
+            .await;
+        let ResumableItem::Checkpoint(checkpoint) = &items[2] else {
+            panic!("expected checkpoint after 2 messages");
+        };
+        // No checkpoint after the last message
+        assert_eq!(items.len(), 5);
+
+        let resumed = streams
+            .resume(checkpoint, Some("team-a"))
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            resumed,
+            [
+                ResumableItem::Message(messages[2].clone()),
+                ResumableItem::Message(messages[3].clone()),
+            ]
+        );
+
+        // Streams are resumed by their consumer only
+        for consumer in [Some("team-b"), None] {
+            assert!(matches!(
+                streams.resume(checkpoint, consumer),
+                Err(Error::NotFound(_))
+            ));
+        }
+        assert!(matches!(
+            streams.resume("invalid", None),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            streams.resume(&token("unknown", 0), None),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        let streams = ResumableStreams::new(&StreamResumptionConfig {
+            max_streams: 1,
+            max_messages: 2,
+            ..config()
+        });
+        let messages = ["a", "b", "c", "d", "e"].map(message);
+        let items = streams
+            .buffer(stream::iter(messages.clone()), None)
+            .collect::<Vec<_>>()
+            .await;
+        // Checkpoints of dropped messages are not resumable
+        let ResumableItem::Checkpoint(checkpoint) = &items[2] else {
+            panic!("expected checkpoint after 2 messages");
+        };
+        assert!(matches!(
+            streams.resume(checkpoint, None),
+            Err(Error::NotFound(_))
+        ));
+        let ResumableItem::Checkpoint(checkpoint) = &items[5] else {
+            panic!("expected checkpoint after 4 messages");
+        };
+        let resumed = streams.resume(checkpoint, None).unwrap();
+        assert_eq!(resumed.count().await, 1);
+
+        // Streams are not buffered while full
+        let items = streams
+            .buffer(stream::iter(messages.clone()), None)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            items,
+            messages
+                .map(ResumableItem::Message)
+                .into_iter()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expiry() {
+        let streams = ResumableStreams::new(&StreamResumptionConfig {
+            window: 1,
+            ..config()
+        });
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        for text in ["a", "b", "c"] {
+            tx.unbounded_send(message(text)).unwrap();
+        }
+        let mut items = streams.buffer(rx, None);
+        let _ = items.next().await;
+        let _ = items.next().await;
+        let Some(ResumableItem::Checkpoint(checkpoint)) = items.next().await else {
+            panic!("expected checkpoint after 2 messages");
+        };
+        assert!(streams.resume(&checkpoint, None).is_ok());
+
+        // Incomplete streams expire once the window elapsed after their last message,
+        // pruned in the background
+        tokio::time::sleep(Duration::from_millis(2100)).await;
+        assert!(matches!(
+            streams.resume(&checkpoint, None),
+            Err(Error::NotFound(_))
+        ));
+        assert!(streams.streams.lock().unwrap().is_empty());
+        drop(tx);
+    }
+}
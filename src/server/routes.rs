@@ -21,9 +21,9 @@ use std::{
 };
 
 use axum::{
-    Json, Router,
-    extract::{Query, State},
-    http::HeaderMap,
+    Extension, Json, Router,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
     response::{
         IntoResponse, Response,
         sse::{Event, KeepAlive, Sse},
@@ -32,22 +32,38 @@ use axum::{
 };
 use axum_extra::{extract::WithRejection, json_lines::JsonLines};
 use futures::{
-    Stream, StreamExt,
+    StreamExt,
     stream::{self, BoxStream},
 };
+use serde::Serialize;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
-use tracing::info;
+use tracing::{error, info};
 
-use super::{Error, ServerState};
+use super::{
+    Error, ServerState,
+    auth::ApiConsumer,
+    extract::{Accept, BodyFormat, BodyPart, DispatchingBody, RequestBody, RequestJson},
+    resumption::{ResumableItem, StreamMessage},
+};
 use crate::{
     clients::openai::{ChatCompletionsRequest, ChatCompletionsResponse},
-    models::{self, InfoParams, InfoResponse, StreamingContentDetectionRequest},
+    models::{
+        self, DebugParams, DetectionsParams, DryRunParams, InfoParams, InfoResponse,
+        PendingDetectionsParams, StreamingContentDetectionRequest,
+    },
     orchestrator::{
         self,
+        common::{pending::DispatchedDetections, unfiltered},
         handlers::{chat_completions_detection::ChatCompletionsDetectionTask, *},
+        startup::StartupReport,
+    },
+    utils::{
+        self,
+        debug_info::{self, DebugInfo},
+        exemplars,
+        trace::{self, current_trace_id},
     },
-    utils::{self, trace::current_trace_id},
 };
 
 const PACKAGE_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -55,10 +71,15 @@ const PACKAGE_NAME: &str = env!("CARGO_PKG_NAME");
 
 /// Creates health router.
 pub fn health_router(state: Arc<ServerState>) -> Router {
-    Router::new()
+    let mut router = Router::new()
         .route("/health", get(health))
         .route("/info", get(info))
-        .with_state(state)
+        .route("/info/startup", get(startup_info));
+    if trace::prometheus_registry().is_some() {
+        info!("Enabling metrics endpoint");
+        router = router.route("/metrics", get(metrics));
+    }
+    router.with_state(state)
 }
 
 /// Creates guardrails router.
@@ -74,21 +95,50 @@ pub fn guardrails_router(state: Arc<ServerState>) -> Router {
             post(stream_classification_with_gen),
         )
         // v2 routes
+        .route(
+            "/api/v2/text/classification-generation",
+            post(classification_with_gen_v2),
+        )
+        .route(
+            "/api/v2/text/classification-generation/stream",
+            post(stream_classification_with_gen_v2),
+        )
         .route(
             "/api/v2/text/detection/stream-content",
             post(stream_content_detection),
         )
+        .route(
+            "/api/v2/text/detection/stream-input",
+            post(stream_input_detection),
+        )
         .route(
             "/api/v2/text/generation-detection",
             post(generation_with_detection),
         )
         .route("/api/v2/text/detection/content", post(detection_content))
+        .route(
+            "/api/v2/text/detection/content/pending/{continuation_token}",
+            get(pending_detection_content),
+        )
         .route("/api/v2/text/detection/chat", post(detect_chat))
         .route(
             "/api/v2/text/detection/context",
             post(detect_context_documents),
         )
-        .route("/api/v2/text/detection/generated", post(detect_generated));
+        .route("/api/v2/text/detection/generated", post(detect_generated))
+        .route("/api/v2/text/suitability", post(suitability))
+        .route("/api/v2/text/chunks", post(chunks))
+        .route("/api/v2/detectors", get(detectors))
+        .route("/api/v2/feedback", post(detector_feedback))
+        .route("/api/v2/jobs/generation", post(submit_generation_job))
+        .route("/api/v2/jobs/{id}", get(generation_job));
+    if state.resumable_streams.is_some() {
+        info!("Enabling stream resumption endpoint");
+        router = router.route(
+            "/api/v2/text/classification-generation/stream/resume/{resumption_token}",
+            get(resume_stream_classification_with_gen),
+        );
+    }
     if state.orchestrator.config().chat_generation.is_some() {
         info!("Enabling chat completions detection endpoint");
         router = router.route(
@@ -116,35 +166,175 @@ async fn info(
     Ok(Json(InfoResponse { services }))
 }
 
+/// Returns metrics in the Prometheus text format, or in the OpenMetrics format with trace
+/// exemplars of latency histograms if accepted by the client.
+async fn metrics(headers: HeaderMap) -> Result<Response, Error> {
+    let Some(registry) = trace::prometheus_registry() else {
+        return Err(Error::Unexpected);
+    };
+    let openmetrics = headers
+        .get_all(http::header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains("application/openmetrics-text"));
+    if openmetrics {
+        let body = exemplars::encode_openmetrics(&registry.gather());
+        return Ok((
+            [(
+                http::header::CONTENT_TYPE,
+                exemplars::OPENMETRICS_CONTENT_TYPE,
+            )],
+            body,
+        )
+            .into_response());
+    }
+    let encoder = prometheus::TextEncoder::new();
+    let mut body = String::new();
+    encoder
+        .encode_utf8(&registry.gather(), &mut body)
+        .map_err(|error| {
+            error!(%error, "error encoding metrics");
+            Error::Unexpected
+        })?;
+    Ok(([(http::header::CONTENT_TYPE, encoder.format_type())], body).into_response())
+}
+
+async fn startup_info(State(state): State<Arc<ServerState>>) -> Json<StartupReport> {
+    Json(state.orchestrator.startup_report().await)
+}
+
 async fn classification_with_gen(
     State(state): State<Arc<ServerState>>,
     headers: HeaderMap,
-    WithRejection(Json(request), _): WithRejection<Json<models::GuardrailsHttpRequest>, Error>,
-) -> Result<impl IntoResponse, Error> {
-    let trace_id = current_trace_id();
+    consumer: Option<Extension<ApiConsumer>>,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
+    WithRejection(Query(DebugParams { debug }), _): WithRejection<Query<DebugParams>, Error>,
+    WithRejection(Query(DryRunParams { dry_run }), _): WithRejection<Query<DryRunParams>, Error>,
+    RequestJson(request): RequestJson<models::GuardrailsHttpRequest>,
+) -> Result<Response, Error> {
     request.validate()?;
+    handle_classification_with_gen(state, headers, consumer, params, debug, dry_run, request).await
+}
+
+async fn classification_with_gen_v2(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    consumer: Option<Extension<ApiConsumer>>,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
+    WithRejection(Query(DebugParams { debug }), _): WithRejection<Query<DebugParams>, Error>,
+    WithRejection(Query(DryRunParams { dry_run }), _): WithRejection<Query<DryRunParams>, Error>,
+    RequestJson(request): RequestJson<models::ClassificationWithGenerationHttpRequest>,
+) -> Result<Response, Error> {
+    let request = request.try_into()?;
+    handle_classification_with_gen(state, headers, consumer, params, debug, dry_run, request).await
+}
+
+/// Handles a validated classification with text generation request of either API version.
+async fn handle_classification_with_gen(
+    state: Arc<ServerState>,
+    headers: HeaderMap,
+    consumer: Option<Extension<ApiConsumer>>,
+    params: DetectionsParams,
+    debug: bool,
+    dry_run: bool,
+    request: models::GuardrailsHttpRequest,
+) -> Result<Response, Error> {
+    let trace_id = current_trace_id();
+    let detections_filter = params.filter(state.orchestrator.config().detections_filter)?;
+    let consumer = consumer.map(|Extension(ApiConsumer(consumer))| consumer);
+    let sampled = state
+        .capture()
+        .filter(|_| !dry_run)
+        .and_then(|capture| capture.sample(&headers, consumer.as_deref(), &request.inputs));
+    let generation_headers = generation_headers(&state, &headers);
     let headers = filter_headers(&state.orchestrator.config().passthrough_headers, headers);
-    let task = ClassificationWithGenTask::new(trace_id, request, headers);
-    match state.orchestrator.handle(task).await {
-        Ok(response) => Ok(Json(response).into_response()),
+    let task =
+        ClassificationWithGenTask::new(trace_id, request, headers, detections_filter, dry_run)
+            .with_generation_headers(generation_headers)
+            .with_consumer(consumer);
+    let ((result, detections), debug_info) = debug_info::collect(
+        debug,
+        unfiltered::collect(sampled.is_some(), state.orchestrator.handle(task)),
+    )
+    .await;
+    match result {
+        Ok(response) => {
+            if let (Some(capture), Some(sampled), Some(detections)) =
+                (&state.capture, sampled, detections)
+            {
+                capture
+                    .capture_generation(trace_id, sampled, &response, detections)
+                    .await;
+            }
+            Ok(json_response(response, debug_info))
+        }
         Err(error) => Err(error.into()),
     }
 }
 
+async fn submit_generation_job(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    consumer: Option<Extension<ApiConsumer>>,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
+    RequestJson(request): RequestJson<models::GenerationJobHttpRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let trace_id = current_trace_id();
+    let models::GenerationJobHttpRequest {
+        request,
+        callback_url,
+    } = request;
+    let request: models::GuardrailsHttpRequest = request.try_into()?;
+    let callback_url = callback_url
+        .map(|callback_url| state.jobs.callback_url(&callback_url))
+        .transpose()?;
+    let detections_filter = params.filter(state.orchestrator.config().detections_filter)?;
+    let generation_headers = generation_headers(&state, &headers);
+    let headers = filter_headers(&state.orchestrator.config().passthrough_headers, headers);
+    let consumer = consumer.map(|Extension(ApiConsumer(consumer))| consumer);
+    let task = ClassificationWithGenTask::new(trace_id, request, headers, detections_filter, false)
+        .with_generation_headers(generation_headers)
+        .with_consumer(consumer.clone());
+    let orchestrator = state.orchestrator.clone();
+    let job = state.jobs.submit(
+        async move { orchestrator.handle(task).await },
+        callback_url,
+        consumer,
+    )?;
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Returns a generation job. Jobs are returned to the API consumer that submitted them only.
+async fn generation_job(
+    State(state): State<Arc<ServerState>>,
+    consumer: Option<Extension<ApiConsumer>>,
+    Path(id): Path<String>,
+) -> Result<Json<models::GenerationJob>, Error> {
+    let consumer = consumer.map(|Extension(ApiConsumer(consumer))| consumer);
+    state
+        .jobs
+        .get(&id, consumer.as_deref())
+        .map(Json)
+        .ok_or_else(|| Error::NotFound(format!("job `{id}` not found")))
+}
+
 async fn generation_with_detection(
     State(state): State<Arc<ServerState>>,
     headers: HeaderMap,
-    WithRejection(Json(request), _): WithRejection<
-        Json<models::GenerationWithDetectionHttpRequest>,
-        Error,
-    >,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
+    WithRejection(Query(DebugParams { debug }), _): WithRejection<Query<DebugParams>, Error>,
+    RequestJson(request): RequestJson<models::GenerationWithDetectionHttpRequest>,
 ) -> Result<impl IntoResponse, Error> {
     let trace_id = current_trace_id();
     request.validate()?;
+    let detections_filter = params.filter(state.orchestrator.config().detections_filter)?;
+    let generation_headers = generation_headers(&state, &headers);
     let headers = filter_headers(&state.orchestrator.config().passthrough_headers, headers);
-    let task = GenerationWithDetectionTask::new(trace_id, request, headers);
-    match state.orchestrator.handle(task).await {
-        Ok(response) => Ok(Json(response).into_response()),
+    let task = GenerationWithDetectionTask::new(trace_id, request, headers, detections_filter)
+        .with_generation_headers(generation_headers);
+    let (result, debug_info) = debug_info::collect(debug, state.orchestrator.handle(task)).await;
+    match result {
+        Ok(response) => Ok(json_response(response, debug_info)),
         Err(error) => Err(error.into()),
     }
 }
@@ -152,65 +342,197 @@ async fn generation_with_detection(
 async fn stream_classification_with_gen(
     State(state): State<Arc<ServerState>>,
     headers: HeaderMap,
-    WithRejection(Json(request), _): WithRejection<Json<models::GuardrailsHttpRequest>, Error>,
-) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    consumer: Option<Extension<ApiConsumer>>,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
+    WithRejection(Query(DryRunParams { dry_run }), _): WithRejection<Query<DryRunParams>, Error>,
+    RequestJson(request): RequestJson<models::GuardrailsHttpRequest>,
+) -> Sse<BoxStream<'static, Result<Event, Infallible>>> {
+    let request = request.validate().map(|_| request);
+    handle_stream_classification_with_gen(state, headers, consumer, params, dry_run, request).await
+}
+
+async fn stream_classification_with_gen_v2(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    consumer: Option<Extension<ApiConsumer>>,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
+    WithRejection(Query(DryRunParams { dry_run }), _): WithRejection<Query<DryRunParams>, Error>,
+    RequestJson(request): RequestJson<models::ClassificationWithGenerationHttpRequest>,
+) -> Sse<BoxStream<'static, Result<Event, Infallible>>> {
+    let request = request.try_into();
+    handle_stream_classification_with_gen(state, headers, consumer, params, dry_run, request).await
+}
+
+/// Handles a streaming classification with text generation request of either API version.
+async fn handle_stream_classification_with_gen(
+    state: Arc<ServerState>,
+    headers: HeaderMap,
+    consumer: Option<Extension<ApiConsumer>>,
+    params: DetectionsParams,
+    dry_run: bool,
+    request: Result<models::GuardrailsHttpRequest, models::ValidationError>,
+) -> Sse<BoxStream<'static, Result<Event, Infallible>>> {
     let trace_id = current_trace_id();
-    if let Err(error) = request.validate() {
-        // Request validation failed, return stream with single error SSE event
-        let error: Error = error.into();
-        return Sse::new(
-            stream::iter([Ok(Event::default()
-                .event("error")
-                .json_data(error.to_json())
-                .unwrap())])
-            .boxed(),
-        );
-    }
+    let validated = request.and_then(|request| {
+        params
+            .filter(state.orchestrator.config().detections_filter)
+            .map(|detections_filter| (request, detections_filter))
+    });
+    let (request, detections_filter) = match validated {
+        Ok(validated) => validated,
+        Err(error) => {
+            // Request validation failed, return stream with single error SSE event
+            let error: Error = error.into();
+            return Sse::new(
+                stream::iter([Ok(Event::default()
+                    .event("error")
+                    .json_data(error.to_json())
+                    .unwrap())])
+                .boxed(),
+            );
+        }
+    };
+    let generation_headers = generation_headers(&state, &headers);
     let headers = filter_headers(&state.orchestrator.config().passthrough_headers, headers);
-    let task = StreamingClassificationWithGenTask::new(trace_id, request, headers);
+    let task = StreamingClassificationWithGenTask::new(
+        trace_id,
+        request,
+        headers,
+        detections_filter,
+        dry_run,
+    )
+    .with_generation_headers(generation_headers);
     let response_stream = state.orchestrator.handle(task).await.unwrap();
     // Convert response stream to a stream of SSE events
-    let event_stream = response_stream
-        .map(|message| match message {
-            Ok(response) => Ok(Event::default()
-                //.event("message") NOTE: per spec, should not be included for data-only message events
-                .json_data(response)
-                .unwrap()),
-            Err(error) => {
-                let error: Error = error.into();
-                Ok(Event::default()
-                    .event("error")
-                    .json_data(error.to_json())
-                    .unwrap())
-            }
-        })
-        .boxed();
+    let event_stream = match &state.resumable_streams {
+        Some(resumable_streams) => resumable_streams
+            .buffer(
+                response_stream,
+                consumer.map(|Extension(ApiConsumer(consumer))| consumer),
+            )
+            .map(|item| Ok(resumable_event(item)))
+            .boxed(),
+        None => response_stream
+            .map(|message| Ok(stream_message_event(message)))
+            .boxed(),
+    };
     Sse::new(event_stream).keep_alive(KeepAlive::default())
 }
 
+/// Resumes a streaming classification with text generation response from a checkpoint.
+/// Streams are resumed by the API consumer of the request only.
+async fn resume_stream_classification_with_gen(
+    State(state): State<Arc<ServerState>>,
+    consumer: Option<Extension<ApiConsumer>>,
+    Path(resumption_token): Path<String>,
+) -> Result<Sse<BoxStream<'static, Result<Event, Infallible>>>, Error> {
+    let Some(resumable_streams) = &state.resumable_streams else {
+        return Err(Error::NotFound("stream resumption is not enabled".into()));
+    };
+    let consumer = consumer.map(|Extension(ApiConsumer(consumer))| consumer);
+    let event_stream = resumable_streams
+        .resume(&resumption_token, consumer.as_deref())?
+        .map(|item| Ok(resumable_event(item)))
+        .boxed();
+    Ok(Sse::new(event_stream).keep_alive(KeepAlive::default()))
+}
+
+/// Converts a streaming classification with text generation message to an SSE event.
+fn stream_message_event(message: StreamMessage) -> Event {
+    match message {
+        Ok(response) => Event::default()
+            //.event("message") NOTE: per spec, should not be included for data-only message events
+            .json_data(response)
+            .unwrap(),
+        Err(error) => {
+            let error: Error = error.into();
+            Event::default()
+                .event("error")
+                .json_data(error.to_json())
+                .unwrap()
+        }
+    }
+}
+
+/// Converts an item of a resumable stream to an SSE event. Checkpoints are sent as
+/// `checkpoint` events with the token resuming the stream after them.
+fn resumable_event(item: ResumableItem) -> Event {
+    match item {
+        ResumableItem::Message(message) => stream_message_event(message),
+        ResumableItem::Checkpoint(resumption_token) => Event::default()
+            .event("checkpoint")
+            .json_data(serde_json::json!({ "resumption_token": resumption_token }))
+            .unwrap(),
+    }
+}
+
 async fn stream_content_detection(
     State(state): State<Arc<ServerState>>,
     headers: HeaderMap,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
+    json_lines: JsonLines<StreamingContentDetectionRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let trace_id = current_trace_id();
+    validate_ndjson_content_type(&headers)?;
+    let detections_filter = params.filter(state.orchestrator.config().detections_filter)?;
+    let headers = filter_headers(&state.orchestrator.config().passthrough_headers, headers);
+
+    // Create task and submit to handler
+    let task = StreamingContentDetectionTask::new(
+        trace_id,
+        headers,
+        detections_filter,
+        content_input_stream(json_lines),
+    );
+    let response_stream = state.orchestrator.handle(task).await?;
+    Ok(ndjson_response(response_stream))
+}
+
+/// Handles text streamed as it is typed or dictated, returning interim detections on
+/// input not yet complete in a chunk along with final detections.
+async fn stream_input_detection(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
     json_lines: JsonLines<StreamingContentDetectionRequest>,
 ) -> Result<impl IntoResponse, Error> {
     let trace_id = current_trace_id();
-    // Validate the content-type from the header and ensure it is application/x-ndjson
-    // If it's not, return a UnsupportedContentType error with the appropriate message
+    validate_ndjson_content_type(&headers)?;
+    let detections_filter = params.filter(state.orchestrator.config().detections_filter)?;
+    let headers = filter_headers(&state.orchestrator.config().passthrough_headers, headers);
+
+    // Create task and submit to handler
+    let task = StreamingInputDetectionTask::new(
+        trace_id,
+        headers,
+        detections_filter,
+        content_input_stream(json_lines),
+    );
+    let response_stream = state.orchestrator.handle(task).await?;
+    Ok(ndjson_response(response_stream))
+}
+
+/// Validates the content-type from the header and ensures it is application/x-ndjson.
+fn validate_ndjson_content_type(headers: &HeaderMap) -> Result<(), Error> {
     let content_type = headers
         .get(http::header::CONTENT_TYPE)
         .and_then(|value| value.to_str().ok());
     match content_type {
-        Some(content_type) if content_type.starts_with("application/x-ndjson") => (),
-        _ => {
-            return Err(Error::UnsupportedContentType(
-                "expected application/x-ndjson".into(),
-            ));
-        }
-    };
-    let headers = filter_headers(&state.orchestrator.config().passthrough_headers, headers);
+        Some(content_type) if content_type.starts_with("application/x-ndjson") => Ok(()),
+        _ => Err(Error::UnsupportedContentType(
+            "expected application/x-ndjson".into(),
+        )),
+    }
+}
 
-    // Create input stream
-    let input_stream = json_lines
+/// Creates an input stream of validated streaming content detection messages.
+fn content_input_stream(
+    json_lines: JsonLines<StreamingContentDetectionRequest>,
+) -> orchestrator::types::BoxStream<(
+    usize,
+    Result<StreamingContentDetectionRequest, orchestrator::Error>,
+)> {
+    json_lines
         .map(|result| match result {
             Ok(message) => {
                 message.validate()?;
@@ -219,15 +541,16 @@ async fn stream_content_detection(
             Err(error) => Err(orchestrator::errors::Error::Validation(error.to_string())),
         })
         .enumerate()
-        .boxed();
-
-    // Create task and submit to handler
-    let task = StreamingContentDetectionTask::new(trace_id, headers, input_stream);
-    let mut response_stream = state.orchestrator.handle(task).await?;
+        .boxed()
+}
 
-    // Create output stream
-    // This stream returns ND-JSON formatted messages to the client
-    // StreamingContentDetectionResponse / server::Error
+/// Creates a response returning ND-JSON formatted messages of a response stream to the client.
+fn ndjson_response<T>(
+    mut response_stream: ReceiverStream<Result<T, orchestrator::Error>>,
+) -> Response
+where
+    T: Serialize + Send + 'static,
+{
     let (output_tx, output_rx) = mpsc::channel::<Result<String, Infallible>>(128);
     let output_stream = ReceiverStream::new(output_rx);
 
@@ -250,53 +573,241 @@ async fn stream_content_detection(
         }
     });
 
-    Ok(Response::new(axum::body::Body::from_stream(output_stream)))
+    Response::new(axum::body::Body::from_stream(output_stream))
 }
 
 async fn detection_content(
     State(state): State<Arc<ServerState>>,
     headers: HeaderMap,
-    WithRejection(Json(request), _): WithRejection<
-        Json<models::TextContentDetectionHttpRequest>,
-        Error,
-    >,
+    consumer: Option<Extension<ApiConsumer>>,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
+    WithRejection(Query(DebugParams { debug }), _): WithRejection<Query<DebugParams>, Error>,
+    Accept(format): Accept,
+    body: DispatchingBody<models::TextContentDetectionHttpRequest>,
 ) -> Result<impl IntoResponse, Error> {
     let trace_id = current_trace_id();
-    request.validate()?;
-    let headers = filter_headers(&state.orchestrator.config().passthrough_headers, headers);
-    let task = TextContentDetectionTask::new(trace_id, request, headers);
+    let consumer = consumer.map(|Extension(ApiConsumer(consumer))| consumer);
+    let passthrough_headers = filter_headers(
+        &state.orchestrator.config().passthrough_headers,
+        headers.clone(),
+    );
+    let (result, debug_info) = debug_info::collect(debug, async {
+        // Dispatch detectors as they are parsed, before the body is received in full
+        let mut dispatched = DispatchedDetections::new();
+        let mut content = None;
+        let received = async {
+            let request = body
+                .receive(|part| match part {
+                    BodyPart::Inputs(inputs) => content = Some(inputs),
+                    BodyPart::Detector(detector_id, params) => {
+                        if let Some(content) = &content {
+                            state.orchestrator.dispatch_content_detector(
+                                &mut dispatched,
+                                &passthrough_headers,
+                                content,
+                                detector_id,
+                                params,
+                            );
+                        }
+                    }
+                })
+                .await?;
+            request.validate()?;
+            let detections_filter = params.filter(state.orchestrator.config().detections_filter)?;
+            Ok::<_, Error>((request, detections_filter))
+        }
+        .await;
+        // Detectors dispatched for rejected requests are aborted, without alerts
+        let (request, detections_filter) = match received {
+            Ok(received) => received,
+            Err(error) => {
+                dispatched.reject();
+                return Err(error);
+            }
+        };
+        let sampled = state
+            .capture()
+            .and_then(|capture| capture.sample(&headers, consumer.as_deref(), &request.content));
+        let task = TextContentDetectionTask::new(
+            trace_id,
+            request,
+            passthrough_headers,
+            detections_filter,
+        )
+        .with_dispatched(dispatched)
+        .with_consumer(consumer.clone());
+        let (result, detections) =
+            unfiltered::collect(sampled.is_some(), state.orchestrator.handle(task)).await;
+        Ok::<_, Error>((result?, sampled, detections))
+    })
+    .await;
+    let (response, sampled, detections) = result?;
+    if let (Some(capture), Some(sampled), Some(detections)) = (&state.capture, sampled, detections)
+    {
+        capture
+            .capture_content_detection(trace_id, sampled, detections)
+            .await;
+    }
+    Ok(encoded_response(format, response, debug_info))
+}
+
+/// Returns detections pending when a time-boxed text content detection request returned.
+/// Pending detections are returned to the API consumer of the request only.
+async fn pending_detection_content(
+    State(state): State<Arc<ServerState>>,
+    consumer: Option<Extension<ApiConsumer>>,
+    Path(continuation_token): Path<String>,
+    WithRejection(Query(params), _): WithRejection<Query<PendingDetectionsParams>, Error>,
+) -> Result<impl IntoResponse, Error> {
+    let trace_id = current_trace_id();
+    let task = PendingContentDetectionTask {
+        trace_id,
+        continuation_token,
+        max_wait_ms: params.max_wait_ms,
+        consumer: consumer.map(|Extension(ApiConsumer(consumer))| consumer),
+    };
     match state.orchestrator.handle(task).await {
         Ok(response) => Ok(Json(response).into_response()),
         Err(error) => Err(error.into()),
     }
 }
 
-async fn detect_context_documents(
+async fn detectors(State(state): State<Arc<ServerState>>) -> Json<models::DetectorsResponse> {
+    let mut detectors = state
+        .orchestrator
+        .config()
+        .detectors
+        .iter()
+        .map(|(detector_id, config)| models::DetectorInfo {
+            detector_id: detector_id.clone(),
+            r#type: config.r#type.clone(),
+            default_threshold: config.default_threshold,
+        })
+        .collect::<Vec<_>>();
+    detectors.sort_by(|a, b| a.detector_id.cmp(&b.detector_id));
+    Json(models::DetectorsResponse { detectors })
+}
+
+async fn detector_feedback(
+    State(state): State<Arc<ServerState>>,
+    RequestJson(request): RequestJson<models::DetectorFeedbackHttpRequest>,
+) -> Result<impl IntoResponse, Error> {
+    request.validate()?;
+    if state
+        .orchestrator
+        .config()
+        .detector(&request.detector_id)
+        .is_none()
+    {
+        return Err(orchestrator::Error::DetectorNotFound(request.detector_id).into());
+    }
+    let id = state.feedback.record(&request).await;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(models::DetectorFeedbackResponse { id }),
+    ))
+}
+
+async fn suitability(
     State(state): State<Arc<ServerState>>,
     headers: HeaderMap,
-    WithRejection(Json(request), _): WithRejection<Json<models::ContextDocsHttpRequest>, Error>,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
+    WithRejection(Query(DebugParams { debug }), _): WithRejection<Query<DebugParams>, Error>,
+    RequestJson(request): RequestJson<models::SuitabilityHttpRequest>,
 ) -> Result<impl IntoResponse, Error> {
     let trace_id = current_trace_id();
     request.validate()?;
+    let detections_filter = params.filter(state.orchestrator.config().detections_filter)?;
     let headers = filter_headers(&state.orchestrator.config().passthrough_headers, headers);
-    let task = ContextDocsDetectionTask::new(trace_id, request, headers);
+    let task = SuitabilityTask::new(trace_id, request, headers, detections_filter);
+    let (result, debug_info) = debug_info::collect(debug, state.orchestrator.handle(task)).await;
+    match result {
+        Ok(response) => Ok(json_response(response, debug_info)),
+        Err(error) => Err(error.into()),
+    }
+}
+
+async fn chunks(
+    State(state): State<Arc<ServerState>>,
+    RequestJson(request): RequestJson<models::ChunksHttpRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let trace_id = current_trace_id();
+    request.validate()?;
+    let task = ChunksTask::new(trace_id, request);
     match state.orchestrator.handle(task).await {
         Ok(response) => Ok(Json(response).into_response()),
         Err(error) => Err(error.into()),
     }
 }
 
+async fn detect_context_documents(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
+    WithRejection(Query(DebugParams { debug }), _): WithRejection<Query<DebugParams>, Error>,
+    Accept(format): Accept,
+    body: DispatchingBody<models::ContextDocsHttpRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let trace_id = current_trace_id();
+    let headers = filter_headers(&state.orchestrator.config().passthrough_headers, headers);
+    let (result, debug_info) = debug_info::collect(debug, async {
+        // Dispatch detectors as they are parsed, before the body is received in full
+        let mut dispatched = DispatchedDetections::new();
+        let mut inputs = None;
+        let received = async {
+            let request = body
+                .receive(|part| match part {
+                    BodyPart::Inputs(parsed) => inputs = Some(parsed),
+                    BodyPart::Detector(detector_id, params) => {
+                        if let Some(inputs) = &inputs {
+                            state.orchestrator.dispatch_context_docs_detector(
+                                &mut dispatched,
+                                &headers,
+                                inputs,
+                                detector_id,
+                                params,
+                            );
+                        }
+                    }
+                })
+                .await?;
+            request.validate()?;
+            let detections_filter = params.filter(state.orchestrator.config().detections_filter)?;
+            Ok::<_, Error>((request, detections_filter))
+        }
+        .await;
+        // Detectors dispatched for rejected requests are aborted, without alerts
+        let (request, detections_filter) = match received {
+            Ok(received) => received,
+            Err(error) => {
+                dispatched.reject();
+                return Err(error);
+            }
+        };
+        let task = ContextDocsDetectionTask::new(trace_id, request, headers, detections_filter)
+            .with_dispatched(dispatched);
+        Ok::<_, Error>(state.orchestrator.handle(task).await?)
+    })
+    .await;
+    Ok(encoded_response(format, result?, debug_info))
+}
+
 async fn detect_chat(
     State(state): State<Arc<ServerState>>,
     headers: HeaderMap,
-    WithRejection(Json(request), _): WithRejection<Json<models::ChatDetectionHttpRequest>, Error>,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
+    WithRejection(Query(DebugParams { debug }), _): WithRejection<Query<DebugParams>, Error>,
+    Accept(format): Accept,
+    RequestBody(request): RequestBody<models::ChatDetectionHttpRequest>,
 ) -> Result<impl IntoResponse, Error> {
     let trace_id = current_trace_id();
     request.validate_for_text()?;
+    let detections_filter = params.filter(state.orchestrator.config().detections_filter)?;
     let headers = filter_headers(&state.orchestrator.config().passthrough_headers, headers);
-    let task = ChatDetectionTask::new(trace_id, request, headers);
-    match state.orchestrator.handle(task).await {
-        Ok(response) => Ok(Json(response).into_response()),
+    let task = ChatDetectionTask::new(trace_id, request, headers, detections_filter);
+    let (result, debug_info) = debug_info::collect(debug, state.orchestrator.handle(task)).await;
+    match result {
+        Ok(response) => Ok(encoded_response(format, response, debug_info)),
         Err(error) => Err(error.into()),
     }
 }
@@ -304,17 +815,19 @@ async fn detect_chat(
 async fn detect_generated(
     State(state): State<Arc<ServerState>>,
     headers: HeaderMap,
-    WithRejection(Json(request), _): WithRejection<
-        Json<models::DetectionOnGeneratedHttpRequest>,
-        Error,
-    >,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
+    WithRejection(Query(DebugParams { debug }), _): WithRejection<Query<DebugParams>, Error>,
+    Accept(format): Accept,
+    RequestBody(request): RequestBody<models::DetectionOnGeneratedHttpRequest>,
 ) -> Result<impl IntoResponse, Error> {
     let trace_id = current_trace_id();
     request.validate()?;
+    let detections_filter = params.filter(state.orchestrator.config().detections_filter)?;
     let headers = filter_headers(&state.orchestrator.config().passthrough_headers, headers);
-    let task = DetectionOnGenerationTask::new(trace_id, request, headers);
-    match state.orchestrator.handle(task).await {
-        Ok(response) => Ok(Json(response).into_response()),
+    let task = DetectionOnGenerationTask::new(trace_id, request, headers, detections_filter);
+    let (result, debug_info) = debug_info::collect(debug, state.orchestrator.handle(task)).await;
+    match result {
+        Ok(response) => Ok(encoded_response(format, response, debug_info)),
         Err(error) => Err(error.into()),
     }
 }
@@ -322,16 +835,24 @@ async fn detect_generated(
 async fn chat_completions_detection(
     State(state): State<Arc<ServerState>>,
     headers: HeaderMap,
-    WithRejection(Json(request), _): WithRejection<Json<ChatCompletionsRequest>, Error>,
+    WithRejection(Query(params), _): WithRejection<Query<DetectionsParams>, Error>,
+    WithRejection(Query(DebugParams { debug }), _): WithRejection<Query<DebugParams>, Error>,
+    WithRejection(Query(DryRunParams { dry_run }), _): WithRejection<Query<DryRunParams>, Error>,
+    RequestJson(request): RequestJson<ChatCompletionsRequest>,
 ) -> Result<impl IntoResponse, Error> {
     use ChatCompletionsResponse::*;
     let trace_id = current_trace_id();
     request.validate()?;
+    let detections_filter = params.filter(state.orchestrator.config().detections_filter)?;
+    let generation_headers = generation_headers(&state, &headers);
     let headers = filter_headers(&state.orchestrator.config().passthrough_headers, headers);
-    let task = ChatCompletionsDetectionTask::new(trace_id, request, headers);
-    match state.orchestrator.handle(task).await {
+    let task =
+        ChatCompletionsDetectionTask::new(trace_id, request, headers, detections_filter, dry_run)
+            .with_generation_headers(generation_headers);
+    let (result, debug_info) = debug_info::collect(debug, state.orchestrator.handle(task)).await;
+    match result {
         Ok(response) => match response {
-            Unary(response) => Ok(Json(response).into_response()),
+            Unary(response) => Ok(json_response(response, debug_info)),
             Streaming(response_rx) => {
                 let response_stream = ReceiverStream::new(response_rx);
                 // Convert response stream to a stream of SSE events
@@ -359,6 +880,55 @@ async fn chat_completions_detection(
     }
 }
 
+/// Returns a JSON response, adding a `debug` block with the request's processing metadata if collected.
+fn json_response<T: Serialize>(response: T, debug_info: Option<DebugInfo>) -> Response {
+    let Some(debug_info) = debug_info else {
+        return Json(response).into_response();
+    };
+    match serde_json::to_value(&response) {
+        Ok(serde_json::Value::Object(mut map)) => {
+            map.insert("debug".into(), serde_json::json!(debug_info));
+            Json(map).into_response()
+        }
+        _ => Json(response).into_response(),
+    }
+}
+
+/// Returns `response` encoded in `format`, including `debug_info` if collected.
+fn encoded_response<T: Serialize>(
+    format: BodyFormat,
+    response: T,
+    debug_info: Option<DebugInfo>,
+) -> Response {
+    if format == BodyFormat::Json {
+        return json_response(response, debug_info);
+    }
+    let body = match debug_info {
+        Some(debug_info) => match serde_json::to_value(&response) {
+            Ok(serde_json::Value::Object(mut map)) => {
+                map.insert("debug".into(), serde_json::json!(debug_info));
+                format.encode(&map)
+            }
+            _ => format.encode(&response),
+        },
+        None => format.encode(&response),
+    };
+    match body {
+        Ok(body) => ([(http::header::CONTENT_TYPE, format.content_type())], body).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+/// Returns request headers passed to the generation service only.
+fn generation_headers(state: &ServerState, headers: &HeaderMap) -> HeaderMap {
+    let generation_passthrough_headers =
+        &state.orchestrator.config().generation_passthrough_headers;
+    if generation_passthrough_headers.is_empty() {
+        return HeaderMap::new();
+    }
+    filter_headers(generation_passthrough_headers, headers.clone())
+}
+
 /// Filters a [`HeaderMap`] with a set of header names, returning a new [`HeaderMap`].
 pub fn filter_headers(passthrough_headers: &HashSet<String>, headers: HeaderMap) -> HeaderMap {
     headers
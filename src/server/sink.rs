@@ -0,0 +1,385 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Records appended to JSON lines files by a background writer.
+//!
+//! While a file cannot be written, records are queued in an on-disk spool, if configured,
+//! and written in order once the file recovers. Records are delivered at least once.
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        OnceLock,
+        mpsc::{self, RecvTimeoutError, SyncSender, TrySendError},
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tracing::{debug, info, warn};
+
+use crate::config::{SpoolConfig, SpoolOverflow};
+
+/// Number of records buffered for writing. Records are dropped while full, unless
+/// backpressure is configured.
+const SINK_BUFFER_SIZE: usize = 1024;
+/// Maximum size in bytes of a spool segment file.
+const SEGMENT_MAX_BYTES: u64 = 1024 * 1024;
+/// Interval at which the writer checks for records to drain if no spool is configured.
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// JSON lines file written by a background thread, spawned on first use.
+#[derive(Debug)]
+pub struct JsonLinesSink {
+    path: PathBuf,
+    spool: Option<SpoolConfig>,
+    tx: OnceLock<SyncSender<String>>,
+}
+
+impl JsonLinesSink {
+    pub fn new(path: PathBuf, spool: Option<SpoolConfig>) -> Self {
+        Self {
+            path,
+            spool,
+            tx: OnceLock::new(),
+        }
+    }
+
+    /// Queues `record` for writing. Returns `false` if it was dropped as the buffer is full.
+    /// Waits for buffer space instead if the spool is configured with backpressure.
+    pub async fn write(&self, record: &impl Serialize) -> bool {
+        let mut line = serde_json::to_string(record).unwrap();
+        line.push('\n');
+        let tx = self.tx.get_or_init(|| {
+            let (tx, rx) = mpsc::sync_channel(SINK_BUFFER_SIZE);
+            let writer = Writer::new(self.path.clone(), self.spool.as_ref());
+            let retry_interval = self
+                .spool
+                .as_ref()
+                .map(|spool| Duration::from_secs(spool.retry_interval))
+                .unwrap_or(DEFAULT_RETRY_INTERVAL);
+            std::thread::Builder::new()
+                .name("sink-writer".into())
+                .spawn(move || writer.run(rx, retry_interval))
+                .expect("sink writer thread should spawn");
+            tx
+        });
+        let backpressure = self.spool.as_ref().is_some_and(|spool| spool.backpressure);
+        match tx.try_send(line) {
+            Ok(_) => true,
+            Err(TrySendError::Full(line)) if backpressure => {
+                let tx = tx.clone();
+                tokio::task::spawn_blocking(move || tx.send(line).is_ok())
+                    .await
+                    .unwrap_or(false)
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Returns the name of the spool directory of the sink file at `path`: its file name with
+/// a digest of its full path, so that sinks of files with the same name don't share a spool.
+fn spool_name(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy();
+    let digest = ring::digest::digest(&ring::digest::SHA256, path.as_os_str().as_encoded_bytes());
+    let digest = digest.as_ref()[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("{name}-{digest}")
+}
+
+/// Writes records to the sink file, spooling them while it cannot be written.
+struct Writer {
+    path: PathBuf,
+    file: Option<File>,
+    spool: Option<Spool>,
+}
+
+impl Writer {
+    fn new(path: PathBuf, spool: Option<&SpoolConfig>) -> Self {
+        let spool = spool.and_then(|config| {
+            match Spool::open(config.dir.join(spool_name(&path)), config.max_bytes, config.overflow) {
+                Ok(spool) => Some(spool),
+                Err(error) => {
+                    warn!(path = %path.display(), %error, "failed to open spool, records that cannot be written are dropped");
+                    None
+                }
+            }
+        });
+        Self {
+            path,
+            file: None,
+            spool,
+        }
+    }
+
+    /// Writes lines received until all senders are dropped, draining the spool every
+    /// `retry_interval`.
+    fn run(mut self, rx: mpsc::Receiver<String>, retry_interval: Duration) {
+        // Records spooled by previous runs
+        self.drain();
+        let mut last_drain = Instant::now();
+        loop {
+            match rx.recv_timeout(retry_interval) {
+                Ok(line) => self.write(line),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if last_drain.elapsed() >= retry_interval {
+                self.drain();
+                last_drain = Instant::now();
+            }
+        }
+    }
+
+    /// Writes a line to the file, or to the spool if the file cannot be written or
+    /// records are already spooled, keeping records in order.
+    fn write(&mut self, line: String) {
+        if self.spool.as_ref().is_none_or(Spool::is_empty) {
+            match self.write_file(line.as_bytes()) {
+                Ok(_) => return,
+                Err(error) => {
+                    warn!(path = %self.path.display(), %error, "failed to write to sink file")
+                }
+            }
+        }
+        let spooled = match &mut self.spool {
+            Some(spool) => spool.push(&line).unwrap_or_else(|error| {
+                warn!(path = %self.path.display(), %error, "failed to spool record");
+                false
+            }),
+            None => false,
+        };
+        if !spooled {
+            warn!(
+                path = %self.path.display(),
+                monotonic_counter.dropped_sink_record_count = 1,
+                "sink record dropped"
+            );
+        }
+    }
+
+    /// Writes spooled records to the file.
+    fn drain(&mut self) {
+        let Some(mut spool) = self.spool.take() else {
+            return;
+        };
+        if !spool.is_empty() {
+            let size = spool.size;
+            match spool.drain(|contents| self.write_file(contents)) {
+                Ok(_) => info!(path = %self.path.display(), size, "spooled records written"),
+                Err(error) => {
+                    debug!(path = %self.path.display(), %error, "sink file still cannot be written")
+                }
+            }
+        }
+        self.spool = Some(spool);
+    }
+
+    /// Appends to the file, re-opening it after failed writes.
+    fn write_file(&mut self, contents: &[u8]) -> io::Result<()> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            ),
+        };
+        let result = file.write_all(contents);
+        if result.is_err() {
+            self.file = None;
+        }
+        result
+    }
+}
+
+/// Bounded on-disk queue of records, in segment files written oldest first.
+struct Spool {
+    dir: PathBuf,
+    max_bytes: u64,
+    overflow: SpoolOverflow,
+    /// Sequence numbers and sizes of segments, oldest first
+    segments: VecDeque<(u64, u64)>,
+    /// Total size of segments
+    size: u64,
+}
+
+impl Spool {
+    /// Opens the spool in `dir`, including segments spooled by previous runs.
+    fn open(dir: PathBuf, max_bytes: u64, overflow: SpoolOverflow) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let seq = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_suffix(".jsonl"))
+                .and_then(|seq| seq.parse::<u64>().ok());
+            if let Some(seq) = seq {
+                segments.push((seq, entry.metadata()?.len()));
+            }
+        }
+        segments.sort();
+        let size = segments.iter().map(|(_, size)| size).sum();
+        Ok(Self {
+            dir,
+            max_bytes,
+            overflow,
+            segments: segments.into(),
+            size,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    fn segment_path(dir: &Path, seq: u64) -> PathBuf {
+        dir.join(format!("{seq:020}.jsonl"))
+    }
+
+    /// Appends a line. Returns `false` if it was dropped as the spool is full.
+    fn push(&mut self, line: &str) -> io::Result<bool> {
+        let len = line.len() as u64;
+        while self.size + len > self.max_bytes {
+            let oldest = match self.overflow {
+                SpoolOverflow::DropNewest => None,
+                SpoolOverflow::DropOldest => self.segments.pop_front(),
+            };
+            let Some((seq, size)) = oldest else {
+                return Ok(false);
+            };
+            fs::remove_file(Self::segment_path(&self.dir, seq))?;
+            self.size -= size;
+            warn!(
+                dir = %self.dir.display(),
+                size,
+                monotonic_counter.dropped_spool_segment_count = 1,
+                "spool full, oldest records dropped"
+            );
+        }
+        if self
+            .segments
+            .back()
+            .is_none_or(|(_, size)| size + len > SEGMENT_MAX_BYTES)
+        {
+            let seq = self.segments.back().map_or(0, |(seq, _)| seq + 1);
+            self.segments.push_back((seq, 0));
+        }
+        let (seq, size) = self.segments.back_mut().unwrap();
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Self::segment_path(&self.dir, *seq))?
+            .write_all(line.as_bytes())?;
+        *size += len;
+        self.size += len;
+        Ok(true)
+    }
+
+    /// Writes segments oldest first with `write`, removing each once written.
+    fn drain(&mut self, mut write: impl FnMut(&[u8]) -> io::Result<()>) -> io::Result<()> {
+        while let Some(&(seq, size)) = self.segments.front() {
+            let path = Self::segment_path(&self.dir, seq);
+            write(&fs::read(&path)?)?;
+            fs::remove_file(&path)?;
+            self.segments.pop_front();
+            self.size -= size;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(uuid::Uuid::new_v4().simple().to_string())
+    }
+
+    #[test]
+    fn test_spool_overflow() -> io::Result<()> {
+        let dir = temp_dir();
+        let mut spool = Spool::open(dir.join("newest"), 8, SpoolOverflow::DropNewest)?;
+        assert!(spool.push("{\"a\":1}\n")?);
+        assert!(!spool.push("{\"a\":2}\n")?);
+
+        let mut spool = Spool::open(dir.join("oldest"), 8, SpoolOverflow::DropOldest)?;
+        assert!(spool.push("{\"a\":1}\n")?);
+        assert!(spool.push("{\"a\":2}\n")?);
+        let mut drained = Vec::new();
+        spool.drain(|contents| {
+            drained.extend_from_slice(contents);
+            Ok(())
+        })?;
+        assert_eq!(drained, b"{\"a\":2}\n");
+        assert!(spool.is_empty());
+        fs::remove_dir_all(dir)
+    }
+
+    #[test]
+    fn test_spool_name() {
+        let name = spool_name(Path::new("/var/lib/guardrails/capture.jsonl"));
+        assert!(name.starts_with("capture.jsonl-"));
+        assert_eq!(
+            name,
+            spool_name(Path::new("/var/lib/guardrails/capture.jsonl"))
+        );
+        assert_ne!(name, spool_name(Path::new("/var/lib/tenant/capture.jsonl")));
+    }
+
+    #[test]
+    fn test_writer_spools_until_file_recovers() -> io::Result<()> {
+        let dir = temp_dir();
+        // Parent directory of the file does not exist yet
+        let path = dir.join("sink").join("records.jsonl");
+        let config = SpoolConfig {
+            dir: dir.join("spool"),
+            max_bytes: 1024,
+            overflow: SpoolOverflow::DropNewest,
+            retry_interval: 1,
+            backpressure: false,
+        };
+        let mut writer = Writer::new(path.clone(), Some(&config));
+        writer.write("{\"a\":1}\n".into());
+        writer.write("{\"a\":2}\n".into());
+        assert!(!writer.spool.as_ref().unwrap().is_empty());
+
+        // Spooled records survive restarts
+        let mut writer = Writer::new(path.clone(), Some(&config));
+        fs::create_dir_all(dir.join("sink"))?;
+        writer.drain();
+        writer.write("{\"a\":3}\n".into());
+        assert_eq!(
+            fs::read_to_string(&path)?,
+            "{\"a\":1}\n{\"a\":2}\n{\"a\":3}\n"
+        );
+        assert!(writer.spool.as_ref().unwrap().is_empty());
+        fs::remove_dir_all(dir)
+    }
+}
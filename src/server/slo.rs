@@ -0,0 +1,75 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Service level objective metrics of guardrails server routes.
+use std::{sync::Arc, time::Instant};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use tracing::info;
+
+use crate::config::SloConfig;
+
+/// Counts requests as good or bad against the objectives of their route.
+///
+/// A request is bad if it fails with a server error or takes longer than the latency
+/// objective to respond. For streaming responses, latency is the time to the response
+/// headers. Requests to unknown routes are not counted.
+pub async fn record_slo(
+    State(slo): State<Arc<SloConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(route) = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+    else {
+        return next.run(request).await;
+    };
+    let objectives = slo.objectives(&route);
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let latency_ms = start.elapsed().as_millis() as u64;
+    let error = response.status().is_server_error();
+    let slow = objectives
+        .latency_ms
+        .is_some_and(|objective| latency_ms > objective);
+    let latency_objective_ms = objectives.latency_ms.unwrap_or_default();
+    let availability_objective = objectives.availability.unwrap_or_default();
+    if error || slow {
+        let reason = if error { "error" } else { "latency" };
+        info!(
+            monotonic_counter.slo_bad_request_count = 1,
+            route = %route,
+            reason,
+            latency_objective_ms,
+            availability_objective,
+        );
+    } else {
+        info!(
+            monotonic_counter.slo_good_request_count = 1,
+            route = %route,
+            latency_objective_ms,
+            availability_objective,
+        );
+    }
+    response
+}
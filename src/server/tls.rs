@@ -14,43 +14,78 @@
  limitations under the License.
 
 */
-use std::{fs::File, io::BufReader, path::PathBuf, sync::Arc};
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use axum::{Router, extract::Request};
 use hyper::body::Incoming;
 use hyper_util::rt::{TokioExecutor, TokioIo};
-use rustls::{RootCertStore, ServerConfig, server::WebPkiClientVerifier};
+use rustls::{
+    DigitallySignedStruct, DistinguishedName, RootCertStore, ServerConfig, SignatureScheme,
+    client::danger::HandshakeSignatureValid,
+    server::{
+        WebPkiClientVerifier,
+        danger::{ClientCertVerified, ClientCertVerifier},
+    },
+};
 use tokio::net::TcpListener;
 use tokio_rustls::TlsAcceptor;
 use tower::Service;
 use tracing::{debug, error, info, warn};
-use webpki::types::{CertificateDer, PrivateKeyDer};
+use webpki::types::{CertificateDer, CertificateRevocationListDer, PrivateKeyDer, UnixTime};
+
+use crate::utils::tls;
+
+/// Guardrails server TLS configuration.
+#[derive(Debug, Clone, Default)]
+pub struct ServerTlsConfig {
+    pub cert_path: Option<PathBuf>,
+    pub key_path: Option<PathBuf>,
+    /// CA certs verifying client certificates, enables mTLS
+    pub client_ca_cert_path: Option<PathBuf>,
+    /// Certificate revocation lists checked against client certificates
+    pub client_crl_path: Option<PathBuf>,
+    /// Interval at which client CA certs and revocation lists are re-loaded
+    pub reload_interval: Option<Duration>,
+    /// Period before expiry at which loaded certificates are logged as warnings
+    pub expiry_warning_period: Duration,
+}
 
 /// Loads certificates and configures TLS.
-pub fn configure_tls(
-    tls_cert_path: Option<PathBuf>,
-    tls_key_path: Option<PathBuf>,
-    tls_client_ca_cert_path: Option<PathBuf>,
-) -> Option<Arc<ServerConfig>> {
-    if let (Some(cert_path), Some(key_path)) = (tls_cert_path, tls_key_path) {
+pub fn configure_tls(tls: ServerTlsConfig) -> Option<Arc<ServerConfig>> {
+    if let (Some(cert_path), Some(key_path)) = (&tls.cert_path, &tls.key_path) {
         let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
-        let cert = load_certs(&cert_path);
-        let key = load_private_key(&key_path);
+        let cert = load_certs(cert_path);
+        observe_certs(cert_path, &cert, tls.expiry_warning_period);
+        let key = load_private_key(key_path);
         // Configure mTLS if client CA is provided
-        let client_auth = if let Some(client_ca_cert_path) = tls_client_ca_cert_path {
-            let client_certs = load_certs(&client_ca_cert_path);
-            let mut client_auth_certs = RootCertStore::empty();
-            for client_cert in client_certs {
-                client_auth_certs
-                    .add(client_cert.clone())
-                    .unwrap_or_else(|e| {
-                        panic!("error adding client cert {:?}: {}", client_cert, e)
-                    });
+        let client_auth = if let Some(client_ca_cert_path) = tls.client_ca_cert_path {
+            let client_crl_path = tls.client_crl_path;
+            let verifier = build_client_verifier(
+                &client_ca_cert_path,
+                client_crl_path.as_ref(),
+                tls.expiry_warning_period,
+            )
+            .unwrap_or_else(|e| panic!("error building client verifier: {}", e));
+            info!(crl = client_crl_path.is_some(), "mTLS enabled");
+            match tls.reload_interval {
+                Some(reload_interval) => {
+                    let verifier = Arc::new(ReloadingClientVerifier(RwLock::new(verifier)));
+                    verifier.clone().watch(
+                        client_ca_cert_path,
+                        client_crl_path,
+                        reload_interval,
+                        tls.expiry_warning_period,
+                    );
+                    verifier as Arc<dyn ClientCertVerifier>
+                }
+                None => verifier,
             }
-            info!("mTLS enabled");
-            WebPkiClientVerifier::builder(client_auth_certs.into())
-                .build()
-                .unwrap_or_else(|e| panic!("error building client verifier: {}", e))
         } else {
             info!("TLS enabled");
             WebPkiClientVerifier::no_client_auth()
@@ -66,6 +101,135 @@ pub fn configure_tls(
     }
 }
 
+/// Builds a client certificate verifier trusting the CA certs at `client_ca_cert_path`,
+/// rejecting client certificates revoked by the CRLs at `client_crl_path`.
+fn build_client_verifier(
+    client_ca_cert_path: &PathBuf,
+    client_crl_path: Option<&PathBuf>,
+    expiry_warning_period: Duration,
+) -> Result<Arc<dyn ClientCertVerifier>, String> {
+    let client_ca_certs = try_load_certs(client_ca_cert_path)?;
+    observe_certs(client_ca_cert_path, &client_ca_certs, expiry_warning_period);
+    let mut client_auth_certs = RootCertStore::empty();
+    for client_cert in client_ca_certs {
+        client_auth_certs
+            .add(client_cert)
+            .map_err(|e| format!("error adding client ca cert: {e}"))?;
+    }
+    let mut builder = WebPkiClientVerifier::builder(client_auth_certs.into());
+    if let Some(client_crl_path) = client_crl_path {
+        // Revocation status is unknown for certificates of issuers without a CRL
+        builder = builder
+            .with_crls(try_load_crls(client_crl_path)?)
+            .only_check_end_entity_revocation()
+            .allow_unknown_revocation_status();
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Client certificate verifier re-loading its CA certs and revocation lists, so new
+/// connections are verified against the latest ones.
+#[derive(Debug)]
+struct ReloadingClientVerifier(RwLock<Arc<dyn ClientCertVerifier>>);
+
+impl ReloadingClientVerifier {
+    fn current(&self) -> Arc<dyn ClientCertVerifier> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Re-builds the verifier at `interval`.
+    fn watch(
+        self: Arc<Self>,
+        client_ca_cert_path: PathBuf,
+        client_crl_path: Option<PathBuf>,
+        interval: Duration,
+        expiry_warning_period: Duration,
+    ) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            // First tick completes immediately
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                self.reload(
+                    client_ca_cert_path.clone(),
+                    client_crl_path.clone(),
+                    expiry_warning_period,
+                )
+                .await;
+            }
+        });
+    }
+
+    /// Re-builds the verifier, reading files on the blocking thread pool. Invalid files are
+    /// logged, keeping the previous verifier.
+    async fn reload(
+        &self,
+        client_ca_cert_path: PathBuf,
+        client_crl_path: Option<PathBuf>,
+        expiry_warning_period: Duration,
+    ) {
+        let verifier = tokio::task::spawn_blocking(move || {
+            build_client_verifier(
+                &client_ca_cert_path,
+                client_crl_path.as_ref(),
+                expiry_warning_period,
+            )
+        })
+        .await
+        .unwrap_or_else(|error| Err(error.to_string()));
+        match verifier {
+            Ok(verifier) => {
+                debug!("reloaded client ca certs and crls");
+                *self.0.write().unwrap() = verifier;
+            }
+            Err(error) => {
+                warn!(%error, "failed to reload client ca certs and crls, keeping previous")
+            }
+        }
+    }
+}
+
+impl ClientCertVerifier for ReloadingClientVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        // Hints borrow from the verifier, which is replaced on reload.
+        // Clients offer their certificate without hints.
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        self.current()
+            .verify_client_cert(end_entity, intermediates, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.current().verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.current().verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.current().supported_verify_schemes()
+    }
+}
+
 /// Serve the service with the supplied listener, TLS config, and shutdown signal.
 /// Based on https://github.com/tokio-rs/axum/blob/main/examples/low-level-rustls/src/main.rs
 pub fn serve_with_tls<F>(
@@ -152,6 +316,33 @@ fn load_certs(filename: &PathBuf) -> Vec<CertificateDer<'static>> {
         .collect()
 }
 
+/// Records the expiry of `certs` loaded from `path` in metrics, logging certificates
+/// that expired or expire within `warning_period`.
+fn observe_certs(path: &Path, certs: &[CertificateDer<'_>], warning_period: Duration) {
+    let source = format!("file `{}`", path.display());
+    for warning in tls::observe_certs(&source, certs, warning_period) {
+        warn!("server TLS: {warning}");
+    }
+}
+
+/// Load certificates from a file, without panicking
+fn try_load_certs(filename: &PathBuf) -> Result<Vec<CertificateDer<'static>>, String> {
+    let cert_file = File::open(filename)
+        .map_err(|e| format!("cannot open certificate file {filename:?}: {e}"))?;
+    rustls_pemfile::certs(&mut BufReader::new(cert_file))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("cannot parse certificate file {filename:?}: {e}"))
+}
+
+/// Load certificate revocation lists from a file
+fn try_load_crls(filename: &PathBuf) -> Result<Vec<CertificateRevocationListDer<'static>>, String> {
+    let crl_file =
+        File::open(filename).map_err(|e| format!("cannot open crl file {filename:?}: {e}"))?;
+    rustls_pemfile::crls(&mut BufReader::new(crl_file))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("cannot parse crl file {filename:?}: {e}"))
+}
+
 /// Load private key from a file
 fn load_private_key(filename: &PathBuf) -> PrivateKeyDer<'static> {
     let key_file = File::open(filename).expect("cannot open private key file");
@@ -170,3 +361,40 @@ fn load_private_key(filename: &PathBuf) -> PrivateKeyDer<'static> {
         filename
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::ensure_crypto_provider;
+
+    #[tokio::test]
+    async fn test_reloading_client_verifier() {
+        ensure_crypto_provider();
+        let resources: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "resources"]
+            .iter()
+            .collect();
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().simple().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        let client_ca_cert_path = dir.join("client-ca.crt");
+        std::fs::copy(resources.join("localhost.crt"), &client_ca_cert_path).unwrap();
+        let verifier = build_client_verifier(&client_ca_cert_path, None, Duration::ZERO).unwrap();
+        let verifier = ReloadingClientVerifier(RwLock::new(verifier));
+        let initial = verifier.current();
+
+        // Invalid CA certs keep the previous verifier
+        std::fs::write(&client_ca_cert_path, "invalid").unwrap();
+        verifier
+            .reload(client_ca_cert_path.clone(), None, Duration::ZERO)
+            .await;
+        assert!(Arc::ptr_eq(&verifier.current(), &initial));
+
+        // Valid CA certs replace the verifier of new connections
+        std::fs::copy(resources.join("localhost.crt"), &client_ca_cert_path).unwrap();
+        verifier
+            .reload(client_ca_cert_path.clone(), None, Duration::ZERO)
+            .await;
+        assert!(!Arc::ptr_eq(&verifier.current(), &initial));
+        assert!(verifier.current().offer_client_auth());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
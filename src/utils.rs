@@ -1,6 +1,15 @@
 use hyper::Uri;
 use url::Url;
+pub mod buffer_pool;
+pub mod debug_info;
+pub mod exemplars;
 pub mod json;
+pub mod secrets;
+pub mod single_flight;
+#[cfg(test)]
+pub mod test_events;
+#[cfg(test)]
+pub mod test_server;
 pub mod tls;
 pub mod trace;
 
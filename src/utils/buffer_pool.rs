@@ -0,0 +1,130 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+use std::sync::{LazyLock, Mutex};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::Serialize;
+
+/// Capacity reserved by buffers before serializing.
+const DEFAULT_BUFFER_CAPACITY: usize = 4 * 1024;
+/// Maximum number of idle buffers retained by the pool.
+const DEFAULT_MAX_BUFFERS: usize = 256;
+/// Buffers that grew beyond this capacity are dropped instead of returned to the pool.
+const DEFAULT_MAX_BUFFER_CAPACITY: usize = 1024 * 1024;
+
+/// Global buffer pool used for serializing client request bodies.
+pub static BUFFER_POOL: LazyLock<BufferPool> = LazyLock::new(BufferPool::default);
+
+/// A pool of reusable byte buffers.
+///
+/// Values are serialized into a pooled buffer and split off as [`Bytes`] sharing its
+/// allocation, without copying. The buffer keeps the remaining capacity, and reclaims
+/// the allocation once all bytes split off from it are dropped, so request bodies
+/// fanned out to many detectors reuse a few allocations instead of growing a fresh
+/// `Vec` each.
+#[derive(Debug)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<BytesMut>>,
+    max_buffers: usize,
+    max_buffer_capacity: usize,
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_BUFFERS, DEFAULT_MAX_BUFFER_CAPACITY)
+    }
+}
+
+impl BufferPool {
+    pub fn new(max_buffers: usize, max_buffer_capacity: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::with_capacity(max_buffers)),
+            max_buffers,
+            max_buffer_capacity,
+        }
+    }
+
+    /// Returns the number of idle buffers in the pool.
+    pub fn len(&self) -> usize {
+        self.buffers.lock().unwrap().len()
+    }
+
+    /// Returns `true` if the pool has no idle buffers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Serializes the given data structure as JSON into a pooled buffer.
+    pub fn to_json_bytes<T>(&self, value: &T) -> Result<Bytes, serde_json::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let mut buf = self.buffers.lock().unwrap().pop().unwrap_or_default();
+        // Reclaims the allocation of dropped bytes, or allocates if they are still used
+        buf.reserve(DEFAULT_BUFFER_CAPACITY);
+        let result = serde_json::to_writer((&mut buf).writer(), value);
+        let bytes = buf.split().freeze();
+        self.put(buf);
+        result.map(|_| bytes)
+    }
+
+    fn put(&self, buf: BytesMut) {
+        if buf.capacity() > self.max_buffer_capacity {
+            return;
+        }
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.max_buffers {
+            buffers.push(buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_reuse() {
+        let pool = BufferPool::new(2, DEFAULT_MAX_BUFFER_CAPACITY);
+        let bytes = pool.to_json_bytes(&vec!["a", "b"]).unwrap();
+        assert_eq!(&bytes[..], br#"["a","b"]"#);
+        assert_eq!(pool.len(), 1);
+
+        // Bytes in use are not overwritten
+        let next = pool.to_json_bytes(&"c").unwrap();
+        assert_eq!(&bytes[..], br#"["a","b"]"#);
+        assert_eq!(&next[..], br#""c""#);
+
+        // The allocation is reclaimed once its bytes are dropped
+        let ptr = next.as_ptr();
+        drop((bytes, next));
+        let reused = pool.to_json_bytes(&"d").unwrap();
+        assert_eq!(&reused[..], br#""d""#);
+        assert_eq!(reused.as_ptr(), ptr);
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_oversized_buffer_dropped() {
+        let pool = BufferPool::new(2, 8);
+        let _ = pool
+            .to_json_bytes(&"a string longer than eight bytes")
+            .unwrap();
+        assert!(pool.is_empty());
+    }
+}
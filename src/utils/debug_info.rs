@@ -0,0 +1,176 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Processing metadata of a request, returned in responses of requests with `debug=true`.
+//!
+//! Metadata is collected in a task-local, so it is recorded by code running in the
+//! request's task only. Recording outside of [`collect`] is a no-op, so futures spawned
+//! as separate tasks must be wrapped with [`propagate`].
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+tokio::task_local! {
+    static DEBUG_INFO: Arc<Mutex<DebugInfo>>;
+}
+
+/// Processing metadata of a request.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DebugInfo {
+    /// Durations of processing phases, e.g. chunking and generation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub phases: Vec<PhaseDuration>,
+    /// Chunkers used
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunkers: Vec<String>,
+    /// Detector requests sent
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub detector_calls: Vec<DetectorCall>,
+    /// Policy decisions, e.g. detections filtered by threshold
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub decisions: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhaseDuration {
+    pub name: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectorCall {
+    pub detector_id: String,
+    pub duration_ms: u64,
+    /// Whether the request was served by an identical in-flight request
+    #[serde(default)]
+    pub coalesced: bool,
+}
+
+/// Awaits `future`, collecting its debug info if `enabled`.
+pub async fn collect<F: Future>(enabled: bool, future: F) -> (F::Output, Option<DebugInfo>) {
+    if !enabled {
+        return (future.await, None);
+    }
+    let info = Arc::new(Mutex::new(DebugInfo::default()));
+    let start = Instant::now();
+    let output = DEBUG_INFO.scope(info.clone(), future).await;
+    let mut info = info.lock().unwrap().clone();
+    info.phases.push(PhaseDuration {
+        name: "total".into(),
+        duration_ms: start.elapsed().as_millis() as u64,
+    });
+    (output, Some(info))
+}
+
+/// Wraps `future` to record to the debug info of the current request, if collected,
+/// when spawned as a separate task.
+pub fn propagate<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let info = DEBUG_INFO.try_with(Arc::clone).ok();
+    async move {
+        match info {
+            Some(info) => DEBUG_INFO.scope(info, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// Records to the debug info of the current request, if collected.
+fn record(f: impl FnOnce(&mut DebugInfo)) {
+    let _ = DEBUG_INFO.try_with(|info| f(&mut info.lock().unwrap()));
+}
+
+/// Records the duration of a processing phase.
+pub fn record_phase(name: &str, duration: Duration) {
+    record(|info| {
+        info.phases.push(PhaseDuration {
+            name: name.into(),
+            duration_ms: duration.as_millis() as u64,
+        })
+    });
+}
+
+/// Records a chunker used.
+pub fn record_chunker(chunker_id: &str) {
+    record(|info| {
+        if !info.chunkers.iter().any(|id| id == chunker_id) {
+            info.chunkers.push(chunker_id.into());
+        }
+    });
+}
+
+/// Records a detector request.
+pub fn record_detector_call(detector_id: &str, duration: Duration, coalesced: bool) {
+    record(|info| {
+        info.detector_calls.push(DetectorCall {
+            detector_id: detector_id.into(),
+            duration_ms: duration.as_millis() as u64,
+            coalesced,
+        })
+    });
+}
+
+/// Records a policy decision.
+pub fn record_decision(decision: impl FnOnce() -> String) {
+    record(|info| info.decisions.push(decision()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_collect() {
+        let (output, info) = collect(true, async {
+            record_chunker("sentence");
+            record_chunker("sentence");
+            record_detector_call("hap", Duration::from_millis(5), false);
+            record_decision(|| "decided".into());
+            1
+        })
+        .await;
+        assert_eq!(output, 1);
+        let info = info.unwrap();
+        assert_eq!(info.chunkers, ["sentence"]);
+        assert_eq!(info.detector_calls.len(), 1);
+        assert_eq!(info.decisions, ["decided"]);
+        assert_eq!(info.phases.last().unwrap().name, "total");
+
+        let (_, info) = collect(false, async { record_chunker("sentence") }).await;
+        assert!(info.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_propagate() {
+        let (_, info) = collect(true, async {
+            tokio::spawn(propagate(async { record_chunker("sentence") }))
+                .await
+                .unwrap();
+            tokio::spawn(async { record_chunker("paragraph") })
+                .await
+                .unwrap();
+        })
+        .await;
+        assert_eq!(info.unwrap().chunkers, ["sentence"]);
+
+        let output = tokio::spawn(propagate(async { record_chunker("sentence") })).await;
+        assert!(output.is_ok());
+    }
+}
@@ -0,0 +1,350 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Latency histograms per route and per downstream client, with exemplars referencing the
+//! trace of a request recorded in each bucket.
+//!
+//! The OpenTelemetry SDK does not support exemplars, so these histograms are registered
+//! with the Prometheus registry directly, and exemplars are served by encoding the
+//! registry in the OpenMetrics format.
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{Mutex, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use opentelemetry::trace::TraceId;
+use prometheus::{
+    HistogramOpts, HistogramVec, Registry,
+    proto::{LabelPair, MetricFamily, MetricType},
+};
+
+use super::trace::current_trace_id;
+
+/// Name of the histogram of incoming request latency by route.
+pub const ROUTE_LATENCY_METRIC: &str = "route_request_duration_seconds";
+/// Name of the histogram of downstream request latency by client.
+pub const CLIENT_LATENCY_METRIC: &str = "downstream_request_duration_seconds";
+/// `Content-Type` of metrics encoded by [`encode_openmetrics`].
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+static LATENCY_HISTOGRAMS: OnceLock<LatencyHistograms> = OnceLock::new();
+
+/// A traced observation of a histogram bucket.
+#[derive(Debug, Clone, PartialEq)]
+struct Exemplar {
+    trace_id: TraceId,
+    value: f64,
+    /// Time of the observation, in seconds since the Unix epoch
+    timestamp: f64,
+}
+
+/// Key of an exemplar: metric name, label value and bucket index.
+type ExemplarKey = (&'static str, String, usize);
+
+#[derive(Debug)]
+struct LatencyHistograms {
+    route: HistogramVec,
+    client: HistogramVec,
+    /// Latest exemplar of each bucket
+    exemplars: Mutex<HashMap<ExemplarKey, Exemplar>>,
+}
+
+impl LatencyHistograms {
+    fn new() -> Result<Self, prometheus::Error> {
+        let route = HistogramVec::new(
+            HistogramOpts::new(
+                ROUTE_LATENCY_METRIC,
+                "Latency of incoming requests by route",
+            ),
+            &["route"],
+        )?;
+        let client = HistogramVec::new(
+            HistogramOpts::new(
+                CLIENT_LATENCY_METRIC,
+                "Latency of downstream requests by client",
+            ),
+            &["client"],
+        )?;
+        Ok(Self {
+            route,
+            client,
+            exemplars: Mutex::default(),
+        })
+    }
+
+    fn observe(&self, metric: &'static str, label: &str, latency: Duration, trace_id: TraceId) {
+        let histogram = match metric {
+            ROUTE_LATENCY_METRIC => &self.route,
+            _ => &self.client,
+        };
+        let value = latency.as_secs_f64();
+        histogram.with_label_values(&[label]).observe(value);
+        if trace_id == TraceId::INVALID {
+            return;
+        }
+        // Index of the first bucket the value falls in, the number of buckets for `+Inf`
+        let bucket = prometheus::DEFAULT_BUCKETS
+            .iter()
+            .position(|upper_bound| value <= *upper_bound)
+            .unwrap_or(prometheus::DEFAULT_BUCKETS.len());
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        self.exemplars.lock().unwrap().insert(
+            (metric, label.to_string(), bucket),
+            Exemplar {
+                trace_id,
+                value,
+                timestamp,
+            },
+        );
+    }
+
+    /// Returns the exemplar of a bucket of `metric` for a series with `labels`.
+    fn exemplar(&self, metric: &str, labels: &[LabelPair], bucket: usize) -> Option<Exemplar> {
+        let (metric, label) = match metric {
+            ROUTE_LATENCY_METRIC => (ROUTE_LATENCY_METRIC, "route"),
+            CLIENT_LATENCY_METRIC => (CLIENT_LATENCY_METRIC, "client"),
+            _ => return None,
+        };
+        let value = labels.iter().find(|pair| pair.get_name() == label)?;
+        self.exemplars
+            .lock()
+            .unwrap()
+            .get(&(metric, value.get_value().to_string(), bucket))
+            .cloned()
+    }
+}
+
+/// Registers the latency histograms with `registry`, enabling their recording.
+pub fn register(registry: &Registry) -> Result<(), prometheus::Error> {
+    let histograms = LatencyHistograms::new()?;
+    registry.register(Box::new(histograms.route.clone()))?;
+    registry.register(Box::new(histograms.client.clone()))?;
+    let _ = LATENCY_HISTOGRAMS.set(histograms);
+    Ok(())
+}
+
+/// Records the latency of an incoming request to `route`, with an exemplar of the current
+/// trace.
+pub fn observe_route(route: &str, latency: Duration) {
+    if let Some(histograms) = LATENCY_HISTOGRAMS.get() {
+        histograms.observe(ROUTE_LATENCY_METRIC, route, latency, current_trace_id());
+    }
+}
+
+/// Records the latency of a downstream request of `client`, with an exemplar of the
+/// current trace.
+pub fn observe_client(client: &str, latency: Duration) {
+    if let Some(histograms) = LATENCY_HISTOGRAMS.get() {
+        histograms.observe(CLIENT_LATENCY_METRIC, client, latency, current_trace_id());
+    }
+}
+
+/// Encodes `families` in the OpenMetrics text format, with exemplars of the buckets of
+/// latency histograms.
+pub fn encode_openmetrics(families: &[MetricFamily]) -> String {
+    encode(families, LATENCY_HISTOGRAMS.get())
+}
+
+fn encode(families: &[MetricFamily], histograms: Option<&LatencyHistograms>) -> String {
+    let mut out = String::new();
+    for family in families {
+        let name = family.get_name();
+        let (name, kind) = match family.get_field_type() {
+            // Counter samples are suffixed with `_total`, but not their family name
+            MetricType::COUNTER => (name.strip_suffix("_total").unwrap_or(name), "counter"),
+            MetricType::GAUGE => (name, "gauge"),
+            MetricType::HISTOGRAM => (name, "histogram"),
+            MetricType::SUMMARY => (name, "summary"),
+            MetricType::UNTYPED => (name, "unknown"),
+        };
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+        if !family.get_help().is_empty() {
+            let _ = writeln!(out, "# HELP {name} {}", escape(family.get_help()));
+        }
+        for metric in family.get_metric() {
+            let labels = metric.get_label();
+            match family.get_field_type() {
+                MetricType::COUNTER => {
+                    let value = metric.get_counter().get_value();
+                    write_sample(&mut out, name, "_total", labels, None, value);
+                }
+                MetricType::GAUGE => {
+                    let value = metric.get_gauge().get_value();
+                    write_sample(&mut out, name, "", labels, None, value);
+                }
+                MetricType::UNTYPED => {
+                    let value = metric.get_untyped().get_value();
+                    write_sample(&mut out, name, "", labels, None, value);
+                }
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    let buckets = histogram.get_bucket();
+                    let upper_bounds = buckets
+                        .iter()
+                        .map(|bucket| (bucket.get_upper_bound(), bucket.get_cumulative_count()))
+                        .chain([(f64::INFINITY, histogram.get_sample_count())]);
+                    for (i, (upper_bound, count)) in upper_bounds.enumerate() {
+                        let le = ("le", format_value(upper_bound));
+                        let exemplar = histograms.and_then(|h| h.exemplar(name, labels, i));
+                        let _ = write!(out, "{name}_bucket");
+                        write_labels(&mut out, labels, Some(le));
+                        let _ = write!(out, " {}", format_value(count as f64));
+                        if let Some(exemplar) = exemplar {
+                            let _ = write!(
+                                out,
+                                " # {{trace_id=\"{}\"}} {} {:.3}",
+                                exemplar.trace_id,
+                                format_value(exemplar.value),
+                                exemplar.timestamp
+                            );
+                        }
+                        out.push('\n');
+                    }
+                    let count = histogram.get_sample_count() as f64;
+                    write_sample(&mut out, name, "_count", labels, None, count);
+                    let sum = histogram.get_sample_sum();
+                    write_sample(&mut out, name, "_sum", labels, None, sum);
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        let label = ("quantile", format_value(quantile.get_quantile()));
+                        let value = quantile.get_value();
+                        write_sample(&mut out, name, "", labels, Some(label), value);
+                    }
+                    let count = summary.get_sample_count() as f64;
+                    write_sample(&mut out, name, "_count", labels, None, count);
+                    let sum = summary.get_sample_sum();
+                    write_sample(&mut out, name, "_sum", labels, None, sum);
+                }
+            }
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+fn write_sample(
+    out: &mut String,
+    name: &str,
+    suffix: &str,
+    labels: &[LabelPair],
+    extra_label: Option<(&str, String)>,
+    value: f64,
+) {
+    let _ = write!(out, "{name}{suffix}");
+    write_labels(out, labels, extra_label);
+    let _ = writeln!(out, " {}", format_value(value));
+}
+
+fn write_labels(out: &mut String, labels: &[LabelPair], extra_label: Option<(&str, String)>) {
+    if labels.is_empty() && extra_label.is_none() {
+        return;
+    }
+    let labels = labels
+        .iter()
+        .map(|pair| (pair.get_name(), escape(pair.get_value())))
+        .chain(extra_label)
+        .map(|(name, value)| format!("{name}=\"{value}\""))
+        .collect::<Vec<_>>()
+        .join(",");
+    let _ = write!(out, "{{{labels}}}");
+}
+
+/// Formats a sample value, as OpenMetrics spells infinities and NaN.
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".into()
+    } else if value == f64::INFINITY {
+        "+Inf".into()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".into()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escapes a label value or help text.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_openmetrics() {
+        let registry = Registry::new();
+        let histograms = LatencyHistograms::new().unwrap();
+        registry
+            .register(Box::new(histograms.route.clone()))
+            .unwrap();
+        let counter = prometheus::IntCounter::new("requests_total", "Requests").unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        counter.inc();
+
+        let trace_id = TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap();
+        let route = "/api/v2/text/detection/content";
+        histograms.observe(
+            ROUTE_LATENCY_METRIC,
+            route,
+            Duration::from_millis(30),
+            trace_id,
+        );
+        // Observations without a trace have no exemplar
+        histograms.observe(
+            ROUTE_LATENCY_METRIC,
+            route,
+            Duration::from_secs(20),
+            TraceId::INVALID,
+        );
+
+        let encoded = encode(&registry.gather(), Some(&histograms));
+        let lines = encoded.lines().collect::<Vec<_>>();
+        assert!(lines.contains(&"# TYPE requests counter"));
+        assert!(lines.contains(&"requests_total 1"));
+        assert!(lines.contains(&"# TYPE route_request_duration_seconds histogram"));
+        let bucket = |le: &str| {
+            let prefix =
+                format!("route_request_duration_seconds_bucket{{route=\"{route}\",le=\"{le}\"}}");
+            lines
+                .iter()
+                .find(|line| line.starts_with(&prefix))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            bucket("0.025"),
+            format!("route_request_duration_seconds_bucket{{route=\"{route}\",le=\"0.025\"}} 0")
+        );
+        assert!(
+            bucket("0.05").contains(" 1 # {trace_id=\"0af7651916cd43dd8448eb211c80319c\"} 0.03 ")
+        );
+        assert!(bucket("+Inf").ends_with(" 2"));
+        assert_eq!(lines.last(), Some(&"# EOF"));
+    }
+}
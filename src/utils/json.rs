@@ -1,4 +1,7 @@
-use serde::Serialize;
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{self, Unexpected},
+};
 
 /// Serialize the given data structure as a String of ND-JSON.
 ///
@@ -16,3 +19,48 @@ where
     let string = unsafe { String::from_utf8_unchecked(bytes) };
     Ok(string)
 }
+
+/// Deserializes a score from a JSON number or a string of a number, as returned by some
+/// detectors. Non-finite scores, e.g. `"NaN"` or `"inf"`, are rejected.
+pub fn deserialize_score<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Score {
+        Number(f64),
+        String(String),
+    }
+    match Score::deserialize(deserializer)? {
+        Score::Number(score) => Ok(score),
+        Score::String(score) => score
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .ok_or_else(|| de::Error::invalid_value(Unexpected::Str(&score), &"a finite score")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_score() {
+        #[derive(Deserialize)]
+        struct Detection {
+            #[serde(deserialize_with = "deserialize_score")]
+            score: f64,
+        }
+        let score = |json| serde_json::from_str::<Detection>(json).map(|d| d.score);
+        assert_eq!(score(r#"{"score": 0.5}"#).unwrap(), 0.5);
+        assert_eq!(score(r#"{"score": 87}"#).unwrap(), 87.0);
+        assert_eq!(score(r#"{"score": " 0.25"}"#).unwrap(), 0.25);
+        assert!(score(r#"{"score": "high"}"#).is_err());
+        assert!(score(r#"{"score": "NaN"}"#).is_err());
+        assert!(score(r#"{"score": "inf"}"#).is_err());
+        assert!(score(r#"{"score": "-infinity"}"#).is_err());
+    }
+}
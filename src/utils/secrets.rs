@@ -0,0 +1,296 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+//! Secrets, e.g. TLS keys, loaded from pluggable sources.
+//!
+//! Secrets are cached once loaded. [`watch`] re-loads secrets periodically, calling a
+//! hook when any of them is rotated.
+use std::{
+    collections::HashMap,
+    fmt,
+    path::PathBuf,
+    sync::{Arc, LazyLock, RwLock},
+    time::Duration,
+};
+
+use base64::Engine;
+use serde::Deserialize;
+use tokio::task::AbortHandle;
+use tracing::{info, warn};
+
+/// Service account files mounted in Kubernetes pods.
+const KUBERNETES_SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+/// Loaded secrets.
+static SECRETS: LazyLock<RwLock<HashMap<SecretSource, Arc<[u8]>>>> =
+    LazyLock::new(Default::default);
+
+#[derive(Debug, thiserror::Error)]
+#[error("failed to load secret from {secret}: {reason}")]
+pub struct Error {
+    secret: String,
+    reason: String,
+}
+
+/// Source of a secret.
+///
+/// A plain path is read from a file, e.g. `cert_path: /certs/client.pem`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretSource {
+    /// Read from a file
+    File(PathBuf),
+    /// Read from an environment variable
+    Env(String),
+    /// Read from a HashiCorp Vault KV version 2 secret. Vault is addressed by the
+    /// `VAULT_ADDR` environment variable and authenticated with `VAULT_TOKEN`
+    Vault(VaultSecret),
+    /// Read from a Kubernetes Secret with the pod's service account
+    Kubernetes(KubernetesSecret),
+    #[serde(untagged)]
+    Path(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VaultSecret {
+    /// KV secrets engine mount, defaults to `secret`
+    pub mount: Option<String>,
+    /// Secret path within the mount
+    pub path: String,
+    /// Field of the secret
+    pub field: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KubernetesSecret {
+    /// Namespace of the secret, defaults to the pod's namespace
+    pub namespace: Option<String>,
+    /// Name of the secret
+    pub name: String,
+    /// Data key of the secret
+    pub key: String,
+}
+
+impl fmt::Display for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretSource::File(path) | SecretSource::Path(path) => {
+                write!(f, "file `{}`", path.display())
+            }
+            SecretSource::Env(name) => write!(f, "environment variable `{name}`"),
+            SecretSource::Vault(secret) => write!(f, "vault `{}#{}`", secret.path, secret.field),
+            SecretSource::Kubernetes(secret) => {
+                write!(f, "kubernetes secret `{}#{}`", secret.name, secret.key)
+            }
+        }
+    }
+}
+
+impl SecretSource {
+    /// Loads the secret, from cache if previously loaded.
+    pub async fn load(&self) -> Result<Arc<[u8]>, Error> {
+        if let Some(secret) = self.cached() {
+            return Ok(secret);
+        }
+        let secret: Arc<[u8]> = self.fetch().await?.into();
+        SECRETS
+            .write()
+            .unwrap()
+            .insert(self.clone(), secret.clone());
+        Ok(secret)
+    }
+
+    /// Returns the secret, if loaded.
+    pub fn cached(&self) -> Option<Arc<[u8]>> {
+        SECRETS.read().unwrap().get(self).cloned()
+    }
+
+    /// Re-loads the secret from its source, returning `true` if it was rotated.
+    pub async fn refresh(&self) -> Result<bool, Error> {
+        let secret: Arc<[u8]> = self.fetch().await?.into();
+        let previous = SECRETS
+            .write()
+            .unwrap()
+            .insert(self.clone(), secret.clone());
+        Ok(previous.is_some_and(|previous| previous != secret))
+    }
+
+    /// Fetches the secret from its source.
+    async fn fetch(&self) -> Result<Vec<u8>, Error> {
+        let error = |reason: String| Error {
+            secret: self.to_string(),
+            reason,
+        };
+        match self {
+            SecretSource::File(path) | SecretSource::Path(path) => tokio::fs::read(path)
+                .await
+                .map_err(|e| error(e.to_string())),
+            SecretSource::Env(name) => std::env::var(name)
+                .map(String::into_bytes)
+                .map_err(|e| error(e.to_string())),
+            SecretSource::Vault(secret) => fetch_vault(secret).await.map_err(error),
+            SecretSource::Kubernetes(secret) => fetch_kubernetes(secret).await.map_err(error),
+        }
+    }
+}
+
+/// Re-loads `secrets` at `interval`, calling `on_rotate` once any of them is rotated.
+/// Loading errors are logged, keeping the previous secret. Returns the handle to stop
+/// watching with.
+pub fn watch(
+    secrets: Vec<SecretSource>,
+    interval: Duration,
+    on_rotate: impl Fn() + Send + Sync + 'static,
+) -> AbortHandle {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        // First tick completes immediately
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let mut rotated = false;
+            for secret in &secrets {
+                match secret.refresh().await {
+                    Ok(true) => {
+                        info!(%secret, "secret rotated");
+                        rotated = true;
+                    }
+                    Ok(false) => (),
+                    Err(error) => warn!(%error, "secret refresh failed"),
+                }
+            }
+            if rotated {
+                on_rotate();
+            }
+        }
+    })
+    .abort_handle()
+}
+
+async fn fetch_vault(secret: &VaultSecret) -> Result<Vec<u8>, String> {
+    let addr = std::env::var("VAULT_ADDR").map_err(|_| "`VAULT_ADDR` is not set".to_string())?;
+    let token = std::env::var("VAULT_TOKEN").map_err(|_| "`VAULT_TOKEN` is not set".to_string())?;
+    let mount = secret.mount.as_deref().unwrap_or("secret");
+    let url = format!(
+        "{}/v1/{mount}/data/{}",
+        addr.trim_end_matches('/'),
+        secret.path
+    );
+    let body: serde_json::Value = reqwest::Client::new()
+        .get(url)
+        .header("X-Vault-Token", token)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    body["data"]["data"][&secret.field]
+        .as_str()
+        .map(|value| value.as_bytes().to_vec())
+        .ok_or_else(|| format!("field `{}` not found", secret.field))
+}
+
+async fn fetch_kubernetes(secret: &KubernetesSecret) -> Result<Vec<u8>, String> {
+    let read = |name: &'static str| async move {
+        tokio::fs::read(format!("{KUBERNETES_SERVICE_ACCOUNT_DIR}/{name}"))
+            .await
+            .map_err(|e| format!("failed to read service account {name}: {e}"))
+    };
+    let host = std::env::var("KUBERNETES_SERVICE_HOST")
+        .map_err(|_| "`KUBERNETES_SERVICE_HOST` is not set".to_string())?;
+    let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".into());
+    let namespace = match &secret.namespace {
+        Some(namespace) => namespace.clone(),
+        None => String::from_utf8_lossy(&read("namespace").await?)
+            .trim()
+            .to_string(),
+    };
+    let token = String::from_utf8_lossy(&read("token").await?)
+        .trim()
+        .to_string();
+    let ca_cert =
+        reqwest::Certificate::from_pem(&read("ca.crt").await?).map_err(|e| e.to_string())?;
+    let client = reqwest::Client::builder()
+        .add_root_certificate(ca_cert)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let url = format!(
+        "https://{host}:{port}/api/v1/namespaces/{namespace}/secrets/{}",
+        secret.name
+    );
+    let body: serde_json::Value = client
+        .get(url)
+        .bearer_auth(token)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    let value = body["data"][&secret.key]
+        .as_str()
+        .ok_or_else(|| format!("key `{}` not found", secret.key))?;
+    base64::engine::general_purpose::STANDARD
+        .decode(value)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_secret_source() {
+        let sources: Vec<SecretSource> = serde_yml::from_str(
+            r#"
+- /certs/client.pem
+- file: /certs/client.pem
+- env: CLIENT_KEY
+- vault:
+    path: orchestrator/tls
+    field: key
+- kubernetes:
+    name: orchestrator-tls
+    key: tls.key
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            sources,
+            [
+                SecretSource::Path("/certs/client.pem".into()),
+                SecretSource::File("/certs/client.pem".into()),
+                SecretSource::Env("CLIENT_KEY".into()),
+                SecretSource::Vault(VaultSecret {
+                    mount: None,
+                    path: "orchestrator/tls".into(),
+                    field: "key".into(),
+                }),
+                SecretSource::Kubernetes(KubernetesSecret {
+                    namespace: None,
+                    name: "orchestrator-tls".into(),
+                    key: "tls.key".into(),
+                }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,182 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use futures::{
+    FutureExt,
+    future::{BoxFuture, Shared},
+};
+
+type Call<V> = Shared<BoxFuture<'static, V>>;
+
+/// Coalesces identical concurrent calls into a single call whose result is shared.
+///
+/// The first caller for a key runs its future, callers arriving with the same key
+/// while it is in flight await the same result. The key is released once the call
+/// completes, or once all of its callers are dropped.
+pub struct SingleFlight<K, V> {
+    next_id: AtomicU64,
+    calls: Mutex<HashMap<K, (u64, Call<V>)>>,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K, V> std::fmt::Debug for SingleFlight<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SingleFlight")
+            .field("in_flight", &self.calls.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl<K, V> SingleFlight<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `fut` unless a call for `key` is already in flight, in which case its result is awaited instead.
+    /// Returns the result and `true` if the call was coalesced.
+    pub async fn run<F>(&self, key: K, fut: F) -> (V, bool)
+    where
+        F: Future<Output = V> + Send + 'static,
+    {
+        let (id, call, coalesced) = {
+            let mut calls = self.calls.lock().unwrap();
+            match calls.get(&key) {
+                Some((id, call)) => (*id, call.clone(), true),
+                None => {
+                    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                    let call = fut.boxed().shared();
+                    calls.insert(key.clone(), (id, call.clone()));
+                    (id, call, false)
+                }
+            }
+        };
+        let mut guard = CallGuard {
+            calls: &self.calls,
+            key,
+            id,
+            call,
+            completed: false,
+        };
+        let value = (&mut guard.call).await;
+        guard.completed = true;
+        (value, coalesced)
+    }
+
+    /// Returns the number of calls in flight.
+    pub fn len(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+
+    /// Returns `true` if no calls are in flight.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Releases the key of a call when it completes or its last caller is dropped.
+struct CallGuard<'a, K: Hash + Eq, V> {
+    calls: &'a Mutex<HashMap<K, (u64, Call<V>)>>,
+    key: K,
+    id: u64,
+    call: Call<V>,
+    completed: bool,
+}
+
+impl<K: Hash + Eq, V> Drop for CallGuard<'_, K, V> {
+    fn drop(&mut self) {
+        let mut calls = self.calls.lock().unwrap();
+        // Held by the map and this guard only
+        let last_caller = self.call.strong_count().is_some_and(|count| count <= 2);
+        let current = calls.get(&self.key).is_some_and(|(id, _)| *id == self.id);
+        if current && (self.completed || last_caller) {
+            calls.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, atomic::AtomicUsize},
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_single_flight() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let single_flight = SingleFlight::<&str, usize>::new();
+        let call = |calls: Arc<AtomicUsize>| async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            calls.fetch_add(1, Ordering::SeqCst) + 1
+        };
+        let (first, second, other) = tokio::join!(
+            single_flight.run("a", call(calls.clone())),
+            single_flight.run("a", call(calls.clone())),
+            single_flight.run("b", call(calls.clone())),
+        );
+        // Identical calls share a single result
+        assert_eq!(first.0, second.0);
+        assert!(!first.1 && second.1);
+        assert!(!other.1);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(single_flight.is_empty());
+
+        // Completed calls are not reused
+        let (value, coalesced) = single_flight.run("a", call(calls.clone())).await;
+        assert_eq!(value, 3);
+        assert!(!coalesced);
+    }
+
+    #[tokio::test]
+    async fn test_single_flight_cancelled() {
+        let single_flight = SingleFlight::<&str, usize>::new();
+        let result = tokio::time::timeout(
+            Duration::from_millis(10),
+            single_flight.run("a", async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                1
+            }),
+        )
+        .await;
+        assert!(result.is_err());
+        // Abandoned calls are released
+        assert!(single_flight.is_empty());
+    }
+}
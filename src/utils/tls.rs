@@ -1,27 +1,54 @@
-use std::{fs::File, io, path::PathBuf, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{Arc, LazyLock, Mutex, OnceLock, RwLock},
+    time::{Duration, SystemTime},
+};
 
 use http_serde::http::StatusCode;
 use hyper_rustls::ConfigBuilderExt;
+use opentelemetry::{KeyValue, global, metrics::ObservableGauge};
+use pkcs8::{EncryptedPrivateKeyInfo, LineEnding};
 use rustls::{
-    ClientConfig, DigitallySignedStruct, SignatureScheme,
-    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    ClientConfig, DigitallySignedStruct, InconsistentKeys, SignatureScheme,
+    client::{
+        ResolvesClientCert,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    crypto::ring::sign::any_supported_type,
     pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    sign::CertifiedKey,
 };
 use serde::Deserialize;
+use tokio::task::AbortHandle;
+use tracing::warn;
+use x509_parser::time::ASN1Time;
 
-use crate::{clients, config::TlsConfig};
+use crate::{
+    clients,
+    config::TlsConfig,
+    utils::secrets::{self, SecretSource},
+};
 
 /// Client TLS configuration errors.
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("failed to read TLS certs from file: {0}")]
+    #[error(transparent)]
+    FailedLoadSecret(#[from] secrets::Error),
+    #[error("failed to read TLS certs: {0}")]
     FailedReadCerts(io::Error),
-    #[error("failed to read TLS private key from file: {0}")]
+    #[error("failed to read TLS private key: {0}")]
     FailedReadKey(io::Error),
-    #[error("failed to read TLS CA certs from file: {0}")]
+    #[error("failed to read TLS CA certs: {0}")]
     FailedReadCaCerts(io::Error),
     #[error("missing TLS private key")]
     MissingTlsKey,
+    #[error("TLS private key is encrypted, but no `key_passphrase` is set")]
+    MissingKeyPassphrase,
+    #[error("failed to decrypt TLS private key: {0}")]
+    FailedDecryptKey(String),
+    #[error("`ca_only` is set, but no TLS CA certs are provided")]
+    MissingCaCerts,
     #[error("TLS configuration error: {0}")]
     RustlsError(#[from] rustls::Error),
 }
@@ -90,13 +117,23 @@ impl ServerCertVerifier for NoVerifier {
     }
 }
 
+/// PEM label of encrypted PKCS#8 private keys.
+const ENCRYPTED_PRIVATE_KEY_LABEL: &str = "ENCRYPTED PRIVATE KEY";
+
+/// Expiry of loaded certificates in seconds since the epoch, by source and subject.
+static CERT_EXPIRY: LazyLock<Mutex<BTreeMap<(String, String), i64>>> =
+    LazyLock::new(Default::default);
+
+/// Gauge reporting [`CERT_EXPIRY`], registered with the first loaded certificate.
+static CERT_EXPIRY_GAUGE: OnceLock<ObservableGauge<i64>> = OnceLock::new();
+
 /// Client TLS configuration builder.
-#[cfg_attr(test, derive(Default))]
 #[derive(Clone, Debug, Deserialize)]
 pub struct TlsConfigBuilder {
-    pub cert_path: PathBuf,
-    pub key_path: Option<PathBuf>,
-    pub ca_cert_path: Option<PathBuf>,
+    pub cert: Option<SecretSource>,
+    pub key: Option<SecretSource>,
+    pub key_passphrase: Option<SecretSource>,
+    pub ca_cert: Option<SecretSource>,
     pub insecure: Option<bool>,
 }
 
@@ -123,15 +160,17 @@ impl Clone for ResolvedTlsConfig<'_> {
 
 impl TlsConfigBuilder {
     pub fn from_parts(
-        cert_path: PathBuf,
-        key_path: Option<PathBuf>,
-        ca_cert_path: Option<PathBuf>,
+        cert: Option<SecretSource>,
+        key: Option<SecretSource>,
+        key_passphrase: Option<SecretSource>,
+        ca_cert: Option<SecretSource>,
         insecure: Option<bool>,
     ) -> Self {
         Self {
-            cert_path,
-            key_path,
-            ca_cert_path,
+            cert,
+            key,
+            key_passphrase,
+            ca_cert,
             insecure,
         }
     }
@@ -140,37 +179,22 @@ impl TlsConfigBuilder {
         use Error::*;
 
         // Certs
-        let file = File::open(self.cert_path).map_err(FailedReadCerts)?;
-        let mut buf = io::BufReader::new(file);
-        let cert = rustls_pemfile::certs(&mut buf)
-            .collect::<Result<Vec<_>, io::Error>>()
-            .map_err(FailedReadCerts)?;
+        let cert = match self.cert {
+            Some(cert) => parse_certs(&cert.load().await?).map_err(FailedReadCerts)?,
+            None => Vec::new(),
+        };
 
         // Private key
-        let key = match self.key_path {
-            Some(path) => {
-                let file = File::open(path).map_err(FailedReadKey)?;
-                let mut buf = io::BufReader::new(file);
-                Some(
-                    rustls_pemfile::private_key(&mut buf)
-                        .map_err(FailedReadKey)?
-                        .ok_or(MissingTlsKey)?,
-                )
-            }
+        let key = match self.key {
+            Some(key) => Some(parse_key(
+                &load_key(&key, self.key_passphrase.as_ref()).await?,
+            )?),
             None => None,
         };
 
         // CA certs
-        let ca_cert = match self.ca_cert_path {
-            Some(path) => {
-                let file = File::open(path).map_err(FailedReadCaCerts)?;
-                let mut buf = io::BufReader::new(file);
-                Some(
-                    rustls_pemfile::certs(&mut buf)
-                        .collect::<Result<Vec<_>, _>>()
-                        .map_err(FailedReadCaCerts)?,
-                )
-            }
+        let ca_cert = match self.ca_cert {
+            Some(ca_cert) => Some(parse_certs(&ca_cert.load().await?).map_err(FailedReadCaCerts)?),
             None => None,
         };
 
@@ -186,6 +210,157 @@ impl TlsConfigBuilder {
     }
 }
 
+fn parse_certs(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>, io::Error> {
+    rustls_pemfile::certs(&mut io::BufReader::new(pem)).collect()
+}
+
+fn parse_key(pem: &[u8]) -> Result<PrivateKeyDer<'static>, Error> {
+    rustls_pemfile::private_key(&mut io::BufReader::new(pem))
+        .map_err(Error::FailedReadKey)?
+        .ok_or(Error::MissingTlsKey)
+}
+
+/// Loads the PEM of a private key, decrypted with `passphrase` if encrypted.
+pub async fn load_key(
+    key: &SecretSource,
+    passphrase: Option<&SecretSource>,
+) -> Result<Vec<u8>, Error> {
+    let pem = key.load().await?;
+    let passphrase = match passphrase {
+        Some(passphrase) => Some(passphrase.load().await?),
+        None => None,
+    };
+    decrypt_key(&pem, passphrase.as_deref())
+}
+
+/// Decrypts an encrypted PKCS#8 private key, returning the PEM of the decrypted key.
+/// Keys that are not encrypted are returned as-is.
+fn decrypt_key(pem: &[u8], passphrase: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+    let begin = format!("-----BEGIN {ENCRYPTED_PRIVATE_KEY_LABEL}-----");
+    let end = format!("-----END {ENCRYPTED_PRIVATE_KEY_LABEL}-----");
+    let text = String::from_utf8_lossy(pem);
+    let encrypted_pem = match (text.find(&begin), text.find(&end)) {
+        (Some(start), Some(end_start)) if start < end_start => &text[start..end_start + end.len()],
+        _ => return Ok(pem.to_vec()),
+    };
+    let passphrase = passphrase.ok_or(Error::MissingKeyPassphrase)?;
+    let decrypt_error = |error: pkcs8::Error| Error::FailedDecryptKey(error.to_string());
+    let (_label, der) = pkcs8::der::pem::decode_vec(encrypted_pem.as_bytes())
+        .map_err(|error| decrypt_error(error.into()))?;
+    let key_info = EncryptedPrivateKeyInfo::try_from(der.as_slice()).map_err(decrypt_error)?;
+    // Passphrases read from files commonly end with a newline
+    let key = key_info
+        .decrypt(passphrase.trim_ascii_end())
+        .map_err(decrypt_error)?;
+    let pem = key
+        .to_pem("PRIVATE KEY", LineEnding::LF)
+        .map_err(|error| decrypt_error(error.into()))?;
+    Ok(pem.as_bytes().to_vec())
+}
+
+/// Client certificate resolver serving the latest certificate and key of rotated secrets.
+/// Watching the secrets stops once the resolver is dropped.
+#[derive(Debug)]
+struct RotatingClientCert {
+    certified_key: RwLock<Arc<CertifiedKey>>,
+    watch: OnceLock<AbortHandle>,
+}
+
+impl RotatingClientCert {
+    fn new(certified_key: CertifiedKey) -> Self {
+        Self {
+            certified_key: RwLock::new(Arc::new(certified_key)),
+            watch: OnceLock::new(),
+        }
+    }
+
+    /// Builds the certified key of `cert` and `key`, failing if the key is not the key of
+    /// the certificate, e.g. while only one of them is rotated.
+    fn certified_key(
+        cert: &[u8],
+        key: &[u8],
+        passphrase: Option<&[u8]>,
+    ) -> Result<CertifiedKey, Error> {
+        let cert = parse_certs(cert).map_err(Error::FailedReadCerts)?;
+        let key = any_supported_type(&parse_key(&decrypt_key(key, passphrase)?)?)?;
+        let certified_key = CertifiedKey::new(cert, key);
+        match certified_key.keys_match() {
+            Err(error @ rustls::Error::InconsistentKeys(InconsistentKeys::KeyMismatch)) => {
+                Err(error.into())
+            }
+            _ => Ok(certified_key),
+        }
+    }
+
+    /// Reloads the certificate and key at `interval`, until the resolver is dropped.
+    fn watch(
+        self: &Arc<Self>,
+        cert: SecretSource,
+        key: SecretSource,
+        passphrase: Option<SecretSource>,
+        interval: Duration,
+    ) {
+        let mut sources = vec![cert.clone(), key.clone()];
+        sources.extend(passphrase.clone());
+        let resolver = Arc::downgrade(self);
+        let watch = secrets::watch(sources, interval, move || {
+            if let Some(resolver) = resolver.upgrade() {
+                resolver.reload(&cert, &key, passphrase.as_ref())
+            }
+        });
+        let _ = self.watch.set(watch);
+    }
+
+    /// Reloads the certificate and key from the secrets cache. Pairs of a certificate and
+    /// a key that don't match are logged, keeping the previous pair.
+    fn reload(
+        &self,
+        cert_source: &SecretSource,
+        key: &SecretSource,
+        passphrase: Option<&SecretSource>,
+    ) {
+        let (Some(cert), Some(key)) = (cert_source.cached(), key.cached()) else {
+            return;
+        };
+        let passphrase = match passphrase {
+            Some(passphrase) => match passphrase.cached() {
+                Some(passphrase) => Some(passphrase),
+                None => return,
+            },
+            None => None,
+        };
+        match Self::certified_key(&cert, &key, passphrase.as_deref()) {
+            Ok(certified_key) => {
+                record_expiry(&cert_source.to_string(), &certified_key.cert);
+                *self.certified_key.write().unwrap() = Arc::new(certified_key)
+            }
+            Err(error) => warn!(%error, "rotated client certificate is invalid, keeping previous"),
+        }
+    }
+}
+
+impl Drop for RotatingClientCert {
+    fn drop(&mut self) {
+        if let Some(watch) = self.watch.get() {
+            watch.abort();
+        }
+    }
+}
+
+impl ResolvesClientCert for RotatingClientCert {
+    fn resolve(
+        &self,
+        _root_hint_subjects: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        Some(self.certified_key.read().unwrap().clone())
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
 /// Builds and insecure TLS client config when no `TlsConfig` is provided (assumes no client auth).
 pub fn build_insecure_client_config() -> ClientConfig {
     let mut config = ClientConfig::builder()
@@ -200,11 +375,16 @@ pub fn build_insecure_client_config() -> ClientConfig {
 
 /// Builds a TLS client config based on the provided `TlsConfig`.
 pub async fn build_client_config(tls_config: &TlsConfig) -> Result<ClientConfig, Error> {
+    let refresh_interval = tls_config.refresh_interval.map(Duration::from_secs);
+    let ca_only = tls_config.ca_only.unwrap_or(false);
+    let sources = (tls_config.cert.clone(), tls_config.key.clone());
+    let passphrase_source = tls_config.key_passphrase.clone();
     // Resolve the TLS config
     let tls_config = TlsConfigBuilder::from_parts(
-        tls_config.cert_path.clone().unwrap(),
-        tls_config.key_path.clone(),
-        tls_config.client_ca_cert_path.clone(),
+        tls_config.cert.clone(),
+        tls_config.key.clone(),
+        tls_config.key_passphrase.clone(),
+        tls_config.client_ca_cert.clone(),
         tls_config.insecure,
     )
     .build()
@@ -219,14 +399,25 @@ pub async fn build_client_config(tls_config: &TlsConfig) -> Result<ClientConfig,
             });
             ClientConfig::builder().with_root_certificates(root)
         }
+        _ if ca_only => return Err(Error::MissingCaCerts),
         _ => ClientConfig::builder()
             .with_native_roots()
             .unwrap_or(ClientConfig::builder().with_webpki_roots()),
     };
 
     // Add certs and private key, if any
-    let mut client_config = match &tls_config.key {
-        Some(key) if !&tls_config.cert.is_empty() => {
+    let mut client_config = match (&tls_config.key, sources, refresh_interval) {
+        (Some(key), (Some(cert_source), Some(key_source)), Some(refresh_interval))
+            if !tls_config.cert.is_empty() =>
+        {
+            // Serve rotated certificates to new connections
+            let certified_key =
+                CertifiedKey::new(tls_config.cert.clone(), any_supported_type(key)?);
+            let resolver = Arc::new(RotatingClientCert::new(certified_key));
+            resolver.watch(cert_source, key_source, passphrase_source, refresh_interval);
+            client_config_builder.with_client_cert_resolver(resolver)
+        }
+        (Some(key), ..) if !&tls_config.cert.is_empty() => {
             client_config_builder.with_client_auth_cert(tls_config.cert.clone(), key.clone_key())?
         }
         _ => client_config_builder.with_no_client_auth(),
@@ -241,3 +432,335 @@ pub async fn build_client_config(tls_config: &TlsConfig) -> Result<ClientConfig,
 
     Ok(client_config)
 }
+
+/// Problems found validating a client TLS config.
+#[derive(Debug, Default)]
+pub struct TlsConfigReport {
+    /// Invalid combinations of settings and unreadable secrets
+    pub errors: Vec<String>,
+    /// Expired certificates and certificates expiring soon
+    pub warnings: Vec<String>,
+}
+
+/// Validates `tls_config`, loading its secrets and parsing its certificates and key.
+/// Certificates expiring within `expiry_warning_period` are reported as warnings.
+pub async fn validate_client_config(
+    tls_config: &TlsConfig,
+    expiry_warning_period: Duration,
+) -> TlsConfigReport {
+    let mut report = TlsConfigReport::default();
+    match (&tls_config.cert, &tls_config.key) {
+        (Some(_), None) => report.errors.push("`cert` is set without `key`".into()),
+        (None, Some(_)) => report.errors.push("`key` is set without `cert`".into()),
+        _ => (),
+    }
+    if tls_config.key_passphrase.is_some() && tls_config.key.is_none() {
+        report
+            .errors
+            .push("`key_passphrase` is set without `key`".into());
+    }
+    if tls_config.insecure == Some(true) && tls_config.client_ca_cert.is_some() {
+        report.errors.push(
+            "`client_ca_cert` is set with `insecure`, which disables server certificate verification"
+                .into(),
+        );
+    }
+    if tls_config.ca_only == Some(true) && tls_config.client_ca_cert.is_none() {
+        report
+            .errors
+            .push("`ca_only` is set without `client_ca_cert`".into());
+    }
+    let certs = [&tls_config.cert, &tls_config.client_ca_cert];
+    for source in certs.into_iter().flatten() {
+        match source.load().await {
+            Ok(pem) => match parse_certs(&pem) {
+                Ok(certs) if certs.is_empty() => report
+                    .errors
+                    .push(format!("{source} contains no certificates")),
+                Ok(certs) => report.warnings.extend(observe_certs(
+                    &source.to_string(),
+                    &certs,
+                    expiry_warning_period,
+                )),
+                Err(error) => report
+                    .errors
+                    .push(format!("{source} contains invalid certificates: {error}")),
+            },
+            Err(error) => report.errors.push(error.to_string()),
+        }
+    }
+    if let Some(source) = &tls_config.key {
+        match load_key(source, tls_config.key_passphrase.as_ref()).await {
+            Ok(pem) => {
+                if let Err(error) = parse_key(&pem) {
+                    report.errors.push(format!("{source}: {error}"));
+                }
+            }
+            Err(Error::FailedLoadSecret(error)) => report.errors.push(error.to_string()),
+            Err(error) => report.errors.push(format!("{source}: {error}")),
+        }
+    }
+    report
+}
+
+/// Records the expiry of `certs` loaded from `source` in the `tls_certificate_expiry` gauge,
+/// returning warnings for certificates that expired or expire within `warning_period`.
+pub fn observe_certs(
+    source: &str,
+    certs: &[CertificateDer<'_>],
+    warning_period: Duration,
+) -> Vec<String> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let warning_period = warning_period.as_secs() as i64;
+    record_expiry(source, certs)
+        .into_iter()
+        .filter_map(|(subject, not_after)| {
+            let expiry = not_after.timestamp();
+            if expiry <= now {
+                Some(format!(
+                    "certificate `{subject}` in {source} expired on {not_after}"
+                ))
+            } else if expiry - now <= warning_period {
+                Some(format!(
+                    "certificate `{subject}` in {source} expires on {not_after}"
+                ))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Records the expiry of `certs` loaded from `source` in the `tls_certificate_expiry` gauge,
+/// returning the subject and expiry of each certificate.
+fn record_expiry(source: &str, certs: &[CertificateDer<'_>]) -> Vec<(String, ASN1Time)> {
+    CERT_EXPIRY_GAUGE.get_or_init(|| {
+        global::meter("fms-guardrails-orchestr8")
+            .i64_observable_gauge("tls_certificate_expiry")
+            .with_description("Expiry (notAfter) of loaded TLS certificates")
+            .with_unit("s")
+            .with_callback(|observer| {
+                for ((source, subject), expiry) in CERT_EXPIRY.lock().unwrap().iter() {
+                    observer.observe(
+                        *expiry,
+                        &[
+                            KeyValue::new("source", source.clone()),
+                            KeyValue::new("subject", subject.clone()),
+                        ],
+                    );
+                }
+            })
+            .build()
+    });
+    let observed = certs
+        .iter()
+        .filter_map(|cert| x509_parser::parse_x509_certificate(cert).ok())
+        .map(|(_, cert)| (cert.subject().to_string(), cert.validity().not_after))
+        .collect::<Vec<_>>();
+    let mut cert_expiry = CERT_EXPIRY.lock().unwrap();
+    for (subject, not_after) in &observed {
+        cert_expiry.insert((source.to_string(), subject.clone()), not_after.timestamp());
+    }
+    observed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_validate_client_config() {
+        let resources: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "resources"]
+            .iter()
+            .collect();
+        let cert = SecretSource::Path(resources.join("localhost.crt"));
+        let key = SecretSource::Path(resources.join("localhost.key"));
+
+        let report = validate_client_config(
+            &TlsConfig {
+                cert: Some(cert.clone()),
+                key: Some(key.clone()),
+                ..Default::default()
+            },
+            Duration::ZERO,
+        )
+        .await;
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+
+        // Certificates expiring within the warning period are reported
+        let report = validate_client_config(
+            &TlsConfig {
+                cert: Some(cert.clone()),
+                key: Some(key.clone()),
+                ..Default::default()
+            },
+            Duration::from_secs(100 * 365 * 24 * 60 * 60),
+        )
+        .await;
+        assert_eq!(
+            report.warnings,
+            [format!(
+                "certificate `CN=localhost` in {cert} expires on Apr 27 19:02:05 2035 +00:00"
+            )]
+        );
+
+        let report = validate_client_config(
+            &TlsConfig {
+                cert: Some(cert.clone()),
+                client_ca_cert: Some(SecretSource::Path(resources.join("missing.crt"))),
+                insecure: Some(true),
+                ..Default::default()
+            },
+            Duration::ZERO,
+        )
+        .await;
+        assert_eq!(report.errors.len(), 3, "{:?}", report.errors);
+        assert_eq!(report.errors[0], "`cert` is set without `key`");
+
+        // Trusting only the CA requires a CA
+        let report = validate_client_config(
+            &TlsConfig {
+                ca_only: Some(true),
+                ..Default::default()
+            },
+            Duration::ZERO,
+        )
+        .await;
+        assert_eq!(report.errors, ["`ca_only` is set without `client_ca_cert`"]);
+
+        // Encrypted keys are decrypted with their passphrase
+        let encrypted_key = SecretSource::Path(resources.join("localhost.encrypted.key"));
+        let report = validate_client_config(
+            &TlsConfig {
+                cert: Some(cert.clone()),
+                key: Some(encrypted_key.clone()),
+                ..Default::default()
+            },
+            Duration::ZERO,
+        )
+        .await;
+        assert_eq!(
+            report.errors,
+            [format!(
+                "{encrypted_key}: TLS private key is encrypted, but no `key_passphrase` is set"
+            )]
+        );
+        let decrypted_pem = decrypt_key(
+            &encrypted_key.load().await.unwrap(),
+            Some(b"orchestrator\n"),
+        )
+        .unwrap();
+        assert_eq!(
+            parse_key(&decrypted_pem).unwrap(),
+            parse_key(&key.load().await.unwrap()).unwrap()
+        );
+        assert!(matches!(
+            decrypt_key(&encrypted_key.load().await.unwrap(), Some(b"wrong")),
+            Err(Error::FailedDecryptKey(_))
+        ));
+
+        // Keys are not certificates
+        let report = validate_client_config(
+            &TlsConfig {
+                cert: Some(key.clone()),
+                key: Some(key),
+                ..Default::default()
+            },
+            Duration::ZERO,
+        )
+        .await;
+        assert_eq!(
+            report.errors,
+            [format!(
+                "{} contains no certificates",
+                SecretSource::Path(resources.join("localhost.key"))
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rotating_client_cert() {
+        let resources: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "resources"]
+            .iter()
+            .collect();
+        let cert_pem = std::fs::read(resources.join("localhost.crt")).unwrap();
+        let key_pem = std::fs::read(resources.join("localhost.key")).unwrap();
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().simple().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("client.crt"), &cert_pem).unwrap();
+        std::fs::write(dir.join("client.key"), &key_pem).unwrap();
+        let cert = SecretSource::File(dir.join("client.crt"));
+        let key = SecretSource::File(dir.join("client.key"));
+        let certified_key = RotatingClientCert::certified_key(
+            &cert.load().await.unwrap(),
+            &key.load().await.unwrap(),
+            None,
+        )
+        .unwrap();
+        let resolver = RotatingClientCert::new(certified_key);
+        let resolve = || resolver.resolve(&[], &[]).unwrap();
+        assert_eq!(resolve().cert.len(), 1);
+
+        // Rotated certificates are served to new connections
+        let chain = [cert_pem.as_slice(), cert_pem.as_slice()].concat();
+        std::fs::write(dir.join("client.crt"), chain).unwrap();
+        assert!(cert.refresh().await.unwrap());
+        resolver.reload(&cert, &key, None);
+        assert_eq!(resolve().cert.len(), 2);
+
+        // Keys not matching the certificate are not served
+        let pkcs8 = ring::signature::EcdsaKeyPair::generate_pkcs8(
+            &ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING,
+            &ring::rand::SystemRandom::new(),
+        )
+        .unwrap();
+        let other_key =
+            pkcs8::der::pem::encode_string("PRIVATE KEY", LineEnding::LF, pkcs8.as_ref()).unwrap();
+        std::fs::write(dir.join("client.key"), other_key).unwrap();
+        std::fs::write(dir.join("client.crt"), &cert_pem).unwrap();
+        assert!(key.refresh().await.unwrap());
+        assert!(cert.refresh().await.unwrap());
+        assert!(matches!(
+            RotatingClientCert::certified_key(
+                &cert.cached().unwrap(),
+                &key.cached().unwrap(),
+                None
+            ),
+            Err(Error::RustlsError(rustls::Error::InconsistentKeys(
+                InconsistentKeys::KeyMismatch
+            )))
+        ));
+        resolver.reload(&cert, &key, None);
+        assert_eq!(resolve().cert.len(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rotating_client_cert_stops_watching() {
+        let resources: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "resources"]
+            .iter()
+            .collect();
+        let cert = SecretSource::Path(resources.join("localhost.crt"));
+        let key = SecretSource::Path(resources.join("localhost.key"));
+        let certified_key = RotatingClientCert::certified_key(
+            &cert.load().await.unwrap(),
+            &key.load().await.unwrap(),
+            None,
+        )
+        .unwrap();
+        let resolver = Arc::new(RotatingClientCert::new(certified_key));
+        resolver.watch(cert, key, None, Duration::from_secs(60));
+        let watch = resolver.watch.get().unwrap().clone();
+        assert!(!watch.is_finished());
+        drop(resolver);
+        while !watch.is_finished() {
+            tokio::task::yield_now().await;
+        }
+    }
+}
@@ -15,29 +15,41 @@
 
 */
 
-use std::time::Duration;
+use std::{collections::HashMap, sync::OnceLock, time::Duration};
 
 use axum::{extract::Request, http::HeaderMap, response::Response};
 use opentelemetry::{
-    KeyValue, global,
+    Context, KeyValue,
+    baggage::{BaggageExt, KeyValueMetadata},
+    global,
+    propagation::{
+        Extractor, Injector, TextMapCompositePropagator, TextMapPropagator,
+        text_map_propagator::FieldIter,
+    },
     trace::{TraceContextExt, TraceError, TraceId, TracerProvider},
 };
+use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
 use opentelemetry_http::{HeaderExtractor, HeaderInjector};
-use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig, WithHttpConfig};
+use opentelemetry_otlp::{
+    LogExporter, MetricExporter, SpanExporter, WithExportConfig, WithHttpConfig, WithTonicConfig,
+};
 use opentelemetry_sdk::{
     Resource,
+    logs::{LogError, LoggerProvider},
     metrics::{MetricError, PeriodicReader, SdkMeterProvider},
-    propagation::TraceContextPropagator,
+    propagation::{BaggagePropagator, TraceContextPropagator},
     runtime,
     trace::Sampler,
 };
 use tracing::{Span, error, info, info_span};
 use tracing_opentelemetry::{MetricsLayer, OpenTelemetrySpanExt};
-use tracing_subscriber::{EnvFilter, Layer, layer::SubscriberExt};
+use tracing_subscriber::{EnvFilter, Layer, filter::filter_fn, layer::SubscriberExt};
 
 use crate::{
-    args::{LogFormat, OtlpProtocol, TracingConfig},
+    args::{LogFormat, OtlpTlsConfig, TracingConfig},
     clients::http::TracedResponse,
+    config::OtlpProtocol,
+    utils::exemplars,
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -46,6 +58,10 @@ pub enum TracingError {
     TraceError(#[from] TraceError),
     #[error("Error from metrics provider: {0}")]
     MetricError(#[from] MetricError),
+    #[error("Error from logger provider: {0}")]
+    LogError(#[from] LogError),
+    #[error("Invalid OTLP export config: {0}")]
+    ExportConfig(String),
 }
 
 fn resource(tracing_config: TracingConfig) -> Resource {
@@ -55,6 +71,93 @@ fn resource(tracing_config: TracingConfig) -> Resource {
     )])
 }
 
+fn read_pem(path: &std::path::Path) -> Result<Vec<u8>, TracingError> {
+    std::fs::read(path)
+        .map_err(|e| TracingError::ExportConfig(format!("failed to read {}: {e}", path.display())))
+}
+
+/// Configures a gRPC OTLP exporter builder with the export headers and TLS config.
+fn with_tonic_config<B: WithExportConfig + WithTonicConfig>(
+    builder: B,
+    endpoint: String,
+    timeout: Duration,
+    tracing_config: &TracingConfig,
+) -> Result<B, TracingError> {
+    let mut headers = http::HeaderMap::new();
+    for (key, value) in &tracing_config.headers {
+        let (Ok(key), Ok(value)) = (
+            http::HeaderName::try_from(key),
+            http::HeaderValue::try_from(value),
+        ) else {
+            return Err(TracingError::ExportConfig(format!(
+                "invalid OTLP header `{key}`"
+            )));
+        };
+        headers.insert(key, value);
+    }
+    let builder = builder
+        .with_endpoint(endpoint)
+        .with_timeout(timeout)
+        .with_metadata(tonic::metadata::MetadataMap::from_headers(headers));
+    match &tracing_config.tls {
+        Some(tls) => Ok(builder.with_tls_config(tonic_tls_config(tls)?)),
+        None => Ok(builder),
+    }
+}
+
+fn tonic_tls_config(
+    tls: &OtlpTlsConfig,
+) -> Result<tonic::transport::ClientTlsConfig, TracingError> {
+    let mut tls_config = tonic::transport::ClientTlsConfig::new().with_enabled_roots();
+    if let Some(ca_cert_path) = &tls.ca_cert_path {
+        tls_config = tls_config.ca_certificate(tonic::transport::Certificate::from_pem(read_pem(
+            ca_cert_path,
+        )?));
+    }
+    if let (Some(cert_path), Some(key_path)) = (&tls.client_cert_path, &tls.client_key_path) {
+        tls_config = tls_config.identity(tonic::transport::Identity::from_pem(
+            read_pem(cert_path)?,
+            read_pem(key_path)?,
+        ));
+    }
+    Ok(tls_config)
+}
+
+/// Configures an HTTP OTLP exporter builder with the export headers and TLS config.
+fn with_http_config<B: WithExportConfig + WithHttpConfig>(
+    builder: B,
+    endpoint: String,
+    timeout: Duration,
+    tracing_config: &TracingConfig,
+) -> Result<B, TracingError> {
+    Ok(builder
+        .with_http_client(http_client(tracing_config.tls.as_ref())?)
+        .with_headers(tracing_config.headers.clone())
+        .with_endpoint(endpoint)
+        .with_timeout(timeout))
+}
+
+fn http_client(tls: Option<&OtlpTlsConfig>) -> Result<reqwest::Client, TracingError> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(tls) = tls {
+        if let Some(ca_cert_path) = &tls.ca_cert_path {
+            let cert = reqwest::Certificate::from_pem(&read_pem(ca_cert_path)?)
+                .map_err(|e| TracingError::ExportConfig(e.to_string()))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        if let (Some(cert_path), Some(key_path)) = (&tls.client_cert_path, &tls.client_key_path) {
+            let mut pem = read_pem(cert_path)?;
+            pem.extend(read_pem(key_path)?);
+            let identity = reqwest::Identity::from_pem(&pem)
+                .map_err(|e| TracingError::ExportConfig(e.to_string()))?;
+            builder = builder.identity(identity);
+        }
+    }
+    builder
+        .build()
+        .map_err(|e| TracingError::ExportConfig(e.to_string()))
+}
+
 /// Initializes an OpenTelemetry tracer provider with an OTLP export pipeline based on the
 /// provided config.
 fn init_tracer_provider(
@@ -63,17 +166,20 @@ fn init_tracer_provider(
     if let Some((protocol, endpoint)) = tracing_config.clone().traces {
         let timeout = Duration::from_secs(3);
         let exporter = match protocol {
-            OtlpProtocol::Grpc => SpanExporter::builder()
-                .with_tonic()
-                .with_endpoint(endpoint)
-                .with_timeout(timeout)
-                .build()?,
-            OtlpProtocol::Http => SpanExporter::builder()
-                .with_http()
-                .with_http_client(reqwest::Client::new())
-                .with_endpoint(endpoint)
-                .with_timeout(timeout)
-                .build()?,
+            OtlpProtocol::Grpc => with_tonic_config(
+                SpanExporter::builder().with_tonic(),
+                endpoint,
+                timeout,
+                &tracing_config,
+            )?
+            .build()?,
+            OtlpProtocol::Http => with_http_config(
+                SpanExporter::builder().with_http(),
+                endpoint,
+                timeout,
+                &tracing_config,
+            )?
+            .build()?,
         };
         Ok(Some(
             opentelemetry_sdk::trace::TracerProvider::builder()
@@ -96,35 +202,88 @@ fn init_tracer_provider(
     }
 }
 
-/// Initializes an OpenTelemetry meter provider with an OTLP export pipeline based on the
-/// provided config.
+/// Registry of metrics served on the `/metrics` endpoint, set if Prometheus metrics are enabled.
+static PROMETHEUS_REGISTRY: OnceLock<prometheus::Registry> = OnceLock::new();
+
+/// Returns the registry of metrics served on the `/metrics` endpoint, if Prometheus metrics
+/// are enabled.
+pub fn prometheus_registry() -> Option<&'static prometheus::Registry> {
+    PROMETHEUS_REGISTRY.get()
+}
+
+/// Initializes an OpenTelemetry meter provider with an OTLP export pipeline and a Prometheus
+/// exporter based on the provided config.
 fn init_meter_provider(
     tracing_config: TracingConfig,
 ) -> Result<Option<SdkMeterProvider>, TracingError> {
+    if tracing_config.metrics.is_none() && !tracing_config.prometheus_metrics {
+        return Ok(None);
+    }
+    let mut builder = SdkMeterProvider::builder().with_resource(resource(tracing_config.clone()));
     if let Some((protocol, endpoint)) = tracing_config.clone().metrics {
         // Note: DefaultAggregationSelector removed from OpenTelemetry SDK as of 0.26.0
         // as custom aggregation should be available in Views. Cumulative temporality is default.
         let timeout = Duration::from_secs(10);
         let exporter = match protocol {
-            OtlpProtocol::Grpc => MetricExporter::builder()
-                .with_tonic()
-                .with_endpoint(endpoint)
-                .with_timeout(timeout)
-                .build()?,
-            OtlpProtocol::Http => MetricExporter::builder()
-                .with_http()
-                .with_http_client(reqwest::Client::new())
-                .with_endpoint(endpoint)
-                .with_timeout(timeout)
-                .build()?,
+            OtlpProtocol::Grpc => with_tonic_config(
+                MetricExporter::builder().with_tonic(),
+                endpoint,
+                timeout,
+                &tracing_config,
+            )?
+            .build()?,
+            OtlpProtocol::Http => with_http_config(
+                MetricExporter::builder().with_http(),
+                endpoint,
+                timeout,
+                &tracing_config,
+            )?
+            .build()?,
         };
         let reader = PeriodicReader::builder(exporter, runtime::Tokio)
             .with_interval(Duration::from_secs(3))
             .build();
+        builder = builder.with_reader(reader);
+    }
+    if tracing_config.prometheus_metrics {
+        let registry = prometheus::Registry::new();
+        let exporter = opentelemetry_prometheus::exporter()
+            .with_registry(registry.clone())
+            .build()?;
+        builder = builder.with_reader(exporter);
+        exemplars::register(&registry).map_err(|e| MetricError::Other(e.to_string()))?;
+        let _ = PROMETHEUS_REGISTRY.set(registry);
+    }
+    Ok(Some(builder.build()))
+}
+
+/// Initializes an OpenTelemetry logger provider with an OTLP export pipeline based on the
+/// provided config.
+fn init_logger_provider(
+    tracing_config: TracingConfig,
+) -> Result<Option<LoggerProvider>, TracingError> {
+    if let Some((protocol, endpoint)) = tracing_config.clone().logs {
+        let timeout = Duration::from_secs(3);
+        let exporter = match protocol {
+            OtlpProtocol::Grpc => with_tonic_config(
+                LogExporter::builder().with_tonic(),
+                endpoint,
+                timeout,
+                &tracing_config,
+            )?
+            .build()?,
+            OtlpProtocol::Http => with_http_config(
+                LogExporter::builder().with_http(),
+                endpoint,
+                timeout,
+                &tracing_config,
+            )?
+            .build()?,
+        };
         Ok(Some(
-            SdkMeterProvider::builder()
+            LoggerProvider::builder()
+                .with_batch_exporter(exporter, runtime::Tokio)
                 .with_resource(resource(tracing_config))
-                .with_reader(reader)
                 .build(),
         ))
     } else {
@@ -132,13 +291,65 @@ fn init_meter_provider(
     }
 }
 
+/// Maximum size of baggage propagated from incoming requests, in bytes.
+const MAX_BAGGAGE_SIZE: usize = 1024;
+
+/// W3C baggage propagator extracting allowed keys only, up to [`MAX_BAGGAGE_SIZE`] bytes,
+/// so that arbitrary client baggage is not forwarded to downstream services.
+#[derive(Debug)]
+struct AllowedBaggagePropagator {
+    inner: BaggagePropagator,
+    keys: Vec<String>,
+}
+
+impl AllowedBaggagePropagator {
+    fn new(keys: Vec<String>) -> Self {
+        Self {
+            inner: BaggagePropagator::new(),
+            keys,
+        }
+    }
+}
+
+impl TextMapPropagator for AllowedBaggagePropagator {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        self.inner.inject_context(cx, injector)
+    }
+
+    fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+        let extracted = self.inner.extract_with_context(cx, extractor);
+        let mut size = 0;
+        let baggage = self
+            .keys
+            .iter()
+            .filter_map(|key| {
+                let (value, metadata) = extracted.baggage().get_with_metadata(key)?;
+                size += key.len() + value.as_str().len() + metadata.as_str().len();
+                (size <= MAX_BAGGAGE_SIZE)
+                    .then(|| KeyValueMetadata::new(key.clone(), value.clone(), metadata.clone()))
+            })
+            .collect::<Vec<_>>();
+        cx.with_baggage(baggage)
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        self.inner.fields()
+    }
+}
+
 /// Initializes tracing for the orchestrator using the OpenTelemetry API/SDK and the `tracing`
 /// crate. What telemetry is exported and to where is determined based on the provided config
 pub fn init_tracing(
     tracing_config: TracingConfig,
 ) -> Result<impl FnOnce() -> Result<(), TracingError>, TracingError> {
     let mut layers = Vec::new();
-    global::set_text_map_propagator(TraceContextPropagator::new());
+    // Propagates W3C trace context and allowed baggage from incoming requests to downstream requests
+    global::set_text_map_propagator(TextMapCompositePropagator::new(vec![
+        Box::new(TraceContextPropagator::new()),
+        Box::new(AllowedBaggagePropagator::new(
+            tracing_config.baggage.clone(),
+        )),
+    ]));
 
     // TODO: Find a better way to only propagate errors from other crates
     let filter = EnvFilter::try_from_default_env()
@@ -150,7 +361,18 @@ pub fn init_tracing(
         .add_directive("trust_dns_proto=error".parse().unwrap())
         .add_directive("tower=error".parse().unwrap())
         .add_directive("tonic=error".parse().unwrap())
-        .add_directive("reqwest=error".parse().unwrap());
+        .add_directive("reqwest=error".parse().unwrap())
+        // Debug events of requests with debug tracing enabled, see `server::admin::debug_trace`
+        .add_directive(
+            "fms_guardrails_orchestr8[request{debug_trace=true}]=debug"
+                .parse()
+                .unwrap(),
+        );
+    // Task instrumentation events required by tokio-console
+    #[cfg(feature = "tokio-console")]
+    let filter = filter
+        .add_directive("tokio=trace".parse().unwrap())
+        .add_directive("runtime=trace".parse().unwrap());
 
     // Set up tracing layer with OTLP exporter
     let trace_provider = init_tracer_provider(tracing_config.clone())?;
@@ -163,16 +385,35 @@ pub fn init_tracing(
         );
     }
 
-    // Set up metrics layer with OTLP exporter
+    // Set up metrics layer with OTLP and Prometheus exporters
     let meter_provider = init_meter_provider(tracing_config.clone())?;
     if let Some(meter_provider) = meter_provider.clone() {
         global::set_meter_provider(meter_provider.clone());
         layers.push(MetricsLayer::new(meter_provider).boxed());
     }
 
+    // Set up logs layer with OTLP exporter
+    let logger_provider = init_logger_provider(tracing_config.clone())?;
+    if let Some(logger_provider) = logger_provider.clone() {
+        layers.push(
+            OpenTelemetryTracingBridge::new(&logger_provider)
+                // Exporter events are not exported, to avoid feedback loops
+                .with_filter(filter_fn(|metadata| {
+                    !["opentelemetry", "tonic", "h2", "hyper", "reqwest"]
+                        .iter()
+                        .any(|target| metadata.target().starts_with(target))
+                }))
+                .boxed(),
+        );
+    }
+
+    // Set up tokio-console layer
+    #[cfg(feature = "tokio-console")]
+    layers.push(console_subscriber::spawn().boxed());
+
     // Set up formatted layer for logging to stdout
     // Because we use the `tracing` crate for logging, all logs are traces and will be exported
-    // to OTLP if `--otlp-export=traces` is set.
+    // to OTLP if `--otlp-export=traces` is set, and as OTLP log records if `--otlp-export=logs` is set.
     if !tracing_config.quiet {
         match tracing_config.log_format {
             LogFormat::Full => layers.push(tracing_subscriber::fmt::layer().boxed()),
@@ -208,6 +449,16 @@ pub fn init_tracing(
         info!("OTLP metrics export disabled")
     }
 
+    if tracing_config.prometheus_metrics {
+        info!("Prometheus metrics enabled: Serving on /metrics of the health server");
+    }
+
+    if let Some(logs) = tracing_config.logs {
+        info!("OTLP logs enabled: Exporting {} to {}", logs.0, logs.1);
+    } else {
+        info!("OTLP logs export disabled")
+    }
+
     if !tracing_config.quiet {
         info!(
             "Stdout logging enabled with format {}",
@@ -224,12 +475,17 @@ pub fn init_tracing(
                 .shutdown()
                 .map_err(TracingError::MetricError)?;
         }
+        if let Some(logger_provider) = logger_provider {
+            logger_provider.shutdown().map_err(TracingError::LogError)?;
+        }
         Ok(())
     })
 }
 
+/// Creates the span of an incoming request, continuing the trace of its `traceparent`
+/// header with the allowed baggage of its `baggage` header, if set.
 pub fn incoming_request_span(request: &Request) -> Span {
-    info_span!(
+    let span = info_span!(
         "request",
         request_method = request.method().to_string(),
         request_path = request.uri().path().to_string(),
@@ -239,7 +495,16 @@ pub fn incoming_request_span(request: &Request) -> Span {
         stream_response_event_count = tracing::field::Empty,
         stream_response_error_count = tracing::field::Empty,
         stream_response_duration_ms = tracing::field::Empty,
-    )
+        debug_trace = tracing::field::Empty,
+        api_consumer = tracing::field::Empty,
+    );
+    let ctx = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    if ctx.span().span_context().is_valid() || !ctx.baggage().is_empty() {
+        span.set_parent(ctx);
+    }
+    span
 }
 
 pub fn on_incoming_request(request: &Request, span: &Span) {
@@ -305,7 +570,8 @@ pub fn on_outgoing_eos(_trailers: Option<&HeaderMap>, stream_duration: Duration,
 
 /// Injects the `traceparent` header into the header map from the current tracing span context.
 /// Also injects empty `tracestate` header by default. This can be used to propagate
-/// vendor-specific trace context.
+/// vendor-specific trace context. The `baggage` header is injected if the context carries
+/// baggage, e.g. of the incoming request.
 /// Used by both gRPC and HTTP requests since `tonic::Metadata` uses `http::HeaderMap`.
 /// See https://www.w3.org/TR/trace-context/#trace-context-http-headers-format.
 pub fn with_traceparent_header(ctx: &opentelemetry::Context, headers: HeaderMap) -> HeaderMap {
@@ -352,3 +618,43 @@ pub fn trace_context_from_grpc_response<T>(span: &Span, response: &tonic::Respon
 pub fn current_trace_id() -> TraceId {
     Span::current().context().span().span_context().trace_id()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowed_baggage_propagator() {
+        let propagator = AllowedBaggagePropagator::new(vec!["tenant".into(), "session".into()]);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "baggage",
+            "tenant=acme,user=alice,session=1234".parse().unwrap(),
+        );
+        let ctx = propagator.extract(&HeaderExtractor(&headers));
+        assert_eq!(
+            ctx.baggage().get("tenant").map(|value| value.to_string()),
+            Some("acme".into())
+        );
+        assert_eq!(
+            ctx.baggage().get("session").map(|value| value.to_string()),
+            Some("1234".into())
+        );
+        assert!(ctx.baggage().get("user").is_none());
+
+        // Baggage exceeding the size cap is dropped
+        let session = "x".repeat(MAX_BAGGAGE_SIZE);
+        headers.insert(
+            "baggage",
+            format!("tenant=acme,session={session}").parse().unwrap(),
+        );
+        let ctx = propagator.extract(&HeaderExtractor(&headers));
+        assert!(ctx.baggage().get("tenant").is_some());
+        assert!(ctx.baggage().get("session").is_none());
+
+        // Only allowed baggage is propagated downstream
+        let mut downstream = HeaderMap::new();
+        propagator.inject_context(&ctx, &mut HeaderInjector(&mut downstream));
+        assert_eq!(downstream.get("baggage").unwrap(), "tenant=acme");
+    }
+}
@@ -35,12 +35,14 @@ use fms_guardrails_orchestr8::{
         detector::{ContentAnalysisRequest, ContentAnalysisResponse},
         openai::{
             ChatCompletion, ChatCompletionChoice, ChatCompletionMessage, ChatDetections, Content,
-            InputDetectionResult, Message, OrchestratorWarning, OutputDetectionResult, Role,
+            InputDetectionResult, Message, MessageField, OrchestratorWarning,
+            OutputDetectionResult, Role, ToolDetectionResult,
         },
     },
     models::{
-        DetectionWarningReason, DetectorParams, Metadata, UNSUITABLE_INPUT_MESSAGE,
-        UNSUITABLE_OUTPUT_MESSAGE,
+        ALL_CHOICES_FLAGGED_MESSAGE, DetectionWarningReason, DetectorParams, Metadata,
+        REMOVED_TOOLS_MESSAGE, UNSUITABLE_INPUT_MESSAGE, UNSUITABLE_OUTPUT_MESSAGE,
+        UNSUITABLE_TOOLS_MESSAGE, invalid_structured_output_message,
     },
     pb::{
         caikit::runtime::chunkers::ChunkerTokenizationTaskRequest,
@@ -89,6 +91,7 @@ async fn no_detections() -> Result<(), anyhow::Error> {
                 content: Some("Hi there!".to_string()),
                 refusal: None,
                 tool_calls: vec![],
+                reasoning_content: None,
             },
             index: 0,
             logprobs: None,
@@ -101,6 +104,7 @@ async fn no_detections() -> Result<(), anyhow::Error> {
                 content: Some("Hello!".to_string()),
                 refusal: None,
                 tool_calls: vec![],
+                reasoning_content: None,
             },
             index: 1,
             logprobs: None,
@@ -214,6 +218,8 @@ async fn input_detections() -> Result<(), anyhow::Error> {
         score: 1.0,
         evidence: None,
         metadata: Metadata::new(),
+        chunk: None,
+        category: None,
     }];
 
     let chat_completions_response = ChatCompletion {
@@ -222,9 +228,11 @@ async fn input_detections() -> Result<(), anyhow::Error> {
         detections: Some(ChatDetections {
             input: vec![InputDetectionResult {
                 message_index: 0,
+                field: MessageField::Content,
+                part_index: None,
                 results: expected_detections.clone(),
             }],
-            output: vec![],
+            ..Default::default()
         }),
         warnings: vec![OrchestratorWarning::new(
             DetectionWarningReason::UnsuitableInput,
@@ -311,6 +319,192 @@ async fn input_detections() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+// Validates that flagged tool definitions reject the request by default, and are removed
+// from the request with the `strip` policy
+#[test(tokio::test)]
+async fn tools_detections() -> Result<(), anyhow::Error> {
+    let detector_name = DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC;
+    let input_text = "What is the weather like today?";
+    let tools = json!([
+        {
+            "type": "function",
+            "function": {
+                "name": "get_weather",
+                "description": "Gets the <weather>",
+            },
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "get_time",
+                "description": "Gets the time",
+            },
+        },
+    ]);
+
+    let messages = vec![Message {
+        content: Some(Content::Text(input_text.to_string())),
+        role: Role::User,
+        ..Default::default()
+    }];
+
+    // Add mocksets
+    let mut detector_mocks = MockSet::new();
+    let mut chat_mocks = MockSet::new();
+
+    // Add detector mocks for each tool definition and the input message
+    let expected_detections = vec![ContentAnalysisResponse {
+        start: 22,
+        end: 29,
+        text: "weather".into(),
+        detection: "has_angle_brackets".into(),
+        detection_type: "angle_brackets".into(),
+        detector_id: Some(detector_name.into()),
+        score: 1.0,
+        evidence: None,
+        metadata: Metadata::new(),
+        chunk: None,
+        category: None,
+    }];
+    for (text, detections) in [
+        (
+            "get_weather\nGets the <weather>",
+            expected_detections.clone(),
+        ),
+        ("get_time\nGets the time", Vec::new()),
+        (input_text, Vec::new()),
+    ] {
+        detector_mocks.mock(|when, then| {
+            when.post()
+                .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+                .json(ContentAnalysisRequest {
+                    contents: vec![text.into()],
+                    detector_params: DetectorParams::new(),
+                });
+            then.json([detections]);
+        });
+    }
+
+    // Add chat completions mock for the request without the flagged tool
+    let expected_choices = vec![ChatCompletionChoice {
+        message: ChatCompletionMessage {
+            role: Role::Assistant,
+            content: Some("It is sunny.".into()),
+            refusal: None,
+            tool_calls: vec![],
+            reasoning_content: None,
+        },
+        index: 0,
+        logprobs: None,
+        finish_reason: "EOS_TOKEN".to_string(),
+        stop_reason: None,
+    }];
+    chat_mocks.mock(|when, then| {
+        when.post().path(CHAT_COMPLETIONS_ENDPOINT).json(json!({
+            "model": MODEL_ID,
+            "messages": messages,
+            "tools": [tools[1].clone()],
+        }));
+        then.json(&ChatCompletion {
+            model: MODEL_ID.into(),
+            choices: expected_choices.clone(),
+            ..Default::default()
+        });
+    });
+
+    // Start orchestrator server and its dependencies
+    let mock_detector_server = MockServer::new(detector_name).with_mocks(detector_mocks);
+    let mock_chat_completions_server = MockServer::new("chat_completions").with_mocks(chat_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .detector_servers([&mock_detector_server])
+        .chat_generation_server(&mock_chat_completions_server)
+        .build()
+        .await?;
+
+    let expected_tool_detections = vec![ToolDetectionResult {
+        tool_index: 0,
+        results: expected_detections,
+    }];
+
+    // Request is rejected with tool detections
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CHAT_COMPLETIONS_DETECTION_ENDPOINT)
+        .json(&json!({
+            "model": MODEL_ID,
+            "detectors": {
+                "input": {
+                    detector_name: {},
+                },
+                "output": {},
+            },
+            "messages": messages,
+            "tools": tools,
+        }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let results = response.json::<ChatCompletion>().await?;
+    debug!("{results:#?}");
+    assert!(results.choices.is_empty());
+    assert_eq!(
+        results.detections,
+        Some(ChatDetections {
+            tools: expected_tool_detections.clone(),
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        results.warnings,
+        vec![OrchestratorWarning::new(
+            DetectionWarningReason::UnsuitableTools,
+            UNSUITABLE_TOOLS_MESSAGE,
+        )]
+    );
+
+    // Flagged tool is removed from the chat completions request
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CHAT_COMPLETIONS_DETECTION_ENDPOINT)
+        .json(&json!({
+            "model": MODEL_ID,
+            "detectors": {
+                "input": {
+                    detector_name: {},
+                },
+                "output": {},
+            },
+            "messages": messages,
+            "tools": tools,
+            "guardrails": {
+                "actions": {
+                    "flagged_tools": "strip",
+                },
+            },
+        }))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let results = response.json::<ChatCompletion>().await?;
+    debug!("{results:#?}");
+    assert_eq!(results.choices, expected_choices);
+    assert_eq!(
+        results.detections,
+        Some(ChatDetections {
+            tools: expected_tool_detections,
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        results.warnings,
+        vec![OrchestratorWarning::new(
+            DetectionWarningReason::UnsuitableTools,
+            REMOVED_TOOLS_MESSAGE,
+        )]
+    );
+
+    Ok(())
+}
+
 // Validates that requests with input detector configured returns propagated errors
 #[test(tokio::test)]
 async fn input_client_error() -> Result<(), anyhow::Error> {
@@ -540,6 +734,8 @@ async fn output_detections() -> Result<(), anyhow::Error> {
         score: 1.0,
         evidence: None,
         metadata: Metadata::new(),
+        chunk: None,
+        category: None,
     }];
 
     // Add chat completion choices response for output detection
@@ -550,6 +746,7 @@ async fn output_detections() -> Result<(), anyhow::Error> {
                 content: Some(input_text.to_string()),
                 refusal: None,
                 tool_calls: vec![],
+                reasoning_content: None,
             },
             index: 0,
             logprobs: None,
@@ -562,6 +759,7 @@ async fn output_detections() -> Result<(), anyhow::Error> {
                 content: Some(output_text.to_string()),
                 refusal: None,
                 tool_calls: vec![],
+                reasoning_content: None,
             },
             index: 1,
             logprobs: None,
@@ -575,11 +773,12 @@ async fn output_detections() -> Result<(), anyhow::Error> {
         model: MODEL_ID.into(),
         choices: expected_choices.clone(),
         detections: Some(ChatDetections {
-            input: vec![],
             output: vec![OutputDetectionResult {
                 choice_index: 1,
+                field: MessageField::Content,
                 results: expected_detections.clone(),
             }],
+            ..Default::default()
         }),
         warnings: vec![OrchestratorWarning::new(
             DetectionWarningReason::UnsuitableOutput,
@@ -694,6 +893,346 @@ async fn output_detections() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+// Validates that the drop policy removes flagged choices, and keeps all choices with a
+// warning when every choice is flagged
+#[test(tokio::test)]
+async fn output_detections_drop_flagged_choices() -> Result<(), anyhow::Error> {
+    let detector_name = DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE;
+    let clean_text = "Sure, I can help you with that.";
+    let flagged_texts = [
+        (
+            "Sure! Let me help you with <something>.",
+            28,
+            37,
+            "something",
+        ),
+        ("Of course, <anything> you need.", 11, 19, "anything"),
+    ];
+
+    // Add mocksets
+    let mut detector_mocks = MockSet::new();
+    let mut chat_mocks = MockSet::new();
+    let mut chunker_mocks = MockSet::new();
+
+    // Add detector and chunker mocks for each choice text
+    let detections = |(_, start, end, text): (&str, usize, usize, &str)| {
+        vec![ContentAnalysisResponse {
+            start,
+            end,
+            text: text.into(),
+            detection: "has_angle_brackets".into(),
+            detection_type: "angle_brackets".into(),
+            detector_id: Some(detector_name.into()),
+            score: 1.0,
+            evidence: None,
+            metadata: Metadata::new(),
+            chunk: None,
+            category: None,
+        }]
+    };
+    let texts = [
+        (clean_text, Vec::new()),
+        (flagged_texts[0].0, detections(flagged_texts[0])),
+        (flagged_texts[1].0, detections(flagged_texts[1])),
+    ];
+    for (text, detections) in &texts {
+        detector_mocks.mock(|when, then| {
+            when.post()
+                .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+                .json(ContentAnalysisRequest {
+                    contents: vec![(*text).into()],
+                    detector_params: DetectorParams::new(),
+                });
+            then.json([detections]);
+        });
+        chunker_mocks.mock(|when, then| {
+            when.path(CHUNKER_UNARY_ENDPOINT)
+                .header(CHUNKER_MODEL_ID_HEADER_NAME, CHUNKER_NAME_SENTENCE)
+                .pb(ChunkerTokenizationTaskRequest {
+                    text: text.to_string(),
+                });
+            then.pb(TokenizationResults {
+                results: vec![Token {
+                    start: 0,
+                    end: text.len() as i64,
+                    text: text.to_string(),
+                }],
+                token_count: 0,
+            });
+        });
+    }
+
+    let choice = |index: u32, text: &str| ChatCompletionChoice {
+        message: ChatCompletionMessage {
+            role: Role::Assistant,
+            content: Some(text.to_string()),
+            refusal: None,
+            tool_calls: vec![],
+            reasoning_content: None,
+        },
+        index,
+        logprobs: None,
+        finish_reason: "EOS_TOKEN".to_string(),
+        stop_reason: None,
+    };
+    let messages = |text: &str| {
+        vec![Message {
+            content: Some(Content::Text(text.to_string())),
+            role: Role::User,
+            ..Default::default()
+        }]
+    };
+
+    // Add chat completions mocks, with one flagged choice and with all choices flagged
+    let some_flagged_choices = vec![choice(0, clean_text), choice(1, flagged_texts[0].0)];
+    let all_flagged_choices = vec![choice(0, flagged_texts[0].0), choice(1, flagged_texts[1].0)];
+    for (input, choices) in [
+        ("Help me with something.", &some_flagged_choices),
+        ("Help me with anything.", &all_flagged_choices),
+    ] {
+        chat_mocks.mock(|when, then| {
+            when.post().path(CHAT_COMPLETIONS_ENDPOINT).json(json!({
+                "model": MODEL_ID,
+                "messages": messages(input),
+                "n": 2,
+            }));
+            then.json(&ChatCompletion {
+                model: MODEL_ID.into(),
+                choices: choices.clone(),
+                ..Default::default()
+            });
+        });
+    }
+
+    // Start orchestrator server and its dependencies
+    let mock_detector_server = MockServer::new(detector_name).with_mocks(detector_mocks);
+    let mock_chat_completions_server = MockServer::new("chat_completions").with_mocks(chat_mocks);
+    let mock_chunker_server = MockServer::new(CHUNKER_NAME_SENTENCE)
+        .grpc()
+        .with_mocks(chunker_mocks);
+
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .detector_servers([&mock_detector_server])
+        .chunker_servers([&mock_chunker_server])
+        .chat_generation_server(&mock_chat_completions_server)
+        .build()
+        .await?;
+
+    let request = |input: &str| {
+        json!({
+            "model": MODEL_ID,
+            "detectors": {
+                "input": {},
+                "output": {
+                    detector_name: {},
+                },
+            },
+            "messages": messages(input),
+            "n": 2,
+            "guardrails": {
+                "actions": {
+                    "flagged_choices": "drop",
+                },
+            },
+        })
+    };
+
+    // Flagged choice is dropped
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CHAT_COMPLETIONS_DETECTION_ENDPOINT)
+        .json(&request("Help me with something."))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let results = response.json::<ChatCompletion>().await?;
+    debug!("{results:#?}");
+    assert_eq!(results.choices, vec![choice(0, clean_text)]);
+    assert_eq!(
+        results.detections.map(|detections| detections.output),
+        Some(vec![OutputDetectionResult {
+            choice_index: 1,
+            field: MessageField::Content,
+            results: detections(flagged_texts[0]),
+        }])
+    );
+    assert_eq!(
+        results.warnings,
+        vec![OrchestratorWarning::new(
+            DetectionWarningReason::UnsuitableOutput,
+            UNSUITABLE_OUTPUT_MESSAGE,
+        )]
+    );
+
+    // All choices are flagged, so none are dropped
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CHAT_COMPLETIONS_DETECTION_ENDPOINT)
+        .json(&request("Help me with anything."))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let results = response.json::<ChatCompletion>().await?;
+    debug!("{results:#?}");
+    assert_eq!(results.choices, all_flagged_choices);
+    assert_eq!(
+        results.detections.map(|detections| detections.output.len()),
+        Some(2)
+    );
+    assert_eq!(
+        results.warnings,
+        vec![
+            OrchestratorWarning::new(
+                DetectionWarningReason::UnsuitableOutput,
+                UNSUITABLE_OUTPUT_MESSAGE,
+            ),
+            OrchestratorWarning::new(
+                DetectionWarningReason::UnsuitableOutput,
+                ALL_CHOICES_FLAGGED_MESSAGE,
+            ),
+        ]
+    );
+
+    Ok(())
+}
+
+// Validates that output not matching a JSON response format is returned with a warning,
+// unless structured output validation is disabled
+#[test(tokio::test)]
+async fn structured_output() -> Result<(), anyhow::Error> {
+    let messages = vec![Message {
+        content: Some(Content::Text("Describe a person as JSON.".into())),
+        role: Role::User,
+        ..Default::default()
+    }];
+    let response_format = json!({
+        "type": "json_schema",
+        "json_schema": {
+            "name": "person",
+            "schema": {
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string" },
+                },
+                "required": ["name"],
+            },
+        },
+    });
+
+    // Add chat completions mock, with the output of the second choice missing `name`
+    let mut chat_mocks = MockSet::new();
+    let choice = |index: u32, text: &str| ChatCompletionChoice {
+        message: ChatCompletionMessage {
+            role: Role::Assistant,
+            content: Some(text.to_string()),
+            refusal: None,
+            tool_calls: vec![],
+            reasoning_content: None,
+        },
+        index,
+        logprobs: None,
+        finish_reason: "EOS_TOKEN".to_string(),
+        stop_reason: None,
+    };
+    let expected_choices = vec![choice(0, r#"{"name": "Ada"}"#), choice(1, r#"{"age": 36}"#)];
+    chat_mocks.mock(|when, then| {
+        when.post().path(CHAT_COMPLETIONS_ENDPOINT).json(json!({
+            "model": MODEL_ID,
+            "messages": messages,
+            "response_format": response_format,
+            "n": 2,
+        }));
+        then.json(&ChatCompletion {
+            model: MODEL_ID.into(),
+            choices: expected_choices.clone(),
+            ..Default::default()
+        });
+    });
+
+    // Start orchestrator server and its dependencies
+    let mock_chat_completions_server = MockServer::new("chat_completions").with_mocks(chat_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .chat_generation_server(&mock_chat_completions_server)
+        .build()
+        .await?;
+
+    let request = |response_format: &serde_json::Value, policy: &str| {
+        json!({
+            "model": MODEL_ID,
+            "detectors": {
+                "input": {},
+                "output": {},
+            },
+            "messages": messages,
+            "response_format": response_format,
+            "n": 2,
+            "guardrails": {
+                "actions": {
+                    "structured_output": policy,
+                },
+            },
+        })
+    };
+    let invalid_output_warning = OrchestratorWarning::new(
+        DetectionWarningReason::InvalidStructuredOutput,
+        &invalid_structured_output_message(&[1]),
+    );
+
+    // Output is not validated
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CHAT_COMPLETIONS_DETECTION_ENDPOINT)
+        .json(&request(&response_format, "passthrough"))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let results = response.json::<ChatCompletion>().await?;
+    debug!("{results:#?}");
+    assert_eq!(results.choices, expected_choices);
+    assert!(results.warnings.is_empty());
+
+    // Invalid output is returned with a warning, with or without retries
+    for policy in ["warn", "retry"] {
+        let response = orchestrator_server
+            .post(ORCHESTRATOR_CHAT_COMPLETIONS_DETECTION_ENDPOINT)
+            .json(&request(&response_format, policy))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let results = response.json::<ChatCompletion>().await?;
+        debug!("{results:#?}");
+        assert_eq!(results.choices, expected_choices, "policy: {policy}");
+        assert_eq!(
+            results.warnings,
+            vec![invalid_output_warning.clone()],
+            "policy: {policy}"
+        );
+    }
+
+    // Invalid schema is rejected
+    let invalid_response_format = json!({
+        "type": "json_schema",
+        "json_schema": {
+            "name": "person",
+            "schema": { "type": "person" },
+        },
+    });
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CHAT_COMPLETIONS_DETECTION_ENDPOINT)
+        .json(&request(&invalid_response_format, "warn"))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let results = response.json::<OrchestratorError>().await?;
+    debug!("{results:#?}");
+    assert!(
+        results
+            .details
+            .starts_with("invalid `response_format` schema:")
+    );
+
+    Ok(())
+}
+
 // Validates that requests with output detector configured returns propagated errors
 // from detector, chunker and completions server when applicable
 #[test(tokio::test)]
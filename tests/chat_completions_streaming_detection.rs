@@ -0,0 +1,271 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+use std::time::Duration;
+
+use common::{
+    chat_generation::serve_chat_completions_stream,
+    chunker::{CHUNKER_MODEL_ID_HEADER_NAME, CHUNKER_NAME_SENTENCE, CHUNKER_STREAMING_ENDPOINT},
+    detectors::{DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE, TEXT_CONTENTS_DETECTOR_ENDPOINT},
+    errors::OrchestratorError,
+    orchestrator::{
+        ORCHESTRATOR_CHAT_COMPLETIONS_DETECTION_ENDPOINT, ORCHESTRATOR_CONFIG_FILE_PATH, SseStream,
+        TestOrchestratorServer,
+    },
+};
+use fms_guardrails_orchestr8::{
+    clients::{
+        detector::{ContentAnalysisRequest, ContentAnalysisResponse},
+        openai::{ChatCompletionChunk, OrchestratorWarning},
+    },
+    models::{DetectionWarningReason, DetectorParams, Metadata, UNSUITABLE_OUTPUT_MESSAGE},
+    pb::{
+        caikit::runtime::chunkers::BidiStreamingChunkerTokenizationTaskRequest,
+        caikit_data_model::nlp::{ChunkerTokenizationStreamResult, Token},
+    },
+};
+use futures::TryStreamExt;
+use hyper::StatusCode;
+use mocktail::prelude::*;
+use serde_json::{Value, json};
+use test_log::test;
+use tracing::debug;
+
+pub mod common;
+
+const MODEL_ID: &str = "my-super-model-8B";
+
+/// Returns a streamed chat completion chunk with `choices`.
+fn chunk(choices: Value) -> String {
+    json!({
+        "id": "chatcmpl-test",
+        "object": "chat.completion.chunk",
+        "created": 1749227854,
+        "model": MODEL_ID,
+        "choices": choices,
+    })
+    .to_string()
+}
+
+/// Returns a chat completions detection request with `n` choices and output detection.
+fn request(n: u32) -> Value {
+    json!({
+        "model": MODEL_ID,
+        "stream": true,
+        "n": n,
+        "detectors": {
+            "input": {},
+            "output": {
+                DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE: {},
+            },
+        },
+        "messages": [{"role": "user", "content": "Hi there!"}],
+    })
+}
+
+/// Asserts that output detections of a streaming chat completion are returned
+/// with the chunk of the choice they were detected on.
+#[test(tokio::test)]
+async fn output_detections() -> Result<(), anyhow::Error> {
+    let detector_name = DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE;
+    let chat_generation_port = serve_chat_completions_stream(vec![
+        chunk(json!([
+            {
+                "index": 0,
+                "delta": {"role": "assistant", "content": "Hi there!"},
+                "finish_reason": "stop",
+            },
+            {
+                "index": 1,
+                "delta": {"role": "assistant", "content": "I <am> fine!"},
+                "finish_reason": "stop",
+            },
+        ])),
+        "[DONE]".into(),
+    ])
+    .await?;
+
+    // Add output chunker mock, called once per choice
+    let mut chunker_mocks = MockSet::new();
+    for (text, end) in [("Hi there!", 9), ("I <am> fine!", 12)] {
+        chunker_mocks.mock(|when, then| {
+            when.path(CHUNKER_STREAMING_ENDPOINT)
+                .header(CHUNKER_MODEL_ID_HEADER_NAME, CHUNKER_NAME_SENTENCE)
+                .pb_stream(vec![BidiStreamingChunkerTokenizationTaskRequest {
+                    text_stream: text.into(),
+                    input_index_stream: 0,
+                }]);
+            then.pb_stream(vec![ChunkerTokenizationStreamResult {
+                results: vec![Token {
+                    start: 0,
+                    end: end as _,
+                    text: text.into(),
+                }],
+                token_count: 0,
+                processed_index: end as _,
+                start_index: 0,
+                input_start_index: 0,
+                input_end_index: 0,
+            }]);
+        });
+    }
+
+    // Add output detection mocks
+    let mut detection_mocks = MockSet::new();
+    detection_mocks.mock(|when, then| {
+        when.path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec!["Hi there!".into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([Vec::<ContentAnalysisResponse>::new()]);
+    });
+    detection_mocks.mock(|when, then| {
+        when.path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec!["I <am> fine!".into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([vec![ContentAnalysisResponse {
+            start: 3,
+            end: 5,
+            text: "am".into(),
+            detection: "has_angle_brackets".into(),
+            detection_type: "angle_brackets".into(),
+            detector_id: Some(detector_name.into()),
+            score: 1.0,
+            evidence: None,
+            metadata: Metadata::new(),
+            chunk: None,
+            category: None,
+        }]]);
+    });
+
+    // Start orchestrator server and its dependencies
+    let mock_chunker_server = MockServer::new(CHUNKER_NAME_SENTENCE)
+        .grpc()
+        .with_mocks(chunker_mocks);
+    let mock_detector_server = MockServer::new(detector_name).with_mocks(detection_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .chat_generation_port(chat_generation_port)
+        .chunker_servers([&mock_chunker_server])
+        .detector_servers([&mock_detector_server])
+        .build()
+        .await?;
+
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CHAT_COMPLETIONS_DETECTION_ENDPOINT)
+        .json(&request(2))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let sse_stream: SseStream<ChatCompletionChunk> = SseStream::new(response.bytes_stream());
+    let mut chunks = sse_stream.try_collect::<Vec<_>>().await?;
+    debug!("{chunks:#?}");
+    // Choices are detected concurrently, so their chunks may arrive in any order
+    chunks.sort_by_key(|chunk| chunk.choices[0].index);
+
+    assert_eq!(chunks.len(), 2);
+    let choice = &chunks[0].choices[0];
+    assert_eq!(choice.delta.content.as_deref(), Some("Hi there!"));
+    assert_eq!(choice.finish_reason.as_deref(), Some("stop"));
+    assert!(chunks[0].detections.is_none());
+    assert!(chunks[0].warnings.is_empty());
+
+    let choice = &chunks[1].choices[0];
+    assert_eq!(choice.index, 1);
+    assert_eq!(choice.delta.content.as_deref(), Some("I <am> fine!"));
+    let output = &chunks[1].detections.as_ref().unwrap().output;
+    assert_eq!(output.len(), 1);
+    assert_eq!(output[0].choice_index, 1);
+    assert_eq!(output[0].results.len(), 1);
+    assert_eq!(output[0].results[0].text, "am");
+    assert_eq!(
+        output[0].results[0].detector_id.as_deref(),
+        Some(detector_name)
+    );
+    assert_eq!(
+        chunks[1].warnings,
+        [OrchestratorWarning::new(
+            DetectionWarningReason::UnsuitableOutput,
+            UNSUITABLE_OUTPUT_MESSAGE
+        )]
+    );
+
+    Ok(())
+}
+
+/// Asserts that an error of the chat completion stream terminates the response
+/// stream with an error event, whichever choices were requested.
+#[test(tokio::test)]
+async fn chat_completion_stream_error() -> Result<(), anyhow::Error> {
+    let chat_generation_port = serve_chat_completions_stream(vec![
+        chunk(json!([
+            {"index": 1, "delta": {"role": "assistant", "content": "Hi"}, "finish_reason": null},
+        ])),
+        "{not a chunk".into(),
+    ])
+    .await?;
+    let mock_chunker_server = MockServer::new(CHUNKER_NAME_SENTENCE).grpc();
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .chat_generation_port(chat_generation_port)
+        .chunker_servers([&mock_chunker_server])
+        .build()
+        .await?;
+
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CHAT_COMPLETIONS_DETECTION_ENDPOINT)
+        .json(&request(3))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let sse_stream: SseStream<Value> = SseStream::new(response.bytes_stream());
+    let events = tokio::time::timeout(Duration::from_secs(10), sse_stream.try_collect::<Vec<_>>())
+        .await
+        .expect("response stream did not complete")?;
+    debug!("{events:#?}");
+    let error = events.last().expect("no error event");
+    assert!(error["code"].is_u64() && error["details"].is_string());
+
+    Ok(())
+}
+
+/// Asserts that requests for more choices than can be detected are rejected.
+#[test(tokio::test)]
+async fn too_many_choices() -> Result<(), anyhow::Error> {
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .build()
+        .await?;
+
+    for n in [0, 17] {
+        let response = orchestrator_server
+            .post(ORCHESTRATOR_CHAT_COMPLETIONS_DETECTION_ENDPOINT)
+            .json(&request(n))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let error = response.json::<OrchestratorError>().await?;
+        assert_eq!(error.details, "`n` must be between 1 and 16");
+    }
+
+    Ok(())
+}
@@ -76,6 +76,7 @@ async fn no_detections() -> Result<(), anyhow::Error> {
         score: 0.01,
         evidence: None,
         metadata: Metadata::new(),
+        category: None,
     };
 
     // Add detector mock
@@ -146,6 +147,7 @@ async fn detections() -> Result<(), anyhow::Error> {
         score: 0.97,
         evidence: None,
         metadata: Metadata::new(),
+        category: None,
     };
 
     // Add detector mock
@@ -187,7 +189,8 @@ async fn detections() -> Result<(), anyhow::Error> {
     assert_eq!(
         response.json::<ChatDetectionResult>().await?,
         ChatDetectionResult {
-            detections: vec![detection]
+            detections: vec![detection],
+            warnings: Vec::new(),
         }
     );
 
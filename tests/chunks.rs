@@ -0,0 +1,209 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+use common::{
+    chunker::{CHUNKER_NAME_SENTENCE, CHUNKER_UNARY_ENDPOINT},
+    orchestrator::{
+        ORCHESTRATOR_CHUNKS_ENDPOINT, ORCHESTRATOR_CONFIG_FILE_PATH, TestOrchestratorServer,
+    },
+};
+use fms_guardrails_orchestr8::{
+    clients::chunker::MODEL_ID_HEADER_NAME as CHUNKER_MODEL_ID_HEADER_NAME,
+    models::{ChunkSpan, ChunksHttpRequest, ChunksResult},
+    pb::{
+        caikit::runtime::chunkers::ChunkerTokenizationTaskRequest,
+        caikit_data_model::nlp::{Token, TokenizationResults},
+    },
+};
+use hyper::{HeaderMap, StatusCode};
+use mocktail::prelude::*;
+use test_log::test;
+use tracing::debug;
+
+pub mod common;
+
+/// Asserts chunks returned by a chunker and the whole doc chunker.
+#[test(tokio::test)]
+async fn chunks() -> Result<(), anyhow::Error> {
+    let chunker_id = CHUNKER_NAME_SENTENCE;
+
+    let mut chunker_mocks = MockSet::new();
+    chunker_mocks.mock(|when, then| {
+        when.path(CHUNKER_UNARY_ENDPOINT)
+            .header(CHUNKER_MODEL_ID_HEADER_NAME, chunker_id)
+            .pb(ChunkerTokenizationTaskRequest {
+                text: "This is the first sentence. This is the second one.".into(),
+            });
+        then.pb(TokenizationResults {
+            results: vec![
+                Token {
+                    start: 0,
+                    end: 27,
+                    text: "This is the first sentence.".into(),
+                },
+                Token {
+                    start: 28,
+                    end: 51,
+                    text: "This is the second one.".into(),
+                },
+            ],
+            token_count: 0,
+        });
+    });
+
+    // Start orchestrator server and its dependencies
+    let mock_chunker_server = MockServer::new(chunker_id).grpc().with_mocks(chunker_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .chunker_servers([&mock_chunker_server])
+        .build()
+        .await?;
+
+    // Assert chunker spans
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CHUNKS_ENDPOINT)
+        .json(&ChunksHttpRequest {
+            chunker_id: chunker_id.into(),
+            content: "This is the first sentence. This is the second one.".into(),
+        })
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json::<ChunksResult>().await?,
+        ChunksResult {
+            chunks: vec![
+                ChunkSpan {
+                    start: 0,
+                    end: 27,
+                    text: "This is the first sentence.".into(),
+                },
+                ChunkSpan {
+                    start: 28,
+                    end: 51,
+                    text: "This is the second one.".into(),
+                },
+            ],
+        }
+    );
+
+    // Assert whole doc chunker span
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CHUNKS_ENDPOINT)
+        .json(&ChunksHttpRequest {
+            chunker_id: "whole_doc_chunker".into(),
+            content: "This is the first sentence. This is the second one.".into(),
+        })
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json::<ChunksResult>().await?,
+        ChunksResult {
+            chunks: vec![ChunkSpan {
+                start: 0,
+                end: 51,
+                text: "This is the first sentence. This is the second one.".into(),
+            }],
+        }
+    );
+
+    // Assert unknown chunker
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CHUNKS_ENDPOINT)
+        .json(&ChunksHttpRequest {
+            chunker_id: "non_existing_chunker".into(),
+            content: "This is the first sentence.".into(),
+        })
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+/// Asserts chunks returned through the orchestrator client.
+#[test(tokio::test)]
+async fn chunks_with_client() -> Result<(), anyhow::Error> {
+    let chunker_id = CHUNKER_NAME_SENTENCE;
+
+    let mut chunker_mocks = MockSet::new();
+    chunker_mocks.mock(|when, then| {
+        when.path(CHUNKER_UNARY_ENDPOINT)
+            .header(CHUNKER_MODEL_ID_HEADER_NAME, chunker_id)
+            .pb(ChunkerTokenizationTaskRequest {
+                text: "This is the first sentence.".into(),
+            });
+        then.pb(TokenizationResults {
+            results: vec![Token {
+                start: 0,
+                end: 27,
+                text: "This is the first sentence.".into(),
+            }],
+            token_count: 0,
+        });
+    });
+
+    // Start orchestrator server and its dependencies
+    let mock_chunker_server = MockServer::new(chunker_id).grpc().with_mocks(chunker_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .chunker_servers([&mock_chunker_server])
+        .build()
+        .await?;
+    let client = orchestrator_server.client().await?;
+
+    // Assert chunker spans
+    let response = client
+        .chunks(
+            ChunksHttpRequest::new(chunker_id, "This is the first sentence."),
+            HeaderMap::new(),
+        )
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(
+        response,
+        ChunksResult {
+            chunks: vec![ChunkSpan {
+                start: 0,
+                end: 27,
+                text: "This is the first sentence.".into(),
+            }],
+        }
+    );
+
+    // Assert unknown chunker error
+    let error = client
+        .chunks(
+            ChunksHttpRequest::new("non_existing_chunker", "This is the first sentence."),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap_err();
+    debug!("{error:#?}");
+
+    assert_eq!(error.status_code(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
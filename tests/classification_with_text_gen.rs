@@ -217,7 +217,7 @@ async fn no_detections() -> Result<(), anyhow::Error> {
         when.post()
             .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
             .json(ContentAnalysisRequest {
-                contents: vec![text_mock_input.clone()],
+                contents: vec![text_mock_input.as_str().into()],
                 detector_params: DetectorParams::new(),
             });
         then.json([Vec::<ContentAnalysisResponse>::new()]);
@@ -229,7 +229,7 @@ async fn no_detections() -> Result<(), anyhow::Error> {
             .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
             .header("x-model-name", DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE)
             .json(ContentAnalysisRequest {
-                contents: vec![expected_response.generated_text.clone()],
+                contents: vec![expected_response.generated_text.as_str().into()],
                 detector_params: DetectorParams::new(),
             });
         then.json([Vec::<ContentAnalysisResponse>::new()]);
@@ -415,6 +415,8 @@ async fn input_detector_detections() -> Result<(), anyhow::Error> {
             score: 1.0,
             evidence: None,
             metadata: Metadata::new(),
+            chunk: None,
+            category: None,
         },
         ContentAnalysisResponse {
             start: 6,
@@ -426,6 +428,8 @@ async fn input_detector_detections() -> Result<(), anyhow::Error> {
             score: 1.0,
             evidence: None,
             metadata: Metadata::new(),
+            chunk: None,
+            category: None,
         },
     ];
 
@@ -566,7 +570,9 @@ async fn input_detector_detections() -> Result<(), anyhow::Error> {
                 entity_group: expected_detections[0].detection_type.clone(),
                 detector_id: expected_detections[0].detector_id.clone(),
                 score: expected_detections[0].score,
-                token_count: None
+                token_count: None,
+                chunk: None,
+                category: None,
             }]),
             output: None
         }
@@ -617,7 +623,9 @@ async fn input_detector_detections() -> Result<(), anyhow::Error> {
                     entity_group: expected_detections[0].detection_type.clone(),
                     detector_id: expected_detections[0].detector_id.clone(),
                     score: expected_detections[0].score,
-                    token_count: None
+                    token_count: None,
+                    chunk: None,
+                    category: None,
                 },
                 TokenClassificationResult {
                     start: 68_u32,
@@ -627,13 +635,56 @@ async fn input_detector_detections() -> Result<(), anyhow::Error> {
                     entity_group: expected_detections[1].detection_type.clone(),
                     detector_id: expected_detections[1].detector_id.clone(),
                     score: expected_detections[1].score,
-                    token_count: None
+                    token_count: None,
+                    chunk: None,
+                    category: None,
                 }
             ]),
             output: None,
         }
     );
 
+    // Orchestrator dry run request for input single detection
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_UNARY_ENDPOINT)
+        .query(&[("dry_run", "true")])
+        .json(&GuardrailsHttpRequest {
+            model_id: MODEL_ID.into(),
+            inputs: "This sentence does not have a detection. But <this one does>.".into(),
+            guardrail_config: Some(GuardrailsConfig {
+                input: Some(GuardrailsConfigInput {
+                    models: HashMap::from([(
+                        DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE.into(),
+                        DetectorParams::new(),
+                    )]),
+                    masks: None,
+                }),
+                output: None,
+            }),
+            text_gen_parameters: None,
+        })
+        .send()
+        .await?;
+
+    // Assertions for dry run input single detection
+    assert_eq!(response.status(), StatusCode::OK);
+    let results = response.json::<ClassifiedGeneratedTextResult>().await?;
+    assert_eq!(results.generated_text, None);
+    assert_eq!(
+        results
+            .token_classification_results
+            .input
+            .map(|input| input.len()),
+        Some(1)
+    );
+    assert_eq!(
+        results.warnings,
+        Some(vec![
+            DetectionWarning::unsuitable_input(),
+            DetectionWarning::dry_run()
+        ])
+    );
+
     Ok(())
 }
 
@@ -824,6 +875,8 @@ async fn output_detector_detections() -> Result<(), anyhow::Error> {
             score: 1.0,
             evidence: None,
             metadata: Metadata::new(),
+            chunk: None,
+            category: None,
         },
         ContentAnalysisResponse {
             start: 6,
@@ -835,6 +888,8 @@ async fn output_detector_detections() -> Result<(), anyhow::Error> {
             score: 1.0,
             evidence: None,
             metadata: Metadata::new(),
+            chunk: None,
+            category: None,
         },
     ];
 
@@ -1048,7 +1103,9 @@ async fn output_detector_detections() -> Result<(), anyhow::Error> {
                 entity_group: expected_detections[0].detection_type.clone(),
                 detector_id: expected_detections[0].detector_id.clone(),
                 score: expected_detections[0].score,
-                token_count: None
+                token_count: None,
+                chunk: None,
+                category: None,
             }])
         }
     );
@@ -1093,7 +1150,9 @@ async fn output_detector_detections() -> Result<(), anyhow::Error> {
                     entity_group: expected_detections[0].detection_type.clone(),
                     detector_id: expected_detections[0].detector_id.clone(),
                     score: expected_detections[0].score,
-                    token_count: None
+                    token_count: None,
+                    chunk: None,
+                    category: None,
                 },
                 TokenClassificationResult {
                     start: 68_u32,
@@ -1103,7 +1162,9 @@ async fn output_detector_detections() -> Result<(), anyhow::Error> {
                     entity_group: expected_detections[1].detection_type.clone(),
                     detector_id: expected_detections[1].detector_id.clone(),
                     score: expected_detections[1].score,
-                    token_count: None
+                    token_count: None,
+                    chunk: None,
+                    category: None,
                 }
             ])
         }
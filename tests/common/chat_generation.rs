@@ -15,5 +15,25 @@
 
 */
 
+use axum::{Router, http::header, routing::post};
+use tokio::net::TcpListener;
+
 // Chat completions server endpoint
 pub const CHAT_COMPLETIONS_ENDPOINT: &str = "/v1/chat/completions";
+
+/// Starts a chat completions server responding with `events` as server-sent events,
+/// e.g. streamed chunks followed by `[DONE]`, returning its port.
+pub async fn serve_chat_completions_stream(events: Vec<String>) -> Result<u16, anyhow::Error> {
+    let body = events
+        .iter()
+        .map(|data| format!("data: {data}\n\n"))
+        .collect::<String>();
+    let app = Router::new().route(
+        CHAT_COMPLETIONS_ENDPOINT,
+        post(|| async move { ([(header::CONTENT_TYPE, "text/event-stream")], body) }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    Ok(port)
+}
@@ -15,6 +15,19 @@
 
 */
 
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use axum::{
+    Json, Router,
+    http::{StatusCode, header},
+    routing::post,
+};
+use serde_json::json;
+use tokio::net::TcpListener;
+
 // Detector names
 pub const DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC: &str = "angle_brackets_detector_whole_doc";
 pub const DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE: &str = "angle_brackets_detector_sentence";
@@ -24,6 +37,7 @@ pub const ANSWER_RELEVANCE_DETECTOR_SENTENCE: &str = "answer_relevance_detector_
 pub const FACT_CHECKING_DETECTOR: &str = "fact_checking_detector";
 pub const FACT_CHECKING_DETECTOR_SENTENCE: &str = "fact_checking_detector_sentence";
 pub const PII_DETECTOR: &str = "pii_detector";
+pub const FLAKY_DETECTOR_SENTENCE: &str = "flaky_detector_sentence";
 pub const NON_EXISTING_DETECTOR: &str = "non_existing_detector";
 
 // Detector endpoints
@@ -31,3 +45,38 @@ pub const TEXT_CONTENTS_DETECTOR_ENDPOINT: &str = "/api/v1/text/contents";
 pub const DETECTION_ON_GENERATION_DETECTOR_ENDPOINT: &str = "/api/v1/text/generation";
 pub const CONTEXT_DOC_DETECTOR_ENDPOINT: &str = "/api/v1/text/context/doc";
 pub const CHAT_DETECTOR_ENDPOINT: &str = "/api/v1/text/chat";
+
+/// Starts a text contents detector server responding 503 Service Unavailable,
+/// counting its requests in `requests`, returning its port.
+pub async fn serve_unavailable_detector(requests: Arc<AtomicUsize>) -> Result<u16, anyhow::Error> {
+    let app = Router::new().route(
+        TEXT_CONTENTS_DETECTOR_ENDPOINT,
+        post(move || async move {
+            requests.fetch_add(1, Ordering::Relaxed);
+            StatusCode::SERVICE_UNAVAILABLE
+        }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    Ok(port)
+}
+
+/// Starts a text contents detector server responding 429 Too Many Requests with
+/// `retry_after` seconds in its `Retry-After` header, returning its port.
+pub async fn serve_rate_limited_detector(retry_after: u64) -> Result<u16, anyhow::Error> {
+    let app = Router::new().route(
+        TEXT_CONTENTS_DETECTOR_ENDPOINT,
+        post(move || async move {
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                Json(json!({ "code": 429, "message": "too many requests" })),
+            )
+        }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    Ok(port)
+}
@@ -25,7 +25,12 @@ use std::{
 
 use bytes::Bytes;
 use eventsource_stream::{EventStream, Eventsource};
-use fms_guardrails_orchestr8::{config::OrchestratorConfig, orchestrator::Orchestrator, server};
+use fms_guardrails_orchestr8::{
+    clients::OrchestratorClient,
+    config::{OrchestratorConfig, ServiceConfig},
+    orchestrator::Orchestrator,
+    server,
+};
 use futures::{
     Stream, StreamExt,
     stream::{
@@ -52,9 +57,15 @@ pub const ORCHESTRATOR_GENERATION_WITH_DETECTION_ENDPOINT: &str =
 pub const ORCHESTRATOR_CONTENT_DETECTION_ENDPOINT: &str = "/api/v2/text/detection/content";
 pub const ORCHESTRATOR_STREAM_CONTENT_DETECTION_ENDPOINT: &str =
     "/api/v2/text/detection/stream-content";
+pub const ORCHESTRATOR_STREAM_INPUT_DETECTION_ENDPOINT: &str =
+    "/api/v2/text/detection/stream-input";
 pub const ORCHESTRATOR_DETECTION_ON_GENERATION_ENDPOINT: &str = "/api/v2/text/detection/generated";
 pub const ORCHESTRATOR_CONTEXT_DOCS_DETECTION_ENDPOINT: &str = "/api/v2/text/detection/context";
 pub const ORCHESTRATOR_CHAT_DETECTION_ENDPOINT: &str = "/api/v2/text/detection/chat";
+pub const ORCHESTRATOR_SUITABILITY_ENDPOINT: &str = "/api/v2/text/suitability";
+pub const ORCHESTRATOR_CHUNKS_ENDPOINT: &str = "/api/v2/text/chunks";
+pub const ORCHESTRATOR_STREAM_RESUME_ENDPOINT: &str =
+    "/api/v2/text/classification-generation/stream/resume";
 
 pub const ORCHESTRATOR_CHAT_COMPLETIONS_DETECTION_ENDPOINT: &str =
     "/api/v2/chat/completions-detection";
@@ -73,8 +84,10 @@ pub struct TestOrchestratorServerBuilder<'a> {
     health_port: Option<u16>,
     generation_server: Option<&'a MockServer>,
     chat_generation_server: Option<&'a MockServer>,
+    chat_generation_port: Option<u16>,
     detector_servers: Option<Vec<&'a MockServer>>,
     chunker_servers: Option<Vec<&'a MockServer>>,
+    configure: Option<Box<dyn FnOnce(&mut OrchestratorConfig) + 'a>>,
 }
 
 impl<'a> TestOrchestratorServerBuilder<'a> {
@@ -107,6 +120,12 @@ impl<'a> TestOrchestratorServerBuilder<'a> {
         self
     }
 
+    /// Sets the port of a chat generation server not started by the builder.
+    pub fn chat_generation_port(mut self, port: u16) -> Self {
+        self.chat_generation_port = Some(port);
+        self
+    }
+
     pub fn detector_servers(mut self, servers: impl IntoIterator<Item = &'a MockServer>) -> Self {
         self.detector_servers = Some(servers.into_iter().collect());
         self
@@ -117,6 +136,12 @@ impl<'a> TestOrchestratorServerBuilder<'a> {
         self
     }
 
+    /// Modifies the loaded config, e.g. to enable optional features.
+    pub fn configure(mut self, configure: impl FnOnce(&mut OrchestratorConfig) + 'a) -> Self {
+        self.configure = Some(Box::new(configure));
+        self
+    }
+
     pub async fn build(self) -> Result<TestOrchestratorServer, anyhow::Error> {
         // Set default crypto provider
         ensure_global_rustls_state();
@@ -127,8 +152,14 @@ impl<'a> TestOrchestratorServerBuilder<'a> {
         // Start & configure mock servers
         initialize_generation_server(self.generation_server, &mut config).await?;
         initialize_chat_generation_server(self.chat_generation_server, &mut config).await?;
+        if let Some(port) = self.chat_generation_port {
+            config.chat_generation.as_mut().unwrap().service.port = Some(port);
+        }
         initialize_detectors(self.detector_servers.as_deref(), &mut config).await?;
         initialize_chunkers(self.chunker_servers.as_deref(), &mut config).await?;
+        if let Some(configure) = self.configure {
+            configure(&mut config);
+        }
 
         // Create & start test orchestrator server
         let server = TestOrchestratorServer::start(config).await?;
@@ -158,7 +189,15 @@ impl TestOrchestratorServer {
             let http_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port);
             let health_http_addr: SocketAddr =
                 SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), health_port);
-            match server::run(http_addr, health_http_addr, None, None, None, orchestrator).await {
+            match server::run(
+                http_addr,
+                health_http_addr,
+                server::ServerTlsConfig::default(),
+                None,
+                orchestrator,
+            )
+            .await
+            {
                 Ok(_) => {
                     // Give the server time to become ready.
                     tokio::time::sleep(Duration::from_millis(10)).await;
@@ -189,6 +228,16 @@ impl TestOrchestratorServer {
         self.health_url.clone()
     }
 
+    /// Creates a client of the orchestrator server.
+    pub async fn client(&self) -> Result<OrchestratorClient, anyhow::Error> {
+        let config = ServiceConfig {
+            hostname: self.base_url.host_str().unwrap().into(),
+            port: self.base_url.port(),
+            ..Default::default()
+        };
+        Ok(OrchestratorClient::new(&config, None).await?)
+    }
+
     pub fn get(&self, path: &str) -> reqwest::RequestBuilder {
         let url = self.server_url(path);
         self.client.get(url)
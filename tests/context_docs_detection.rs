@@ -54,6 +54,7 @@ async fn no_detections() -> Result<(), anyhow::Error> {
         score: 0.23,
         evidence: None,
         metadata: Metadata::new(),
+        category: None,
     };
 
     // Add detector mock
@@ -115,6 +116,7 @@ async fn detections() -> Result<(), anyhow::Error> {
         score: 0.91,
         evidence: None,
         metadata: Metadata::new(),
+        category: None,
     };
 
     // Add detector mock
@@ -159,7 +161,8 @@ async fn detections() -> Result<(), anyhow::Error> {
     assert_eq!(
         response.json::<ContextDocsResult>().await?,
         ContextDocsResult {
-            detections: vec![detection]
+            detections: vec![detection],
+            warnings: Vec::new(),
         }
     );
 
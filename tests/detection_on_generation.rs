@@ -56,6 +56,7 @@ async fn no_detections() -> Result<(), anyhow::Error> {
         score: 0.49,
         evidence: None,
         metadata: Metadata::new(),
+        category: None,
     };
 
     // Add detector mock
@@ -116,6 +117,7 @@ async fn detections() -> Result<(), anyhow::Error> {
         score: 0.89,
         evidence: None,
         metadata: Metadata::new(),
+        category: None,
     };
 
     // Add detector mock
@@ -156,7 +158,8 @@ async fn detections() -> Result<(), anyhow::Error> {
     assert_eq!(
         response.json::<DetectionOnGenerationResult>().await?,
         DetectionOnGenerationResult {
-            detections: vec![detection]
+            detections: vec![detection],
+            warnings: Vec::new(),
         }
     );
 
@@ -30,14 +30,17 @@ use common::{
 };
 use fms_guardrails_orchestr8::{
     clients::detector::GenerationDetectionRequest,
+    config::ProvenanceConfig,
     models::{
         DetectionResult, DetectorParams, GenerationWithDetectionHttpRequest,
-        GenerationWithDetectionResult, Metadata,
+        GenerationWithDetectionResult, Metadata, PolicyDecision,
     },
+    orchestrator::common::{alerts::sign, provenance::content_hash},
     pb::{
         caikit::runtime::nlp::TextGenerationTaskRequest,
         caikit_data_model::nlp::GeneratedTextResult,
     },
+    utils::secrets::SecretSource,
 };
 use http::StatusCode;
 use mocktail::{MockSet, server::MockServer};
@@ -60,6 +63,7 @@ async fn no_detections() -> Result<(), anyhow::Error> {
         score: 0.49,
         evidence: None,
         metadata: Metadata::new(),
+        category: None,
     };
 
     // Add generation mock
@@ -128,6 +132,64 @@ async fn no_detections() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+/// Asserts generated text is returned without detection when no detectors are requested.
+#[test(tokio::test)]
+async fn no_detectors() -> Result<(), anyhow::Error> {
+    let prompt = "In 2014, what was the average height of men who were born in 1996?";
+    let generated_text = "The average height of women is 159cm (or 5'3'').";
+
+    // Add generation mock
+    let model_id = "my-super-model-8B";
+
+    let mut generation_mocks = MockSet::new();
+    generation_mocks.mock(|when, then| {
+        when.path(GENERATION_NLP_UNARY_ENDPOINT)
+            .header(GENERATION_NLP_MODEL_ID_HEADER_NAME, model_id)
+            .pb(TextGenerationTaskRequest {
+                text: prompt.into(),
+                ..Default::default()
+            });
+        then.pb(GeneratedTextResult {
+            generated_text: generated_text.into(),
+            input_token_count: 15,
+            ..Default::default()
+        });
+    });
+
+    // Start orchestrator server and its dependencies
+    let mock_generation_server = MockServer::new("nlp").grpc().with_mocks(generation_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .generation_server(&mock_generation_server)
+        .build()
+        .await?;
+
+    // Make orchestrator call
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_GENERATION_WITH_DETECTION_ENDPOINT)
+        .json(&json!({
+            "model_id": model_id,
+            "prompt": prompt,
+            "detectors": {}
+        }))
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    // assertions
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json::<GenerationWithDetectionResult>().await?,
+        GenerationWithDetectionResult {
+            generated_text: generated_text.into(),
+            input_token_count: 15,
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
+
 /// Asserts detections above default threshold are returned.
 #[test(tokio::test)]
 async fn detections() -> Result<(), anyhow::Error> {
@@ -142,6 +204,7 @@ async fn detections() -> Result<(), anyhow::Error> {
         score: 0.89,
         evidence: None,
         metadata: Metadata::new(),
+        category: None,
     };
 
     // Add generation mock
@@ -204,12 +267,114 @@ async fn detections() -> Result<(), anyhow::Error> {
         GenerationWithDetectionResult {
             generated_text: generated_text.into(),
             detections: vec![detection.clone()],
-            input_token_count: 0
+            input_token_count: 0,
+            ..Default::default()
         }
     );
 
     Ok(())
 }
+/// Asserts provenance of generated text is signed with the trace ID and timestamp, and
+/// passed as detections below the threshold are not returned.
+#[test(tokio::test)]
+async fn provenance() -> Result<(), anyhow::Error> {
+    let detector_name = ANSWER_RELEVANCE_DETECTOR;
+    let prompt = "In 2014, what was the average height of men who were born in 1996?";
+    let generated_text = "The average height of women is 159cm (or 5'3'').";
+    let detection = DetectionResult {
+        detection_type: "relevance".into(),
+        detection: "is_relevant".into(),
+        detector_id: Some(detector_name.into()),
+        score: 0.49,
+        evidence: None,
+        metadata: Metadata::new(),
+        category: None,
+    };
+    let signing_key_path =
+        std::env::temp_dir().join(format!("{}-signing-key", uuid::Uuid::new_v4().simple()));
+    std::fs::write(&signing_key_path, "secret")?;
+
+    // Add generation mock
+    let model_id = "my-super-model-8B";
+
+    let mut generation_mocks = MockSet::new();
+    generation_mocks.mock(|when, then| {
+        when.path(GENERATION_NLP_UNARY_ENDPOINT)
+            .header(GENERATION_NLP_MODEL_ID_HEADER_NAME, model_id)
+            .pb(TextGenerationTaskRequest {
+                text: prompt.into(),
+                ..Default::default()
+            });
+        then.pb(GeneratedTextResult {
+            generated_text: generated_text.into(),
+            ..Default::default()
+        });
+    });
+
+    // Add detection mock
+    let mut detection_mocks = MockSet::new();
+    detection_mocks.mock(|when, then| {
+        when.post()
+            .path(DETECTION_ON_GENERATION_DETECTOR_ENDPOINT)
+            .json(GenerationDetectionRequest {
+                prompt: prompt.into(),
+                generated_text: generated_text.into(),
+                detector_params: DetectorParams::new(),
+            });
+        then.json([&detection]);
+    });
+
+    // Start orchestrator server and its dependencies
+    let mock_generation_server = MockServer::new("nlp").grpc().with_mocks(generation_mocks);
+    let mock_detector_server = MockServer::new(detector_name).with_mocks(detection_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .configure(|config| {
+            config.provenance = Some(ProvenanceConfig {
+                signing_key: Some(SecretSource::File(signing_key_path.clone())),
+            })
+        })
+        .generation_server(&mock_generation_server)
+        .detector_servers([&mock_detector_server])
+        .build()
+        .await?;
+
+    // Make orchestrator call
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_GENERATION_WITH_DETECTION_ENDPOINT)
+        .json(&GenerationWithDetectionHttpRequest {
+            model_id: model_id.into(),
+            prompt: prompt.into(),
+            detectors: HashMap::from([(detector_name.into(), DetectorParams::new())]),
+            text_gen_parameters: None,
+        })
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    // assertions
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = response.json::<GenerationWithDetectionResult>().await?;
+    assert!(response.detections.is_empty());
+    let provenance = response.provenance.expect("provenance should be returned");
+    assert_eq!(provenance.content_hash, content_hash(generated_text));
+    assert_eq!(provenance.decision, PolicyDecision::Passed);
+    assert_eq!(provenance.trace_id.len(), 32);
+    assert_eq!(
+        provenance.signature,
+        Some(sign(
+            b"secret",
+            format!(
+                "{}:passed:{}:{}",
+                provenance.content_hash, provenance.trace_id, provenance.timestamp
+            )
+            .as_bytes()
+        ))
+    );
+
+    Ok(())
+}
+
 /// Asserts scenarios in which errors are returned from clients.
 #[test(tokio::test)]
 async fn client_error() -> Result<(), anyhow::Error> {
@@ -401,23 +566,6 @@ async fn orchestrator_validation_error() -> Result<(), anyhow::Error> {
     assert_eq!(response.code, 422);
     assert!(response.details.contains("missing field `detectors`"));
 
-    // assert request with invalid `detectors`
-    let response = orchestrator_server
-        .post(ORCHESTRATOR_GENERATION_WITH_DETECTION_ENDPOINT)
-        .json(&json!({
-            "model_id": model_id,
-            "prompt": prompt,
-            "detectors": {}
-        }))
-        .send()
-        .await?;
-    debug!("{response:#?}");
-
-    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
-    let response = response.json::<OrchestratorError>().await?;
-    debug!("{response:#?}");
-    assert_eq!(response, OrchestratorError::required("detectors"));
-
     // assert request with invalid type detectors
     let response = orchestrator_server
         .post(ORCHESTRATOR_GENERATION_WITH_DETECTION_ENDPOINT)
@@ -0,0 +1,82 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+use common::orchestrator::{
+    ORCHESTRATOR_CONFIG_FILE_PATH, TestOrchestratorServer, ensure_global_rustls_state,
+};
+use fms_guardrails_orchestr8::config::{EgressPolicy, OrchestratorConfig};
+use hyper::StatusCode;
+use serde_json::json;
+use test_log::test;
+use tracing::debug;
+
+pub mod common;
+
+const ORCHESTRATOR_GENERATION_JOB_ENDPOINT: &str = "/api/v2/jobs/generation";
+
+/// Asserts job callbacks are only accepted to hosts allowed by both the callbacks
+/// config and the egress policy.
+#[test(tokio::test)]
+async fn callback_url_egress() -> Result<(), anyhow::Error> {
+    ensure_global_rustls_state();
+    let mut config = OrchestratorConfig::load(ORCHESTRATOR_CONFIG_FILE_PATH).await?;
+    config.egress = Some(EgressPolicy {
+        hosts: vec!["localhost".into()],
+        cidrs: vec![],
+    });
+    config.jobs.callbacks = Some(EgressPolicy {
+        hosts: vec!["localhost".into(), "hooks.example.com".into()],
+        cidrs: vec![
+            "10.0.0.0/8"
+                .to_string()
+                .try_into()
+                .map_err(anyhow::Error::msg)?,
+        ],
+    });
+    let orchestrator_server = TestOrchestratorServer::start(config).await?;
+
+    let submit = |callback_url: &str| {
+        orchestrator_server
+            .post(ORCHESTRATOR_GENERATION_JOB_ENDPOINT)
+            .json(&json!({
+                "request": {
+                    "model_id": "my-super-model-8B",
+                    "inputs": "Hi there! How are you?",
+                },
+                "callback_url": callback_url,
+            }))
+            .send()
+    };
+
+    // Allowed by the callbacks config, not by the egress policy
+    for callback_url in ["http://10.1.2.3/jobs", "http://hooks.example.com/jobs"] {
+        let response = submit(callback_url).await?;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = response.json::<serde_json::Value>().await?;
+        debug!("{body:#?}");
+        assert_eq!(
+            body["details"],
+            format!("callbacks to `{callback_url}` are not allowed")
+        );
+    }
+
+    // Allowed by both
+    let response = submit("http://localhost:9/jobs").await?;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    Ok(())
+}
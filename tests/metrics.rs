@@ -0,0 +1,110 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+use std::collections::HashMap;
+
+use clap::Parser;
+use common::{
+    detectors::{DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC, TEXT_CONTENTS_DETECTOR_ENDPOINT},
+    orchestrator::{
+        ORCHESTRATOR_CONFIG_FILE_PATH, ORCHESTRATOR_CONTENT_DETECTION_ENDPOINT,
+        TestOrchestratorServer,
+    },
+};
+use fms_guardrails_orchestr8::{
+    args::{Args, TracingConfig},
+    clients::detector::{ContentAnalysisRequest, ContentAnalysisResponse},
+    models::{DetectorParams, TextContentDetectionHttpRequest},
+    utils::trace::init_tracing,
+};
+use hyper::StatusCode;
+use mocktail::prelude::*;
+use tracing::debug;
+
+pub mod common;
+
+/// Asserts route, detector and client health metrics are served on `/metrics` with
+/// `PROMETHEUS_METRICS=true`.
+#[tokio::test]
+async fn prometheus_metrics() -> Result<(), anyhow::Error> {
+    // SAFETY: the only test of this binary sets the variable before spawning tasks
+    unsafe { std::env::set_var("PROMETHEUS_METRICS", "true") };
+    let args = Args::parse_from(["fms-guardrails-orchestr8"]);
+    let _trace_shutdown = init_tracing(TracingConfig::from(args))?;
+
+    let detector_name = DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC;
+    let mut detector_mocks = MockSet::new();
+    detector_mocks.mock(|when, then| {
+        when.post()
+            .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec!["This sentence has no detections.".into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([Vec::<ContentAnalysisResponse>::new()]);
+    });
+    let mock_detector_server = MockServer::new(detector_name).with_mocks(detector_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .detector_servers([&mock_detector_server])
+        .build()
+        .await?;
+
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CONTENT_DETECTION_ENDPOINT)
+        .json(&TextContentDetectionHttpRequest {
+            content: "This sentence has no detections.".into(),
+            detectors: HashMap::from([(detector_name.into(), DetectorParams::new())]),
+            max_wait_ms: None,
+        })
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Probe client health, recording the client health gauge
+    let info_url = orchestrator_server.health_url().join("info?probe=true")?;
+    assert_eq!(reqwest::get(info_url).await?.status(), StatusCode::OK);
+
+    let metrics_url = orchestrator_server.health_url().join("metrics")?;
+    let response = reqwest::get(metrics_url).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let metrics = response.text().await?;
+    debug!("{metrics}");
+    let has_metric = |name: &str, label: &str| {
+        metrics
+            .lines()
+            .any(|line| line.starts_with(name) && line.contains(label))
+    };
+    assert!(has_metric(
+        "route_request_count",
+        r#"route="/api/v2/text/detection/content""#
+    ));
+    assert!(has_metric("route_request_duration", r#"status="200""#));
+    assert!(has_metric(
+        "detector_request_count",
+        &format!(r#"detector_id="{detector_name}""#)
+    ));
+    assert!(has_metric(
+        "detector_request_duration",
+        &format!(r#"detector_id="{detector_name}""#)
+    ));
+    assert!(has_metric(
+        "client_health",
+        &format!(r#"service="{detector_name}""#)
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,147 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+use common::{
+    generation::{GENERATION_NLP_MODEL_ID_HEADER_NAME, GENERATION_NLP_STREAMING_ENDPOINT},
+    orchestrator::{
+        ORCHESTRATOR_CONFIG_FILE_PATH, ORCHESTRATOR_STREAM_RESUME_ENDPOINT,
+        ORCHESTRATOR_STREAMING_ENDPOINT, TestOrchestratorServer,
+    },
+};
+use eventsource_stream::{Event, Eventsource};
+use fms_guardrails_orchestr8::{
+    config::StreamResumptionConfig,
+    models::{ClassifiedGeneratedTextStreamResult, GuardrailsHttpRequest},
+    pb::{
+        caikit::runtime::nlp::ServerStreamingTextGenerationTaskRequest,
+        caikit_data_model::nlp::GeneratedTextStreamResult,
+    },
+};
+use futures::TryStreamExt;
+use hyper::StatusCode;
+use mocktail::prelude::*;
+use serde_json::Value;
+use test_log::test;
+use tracing::debug;
+
+pub mod common;
+
+const MODEL_ID: &str = "my-super-model-8B";
+
+/// Returns the SSE events of `response`.
+async fn events(response: reqwest::Response) -> Result<Vec<Event>, anyhow::Error> {
+    let events = response
+        .bytes_stream()
+        .eventsource()
+        .try_collect::<Vec<_>>()
+        .await?;
+    debug!("{events:#?}");
+    Ok(events)
+}
+
+/// Returns the generated text of a message event.
+fn generated_text(event: &Event) -> Option<String> {
+    serde_json::from_str::<ClassifiedGeneratedTextStreamResult>(&event.data)
+        .unwrap()
+        .generated_text
+}
+
+/// Asserts that a streaming classification with text generation response is resumed
+/// from a checkpoint, and that invalid and unknown resumption tokens are rejected.
+#[test(tokio::test)]
+async fn resume_from_checkpoint() -> Result<(), anyhow::Error> {
+    let mut mocks = MockSet::new();
+    mocks.mock(|when, then| {
+        when.path(GENERATION_NLP_STREAMING_ENDPOINT)
+            .header(GENERATION_NLP_MODEL_ID_HEADER_NAME, MODEL_ID)
+            .pb(ServerStreamingTextGenerationTaskRequest {
+                text: "Hi there! How are you?".into(),
+                ..Default::default()
+            });
+        then.pb_stream(
+            ["I", " am", " great!"].map(|text| GeneratedTextStreamResult {
+                generated_text: text.into(),
+                ..Default::default()
+            }),
+        );
+    });
+    let generation_server = MockServer::new("nlp").grpc().with_mocks(mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .generation_server(&generation_server)
+        .configure(|config| {
+            config.stream_resumption = Some(StreamResumptionConfig {
+                window: 60,
+                checkpoint_interval: 2,
+                max_streams: 10,
+                max_messages: 10,
+            })
+        })
+        .build()
+        .await?;
+
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_STREAMING_ENDPOINT)
+        .json(&GuardrailsHttpRequest {
+            model_id: MODEL_ID.into(),
+            inputs: "Hi there! How are you?".into(),
+            guardrail_config: None,
+            text_gen_parameters: None,
+        })
+        .send()
+        .await?;
+    let events = events(response).await?;
+
+    // A checkpoint is sent after every 2 messages
+    assert_eq!(events.len(), 4);
+    assert_eq!(generated_text(&events[0]).as_deref(), Some("I"));
+    assert_eq!(generated_text(&events[1]).as_deref(), Some(" am"));
+    assert_eq!(events[2].event, "checkpoint");
+    assert_eq!(generated_text(&events[3]).as_deref(), Some(" great!"));
+    let checkpoint = serde_json::from_str::<Value>(&events[2].data)?;
+    let resumption_token = checkpoint["resumption_token"].as_str().unwrap();
+
+    // Resumed streams return messages after the checkpoint
+    let response = orchestrator_server
+        .get(&format!(
+            "{ORCHESTRATOR_STREAM_RESUME_ENDPOINT}/{resumption_token}"
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let events = events(response).await?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(generated_text(&events[0]).as_deref(), Some(" great!"));
+
+    // Invalid scenario
+    let response = orchestrator_server
+        .get(&format!("{ORCHESTRATOR_STREAM_RESUME_ENDPOINT}/invalid"))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Unknown stream scenario
+    let response = orchestrator_server
+        .get(&format!(
+            "{ORCHESTRATOR_STREAM_RESUME_ENDPOINT}/dW5rbm93bjow"
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
@@ -41,9 +41,9 @@ use eventsource_stream::Eventsource;
 use fms_guardrails_orchestr8::{
     clients::detector::{ContentAnalysisRequest, ContentAnalysisResponse},
     models::{
-        ClassifiedGeneratedTextStreamResult, DetectionWarning, DetectorParams, GuardrailsConfig,
-        GuardrailsConfigInput, GuardrailsConfigOutput, GuardrailsHttpRequest, Metadata,
-        TextGenTokenClassificationResults, TokenClassificationResult,
+        ClassifiedGeneratedTextStreamResult, DetectionChunk, DetectionWarning, DetectorParams,
+        GuardrailsConfig, GuardrailsConfigInput, GuardrailsConfigOutput, GuardrailsHttpRequest,
+        Metadata, TextGenTokenClassificationResults, TokenClassificationResult,
     },
     pb::{
         caikit::runtime::{
@@ -375,6 +375,8 @@ async fn input_detector_detections() -> Result<(), anyhow::Error> {
         score: 1.0,
         evidence: None,
         metadata: Metadata::new(),
+        chunk: None,
+        category: None,
     };
     let mut detection_mocks = MockSet::new();
     detection_mocks.mock(|when, then| {
@@ -415,6 +417,8 @@ async fn input_detector_detections() -> Result<(), anyhow::Error> {
         score: 1.0,
         evidence: None,
         metadata: Metadata::new(),
+        chunk: None,
+        category: None,
     };
     let mut whole_doc_detection_mocks = MockSet::new();
     whole_doc_detection_mocks.mock(|when, then| {
@@ -480,7 +484,9 @@ async fn input_detector_detections() -> Result<(), anyhow::Error> {
                 entity_group: mock_detection_response.detection_type,
                 detector_id: mock_detection_response.detector_id,
                 score: mock_detection_response.score,
-                token_count: None
+                token_count: None,
+                chunk: None,
+                category: None,
             }]),
             output: None
         }
@@ -539,7 +545,9 @@ async fn input_detector_detections() -> Result<(), anyhow::Error> {
                     entity_group: whole_doc_mock_detection_response.detection_type,
                     detector_id: whole_doc_mock_detection_response.detector_id,
                     score: whole_doc_mock_detection_response.score,
-                    token_count: None
+                    token_count: None,
+                    chunk: None,
+                    category: None,
                 },
                 TokenClassificationResult {
                     start: 46, // index of first token of detected text, relative to the `inputs` string sent in the orchestrator request.
@@ -549,7 +557,9 @@ async fn input_detector_detections() -> Result<(), anyhow::Error> {
                     entity_group: "angle_brackets".into(),
                     detector_id: Some(detector_name.to_string()),
                     score: mock_detection_response.score,
-                    token_count: None
+                    token_count: None,
+                    chunk: None,
+                    category: None,
                 }
             ]),
             output: None
@@ -874,38 +884,6 @@ async fn orchestrator_validation_error() -> Result<(), anyhow::Error> {
         "failed at invalid output detector scenario"
     );
 
-    // Invalid chunker on output detector scenario
-    let response = orchestrator_server
-        .post(ORCHESTRATOR_STREAMING_ENDPOINT)
-        .json(&GuardrailsHttpRequest {
-            model_id: model_id.into(),
-            inputs: "This request contains a detector with an invalid chunker".into(),
-            guardrail_config: Some(GuardrailsConfig {
-                input: None,
-                output: Some(GuardrailsConfigOutput {
-                    models: HashMap::from([(
-                        DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC.into(),
-                        DetectorParams::new(),
-                    )]),
-                }),
-            }),
-            text_gen_parameters: None,
-        })
-        .send()
-        .await?;
-    debug!("{response:#?}");
-
-    assert_eq!(response.status(), 200);
-    let sse_stream: SseStream<OrchestratorError> = SseStream::new(response.bytes_stream());
-    let messages = sse_stream.try_collect::<Vec<_>>().await?;
-    debug!("{messages:#?}");
-    assert_eq!(messages.len(), 1);
-    assert_eq!(
-        messages[0],
-        OrchestratorError::chunker_not_supported(DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC),
-        "failed on output detector with invalid chunker scenario"
-    );
-
     // Non-existing output detector scenario
     let response = orchestrator_server
         .post(ORCHESTRATOR_STREAMING_ENDPOINT)
@@ -1321,6 +1299,8 @@ async fn output_detectors_detections() -> Result<(), anyhow::Error> {
             score: 1.0,
             evidence: None,
             metadata: Metadata::new(),
+            chunk: None,
+            category: None,
         }]]);
     });
 
@@ -1343,6 +1323,8 @@ async fn output_detectors_detections() -> Result<(), anyhow::Error> {
             score: 1.0,
             evidence: None,
             metadata: Metadata::new(),
+            chunk: None,
+            category: None,
         }]]);
     });
     parenthesis_mocks.mock(|when, then| {
@@ -1430,6 +1412,12 @@ async fn output_detectors_detections() -> Result<(), anyhow::Error> {
                     detector_id: Some(angle_brackets_detector.into()),
                     score: 1.0,
                     token_count: None,
+                    chunk: Some(DetectionChunk {
+                        chunker_id: chunker_id.into(),
+                        start_index: 13,
+                        processed_index: 31,
+                    }),
+                    category: None,
                 }]),
             },
             processed_index: Some(31),
@@ -1490,6 +1478,12 @@ async fn output_detectors_detections() -> Result<(), anyhow::Error> {
                     detector_id: Some(parenthesis_detector.into()),
                     score: 1.0,
                     token_count: None,
+                    chunk: Some(DetectionChunk {
+                        chunker_id: chunker_id.into(),
+                        start_index: 0,
+                        processed_index: 13,
+                    }),
+                    category: None,
                 }]),
             },
             processed_index: Some(13),
@@ -1511,6 +1505,12 @@ async fn output_detectors_detections() -> Result<(), anyhow::Error> {
                     detector_id: Some(angle_brackets_detector.into()),
                     score: 1.0,
                     token_count: None,
+                    chunk: Some(DetectionChunk {
+                        chunker_id: chunker_id.into(),
+                        start_index: 13,
+                        processed_index: 31,
+                    }),
+                    category: None,
                 }]),
             },
             processed_index: Some(31),
@@ -1812,3 +1812,275 @@ async fn output_detector_client_error() -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+/// Asserts that output detectors using `whole_doc_chunker` run in a final pass on the
+/// complete generated text, returned in a final message after the generated text.
+#[test(tokio::test)]
+async fn output_detectors_final_pass() -> Result<(), anyhow::Error> {
+    let model_id = "my-super-model-8B";
+    let detector_name = DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC;
+
+    // Add generation mock
+    let mut generation_mocks = MockSet::new();
+    generation_mocks.mock(|when, then| {
+        when.path(GENERATION_NLP_STREAMING_ENDPOINT)
+            .header(GENERATION_NLP_MODEL_ID_HEADER_NAME, model_id)
+            .pb(ServerStreamingTextGenerationTaskRequest {
+                text: "Hi there! How are you?".into(),
+                ..Default::default()
+            });
+        then.pb_stream([
+            GeneratedTextStreamResult {
+                generated_text: "I".into(),
+                ..Default::default()
+            },
+            GeneratedTextStreamResult {
+                generated_text: " am".into(),
+                ..Default::default()
+            },
+            GeneratedTextStreamResult {
+                generated_text: " <great>!".into(),
+                ..Default::default()
+            },
+        ]);
+    });
+
+    // Add final pass detection mock
+    let mock_detection_response = ContentAnalysisResponse {
+        start: 6,
+        end: 11,
+        text: "great".into(),
+        detection: "has_angle_brackets".into(),
+        detection_type: "angle_brackets".into(),
+        detector_id: Some(detector_name.into()),
+        score: 1.0,
+        evidence: None,
+        metadata: Metadata::new(),
+        chunk: None,
+        category: None,
+    };
+    let mut detection_mocks = MockSet::new();
+    detection_mocks.mock(|when, then| {
+        when.path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec!["I am <great>!".into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([vec![&mock_detection_response]]);
+    });
+
+    // Start orchestrator server and its dependencies
+    let generation_server = MockServer::new("nlp").grpc().with_mocks(generation_mocks);
+    let mock_detector_server = MockServer::new(detector_name).with_mocks(detection_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .generation_server(&generation_server)
+        .detector_servers([&mock_detector_server])
+        .build()
+        .await?;
+
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_STREAMING_ENDPOINT)
+        .json(&GuardrailsHttpRequest {
+            model_id: model_id.into(),
+            inputs: "Hi there! How are you?".into(),
+            guardrail_config: Some(GuardrailsConfig {
+                input: None,
+                output: Some(GuardrailsConfigOutput {
+                    models: HashMap::from([(detector_name.into(), DetectorParams::new())]),
+                }),
+            }),
+            text_gen_parameters: None,
+        })
+        .send()
+        .await?;
+
+    let sse_stream: SseStream<ClassifiedGeneratedTextStreamResult> =
+        SseStream::new(response.bytes_stream());
+    let messages = sse_stream.try_collect::<Vec<_>>().await?;
+    debug!("{messages:#?}");
+
+    assert_eq!(messages.len(), 4);
+    assert_eq!(messages[0].generated_text, Some("I".into()));
+    assert_eq!(messages[1].generated_text, Some(" am".into()));
+    assert_eq!(messages[2].generated_text, Some(" <great>!".into()));
+    assert!(messages[3].generated_text.is_none());
+    assert_eq!(messages[3].start_index, Some(0));
+    assert_eq!(messages[3].processed_index, Some(13));
+    assert_eq!(
+        messages[3].token_classification_results,
+        TextGenTokenClassificationResults {
+            input: None,
+            output: Some(vec![TokenClassificationResult {
+                start: 6,
+                end: 11,
+                word: mock_detection_response.text,
+                entity: mock_detection_response.detection,
+                entity_group: mock_detection_response.detection_type,
+                detector_id: mock_detection_response.detector_id,
+                score: mock_detection_response.score,
+                token_count: None,
+                chunk: None,
+                category: None,
+            }]),
+        }
+    );
+    assert_eq!(
+        messages[3].warnings,
+        Some(vec![DetectionWarning::unsuitable_output()])
+    );
+
+    Ok(())
+}
+
+/// Asserts that the final pass runs on the text streamed by the generation service,
+/// not on the chunks of detectors run as text is generated, which may not cover it.
+#[test(tokio::test)]
+async fn output_detectors_final_pass_generated_text() -> Result<(), anyhow::Error> {
+    let model_id = "my-super-model-8B";
+    let sentence_detector = DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE;
+    let whole_doc_detector = DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC;
+
+    // Add generation mock
+    let mut generation_mocks = MockSet::new();
+    generation_mocks.mock(|when, then| {
+        when.path(GENERATION_NLP_STREAMING_ENDPOINT)
+            .header(GENERATION_NLP_MODEL_ID_HEADER_NAME, model_id)
+            .pb(ServerStreamingTextGenerationTaskRequest {
+                text: "Hi there! How are you?".into(),
+                ..Default::default()
+            });
+        then.pb_stream([
+            GeneratedTextStreamResult {
+                generated_text: "I am great!".into(),
+                ..Default::default()
+            },
+            GeneratedTextStreamResult {
+                generated_text: " What?".into(),
+                ..Default::default()
+            },
+        ]);
+    });
+
+    // Add output chunker mock, with chunks excluding whitespace between sentences
+    let chunker_id = CHUNKER_NAME_SENTENCE;
+    let mut chunker_mocks = MockSet::new();
+    chunker_mocks.mock(|when, then| {
+        when.path(CHUNKER_STREAMING_ENDPOINT)
+            .header(CHUNKER_MODEL_ID_HEADER_NAME, chunker_id)
+            .pb_stream(vec![
+                BidiStreamingChunkerTokenizationTaskRequest {
+                    text_stream: "I am great!".into(),
+                    input_index_stream: 0,
+                },
+                BidiStreamingChunkerTokenizationTaskRequest {
+                    text_stream: " What?".into(),
+                    input_index_stream: 1,
+                },
+            ]);
+        then.pb_stream(vec![
+            ChunkerTokenizationStreamResult {
+                results: vec![Token {
+                    start: 0,
+                    end: 11,
+                    text: "I am great!".into(),
+                }],
+                token_count: 0,
+                processed_index: 11,
+                start_index: 0,
+                input_start_index: 0,
+                input_end_index: 0,
+            },
+            ChunkerTokenizationStreamResult {
+                results: vec![Token {
+                    start: 12,
+                    end: 17,
+                    text: "What?".into(),
+                }],
+                token_count: 0,
+                processed_index: 17,
+                start_index: 12,
+                input_start_index: 1,
+                input_end_index: 1,
+            },
+        ]);
+    });
+
+    // Add detection mocks
+    let mut sentence_detection_mocks = MockSet::new();
+    for chunk in ["I am great!", "What?"] {
+        sentence_detection_mocks.mock(|when, then| {
+            when.path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+                .json(ContentAnalysisRequest {
+                    contents: vec![chunk.into()],
+                    detector_params: DetectorParams::new(),
+                });
+            then.json([Vec::<ContentAnalysisResponse>::new()]);
+        });
+    }
+    let mut whole_doc_detection_mocks = MockSet::new();
+    whole_doc_detection_mocks.mock(|when, then| {
+        when.path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec!["I am great! What?".into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([Vec::<ContentAnalysisResponse>::new()]);
+    });
+
+    // Start orchestrator server and its dependencies
+    let generation_server = MockServer::new("nlp").grpc().with_mocks(generation_mocks);
+    let mock_chunker_server = MockServer::new(chunker_id).grpc().with_mocks(chunker_mocks);
+    let mock_sentence_detector_server =
+        MockServer::new(sentence_detector).with_mocks(sentence_detection_mocks);
+    let mock_whole_doc_detector_server =
+        MockServer::new(whole_doc_detector).with_mocks(whole_doc_detection_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .generation_server(&generation_server)
+        .chunker_servers([&mock_chunker_server])
+        .detector_servers([
+            &mock_sentence_detector_server,
+            &mock_whole_doc_detector_server,
+        ])
+        .build()
+        .await?;
+
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_STREAMING_ENDPOINT)
+        .json(&GuardrailsHttpRequest {
+            model_id: model_id.into(),
+            inputs: "Hi there! How are you?".into(),
+            guardrail_config: Some(GuardrailsConfig {
+                input: None,
+                output: Some(GuardrailsConfigOutput {
+                    models: HashMap::from([
+                        (sentence_detector.into(), DetectorParams::new()),
+                        (whole_doc_detector.into(), DetectorParams::new()),
+                    ]),
+                }),
+            }),
+            text_gen_parameters: None,
+        })
+        .send()
+        .await?;
+
+    let sse_stream: SseStream<ClassifiedGeneratedTextStreamResult> =
+        SseStream::new(response.bytes_stream());
+    let messages = sse_stream.try_collect::<Vec<_>>().await?;
+    debug!("{messages:#?}");
+
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[0].generated_text, Some("I am great!".into()));
+    assert_eq!(messages[1].generated_text, Some("What?".into()));
+    // Final pass on the generated text, including whitespace between chunks
+    assert!(messages[2].generated_text.is_none());
+    assert_eq!(messages[2].processed_index, Some(17));
+    assert_eq!(
+        messages[2].token_classification_results.output,
+        Some(vec![])
+    );
+    assert!(messages[2].warnings.is_none());
+
+    Ok(())
+}
@@ -20,8 +20,8 @@ use common::{
     chunker::{CHUNKER_MODEL_ID_HEADER_NAME, CHUNKER_NAME_SENTENCE, CHUNKER_STREAMING_ENDPOINT},
     detectors::{
         DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE, DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC,
-        DETECTOR_NAME_PARENTHESIS_SENTENCE, FACT_CHECKING_DETECTOR_SENTENCE, NON_EXISTING_DETECTOR,
-        TEXT_CONTENTS_DETECTOR_ENDPOINT,
+        DETECTOR_NAME_PARENTHESIS_SENTENCE, FACT_CHECKING_DETECTOR_SENTENCE,
+        FLAKY_DETECTOR_SENTENCE, NON_EXISTING_DETECTOR, TEXT_CONTENTS_DETECTOR_ENDPOINT,
     },
     errors::{DetectorError, OrchestratorError},
     orchestrator::{
@@ -32,8 +32,8 @@ use common::{
 use fms_guardrails_orchestr8::{
     clients::detector::{ContentAnalysisRequest, ContentAnalysisResponse},
     models::{
-        DetectorParams, Metadata, StreamingContentDetectionRequest,
-        StreamingContentDetectionResponse,
+        DetectionChunk, DetectionWarning, DetectorParams, Metadata,
+        StreamingContentDetectionRequest, StreamingContentDetectionResponse,
     },
     pb::{
         caikit::runtime::chunkers::BidiStreamingChunkerTokenizationTaskRequest,
@@ -191,11 +191,13 @@ async fn no_detections() -> Result<(), anyhow::Error> {
             detections: vec![],
             start_index: 0,
             processed_index: 9,
+            warnings: vec![],
         },
         StreamingContentDetectionResponse {
             detections: vec![],
             start_index: 9,
             processed_index: 22,
+            warnings: vec![],
         },
     ];
     assert_eq!(
@@ -247,11 +249,13 @@ async fn no_detections() -> Result<(), anyhow::Error> {
             detections: vec![],
             start_index: 0,
             processed_index: 9,
+            warnings: vec![],
         },
         StreamingContentDetectionResponse {
             detections: vec![],
             start_index: 9,
             processed_index: 22,
+            warnings: vec![],
         },
     ];
     assert_eq!(
@@ -334,6 +338,8 @@ async fn detections() -> Result<(), anyhow::Error> {
             score: 1.0,
             evidence: None,
             metadata: Metadata::new(),
+            chunk: None,
+            category: None,
         }]]);
     });
 
@@ -355,6 +361,8 @@ async fn detections() -> Result<(), anyhow::Error> {
             score: 1.0,
             evidence: None,
             metadata: Metadata::new(),
+            chunk: None,
+            category: None,
         }]]);
     });
     parenthesis_detection_mocks.mock(|when, then| {
@@ -411,6 +419,7 @@ async fn detections() -> Result<(), anyhow::Error> {
             detections: vec![],
             start_index: 0,
             processed_index: 11,
+            warnings: vec![],
         },
         StreamingContentDetectionResponse {
             detections: vec![ContentAnalysisResponse {
@@ -423,9 +432,16 @@ async fn detections() -> Result<(), anyhow::Error> {
                 score: 1.0,
                 evidence: None,
                 metadata: Metadata::new(),
+                chunk: Some(DetectionChunk {
+                    chunker_id: chunker_id.into(),
+                    start_index: 11,
+                    processed_index: 26,
+                }),
+                category: None,
             }],
             start_index: 11,
             processed_index: 26,
+            warnings: vec![],
         },
     ];
     assert_eq!(
@@ -468,9 +484,16 @@ async fn detections() -> Result<(), anyhow::Error> {
                 score: 1.0,
                 evidence: None,
                 metadata: Metadata::new(),
+                chunk: Some(DetectionChunk {
+                    chunker_id: chunker_id.into(),
+                    start_index: 0,
+                    processed_index: 11,
+                }),
+                category: None,
             }],
             start_index: 0,
             processed_index: 11,
+            warnings: vec![],
         },
         StreamingContentDetectionResponse {
             detections: vec![ContentAnalysisResponse {
@@ -483,9 +506,16 @@ async fn detections() -> Result<(), anyhow::Error> {
                 score: 1.0,
                 evidence: None,
                 metadata: Metadata::new(),
+                chunk: Some(DetectionChunk {
+                    chunker_id: chunker_id.into(),
+                    start_index: 11,
+                    processed_index: 26,
+                }),
+                category: None,
             }],
             start_index: 11,
             processed_index: 26,
+            warnings: vec![],
         },
     ];
     assert_eq!(
@@ -613,6 +643,130 @@ async fn client_error() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+/// Asserts a detector failing mid-stream with the `retry` stream failure policy.
+#[test(tokio::test)]
+async fn detector_stream_failure() -> Result<(), anyhow::Error> {
+    let chunker_id = CHUNKER_NAME_SENTENCE;
+    let detector_name = FLAKY_DETECTOR_SENTENCE;
+
+    let mut chunker_mocks = MockSet::new();
+    chunker_mocks.mock(|when, then| {
+        when.path(CHUNKER_STREAMING_ENDPOINT)
+            .header(CHUNKER_MODEL_ID_HEADER_NAME, chunker_id)
+            .pb_stream(vec![
+                BidiStreamingChunkerTokenizationTaskRequest {
+                    text_stream: "Hi there!".into(),
+                    input_index_stream: 0,
+                },
+                BidiStreamingChunkerTokenizationTaskRequest {
+                    text_stream: " How are you?".into(),
+                    input_index_stream: 1,
+                },
+            ]);
+        then.pb_stream(vec![
+            ChunkerTokenizationStreamResult {
+                results: vec![Token {
+                    start: 0,
+                    end: 9,
+                    text: "Hi there!".into(),
+                }],
+                token_count: 0,
+                processed_index: 9,
+                start_index: 0,
+                input_start_index: 0,
+                input_end_index: 0,
+            },
+            ChunkerTokenizationStreamResult {
+                results: vec![Token {
+                    start: 9,
+                    end: 22,
+                    text: " How are you?".into(),
+                }],
+                token_count: 0,
+                processed_index: 22,
+                start_index: 9,
+                input_start_index: 1,
+                input_end_index: 1,
+            },
+        ]);
+    });
+
+    // The detector fails on the first chunk only
+    let mut detection_mocks = MockSet::new();
+    detection_mocks.mock(|when, then| {
+        when.post()
+            .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec!["Hi there!".into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.internal_server_error();
+    });
+    detection_mocks.mock(|when, then| {
+        when.post()
+            .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec![" How are you?".into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([Vec::<ContentAnalysisResponse>::new()]);
+    });
+
+    // Run test orchestrator server
+    let mock_chunker_server = MockServer::new(chunker_id).grpc().with_mocks(chunker_mocks);
+    let mock_detector_server = MockServer::new(detector_name).with_mocks(detection_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .detector_servers([&mock_detector_server])
+        .chunker_servers([&mock_chunker_server])
+        .build()
+        .await?;
+
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_STREAM_CONTENT_DETECTION_ENDPOINT)
+        .header("content-type", "application/x-ndjson")
+        .body(reqwest::Body::wrap_stream(json_lines_stream([
+            StreamingContentDetectionRequest {
+                detectors: Some(HashMap::from([(
+                    detector_name.into(),
+                    DetectorParams::new(),
+                )])),
+                content: "Hi there!".into(),
+            },
+            StreamingContentDetectionRequest {
+                detectors: None,
+                content: " How are you?".into(),
+            },
+        ])))
+        .send()
+        .await?;
+    let mut messages = Vec::<StreamingContentDetectionResponse>::with_capacity(2);
+    let mut stream = response.bytes_stream();
+    while let Some(Ok(msg)) = stream.next().await {
+        debug!("recv: {msg:?}");
+        messages.push(serde_json::from_slice(&msg[..]).unwrap());
+    }
+
+    // The failed chunk is reported as not analyzed, the next chunk is analyzed
+    let expected_messages = [
+        StreamingContentDetectionResponse {
+            detections: vec![],
+            start_index: 0,
+            processed_index: 9,
+            warnings: vec![DetectionWarning::partial_detection(detector_name, 0, 9)],
+        },
+        StreamingContentDetectionResponse {
+            detections: vec![],
+            start_index: 9,
+            processed_index: 22,
+            warnings: vec![],
+        },
+    ];
+    assert_eq!(messages, expected_messages);
+
+    Ok(())
+}
+
 /// Asserts orchestrator request validation
 #[test(tokio::test)]
 async fn orchestrator_validation_error() -> Result<(), anyhow::Error> {
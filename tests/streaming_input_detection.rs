@@ -0,0 +1,352 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+use std::collections::HashMap;
+
+use common::{
+    chunker::{
+        CHUNKER_MODEL_ID_HEADER_NAME, CHUNKER_NAME_SENTENCE, CHUNKER_STREAMING_ENDPOINT,
+        CHUNKER_UNARY_ENDPOINT,
+    },
+    detectors::{DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE, TEXT_CONTENTS_DETECTOR_ENDPOINT},
+    errors::OrchestratorError,
+    orchestrator::{
+        ORCHESTRATOR_CONFIG_FILE_PATH, ORCHESTRATOR_STREAM_INPUT_DETECTION_ENDPOINT,
+        TestOrchestratorServer, json_lines_stream,
+    },
+};
+use fms_guardrails_orchestr8::{
+    clients::detector::{ContentAnalysisRequest, ContentAnalysisResponse},
+    models::{
+        DetectionChunk, DetectorParams, Metadata, StreamingContentDetectionRequest,
+        StreamingInputDetectionResponse,
+    },
+    pb::{
+        caikit::runtime::chunkers::{
+            BidiStreamingChunkerTokenizationTaskRequest, ChunkerTokenizationTaskRequest,
+        },
+        caikit_data_model::nlp::{ChunkerTokenizationStreamResult, Token, TokenizationResults},
+    },
+};
+use futures::StreamExt;
+use mocktail::{MockSet, server::MockServer};
+use test_log::test;
+use tracing::debug;
+
+pub mod common;
+
+/// Asserts final detections are returned for complete chunks, following interim detections
+/// on input not yet complete in a chunk.
+#[test(tokio::test)]
+async fn detections() -> Result<(), anyhow::Error> {
+    let chunker_id = CHUNKER_NAME_SENTENCE;
+    let angle_brackets_detector = DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE;
+
+    let mut chunker_mocks = MockSet::new();
+    chunker_mocks.mock(|when, then| {
+        when.path(CHUNKER_STREAMING_ENDPOINT)
+            .header(CHUNKER_MODEL_ID_HEADER_NAME, chunker_id)
+            .pb_stream(vec![BidiStreamingChunkerTokenizationTaskRequest {
+                text_stream: "Hi there! How are <you>?".into(),
+                input_index_stream: 0,
+            }]);
+
+        then.pb_stream(vec![
+            ChunkerTokenizationStreamResult {
+                results: vec![Token {
+                    start: 0,
+                    end: 9,
+                    text: "Hi there!".into(),
+                }],
+                token_count: 0,
+                processed_index: 9,
+                start_index: 0,
+                input_start_index: 0,
+                input_end_index: 0,
+            },
+            ChunkerTokenizationStreamResult {
+                results: vec![Token {
+                    start: 9,
+                    end: 24,
+                    text: " How are <you>?".into(),
+                }],
+                token_count: 0,
+                processed_index: 24,
+                start_index: 9,
+                input_start_index: 0,
+                input_end_index: 0,
+            },
+        ]);
+    });
+
+    // Add input detection mocks
+    let mut detection_mocks = MockSet::new();
+    detection_mocks.mock(|when, then| {
+        when.post()
+            .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec!["Hi there!".into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([Vec::<ContentAnalysisResponse>::new()]);
+    });
+    detection_mocks.mock(|when, then| {
+        when.post()
+            .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec![" How are <you>?".into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([[ContentAnalysisResponse {
+            start: 10,
+            end: 13,
+            text: "you".into(),
+            detection: "has_angle_brackets".into(),
+            detection_type: "angle_brackets".into(),
+            detector_id: Some(angle_brackets_detector.into()),
+            score: 1.0,
+            evidence: None,
+            metadata: Metadata::new(),
+            chunk: None,
+            category: None,
+        }]]);
+    });
+
+    // Run test orchestrator server
+    let mock_chunker_server = MockServer::new(chunker_id).grpc().with_mocks(chunker_mocks);
+    let mock_angle_brackets_detector_server =
+        MockServer::new(angle_brackets_detector).with_mocks(detection_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .detector_servers([&mock_angle_brackets_detector_server])
+        .chunker_servers([&mock_chunker_server])
+        .build()
+        .await?;
+
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_STREAM_INPUT_DETECTION_ENDPOINT)
+        .header("content-type", "application/x-ndjson")
+        .body(reqwest::Body::wrap_stream(json_lines_stream([
+            StreamingContentDetectionRequest {
+                detectors: Some(HashMap::from([(
+                    angle_brackets_detector.into(),
+                    DetectorParams::new(),
+                )])),
+                content: "Hi there! How are <you>?".into(),
+            },
+        ])))
+        .send()
+        .await?;
+
+    let mut messages = Vec::<StreamingInputDetectionResponse>::with_capacity(2);
+    let mut stream = response.bytes_stream();
+    while let Some(Ok(msg)) = stream.next().await {
+        debug!("recv: {msg:?}");
+        messages.push(serde_json::from_slice(&msg[..]).unwrap());
+    }
+
+    // Interim detections depend on timing, as they are dropped once superseded
+    // by final detections
+    let (interim_messages, final_messages): (Vec<_>, Vec<_>) =
+        messages.into_iter().partition(|message| message.interim);
+    assert!(
+        interim_messages
+            .iter()
+            .all(|message| message.start_index == 0 && message.processed_index == 24),
+        "failed on interim detections"
+    );
+    let expected_messages = [
+        StreamingInputDetectionResponse {
+            detections: vec![],
+            start_index: 0,
+            processed_index: 9,
+            interim: false,
+            warnings: vec![],
+        },
+        StreamingInputDetectionResponse {
+            detections: vec![ContentAnalysisResponse {
+                start: 10,
+                end: 13,
+                text: "you".into(),
+                detection: "has_angle_brackets".into(),
+                detection_type: "angle_brackets".into(),
+                detector_id: Some(angle_brackets_detector.into()),
+                score: 1.0,
+                evidence: None,
+                metadata: Metadata::new(),
+                chunk: Some(DetectionChunk {
+                    chunker_id: chunker_id.into(),
+                    start_index: 9,
+                    processed_index: 24,
+                }),
+                category: None,
+            }],
+            start_index: 9,
+            processed_index: 24,
+            interim: false,
+            warnings: vec![],
+        },
+    ];
+    assert_eq!(
+        final_messages, expected_messages,
+        "failed on final detections"
+    );
+
+    Ok(())
+}
+
+/// Asserts interim detections are returned for input not yet complete in a chunk.
+#[test(tokio::test)]
+async fn interim_detections() -> Result<(), anyhow::Error> {
+    let chunker_id = CHUNKER_NAME_SENTENCE;
+    let angle_brackets_detector = DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE;
+
+    // The input is not complete in a chunk, so no final detections are returned
+    let mut chunker_mocks = MockSet::new();
+    chunker_mocks.mock(|when, then| {
+        when.path(CHUNKER_STREAMING_ENDPOINT)
+            .header(CHUNKER_MODEL_ID_HEADER_NAME, chunker_id)
+            .pb_stream(vec![BidiStreamingChunkerTokenizationTaskRequest {
+                text_stream: "How are <you>".into(),
+                input_index_stream: 0,
+            }]);
+        then.pb_stream(Vec::<ChunkerTokenizationStreamResult>::new());
+    });
+    // Add interim detection mocks, chunking the input text received so far
+    chunker_mocks.mock(|when, then| {
+        when.path(CHUNKER_UNARY_ENDPOINT)
+            .header(CHUNKER_MODEL_ID_HEADER_NAME, chunker_id)
+            .pb(ChunkerTokenizationTaskRequest {
+                text: "How are <you>".into(),
+            });
+        then.pb(TokenizationResults {
+            results: vec![Token {
+                start: 0,
+                end: 13,
+                text: "How are <you>".into(),
+            }],
+            token_count: 0,
+        });
+    });
+    let mut detection_mocks = MockSet::new();
+    detection_mocks.mock(|when, then| {
+        when.post()
+            .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec!["How are <you>".into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([[ContentAnalysisResponse {
+            start: 9,
+            end: 12,
+            text: "you".into(),
+            detection: "has_angle_brackets".into(),
+            detection_type: "angle_brackets".into(),
+            detector_id: Some(angle_brackets_detector.into()),
+            score: 1.0,
+            evidence: None,
+            metadata: Metadata::new(),
+            chunk: None,
+            category: None,
+        }]]);
+    });
+
+    // Run test orchestrator server
+    let mock_chunker_server = MockServer::new(chunker_id).grpc().with_mocks(chunker_mocks);
+    let mock_angle_brackets_detector_server =
+        MockServer::new(angle_brackets_detector).with_mocks(detection_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .detector_servers([&mock_angle_brackets_detector_server])
+        .chunker_servers([&mock_chunker_server])
+        .build()
+        .await?;
+
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_STREAM_INPUT_DETECTION_ENDPOINT)
+        .header("content-type", "application/x-ndjson")
+        .body(reqwest::Body::wrap_stream(json_lines_stream([
+            StreamingContentDetectionRequest {
+                detectors: Some(HashMap::from([(
+                    angle_brackets_detector.into(),
+                    DetectorParams::new(),
+                )])),
+                content: "How are <you>".into(),
+            },
+        ])))
+        .send()
+        .await?;
+
+    let mut messages = Vec::<StreamingInputDetectionResponse>::with_capacity(1);
+    let mut stream = response.bytes_stream();
+    while let Some(Ok(msg)) = stream.next().await {
+        debug!("recv: {msg:?}");
+        messages.push(serde_json::from_slice(&msg[..]).unwrap());
+    }
+
+    let expected_messages = [StreamingInputDetectionResponse {
+        detections: vec![ContentAnalysisResponse {
+            start: 9,
+            end: 12,
+            text: "you".into(),
+            detection: "has_angle_brackets".into(),
+            detection_type: "angle_brackets".into(),
+            detector_id: Some(angle_brackets_detector.into()),
+            score: 1.0,
+            evidence: None,
+            metadata: Metadata::new(),
+            chunk: None,
+            category: None,
+        }],
+        start_index: 0,
+        processed_index: 13,
+        interim: true,
+        warnings: vec![],
+    }];
+    assert_eq!(messages, expected_messages, "failed on interim detections");
+
+    Ok(())
+}
+
+/// Asserts the request content type must be ND-JSON.
+#[test(tokio::test)]
+async fn unsupported_content_type() -> Result<(), anyhow::Error> {
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .build()
+        .await?;
+
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_STREAM_INPUT_DETECTION_ENDPOINT)
+        .header("content-type", "application/json")
+        .body(reqwest::Body::wrap_stream(json_lines_stream([
+            StreamingContentDetectionRequest {
+                detectors: Some(HashMap::from([(
+                    DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE.into(),
+                    DetectorParams::new(),
+                )])),
+                content: "Hi".into(),
+            },
+        ])))
+        .send()
+        .await?;
+
+    assert_eq!(response.status(), 415);
+    let response = response.json::<OrchestratorError>().await?;
+    assert_eq!(response.code, 415);
+
+    Ok(())
+}
@@ -0,0 +1,247 @@
+/*
+ Copyright FMS Guardrails Orchestrator Authors
+
+ Licensed under the Apache License, Version 2.0 (the "License");
+ you may not use this file except in compliance with the License.
+ You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+ Unless required by applicable law or agreed to in writing, software
+ distributed under the License is distributed on an "AS IS" BASIS,
+ WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ See the License for the specific language governing permissions and
+ limitations under the License.
+
+*/
+
+use std::collections::{BTreeMap, HashMap};
+
+use common::{
+    detectors::{DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC, TEXT_CONTENTS_DETECTOR_ENDPOINT},
+    orchestrator::{
+        ORCHESTRATOR_CONFIG_FILE_PATH, ORCHESTRATOR_SUITABILITY_ENDPOINT, TestOrchestratorServer,
+    },
+};
+use fms_guardrails_orchestr8::{
+    clients::detector::{ContentAnalysisRequest, ContentAnalysisResponse},
+    models::{
+        DetectorParams, Metadata, Redaction, SuitabilityHttpRequest, SuitabilityResult, Verdict,
+    },
+};
+use hyper::StatusCode;
+use mocktail::prelude::*;
+use test_log::test;
+use tracing::debug;
+
+pub mod common;
+
+const DETECTOR_NAME_ANGLE_BRACKETS_REDACT: &str = "angle_brackets_detector_redact";
+
+/// Returns an angle brackets detection of `text` at `start`.
+fn detection(detector_name: &str, text: &str, start: usize, score: f64) -> ContentAnalysisResponse {
+    ContentAnalysisResponse {
+        start,
+        end: start + text.len(),
+        text: text.into(),
+        detection: "has_angle_brackets".into(),
+        detection_type: "angle_brackets".into(),
+        detector_id: Some(detector_name.into()),
+        score,
+        evidence: None,
+        metadata: Metadata::new(),
+        chunk: None,
+        category: None,
+    }
+}
+
+/// Asserts allow and block verdicts.
+#[test(tokio::test)]
+async fn verdicts() -> Result<(), anyhow::Error> {
+    let detector_name = DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC;
+
+    let mut mocks = MockSet::new();
+    mocks.mock(|when, then| {
+        when.post()
+            .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec!["This sentence has no detections.".into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([Vec::<ContentAnalysisResponse>::new()]);
+    });
+    mocks.mock(|when, then| {
+        when.post()
+            .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec!["This sentence has <a detection here>.".into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([[ContentAnalysisResponse {
+            start: 18,
+            end: 35,
+            text: "a detection here".into(),
+            detection: "has_angle_brackets".into(),
+            detection_type: "angle_brackets".into(),
+            detector_id: Some(detector_name.into()),
+            score: 0.9,
+            evidence: None,
+            metadata: Metadata::new(),
+            chunk: None,
+            category: None,
+        }]]);
+    });
+
+    // Start orchestrator server and its dependencies
+    let mock_detector_server = MockServer::new(detector_name).with_mocks(mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .detector_servers([&mock_detector_server])
+        .build()
+        .await?;
+
+    // Assert allow verdict
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_SUITABILITY_ENDPOINT)
+        .json(&SuitabilityHttpRequest {
+            content: "This sentence has no detections.".into(),
+            detectors: HashMap::from([(detector_name.into(), DetectorParams::new())]),
+        })
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json::<SuitabilityResult>().await?,
+        SuitabilityResult::default()
+    );
+
+    // Assert block verdict
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_SUITABILITY_ENDPOINT)
+        .json(&SuitabilityHttpRequest {
+            content: "This sentence has <a detection here>.".into(),
+            detectors: HashMap::from([(detector_name.into(), DetectorParams::new())]),
+        })
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json::<SuitabilityResult>().await?,
+        SuitabilityResult {
+            verdict: Verdict::Block,
+            categories: vec!["angle_brackets".into()],
+            max_scores: BTreeMap::from([(detector_name.into(), 0.9)]),
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
+
+/// Asserts redact verdicts, and that verdicts cover detections excluded from
+/// responses by the detections filter.
+#[test(tokio::test)]
+async fn filtered_verdicts() -> Result<(), anyhow::Error> {
+    let content = "This <sentence> has <two> detections.";
+
+    let mut redact_mocks = MockSet::new();
+    redact_mocks.mock(|when, then| {
+        when.post()
+            .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec![content.into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([[
+            detection(DETECTOR_NAME_ANGLE_BRACKETS_REDACT, "sentence", 6, 0.9),
+            detection(DETECTOR_NAME_ANGLE_BRACKETS_REDACT, "two", 21, 0.6),
+        ]]);
+    });
+    let block_detector_name = DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC;
+    let mut block_mocks = MockSet::new();
+    block_mocks.mock(|when, then| {
+        when.post()
+            .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec![content.into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([[detection(block_detector_name, "two", 21, 0.6)]]);
+    });
+
+    // Start orchestrator server and its dependencies
+    let mock_redact_detector_server =
+        MockServer::new(DETECTOR_NAME_ANGLE_BRACKETS_REDACT).with_mocks(redact_mocks);
+    let mock_block_detector_server = MockServer::new(block_detector_name).with_mocks(block_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .detector_servers([&mock_redact_detector_server, &mock_block_detector_server])
+        .build()
+        .await?;
+
+    // Assert redact verdict, redacting spans of filtered detections
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_SUITABILITY_ENDPOINT)
+        .query(&[("min_score", 0.7)])
+        .json(&SuitabilityHttpRequest {
+            content: content.into(),
+            detectors: HashMap::from([(
+                DETECTOR_NAME_ANGLE_BRACKETS_REDACT.into(),
+                DetectorParams::new(),
+            )]),
+        })
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let redaction = |start: usize, end: usize| Redaction {
+        start,
+        end,
+        detector_id: Some(DETECTOR_NAME_ANGLE_BRACKETS_REDACT.into()),
+        category: None,
+    };
+    assert_eq!(
+        response.json::<SuitabilityResult>().await?,
+        SuitabilityResult {
+            verdict: Verdict::Redact,
+            categories: vec!["angle_brackets".into()],
+            max_scores: BTreeMap::from([(DETECTOR_NAME_ANGLE_BRACKETS_REDACT.into(), 0.9)]),
+            redactions: vec![redaction(6, 14), redaction(21, 24)],
+            ..Default::default()
+        }
+    );
+
+    // Assert block verdict of a detection filtered from the response
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_SUITABILITY_ENDPOINT)
+        .query(&[("min_score", 0.7)])
+        .json(&SuitabilityHttpRequest {
+            content: content.into(),
+            detectors: HashMap::from([
+                (
+                    DETECTOR_NAME_ANGLE_BRACKETS_REDACT.into(),
+                    DetectorParams::new(),
+                ),
+                (block_detector_name.into(), DetectorParams::new()),
+            ]),
+        })
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let result = response.json::<SuitabilityResult>().await?;
+    assert_eq!(result.verdict, Verdict::Block);
+    assert!(result.redactions.is_empty());
+    assert_eq!(
+        result.max_scores,
+        BTreeMap::from([(DETECTOR_NAME_ANGLE_BRACKETS_REDACT.into(), 0.9)])
+    );
+
+    Ok(())
+}
@@ -15,13 +15,20 @@
 
 */
 
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
 
 use common::{
     chunker::{CHUNKER_NAME_SENTENCE, CHUNKER_UNARY_ENDPOINT},
     detectors::{
         DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE, DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC,
         FACT_CHECKING_DETECTOR_SENTENCE, NON_EXISTING_DETECTOR, TEXT_CONTENTS_DETECTOR_ENDPOINT,
+        serve_rate_limited_detector, serve_unavailable_detector,
     },
     errors::{DetectorError, OrchestratorError},
     orchestrator::{
@@ -34,8 +41,10 @@ use fms_guardrails_orchestr8::{
         chunker::MODEL_ID_HEADER_NAME as CHUNKER_MODEL_ID_HEADER_NAME,
         detector::{ContentAnalysisRequest, ContentAnalysisResponse},
     },
+    config::CircuitBreakerConfig,
     models::{
-        DetectorParams, Metadata, TextContentDetectionHttpRequest, TextContentDetectionResult,
+        DetectionWarning, DetectorParams, Metadata, TextContentDetectionHttpRequest,
+        TextContentDetectionResult,
     },
     pb::{
         caikit::runtime::chunkers::ChunkerTokenizationTaskRequest,
@@ -131,6 +140,7 @@ async fn no_detections() -> Result<(), anyhow::Error> {
         .json(&TextContentDetectionHttpRequest {
             content: "This sentence has no detections.".into(),
             detectors: HashMap::from([(whole_doc_detector.into(), DetectorParams::new())]),
+            max_wait_ms: None,
         })
         .send()
         .await?;
@@ -153,6 +163,7 @@ async fn no_detections() -> Result<(), anyhow::Error> {
         .json(&TextContentDetectionHttpRequest {
             content: "This sentence does not have a detection. Neither does this one.".into(),
             detectors: HashMap::from([(sentence_detector.into(), DetectorParams::new())]),
+            max_wait_ms: None,
         })
         .send()
         .await?;
@@ -226,6 +237,8 @@ async fn detections() -> Result<(), anyhow::Error> {
                 score: 1.0,
                 evidence: None,
                 metadata: Metadata::new(),
+                chunk: None,
+                category: None,
             }],
         ]);
     });
@@ -248,6 +261,8 @@ async fn detections() -> Result<(), anyhow::Error> {
             score: 1.0,
             evidence: None,
             metadata: Metadata::new(),
+            chunk: None,
+            category: None,
         }]]);
     });
 
@@ -273,6 +288,7 @@ async fn detections() -> Result<(), anyhow::Error> {
         .json(&TextContentDetectionHttpRequest {
             content: "This sentence has <a detection here>.".into(),
             detectors: HashMap::from([(whole_doc_detector.into(), DetectorParams::new())]),
+            max_wait_ms: None,
         })
         .send()
         .await?;
@@ -298,7 +314,10 @@ async fn detections() -> Result<(), anyhow::Error> {
                 score: 1.0,
                 evidence: None,
                 metadata: Metadata::new(),
+                chunk: None,
+                category: None,
             }],
+            ..Default::default()
         },
         "error on whole doc detector response body assertion"
     );
@@ -309,6 +328,7 @@ async fn detections() -> Result<(), anyhow::Error> {
         .json(&TextContentDetectionHttpRequest {
             content: "This sentence does not have a detection. But <this one does>.".into(),
             detectors: HashMap::from([(sentence_detector.into(), DetectorParams::new())]),
+            max_wait_ms: None,
         })
         .send()
         .await?;
@@ -334,7 +354,10 @@ async fn detections() -> Result<(), anyhow::Error> {
                 score: 1.0,
                 evidence: None,
                 metadata: Metadata::new(),
+                chunk: None,
+                category: None,
             }],
+            ..Default::default()
         },
         "error on sentence detector response body assertion"
     );
@@ -377,6 +400,7 @@ async fn client_error() -> Result<(), anyhow::Error> {
         .json(&TextContentDetectionHttpRequest {
             content: "This should return a 500".into(),
             detectors: HashMap::from([(detector_name.into(), DetectorParams::new())]),
+            max_wait_ms: None,
         })
         .send()
         .await?;
@@ -392,6 +416,84 @@ async fn client_error() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+/// Asserts a request-scoped chunker override.
+#[test(tokio::test)]
+async fn chunker_override() -> Result<(), anyhow::Error> {
+    let sentence_detector = DETECTOR_NAME_ANGLE_BRACKETS_SENTENCE;
+    let content = "This sentence does not have a detection. Neither does this one.";
+
+    // The override is not sent to the detector
+    let mut detector_mocks = MockSet::new();
+    detector_mocks.mock(|when, then| {
+        when.post()
+            .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec![content.into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([Vec::<ContentAnalysisResponse>::new()]);
+    });
+
+    // Start orchestrator server and its dependencies, without the sentence chunker
+    let mock_detector_server = MockServer::new(sentence_detector).with_mocks(detector_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .detector_servers([&mock_detector_server])
+        .build()
+        .await?;
+
+    // Assert sentence detector call with whole doc chunker override
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CONTENT_DETECTION_ENDPOINT)
+        .json(&json!({
+            "content": content,
+            "detectors": {
+                sentence_detector: { "chunker_id": "whole_doc_chunker" }
+            }
+        }))
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json::<TextContentDetectionResult>().await?,
+        TextContentDetectionResult::default(),
+    );
+
+    // Assert unknown chunker override
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CONTENT_DETECTION_ENDPOINT)
+        .json(&json!({
+            "content": content,
+            "detectors": {
+                sentence_detector: { "chunker_id": "does_not_exist" }
+            }
+        }))
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Assert invalid chunker override type
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CONTENT_DETECTION_ENDPOINT)
+        .json(&json!({
+            "content": content,
+            "detectors": {
+                sentence_detector: { "chunker_id": 1 }
+            }
+        }))
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
 /// Asserts orchestrator validation errors.
 #[test(tokio::test)]
 async fn orchestrator_validation_error() -> Result<(), anyhow::Error> {
@@ -527,3 +629,216 @@ async fn orchestrator_validation_error() -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+/// Asserts processing metadata returned with `debug=true`.
+#[test(tokio::test)]
+async fn debug_block() -> Result<(), anyhow::Error> {
+    let whole_doc_detector = DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC;
+    let content = "This sentence does not have a detection.";
+
+    let mut detector_mocks = MockSet::new();
+    detector_mocks.mock(|when, then| {
+        when.post()
+            .path(TEXT_CONTENTS_DETECTOR_ENDPOINT)
+            .json(ContentAnalysisRequest {
+                contents: vec![content.into()],
+                detector_params: DetectorParams::new(),
+            });
+        then.json([Vec::<ContentAnalysisResponse>::new()]);
+    });
+
+    // Start orchestrator server and its dependencies
+    let mock_detector_server = MockServer::new(whole_doc_detector).with_mocks(detector_mocks);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .detector_servers([&mock_detector_server])
+        .build()
+        .await?;
+
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CONTENT_DETECTION_ENDPOINT)
+        .query(&[("debug", "true")])
+        .json(&json!({
+            "content": content,
+            "detectors": { whole_doc_detector: {} }
+        }))
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = response.json::<serde_json::Value>().await?;
+    assert_eq!(response["detections"], json!([]));
+    let debug_info = &response["debug"];
+    assert_eq!(debug_info["chunkers"], json!(["whole_doc_chunker"]));
+    assert_eq!(
+        debug_info["detector_calls"][0]["detector_id"],
+        json!(whole_doc_detector)
+    );
+    assert_eq!(debug_info["detector_calls"][0]["coalesced"], json!(false));
+    assert!(
+        debug_info["phases"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|phase| phase["name"] == "total")
+    );
+
+    // Assert no debug block by default
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CONTENT_DETECTION_ENDPOINT)
+        .json(&json!({
+            "content": content,
+            "detectors": { whole_doc_detector: {} }
+        }))
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response
+            .json::<serde_json::Value>()
+            .await?
+            .get("debug")
+            .is_none()
+    );
+
+    Ok(())
+}
+
+/// Asserts that detectors are skipped with a warning while input detection is disabled.
+#[test(tokio::test)]
+async fn input_detection_disabled() -> Result<(), anyhow::Error> {
+    let whole_doc_detector = DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC;
+
+    // Start orchestrator server and its dependencies, without detector mocks
+    let mock_detector_server = MockServer::new(whole_doc_detector);
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .detector_servers([&mock_detector_server])
+        .configure(|config| config.features.input_detection = false)
+        .build()
+        .await?;
+
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CONTENT_DETECTION_ENDPOINT)
+        .json(&TextContentDetectionHttpRequest {
+            content: "This sentence has <a detection here>.".into(),
+            detectors: HashMap::from([(whole_doc_detector.into(), DetectorParams::new())]),
+            max_wait_ms: None,
+        })
+        .send()
+        .await?;
+    debug!("{response:#?}");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json::<TextContentDetectionResult>().await?,
+        TextContentDetectionResult {
+            warnings: vec![DetectionWarning::input_detection_disabled()],
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
+
+/// Asserts requests fail without being sent to a detector once its circuit breaker opens,
+/// and the detector is reported unhealthy.
+#[test(tokio::test)]
+async fn circuit_breaker_open() -> Result<(), anyhow::Error> {
+    let detector_name = DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC;
+    let requests = Arc::new(AtomicUsize::new(0));
+    let detector_port = serve_unavailable_detector(requests.clone()).await?;
+
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .configure(|config| {
+            let service = &mut config.detectors.get_mut(detector_name).unwrap().service;
+            service.port = Some(detector_port);
+            service.circuit_breaker = Some(CircuitBreakerConfig {
+                failure_threshold: 1,
+                cool_down: 60,
+            });
+        })
+        .build()
+        .await?;
+
+    for _ in 0..2 {
+        let response = orchestrator_server
+            .post(ORCHESTRATOR_CONTENT_DETECTION_ENDPOINT)
+            .json(&TextContentDetectionHttpRequest {
+                content: "This sentence has <a detection here>.".into(),
+                detectors: HashMap::from([(detector_name.into(), DetectorParams::new())]),
+                max_wait_ms: None,
+            })
+            .send()
+            .await?;
+        debug!("{response:#?}");
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    // The second request failed without being sent
+    assert_eq!(requests.load(Ordering::Relaxed), 1);
+
+    let info_url = orchestrator_server.health_url().join("info?probe=true")?;
+    let info = reqwest::get(info_url)
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+    debug!("{info:#?}");
+    let detector_health = &info["services"][detector_name];
+    assert_eq!(detector_health["status"], "UNHEALTHY");
+    assert_eq!(detector_health["reason"], "circuit breaker open");
+
+    Ok(())
+}
+
+/// Asserts requests to rate limited detectors fail with the latest `Retry-After` of
+/// the detectors.
+#[test(tokio::test)]
+async fn rate_limited() -> Result<(), anyhow::Error> {
+    let detector_names = [
+        DETECTOR_NAME_ANGLE_BRACKETS_WHOLE_DOC,
+        "angle_brackets_detector_redact",
+    ];
+    let detector_ports = [
+        serve_rate_limited_detector(3).await?,
+        serve_rate_limited_detector(7).await?,
+    ];
+
+    let orchestrator_server = TestOrchestratorServer::builder()
+        .config_path(ORCHESTRATOR_CONFIG_FILE_PATH)
+        .configure(|config| {
+            for (detector_name, port) in detector_names.into_iter().zip(detector_ports) {
+                config
+                    .detectors
+                    .get_mut(detector_name)
+                    .unwrap()
+                    .service
+                    .port = Some(port);
+            }
+        })
+        .build()
+        .await?;
+
+    let response = orchestrator_server
+        .post(ORCHESTRATOR_CONTENT_DETECTION_ENDPOINT)
+        .json(&TextContentDetectionHttpRequest {
+            content: "This sentence has <a detection here>.".into(),
+            detectors: detector_names
+                .into_iter()
+                .map(|detector_name| (detector_name.into(), DetectorParams::new()))
+                .collect(),
+            max_wait_ms: None,
+        })
+        .send()
+        .await?;
+    debug!("{response:#?}");
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()[hyper::header::RETRY_AFTER], "7");
+    let error = response.json::<OrchestratorError>().await?;
+    assert_eq!(error.code, 429);
+
+    Ok(())
+}